@@ -0,0 +1,34 @@
+//! Sets up a small Node project next to a checkout of skies itself.
+//!
+//! Run with `cargo run --example node_project -- [apply|revert]`.
+
+use skies::report::ConsoleReporter;
+use skies::shifts::{NodeInstall, NodeProjectInit};
+use skies::{ExecutionContext, ShiftPlan, ShiftResult};
+
+fn main() -> ShiftResult<()> {
+    let plan = ShiftPlan::builder()
+        .create_dir("demo")
+        .id("root")
+        .file("demo/README.md")
+        .contents("# demo\n")
+        .mode(0o644)
+        .depends_on("root")
+        .shift(NodeProjectInit::new("demo"))
+        .id("package")
+        .depends_on("root")
+        .tag("node")
+        .shift(NodeInstall::new("demo"))
+        .depends_on("package")
+        .tag("node")
+        .clone_repo("danbruder/skies", "demo/vendor/skies")
+        .tag("git")
+        .build()?;
+
+    let ctx = ExecutionContext::new();
+    let mut reporter = ConsoleReporter::default();
+    match std::env::args().nth(1).as_deref() {
+        Some("revert") => plan.revert_with(&ctx, &mut reporter),
+        _ => plan.apply_with(&ctx, &mut reporter),
+    }
+}
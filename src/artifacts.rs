@@ -0,0 +1,322 @@
+//! What a plan downloads, cached ahead of time so machines without
+//! internet access can still be provisioned.
+//!
+//! `skies fetch` asks each shift for its [`Artifact`]s and downloads them
+//! into an [`ArtifactCache`] directory. Applying with that cache set on
+//! the context takes files, clones and apt packages from it instead of
+//! the network, and with [`ArtifactCache::offline`] anything missing from
+//! it is an error rather than a download.
+//!
+//! The cache holds:
+//!
+//! - `files/<hash>`: downloads, keyed by a hash of their URL;
+//! - `git/<hash>.bundle`: repositories as bundles of every ref;
+//! - `apt/`: `.deb` archives and the package lists they were found in.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::hash::{sha256_hex, short_hash};
+use crate::network;
+use crate::packages::PackageManager;
+use crate::shift::Shift;
+use crate::shifts::{Cmd, GitHubClone};
+
+/// Something a shift downloads when it applies.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Artifact {
+    /// A file fetched from `url`.
+    File { url: String },
+    /// A git repository, with the branch cloned, if not the default one,
+    /// and the environment variable holding a token for it, if it needs
+    /// one.
+    Git {
+        url: String,
+        branch: Option<String>,
+        token_env: Option<String>,
+    },
+    /// System packages, with whatever they depend on that the fetching
+    /// machine does not have installed. Only apt can cache them.
+    Packages(Vec<String>),
+}
+
+impl Artifact {
+    pub fn file(url: impl Into<String>) -> Self {
+        Artifact::File { url: url.into() }
+    }
+
+    /// What the artifact is now, for a [lockfile](crate::lockfile): the
+    /// SHA-256 of a file, downloaded afresh past every cache, or the commit
+    /// a clone would get. Packages are not locked.
+    pub fn resolve(&self, ctx: &ExecutionContext) -> ShiftResult<Option<String>> {
+        match self {
+            Artifact::File { url } => {
+                let dest = ctx.temp_dir()?.join("download");
+                curl(ctx, url, &dest)?;
+                Ok(Some(sha256_hex(fs::read(&dest)?)))
+            }
+            Artifact::Git {
+                url,
+                branch,
+                token_env,
+            } => {
+                let mut clone = GitHubClone::new(url, ".");
+                if let Some(branch) = branch {
+                    clone = clone.branch(branch);
+                }
+                if let Some(var) = token_env {
+                    clone = clone.token_env(var);
+                }
+                clone.remote_commit(ctx).map(Some)
+            }
+            Artifact::Packages(_) => Ok(None),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Artifact::File { url } => url.clone(),
+            Artifact::Git { url, .. } => format!("git {url}"),
+            Artifact::Packages(names) => format!("packages {}", names.join(" ")),
+        }
+    }
+}
+
+/// What [`ArtifactCache::fetch`] did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fetched {
+    Downloaded,
+    /// Already in the cache.
+    Cached,
+    /// The artifact cannot be cached on this machine, and why.
+    Unsupported(String),
+}
+
+/// A directory of downloaded artifacts; see the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct ArtifactCache {
+    dir: PathBuf,
+    offline: bool,
+}
+
+impl ArtifactCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        let dir = dir.into();
+        ArtifactCache {
+            // Absolute, since clones run from the directory they clone into.
+            dir: std::path::absolute(&dir).unwrap_or(dir),
+            offline: false,
+        }
+    }
+
+    /// Fails instead of downloading what the cache does not hold.
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn key(url: &str) -> String {
+        short_hash(url, 32)
+    }
+
+    pub fn file_path(&self, url: &str) -> PathBuf {
+        self.dir.join("files").join(Self::key(url))
+    }
+
+    pub fn bundle_path(&self, url: &str) -> PathBuf {
+        self.dir
+            .join("git")
+            .join(format!("{}.bundle", Self::key(url)))
+    }
+
+    /// `apt-get` options that keep its package lists and archives in the
+    /// cache.
+    pub fn apt_options(&self) -> ShiftResult<Vec<String>> {
+        let apt = self.dir.join("apt");
+        let lists = apt.join("lists");
+        let archives = apt.join("archives");
+        for dir in [&lists, &archives] {
+            fs::create_dir_all(dir.join("partial"))?;
+        }
+        Ok(vec![
+            "-o".into(),
+            format!("Dir::State::Lists={}", lists.display()),
+            "-o".into(),
+            format!("Dir::Cache::Archives={}", archives.display()),
+        ])
+    }
+
+    /// The error for `what` missing from an offline cache.
+    pub fn missing(&self, what: &str) -> ShiftError {
+        ShiftError::Custom(format!(
+            "{what} is not in the artifact cache {}",
+            self.dir.display()
+        ))
+        .hint(
+            "run `skies fetch` for this plan with the same cache on a machine with internet access",
+        )
+    }
+
+    /// Downloads `artifact` into the cache. Files already there are kept;
+    /// repositories and packages are fetched again, to pick up changes.
+    pub fn fetch(&self, ctx: &ExecutionContext, artifact: &Artifact) -> ShiftResult<Fetched> {
+        match artifact {
+            Artifact::File { url } => {
+                let path = self.file_path(url);
+                if path.is_file() {
+                    return Ok(Fetched::Cached);
+                }
+                fs::create_dir_all(self.dir.join("files"))?;
+                let partial = path.with_extension("partial");
+                download(ctx, url, None, &partial)?;
+                fs::rename(&partial, &path)?;
+            }
+            Artifact::Git { url, token_env, .. } => {
+                // Cloned in a scratch directory, outside the plan's root.
+                let scratch = ctx.temp_dir()?;
+                let in_scratch = ctx.clone().with_root(&scratch);
+                let mirror = scratch.join("mirror.git");
+                let mut clone = GitHubClone::new(url, "mirror.git").mirror(true);
+                if let Some(var) = token_env {
+                    clone = clone.token_env(var);
+                }
+                network::transfer(ctx, |_| clone.build_plan(&in_scratch).apply(&in_scratch))?;
+                let bundle = self.bundle_path(url);
+                fs::create_dir_all(self.dir.join("git"))?;
+                let partial = bundle.with_extension("partial");
+                Cmd::new("git")
+                    .args(["bundle", "create"])
+                    .arg(partial.display().to_string())
+                    .args(["HEAD", "--all"])
+                    .cwd("mirror.git")
+                    .output(&in_scratch)?;
+                fs::rename(&partial, &bundle)?;
+                fs::remove_dir_all(&mirror)?;
+            }
+            Artifact::Packages(names) => {
+                if PackageManager::detect() != Some(PackageManager::Apt) {
+                    return Ok(Fetched::Unsupported(
+                        "only apt packages can be cached".into(),
+                    ));
+                }
+                let options = self.apt_options()?;
+                Cmd::new("apt-get")
+                    .args(options.iter().map(String::as_str))
+                    .arg("update")
+                    .output(ctx)?;
+                Cmd::new("apt-get")
+                    .args(["install", "-y", "--no-install-recommends", "--reinstall"])
+                    .arg("--download-only")
+                    .args(options.iter().map(String::as_str))
+                    .args(names.iter().map(String::as_str))
+                    .env("DEBIAN_FRONTEND", "noninteractive")
+                    .output(ctx)?;
+            }
+        }
+        Ok(Fetched::Downloaded)
+    }
+}
+
+fn curl(ctx: &ExecutionContext, url: &str, dest: &Path) -> ShiftResult<()> {
+    network::transfer(ctx, |rate| {
+        let mut curl = Cmd::new("curl").args(["-fsSL", "-o"]);
+        curl = curl.arg(dest.display().to_string());
+        if let Some(rate) = rate {
+            curl = curl.args(["--limit-rate".to_string(), rate.to_string()]);
+        }
+        curl.arg(url).output(ctx).map(drop)
+    })
+}
+
+/// Downloads `url` to `dest` and checks it against `sha256`, if given, or
+/// else against the context's [lockfile](crate::lockfile), which records
+/// what was downloaded. The context's artifact cache is tried first, then
+/// its [download cache](crate::download_cache), which keeps what is
+/// downloaded.
+pub fn download(
+    ctx: &ExecutionContext,
+    url: &str,
+    sha256: Option<&str>,
+    dest: &Path,
+) -> ShiftResult<()> {
+    let lock = ctx.lock();
+    let locked = lock.and_then(|lock| lock.sha256(url));
+    let from_lock = sha256.is_none() && locked.is_some();
+    let sha256 = sha256.or(locked.as_deref());
+    let verified = |dest: &Path| -> ShiftResult<()> {
+        if sha256.is_none() && lock.is_none() {
+            return Ok(());
+        }
+        let actual = sha256_hex(fs::read(dest)?);
+        if let Some(expected) = sha256.filter(|expected| !actual.eq_ignore_ascii_case(expected)) {
+            let err =
+                ShiftError::Custom(format!("{url} has SHA-256 {actual}, expected {expected}"));
+            return Err(match from_lock {
+                true => err.hint(
+                    "the download changed since it was locked; if that is expected, run `skies update-lock`",
+                ),
+                false => err,
+            });
+        }
+        if let Some(lock) = lock {
+            lock.record_download(url, &actual);
+        }
+        Ok(())
+    };
+    if let Some(cache) = ctx.artifacts() {
+        let cached = cache.file_path(url);
+        if cached.is_file() {
+            ctx.debug(&format!("using cached {url}"));
+            fs::copy(&cached, dest)?;
+            return verified(dest);
+        }
+        if cache.is_offline() {
+            return Err(cache.missing(url));
+        }
+    }
+    let downloads = ctx.download_cache();
+    if let Some(cached) = downloads.and_then(|cache| cache.lookup(url, sha256)) {
+        ctx.debug(&format!("using {} for {url}", cached.display()));
+        fs::copy(&cached, dest)?;
+        return verified(dest);
+    }
+    curl(ctx, url, dest)?;
+    verified(dest)?;
+    if let Some(cache) = downloads {
+        // The download succeeded; a cache that cannot keep it only costs
+        // a download next time.
+        if let Err(err) = cache.store(url, dest) {
+            ctx.warn(&format!(
+                "could not cache {url} in {}: {err}",
+                cache.dir().display()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The cached bundle to clone `url` from, if the context's artifact cache
+/// has one; `None` means cloning from `url` itself.
+pub fn git_source(ctx: &ExecutionContext, url: &str) -> ShiftResult<Option<PathBuf>> {
+    let Some(cache) = ctx.artifacts() else {
+        return Ok(None);
+    };
+    let bundle = cache.bundle_path(url);
+    if bundle.is_file() {
+        return Ok(Some(bundle));
+    }
+    if cache.is_offline() {
+        return Err(cache.missing(url));
+    }
+    Ok(None)
+}
@@ -0,0 +1,317 @@
+//! Fluent construction of [`ShiftPlan`]s.
+//!
+//! ```no_run
+//! use skies::ShiftPlan;
+//!
+//! let plan = ShiftPlan::builder()
+//!     .create_dir("app")
+//!     .id("app-dir")
+//!     .file("app/.env")
+//!     .contents("PORT=3000\n")
+//!     .mode(0o600)
+//!     .depends_on("app-dir")
+//!     .cmd("npm")
+//!     .args(["init", "-y"])
+//!     .cwd("app")
+//!     .env("CI", "1")
+//!     .depends_on("app-dir")
+//!     .tag("node")
+//!     .build()?;
+//! # Ok::<(), skies::ShiftError>(())
+//! ```
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::error::ShiftResult;
+use crate::permissions::PermissionPolicy;
+use crate::plan::{PlanEntry, ShiftPlan};
+use crate::shift::Shift;
+use crate::shifts::{Cmd, CreateDir, CreateFile, GitHubClone, Symlink};
+
+/// Collects shifts and builds a validated [`ShiftPlan`].
+#[derive(Default)]
+pub struct PlanBuilder {
+    plan: ShiftPlan,
+}
+
+impl PlanBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn create_dir(self, path: impl Into<PathBuf>) -> StepBuilder<CreateDir> {
+        self.step(CreateDir::new(path))
+    }
+
+    /// Adds an empty file; set its contents and mode on the returned step.
+    pub fn file(self, path: impl Into<PathBuf>) -> StepBuilder<CreateFile> {
+        self.step(CreateFile::new(path, ""))
+    }
+
+    pub fn cmd(self, program: impl Into<String>) -> StepBuilder<Cmd> {
+        self.step(Cmd::new(program))
+    }
+
+    pub fn clone_repo(
+        self,
+        repo: impl Into<String>,
+        target: impl Into<PathBuf>,
+    ) -> StepBuilder<GitHubClone> {
+        self.step(GitHubClone::new(repo, target))
+    }
+
+    /// Adds a symbolic link at `path` pointing to `target`.
+    pub fn symlink(
+        self,
+        path: impl Into<PathBuf>,
+        target: impl Into<PathBuf>,
+    ) -> StepBuilder<Symlink> {
+        self.step(Symlink::new(path, target))
+    }
+
+    /// Adds any other shift, e.g. one defined outside this crate.
+    pub fn shift<S: Shift + 'static>(self, shift: S) -> StepBuilder<S> {
+        self.step(shift)
+    }
+
+    /// Confines the plan's shifts to `root` (see [`ShiftPlan::set_root`]).
+    pub fn root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.plan.set_root(root);
+        self
+    }
+
+    /// Default permissions for files and directories the plan creates.
+    pub fn permissions(mut self, permissions: PermissionPolicy) -> Self {
+        self.plan.set_permissions(permissions);
+        self
+    }
+
+    /// Shorthand for a permission policy with only a umask.
+    pub fn umask(self, umask: u32) -> Self {
+        let permissions = PermissionPolicy {
+            umask: Some(umask),
+            ..*self.plan.permissions()
+        };
+        self.permissions(permissions)
+    }
+
+    /// Adds a check for the verify phase (see [`ShiftPlan::add_check`]).
+    pub fn verify(mut self, check: impl Shift + 'static) -> Self {
+        self.plan.add_check(check);
+        self
+    }
+
+    /// Limits how long an apply or revert of the whole plan may take.
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.plan.set_deadline(deadline);
+        self
+    }
+
+    /// Validates dependencies and returns the finished plan.
+    pub fn build(self) -> ShiftResult<ShiftPlan> {
+        self.plan.validate()?;
+        Ok(self.plan)
+    }
+
+    fn step<S: Shift + 'static>(self, shift: S) -> StepBuilder<S> {
+        StepBuilder {
+            parent: self,
+            shift,
+            id: None,
+            tags: Vec::new(),
+            depends_on: Vec::new(),
+            allow_outside_root: false,
+            time_limit: None,
+            serial_group: None,
+            verify: Vec::new(),
+        }
+    }
+}
+
+/// A shift being configured inside a [`PlanBuilder`].
+///
+/// Starting the next shift (or calling `build`) finishes this one.
+pub struct StepBuilder<S> {
+    parent: PlanBuilder,
+    shift: S,
+    id: Option<String>,
+    tags: Vec<String>,
+    depends_on: Vec<String>,
+    allow_outside_root: bool,
+    time_limit: Option<Duration>,
+    serial_group: Option<String>,
+    verify: Vec<Box<dyn Shift>>,
+}
+
+impl<S: Shift + 'static> StepBuilder<S> {
+    /// Gives the shift an explicit ID; otherwise one is derived from its
+    /// content.
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    pub fn tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.push(tag.into());
+        self
+    }
+
+    pub fn depends_on(mut self, id: impl Into<String>) -> Self {
+        self.depends_on.push(id.into());
+        self
+    }
+
+    /// Lets this shift touch paths outside the plan root.
+    pub fn allow_outside_root(mut self) -> Self {
+        self.allow_outside_root = true;
+        self
+    }
+
+    /// Limits how long this shift may take; unlike a command's `timeout`,
+    /// this covers the whole shift.
+    pub fn time_limit(mut self, limit: Duration) -> Self {
+        self.time_limit = Some(limit);
+        self
+    }
+
+    /// Never runs this shift alongside another of the same `group`, even
+    /// in a parallel apply.
+    pub fn serial_group(mut self, group: impl Into<String>) -> Self {
+        self.serial_group = Some(group.into());
+        self
+    }
+
+    /// Checks `check` once the plan has applied (see
+    /// [`PlanEntry::verify`]).
+    pub fn verify(mut self, check: impl Shift + 'static) -> Self {
+        self.verify.push(Box::new(check));
+        self
+    }
+
+    pub fn create_dir(self, path: impl Into<PathBuf>) -> StepBuilder<CreateDir> {
+        self.finish().create_dir(path)
+    }
+
+    pub fn file(self, path: impl Into<PathBuf>) -> StepBuilder<CreateFile> {
+        self.finish().file(path)
+    }
+
+    pub fn cmd(self, program: impl Into<String>) -> StepBuilder<Cmd> {
+        self.finish().cmd(program)
+    }
+
+    pub fn clone_repo(
+        self,
+        repo: impl Into<String>,
+        target: impl Into<PathBuf>,
+    ) -> StepBuilder<GitHubClone> {
+        self.finish().clone_repo(repo, target)
+    }
+
+    pub fn symlink(
+        self,
+        path: impl Into<PathBuf>,
+        target: impl Into<PathBuf>,
+    ) -> StepBuilder<Symlink> {
+        self.finish().symlink(path, target)
+    }
+
+    pub fn shift<T: Shift + 'static>(self, shift: T) -> StepBuilder<T> {
+        self.finish().shift(shift)
+    }
+
+    pub fn build(self) -> ShiftResult<ShiftPlan> {
+        self.finish().build()
+    }
+
+    /// Adds this shift to the plan and returns the plan builder.
+    pub fn finish(self) -> PlanBuilder {
+        let mut parent = self.parent;
+        let mut entry = PlanEntry::new(self.shift);
+        if let Some(id) = self.id {
+            entry = entry.with_id(id);
+        }
+        entry.tags = self.tags;
+        entry.depends_on = self.depends_on;
+        entry.allow_outside_root = self.allow_outside_root;
+        entry.time_limit = self.time_limit;
+        entry.serial_group = self.serial_group;
+        entry.verify = self.verify;
+        parent.plan.push(entry);
+        parent
+    }
+
+    fn map(mut self, f: impl FnOnce(S) -> S) -> Self {
+        self.shift = f(self.shift);
+        self
+    }
+}
+
+impl StepBuilder<CreateDir> {
+    pub fn mode(self, mode: u32) -> Self {
+        self.map(|s| s.mode(mode))
+    }
+}
+
+impl StepBuilder<CreateFile> {
+    pub fn contents(self, contents: impl Into<String>) -> Self {
+        self.map(|s| s.contents(contents))
+    }
+
+    pub fn mode(self, mode: u32) -> Self {
+        self.map(|s| s.mode(mode))
+    }
+
+    pub fn sensitive(self) -> Self {
+        self.map(|s| s.sensitive())
+    }
+}
+
+impl StepBuilder<Cmd> {
+    pub fn arg(self, arg: impl Into<String>) -> Self {
+        self.map(|s| s.arg(arg))
+    }
+
+    pub fn args<I, A>(self, args: I) -> Self
+    where
+        I: IntoIterator<Item = A>,
+        A: Into<String>,
+    {
+        self.map(|s| s.args(args))
+    }
+
+    pub fn cwd(self, dir: impl Into<PathBuf>) -> Self {
+        self.map(|s| s.cwd(dir))
+    }
+
+    pub fn env(self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.map(|s| s.env(key, value))
+    }
+
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.map(|s| s.timeout(timeout))
+    }
+
+    pub fn creates(self, path: impl Into<PathBuf>) -> Self {
+        self.map(|s| s.creates(path))
+    }
+
+    pub fn undo<I, A>(self, argv: I) -> Self
+    where
+        I: IntoIterator<Item = A>,
+        A: Into<String>,
+    {
+        self.map(|s| s.undo(argv))
+    }
+}
+
+impl StepBuilder<GitHubClone> {
+    pub fn branch(self, branch: impl Into<String>) -> Self {
+        self.map(|s| s.branch(branch))
+    }
+
+    pub fn token_env(self, var: impl Into<String>) -> Self {
+        self.map(|s| s.token_env(var))
+    }
+}
@@ -0,0 +1,32 @@
+//! Cooperative cancellation of a running plan.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A flag shared between whoever may stop a run and the shifts doing the
+/// work.
+///
+/// Cancelling does not interrupt anything by itself: the executor checks
+/// the token before each shift, and long-running work (commands run with
+/// [`Cmd`](crate::shifts::Cmd), large file writes) checks it as it goes,
+/// through [`ExecutionContext::check_cancelled`](crate::ExecutionContext::check_cancelled).
+/// Clones share the flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks everything holding a clone of this token to stop.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
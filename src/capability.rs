@@ -0,0 +1,80 @@
+//! What a shift needs to be allowed to do, so that whoever applies a plan
+//! from elsewhere can see it first and agree to it, the way a package
+//! manager lists what an install will change.
+//!
+//! Each shift declares its own through [`Shift::capabilities`]; the plan
+//! adds [`Capability::WritesOutsideRoot`] for entries that touch paths
+//! outside the plan root (see [`PlanEntry::capabilities`]).
+//!
+//! [`Shift::capabilities`]: crate::Shift::capabilities
+//! [`PlanEntry::capabilities`]: crate::PlanEntry::capabilities
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    /// Downloads or otherwise talks to other machines.
+    Network,
+    /// Needs root, or runs something as another user.
+    Sudo,
+    /// Creates or changes files outside the plan root.
+    WritesOutsideRoot,
+    /// Runs commands, scripts or code the plan supplies, which can do
+    /// anything the user running skies can.
+    RunsArbitraryCode,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 4] = [
+        Capability::Network,
+        Capability::Sudo,
+        Capability::WritesOutsideRoot,
+        Capability::RunsArbitraryCode,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Capability::Network => "network",
+            Capability::Sudo => "sudo",
+            Capability::WritesOutsideRoot => "writes_outside_root",
+            Capability::RunsArbitraryCode => "runs_arbitrary_code",
+        }
+    }
+
+    /// What agreeing to the capability means, for prompts.
+    pub fn description(self) -> &'static str {
+        match self {
+            Capability::Network => "uses the network",
+            Capability::Sudo => "needs root or runs things as another user",
+            Capability::WritesOutsideRoot => "writes outside the plan's directory",
+            Capability::RunsArbitraryCode => "runs commands the plan supplies",
+        }
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Capability {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Capability::ALL
+            .into_iter()
+            .find(|capability| capability.name() == s.replace('-', "_"))
+            .ok_or_else(|| {
+                let names: Vec<_> = Capability::ALL.iter().map(|c| c.name()).collect();
+                format!(
+                    "unknown capability `{s}`; expected one of {}",
+                    names.join(", ")
+                )
+            })
+    }
+}
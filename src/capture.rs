@@ -0,0 +1,173 @@
+//! Plans captured from an existing directory tree.
+//!
+//! Every directory under the captured one becomes a `create_dir` shift,
+//! every text file a `file` shift with its contents, and every symbolic
+//! link a `symlink`, all at the same paths relative to the directory the
+//! plan recreates the tree in. Modes other than the
+//! usual `0o755` for directories and `0o644` for files are kept. Files
+//! that are not UTF-8 text, or larger than the size limit, cannot be
+//! written inline and are listed as skipped instead.
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use crate::error::{ShiftError, ShiftResult};
+
+/// Names that are never captured.
+const ALWAYS_SKIPPED: &[&str] = &[".git", ".skies"];
+
+/// Default limit on the size of a captured file.
+pub const DEFAULT_MAX_SIZE: u64 = 64 * 1024;
+
+const DIR_MODE: u32 = 0o755;
+const FILE_MODE: u32 = 0o644;
+
+/// Something found in the tree, by path relative to its root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Captured {
+    Dir {
+        path: PathBuf,
+        /// Set if not the usual `0o755`.
+        mode: Option<u32>,
+    },
+    File {
+        path: PathBuf,
+        contents: String,
+        /// Set if not the usual `0o644`.
+        mode: Option<u32>,
+    },
+    Symlink {
+        path: PathBuf,
+        target: PathBuf,
+    },
+    /// Left out, and why.
+    Skipped {
+        path: PathBuf,
+        reason: String,
+    },
+}
+
+/// Walks `source`, skipping entries named in `exclude` at any depth and
+/// files over `max_size` bytes.
+pub fn scan(source: &Path, exclude: &[String], max_size: u64) -> ShiftResult<Vec<Captured>> {
+    if !source.is_dir() {
+        return Err(ShiftError::Custom(format!(
+            "{} is not a directory",
+            source.display()
+        )));
+    }
+    let mut captured = Vec::new();
+    walk(source, Path::new(""), exclude, max_size, &mut captured)?;
+    Ok(captured)
+}
+
+fn walk(
+    dir: &Path,
+    rel: &Path,
+    exclude: &[String],
+    max_size: u64,
+    captured: &mut Vec<Captured>,
+) -> ShiftResult<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if ALWAYS_SKIPPED.contains(&name.as_str()) || exclude.contains(&name) {
+            continue;
+        }
+        let path = rel.join(&name);
+        let meta = fs::symlink_metadata(entry.path())?;
+        let mode = meta.permissions().mode() & 0o7777;
+        if meta.file_type().is_symlink() {
+            let target = fs::read_link(entry.path())?;
+            captured.push(Captured::Symlink { path, target });
+        } else if meta.is_dir() {
+            captured.push(Captured::Dir {
+                path: path.clone(),
+                mode: (mode != DIR_MODE).then_some(mode),
+            });
+            walk(&entry.path(), &path, exclude, max_size, captured)?;
+        } else if !meta.is_file() {
+            captured.push(Captured::Skipped {
+                path,
+                reason: "not a regular file".into(),
+            });
+        } else if meta.len() > max_size {
+            captured.push(Captured::Skipped {
+                path,
+                reason: format!("{} bytes, over the {max_size} byte limit", meta.len()),
+            });
+        } else {
+            match String::from_utf8(fs::read(entry.path())?) {
+                Ok(contents) if !contents.contains('\0') => captured.push(Captured::File {
+                    path,
+                    contents,
+                    mode: (mode != FILE_MODE).then_some(mode),
+                }),
+                _ => captured.push(Captured::Skipped {
+                    path,
+                    reason: "binary".into(),
+                }),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A plan file recreating `captured` in `into`, which it creates first.
+/// Skipped entries are listed in a comment at the top.
+pub fn plan_toml(captured: &[Captured], into: &Path) -> String {
+    let mut out = String::from("# Generated by `skies capture`.\n");
+    for item in captured {
+        if let Captured::Skipped { path, reason } = item {
+            out.push_str(&format!("# skipped {}: {reason}\n", path.display()));
+        }
+    }
+    let into_dir = Captured::Dir {
+        path: PathBuf::new(),
+        mode: None,
+    };
+    for item in std::iter::once(&into_dir).chain(captured) {
+        let (kind, path, fields) = match item {
+            Captured::Dir { path, mode } => ("create_dir", path, mode_line(*mode)),
+            Captured::File {
+                path,
+                contents,
+                mode,
+            } => (
+                "file",
+                path,
+                format!("contents = {}\n{}", string(contents), mode_line(*mode)),
+            ),
+            Captured::Symlink { path, target } => (
+                "symlink",
+                path,
+                format!("target = {}\n", string(&target.display().to_string())),
+            ),
+            Captured::Skipped { .. } => continue,
+        };
+        let path = match path.as_os_str().is_empty() {
+            true => into.to_path_buf(),
+            false => into.join(path),
+        };
+        out.push_str(&format!(
+            "\n[[shift]]\ntype = \"{kind}\"\npath = {}\n{fields}",
+            string(&path.display().to_string())
+        ));
+    }
+    out
+}
+
+/// `text` as a TOML string that loads back as `text`: `$` is doubled so
+/// it is not taken for a variable.
+fn string(text: &str) -> String {
+    toml::Value::String(text.replace('$', "$$")).to_string()
+}
+
+fn mode_line(mode: Option<u32>) -> String {
+    match mode {
+        Some(mode) => format!("mode = 0o{mode:o}\n"),
+        None => String::new(),
+    }
+}
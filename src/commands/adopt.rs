@@ -0,0 +1,53 @@
+use clap::Args;
+use skies::journal::{Journal, Operation, RunRecord, ShiftStatus};
+use skies::report::{ConsoleReporter, Fanout, JsonReporter, Reporter};
+use skies::ShiftResult;
+
+use super::target::Target;
+use super::Format;
+
+#[derive(Args)]
+pub struct AdoptArgs {
+    #[command(flatten)]
+    target: Target,
+    /// Show what would be adopted without writing the journal.
+    #[arg(long)]
+    dry_run: bool,
+}
+
+/// Records the shifts already in place as applied, with a hash of each
+/// target file as its baseline, so a machine set up by hand can be taken
+/// over without applying anything.
+pub fn adopt(args: AdoptArgs, format: Format) -> ShiftResult<()> {
+    let (plan, ctx) = args.target.load()?;
+    let journal_path = Journal::path_for(&args.target.plan);
+    let mut journal = Journal::load(&journal_path)?;
+    let mut run = RunRecord::start(Operation::Adopt);
+    run.plan_hash = Some(plan.content_hash());
+
+    let mut console = ConsoleReporter::new(super::style()).quiet(super::verbosity().quiet);
+    let mut json = JsonReporter::new(std::io::stdout());
+    let output: &mut dyn Reporter = match format {
+        Format::Human => &mut console,
+        Format::Json => &mut json,
+    };
+    let result = plan.adopt(&ctx, &mut Fanout(vec![output, &mut run]));
+    run.record_baselines(&plan, &ctx);
+    run.finish(result.is_ok());
+    if format == Format::Human {
+        let adopted = run
+            .shifts
+            .iter()
+            .filter(|shift| shift.status == ShiftStatus::Adopted)
+            .count();
+        println!("adopted {adopted} of {} shifts", plan.len());
+    }
+    if !args.dry_run {
+        journal.runs.push(run);
+        journal.save(&journal_path)?;
+        if result.is_ok() {
+            super::hook::mark_applied(&args.target.plan);
+        }
+    }
+    result
+}
@@ -0,0 +1,358 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use clap::Args;
+use serde_json::{json, Value};
+use skies::facts::Facts;
+use skies::{CancellationToken, Capability, ShiftError, ShiftResult};
+
+use super::run::cancel_on_interrupt;
+use super::target::parse_var;
+use super::Format;
+
+#[derive(Args)]
+pub struct AgentArgs {
+    /// Git repository holding the plans.
+    #[arg(long, value_name = "URL", required_unless_present = "url")]
+    repo: Option<String>,
+    /// Branch of `--repo` to follow.
+    #[arg(long, default_value = "main", requires = "repo")]
+    branch: String,
+    /// Download one plan file from here instead of using a repository;
+    /// `{hostname}` and other facts in it are filled in.
+    #[arg(long, value_name = "URL", conflicts_with = "repo")]
+    url: Option<String>,
+    /// Plan to apply, relative to the repository; the first that exists
+    /// wins. `{hostname}`, `{label.NAME}` and other facts are filled in.
+    #[arg(
+        long = "plan",
+        value_name = "PATH",
+        default_values = ["hosts/{hostname}.toml", "default.toml"]
+    )]
+    plans: Vec<String>,
+    /// Label this machine, both for picking its plan and as facts the
+    /// plan can test.
+    #[arg(long = "label", value_name = "NAME=VALUE", value_parser = parse_var)]
+    labels: Vec<(String, String)>,
+    /// Let the plans have these capabilities (see `skies describe`). Plans
+    /// are applied as untrusted, so one that needs anything else, say
+    /// after a change, is not applied.
+    #[arg(long, value_name = "CAPABILITY", value_delimiter = ',')]
+    accept: Vec<Capability>,
+    /// Where the checkout lives, along with the journal and state of the
+    /// runs applied from it. Plans are applied from the checkout's top, so
+    /// relative paths in them start there.
+    #[arg(long, value_name = "DIR", default_value = "/var/lib/skies/agent")]
+    dir: PathBuf,
+    /// Seconds between reconciles.
+    #[arg(long, value_name = "SECS", default_value_t = 900)]
+    interval: u64,
+    /// Reconcile once and exit, to run from a timer instead.
+    #[arg(long)]
+    once: bool,
+    /// POST a JSON report of each reconcile to this URL.
+    #[arg(long, value_name = "URL")]
+    report: Option<String>,
+    /// Environment variable holding a bearer token for `--report`.
+    #[arg(long, value_name = "VAR", requires = "report")]
+    report_token_env: Option<String>,
+}
+
+/// Pulls the machine's plan and applies it, every `--interval` seconds,
+/// so the machine converges on what the repository says and drift is
+/// corrected. Each apply only changes shifts that are not in place.
+pub fn agent(args: AgentArgs, format: Format) -> ShiftResult<()> {
+    let token = cancel_on_interrupt()?;
+    let mut facts = Facts::gather();
+    for (name, value) in &args.labels {
+        facts.set(format!("label.{name}"), value);
+    }
+    loop {
+        let report = reconcile(&args, &facts);
+        print_report(&report, format);
+        if let Some(url) = &args.report {
+            if let Err(err) = send_report(url, args.report_token_env.as_deref(), &report) {
+                eprintln!("warning: cannot send the report to {url}: {err}");
+            }
+        }
+        if args.once {
+            return match report["outcome"].as_str() {
+                Some("succeeded") => Ok(()),
+                _ => Err(ShiftError::Custom(
+                    report["error"]
+                        .as_str()
+                        .unwrap_or("the apply failed")
+                        .to_string(),
+                )),
+            };
+        }
+        // Stopping between reconciles is how the agent is meant to end.
+        if !wait(&token, Duration::from_secs(args.interval)) {
+            return Ok(());
+        }
+    }
+}
+
+/// Sleeps for `duration`; `false` if cancelled first.
+fn wait(token: &CancellationToken, duration: Duration) -> bool {
+    let step = Duration::from_millis(250);
+    let mut waited = Duration::ZERO;
+    while waited < duration {
+        if token.is_cancelled() {
+            return false;
+        }
+        thread::sleep(step);
+        waited += step;
+    }
+    !token.is_cancelled()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// One pull and apply, as the report sent for it.
+fn reconcile(args: &AgentArgs, facts: &Facts) -> Value {
+    let started_at = now();
+    let mut report = json!({
+        "hostname": facts.get("hostname"),
+        "labels": args.labels.iter().cloned().collect::<std::collections::BTreeMap<_, _>>(),
+        "started_at": started_at,
+    });
+    let result = pull(args, facts).and_then(|(plan, revision)| {
+        report["plan"] = json!(plan);
+        report["revision"] = json!(revision);
+        apply(args, &plan)
+    });
+    report["finished_at"] = json!(now());
+    match result {
+        Ok(events) => {
+            report["outcome"] = json!("succeeded");
+            report["events"] = json!(events);
+        }
+        Err((err, events)) => {
+            report["outcome"] = json!("failed");
+            report["error"] = json!(err);
+            report["events"] = json!(events);
+        }
+    }
+    report
+}
+
+type Failure = (String, Vec<Value>);
+
+fn failure(err: impl ToString) -> Failure {
+    (err.to_string(), Vec::new())
+}
+
+/// `template` with `{fact}` placeholders filled in, or `None` if it names
+/// a fact this machine does not have.
+fn fill(template: &str, facts: &Facts) -> Option<String> {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = start + rest[start..].find('}')?;
+        out.push_str(&rest[..start]);
+        out.push_str(facts.get(&rest[start + 1..end])?);
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    Some(out)
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<String, Failure> {
+    let out = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::null())
+        .output()
+        .map_err(failure)?;
+    if !out.status.success() {
+        return Err(failure(format!(
+            "`git {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&out.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).trim().to_string())
+}
+
+/// Where plans are applied from.
+fn workdir(args: &AgentArgs) -> PathBuf {
+    match args.url {
+        Some(_) => args.dir.clone(),
+        None => args.dir.join("repo"),
+    }
+}
+
+/// Brings the plans up to date and picks this machine's, returning its
+/// path and the revision it is at.
+fn pull(args: &AgentArgs, facts: &Facts) -> Result<(PathBuf, String), Failure> {
+    std::fs::create_dir_all(&args.dir).map_err(failure)?;
+    if let Some(url) = &args.url {
+        let url = fill(url, facts)
+            .ok_or_else(|| failure(format!("`{url}` names a fact this machine lacks")))?;
+        let plan = args.dir.join("plan.toml");
+        let partial = args.dir.join("plan.toml.partial");
+        let out = Command::new("curl")
+            .args(["-fsSL", "-o"])
+            .arg(&partial)
+            .arg(&url)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(failure)?;
+        if !out.status.success() {
+            return Err(failure(format!(
+                "cannot download {url}: {}",
+                String::from_utf8_lossy(&out.stderr).trim()
+            )));
+        }
+        std::fs::rename(&partial, &plan).map_err(failure)?;
+        let text = std::fs::read(&plan).map_err(failure)?;
+        return Ok((plan, skies::hash::short_hash(text, 12)));
+    }
+    let repo = args.repo.as_deref().unwrap_or_default();
+    let checkout = workdir(args);
+    if checkout.join(".git").is_dir() {
+        git(&checkout, &["remote", "set-url", "origin", repo])?;
+        git(&checkout, &["fetch", "--quiet", "origin", &args.branch])?;
+        // Untracked files, such as each plan's `.skies` journal and
+        // state, are left alone.
+        git(&checkout, &["reset", "--quiet", "--hard", "FETCH_HEAD"])?;
+    } else {
+        let checkout = checkout.display().to_string();
+        git(
+            &args.dir,
+            &[
+                "clone",
+                "--quiet",
+                "--branch",
+                &args.branch,
+                repo,
+                &checkout,
+            ],
+        )?;
+    }
+    let revision = git(&checkout, &["rev-parse", "HEAD"])?;
+    let plan = args
+        .plans
+        .iter()
+        .filter_map(|template| fill(template, facts))
+        .map(|path| checkout.join(path))
+        .find(|path| path.is_file())
+        .ok_or_else(|| {
+            failure(format!(
+                "none of the plans {} exist for this machine",
+                args.plans.join(", ")
+            ))
+        })?;
+    Ok((plan, revision))
+}
+
+/// Applies `plan` with the agent's labels, returning the events the apply
+/// printed.
+fn apply(args: &AgentArgs, plan: &Path) -> Result<Vec<Value>, Failure> {
+    let exe = std::env::current_exe().map_err(failure)?;
+    if !args.labels.is_empty() {
+        let out = Command::new(&exe)
+            .args(["machine", "label"])
+            .arg(plan)
+            .args(
+                args.labels
+                    .iter()
+                    .map(|(name, value)| format!("{name}={value}")),
+            )
+            .stdin(Stdio::null())
+            .output()
+            .map_err(failure)?;
+        if !out.status.success() {
+            return Err(failure(String::from_utf8_lossy(&out.stderr).trim()));
+        }
+    }
+    let out = Command::new(&exe)
+        .args([
+            "apply",
+            "--format",
+            "json",
+            "--non-interactive",
+            "--untrusted",
+        ])
+        .args(
+            args.accept
+                .iter()
+                .flat_map(|capability| ["--accept".to_string(), capability.to_string()]),
+        )
+        .arg(plan)
+        .current_dir(workdir(args))
+        .stdin(Stdio::null())
+        .output()
+        .map_err(failure)?;
+    let events: Vec<Value> = String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    if out.status.success() {
+        Ok(events)
+    } else {
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        let error = stderr
+            .lines()
+            .find_map(|line| line.strip_prefix("error: "))
+            .unwrap_or("the apply failed");
+        Err((error.to_string(), events))
+    }
+}
+
+fn print_report(report: &Value, format: Format) {
+    if format == Format::Json {
+        println!("{report}");
+        return;
+    }
+    let plan = report["plan"].as_str().unwrap_or("(no plan)");
+    let revision: String = report["revision"]
+        .as_str()
+        .unwrap_or_default()
+        .chars()
+        .take(12)
+        .collect();
+    let changed = report["events"].as_array().map_or(0, |events| {
+        events
+            .iter()
+            .filter(|event| event["event"] == "applied")
+            .count()
+    });
+    match report["error"].as_str() {
+        Some(err) => eprintln!("reconcile of {plan} at {revision} failed: {err}"),
+        None => println!("reconciled {plan} at {revision}: {changed} changed"),
+    }
+}
+
+fn send_report(url: &str, token_env: Option<&str>, report: &Value) -> ShiftResult<()> {
+    let mut curl = Command::new("curl");
+    curl.args(["-fsS", "-X", "POST", "-H", "Content-Type: application/json"]);
+    if let Some(var) = token_env {
+        let token =
+            std::env::var(var).map_err(|_| ShiftError::Custom(format!("`{var}` is not set")))?;
+        curl.args(["-H", &format!("Authorization: Bearer {token}")]);
+    }
+    let mut child = curl
+        .args(["--data-binary", "@-", url])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(report.to_string().as_bytes())?;
+    }
+    if !child.wait()?.success() {
+        return Err(ShiftError::Custom("curl failed".into()));
+    }
+    Ok(())
+}
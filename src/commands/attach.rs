@@ -0,0 +1,129 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use clap::Args;
+use skies::journal::{Journal, RunOutcome};
+use skies::{ShiftError, ShiftResult};
+
+use super::Format;
+
+#[derive(Args)]
+pub struct AttachArgs {
+    /// Path to the plan file.
+    #[arg(env = "SKIES_PLAN")]
+    plan: PathBuf,
+}
+
+/// Where a detached run's console output goes.
+fn log_path(plan: &Path) -> PathBuf {
+    Journal::path_for(plan).with_file_name("detached.log")
+}
+
+fn pid_path(plan: &Path) -> PathBuf {
+    Journal::path_for(plan).with_file_name("detached.pid")
+}
+
+/// Whether the process `pid` is still running.
+fn is_alive(pid: u32) -> bool {
+    let Ok(stat) = fs::read_to_string(format!("/proc/{pid}/stat")) else {
+        return false;
+    };
+    // A zombie has exited; its state follows the parenthesized name.
+    let state = stat.rsplit_once(')').map(|(_, rest)| rest.trim_start());
+    !state.is_some_and(|state| state.starts_with('Z'))
+}
+
+fn running_pid(plan: &Path) -> Option<u32> {
+    let pid = fs::read_to_string(pid_path(plan))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    is_alive(pid).then_some(pid)
+}
+
+/// Starts `skies` again with the same arguments, less `--detach`, in the
+/// background, with its output going to `.skies/detached.log`.
+pub fn detach(plan: &Path, format: Format) -> ShiftResult<()> {
+    if let Some(pid) = running_pid(plan) {
+        return Err(ShiftError::Custom(format!(
+            "a detached run of this plan is still going (pid {pid})"
+        ))
+        .hint("follow it with `skies attach`"));
+    }
+    let log_path = log_path(plan);
+    if let Some(dir) = log_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let log = File::create(&log_path)?;
+    let args = env::args_os().skip(1).filter(|arg| arg != "--detach");
+    let child = Command::new(env::current_exe()?)
+        .args(args)
+        .env("SKIES_NON_INTERACTIVE", "1")
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log)
+        // Out of the terminal's process group, so closing the terminal
+        // or the SSH session does not take the run with it.
+        .process_group(0)
+        .spawn()
+        .map_err(|err| ShiftError::from(err).context("starting the detached run"))?;
+    fs::write(pid_path(plan), format!("{}\n", child.id()))?;
+    match format {
+        Format::Json => println!(
+            "{}",
+            serde_json::json!({ "event": "detached", "pid": child.id(), "log": log_path })
+        ),
+        Format::Human => println!(
+            "applying in the background (pid {}); follow it with `skies attach {}`",
+            child.id(),
+            plan.display()
+        ),
+    }
+    Ok(())
+}
+
+/// Prints the detached run's output so far, then follows it until the run
+/// ends, and fails if the run did. Ctrl-C stops following, not the run.
+pub fn attach(args: AttachArgs) -> ShiftResult<()> {
+    let log_path = log_path(&args.plan);
+    let mut log = File::open(&log_path).map_err(|err| {
+        ShiftError::from(err)
+            .context(format!("opening {}", log_path.display()))
+            .hint("start a run with `skies apply --detach`")
+    })?;
+    let pid = running_pid(&args.plan);
+    let mut stdout = io::stdout();
+    let mut buf = Vec::new();
+    loop {
+        // Checked before reading, so what the run printed last is read
+        // too.
+        let alive = pid.is_some_and(is_alive);
+        buf.clear();
+        log.read_to_end(&mut buf)?;
+        stdout.write_all(&buf)?;
+        stdout.flush()?;
+        if !alive {
+            break;
+        }
+        if buf.is_empty() {
+            thread::sleep(Duration::from_millis(200));
+        }
+    }
+    let journal = Journal::load(&Journal::path_for(&args.plan))?;
+    match journal.last_run().map(|run| (run.operation, run.outcome)) {
+        Some((operation, RunOutcome::Failed)) => Err(ShiftError::Custom(format!(
+            "the detached {operation} failed"
+        ))),
+        Some((operation, RunOutcome::Running)) => Err(ShiftError::Custom(format!(
+            "the detached {operation} stopped before it finished"
+        ))),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+use clap::Subcommand;
+use serde_json::json;
+use skies::download_cache::{DownloadCache, CACHE_DIR_ENV};
+use skies::{ShiftError, ShiftResult};
+
+use super::Format;
+
+#[derive(Subcommand)]
+pub enum CacheCommand {
+    /// Print where downloads are cached.
+    Dir,
+    /// Remove cached downloads that no run has used for a while.
+    Gc {
+        /// Keep what was used within this many days; 0 empties the cache.
+        #[arg(long, value_name = "DAYS", default_value_t = 30)]
+        unused_for: u64,
+        /// Only report what would be removed.
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+pub fn run(command: CacheCommand, format: Format) -> ShiftResult<()> {
+    let cache = DownloadCache::default_dir()
+        .map(DownloadCache::new)
+        .ok_or_else(|| {
+            ShiftError::Custom("the download cache is off".into()).hint(format!(
+                "unset {CACHE_DIR_ENV}, or set it or HOME to a directory"
+            ))
+        })?;
+    match command {
+        CacheCommand::Dir => {
+            println!("{}", cache.dir().display());
+            Ok(())
+        }
+        CacheCommand::Gc {
+            unused_for,
+            dry_run,
+        } => {
+            let report = cache.gc(Duration::from_secs(unused_for * 24 * 60 * 60), dry_run)?;
+            match format {
+                Format::Json => println!(
+                    "{}",
+                    json!({
+                        "removed": report.removed,
+                        "freed": report.freed,
+                        "kept": report.kept,
+                        "dry_run": dry_run,
+                    })
+                ),
+                Format::Human => {
+                    let verb = if dry_run { "would remove" } else { "removed" };
+                    println!(
+                        "{verb} {} file(s), {:.1} MiB; {} download(s) kept",
+                        report.removed,
+                        report.freed as f64 / (1024.0 * 1024.0),
+                        report.kept
+                    );
+                }
+            }
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use std::fs;
+use std::path::PathBuf;
+
+use clap::Args;
+use skies::capture::{self, Captured, DEFAULT_MAX_SIZE};
+use skies::ShiftResult;
+
+#[derive(Args)]
+pub struct CaptureArgs {
+    /// Directory tree to capture.
+    source: PathBuf,
+    /// Directory the plan recreates the tree in. Defaults to SOURCE itself.
+    #[arg(long, value_name = "DIR")]
+    into: Option<PathBuf>,
+    /// Skip files and directories with this name.
+    #[arg(long, value_name = "NAME")]
+    exclude: Vec<String>,
+    /// Skip files larger than this many bytes.
+    #[arg(long, value_name = "BYTES", default_value_t = DEFAULT_MAX_SIZE)]
+    max_size: u64,
+    /// Write the plan here instead of to stdout.
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<PathBuf>,
+}
+
+pub fn capture(args: CaptureArgs) -> ShiftResult<()> {
+    let captured = capture::scan(&args.source, &args.exclude, args.max_size)?;
+    let into = args.into.as_ref().unwrap_or(&args.source);
+    let plan = capture::plan_toml(&captured, into);
+    let skipped = captured
+        .iter()
+        .filter(|item| matches!(item, Captured::Skipped { .. }))
+        .count();
+    match &args.output {
+        Some(path) => {
+            fs::write(path, plan)?;
+            eprintln!(
+                "wrote {} shifts to {}",
+                captured.len() - skipped + 1,
+                path.display()
+            );
+        }
+        None => print!("{plan}"),
+    }
+    if skipped > 0 {
+        eprintln!("skipped {skipped} files; see the comments at the top of the plan");
+    }
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use std::collections::BTreeSet;
+use std::io::{IsTerminal, Write};
+
+use clap::builder::FalseyValueParser;
+use clap::Args;
+use skies::journal::{Consent, Journal};
+use skies::{Capability, ExecutionContext, ShiftError, ShiftPlan, ShiftResult};
+
+/// Flags for applying a plan from elsewhere.
+#[derive(Args)]
+pub struct ConsentArgs {
+    /// The plan came from elsewhere: before its first apply, and whenever
+    /// a change needs more than was agreed to, list what it needs (network,
+    /// sudo, writing outside its directory, running its own commands) and
+    /// ask.
+    #[arg(long, env = "SKIES_UNTRUSTED", value_parser = FalseyValueParser::new())]
+    pub untrusted: bool,
+    /// Agree to these capabilities without asking, as in a non-interactive
+    /// run.
+    #[arg(
+        long,
+        value_name = "CAPABILITY",
+        value_delimiter = ',',
+        requires = "untrusted"
+    )]
+    pub accept: Vec<Capability>,
+}
+
+impl ConsentArgs {
+    /// Fails unless what `plan` needs has been agreed to, asking if it
+    /// has not and `interactive` allows. What is agreed to is kept in
+    /// `journal`.
+    pub fn check(
+        &self,
+        plan: &ShiftPlan,
+        ctx: &ExecutionContext,
+        journal: &mut Journal,
+        interactive: bool,
+    ) -> ShiftResult<()> {
+        if !self.untrusted {
+            return Ok(());
+        }
+        let needs = plan.capabilities(ctx)?;
+        let wanted: BTreeSet<Capability> = needs.iter().flat_map(|(_, c)| c).copied().collect();
+        let mut agreed: BTreeSet<Capability> = self.accept.iter().copied().collect();
+        if let Some(consent) = &journal.consent {
+            agreed.extend(&consent.capabilities);
+        }
+        let new: BTreeSet<Capability> = wanted.difference(&agreed).copied().collect();
+        if !new.is_empty() {
+            let first = journal.consent.is_none();
+            eprintln!(
+                "{} needs to be allowed to:",
+                if first {
+                    "this plan"
+                } else {
+                    "this plan has changed and now"
+                }
+            );
+            for capability in &new {
+                let shifts: Vec<&str> = needs
+                    .iter()
+                    .filter(|(_, needs)| needs.contains(capability))
+                    .map(|(entry, _)| entry.id())
+                    .collect();
+                eprintln!(
+                    "  {capability}: {} ({})",
+                    capability.description(),
+                    shifts.join(", ")
+                );
+            }
+            if !(interactive && std::io::stdin().is_terminal() && ask("apply it?")?) {
+                let problems = needs
+                    .iter()
+                    .filter_map(|(entry, needs)| {
+                        let names: Vec<&str> = needs.intersection(&new).map(|c| c.name()).collect();
+                        (!names.is_empty()).then(|| {
+                            let err = ShiftError::PermissionDenied(format!(
+                                "needs {}, which was not agreed to",
+                                names.join(" and ")
+                            ));
+                            (entry.id().to_string(), err)
+                        })
+                    })
+                    .collect();
+                let names: Vec<&str> = new.iter().map(|c| c.name()).collect();
+                return Err(ShiftError::Preflight(problems).hint(format!(
+                    "run it in a terminal to be asked, or pass --accept {}",
+                    names.join(",")
+                )));
+            }
+        }
+        agreed.extend(wanted);
+        journal.consent = Some(Consent::new(agreed, plan.content_hash()));
+        Ok(())
+    }
+}
+
+/// Asks a yes-or-no question on the terminal; no unless answered yes.
+pub(super) fn ask(question: &str) -> ShiftResult<bool> {
+    eprint!("{question} [y/N] ");
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
@@ -0,0 +1,55 @@
+use std::fs;
+use std::path::PathBuf;
+
+use clap::Args;
+use skies::devcontainer::{DevContainer, DEFAULT_IMAGE};
+use skies::ShiftResult;
+
+use super::target::Target;
+
+#[derive(Args)]
+pub struct DevcontainerArgs {
+    #[command(flatten)]
+    target: Target,
+    /// The project's dev container directory; the workspace is the
+    /// directory holding it.
+    #[arg(long, value_name = "DIR", default_value = ".devcontainer")]
+    dir: PathBuf,
+    /// Image for a devcontainer.json written from scratch.
+    #[arg(long, value_name = "IMAGE", default_value = DEFAULT_IMAGE)]
+    image: String,
+    /// The skies binary to install in the container, instead of this one;
+    /// it has to run on the image, such as a static Linux build.
+    #[arg(long, value_name = "PATH")]
+    binary: Option<PathBuf>,
+}
+
+/// Writes the feature, then a devcontainer.json that uses it, or the
+/// settings to add to an existing one.
+pub fn devcontainer(args: DevcontainerArgs) -> ShiftResult<()> {
+    // Fail now rather than when the container is created.
+    args.target.load()?;
+    let devcontainer = DevContainer::new(&args.dir);
+    let plan = devcontainer.plan_path(&args.target.plan)?;
+    let binary = match args.binary {
+        Some(binary) => binary,
+        None => std::env::current_exe()?,
+    };
+    devcontainer.install(&binary)?;
+    let forwarded = args.target.forwarded(&plan);
+    let path = devcontainer.config_path();
+    eprintln!(
+        "wrote the skies feature to {}",
+        devcontainer.feature_dir().display()
+    );
+    if path.exists() {
+        let config = devcontainer.config(&forwarded, None);
+        eprintln!("{} exists; add these settings to it:", path.display());
+        println!("{:#}", config);
+    } else {
+        let config = devcontainer.config(&forwarded, Some(&args.image));
+        fs::write(&path, format!("{:#}\n", config))?;
+        eprintln!("wrote {}", path.display());
+    }
+    Ok(())
+}
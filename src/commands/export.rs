@@ -0,0 +1,62 @@
+use std::fs;
+use std::path::PathBuf;
+
+use clap::{Args, ValueEnum};
+use skies::ShiftResult;
+use skies::{export, permissions};
+
+use super::target::Target;
+
+#[derive(Args)]
+pub struct ExportArgs {
+    #[command(flatten)]
+    target: Target,
+    /// What to render the plan as.
+    #[arg(long = "to", value_name = "FORMAT")]
+    to: ExportFormat,
+    /// Image a Dockerfile builds on; its name tells which package manager
+    /// installs packages.
+    #[arg(long, value_name = "IMAGE", default_value = "debian:stable-slim")]
+    base: String,
+    /// Write it here instead of to stdout.
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    /// A standalone bash script.
+    Bash,
+    /// A Dockerfile, for an image set up as the plan sets up a machine.
+    Dockerfile,
+}
+
+pub fn export(args: ExportArgs) -> ShiftResult<()> {
+    let (plan, _) = args.target.load()?;
+    let mut exported = export::export(&plan)?;
+    let source = args.target.plan.display().to_string();
+    let text = match args.to {
+        ExportFormat::Bash => export::bash::render(&exported, &source),
+        ExportFormat::Dockerfile => export::dockerfile::render(&mut exported, &source, &args.base),
+    };
+    match &args.output {
+        Some(path) => {
+            fs::write(path, text)?;
+            if let (ExportFormat::Bash, Some(mode)) = (args.to, permissions::mode_of(path)?) {
+                permissions::set_mode(path, mode | 0o111)?;
+            }
+            eprintln!(
+                "wrote {} shifts to {}",
+                exported.steps.len(),
+                path.display()
+            );
+        }
+        None => print!("{text}"),
+    }
+    match exported.todo_count() {
+        0 => {}
+        1 => eprintln!("1 TODO left to do by hand; see the comments in the output"),
+        n => eprintln!("{n} TODOs left to do by hand; see the comments in the output"),
+    }
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use serde_json::json;
+use skies::artifacts::{ArtifactCache, Fetched};
+use skies::network::Transfers;
+use skies::{Shift, ShiftError, ShiftResult};
+
+use super::target::Target;
+use super::Format;
+
+#[derive(Args)]
+pub struct FetchArgs {
+    #[command(flatten)]
+    target: Target,
+    /// Directory to download into; fetching again into the same one only
+    /// adds what is missing and refreshes repositories.
+    #[arg(long, value_name = "DIR")]
+    cache: PathBuf,
+}
+
+/// Downloads everything the plan's shifts would, so `apply --offline`
+/// can run from the cache.
+pub fn fetch(args: FetchArgs, format: Format) -> ShiftResult<()> {
+    let (plan, ctx) = args.target.load()?;
+    let cache = ArtifactCache::new(&args.cache);
+    let mut ctx = ctx.with_artifacts(cache.clone());
+    if !plan.network().is_unlimited() {
+        ctx = ctx.with_transfers(Transfers::new(*plan.network()));
+    }
+    let mut failed = 0;
+    for artifact in plan.artifacts(&ctx)? {
+        ctx.check_cancelled()?;
+        let what = artifact.describe();
+        let (status, detail) = match cache.fetch(&ctx, &artifact) {
+            Ok(Fetched::Downloaded) => ("fetched", None),
+            Ok(Fetched::Cached) => ("cached", None),
+            Ok(Fetched::Unsupported(why)) => ("skipped", Some(why)),
+            Err(err) => {
+                failed += 1;
+                ("failed", Some(err.to_string()))
+            }
+        };
+        match format {
+            Format::Json => println!(
+                "{}",
+                json!({ "artifact": what, "status": status, "detail": detail })
+            ),
+            Format::Human => match detail {
+                Some(detail) => println!("{status:>8}  {what}: {detail}"),
+                None => println!("{status:>8}  {what}"),
+            },
+        }
+    }
+    if failed > 0 {
+        return Err(ShiftError::Custom(format!(
+            "{failed} artifact(s) could not be fetched"
+        )));
+    }
+    Ok(())
+}
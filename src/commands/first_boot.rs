@@ -0,0 +1,78 @@
+use std::path::PathBuf;
+
+use clap::{Args, Subcommand};
+use serde_json::json;
+use skies::first_boot::{FirstBoot, FirstBootStatus, DEFAULT_LOG};
+use skies::ShiftResult;
+
+use super::target::Target;
+use super::Format;
+
+#[derive(Subcommand)]
+pub enum FirstBootCommand {
+    /// Stage a plan and enable a unit that applies it at the next boot.
+    Install {
+        #[command(flatten)]
+        target: Target,
+        #[command(flatten)]
+        root: Root,
+        /// Append the apply's output here on the booted machine.
+        #[arg(long, value_name = "PATH", default_value = DEFAULT_LOG)]
+        log: PathBuf,
+    },
+    /// Remove the unit and the staged plan.
+    Uninstall {
+        #[command(flatten)]
+        root: Root,
+    },
+    /// Show whether the first-boot plan is pending or done.
+    Status {
+        #[command(flatten)]
+        root: Root,
+    },
+}
+
+#[derive(Args)]
+pub struct Root {
+    /// Install into this directory instead of `/`, e.g. a mounted image.
+    #[arg(long, value_name = "DIR", default_value = "/")]
+    root: PathBuf,
+}
+
+pub fn run(command: FirstBootCommand, format: Format) -> ShiftResult<()> {
+    match command {
+        FirstBootCommand::Install { target, root, log } => {
+            // Fail now rather than at boot if the plan does not load.
+            target.load()?;
+            let first_boot = FirstBoot::new(&root.root).log(&log);
+            let args = target.forwarded(&FirstBoot::plan_path(&target.plan));
+            first_boot.install(&target.plan, &args)?;
+            eprintln!(
+                "{} will apply at the next boot of {}; output goes to {}",
+                target.plan.display(),
+                root.root.display(),
+                log.display()
+            );
+            Ok(())
+        }
+        FirstBootCommand::Uninstall { root } => FirstBoot::new(&root.root).uninstall(),
+        FirstBootCommand::Status { root } => {
+            let status = match FirstBoot::new(&root.root).status() {
+                FirstBootStatus::NotInstalled => "not installed",
+                FirstBootStatus::Pending => "pending",
+                FirstBootStatus::Done => "done",
+            };
+            match format {
+                Format::Json => println!(
+                    "{}",
+                    json!({
+                        "status": status,
+                        "result_file": FirstBoot::result_path(),
+                    })
+                ),
+                Format::Human => println!("{status}"),
+            }
+            Ok(())
+        }
+    }
+}
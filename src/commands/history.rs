@@ -0,0 +1,99 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use clap::Subcommand;
+use serde_json::json;
+use skies::journal::{format_timestamp, Journal, RunRecord};
+use skies::{ShiftError, ShiftResult};
+
+use super::Format;
+
+#[derive(Subcommand)]
+pub enum HistoryCommand {
+    /// List past runs of a plan, oldest first.
+    List {
+        /// Path to the plan file.
+        plan: PathBuf,
+        /// Serve the runs as a web dashboard instead, with each shift's
+        /// timeline, failures and the changes from the run before.
+        #[cfg(feature = "web")]
+        #[arg(long)]
+        web: bool,
+        /// Address the dashboard listens on.
+        #[cfg(feature = "web")]
+        #[arg(
+            long,
+            value_name = "ADDR",
+            default_value = "127.0.0.1:7373",
+            requires = "web"
+        )]
+        listen: String,
+    },
+    /// Show the shifts, errors and kept temp workspace of one run.
+    Show {
+        /// Path to the plan file.
+        plan: PathBuf,
+        /// Run number as shown by `history list`; defaults to the latest.
+        run: Option<usize>,
+    },
+}
+
+pub fn run(command: HistoryCommand, format: Format) -> ShiftResult<()> {
+    match command {
+        #[cfg(feature = "web")]
+        HistoryCommand::List {
+            plan,
+            web: true,
+            listen,
+        } => super::web::history(&plan, &listen),
+        HistoryCommand::List { plan, .. } => {
+            let journal = Journal::load(&Journal::path_for(&plan))?;
+            if format == Format::Json {
+                println!("{}", json!(journal.runs));
+                return Ok(());
+            }
+            for (idx, run) in journal.runs.iter().enumerate() {
+                println!("{:>4}  {}", idx + 1, headline(run));
+            }
+            Ok(())
+        }
+        HistoryCommand::Show { plan, run } => {
+            let journal = Journal::load(&Journal::path_for(&plan))?;
+            let number = run.unwrap_or(journal.runs.len());
+            let record = number
+                .checked_sub(1)
+                .and_then(|idx| journal.runs.get(idx))
+                .ok_or_else(|| ShiftError::Custom(format!("no run #{number} in the journal")))?;
+            if format == Format::Json {
+                println!("{}", json!(record));
+                return Ok(());
+            }
+            println!("run #{number}: {}", headline(record));
+            if let Some(finished) = record.finished_at {
+                println!("finished: {}", format_timestamp(finished));
+            }
+            if let Some(dir) = &record.temp_dir {
+                let note = if dir.exists() { "" } else { " (since removed)" };
+                println!("temp workspace: {}{note}", dir.display());
+            }
+            for shift in &record.shifts {
+                match &shift.error {
+                    Some(err) => println!("  {:<12} {}: {err}", shift.status, shift.id),
+                    None => println!("  {:<12} {}", shift.status, shift.id),
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+fn headline(run: &RunRecord) -> String {
+    let shifts: HashSet<&str> = run.shifts.iter().map(|s| s.id.as_str()).collect();
+    format!(
+        "{}  {:<7} {:<9} {} shifts",
+        format_timestamp(run.started_at),
+        run.operation,
+        run.outcome,
+        shifts.len()
+    )
+}
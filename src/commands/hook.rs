@@ -0,0 +1,318 @@
+use std::env;
+use std::fs;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+use clap::{Args, Subcommand, ValueEnum};
+use skies::export::quote;
+use skies::hash::short_hash;
+use skies::journal::Journal;
+use skies::{ShiftError, ShiftResult};
+
+use super::consent::ask;
+use super::target::Target;
+
+/// Written beside the journal each time a hook checks the plan; its age
+/// is the cooldown's.
+const STAMP: &str = "hook-checked";
+
+#[derive(Subcommand)]
+pub enum HookCommand {
+    /// Print code for a shell's startup file that checks the plan whenever
+    /// the shell enters a directory that has one.
+    Shell {
+        #[arg(long, value_enum, default_value_t = HookShell::Bash)]
+        shell: HookShell,
+        /// Offer to apply what is not applied, asking each time.
+        #[arg(long)]
+        apply: bool,
+        #[command(flatten)]
+        options: HookOptions,
+    },
+    /// Print lines for a project's `.envrc` that check its plan whenever
+    /// direnv loads it.
+    Direnv {
+        #[command(flatten)]
+        options: HookOptions,
+    },
+    /// Check a plan the way the hooks do: quietly, at most once per
+    /// cooldown, and only if it was applied here before.
+    Check {
+        /// Offer to apply what is not applied, when run in a terminal.
+        #[arg(long)]
+        apply: bool,
+        /// Seconds to wait before checking the same plan again, unless it
+        /// changes.
+        #[arg(long, value_name = "SECS", default_value_t = 900)]
+        cooldown: u64,
+        /// The plan file.
+        #[arg(default_value = "skies.toml")]
+        plan: PathBuf,
+    },
+    /// Fail unless every shift of the plans is applied, for a git
+    /// `pre-commit` hook in the repository that holds them.
+    PreCommit {
+        /// Only list the shifts that are not applied; let the commit go on.
+        #[arg(long)]
+        allow_drift: bool,
+        /// The plan files.
+        #[arg(default_value = "skies.toml")]
+        plans: Vec<PathBuf>,
+    },
+}
+
+#[derive(Args)]
+pub struct HookOptions {
+    /// Name of the plan file the hook looks for.
+    #[arg(long, value_name = "FILE", default_value = "skies.toml")]
+    plan: String,
+    /// Seconds to wait before checking the same plan again, unless it
+    /// changes.
+    #[arg(long, value_name = "SECS", default_value_t = 900)]
+    cooldown: u64,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum HookShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+pub fn run(command: HookCommand) -> ShiftResult<()> {
+    match command {
+        HookCommand::Shell {
+            shell,
+            apply,
+            options,
+        } => {
+            print!("{}", shell_hook(shell, &check_command(&options, apply)));
+            Ok(())
+        }
+        HookCommand::Direnv { options } => {
+            println!(
+                "# Check that {} is applied whenever direnv loads this directory.",
+                options.plan
+            );
+            println!("{} || true", check_command(&options, false));
+            Ok(())
+        }
+        HookCommand::Check {
+            apply,
+            cooldown,
+            plan,
+        } => {
+            if let Err(err) = check(&plan, Duration::from_secs(cooldown), apply) {
+                eprintln!("skies: cannot check {}: {err}", plan.display());
+            }
+            Ok(())
+        }
+        HookCommand::PreCommit { allow_drift, plans } => pre_commit(&plans, allow_drift),
+    }
+}
+
+/// The `skies hook check` a hook runs.
+fn check_command(options: &HookOptions, apply: bool) -> String {
+    let apply = if apply { " --apply" } else { "" };
+    format!(
+        "skies hook check{apply} --cooldown {} {}",
+        options.cooldown,
+        quote(&options.plan)
+    )
+}
+
+fn shell_hook(shell: HookShell, check: &str) -> String {
+    match shell {
+        HookShell::Bash => format!(
+            r#"# Added by `eval "$(skies hook shell --shell bash)"` in ~/.bashrc.
+_skies_hook() {{
+    if [ "${{_skies_hook_dir:-}}" != "$PWD" ]; then
+        _skies_hook_dir=$PWD
+        {check}
+    fi
+}}
+case ";${{PROMPT_COMMAND:-}};" in
+    *";_skies_hook;"*) ;;
+    *) PROMPT_COMMAND="_skies_hook${{PROMPT_COMMAND:+;$PROMPT_COMMAND}}" ;;
+esac
+"#
+        ),
+        HookShell::Zsh => format!(
+            r#"# Added by `eval "$(skies hook shell --shell zsh)"` in ~/.zshrc.
+_skies_hook() {{
+    {check}
+}}
+autoload -Uz add-zsh-hook
+add-zsh-hook chpwd _skies_hook
+_skies_hook
+"#
+        ),
+        HookShell::Fish => format!(
+            r#"# Added by `skies hook shell --shell fish | source` in config.fish.
+function _skies_hook --on-variable PWD
+    {check}
+end
+_skies_hook
+"#
+        ),
+    }
+}
+
+/// Reports shifts of `plan` that are not applied, and with `apply` offers
+/// to apply them. Plans never applied here are not loaded, since
+/// checking a shift can run commands; they get a hint instead. What was
+/// applied is told by [`mark_applied`], not the journal beside the plan,
+/// which a cloned repository could bring along.
+fn check(plan: &Path, cooldown: Duration, apply: bool) -> ShiftResult<()> {
+    if !plan.is_file() {
+        return Ok(());
+    }
+    let journal_path = Journal::path_for(plan);
+    let stamp = journal_path.with_file_name(STAMP);
+    if checked_since(&stamp, plan, cooldown) {
+        return Ok(());
+    }
+    if let Some(dir) = stamp.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&stamp, "")?;
+    if !was_applied(plan) || Journal::load(&journal_path)?.last_apply().is_none() {
+        eprintln!(
+            "skies: {0} has not been applied here; `skies apply {0}` sets it up",
+            plan.display()
+        );
+        return Ok(());
+    }
+    let (pending, total) = pending(plan)?;
+    if pending.is_empty() {
+        return Ok(());
+    }
+    eprintln!(
+        "skies: {} of {total} shifts in {} are not applied",
+        pending.len(),
+        plan.display()
+    );
+    if !(apply && std::io::stdin().is_terminal()) {
+        eprintln!("skies: `skies apply {}` applies them", plan.display());
+        return Ok(());
+    }
+    if ask("apply them now?")? {
+        let status = Command::new(std::env::current_exe()?)
+            .arg("apply")
+            .arg(plan)
+            .status()?;
+        if !status.success() {
+            return Err(ShiftError::Custom("the apply failed".into()));
+        }
+    }
+    Ok(())
+}
+
+/// Records that `plan` was applied here by this user, for hooks to check
+/// it from now on.
+pub fn mark_applied(plan: &Path) {
+    let Some((marker, canonical)) = applied_marker(plan) else {
+        return;
+    };
+    let written = marker
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| fs::write(&marker, canonical.to_string_lossy().as_bytes()));
+    if let Err(err) = written {
+        eprintln!("warning: cannot write {}: {err}", marker.display());
+    }
+}
+
+/// Whether [`mark_applied`] recorded `plan`.
+fn was_applied(plan: &Path) -> bool {
+    applied_marker(plan).is_some_and(|(marker, canonical)| {
+        fs::read(marker).is_ok_and(|text| text == canonical.to_string_lossy().as_bytes())
+    })
+}
+
+/// Where [`mark_applied`] records `plan`, named for a hash of its
+/// canonical path, and that path: under `$XDG_STATE_HOME/skies/applied`,
+/// else `~/.local/state/skies/applied`. `None` when the plan is missing or
+/// there is no home directory.
+fn applied_marker(plan: &Path) -> Option<(PathBuf, PathBuf)> {
+    let canonical = fs::canonicalize(plan).ok()?;
+    let state = match env::var_os("XDG_STATE_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME").filter(|home| !home.is_empty())?)
+            .join(".local")
+            .join("state"),
+    };
+    let name = short_hash(canonical.to_string_lossy().as_bytes(), 32);
+    Some((state.join("skies").join("applied").join(name), canonical))
+}
+
+/// Checks each of `plans` before a commit, failing on the first that
+/// cannot be loaded or, unless `allow_drift`, is not applied.
+fn pre_commit(plans: &[PathBuf], allow_drift: bool) -> ShiftResult<()> {
+    for plan in plans {
+        let (pending, total) =
+            pending(plan).map_err(|err| err.context(format!("checking {}", plan.display())))?;
+        if pending.is_empty() {
+            continue;
+        }
+        eprintln!(
+            "skies: {} of {total} shifts in {} are not applied:",
+            pending.len(),
+            plan.display()
+        );
+        for id in &pending {
+            eprintln!("  {id}");
+        }
+        if !allow_drift {
+            return Err(
+                ShiftError::Custom(format!("{} is not applied", plan.display()))
+                    .hint("apply it before committing, or commit with `git commit --no-verify`"),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The IDs of the shifts in `plan` that are not applied, and how many
+/// shifts it has.
+fn pending(plan: &Path) -> ShiftResult<(Vec<String>, usize)> {
+    let target = Target {
+        plan: plan.to_path_buf(),
+        only: Vec::new(),
+        tags: Vec::new(),
+        vars: Vec::new(),
+        params: Vec::new(),
+        policies: Vec::new(),
+        sandbox: None,
+        root: None,
+    };
+    let (loaded, ctx) = target.load()?;
+    let ctx = loaded.context(&ctx);
+    let entries = loaded.entries();
+    let pending = entries
+        .iter()
+        .filter(|entry| {
+            let ctx = ctx
+                .for_shift(entry.id())
+                .allowing_outside_root(entry.allow_outside_root);
+            !matches!(entry.shift.is_applied(&ctx), Ok(true))
+        })
+        .map(|entry| entry.id().to_string())
+        .collect();
+    Ok((pending, entries.len()))
+}
+
+/// Whether a hook checked `plan` less than `cooldown` ago, and it has not
+/// changed since.
+fn checked_since(stamp: &Path, plan: &Path, cooldown: Duration) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    let Some(checked) = modified(stamp) else {
+        return false;
+    };
+    let fresh = SystemTime::now()
+        .duration_since(checked)
+        .is_ok_and(|age| age < cooldown);
+    fresh && modified(plan).is_some_and(|changed| changed <= checked)
+}
@@ -0,0 +1,57 @@
+use std::fs;
+use std::path::PathBuf;
+
+use clap::{Args, ValueEnum};
+use skies::import::{self, Imported};
+use skies::ShiftResult;
+
+#[derive(Args)]
+pub struct ImportArgs {
+    /// What SOURCE is.
+    #[arg(long = "from", value_name = "FORMAT")]
+    from: ImportFormat,
+    /// File to convert.
+    source: PathBuf,
+    /// Write the plan here instead of to stdout.
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ImportFormat {
+    /// An Ansible playbook or task file.
+    Ansible,
+    /// A shell setup script.
+    Shell,
+    /// A Brewfile, as `brew bundle` takes.
+    Brewfile,
+    /// A Dockerfile, its directory the build context.
+    Dockerfile,
+}
+
+pub fn import(args: ImportArgs) -> ShiftResult<()> {
+    let imported: Imported = match args.from {
+        ImportFormat::Ansible => import::ansible::import(&args.source)?,
+        ImportFormat::Shell => import::shell::import(&args.source)?,
+        ImportFormat::Brewfile => import::brewfile::import(&args.source)?,
+        ImportFormat::Dockerfile => import::dockerfile::import(&args.source)?,
+    };
+    let plan = imported.to_toml();
+    match &args.output {
+        Some(path) => {
+            fs::write(path, plan)?;
+            eprintln!(
+                "wrote {} shifts to {}",
+                imported.shift_count(),
+                path.display()
+            );
+        }
+        None => print!("{plan}"),
+    }
+    match imported.todo_count() {
+        0 => {}
+        1 => eprintln!("1 TODO left to finish by hand; see the comments in the plan"),
+        n => eprintln!("{n} TODOs left to finish by hand; see the comments in the plan"),
+    }
+    Ok(())
+}
@@ -0,0 +1,241 @@
+use serde_json::json;
+use skies::journal::Journal;
+use skies::lockfile::{Lock, Lockfile};
+use skies::{plan_file, ExecutionContext, PlanEntry, Registry, ShiftError, ShiftPlan, ShiftResult};
+
+use super::target::Target;
+use super::Format;
+
+pub fn status(target: Target, format: Format) -> ShiftResult<()> {
+    let (plan, ctx) = target.load()?;
+    let journal = Journal::load(&Journal::path_for(&target.plan))?;
+    print_status(&plan, &ctx, &journal, format)
+}
+
+/// Checks every expression in the plan ahead of time, then that the plan
+/// loads.
+pub fn lint(target: Target) -> ShiftResult<()> {
+    let problems = target.lint()?;
+    for problem in &problems {
+        println!("{problem}");
+    }
+    if !problems.is_empty() {
+        return Err(ShiftError::Plan(format!(
+            "{} problem(s) in the plan's expressions",
+            problems.len()
+        )));
+    }
+    target.load_with(&target.sample()?)?;
+    println!("no problems found");
+    Ok(())
+}
+
+/// Prints a JSON Schema of plan files, covering every built-in shift type.
+pub fn schema() -> ShiftResult<()> {
+    let schema = plan_file::schema(&Registry::builtin());
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&schema).unwrap_or_default()
+    );
+    Ok(())
+}
+
+/// Prints what each shift type's fields are, when it counts as applied
+/// and what revert does, or just that of `kind`.
+pub fn docs(kind: Option<String>, format: Format) -> ShiftResult<()> {
+    let registry = Registry::builtin();
+    let types = match kind {
+        None => skies::docs::all(&registry),
+        Some(kind) => match skies::docs::for_kind(&registry, &kind) {
+            Some(doc) => vec![doc],
+            None => {
+                let err = ShiftError::Plan(format!("unknown shift type `{kind}`"));
+                return Err(err.hint("`skies docs` lists every type"));
+            }
+        },
+    };
+    if format == Format::Json {
+        println!("{}", json!(types));
+        return Ok(());
+    }
+    for doc in &types {
+        println!("{doc}");
+    }
+    println!("Every entry also takes the common fields id, depends_on, tags, when,");
+    println!("os, hostname, facts, time_limit, serial_group, allow_outside_root and verify.");
+    Ok(())
+}
+
+pub fn validate(target: Target) -> ShiftResult<()> {
+    let (plan, ctx) = target.load()?;
+    plan.preflight(&ctx)?;
+    println!("plan is valid");
+    Ok(())
+}
+
+pub fn describe(target: Target, format: Format) -> ShiftResult<()> {
+    let (plan, ctx) = target.load()?;
+    print_description(&plan, &ctx, format)
+}
+
+/// Lists what the plan fetches from outside the machine, with what each is
+/// pinned to, from the plan or its lockfile.
+pub fn inputs(target: Target, format: Format) -> ShiftResult<()> {
+    let (plan, ctx) = target.load()?;
+    let ctx = ctx.with_lock(Lock::open(Lockfile::path_for(&target.plan))?);
+    let mut rows = Vec::new();
+    for (entry, inputs) in plan.external_inputs(&ctx)? {
+        for input in inputs {
+            match format {
+                Format::Json => {
+                    let mut row = json!(input);
+                    row["id"] = json!(entry.id());
+                    rows.push(row);
+                }
+                Format::Human => match &input.pinned {
+                    Some(pin) => {
+                        println!("  pinned  {}: {} at {pin}", entry.id(), input.describe())
+                    }
+                    None => println!("floating  {}: {}", entry.id(), input.describe()),
+                },
+            }
+        }
+    }
+    if format == Format::Json {
+        println!("{}", json!(rows));
+    }
+    Ok(())
+}
+
+pub fn explain(target: Target, format: Format) -> ShiftResult<()> {
+    let (plan, ctx) = target.load()?;
+    if format == Format::Human {
+        print!("{}", plan_file::render(&plan, ctx.vars())?);
+        return Ok(());
+    }
+    let entry_json = |entry: &PlanEntry| {
+        json!({
+            "id": entry.id(),
+            "depends_on": entry.depends_on,
+            "tags": entry.tags,
+            "definition": entry.definition(),
+            "origin": entry.origin(),
+        })
+    };
+    let shifts: Vec<_> = plan
+        .execution_order()?
+        .into_iter()
+        .map(|idx| entry_json(&plan.entries()[idx]))
+        .collect();
+    let checks: Vec<_> = plan.checks().iter().map(entry_json).collect();
+    println!(
+        "{}",
+        json!({
+            "root": plan.root(),
+            "vars": ctx.vars(),
+            "shifts": shifts,
+            "verify": checks,
+        })
+    );
+    Ok(())
+}
+
+fn print_status(
+    plan: &ShiftPlan,
+    ctx: &ExecutionContext,
+    journal: &Journal,
+    format: Format,
+) -> ShiftResult<()> {
+    let hash = plan.content_hash();
+    let applied_hash = journal
+        .last_apply()
+        .and_then(|run| run.plan_hash.as_deref());
+    let version = match applied_hash {
+        None => "not applied",
+        Some(applied) if applied == hash => "current",
+        Some(_) => "changed since last apply",
+    };
+    if format == Format::Human {
+        println!("plan {}: {version}", &hash[..12]);
+    }
+    let ctx = plan.context(ctx);
+    let mut rows = Vec::new();
+    for idx in plan.execution_order()? {
+        let entry = &plan.entries()[idx];
+        let entry_ctx = ctx
+            .for_shift(entry.id())
+            .allowing_outside_root(entry.allow_outside_root);
+        let state = match entry.shift.is_applied(&entry_ctx) {
+            Ok(true) => "applied",
+            Ok(false) => "pending",
+            Err(_) => "unknown",
+        };
+        let meta = entry.shift.metadata();
+        match format {
+            Format::Human => println!("{:<8} {:<40} {}", state, entry.id(), meta),
+            Format::Json => rows.push(json!({
+                "id": entry.id(),
+                "state": state,
+                "metadata": meta.redacted(),
+            })),
+        }
+    }
+    if format == Format::Json {
+        println!(
+            "{}",
+            json!({
+                "plan_hash": hash,
+                "applied_plan_hash": applied_hash,
+                "plan": version,
+                "shifts": rows,
+            })
+        );
+    }
+    Ok(())
+}
+
+fn print_description(plan: &ShiftPlan, ctx: &ExecutionContext, format: Format) -> ShiftResult<()> {
+    let mut rows = Vec::new();
+    if format == Format::Human {
+        for param in plan.params() {
+            println!("param {param}");
+        }
+    }
+    // The plan's checks come last; they are not described.
+    let capabilities = plan.capabilities(ctx)?;
+    for (entry, needs) in capabilities.into_iter().take(plan.entries().len()) {
+        let meta = entry.shift.metadata().redacted();
+        if format == Format::Json {
+            rows.push(json!({
+                "id": entry.id(),
+                "tags": entry.tags,
+                "depends_on": entry.depends_on,
+                "metadata": meta,
+                "capabilities": needs,
+            }));
+            continue;
+        }
+        println!("{} ({})", entry.id(), meta.kind);
+        println!("  {}", meta.summary);
+        for target in &meta.targets {
+            println!("  target: {}", target.display());
+        }
+        for input in &meta.inputs {
+            println!("  {} = {}", input.name, input.value);
+        }
+        if !entry.depends_on.is_empty() {
+            println!("  depends on: {}", entry.depends_on.join(", "));
+        }
+        if !entry.tags.is_empty() {
+            println!("  tags: {}", entry.tags.join(", "));
+        }
+        if !needs.is_empty() {
+            let needs: Vec<_> = needs.iter().map(|c| c.name()).collect();
+            println!("  needs: {}", needs.join(", "));
+        }
+    }
+    if format == Format::Json {
+        println!("{}", json!(rows));
+    }
+    Ok(())
+}
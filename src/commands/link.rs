@@ -0,0 +1,33 @@
+use std::fs;
+use std::path::PathBuf;
+
+use clap::Args;
+use skies::{dotfiles, ShiftResult};
+
+#[derive(Args)]
+pub struct LinkArgs {
+    /// Dotfiles directory whose layout mirrors the link directory.
+    source: PathBuf,
+    /// Directory to create the links in; becomes the plan's root.
+    #[arg(long, default_value = "~")]
+    into: String,
+    /// Skip files and directories with this name.
+    #[arg(long, value_name = "NAME")]
+    exclude: Vec<String>,
+    /// Write the plan here instead of to stdout.
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<PathBuf>,
+}
+
+pub fn link(args: LinkArgs) -> ShiftResult<()> {
+    let links = dotfiles::scan(&args.source, &args.exclude)?;
+    let plan = dotfiles::plan_toml(&links, &args.into);
+    match &args.output {
+        Some(path) => {
+            fs::write(path, plan)?;
+            eprintln!("wrote {} links to {}", links.len(), path.display());
+        }
+        None => print!("{plan}"),
+    }
+    Ok(())
+}
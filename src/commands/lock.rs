@@ -0,0 +1,111 @@
+use clap::Args;
+use serde_json::json;
+use skies::artifacts::Artifact;
+use skies::lockfile::{Lockfile, LOCKFILE};
+use skies::{ExecutionContext, Shift, ShiftError, ShiftPlan, ShiftResult};
+
+use super::target::Target;
+use super::Format;
+
+#[derive(Args)]
+pub struct UpdateLockArgs {
+    #[command(flatten)]
+    target: Target,
+}
+
+/// Fails, naming each shift and what it leaves floating, unless every
+/// input of `plan` is pinned; see [`skies::inputs`].
+pub fn check_pinned(plan: &ShiftPlan, ctx: &ExecutionContext) -> ShiftResult<()> {
+    let problems: Vec<(String, ShiftError)> = plan
+        .external_inputs(ctx)?
+        .into_iter()
+        .filter_map(|(entry, inputs)| {
+            let floating: Vec<String> = inputs
+                .iter()
+                .filter(|input| !input.is_pinned())
+                .map(|input| input.describe())
+                .collect();
+            (!floating.is_empty()).then(|| {
+                let err = ShiftError::Custom(format!("not pinned: {}", floating.join(", ")));
+                (entry.id().to_string(), err)
+            })
+        })
+        .collect();
+    if problems.is_empty() {
+        return Ok(());
+    }
+    Err(ShiftError::Preflight(problems).hint(
+        "give exact versions, digests or revisions in the plan; \
+         run `skies update-lock` to pin downloads and clones",
+    ))
+}
+
+/// Resolves every download and clone in the plan from the network again,
+/// and writes them to its lockfile in place of what was there.
+pub fn update_lock(args: UpdateLockArgs, format: Format) -> ShiftResult<()> {
+    let (plan, ctx) = args.target.load()?;
+    let path = Lockfile::path_for(&args.target.plan);
+    let old = Lockfile::load(&path)?;
+    let mut new = Lockfile::default();
+    let mut failed = 0;
+    for artifact in plan.artifacts(&ctx)? {
+        ctx.check_cancelled()?;
+        let (key, pins, old) = match &artifact {
+            Artifact::File { url } => (url.clone(), &mut new.downloads, &old.downloads),
+            Artifact::Git { url, branch, .. } => (
+                Lockfile::git_key(url, branch.as_deref()),
+                &mut new.git,
+                &old.git,
+            ),
+            Artifact::Packages(_) => continue,
+        };
+        let (status, detail) = match artifact.resolve(&ctx) {
+            Ok(Some(pin)) => {
+                let status = match old.get(&key) {
+                    None => "added",
+                    Some(old) if *old == pin => "unchanged",
+                    Some(_) => "updated",
+                };
+                pins.insert(key.clone(), pin.clone());
+                (status, pin)
+            }
+            Ok(None) => continue,
+            Err(err) => {
+                failed += 1;
+                ("failed", err.to_string())
+            }
+        };
+        match format {
+            Format::Json => println!(
+                "{}",
+                json!({ "artifact": key, "status": status, "detail": detail })
+            ),
+            Format::Human => println!("{status:>9}  {key}: {detail}"),
+        }
+    }
+    let removed = old
+        .git
+        .keys()
+        .filter(|key| !new.git.contains_key(*key))
+        .chain(
+            old.downloads
+                .keys()
+                .filter(|key| !new.downloads.contains_key(*key)),
+        );
+    for key in removed {
+        match format {
+            Format::Json => println!("{}", json!({ "artifact": key, "status": "removed" })),
+            Format::Human => println!("{:>9}  {key}", "removed"),
+        }
+    }
+    // A partial refresh would drop what failed to resolve.
+    if failed > 0 {
+        return Err(ShiftError::Custom(format!(
+            "{failed} artifact(s) could not be resolved; {LOCKFILE} was left as it was"
+        )));
+    }
+    if new != old || !path.exists() {
+        new.save(&path)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,72 @@
+use std::path::PathBuf;
+
+use clap::Subcommand;
+use serde_json::json;
+use skies::journal::Journal;
+use skies::ShiftResult;
+
+use super::target::parse_var;
+use super::Format;
+
+#[derive(Subcommand)]
+pub enum MachineCommand {
+    /// Show the machine's ID and labels.
+    Show {
+        /// Path to the plan file.
+        plan: PathBuf,
+    },
+    /// Set labels, available to the plan as `label.<name>` facts.
+    Label {
+        /// Path to the plan file.
+        plan: PathBuf,
+        #[arg(required = true, value_name = "NAME=VALUE", value_parser = parse_var)]
+        labels: Vec<(String, String)>,
+    },
+    /// Remove labels.
+    Unlabel {
+        /// Path to the plan file.
+        plan: PathBuf,
+        #[arg(required = true, value_name = "NAME")]
+        names: Vec<String>,
+    },
+}
+
+pub fn run(command: MachineCommand, format: Format) -> ShiftResult<()> {
+    match command {
+        MachineCommand::Show { plan } => {
+            let journal = Journal::load(&Journal::path_for(&plan))?;
+            let machine = &journal.machine;
+            if format == Format::Json {
+                println!("{}", json!(machine));
+                return Ok(());
+            }
+            match machine.id.as_str() {
+                "" => println!("id: (assigned on first apply)"),
+                id => println!("id: {id}"),
+            }
+            for (name, value) in &machine.labels {
+                println!("label.{name} = {value}");
+            }
+            Ok(())
+        }
+        MachineCommand::Label { plan, labels } => {
+            let path = Journal::path_for(&plan);
+            let mut journal = Journal::load(&path)?;
+            journal.machine.ensure_id();
+            for (name, value) in &labels {
+                journal.machine.label(name, value)?;
+            }
+            journal.save(&path)
+        }
+        MachineCommand::Unlabel { plan, names } => {
+            let path = Journal::path_for(&plan);
+            let mut journal = Journal::load(&path)?;
+            for name in &names {
+                if journal.machine.labels.remove(name).is_none() {
+                    eprintln!("warning: no label `{name}`");
+                }
+            }
+            journal.save(&path)
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use skies::journal::{Journal, Operation, RunRecord};
+use skies::migrations::{Migration, Migrations};
+use skies::{CancellationToken, ShiftResult};
+
+use super::provision::Provisioning;
+use super::run::{cancel_on_interrupt, record};
+use super::target::{parse_var, Target};
+use super::Format;
+
+/// A migrations directory and how to run its plans.
+#[derive(Args)]
+pub struct MigrationsDir {
+    /// Directory of numbered plan files such as `001_init.toml`.
+    #[arg(default_value = ".")]
+    dir: PathBuf,
+    /// Set a plan variable in every migration.
+    #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_var)]
+    vars: Vec<(String, String)>,
+    /// Hold every migration to this policy as well as the system one.
+    #[arg(long = "policy", value_name = "PATH", env = "SKIES_POLICY")]
+    policies: Vec<PathBuf>,
+    #[command(flatten)]
+    provision: Provisioning,
+}
+
+#[derive(Args)]
+pub struct UpArgs {
+    #[command(flatten)]
+    migrations: MigrationsDir,
+    /// Stop after this version instead of applying every migration.
+    #[arg(long, value_name = "VERSION")]
+    to: Option<u64>,
+}
+
+#[derive(Args)]
+pub struct DownArgs {
+    #[command(flatten)]
+    migrations: MigrationsDir,
+    /// Revert every migration newer than this version (0 reverts all);
+    /// defaults to reverting only the newest.
+    #[arg(long, value_name = "VERSION")]
+    to: Option<u64>,
+}
+
+/// Where the migrations are and what the journal says is applied.
+struct Session {
+    args: MigrationsDir,
+    migrations: Migrations,
+    journal: Journal,
+    cancel: CancellationToken,
+}
+
+impl Session {
+    fn open(args: MigrationsDir) -> ShiftResult<Session> {
+        let migrations = Migrations::discover(&args.dir)?;
+        let journal = Journal::load(&migrations.journal_path())?;
+        Ok(Session {
+            args,
+            migrations,
+            journal,
+            cancel: cancel_on_interrupt()?,
+        })
+    }
+
+    /// Applies or reverts one migration, recording it in the journal.
+    fn run(
+        &mut self,
+        migration: &Migration,
+        operation: Operation,
+        format: Format,
+    ) -> ShiftResult<()> {
+        let target = Target {
+            plan: migration.path.clone(),
+            only: Vec::new(),
+            tags: Vec::new(),
+            vars: self.args.vars.clone(),
+            params: Vec::new(),
+            policies: self.args.policies.clone(),
+            sandbox: None,
+            root: None,
+        };
+        let (plan, ctx) = target.load()?;
+        let ctx = ctx
+            .with_interactive(!self.args.provision.non_interactive)
+            .with_cancellation(self.cancel.clone());
+        eprintln!("{operation} {}", migration.path.display());
+        let mut run = RunRecord::start(operation);
+        run.plan_hash = Some(plan.content_hash());
+        run.migration = Some(migration.version);
+        let journal_path = self.migrations.journal_path();
+        record(
+            &mut self.journal,
+            Some(&journal_path),
+            &ctx,
+            run,
+            &self.args.provision,
+            format.into(),
+            |reporter| match operation {
+                Operation::Apply => plan.apply_with(&ctx, reporter),
+                Operation::Revert => plan.revert_with(&ctx, reporter),
+                Operation::Adopt => plan.adopt(&ctx, reporter),
+            },
+        )
+    }
+
+    fn up(&mut self, to: Option<u64>, format: Format) -> ShiftResult<()> {
+        let applied = self.journal.applied_migrations();
+        let pending: Vec<Migration> = self
+            .migrations
+            .pending(&applied, to)?
+            .into_iter()
+            .cloned()
+            .collect();
+        for migration in &pending {
+            self.run(migration, Operation::Apply, format)?;
+        }
+        self.report();
+        Ok(())
+    }
+
+    fn down(&mut self, to: Option<u64>, format: Format) -> ShiftResult<()> {
+        let applied = self.journal.applied_migrations();
+        let to = to.unwrap_or_else(|| applied.iter().rev().nth(1).copied().unwrap_or(0));
+        let reverting: Vec<Migration> = self
+            .migrations
+            .to_revert(&applied, to)?
+            .into_iter()
+            .cloned()
+            .collect();
+        for migration in &reverting {
+            self.run(migration, Operation::Revert, format)?;
+        }
+        self.report();
+        Ok(())
+    }
+
+    fn report(&self) {
+        let current = Migrations::current(&self.journal.applied_migrations());
+        eprintln!(
+            "{} is at version {current}",
+            self.migrations.dir().display()
+        );
+    }
+}
+
+/// Applies pending migrations, oldest first.
+pub fn up(args: UpArgs, format: Format) -> ShiftResult<()> {
+    Session::open(args.migrations)?.up(args.to, format)
+}
+
+/// Reverts applied migrations, newest first.
+pub fn down(args: DownArgs, format: Format) -> ShiftResult<()> {
+    Session::open(args.migrations)?.down(args.to, format)
+}
+
+/// Reverts the newest applied migration and applies it again.
+pub fn redo(args: MigrationsDir, format: Format) -> ShiftResult<()> {
+    let mut session = Session::open(args)?;
+    let Some(&newest) = session.journal.applied_migrations().last() else {
+        session.report();
+        return Ok(());
+    };
+    session.down(None, format)?;
+    session.up(Some(newest), format)
+}
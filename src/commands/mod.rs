@@ -0,0 +1,125 @@
+//! Implementations of the `skies` subcommands.
+
+pub mod adopt;
+pub mod agent;
+pub mod attach;
+pub mod cache;
+pub mod capture;
+pub mod consent;
+pub mod devcontainer;
+pub mod export;
+pub mod fetch;
+pub mod first_boot;
+pub mod history;
+pub mod hook;
+pub mod import;
+pub mod inspect;
+pub mod link;
+pub mod lock;
+pub mod machine;
+pub mod migrate;
+pub mod outputs;
+pub mod provision;
+pub mod run;
+pub mod serve;
+pub mod target;
+#[cfg(feature = "tui")]
+pub mod tui;
+#[cfg(feature = "web")]
+pub mod web;
+
+use std::sync::{Arc, OnceLock};
+
+use clap::ValueEnum;
+use skies::context::{FileLogger, LogLevel, Logger, StderrLogger, TeeLogger};
+use skies::report::{ColorChoice, ConsoleStyle};
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Human,
+    Json,
+}
+
+/// `--color`, mirroring [`ColorChoice`].
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Color {
+    Auto,
+    Always,
+    Never,
+}
+
+static COLOR: OnceLock<ColorChoice> = OnceLock::new();
+
+/// Settles `--color` for the rest of the process.
+pub fn set_color(color: Color) {
+    let _ = COLOR.set(match color {
+        Color::Auto => ColorChoice::Auto,
+        Color::Always => ColorChoice::Always,
+        Color::Never => ColorChoice::Never,
+    });
+}
+
+fn color() -> ColorChoice {
+    COLOR.get().copied().unwrap_or_default()
+}
+
+/// How to print to stdout under `--color`.
+pub fn style() -> ConsoleStyle {
+    ConsoleStyle::stdout(color())
+}
+
+/// How to print to stderr under `--color`.
+pub fn error_style() -> ConsoleStyle {
+    ConsoleStyle::stderr(color())
+}
+
+/// `-q`, `-v` and `--log-file`.
+#[derive(Default)]
+pub struct Verbosity {
+    pub quiet: bool,
+    pub verbose: u8,
+    /// Gets every message and event, whatever the console shows.
+    pub log_file: Option<Arc<FileLogger>>,
+}
+
+static VERBOSITY: OnceLock<Verbosity> = OnceLock::new();
+
+/// Settles the verbosity for the rest of the process.
+pub fn set_verbosity(verbosity: Verbosity) {
+    let _ = VERBOSITY.set(verbosity);
+}
+
+pub fn verbosity() -> &'static Verbosity {
+    VERBOSITY.get_or_init(Verbosity::default)
+}
+
+impl Verbosity {
+    pub(super) fn console_level(&self) -> LogLevel {
+        match (self.quiet, self.verbose) {
+            (true, _) => LogLevel::Warn,
+            (false, 0) => LogLevel::Info,
+            (false, 1) => LogLevel::Debug,
+            (false, _) => LogLevel::Trace,
+        }
+    }
+
+    /// Logs to stderr at the console level and to the log file, if any.
+    pub fn logger(&self) -> TeeLogger {
+        let mut loggers: Vec<Arc<dyn Logger>> = vec![Arc::new(StderrLogger {
+            min: self.console_level(),
+        })];
+        if let Some(file) = &self.log_file {
+            loggers.push(file.clone());
+        }
+        TeeLogger(loggers)
+    }
+
+    /// Flags giving a run elsewhere the same console verbosity. The log
+    /// file is local and not passed on.
+    pub fn forwarded(&self) -> Vec<String> {
+        if self.quiet {
+            return vec!["--quiet".into()];
+        }
+        (0..self.verbose).map(|_| "--verbose".into()).collect()
+    }
+}
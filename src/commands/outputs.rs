@@ -0,0 +1,56 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use serde_json::{json, Map};
+use skies::journal::Journal;
+use skies::{ShiftError, ShiftResult};
+
+use super::Format;
+
+#[derive(Args)]
+pub struct OutputsArgs {
+    /// Path to the plan file.
+    plan: PathBuf,
+    /// Print only this output, as `<shift id>.<name>`, without quoting.
+    output: Option<String>,
+    /// Show sensitive outputs instead of `[redacted]`.
+    #[arg(long)]
+    reveal: bool,
+}
+
+pub fn outputs(args: OutputsArgs, format: Format) -> ShiftResult<()> {
+    let journal = Journal::load(&Journal::path_for(&args.plan))?;
+    let outputs = journal.outputs();
+    if let Some(wanted) = &args.output {
+        let found = wanted
+            .rsplit_once('.')
+            .and_then(|(id, name)| outputs.get(id)?.iter().find(|output| output.name == name));
+        let output =
+            found.ok_or_else(|| ShiftError::Custom(format!("no output `{wanted}` recorded")))?;
+        match output.shown(args.reveal) {
+            serde_json::Value::String(text) if format == Format::Human => println!("{text}"),
+            value => println!("{value}"),
+        }
+        return Ok(());
+    }
+    if format == Format::Json {
+        let by_shift: Map<_, _> = outputs
+            .iter()
+            .map(|(id, list)| {
+                let values: Map<_, _> = list
+                    .iter()
+                    .map(|output| (output.name.clone(), output.shown(args.reveal)))
+                    .collect();
+                (id.to_string(), json!(values))
+            })
+            .collect();
+        println!("{}", json!(by_shift));
+        return Ok(());
+    }
+    for (id, list) in &outputs {
+        for output in *list {
+            println!("{id}.{} = {}", output.name, output.shown(args.reveal));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,251 @@
+//! Support for running under provisioners such as Vagrant and Packer.
+
+use std::fs;
+use std::path::PathBuf;
+
+use clap::builder::FalseyValueParser;
+use clap::{Args, ValueEnum};
+use serde_json::json;
+use skies::facts::Facts;
+use skies::journal::{Operation, RunRecord, ShiftStatus};
+use skies::junit;
+use skies::report::{add_error_json, gha_data};
+use skies::summary::RunSummary;
+use skies::{ShiftError, ShiftResult};
+
+use super::Format;
+
+/// The contract provisioners can rely on; shown by `skies apply --help`.
+pub const CONTRACT: &str = "\
+Provisioning contract:
+  Inputs can come from the environment instead of arguments:
+    SKIES_PLAN              path to the plan file
+    SKIES_VAR_<NAME>        plan variable NAME (--var takes precedence)
+    SKIES_MACHINE_READABLE  same as --machine-readable when 1 or true
+    SKIES_NON_INTERACTIVE   same as --non-interactive when 1 or true
+    SKIES_GHA               same as --gha when 1 or true
+    SKIES_RESULT_FILE       same as --result-file
+    SKIES_SUMMARY_FORMAT    same as --summary-format
+    SKIES_SUMMARY_FILE      same as --summary-file
+    SKIES_REPORT            same as --report
+
+  Exit codes:
+    0  the run succeeded (including when nothing needed to change)
+    1  a shift failed
+    2  bad arguments or an invalid plan
+    3  preflight checks failed; nothing was changed
+    4  a shift or the plan ran past its time limit
+  130  interrupted with Ctrl-C
+
+  With --machine-readable, stdout carries only JSON lines: one per event,
+  then a final {\"event\":\"result\",...} line. Logs go to stderr.
+
+  With --gha, each shift's output is folded into a GitHub Actions log
+  group, and failed shifts, or a run that failed outside any shift, are
+  ::error annotations on the workflow run and its pull request.
+
+  --result-file receives a single JSON object with `operation`, `outcome`,
+  `exit_code`, `error` (with `error_kind`, `error_chain` and `hints` on
+  failure) and, for local runs, `shifts` and `temp_dir`. It is written
+  whenever the plan could be loaded, even if the run failed.
+
+  --summary-format markdown or slack sums the run up as a chat message,
+  printed at the end (to stderr under --machine-readable) or written to
+  --summary-file. The slack one is a JSON payload for an incoming webhook.
+
+  --report junit=PATH writes a JUnit XML report for CI test views: each
+  shift is a test case with its duration, failing with its error if it
+  failed or timed out, skipped if it was rolled back.";
+
+pub const EXIT_FAILED: u8 = 1;
+pub const EXIT_INVALID: u8 = 2;
+pub const EXIT_PREFLIGHT: u8 = 3;
+pub const EXIT_TIMED_OUT: u8 = 4;
+pub const EXIT_CANCELLED: u8 = 130;
+
+/// The exit code for a command that failed with `err`.
+pub fn exit_code(err: &ShiftError) -> u8 {
+    match err.root() {
+        ShiftError::Plan(_) => EXIT_INVALID,
+        ShiftError::Preflight(_) => EXIT_PREFLIGHT,
+        ShiftError::TimedOut(_) => EXIT_TIMED_OUT,
+        ShiftError::Cancelled => EXIT_CANCELLED,
+        _ => EXIT_FAILED,
+    }
+}
+
+/// Flags for unattended runs.
+#[derive(Args)]
+pub struct Provisioning {
+    /// Only JSON on stdout, ending with a result line; implies `--format json`.
+    #[arg(long, env = "SKIES_MACHINE_READABLE", value_parser = FalseyValueParser::new())]
+    pub machine_readable: bool,
+    /// Never prompt: commands get no stdin and are asked not to prompt.
+    #[arg(long, env = "SKIES_NON_INTERACTIVE", value_parser = FalseyValueParser::new())]
+    pub non_interactive: bool,
+    /// Print GitHub Actions workflow commands: a log group per shift and
+    /// an annotation per failure.
+    #[arg(
+        long,
+        env = "SKIES_GHA",
+        value_parser = FalseyValueParser::new(),
+        conflicts_with = "machine_readable"
+    )]
+    pub gha: bool,
+    /// Write a JSON summary of the run to this file.
+    #[arg(long, value_name = "PATH", env = "SKIES_RESULT_FILE")]
+    pub result_file: Option<PathBuf>,
+    /// Sum up the run as a chat message in this format.
+    #[arg(long, value_name = "FORMAT", env = "SKIES_SUMMARY_FORMAT")]
+    pub summary_format: Option<SummaryFormat>,
+    /// Write the summary to this file instead of printing it.
+    #[arg(
+        long,
+        value_name = "PATH",
+        env = "SKIES_SUMMARY_FILE",
+        requires = "summary_format"
+    )]
+    pub summary_file: Option<PathBuf>,
+    /// Write a report for other tools, as KIND=PATH; KIND is `junit`.
+    #[arg(long = "report", value_name = "KIND=PATH", env = "SKIES_REPORT", value_parser = parse_report)]
+    pub reports: Vec<Report>,
+}
+
+/// A `--report` to write.
+#[derive(Clone)]
+pub enum Report {
+    /// JUnit XML, one test case per shift.
+    Junit(PathBuf),
+}
+
+fn parse_report(s: &str) -> Result<Report, String> {
+    let (kind, path) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected KIND=PATH, got `{s}`"))?;
+    if path.is_empty() {
+        return Err(format!("no path given for the {kind} report"));
+    }
+    match kind {
+        "junit" => Ok(Report::Junit(PathBuf::from(path))),
+        _ => Err(format!("unknown report kind `{kind}`; expected junit")),
+    }
+}
+
+/// `--summary-format`.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SummaryFormat {
+    /// Markdown, for Discord, GitHub comments and the like.
+    Markdown,
+    /// A Slack message payload with Block Kit blocks.
+    Slack,
+}
+
+impl Provisioning {
+    /// `format`, or JSON under `--machine-readable`.
+    pub fn format(&self, format: Format) -> Format {
+        if self.machine_readable {
+            Format::Json
+        } else {
+            format
+        }
+    }
+
+    /// Ends machine-readable output with a line describing how the run
+    /// ended. Under `--gha`, annotates a failure that no failed shift of
+    /// `run` accounts for, such as a failed preflight.
+    pub fn print_result(&self, operation: Operation, run: &RunRecord, result: &ShiftResult<()>) {
+        if self.machine_readable {
+            println!("{}", summary(operation, result));
+        }
+        let Err(err) = result else {
+            return;
+        };
+        let in_shift = run
+            .shifts
+            .iter()
+            .any(|shift| matches!(shift.status, ShiftStatus::Failed | ShiftStatus::TimedOut));
+        if self.gha && !in_shift {
+            println!(
+                "::error title=skies {operation} failed::{}",
+                gha_data(&err.to_string())
+            );
+        }
+    }
+
+    /// Writes the result file, the summary and the reports, if asked for.
+    /// `run` is the journal record of a local run.
+    pub fn write_result(
+        &self,
+        operation: Operation,
+        run: Option<&RunRecord>,
+        result: &ShiftResult<()>,
+    ) -> ShiftResult<()> {
+        self.write_summary(operation, run, result)?;
+        for report in &self.reports {
+            match report {
+                Report::Junit(path) => {
+                    fs::write(path, junit::render(operation, run, result.as_ref().err())).map_err(
+                        |err| ShiftError::from(err).context(format!("writing {}", path.display())),
+                    )?
+                }
+            }
+        }
+        let Some(path) = &self.result_file else {
+            return Ok(());
+        };
+        let mut summary = summary(operation, result);
+        if let Some(obj) = summary.as_object_mut() {
+            obj.remove("event");
+        }
+        if let Some(run) = run {
+            summary["started_at"] = json!(run.started_at);
+            summary["finished_at"] = json!(run.finished_at);
+            summary["shifts"] = json!(run.shifts);
+            summary["temp_dir"] = json!(run.temp_dir);
+        }
+        fs::write(path, format!("{summary:#}\n"))?;
+        Ok(())
+    }
+
+    fn write_summary(
+        &self,
+        operation: Operation,
+        run: Option<&RunRecord>,
+        result: &ShiftResult<()>,
+    ) -> ShiftResult<()> {
+        let Some(format) = self.summary_format else {
+            return Ok(());
+        };
+        // A run elsewhere happened on another machine.
+        let host = run.and_then(|_| Facts::gather().get("hostname").map(String::from));
+        let summary = RunSummary::new(operation, run, result.as_ref().err(), host);
+        let text = match format {
+            SummaryFormat::Markdown => summary.markdown(),
+            SummaryFormat::Slack => format!("{:#}\n", summary.slack()),
+        };
+        match &self.summary_file {
+            Some(path) => fs::write(path, text)?,
+            None if self.machine_readable => eprint!("{text}"),
+            None => print!("{text}"),
+        }
+        Ok(())
+    }
+}
+
+fn summary(operation: Operation, result: &ShiftResult<()>) -> serde_json::Value {
+    let (outcome, exit_code) = match result {
+        Ok(()) => ("succeeded", 0),
+        Err(err) => ("failed", exit_code(err)),
+    };
+    let mut summary = json!({
+        "event": "result",
+        "operation": operation,
+        "outcome": outcome,
+        "exit_code": exit_code,
+        "error": null,
+    });
+    if let Err(err) = result {
+        add_error_json(&mut summary, err);
+    }
+    summary
+}
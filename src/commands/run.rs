@@ -0,0 +1,791 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use clap::builder::FalseyValueParser;
+use clap::Args;
+use serde_json::json;
+use skies::artifacts::ArtifactCache;
+use skies::context::{Logger, MemoryLogger, TeeLogger};
+use skies::diagnostics::Diagnostics;
+use skies::exec::RealExec;
+use skies::journal::{Journal, Operation, RunOutcome, RunRecord, ShiftStatus};
+use skies::lockfile::{Lock, Lockfile};
+use skies::matrix::{label, Combination};
+use skies::monorepo::Monorepo;
+use skies::network::{parse_rate, NetworkPolicy};
+use skies::paths;
+use skies::policy::SYSTEM_POLICY;
+use skies::recording::{Recording, RecordingExec, ReplayExec};
+use skies::report::{
+    add_error_json, render_error, ConsoleReporter, Fanout, GhaReporter, JsonReporter, LogReporter,
+    PlanEvent, Reporter,
+};
+use skies::run_target::{RemoteRun, RunTarget};
+use skies::{ApplyOptions, CancellationToken, ExecutionContext, ShiftError, ShiftResult};
+
+use super::attach;
+use super::consent::ConsentArgs;
+use super::lock;
+use super::provision::{self, Provisioning, CONTRACT};
+use super::target::Target;
+#[cfg(feature = "tui")]
+use super::tui::{self, Tui};
+use super::Format;
+
+#[derive(Args)]
+#[command(after_long_help = CONTRACT)]
+pub struct ApplyArgs {
+    #[command(flatten)]
+    target: Target,
+    /// Leave already applied shifts in place when one fails.
+    #[arg(long)]
+    no_rollback: bool,
+    /// Skip shifts the last (failed) apply already completed.
+    #[arg(long)]
+    resume: bool,
+    /// Skip, without checking them, shifts whose inputs and targets are as
+    /// they were when a run last left them in place, and whose
+    /// dependencies are skipped too.
+    #[arg(long)]
+    incremental: bool,
+    /// Report what would change without changing anything.
+    #[arg(long)]
+    dry_run: bool,
+    /// If the run fails, write a `.tar.gz` of its full log, the journal,
+    /// the failing shifts and the machine's facts here.
+    #[arg(long, value_name = "PATH")]
+    diagnostics: Option<PathBuf>,
+    /// Run up to this many independent shifts at once.
+    #[arg(long, short = 'j', value_name = "N", default_value_t = 1)]
+    jobs: usize,
+    /// Save every command the run executes, with its output, to this file.
+    #[arg(long, value_name = "PATH", conflicts_with = "replay")]
+    record: Option<PathBuf>,
+    /// Answer commands from a file written by `--record` instead of running
+    /// them. Files are still written; the run is not journaled.
+    #[arg(long, value_name = "PATH")]
+    replay: Option<PathBuf>,
+    /// Take downloads, clones and apt packages from this `skies fetch`
+    /// cache when it has them.
+    #[arg(long, value_name = "DIR")]
+    cache: Option<PathBuf>,
+    /// Use only the `--cache`: anything missing from it fails instead of
+    /// being downloaded.
+    #[arg(long, requires = "cache")]
+    offline: bool,
+    /// Fail before changing anything if the plan fetches something it
+    /// does not pin: a branch, a download without a digest, a package or
+    /// toolchain without an exact version. Downloads and clones in
+    /// skies.lock count as pinned.
+    #[arg(long, env = "SKIES_DETERMINISTIC", value_parser = FalseyValueParser::new())]
+    deterministic: bool,
+    /// Run at most this many downloads and clones at once, over the
+    /// plan's `[network]` setting.
+    #[arg(long, value_name = "N")]
+    max_transfers: Option<usize>,
+    /// Hold downloads to this many bytes per second in total, e.g. `500K`
+    /// or `2M`, over the plan's `[network]` setting.
+    #[arg(long, value_name = "RATE", value_parser = parse_rate)]
+    rate_limit: Option<u64>,
+    /// Run in the background and return at once; follow the run with
+    /// `skies attach`. Implies `--non-interactive`.
+    #[arg(long)]
+    detach: bool,
+    /// Follow the run in a terminal UI, where pending shifts can be left
+    /// out and a failed shift retried or skipped.
+    #[cfg(feature = "tui")]
+    #[arg(long, conflicts_with_all = ["detach", "non_interactive", "recursive"])]
+    tui: bool,
+    /// Take PLAN as a workspace directory and apply every skies.toml under
+    /// it, each from its own directory and after the plans its `requires`
+    /// names; `--var`s apply to all of them.
+    #[arg(
+        long,
+        conflicts_with_all = ["only", "params", "resume", "detach", "record", "replay", "diagnostics"]
+    )]
+    recursive: bool,
+    /// With `--recursive`, skip plans that are the same as when they last
+    /// applied successfully, including what they use of the plans they
+    /// require, without checking their shifts.
+    #[arg(long, requires = "recursive")]
+    skip_unchanged: bool,
+    #[command(flatten)]
+    consent: ConsentArgs,
+    #[command(flatten)]
+    on: On,
+    #[command(flatten)]
+    provision: Provisioning,
+}
+
+impl ApplyArgs {
+    #[cfg(feature = "tui")]
+    fn tui(&self) -> bool {
+        self.tui
+    }
+
+    #[cfg(not(feature = "tui"))]
+    fn tui(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Args)]
+pub struct RevertArgs {
+    #[command(flatten)]
+    target: Target,
+    /// Report what would be reverted without changing anything.
+    #[arg(long)]
+    dry_run: bool,
+    /// Revert even where that loses local work, such as changes in a clone.
+    #[arg(long)]
+    force: bool,
+    #[command(flatten)]
+    on: On,
+    #[command(flatten)]
+    provision: Provisioning,
+}
+
+/// Where to run: here, or inside a chroot or container.
+#[derive(Args)]
+struct On {
+    /// Run the plan in `local`, `chroot:///path` or `docker://image`.
+    #[arg(long = "target", value_name = "TARGET", default_value = "local")]
+    target: RunTarget,
+    /// Save the container as this image after a docker run.
+    #[arg(long, value_name = "IMAGE")]
+    commit: Option<String>,
+}
+
+impl On {
+    /// Runs `skies <command>` in the target, or returns `None` if the
+    /// target is local and the caller should run the plan itself.
+    fn delegate(
+        &self,
+        command: &str,
+        target: &Target,
+        flags: &[(&str, bool)],
+        options: &[String],
+        provision: &Provisioning,
+        format: Format,
+    ) -> Option<ShiftResult<()>> {
+        if self.target != RunTarget::Local && !target.policies.is_empty() {
+            return Some(Err(ShiftError::Custom(
+                "--policy only works with the local target".into(),
+            )
+            .hint(format!(
+                "put the policy at {SYSTEM_POLICY} on the target instead"
+            ))));
+        }
+        if self.target == RunTarget::Local {
+            return self
+                .commit
+                .as_ref()
+                .map(|_| Err(ShiftError::Custom("--commit needs a docker target".into())));
+        }
+        let mut args = vec![command.to_string()];
+        args.extend(target.forwarded(&self.target.plan_path(&target.plan)));
+        let unattended = [
+            ("--machine-readable", provision.machine_readable),
+            ("--non-interactive", provision.non_interactive),
+            ("--gha", provision.gha),
+        ];
+        let flags = flags.iter().chain(&unattended);
+        args.extend(
+            flags
+                .filter(|(_, set)| *set)
+                .map(|(flag, _)| flag.to_string()),
+        );
+        args.extend(options.iter().cloned());
+        args.extend(super::verbosity().forwarded());
+        if format == Format::Json {
+            args.extend(["--format".to_string(), "json".to_string()]);
+        }
+        let run = RemoteRun {
+            args,
+            commit: self.commit.clone(),
+        };
+        Some(self.target.run(&target.plan, &run))
+    }
+}
+
+pub fn apply(args: ApplyArgs, format: Format) -> ShiftResult<()> {
+    let flags = [
+        ("--no-rollback", args.no_rollback),
+        ("--resume", args.resume),
+        ("--dry-run", args.dry_run),
+        ("--untrusted", args.consent.untrusted),
+        ("--deterministic", args.deterministic),
+        ("--incremental", args.incremental),
+    ];
+    let mut options = vec!["--jobs".to_string(), args.jobs.to_string()];
+    for capability in &args.consent.accept {
+        options.extend(["--accept".to_string(), capability.to_string()]);
+    }
+    if let Some(max) = args.max_transfers {
+        options.extend(["--max-transfers".to_string(), max.to_string()]);
+    }
+    if let Some(rate) = args.rate_limit {
+        options.extend(["--rate-limit".to_string(), rate.to_string()]);
+    }
+    if args.on.target != RunTarget::Local && (args.record.is_some() || args.replay.is_some()) {
+        return Err(ShiftError::Custom(
+            "--record and --replay only work with the local target".into(),
+        ));
+    }
+    if args.on.target != RunTarget::Local && args.recursive {
+        return Err(ShiftError::Custom(
+            "--recursive only works with the local target".into(),
+        ));
+    }
+    if args.on.target != RunTarget::Local && args.cache.is_some() {
+        return Err(ShiftError::Custom(
+            "--cache only works with the local target".into(),
+        ));
+    }
+    if args.detach {
+        return attach::detach(&args.target.plan, format);
+    }
+    if args.tui() && (args.on.target != RunTarget::Local || format == Format::Json) {
+        return Err(ShiftError::Custom(
+            "--tui only works with the local target and human output".into(),
+        ));
+    }
+    let delegated = args.on.delegate(
+        "apply",
+        &args.target,
+        &flags,
+        &options,
+        &args.provision,
+        format,
+    );
+    if let Some(result) = delegated {
+        args.provision
+            .write_result(Operation::Apply, None, &result)?;
+        return result;
+    }
+    let token = cancel_on_interrupt()?;
+    if args.recursive {
+        return apply_recursive(&args, &token, format);
+    }
+    apply_plan(&args, &args.target, &token, format)
+}
+
+/// Applies every plan of the workspace at `args.target.plan`, each rooted
+/// at its own directory, stopping at the first that fails.
+fn apply_recursive(args: &ApplyArgs, token: &CancellationToken, format: Format) -> ShiftResult<()> {
+    let monorepo = Monorepo::discover(&args.target.plan)?;
+    let members = monorepo.members();
+    for (idx, member) in members.iter().enumerate() {
+        if token.is_cancelled() {
+            return Err(ShiftError::Cancelled);
+        }
+        if format == Format::Human && !super::verbosity().quiet {
+            let style = super::style();
+            println!(
+                "{}",
+                style.paint("1", &format!("plan {}", member.path.display()))
+            );
+        }
+        let mut vars: Vec<(String, String)> = monorepo.inherited_vars(member).into_iter().collect();
+        vars.extend(args.target.vars.iter().cloned());
+        let plan = paths::normalize(&std::path::absolute(&member.path)?);
+        let target = Target {
+            root: plan.parent().map(Path::to_path_buf),
+            plan,
+            only: Vec::new(),
+            tags: args.target.tags.clone(),
+            vars,
+            params: Vec::new(),
+            policies: args.target.policies.clone(),
+            sandbox: args.target.sandbox,
+        };
+        if args.skip_unchanged && unchanged(&target)? {
+            if format == Format::Human && !super::verbosity().quiet {
+                println!("unchanged since its last apply; skipped");
+            }
+            continue;
+        }
+        if let Err(err) = apply_plan(args, &target, token, format) {
+            let err = err.context(format!("applying {}", member.path.display()));
+            return Err(match members.len() - idx - 1 {
+                0 => err,
+                1 => err.hint("1 later plan was not applied"),
+                n => err.hint(format!("{n} later plans were not applied")),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Whether the plan at `target` loads the same as it was when it last
+/// applied successfully. Matrix plans never count as unchanged, as their
+/// runs take turns in the journal.
+fn unchanged(target: &Target) -> ShiftResult<bool> {
+    if target.matrix()?.is_some() {
+        return Ok(false);
+    }
+    let (plan, _) = target.load()?;
+    let journal = Journal::load(&Journal::path_for(&target.plan))?;
+    Ok(journal
+        .last_apply()
+        .and_then(|run| run.plan_hash.as_deref())
+        .is_some_and(|hash| hash == plan.content_hash()))
+}
+
+/// Applies the plan at `target`, once per combination if it has a matrix.
+fn apply_plan(
+    args: &ApplyArgs,
+    target: &Target,
+    token: &CancellationToken,
+    format: Format,
+) -> ShiftResult<()> {
+    let Some(matrix) = target.matrix()? else {
+        return apply_one(args, target, &Combination::new(), token, format);
+    };
+    if args.resume
+        || args.record.is_some()
+        || args.replay.is_some()
+        || args.diagnostics.is_some()
+        || args.tui()
+    {
+        return Err(ShiftError::Custom(
+            "--resume, --record, --replay, --diagnostics and --tui do not work with a matrix plan"
+                .into(),
+        ));
+    }
+    let format = args.provision.format(format);
+    run_matrix(matrix.combinations(), token, format, |combination| {
+        apply_one(args, target, combination, token, format)
+    })
+}
+
+/// Applies the plan at `target` with `combination`'s variables, here.
+fn apply_one(
+    args: &ApplyArgs,
+    target: &Target,
+    combination: &Combination,
+    token: &CancellationToken,
+    format: Format,
+) -> ShiftResult<()> {
+    let (mut plan, ctx) = target.load_with(combination)?;
+    plan.set_network(plan.network().merged(NetworkPolicy {
+        max_transfers: args.max_transfers,
+        rate_limit: args.rate_limit,
+    }));
+    let mut ctx = ctx
+        .with_dry_run(args.dry_run)
+        .with_interactive(!args.provision.non_interactive)
+        .with_cancellation(token.clone());
+    let recorder = args
+        .record
+        .as_ref()
+        .map(|_| Arc::new(RecordingExec::new(Arc::new(RealExec))));
+    if let Some(recorder) = &recorder {
+        ctx = ctx.with_executor(recorder.clone());
+    }
+    let replay = match &args.replay {
+        Some(path) => Some(Arc::new(ReplayExec::new(Recording::load(path)?))),
+        None => None,
+    };
+    if let Some(replay) = &replay {
+        ctx = ctx.with_executor(replay.clone());
+    }
+    if let Some(dir) = &args.cache {
+        ctx = ctx.with_artifacts(ArtifactCache::new(dir).offline(args.offline));
+    }
+    // A replay downloads nothing, so there is nothing to pin.
+    if replay.is_none() {
+        ctx = ctx.with_lock(Lock::open(Lockfile::path_for(&target.plan))?);
+    }
+    if args.deterministic {
+        lock::check_pinned(&plan, &ctx)?;
+    }
+    let mut options = ApplyOptions {
+        rollback: !args.no_rollback,
+        jobs: args.jobs,
+        ..ApplyOptions::default()
+    };
+    let console: Arc<dyn Logger> = Arc::new(super::verbosity().logger());
+    #[cfg(feature = "tui")]
+    let tui = match args.tui {
+        true => Some(Tui::new(&target.plan.display().to_string(), &plan)?),
+        false => None,
+    };
+    // Shifts' messages go to the TUI's log pane instead of the console.
+    #[cfg(feature = "tui")]
+    let console: Arc<dyn Logger> = match &tui {
+        Some(tui) => {
+            options.control = Some(Arc::new(tui.clone()));
+            ctx = ctx.with_logger(tui.logger());
+            Arc::new(tui.logger())
+        }
+        None => console,
+    };
+    // Everything, at full detail, in case it goes into a diagnostics bundle.
+    let log = args.diagnostics.as_ref().map(|_| {
+        let log = Arc::new(MemoryLogger::default());
+        ctx = ctx
+            .clone()
+            .with_logger(TeeLogger(vec![console, log.clone()]));
+        log
+    });
+    let journal_path = Journal::path_for(&target.plan);
+    let mut journal = Journal::load(&journal_path)?;
+    journal.machine.ensure_id();
+    if args.resume {
+        options.completed = resumable(&journal)?;
+    }
+    if args.incremental {
+        options.completed.extend(journal.unchanged(&plan, &ctx)?);
+    }
+    if !args.dry_run {
+        args.consent
+            .check(&plan, &ctx, &mut journal, !args.provision.non_interactive)?;
+    }
+    let mut run = RunRecord::start(Operation::Apply);
+    run.plan_hash = Some(plan.content_hash());
+    // Matrix runs take turns in the journal, so only compare plain runs.
+    if let Some(last) = journal.last_apply().filter(|_| combination.is_empty()) {
+        if last.plan_hash.is_some() && last.plan_hash != run.plan_hash {
+            eprintln!("warning: plan changed since last apply");
+        }
+    }
+    // Where `record` puts this run, if it records it.
+    let recorded = journal.runs.len();
+    let apply = |console: Console<'_>| {
+        record(
+            &mut journal,
+            replay.is_none().then_some(journal_path.as_path()),
+            &ctx,
+            run,
+            &args.provision,
+            console,
+            |reporter| plan.apply_with_options(&ctx, &options, reporter),
+        )
+    };
+    #[cfg(feature = "tui")]
+    let result = match &tui {
+        Some(tui) => tui::run(tui, token, apply),
+        None => apply(format.into()),
+    };
+    #[cfg(not(feature = "tui"))]
+    let result = apply(format.into());
+    // What the run left in place, for later `--incremental` applies.
+    if replay.is_none() && !args.dry_run && journal.runs.len() > recorded {
+        journal.record_inputs(recorded, &plan, &ctx);
+        if let Err(err) = journal.save(&journal_path) {
+            eprintln!("warning: cannot write {}: {err}", journal_path.display());
+        }
+    }
+    if replay.is_none() && !args.dry_run && result.is_ok() {
+        super::hook::mark_applied(&target.plan);
+    }
+    // What was pinned before a failure still holds.
+    if let Some(lock) = ctx.lock() {
+        match lock.save() {
+            Ok(true) => ctx.info(&format!("updated {}", lock.path().display())),
+            Ok(false) => {}
+            Err(err) => eprintln!("warning: cannot write {}: {err}", lock.path().display()),
+        }
+    }
+    if let (Some(path), Some(recorder)) = (&args.record, &recorder) {
+        recorder.recording().save(path)?;
+    }
+    if let Some(unused) = replay.map(|replay| replay.unused().len()) {
+        if unused > 0 {
+            eprintln!("warning: {unused} recorded commands were not replayed");
+        }
+    }
+    if let (Err(err), Some(path), Some(log)) = (&result, &args.diagnostics, log) {
+        let bundle = Diagnostics::for_failure(&plan, &ctx, &journal, &log.lines(), err);
+        match bundle.write(path) {
+            Ok(()) => eprintln!("wrote diagnostics to {}", path.display()),
+            Err(write_err) => eprintln!("cannot write diagnostics: {write_err}"),
+        }
+    }
+    result
+}
+
+pub fn revert(args: RevertArgs, format: Format) -> ShiftResult<()> {
+    let flags = [("--dry-run", args.dry_run), ("--force", args.force)];
+    let delegated = args
+        .on
+        .delegate("revert", &args.target, &flags, &[], &args.provision, format);
+    if let Some(result) = delegated {
+        args.provision
+            .write_result(Operation::Revert, None, &result)?;
+        return result;
+    }
+    let token = cancel_on_interrupt()?;
+    let Some(matrix) = args.target.matrix()? else {
+        return revert_one(&args, &Combination::new(), &token, format);
+    };
+    // Last to first, as within a plan.
+    let mut combinations = matrix.combinations();
+    combinations.reverse();
+    let format = args.provision.format(format);
+    run_matrix(combinations, &token, format, |combination| {
+        revert_one(&args, combination, &token, format)
+    })
+}
+
+/// Reverts the plan with `combination`'s variables, here.
+fn revert_one(
+    args: &RevertArgs,
+    combination: &Combination,
+    token: &CancellationToken,
+    format: Format,
+) -> ShiftResult<()> {
+    let (plan, ctx) = args.target.load_with(combination)?;
+    let ctx = ctx
+        .with_dry_run(args.dry_run)
+        .with_force(args.force)
+        .with_interactive(!args.provision.non_interactive)
+        .with_cancellation(token.clone());
+    let journal_path = Journal::path_for(&args.target.plan);
+    let mut journal = Journal::load(&journal_path)?;
+    let mut run = RunRecord::start(Operation::Revert);
+    run.plan_hash = Some(plan.content_hash());
+    record(
+        &mut journal,
+        Some(&journal_path),
+        &ctx,
+        run,
+        &args.provision,
+        format.into(),
+        |reporter| plan.revert_with(&ctx, reporter),
+    )
+}
+
+/// Runs `f` once per combination of a matrix plan, carrying on after a
+/// failure, then sums up how each run went. Fails if any run did.
+fn run_matrix(
+    combinations: Vec<Combination>,
+    token: &CancellationToken,
+    format: Format,
+    mut f: impl FnMut(&Combination) -> ShiftResult<()>,
+) -> ShiftResult<()> {
+    let style = super::style();
+    let mut results = Vec::new();
+    for combination in &combinations {
+        if token.is_cancelled() {
+            break;
+        }
+        if format == Format::Human && !super::verbosity().quiet {
+            println!(
+                "{}",
+                style.paint("1", &format!("matrix {}", label(combination)))
+            );
+        }
+        let result = f(combination);
+        if let Err(err) = &result {
+            let error = super::error_style().paint("1;31", "error");
+            eprintln!("{error}: {}", render_error(err, &super::error_style()));
+        }
+        results.push((combination, result));
+    }
+    let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+    match format {
+        Format::Human => {
+            println!(
+                "matrix: {} of {} runs succeeded",
+                results.len() - failed,
+                combinations.len()
+            );
+            for (combination, result) in &results {
+                let (symbol, word, color) = match result {
+                    Ok(()) => ("✓", "ok", "32"),
+                    Err(_) => ("✗", "FAIL", "31"),
+                };
+                let marker = style.paint(color, if style.symbols { symbol } else { word });
+                match result {
+                    Ok(()) => println!("  {marker} {}", label(combination)),
+                    Err(err) => println!("  {marker} {}: {err}", label(combination)),
+                }
+            }
+        }
+        Format::Json => {
+            let runs: Vec<_> = results
+                .iter()
+                .map(|(combination, result)| {
+                    let mut run = json!({
+                        "vars": combination,
+                        "outcome": if result.is_ok() { "succeeded" } else { "failed" },
+                    });
+                    if let Err(err) = result {
+                        add_error_json(&mut run, err);
+                    }
+                    run
+                })
+                .collect();
+            println!("{}", json!({ "event": "matrix", "runs": runs }));
+        }
+    }
+    if token.is_cancelled() {
+        Err(ShiftError::Cancelled)
+    } else if failed > 0 {
+        Err(ShiftError::Custom(format!(
+            "{failed} of {} matrix runs failed",
+            combinations.len()
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+/// How [`record`] shows a run's events.
+pub(super) struct Console<'a> {
+    /// Printed to stdout in this format...
+    pub(super) format: Format,
+    /// ...unless handed to a UI, such as `--tui`, instead.
+    pub(super) ui: Option<&'a mut dyn Reporter>,
+}
+
+impl From<Format> for Console<'_> {
+    fn from(format: Format) -> Self {
+        Console { format, ui: None }
+    }
+}
+
+/// Runs `f` with console output while recording the run in the journal
+/// at `journal_path`.
+///
+/// Dry runs, and runs without a journal path, are not recorded. The run's
+/// temp workspace is removed on success and kept, and recorded, on
+/// failure.
+pub(super) fn record(
+    journal: &mut Journal,
+    journal_path: Option<&Path>,
+    ctx: &ExecutionContext,
+    mut run: RunRecord,
+    provision: &Provisioning,
+    console: Console<'_>,
+    f: impl FnOnce(&mut Fanout<'_>) -> ShiftResult<()>,
+) -> ShiftResult<()> {
+    let Console { format, ui } = console;
+    let format = provision.format(format);
+    let operation = run.operation;
+    let mut json = JsonReporter::new(std::io::stdout());
+    let verbosity = super::verbosity();
+    let mut console = ConsoleReporter::new(super::style()).quiet(verbosity.quiet);
+    let mut gha = GhaReporter::new(ConsoleReporter::new(super::style()).quiet(verbosity.quiet));
+    let mut log = verbosity
+        .log_file
+        .clone()
+        .map(|file| LogReporter(file as Arc<dyn Logger>));
+    let shows_ui = ui.is_some();
+    let console: &mut dyn Reporter = match (ui, format) {
+        (Some(ui), _) => ui,
+        (None, Format::Human) if provision.gha => &mut gha,
+        (None, Format::Human) => &mut console,
+        (None, Format::Json) => &mut json,
+    };
+    let mut progress = match journal_path.filter(|_| !ctx.is_dry_run()) {
+        Some(path) => Some(Progress::start(path, &run)?),
+        None => None,
+    };
+    let mut reporters = vec![console, &mut run];
+    if let Some(log) = &mut log {
+        reporters.push(log);
+    }
+    if let Some(progress) = &mut progress {
+        reporters.push(progress);
+    }
+    let result = f(&mut Fanout(reporters));
+    run.take_outputs(ctx.outputs());
+    run.temp_dir = ctx.temp_workspace().finish(result.is_ok());
+    if let Some(dir) = &run.temp_dir {
+        eprintln!("kept temp workspace {} for debugging", dir.display());
+    }
+    run.finish(result.is_ok());
+    if verbosity.quiet && format == Format::Human && !shows_ui {
+        println!("{}", quiet_summary(&run));
+    }
+    provision.print_result(operation, &run, &result);
+    provision.write_result(operation, Some(&run), &result)?;
+    let Some(journal_path) = journal_path.filter(|_| !ctx.is_dry_run()) else {
+        return result;
+    };
+    journal.runs.push(run);
+    journal.save(journal_path)?;
+    ctx.state().save()?;
+    result
+}
+
+/// Saves the run to the journal as each shift finishes, so `skies attach`
+/// and `skies history` can follow a run in progress. The finished run
+/// replaces it.
+struct Progress {
+    journal: Journal,
+    path: PathBuf,
+}
+
+impl Progress {
+    fn start(path: &Path, run: &RunRecord) -> ShiftResult<Self> {
+        let mut journal = Journal::load(path)?;
+        let mut running = RunRecord::start(run.operation);
+        running.plan_hash = run.plan_hash.clone();
+        running.migration = run.migration;
+        journal.runs.push(running);
+        journal.save(path)?;
+        Ok(Progress {
+            journal,
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Reporter for Progress {
+    fn report(&mut self, event: &PlanEvent<'_>) {
+        if let Some(run) = self.journal.runs.last_mut() {
+            run.report(event);
+        }
+        // Only progress is lost; the finished run is saved regardless.
+        let _ = self.journal.save(&self.path);
+    }
+}
+
+/// One line on how a run went, for `--quiet`.
+fn quiet_summary(run: &RunRecord) -> String {
+    let count = |wanted: &[ShiftStatus]| {
+        run.shifts
+            .iter()
+            .filter(|shift| wanted.contains(&shift.status))
+            .count()
+    };
+    format!(
+        "{} {}: {} changed, {} unchanged",
+        run.operation,
+        run.outcome,
+        count(&[ShiftStatus::Applied, ShiftStatus::Reverted]),
+        count(&[ShiftStatus::Skipped])
+    )
+}
+
+/// A token cancelled by Ctrl-C. The run then stops at the next check and
+/// rolls back; a second Ctrl-C exits at once.
+pub(super) fn cancel_on_interrupt() -> ShiftResult<CancellationToken> {
+    let token = CancellationToken::new();
+    let handler = token.clone();
+    ctrlc::set_handler(move || {
+        if handler.is_cancelled() {
+            std::process::exit(i32::from(provision::EXIT_CANCELLED));
+        }
+        eprintln!("cancelling; press Ctrl-C again to stop immediately");
+        handler.cancel();
+    })
+    .map_err(|err| ShiftError::Custom(format!("cannot handle Ctrl-C: {err}")))?;
+    Ok(token)
+}
+
+fn resumable(journal: &Journal) -> ShiftResult<HashSet<String>> {
+    match journal.last_run() {
+        Some(run) if run.operation == Operation::Apply && run.outcome == RunOutcome::Failed => {
+            Ok(run.completed_ids().into_iter().map(String::from).collect())
+        }
+        _ => Err(ShiftError::Custom(
+            "nothing to resume: the last run was not a failed apply".into(),
+        )),
+    }
+}
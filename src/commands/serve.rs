@@ -0,0 +1,589 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use clap::Args;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use skies::journal::Journal;
+use skies::{ShiftError, ShiftResult};
+
+/// Appended to `skies serve --help`.
+pub const API: &str = "\
+API (JSON over HTTP; send `Authorization: Bearer <token>` with --token-env):
+  GET  /plans                     names of the plans submitted
+  PUT  /plans/NAME                submit or replace a plan; the body is the plan file
+  GET  /plans/NAME                the plan file
+  GET  /plans/NAME/status         each shift and whether it is applied
+  GET  /plans/NAME/history        past runs, from the plan's journal
+  POST /plans/NAME/apply          start an apply; body: {\"vars\", \"only\", \"tags\", \"dry_run\"}
+  POST /plans/NAME/revert         start a revert; same body
+  GET  /runs                      runs started by this server
+  GET  /runs/ID                   one run, with its events so far
+  GET  /runs/ID/events            the run's events as JSON lines, streamed until it ends
+  POST /runs/ID/cancel            cancel a run, which then rolls back
+
+Runs are `skies apply`/`revert` processes with `--format json`; their
+events are the lines those print. A plan has one run at a time.
+
+Built with the `web` feature, `GET /` is a dashboard of the plans, their
+past runs and the runs going.";
+
+/// Largest request body accepted.
+const MAX_BODY: usize = 1 << 20;
+
+/// Largest request line and headers accepted, together.
+const MAX_HEAD: u64 = 16 << 10;
+
+#[derive(Args)]
+#[command(after_long_help = API)]
+pub struct ServeArgs {
+    /// Address to listen on.
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:7373")]
+    listen: String,
+    /// Where submitted plans are kept, each in a directory of its own with
+    /// its journal and state.
+    #[arg(long, value_name = "DIR", default_value = "skies-plans")]
+    dir: PathBuf,
+    /// Environment variable holding the token clients must send; needed
+    /// to listen on other than a loopback address.
+    #[arg(long, value_name = "VAR")]
+    token_env: Option<String>,
+}
+
+pub fn serve(args: ServeArgs) -> ShiftResult<()> {
+    let token = match &args.token_env {
+        Some(var) => Some(std::env::var(var).map_err(|_| {
+            ShiftError::Custom(format!("`{var}` is not set")).hint("export the API token in it")
+        })?),
+        None => None,
+    };
+    fs::create_dir_all(&args.dir)?;
+    let listener = TcpListener::bind(&args.listen)
+        .map_err(|err| ShiftError::from(err).context(format!("listening on {}", args.listen)))?;
+    // Anyone who can reach the API can run plans as this user.
+    if token.is_none() && !listener.local_addr()?.ip().is_loopback() {
+        return Err(ShiftError::Custom(format!(
+            "refusing to serve {} without a token",
+            args.listen
+        ))
+        .hint("set --token-env, or listen on 127.0.0.1"));
+    }
+    let server = Arc::new(Server {
+        dir: args.dir,
+        token,
+        runs: Mutex::new(Vec::new()),
+        changed: Condvar::new(),
+    });
+    eprintln!("listening on http://{}", listener.local_addr()?);
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let server = server.clone();
+        thread::spawn(move || {
+            if let Err(err) = server.handle(stream) {
+                eprintln!("warning: request failed: {err}");
+            }
+        });
+    }
+    Ok(())
+}
+
+struct Run {
+    id: u64,
+    plan: String,
+    operation: String,
+    started_at: u64,
+    pid: u32,
+    events: Vec<Value>,
+    /// What the run printed to stderr, such as its final error.
+    stderr: String,
+    /// `None` while the run is going.
+    succeeded: Option<bool>,
+}
+
+impl Run {
+    fn summary(&self) -> Value {
+        json!({
+            "id": self.id,
+            "plan": self.plan,
+            "operation": self.operation,
+            "started_at": self.started_at,
+            "state": match self.succeeded {
+                None => "running",
+                Some(true) => "succeeded",
+                Some(false) => "failed",
+            },
+        })
+    }
+}
+
+/// The body of `POST /plans/NAME/apply` and `/revert`.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RunRequest {
+    vars: BTreeMap<String, String>,
+    only: Vec<String>,
+    tags: Vec<String>,
+    dry_run: bool,
+}
+
+pub(super) struct Request {
+    pub(super) method: String,
+    pub(super) path: String,
+    headers: BTreeMap<String, String>,
+    body: Vec<u8>,
+}
+
+/// An error answered with an HTTP status.
+pub(super) struct HttpError(pub(super) u16, pub(super) String);
+
+impl From<io::Error> for HttpError {
+    fn from(err: io::Error) -> Self {
+        HttpError(500, err.to_string())
+    }
+}
+
+impl From<ShiftError> for HttpError {
+    fn from(err: ShiftError) -> Self {
+        HttpError(500, err.to_string())
+    }
+}
+
+pub(super) fn not_found() -> HttpError {
+    HttpError(404, "not found".into())
+}
+
+enum Reply {
+    Json(u16, Value),
+    Text(String),
+    #[cfg(feature = "web")]
+    Html(&'static str),
+    /// The events of this run, streamed.
+    Events(u64),
+}
+
+struct Server {
+    dir: PathBuf,
+    token: Option<String>,
+    runs: Mutex<Vec<Run>>,
+    changed: Condvar,
+}
+
+impl Server {
+    fn runs(&self) -> MutexGuard<'_, Vec<Run>> {
+        self.runs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn handle(self: &Arc<Self>, mut stream: TcpStream) -> io::Result<()> {
+        let reply = match read_request(&mut stream) {
+            Ok(request) => self.route(&request),
+            Err(err) => Err(err),
+        };
+        match reply {
+            Ok(Reply::Json(status, body)) => {
+                respond(&mut stream, status, "application/json", &body.to_string())
+            }
+            Ok(Reply::Text(text)) => respond(&mut stream, 200, "text/plain; charset=utf-8", &text),
+            #[cfg(feature = "web")]
+            Ok(Reply::Html(page)) => respond(&mut stream, 200, "text/html; charset=utf-8", page),
+            Ok(Reply::Events(id)) => self.stream_events(&mut stream, id),
+            Err(HttpError(status, message)) => respond(
+                &mut stream,
+                status,
+                "application/json",
+                &json!({ "error": message }).to_string(),
+            ),
+        }
+    }
+
+    fn route(self: &Arc<Self>, request: &Request) -> Result<Reply, HttpError> {
+        // The page holds no data, so it is served without the token; it
+        // asks for one when the API does.
+        #[cfg(feature = "web")]
+        if request.method == "GET" && request.path == "/" {
+            return Ok(Reply::Html(super::web::DASHBOARD));
+        }
+        if let Some(token) = &self.token {
+            let sent = request
+                .headers
+                .get("authorization")
+                .map_or("", String::as_str);
+            if !same(sent.as_bytes(), format!("Bearer {token}").as_bytes()) {
+                return Err(HttpError(401, "missing or wrong token".into()));
+            }
+        }
+        let segments: Vec<&str> = request
+            .path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect();
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", ["plans"]) => {
+                let mut names = Vec::new();
+                for entry in fs::read_dir(&self.dir)? {
+                    let entry = entry?;
+                    if entry.path().join("plan.toml").is_file() {
+                        names.push(entry.file_name().to_string_lossy().into_owned());
+                    }
+                }
+                names.sort();
+                Ok(Reply::Json(200, json!(names)))
+            }
+            ("PUT", ["plans", name]) => {
+                let text = String::from_utf8(request.body.clone())
+                    .map_err(|_| HttpError(400, "the plan is not UTF-8".into()))?;
+                if let Err(err) = text.parse::<toml::Table>() {
+                    return Err(HttpError(
+                        400,
+                        format!("not a plan file: {}", err.message()),
+                    ));
+                }
+                if self.is_running(name) {
+                    return Err(HttpError(409, format!("`{name}` has a run going")));
+                }
+                let dir = self.dir.join(plan_name(name)?);
+                fs::create_dir_all(&dir)?;
+                fs::write(dir.join("plan.toml"), text)?;
+                Ok(Reply::Json(200, json!({ "plan": name })))
+            }
+            ("GET", ["plans", name]) => Ok(Reply::Text(fs::read_to_string(self.plan_path(name)?)?)),
+            ("GET", ["plans", name, "status"]) => {
+                let plan = self.plan_path(name)?;
+                let out = Command::new(std::env::current_exe()?)
+                    .args(["status", "--format", "json"])
+                    .arg(&plan)
+                    .stdin(Stdio::null())
+                    .output()?;
+                if !out.status.success() {
+                    return Err(HttpError(
+                        422,
+                        String::from_utf8_lossy(&out.stderr).trim().to_string(),
+                    ));
+                }
+                let status = serde_json::from_slice(&out.stdout)
+                    .map_err(|err| HttpError(500, format!("unexpected status output: {err}")))?;
+                Ok(Reply::Json(200, status))
+            }
+            ("GET", ["plans", name, "history"]) => {
+                let journal = Journal::load(&Journal::path_for(&self.plan_path(name)?))?;
+                Ok(Reply::Json(200, json!(journal.runs)))
+            }
+            ("POST", ["plans", name, operation @ ("apply" | "revert")]) => {
+                let body: RunRequest = if request.body.is_empty() {
+                    RunRequest::default()
+                } else {
+                    serde_json::from_slice(&request.body)
+                        .map_err(|err| HttpError(400, err.to_string()))?
+                };
+                let id = self.start(name, operation, &body)?;
+                Ok(Reply::Json(202, json!({ "run": id })))
+            }
+            ("GET", ["runs"]) => {
+                let runs: Vec<Value> = self.runs().iter().map(Run::summary).collect();
+                Ok(Reply::Json(200, json!(runs)))
+            }
+            ("GET", ["runs", id]) => {
+                let id = run_id(id)?;
+                let runs = self.runs();
+                let run = runs.iter().find(|run| run.id == id).ok_or_else(not_found)?;
+                let mut body = run.summary();
+                body["events"] = json!(run.events);
+                body["stderr"] = json!(run.stderr);
+                Ok(Reply::Json(200, body))
+            }
+            ("GET", ["runs", id, "events"]) => {
+                let id = run_id(id)?;
+                if !self.runs().iter().any(|run| run.id == id) {
+                    return Err(not_found());
+                }
+                Ok(Reply::Events(id))
+            }
+            ("POST", ["runs", id, "cancel"]) => {
+                let id = run_id(id)?;
+                let pid = {
+                    let runs = self.runs();
+                    let run = runs.iter().find(|run| run.id == id).ok_or_else(not_found)?;
+                    if run.succeeded.is_some() {
+                        return Err(HttpError(409, format!("run {id} already finished")));
+                    }
+                    run.pid
+                };
+                // As Ctrl-C would: the run stops at its next check and
+                // rolls back.
+                Command::new("kill")
+                    .args(["-INT", &pid.to_string()])
+                    .status()?;
+                Ok(Reply::Json(202, json!({ "run": id })))
+            }
+            (_, ["plans", ..] | ["runs", ..]) => Err(HttpError(405, "method not allowed".into())),
+            _ => Err(not_found()),
+        }
+    }
+
+    fn plan_path(&self, name: &str) -> Result<PathBuf, HttpError> {
+        let path = self.dir.join(plan_name(name)?).join("plan.toml");
+        if !path.is_file() {
+            return Err(HttpError(404, format!("no plan `{name}`")));
+        }
+        Ok(path)
+    }
+
+    fn is_running(&self, name: &str) -> bool {
+        self.runs()
+            .iter()
+            .any(|run| run.plan == name && run.succeeded.is_none())
+    }
+
+    /// Starts `skies <operation>` on the plan and follows its output.
+    fn start(
+        self: &Arc<Self>,
+        name: &str,
+        operation: &str,
+        request: &RunRequest,
+    ) -> Result<u64, HttpError> {
+        let plan = self.plan_path(name)?;
+        let mut runs = self.runs();
+        if runs
+            .iter()
+            .any(|run| run.plan == name && run.succeeded.is_none())
+        {
+            return Err(HttpError(409, format!("`{name}` already has a run going")));
+        }
+        let mut cmd = Command::new(std::env::current_exe()?);
+        cmd.args([operation, "--format", "json", "--non-interactive"])
+            .arg(&plan);
+        for (key, value) in &request.vars {
+            cmd.arg("--var").arg(format!("{key}={value}"));
+        }
+        for id in &request.only {
+            cmd.args(["--only", id]);
+        }
+        for tag in &request.tags {
+            cmd.args(["--tag", tag]);
+        }
+        if request.dry_run {
+            cmd.arg("--dry-run");
+        }
+        let mut child = cmd
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let id = runs.last().map_or(1, |run| run.id + 1);
+        runs.push(Run {
+            id,
+            plan: name.to_string(),
+            operation: operation.to_string(),
+            started_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            pid: child.id(),
+            events: Vec::new(),
+            stderr: String::new(),
+            succeeded: None,
+        });
+        drop(runs);
+        eprintln!("run {id}: {operation} {name}");
+
+        let stdout = child.stdout.take();
+        let stderr = child.stderr.take();
+        let server = self.clone();
+        thread::spawn(move || {
+            let errors = thread::spawn(move || {
+                let mut text = String::new();
+                if let Some(mut stderr) = stderr {
+                    let _ = stderr.read_to_string(&mut text);
+                }
+                text
+            });
+            for line in stdout
+                .into_iter()
+                .flat_map(|out| BufReader::new(out).lines())
+            {
+                let Ok(line) = line else {
+                    break;
+                };
+                let event = serde_json::from_str(&line).unwrap_or(Value::String(line));
+                server.update(id, |run| run.events.push(event));
+            }
+            let succeeded = child.wait().is_ok_and(|status| status.success());
+            let stderr = errors.join().unwrap_or_default();
+            server.update(id, |run| {
+                run.stderr = stderr;
+                run.succeeded = Some(succeeded);
+            });
+            eprintln!(
+                "run {id}: {}",
+                if succeeded { "succeeded" } else { "failed" }
+            );
+        });
+        Ok(id)
+    }
+
+    fn update(&self, id: u64, f: impl FnOnce(&mut Run)) {
+        if let Some(run) = self.runs().iter_mut().find(|run| run.id == id) {
+            f(run);
+        }
+        self.changed.notify_all();
+    }
+
+    /// Sends the run's events as they come, one JSON object per line, and
+    /// ends the response when the run does.
+    fn stream_events(&self, stream: &mut TcpStream, id: u64) -> io::Result<()> {
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: application/x-ndjson\r\n\
+             Transfer-Encoding: chunked\r\nConnection: close\r\n\r\n"
+        )?;
+        let mut sent = 0;
+        loop {
+            let (lines, finished) = {
+                let mut runs = self.runs();
+                loop {
+                    let run = runs.iter().find(|run| run.id == id).expect("runs are kept");
+                    if run.events.len() > sent || run.succeeded.is_some() {
+                        break;
+                    }
+                    runs = self
+                        .changed
+                        .wait_timeout(runs, Duration::from_secs(1))
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0;
+                }
+                let run = runs.iter().find(|run| run.id == id).expect("runs are kept");
+                let lines: String = run.events[sent..]
+                    .iter()
+                    .map(|event| format!("{event}\n"))
+                    .collect();
+                sent = run.events.len();
+                (lines, run.succeeded.is_some())
+            };
+            if !lines.is_empty() {
+                write!(stream, "{:x}\r\n{lines}\r\n", lines.len())?;
+                stream.flush()?;
+            }
+            if finished {
+                return write!(stream, "0\r\n\r\n");
+            }
+        }
+    }
+}
+
+/// `name` if it is usable as a plan's directory name.
+fn plan_name(name: &str) -> Result<&str, HttpError> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(name)
+    } else {
+        Err(HttpError(
+            400,
+            format!("`{name}` is not a usable plan name"),
+        ))
+    }
+}
+
+/// Whether `a` and `b` are equal, in time that depends on their lengths
+/// but not on where they differ, so a token cannot be guessed a byte at a
+/// time.
+fn same(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn run_id(text: &str) -> Result<u64, HttpError> {
+    text.parse().map_err(|_| not_found())
+}
+
+pub(super) fn read_request(stream: &mut TcpStream) -> Result<Request, HttpError> {
+    stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+    let mut reader = BufReader::new(stream).take(MAX_HEAD);
+    let mut line = String::new();
+    read_head_line(&mut reader, &mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(HttpError(400, "malformed request line".into()));
+    };
+    let (method, path) = (
+        method.to_string(),
+        target.split('?').next().unwrap_or_default().to_string(),
+    );
+    let mut headers = BTreeMap::new();
+    loop {
+        line.clear();
+        read_head_line(&mut reader, &mut line)?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+    let length: usize = match headers.get("content-length") {
+        Some(length) => length
+            .parse()
+            .map_err(|_| HttpError(400, "bad Content-Length".into()))?,
+        None => 0,
+    };
+    if length > MAX_BODY {
+        return Err(HttpError(413, "request body too large".into()));
+    }
+    let mut body = vec![0; length];
+    reader.into_inner().read_exact(&mut body)?;
+    Ok(Request {
+        method,
+        path,
+        headers,
+        body,
+    })
+}
+
+/// Reads a line of the request line and headers into `line`.
+fn read_head_line(reader: &mut io::Take<impl BufRead>, line: &mut String) -> Result<(), HttpError> {
+    reader.read_line(line)?;
+    match (line.ends_with('\n'), reader.limit()) {
+        (true, _) => Ok(()),
+        (false, 0) => Err(HttpError(431, "request headers too large".into())),
+        (false, _) => Err(HttpError(400, "incomplete request".into())),
+    }
+}
+
+pub(super) fn respond(
+    stream: &mut TcpStream,
+    status: u16,
+    content_type: &str,
+    body: &str,
+) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        422 => "Unprocessable Entity",
+        431 => "Request Header Fields Too Large",
+        _ => "Internal Server Error",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: {content_type}\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
@@ -0,0 +1,224 @@
+use std::path::{Path, PathBuf};
+
+use clap::{Args, ValueEnum};
+use skies::download_cache::DownloadCache;
+use skies::journal::Journal;
+use skies::matrix::{Combination, Matrix};
+use skies::monorepo::Member;
+use skies::policy::Policy;
+use skies::sandbox::{Sandbox, SandboxMode};
+use skies::state::StateStore;
+use skies::{plan_file, starlark_file, ExecutionContext, ShiftError, ShiftPlan, ShiftResult};
+
+/// Environment variables starting with this set plan variables.
+const VAR_ENV_PREFIX: &str = "SKIES_VAR_";
+
+/// The plan a command operates on, and which part of it.
+#[derive(Args)]
+pub struct Target {
+    /// Path to the plan file.
+    #[arg(env = "SKIES_PLAN")]
+    pub plan: PathBuf,
+    /// Only consider these shift IDs (and what they depend on).
+    #[arg(long = "only", value_name = "ID")]
+    pub only: Vec<String>,
+    /// Only consider shifts with this tag (and what they depend on).
+    #[arg(long = "tag", value_name = "TAG")]
+    pub tags: Vec<String>,
+    /// Set a plan variable, overriding the plan's default.
+    #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_var)]
+    pub vars: Vec<(String, String)>,
+    /// Set a parameter the plan declares; its value is checked against
+    /// the parameter's type before anything runs.
+    #[arg(long = "param", value_name = "NAME=VALUE", value_parser = parse_var)]
+    pub params: Vec<(String, String)>,
+    /// Hold the plan to this policy as well as the system one, if any.
+    #[arg(long = "policy", value_name = "PATH", env = "SKIES_POLICY")]
+    pub policies: Vec<PathBuf>,
+    /// Run commands in a bubblewrap sandbox that can only write to the
+    /// plan root and the paths shifts declare; `require` fails where that
+    /// is not possible instead of running them as usual.
+    #[arg(
+        long,
+        value_name = "MODE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "auto",
+        env = "SKIES_SANDBOX"
+    )]
+    pub sandbox: Option<Sandboxing>,
+    /// Where the plan's shifts run from, instead of the current directory,
+    /// for plans applied as part of a workspace.
+    #[arg(skip)]
+    pub root: Option<PathBuf>,
+}
+
+/// `--sandbox`, mirroring [`SandboxMode`].
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Sandboxing {
+    Auto,
+    Require,
+}
+
+impl Target {
+    /// Loads the plan along with the context it runs in.
+    pub fn load(&self) -> ShiftResult<(ShiftPlan, ExecutionContext)> {
+        self.load_with(&Combination::new())
+    }
+
+    /// Like [`load`](Self::load), with one combination of the plan's
+    /// matrix set as variables.
+    pub fn load_with(
+        &self,
+        combination: &Combination,
+    ) -> ShiftResult<(ShiftPlan, ExecutionContext)> {
+        let mut ctx = ExecutionContext::new()
+            .with_logger(super::verbosity().logger())
+            .with_state(StateStore::open(StateStore::path_for(&self.plan))?);
+        if let Some(root) = &self.root {
+            ctx = ctx.with_root(root);
+        }
+        if let Some(dir) = DownloadCache::default_dir() {
+            ctx = ctx.with_download_cache(DownloadCache::new(dir));
+        }
+        if let Some(policy) = Policy::system()? {
+            ctx = ctx.with_policy(policy);
+        }
+        for path in &self.policies {
+            ctx = ctx.with_policy(Policy::load(path)?);
+        }
+        let required = ctx.policies().iter().any(|policy| policy.sandbox);
+        let mode = match self.sandbox {
+            _ if required => Some(SandboxMode::Required),
+            Some(Sandboxing::Auto) => Some(SandboxMode::Auto),
+            Some(Sandboxing::Require) => Some(SandboxMode::Required),
+            None => None,
+        };
+        if let Some(mode) = mode {
+            ctx = ctx.with_sandbox(Sandbox::new(mode));
+        }
+        let journal = Journal::load(&Journal::path_for(&self.plan))?;
+        let mut facts = ctx.facts().clone();
+        journal.machine.add_facts(&mut facts);
+        let outputs = journal.outputs().into_iter();
+        let outputs = outputs.map(|(id, list)| (id.to_string(), list.to_vec()));
+        ctx = ctx.with_facts(facts).with_prior_outputs(outputs.collect());
+        for (key, value) in std::env::vars() {
+            if let Some(name) = key.strip_prefix(VAR_ENV_PREFIX) {
+                ctx.set_var(name, value);
+            }
+        }
+        let vars = self.vars.iter().chain(&self.params);
+        for (name, value) in vars.map(|(name, value)| (name, value)).chain(combination) {
+            ctx.set_var(name, value);
+        }
+        let mut unapplied = Vec::new();
+        if !starlark_file::is_starlark(&self.plan) && self.plan.is_file() {
+            let member = Member::load(self.plan.clone())?;
+            for (name, path) in &member.requires {
+                if Journal::load(&Journal::path_for(path))?
+                    .last_apply()
+                    .is_none()
+                {
+                    unapplied.push(format!("{name} ({})", path.display()));
+                }
+            }
+            ctx = ctx.with_plan_outputs(member.required_outputs()?);
+        }
+        let mut plan = plan_file::load(&self.plan, &mut ctx).map_err(|err| {
+            match unapplied.is_empty() {
+                true => err,
+                false => err.hint(format!(
+                    "outputs of required plans are only known once they are applied; not applied yet: {}",
+                    unapplied.join(", ")
+                )),
+            }
+        })?;
+        for (name, _) in &self.params {
+            if !plan.params().iter().any(|param| &param.name == name) {
+                let declared: Vec<_> = plan.params().iter().map(|p| p.name.as_str()).collect();
+                let err = ShiftError::Plan(format!("the plan has no param `{name}`"));
+                return Err(match declared.as_slice() {
+                    [] => err.hint("use `--var` to set a plain variable"),
+                    _ => err.hint(format!("it declares: {}", declared.join(", "))),
+                });
+            }
+        }
+        if !self.only.is_empty() {
+            plan.only(&self.only)?;
+        }
+        if !self.tags.is_empty() {
+            plan.select(|entry| self.tags.iter().any(|tag| entry.has_tag(tag)));
+        }
+        Ok((plan, ctx))
+    }
+
+    /// Checks the plan's expressions without loading it; see
+    /// [`plan_file::lint`]. Variables set from outside, and the matrix's,
+    /// count as defined.
+    pub fn lint(&self) -> ShiftResult<Vec<String>> {
+        let mut ctx = ExecutionContext::new();
+        for (key, value) in std::env::vars() {
+            if let Some(name) = key.strip_prefix(VAR_ENV_PREFIX) {
+                ctx.set_var(name, value);
+            }
+        }
+        let combination = self.sample()?;
+        let vars = self.vars.iter().chain(&self.params);
+        for (name, value) in vars.map(|(name, value)| (name, value)).chain(&combination) {
+            ctx.set_var(name, value);
+        }
+        plan_file::lint(&self.plan, &ctx)
+    }
+
+    /// The first combination of the plan's matrix, or none if it has
+    /// none, to check the plan with.
+    pub fn sample(&self) -> ShiftResult<Combination> {
+        let matrix = self.matrix()?.map(|matrix| matrix.combinations());
+        Ok(matrix
+            .and_then(|c| c.into_iter().next())
+            .unwrap_or_default())
+    }
+
+    /// The plan's matrix, narrowed by `--var`s naming its variables.
+    pub fn matrix(&self) -> ShiftResult<Option<Matrix>> {
+        let mut matrix = Matrix::load(&self.plan)?;
+        if let Some(matrix) = &mut matrix {
+            for (name, value) in self.vars.iter().chain(&self.params) {
+                matrix.pin(name, value);
+            }
+        }
+        Ok(matrix)
+    }
+
+    /// Arguments that select the same plan, shifts and variables from
+    /// inside a run target, where the plan lives at `plan`.
+    pub fn forwarded(&self, plan: &Path) -> Vec<String> {
+        let mut args = vec![plan.display().to_string()];
+        for id in &self.only {
+            args.extend(["--only".to_string(), id.clone()]);
+        }
+        for tag in &self.tags {
+            args.extend(["--tag".to_string(), tag.clone()]);
+        }
+        for (name, value) in &self.vars {
+            args.extend(["--var".to_string(), format!("{name}={value}")]);
+        }
+        for (name, value) in &self.params {
+            args.extend(["--param".to_string(), format!("{name}={value}")]);
+        }
+        match self.sandbox {
+            Some(Sandboxing::Auto) => args.push("--sandbox=auto".into()),
+            Some(Sandboxing::Require) => args.push("--sandbox=require".into()),
+            None => {}
+        }
+        args
+    }
+}
+
+pub(super) fn parse_var(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!("expected NAME=VALUE, got `{arg}`")),
+    }
+}
@@ -0,0 +1,427 @@
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Gauge, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use skies::context::{LogLevel, Logger, TeeLogger};
+use skies::report::{PlanEvent, Reporter};
+use skies::{
+    CancellationToken, FailureAction, PlanEntry, RunControl, ShiftError, ShiftPlan, ShiftResult,
+};
+
+use super::run::Console;
+use super::Format;
+
+/// Log lines kept for the log pane.
+const LOG_LINES: usize = 2000;
+
+/// Where a shift is at, as the list shows it.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Pending,
+    Running,
+    /// Set to be left out when its turn comes.
+    LeftOut,
+    /// An event name, such as `applied` or `failed`.
+    Done(&'static str),
+}
+
+struct Row {
+    id: String,
+    summary: String,
+    status: Status,
+    error: Option<String>,
+    started: Option<Instant>,
+    took: Option<Duration>,
+}
+
+struct State {
+    plan: String,
+    rows: Vec<Row>,
+    log: VecDeque<(LogLevel, String)>,
+    /// Row of the failure waiting for retry, skip or abort.
+    asking: Option<usize>,
+    answer: Option<FailureAction>,
+    /// The UI failed, so nothing is left to answer.
+    closed: bool,
+    /// How the run ended, once it has.
+    outcome: Option<Result<(), String>>,
+}
+
+impl State {
+    fn row(&mut self, id: &str) -> Option<(usize, &mut Row)> {
+        self.rows
+            .iter_mut()
+            .enumerate()
+            .find(|(_, row)| row.id == id)
+    }
+
+    fn done(&self) -> usize {
+        self.rows
+            .iter()
+            .filter(|row| matches!(row.status, Status::Done(_) | Status::LeftOut))
+            .count()
+    }
+}
+
+/// The TUI's view of a run: the run's thread reports, logs and asks
+/// through it, and the UI thread draws it and answers.
+#[derive(Clone)]
+pub(super) struct Tui {
+    state: Arc<(Mutex<State>, Condvar)>,
+    min: LogLevel,
+}
+
+impl Tui {
+    pub(super) fn new(name: &str, plan: &ShiftPlan) -> ShiftResult<Self> {
+        let rows = plan
+            .execution_order()?
+            .into_iter()
+            .map(|idx| {
+                let entry = &plan.entries()[idx];
+                Row {
+                    id: entry.id().to_string(),
+                    summary: entry.shift.metadata().summary,
+                    status: Status::Pending,
+                    error: None,
+                    started: None,
+                    took: None,
+                }
+            })
+            .collect();
+        let state = State {
+            plan: name.to_string(),
+            rows,
+            log: VecDeque::new(),
+            asking: None,
+            answer: None,
+            closed: false,
+            outcome: None,
+        };
+        Ok(Tui {
+            state: Arc::new((Mutex::new(state), Condvar::new())),
+            min: super::verbosity().console_level(),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Logs to the log pane and to the log file, if any.
+    pub(super) fn logger(&self) -> TeeLogger {
+        let mut loggers: Vec<Arc<dyn Logger>> = vec![Arc::new(self.clone())];
+        if let Some(file) = &super::verbosity().log_file {
+            loggers.push(file.clone());
+        }
+        TeeLogger(loggers)
+    }
+
+    fn finish(&self, result: &ShiftResult<()>) {
+        self.lock().outcome = Some(result.as_ref().map(drop).map_err(ToString::to_string));
+    }
+
+    fn answer(&self, action: FailureAction) {
+        let mut state = self.lock();
+        if state.asking.is_some() {
+            state.answer = Some(action);
+            self.state.1.notify_all();
+        }
+    }
+}
+
+impl Reporter for Tui {
+    fn report(&mut self, event: &PlanEvent<'_>) {
+        let mut state = self.lock();
+        let line = match event.error() {
+            Some(err) => format!("{} {}: {err}", event.name(), event.entry().id()),
+            None => format!("{} {}", event.name(), event.entry().id()),
+        };
+        let Some((_, row)) = state.row(event.entry().id()) else {
+            return;
+        };
+        match event {
+            PlanEvent::Started(_) => {
+                row.status = Status::Running;
+                row.error = None;
+                row.started = Some(Instant::now());
+            }
+            // The verify phase and rollback come after the shift's own
+            // result, which stays what the list shows.
+            PlanEvent::Verified(_) => {}
+            _ => {
+                row.status = Status::Done(event.name());
+                row.error = event.error().map(ToString::to_string);
+                row.took = row.started.map(|started| started.elapsed());
+            }
+        }
+        push_log(&mut state, LogLevel::Info, line);
+    }
+}
+
+impl Logger for Tui {
+    fn log(&self, level: LogLevel, message: &str) {
+        if level >= self.min {
+            let mut state = self.lock();
+            for line in message.lines() {
+                push_log(&mut state, level, line.to_string());
+            }
+        }
+    }
+}
+
+impl RunControl for Tui {
+    fn skip(&self, entry: &PlanEntry) -> bool {
+        let mut state = self.lock();
+        state
+            .row(entry.id())
+            .is_some_and(|(_, row)| row.status == Status::LeftOut)
+    }
+
+    fn on_failure(&self, entry: &PlanEntry, _err: &ShiftError) -> FailureAction {
+        let mut state = self.lock();
+        let closed = state.closed;
+        let Some((idx, _)) = state.row(entry.id()).filter(|_| !closed) else {
+            return FailureAction::Abort;
+        };
+        state.asking = Some(idx);
+        state.answer = None;
+        let mut state = self
+            .state
+            .1
+            .wait_while(state, |state| state.answer.is_none())
+            .unwrap_or_else(|e| e.into_inner());
+        state.asking = None;
+        state.answer.take().unwrap_or(FailureAction::Abort)
+    }
+}
+
+fn push_log(state: &mut State, level: LogLevel, line: String) {
+    if state.log.len() == LOG_LINES {
+        state.log.pop_front();
+    }
+    state.log.push_back((level, line));
+}
+
+/// Runs `apply` on a thread of its own, reporting to `tui`, and shows the
+/// TUI until the run has finished and the user leaves. Aborting cancels
+/// `token`, so the run rolls back as on Ctrl-C.
+pub(super) fn run(
+    tui: &Tui,
+    token: &CancellationToken,
+    apply: impl FnOnce(Console<'_>) -> ShiftResult<()> + Send,
+) -> ShiftResult<()> {
+    let mut terminal = ratatui::try_init()?;
+    thread::scope(|scope| {
+        let worker = scope.spawn(|| {
+            let result = apply(Console {
+                format: Format::Human,
+                ui: Some(&mut tui.clone()),
+            });
+            tui.finish(&result);
+            result
+        });
+        let shown = event_loop(&mut terminal, tui, token);
+        ratatui::restore();
+        if shown.is_err() {
+            token.cancel();
+            tui.lock().closed = true;
+            tui.answer(FailureAction::Abort);
+        }
+        let result = worker
+            .join()
+            .unwrap_or_else(|_| Err(ShiftError::Custom("the run panicked".into())));
+        shown?;
+        result
+    })
+}
+
+fn event_loop(
+    terminal: &mut DefaultTerminal,
+    tui: &Tui,
+    token: &CancellationToken,
+) -> io::Result<()> {
+    let mut list = ListState::default().with_selected(Some(0));
+    let mut repainted = false;
+    loop {
+        let finished = tui.lock().outcome.is_some();
+        // What the run prints to stderr as it ends lands over the screen.
+        if finished && !repainted {
+            terminal.clear()?;
+            repainted = true;
+        }
+        terminal.draw(|frame| draw(frame, &tui.lock(), &mut list))?;
+        if !event::poll(Duration::from_millis(100))? {
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        let asking = tui.lock().asking.is_some();
+        let ctrl_c =
+            key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c');
+        match key.code {
+            _ if finished && (ctrl_c || matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)) => {
+                return Ok(())
+            }
+            _ if ctrl_c => {
+                token.cancel();
+                tui.answer(FailureAction::Abort);
+            }
+            KeyCode::Char('q') | KeyCode::Char('a') | KeyCode::Esc if !finished => {
+                token.cancel();
+                tui.answer(FailureAction::Abort);
+            }
+            KeyCode::Char('r') if asking => tui.answer(FailureAction::Retry),
+            KeyCode::Char('s') if asking => tui.answer(FailureAction::Skip),
+            KeyCode::Char('s') | KeyCode::Char(' ') => {
+                let mut state = tui.lock();
+                if let Some(row) = list.selected().and_then(|idx| state.rows.get_mut(idx)) {
+                    row.status = match row.status {
+                        Status::Pending => Status::LeftOut,
+                        Status::LeftOut => Status::Pending,
+                        status => status,
+                    };
+                }
+            }
+            KeyCode::Down | KeyCode::Char('j') => list.select_next(),
+            KeyCode::Up | KeyCode::Char('k') => list.select_previous(),
+            KeyCode::Home | KeyCode::Char('g') => list.select_first(),
+            KeyCode::End | KeyCode::Char('G') => list.select_last(),
+            _ => {}
+        }
+    }
+}
+
+fn status_style(status: Status) -> (&'static str, Style) {
+    let style = Style::default();
+    match status {
+        Status::Pending => ("·", style.fg(Color::DarkGray)),
+        Status::Running => ("▶", style.fg(Color::Blue).add_modifier(Modifier::BOLD)),
+        Status::LeftOut => (
+            "⤼",
+            style
+                .fg(Color::DarkGray)
+                .add_modifier(Modifier::CROSSED_OUT),
+        ),
+        Status::Done("applied" | "verified" | "adopted") => ("✓", style.fg(Color::Green)),
+        Status::Done("skipped") => ("-", style.fg(Color::DarkGray)),
+        Status::Done("timed_out") => ("⏱", style.fg(Color::Yellow)),
+        Status::Done("rolled_back" | "reverted") => ("↺", style.fg(Color::Cyan)),
+        Status::Done("would_apply") => ("+", style.fg(Color::Green)),
+        Status::Done(_) => ("✗", style.fg(Color::Red)),
+    }
+}
+
+fn draw(frame: &mut Frame<'_>, state: &State, list: &mut ListState) {
+    let [top, middle, bottom] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Min(3),
+        Constraint::Length(2),
+    ])
+    .areas(frame.area());
+    let [shifts, log] =
+        Layout::horizontal([Constraint::Percentage(45), Constraint::Percentage(55)]).areas(middle);
+
+    let total = state.rows.len().max(1);
+    let done = state.done();
+    let (title, color) = match &state.outcome {
+        None => (format!(" applying {} ", state.plan), Color::Blue),
+        Some(Ok(())) => (format!(" {} applied ", state.plan), Color::Green),
+        Some(Err(_)) => (format!(" {} failed ", state.plan), Color::Red),
+    };
+    frame.render_widget(
+        Gauge::default()
+            .block(Block::bordered().title(title))
+            .gauge_style(Style::default().fg(color))
+            .ratio(done as f64 / total as f64)
+            .label(format!("{done}/{} shifts", state.rows.len())),
+        top,
+    );
+
+    let items: Vec<ListItem<'_>> = state
+        .rows
+        .iter()
+        .map(|row| {
+            let (marker, style) = status_style(row.status);
+            let took = match (row.status, row.took, row.started) {
+                (_, Some(took), _) => format!(" {:.1}s", took.as_secs_f64()),
+                (Status::Running, None, Some(started)) => {
+                    format!(" {}s", started.elapsed().as_secs())
+                }
+                _ => String::new(),
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("{marker} "), style),
+                Span::styled(row.id.clone(), style),
+                Span::styled(
+                    format!("  {}", row.summary),
+                    Style::default().fg(Color::Gray),
+                ),
+                Span::styled(took, Style::default().fg(Color::DarkGray)),
+            ]))
+        })
+        .collect();
+    frame.render_stateful_widget(
+        List::new(items)
+            .block(Block::bordered().title(" shifts "))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED)),
+        shifts,
+        list,
+    );
+
+    let selected = list.selected().and_then(|idx| state.rows.get(idx));
+    let mut lines: Vec<Line<'_>> = Vec::new();
+    if let Some(err) = selected.and_then(|row| row.error.as_deref()) {
+        lines.push(Line::styled(err, Style::default().fg(Color::Red)));
+        lines.push(Line::raw(""));
+    }
+    let height = log.height.saturating_sub(2) as usize;
+    let skip = state
+        .log
+        .len()
+        .saturating_sub(height.saturating_sub(lines.len()));
+    lines.extend(state.log.iter().skip(skip).map(|(level, line)| {
+        let style = match level {
+            LogLevel::Warn => Style::default().fg(Color::Yellow),
+            LogLevel::Info => Style::default(),
+            LogLevel::Debug | LogLevel::Trace => Style::default().fg(Color::DarkGray),
+        };
+        Line::styled(line.as_str(), style)
+    }));
+    frame.render_widget(
+        Paragraph::new(lines)
+            .block(Block::bordered().title(" log "))
+            .wrap(Wrap { trim: false }),
+        log,
+    );
+
+    let help = match (&state.outcome, state.asking) {
+        (Some(Err(err)), _) => Line::from(vec![
+            Span::styled(err.as_str(), Style::default().fg(Color::Red)),
+            Span::raw("  ·  q quit"),
+        ]),
+        (Some(Ok(())), _) => Line::raw("done  ·  q quit"),
+        (None, Some(idx)) => Line::from(vec![
+            Span::styled(
+                format!("`{}` failed", state.rows[idx].id),
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw("  ·  r retry  ·  s skip it and go on  ·  a abort and roll back"),
+        ]),
+        (None, None) => Line::raw(
+            "↑/↓ select  ·  s leave out (or back in) a pending shift  ·  a abort and roll back",
+        ),
+    };
+    frame.render_widget(Paragraph::new(help).wrap(Wrap { trim: true }), bottom);
+}
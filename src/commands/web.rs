@@ -0,0 +1,69 @@
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+
+use serde_json::json;
+use skies::journal::Journal;
+use skies::{ShiftError, ShiftResult};
+
+use super::serve::{not_found, read_request, respond, HttpError, Request};
+
+/// The dashboard page. It reads everything else from the API of
+/// `skies serve`, or the read-only part of it `history list --web` serves.
+pub const DASHBOARD: &str = include_str!("dashboard.html");
+
+/// Serves the dashboard for the journal of `plan`, until interrupted.
+pub fn history(plan: &Path, listen: &str) -> ShiftResult<()> {
+    let name = plan
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "plan".into());
+    let listener = TcpListener::bind(listen)
+        .map_err(|err| ShiftError::from(err).context(format!("listening on {listen}")))?;
+    eprintln!("dashboard at http://{}/", listener.local_addr()?);
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else {
+            continue;
+        };
+        if let Err(err) = handle(&mut stream, plan, &name) {
+            eprintln!("warning: request failed: {err}");
+        }
+    }
+    Ok(())
+}
+
+fn handle(stream: &mut TcpStream, plan: &Path, name: &str) -> std::io::Result<()> {
+    match read_request(stream).and_then(|request| route(&request, plan, name)) {
+        Ok((content_type, body)) => respond(stream, 200, content_type, &body),
+        Err(HttpError(status, message)) => respond(
+            stream,
+            status,
+            "application/json",
+            &json!({ "error": message }).to_string(),
+        ),
+    }
+}
+
+/// The body, and its type, for `request`; the journal is read anew for
+/// each, so the page shows runs made since it was opened.
+fn route(request: &Request, plan: &Path, name: &str) -> Result<(&'static str, String), HttpError> {
+    if request.method != "GET" {
+        return Err(HttpError(405, "method not allowed".into()));
+    }
+    let segments: Vec<&str> = request
+        .path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    match segments.as_slice() {
+        [] => Ok(("text/html; charset=utf-8", DASHBOARD.to_string())),
+        ["plans"] => Ok(("application/json", json!([name]).to_string())),
+        ["plans", plan_name] if *plan_name == name => {
+            Ok(("text/plain; charset=utf-8", std::fs::read_to_string(plan)?))
+        }
+        ["plans", plan_name, "history"] if *plan_name == name => {
+            let journal = Journal::load(&Journal::path_for(plan))?;
+            Ok(("application/json", json!(journal.runs).to_string()))
+        }
+        _ => Err(not_found()),
+    }
+}
@@ -0,0 +1,790 @@
+//! Shared state handed to every shift while a plan runs.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::artifacts::ArtifactCache;
+use crate::cancel::CancellationToken;
+use crate::download_cache::DownloadCache;
+use crate::error::{ShiftError, ShiftResult};
+use crate::exec::{Exec, RealExec};
+use crate::expr::{self, Expr, Value};
+use crate::facts::Facts;
+use crate::fs::{Fs, RealFs};
+use crate::journal::format_timestamp;
+use crate::lockfile::Lock;
+use crate::network::Transfers;
+use crate::outputs::{Output, Outputs};
+use crate::paths;
+use crate::permissions::PermissionPolicy;
+use crate::policy::Policy;
+use crate::sandbox::Sandbox;
+use crate::state::StateStore;
+use crate::workspace::TempWorkspace;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    /// Raw detail such as every line a command printed.
+    Trace,
+    Debug,
+    Info,
+    Warn,
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            LogLevel::Trace => "trace",
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+        })
+    }
+}
+
+/// Destination for diagnostic messages emitted by shifts.
+pub trait Logger: Send + Sync {
+    fn log(&self, level: LogLevel, message: &str);
+}
+
+/// Drops every message.
+pub struct NullLogger;
+
+impl Logger for NullLogger {
+    fn log(&self, _level: LogLevel, _message: &str) {}
+}
+
+/// Prints messages at or above `min` to stderr.
+pub struct StderrLogger {
+    pub min: LogLevel,
+}
+
+impl Logger for StderrLogger {
+    fn log(&self, level: LogLevel, message: &str) {
+        if level >= self.min {
+            eprintln!("{message}");
+        }
+    }
+}
+
+/// Appends every message, with a timestamp and its level, to a file.
+pub struct FileLogger {
+    file: Mutex<File>,
+}
+
+impl FileLogger {
+    /// Opens `path` for appending, creating it if needed.
+    pub fn append(path: &Path) -> ShiftResult<FileLogger> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(FileLogger {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl Logger for FileLogger {
+    fn log(&self, level: LogLevel, message: &str) {
+        let mut file = self
+            .file
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        for line in stamped(level, message) {
+            let _ = writeln!(file, "{line}");
+        }
+    }
+}
+
+/// Keeps every message, stamped like [`FileLogger`]'s, in memory.
+#[derive(Default)]
+pub struct MemoryLogger {
+    lines: Mutex<Vec<String>>,
+}
+
+impl MemoryLogger {
+    pub fn lines(&self) -> Vec<String> {
+        self.lines
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+}
+
+impl Logger for MemoryLogger {
+    fn log(&self, level: LogLevel, message: &str) {
+        self.lines
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .extend(stamped(level, message));
+    }
+}
+
+/// `message`'s lines prefixed with the time and `level`.
+fn stamped(level: LogLevel, message: &str) -> impl Iterator<Item = String> + '_ {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let time = format_timestamp(now);
+    message
+        .lines()
+        .map(move |line| format!("{time} {level:<5} {line}"))
+}
+
+/// Sends every message to each of several loggers.
+pub struct TeeLogger(pub Vec<Arc<dyn Logger>>);
+
+impl Logger for TeeLogger {
+    fn log(&self, level: LogLevel, message: &str) {
+        for logger in &self.0 {
+            logger.log(level, message);
+        }
+    }
+}
+
+/// Everything a shift may consult besides its own fields.
+///
+/// Cloning is cheap; clones share the logger and state store.
+#[derive(Clone)]
+pub struct ExecutionContext {
+    root: PathBuf,
+    vars: BTreeMap<String, String>,
+    facts: Facts,
+    dry_run: bool,
+    interactive: bool,
+    force: bool,
+    logger: Arc<dyn Logger>,
+    state: Arc<Mutex<StateStore>>,
+    shift_id: Option<String>,
+    allow_outside_root: bool,
+    permissions: PermissionPolicy,
+    policies: Vec<Arc<Policy>>,
+    sandbox: Option<Arc<Sandbox>>,
+    temp: Arc<TempWorkspace>,
+    deadline: Option<Instant>,
+    cancel: CancellationToken,
+    outputs: Arc<Outputs>,
+    prior_outputs: Arc<BTreeMap<String, Vec<Output>>>,
+    /// Outputs of other plans of the workspace, by their name, then shift.
+    plan_outputs: Arc<BTreeMap<String, BTreeMap<String, Vec<Output>>>>,
+    fs: Arc<dyn Fs>,
+    executor: Arc<dyn Exec>,
+    artifacts: Option<Arc<ArtifactCache>>,
+    downloads: Option<Arc<DownloadCache>>,
+    lock: Option<Arc<Lock>>,
+    transfers: Option<Arc<Transfers>>,
+    /// Plan files loaded on the way to this context, outermost first.
+    plan_files: Vec<PathBuf>,
+}
+
+impl ExecutionContext {
+    /// A context rooted at the current directory with facts gathered from
+    /// the local machine, no variables, and in-memory state.
+    pub fn new() -> Self {
+        ExecutionContext {
+            root: env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+            vars: BTreeMap::new(),
+            facts: Facts::gather(),
+            dry_run: false,
+            interactive: true,
+            force: false,
+            logger: Arc::new(NullLogger),
+            state: Arc::new(Mutex::new(StateStore::in_memory())),
+            shift_id: None,
+            allow_outside_root: false,
+            permissions: PermissionPolicy::default(),
+            policies: Vec::new(),
+            sandbox: None,
+            temp: Arc::new(TempWorkspace::new()),
+            deadline: None,
+            cancel: CancellationToken::new(),
+            outputs: Arc::new(Outputs::default()),
+            prior_outputs: Arc::default(),
+            plan_outputs: Arc::default(),
+            fs: Arc::new(RealFs),
+            executor: Arc::new(RealExec),
+            artifacts: None,
+            downloads: None,
+            lock: None,
+            transfers: None,
+            plan_files: Vec::new(),
+        }
+    }
+
+    /// Directory relative paths are resolved against and that shifts may
+    /// not leave (see [`ExecutionContext::resolve`]).
+    pub fn with_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.root = root.into();
+        self
+    }
+
+    pub fn with_facts(mut self, facts: Facts) -> Self {
+        self.facts = facts;
+        self
+    }
+
+    /// Outputs of earlier runs, by shift ID, for `outputs.<shift>.<name>`
+    /// in expressions.
+    pub fn with_prior_outputs(mut self, outputs: BTreeMap<String, Vec<Output>>) -> Self {
+        self.prior_outputs = Arc::new(outputs);
+        self
+    }
+
+    /// The output `name` of `shift` from an earlier run.
+    pub fn prior_output(&self, shift: &str, name: &str) -> Option<&Output> {
+        let outputs = self.prior_outputs.get(shift)?;
+        outputs.iter().find(|output| output.name == name)
+    }
+
+    /// Outputs of the plans a plan [requires](crate::monorepo), by the
+    /// name it knows each by, for `plans.<plan>.<shift>.<name>` in
+    /// expressions.
+    pub fn with_plan_outputs(
+        mut self,
+        outputs: BTreeMap<String, BTreeMap<String, Vec<Output>>>,
+    ) -> Self {
+        self.plan_outputs = Arc::new(outputs);
+        self
+    }
+
+    /// The output `name` of `shift` in the required plan `plan`.
+    pub fn plan_output(&self, plan: &str, shift: &str, name: &str) -> Option<&Output> {
+        let outputs = self.plan_outputs.get(plan)?.get(shift)?;
+        outputs.iter().find(|output| output.name == name)
+    }
+
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Whether commands may prompt. Non-interactive runs ask tools that
+    /// commonly prompt (git, apt) not to.
+    pub fn with_interactive(mut self, interactive: bool) -> Self {
+        self.interactive = interactive;
+        self
+    }
+
+    /// Whether shifts may go ahead with a change they would otherwise
+    /// refuse because it loses data, such as removing a clone with local
+    /// changes.
+    pub fn with_force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    pub fn with_logger(mut self, logger: impl Logger + 'static) -> Self {
+        self.logger = Arc::new(logger);
+        self
+    }
+
+    pub fn with_state(mut self, state: StateStore) -> Self {
+        self.state = Arc::new(Mutex::new(state));
+        self
+    }
+
+    /// Use `temp` instead of a workspace under the system temp directory.
+    pub fn with_temp_workspace(mut self, temp: TempWorkspace) -> Self {
+        self.temp = Arc::new(temp);
+        self
+    }
+
+    /// The run's scratch workspace; see [`TempWorkspace`].
+    pub fn temp_workspace(&self) -> &TempWorkspace {
+        &self.temp
+    }
+
+    /// A fresh scratch directory for the current shift, cleaned up with the
+    /// rest of the run's workspace.
+    pub fn temp_dir(&self) -> ShiftResult<PathBuf> {
+        let prefix = self
+            .shift_id
+            .as_deref()
+            .map(|id| id.replace('/', "_"))
+            .unwrap_or_else(|| "run".to_string());
+        self.temp.subdir(&prefix)
+    }
+
+    /// Work must finish by `deadline`; an earlier deadline already set
+    /// is kept. Commands run through [`Cmd`](crate::shifts::Cmd) are
+    /// killed when it passes.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(self.deadline.map_or(deadline, |d| d.min(deadline)));
+        self
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Use `token` to cancel this context's work; see [`CancellationToken`].
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancel = token;
+        self
+    }
+
+    pub fn cancellation(&self) -> &CancellationToken {
+        &self.cancel
+    }
+
+    /// Fails if the run was cancelled or its deadline has passed. Call this
+    /// every so often from work that can take a while.
+    pub fn check_cancelled(&self) -> ShiftResult<()> {
+        if self.cancel.is_cancelled() {
+            return Err(ShiftError::Cancelled);
+        }
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => {
+                Err(ShiftError::TimedOut("the shift ran out of time".into()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Take downloads from `cache`, and with an offline cache nothing
+    /// but them; see [`crate::artifacts`].
+    pub fn with_artifacts(mut self, cache: ArtifactCache) -> Self {
+        self.artifacts = Some(Arc::new(cache));
+        self
+    }
+
+    pub fn artifacts(&self) -> Option<&ArtifactCache> {
+        self.artifacts.as_deref()
+    }
+
+    /// Keeps downloads in `cache` and reuses them from there; see
+    /// [`crate::download_cache`].
+    pub fn with_download_cache(mut self, cache: DownloadCache) -> Self {
+        self.downloads = Some(Arc::new(cache));
+        self
+    }
+
+    pub fn download_cache(&self) -> Option<&DownloadCache> {
+        self.downloads.as_deref()
+    }
+
+    /// Pins downloads and clones to `lock`, and records in it what they
+    /// resolve to; see [`crate::lockfile`].
+    pub fn with_lock(mut self, lock: Lock) -> Self {
+        self.lock = Some(Arc::new(lock));
+        self
+    }
+
+    pub fn lock(&self) -> Option<&Lock> {
+        self.lock.as_deref()
+    }
+
+    /// Limits downloads and clones to `transfers`; see
+    /// [`crate::network`].
+    pub fn with_transfers(mut self, transfers: Transfers) -> Self {
+        self.transfers = Some(Arc::new(transfers));
+        self
+    }
+
+    pub fn transfers(&self) -> Option<&Transfers> {
+        self.transfers.as_deref()
+    }
+
+    /// Whether the run may only use cached artifacts.
+    pub fn is_offline(&self) -> bool {
+        self.artifacts().is_some_and(ArtifactCache::is_offline)
+    }
+
+    /// Default modes for created files and directories.
+    pub fn with_permissions(mut self, permissions: PermissionPolicy) -> Self {
+        self.permissions = permissions;
+        self
+    }
+
+    pub fn permissions(&self) -> &PermissionPolicy {
+        &self.permissions
+    }
+
+    /// Adds a [`Policy`] every shift must keep to, here and in plans
+    /// applied from this one. Policies add up; none can loosen another.
+    pub fn with_policy(mut self, policy: Policy) -> Self {
+        self.policies.push(Arc::new(policy));
+        self
+    }
+
+    pub fn policies(&self) -> &[Arc<Policy>] {
+        &self.policies
+    }
+
+    /// Run [`Cmd`](crate::shifts::Cmd) shifts' commands in `sandbox`.
+    pub fn with_sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = Some(Arc::new(sandbox));
+        self
+    }
+
+    pub fn sandbox(&self) -> Option<&Sandbox> {
+        self.sandbox.as_deref()
+    }
+
+    /// Run file shifts against `fs`, e.g. a [`MemoryFs`](crate::fs::MemoryFs)
+    /// in tests. Keep a clone of the `Arc` to inspect it afterwards.
+    pub fn with_fs(mut self, fs: Arc<dyn Fs>) -> Self {
+        self.fs = fs;
+        self
+    }
+
+    /// The filesystem file shifts read and write; the real one by default.
+    pub fn fs(&self) -> &dyn Fs {
+        &*self.fs
+    }
+
+    /// Run commands through `executor`, e.g. a
+    /// [`MockExec`](crate::exec::MockExec) in tests.
+    pub fn with_executor(mut self, executor: Arc<dyn Exec>) -> Self {
+        self.executor = executor;
+        self
+    }
+
+    /// What [`Cmd`](crate::shifts::Cmd) runs commands through; local
+    /// processes by default.
+    pub fn executor(&self) -> &dyn Exec {
+        &*self.executor
+    }
+
+    pub fn set_var(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.vars.insert(name.into(), value.into());
+    }
+
+    pub fn var(&self, name: &str) -> Option<&str> {
+        self.vars.get(name).map(String::as_str)
+    }
+
+    /// Drops every variable, e.g. before loading a nested plan that should
+    /// not see the outer plan's.
+    pub(crate) fn clear_vars(&mut self) {
+        self.vars.clear();
+    }
+
+    /// Records that the plan file at `path` is being loaded, or fails if it
+    /// already was on the way here, since a plan applying itself, directly
+    /// or through others, would never finish.
+    pub(crate) fn enter_plan_file(&mut self, path: &Path) -> ShiftResult<()> {
+        let path = paths::canonical(path);
+        if self.plan_files.contains(&path) {
+            let chain: Vec<String> = self
+                .plan_files
+                .iter()
+                .chain([&path])
+                .map(|file| file.display().to_string())
+                .collect();
+            return Err(ShiftError::Plan(format!(
+                "plan files apply each other in a cycle: {}",
+                chain.join(" -> ")
+            )));
+        }
+        self.plan_files.push(path);
+        Ok(())
+    }
+
+    pub fn vars(&self) -> &BTreeMap<String, String> {
+        &self.vars
+    }
+
+    pub fn facts(&self) -> &Facts {
+        &self.facts
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// When set, plans report what they would change instead of changing it.
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    pub fn is_interactive(&self) -> bool {
+        self.interactive
+    }
+
+    pub fn is_forced(&self) -> bool {
+        self.force
+    }
+
+    /// Lets the current shift resolve paths outside the root.
+    pub fn allowing_outside_root(mut self, allow: bool) -> Self {
+        self.allow_outside_root = self.allow_outside_root || allow;
+        self
+    }
+
+    /// `path` under the root (or `path` itself if absolute, after `~`
+    /// expansion), without any containment check. Use for display and
+    /// bookkeeping only.
+    pub fn join_root(&self, path: &Path) -> PathBuf {
+        self.root.join(self.expand_home(path))
+    }
+
+    /// Expands a leading `~` to the `home` fact.
+    pub fn expand_home(&self, path: &Path) -> PathBuf {
+        paths::expand_home(path, self.facts.get("home").map(Path::new))
+    }
+
+    /// Resolves `path` against the root for a shift to operate on.
+    ///
+    /// After symlinks and `..` are resolved, in the order the kernel would
+    /// follow them, the result must stay inside the root, unless the shift
+    /// was marked `allow_outside_root`; this stops a bad variable from
+    /// pointing a shift (or its revert) at `/` or `$HOME`.
+    ///
+    /// The path returned has its directories resolved but not its last
+    /// component, so a shift managing a symlink gets the link itself.
+    pub fn resolve(&self, path: &Path) -> ShiftResult<PathBuf> {
+        let joined = self.join_root(path);
+        let located = match (joined.parent(), joined.components().next_back()) {
+            (Some(parent), Some(Component::Normal(name))) => paths::resolve(parent).join(name),
+            _ => paths::resolve(&joined),
+        };
+        if self.allow_outside_root {
+            return Ok(located);
+        }
+        let root = paths::resolve(&self.root);
+        let resolved = paths::resolve(&joined);
+        if resolved.starts_with(&root) && located.starts_with(&root) {
+            Ok(located)
+        } else {
+            Err(ShiftError::PermissionDenied(format!(
+                "{} resolves to {}, outside the plan root {}",
+                path.display(),
+                resolved.display(),
+                root.display()
+            ))
+            .hint("set allow_outside_root on the shift to permit this"))
+        }
+    }
+
+    /// Replaces `${...}` with the value of the [expression](crate::expr)
+    /// inside, such as `${name}`, `${facts.os}` or `${lower(env.USER)}`.
+    ///
+    /// `$$` produces a literal `$`; unknown names are an error.
+    pub fn interpolate(&self, text: &str) -> ShiftResult<String> {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(pos) = rest.find('$') {
+            out.push_str(&rest[..pos]);
+            rest = &rest[pos + 1..];
+            if let Some(after) = rest.strip_prefix('$') {
+                out.push('$');
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix('{') {
+                let end = expr::closing_brace(after).ok_or_else(|| {
+                    ShiftError::Custom(format!("unterminated `${{` in \"{text}\""))
+                })?;
+                out.push_str(&self.eval(after[..end].trim())?.text()?);
+                rest = &after[end + 1..];
+            } else {
+                out.push('$');
+            }
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+
+    /// Evaluates an [expression](crate::expr) against the variables, facts
+    /// and earlier outputs.
+    pub fn eval(&self, source: &str) -> ShiftResult<Value> {
+        Expr::parse(source)?.eval(&|path| self.reference(path))
+    }
+
+    /// What a reference in an expression names: `facts.x`, `env.X`,
+    /// `outputs.<shift>.<name>`, `plans.<plan>.<shift>.<name>`, `vars.x`,
+    /// or a variable by its whole dotted name.
+    fn reference(&self, path: &[String]) -> Option<Value> {
+        let rest = || path[1..].join(".");
+        match path[0].as_str() {
+            "facts" if path.len() > 1 => self.facts.get(&rest()).map(|f| Value::Str(f.into())),
+            "env" if path.len() > 1 => env::var(rest()).ok().map(Value::Str),
+            "vars" if path.len() > 1 => self.var(&rest()).map(|v| Value::Str(v.into())),
+            // Shift IDs may have dots in them, as in `api.dir`.
+            "outputs" => (2..path.len()).find_map(|split| {
+                let output =
+                    self.prior_output(&path[1..split].join("."), &path[split..].join("."))?;
+                Some(Value::from(&output.value))
+            }),
+            "plans" if path.len() > 1 => (3..path.len()).find_map(|split| {
+                let output = self.plan_output(
+                    &path[1],
+                    &path[2..split].join("."),
+                    &path[split..].join("."),
+                )?;
+                Some(Value::from(&output.value))
+            }),
+            _ => self.var(&path.join(".")).map(|v| Value::Str(v.into())),
+        }
+    }
+
+    pub fn log(&self, level: LogLevel, message: &str) {
+        self.logger.log(level, message);
+    }
+
+    pub fn trace(&self, message: &str) {
+        self.log(LogLevel::Trace, message);
+    }
+
+    pub fn debug(&self, message: &str) {
+        self.log(LogLevel::Debug, message);
+    }
+
+    pub fn info(&self, message: &str) {
+        self.log(LogLevel::Info, message);
+    }
+
+    pub fn warn(&self, message: &str) {
+        self.log(LogLevel::Warn, message);
+    }
+
+    /// ID of the shift this context was handed to, if any.
+    pub fn shift_id(&self) -> Option<&str> {
+        self.shift_id.as_deref()
+    }
+
+    /// A copy of this context scoped to the shift `id`, so its state calls
+    /// land in that shift's namespace. Scoping an already scoped context
+    /// (for shifts made of sub-plans) nests the IDs as `outer/inner`.
+    pub fn for_shift(&self, id: &str) -> ExecutionContext {
+        let mut scoped = self.clone();
+        scoped.shift_id = Some(match &self.shift_id {
+            Some(outer) => format!("{outer}/{id}"),
+            None => id.to_string(),
+        });
+        scoped
+    }
+
+    /// The whole state store, shared by every shift.
+    pub fn state(&self) -> MutexGuard<'_, StateStore> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Reads a value the current shift stored in an earlier run.
+    pub fn get_state(&self, key: &str) -> Option<serde_json::Value> {
+        let id = self.shift_id.as_deref()?;
+        self.state().get(id, key).cloned()
+    }
+
+    /// Persists a value for the current shift. Ignored in dry runs and
+    /// outside a shift.
+    pub fn set_state(&self, key: &str, value: serde_json::Value) {
+        if let (Some(id), false) = (self.shift_id.as_deref(), self.dry_run) {
+            self.state().set(id, key, value);
+        }
+    }
+
+    pub fn clear_state(&self, key: &str) {
+        if let (Some(id), false) = (self.shift_id.as_deref(), self.dry_run) {
+            self.state().remove(id, key);
+        }
+    }
+
+    /// Reports an artifact of the current shift, such as a path it created
+    /// or an ID it was assigned; see [`crate::outputs`]. Ignored in dry
+    /// runs and outside a shift.
+    pub fn output(&self, name: &str, value: impl Serialize) {
+        self.push_output(name, value, false);
+    }
+
+    /// Like [`output`](Self::output), for credentials and other secrets.
+    pub fn sensitive_output(&self, name: &str, value: impl Serialize) {
+        self.push_output(name, value, true);
+    }
+
+    fn push_output(&self, name: &str, value: impl Serialize, sensitive: bool) {
+        if let (Some(id), false) = (self.shift_id.as_deref(), self.dry_run) {
+            let value = serde_json::to_value(value).unwrap_or(serde_json::Value::Null);
+            self.outputs.record(
+                id,
+                Output {
+                    name: name.to_string(),
+                    value,
+                    sensitive,
+                },
+            );
+        }
+    }
+
+    /// Every output reported so far in this run, shared by every shift.
+    pub fn outputs(&self) -> &Outputs {
+        &self.outputs
+    }
+}
+
+impl Default for ExecutionContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::symlink;
+    use std::path::{Path, PathBuf};
+
+    use super::ExecutionContext;
+
+    /// A fresh directory holding `root/` and, beside it, `outside/secret`.
+    fn layout(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("skies-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("root/sub")).unwrap();
+        fs::create_dir_all(dir.join("outside")).unwrap();
+        fs::write(dir.join("outside/secret"), "").unwrap();
+        dir
+    }
+
+    fn denied(ctx: &ExecutionContext, path: &str) -> bool {
+        ctx.resolve(Path::new(path))
+            .is_err_and(|err| err.kind() == "permission_denied")
+    }
+
+    #[test]
+    fn parent_dir_escape_is_denied() {
+        let dir = layout("dotdot");
+        let ctx = ExecutionContext::new().with_root(dir.join("root"));
+        assert!(denied(&ctx, "../outside/secret"));
+        assert!(denied(&ctx, "sub/../../outside/secret"));
+        assert!(!denied(&ctx, "sub/../file"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn symlink_escape_is_denied() {
+        let dir = layout("symlink");
+        let root = dir.join("root");
+        symlink(dir.join("outside"), root.join("link")).unwrap();
+        // `link/..` is the directory above `outside`, not the root.
+        symlink(dir.join("outside"), root.join("sub/link")).unwrap();
+        let ctx = ExecutionContext::new().with_root(&root);
+        assert!(denied(&ctx, "link/secret"));
+        assert!(denied(&ctx, "sub/link/../outside/secret"));
+        assert!(denied(&ctx, "link/../root-sibling"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn resolved_path_is_returned() {
+        let dir = layout("resolved");
+        let root = dir.join("root");
+        symlink(root.join("sub"), root.join("alias")).unwrap();
+        let ctx = ExecutionContext::new().with_root(&root);
+        let real = root.canonicalize().unwrap();
+        assert_eq!(
+            ctx.resolve(Path::new("alias/../sub/file")).unwrap(),
+            real.join("sub/file")
+        );
+        // The last component is left as is, so links can be managed.
+        assert_eq!(ctx.resolve(Path::new("alias")).unwrap(), real.join("alias"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
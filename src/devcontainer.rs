@@ -0,0 +1,128 @@
+//! Applying a plan when a dev container is created, as in Codespaces.
+//!
+//! [`DevContainer::install`] writes a local dev container feature into the
+//! project's `.devcontainer` directory: a `skies` binary and an
+//! `install.sh` that puts it on the image's `PATH`. The project's
+//! `devcontainer.json` then names the feature and runs `skies apply` in its
+//! `onCreateCommand`; [`DevContainer::config`] gives those settings. The
+//! binary has to run in the image, so it should be built for Linux on the
+//! image's architecture, statically for images without glibc.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+
+use crate::error::{ShiftError, ShiftResult};
+use crate::paths;
+use crate::permissions;
+
+/// Name of the feature's directory, next to `devcontainer.json`.
+pub const FEATURE: &str = "skies";
+/// Where `install.sh` puts the binary in the image.
+pub const BINARY: &str = "/usr/local/bin/skies";
+/// Image for a `devcontainer.json` written from scratch.
+pub const DEFAULT_IMAGE: &str = "mcr.microsoft.com/devcontainers/base:debian";
+
+/// A project's `.devcontainer` directory.
+pub struct DevContainer {
+    dir: PathBuf,
+}
+
+impl DevContainer {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        DevContainer { dir: dir.into() }
+    }
+
+    pub fn feature_dir(&self) -> PathBuf {
+        self.dir.join(FEATURE)
+    }
+
+    pub fn config_path(&self) -> PathBuf {
+        self.dir.join("devcontainer.json")
+    }
+
+    /// The workspace the container opens: the directory holding
+    /// `.devcontainer`.
+    pub fn workspace(&self) -> PathBuf {
+        match self.dir.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        }
+    }
+
+    /// `plan` relative to the [workspace](Self::workspace), as
+    /// `onCreateCommand` runs there; it has to be inside it.
+    pub fn plan_path(&self, plan: &Path) -> ShiftResult<PathBuf> {
+        let workspace = paths::canonical(&std::path::absolute(self.workspace())?);
+        let plan = paths::canonical(&std::path::absolute(plan)?);
+        plan.strip_prefix(&workspace)
+            .map(Path::to_path_buf)
+            .map_err(|_| {
+                ShiftError::Custom(format!(
+                    "{} is outside the workspace {}",
+                    plan.display(),
+                    workspace.display()
+                ))
+                .hint("put the plan in the project, or pass --dir for the project's .devcontainer")
+            })
+    }
+
+    /// Writes the feature, with `binary` as its `skies`. Installing again
+    /// replaces the binary, so it can be refreshed after an upgrade.
+    pub fn install(&self, binary: &Path) -> ShiftResult<()> {
+        let dir = self.feature_dir();
+        fs::create_dir_all(&dir)?;
+        let feature = json!({
+            "id": FEATURE,
+            "version": "1.0.0",
+            "name": "skies",
+            "description": "Installs skies, to apply the project's plan when the container is created.",
+        });
+        fs::write(
+            dir.join("devcontainer-feature.json"),
+            format!("{:#}\n", feature),
+        )?;
+        let script = dir.join("install.sh");
+        fs::write(&script, install_script())?;
+        permissions::set_mode(&script, 0o755)?;
+        let staged = dir.join("skies");
+        fs::copy(binary, &staged)?;
+        permissions::set_mode(&staged, 0o755)?;
+        Ok(())
+    }
+
+    /// The `devcontainer.json` settings that use the feature and apply the
+    /// plan with `args`, which should name it by its
+    /// [`plan_path`](Self::plan_path). With `image`, a whole file.
+    pub fn config(&self, args: &[String], image: Option<&str>) -> Value {
+        let mut command = vec![
+            "skies".to_string(),
+            "apply".into(),
+            "--non-interactive".into(),
+        ];
+        command.extend(args.iter().cloned());
+        let mut config = json!({
+            "features": { format!("./{FEATURE}"): {} },
+            "onCreateCommand": command,
+        });
+        if let Some(image) = image {
+            config["image"] = image.into();
+        }
+        config
+    }
+}
+
+fn install_script() -> String {
+    format!(
+        "#!/bin/sh
+# Written by `skies devcontainer`; run when the dev container's image is built.
+set -e
+install -m 0755 \"$(dirname \"$0\")/skies\" {BINARY}
+if ! {BINARY} --version >/dev/null 2>&1; then
+    echo 'the staged skies binary does not run in this image; rerun `skies devcontainer` with --binary for a Linux build' >&2
+    exit 1
+fi
+"
+    )
+}
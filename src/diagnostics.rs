@@ -0,0 +1,122 @@
+//! Bundles of what went wrong in a failed run, for debugging a machine
+//! without logging in to it.
+//!
+//! A bundle is a `.tar.gz` of small files: the run's log at full detail
+//! (including what commands printed), the journal, the failing shifts'
+//! metadata and errors, the plan's shifts and the gathered facts.
+//! Sensitive shift inputs are redacted; log lines are kept as logged.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::journal::{Journal, ShiftStatus};
+use crate::plan::ShiftPlan;
+use crate::report::{render_error, ConsoleStyle};
+
+/// Files to put in a bundle, by name.
+#[derive(Default)]
+pub struct Diagnostics {
+    files: BTreeMap<String, Vec<u8>>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The usual bundle for a failed run of `plan`: `error.txt`,
+    /// `run.log` (from `log`), `journal.json`, `failures.json`,
+    /// `plan.json` and `facts.json`.
+    pub fn for_failure(
+        plan: &ShiftPlan,
+        ctx: &ExecutionContext,
+        journal: &Journal,
+        log: &[String],
+        err: &ShiftError,
+    ) -> Self {
+        let mut failures = Vec::new();
+        if let Some(run) = journal.last_run() {
+            for record in &run.shifts {
+                if !matches!(record.status, ShiftStatus::Failed | ShiftStatus::TimedOut) {
+                    continue;
+                }
+                let metadata = plan
+                    .entry(&record.id)
+                    .map(|entry| entry.shift.metadata().redacted());
+                failures.push(json!({
+                    "id": record.id,
+                    "status": record.status,
+                    "error": record.error,
+                    "metadata": metadata,
+                }));
+            }
+        }
+        let shifts: Vec<_> = plan
+            .entries()
+            .iter()
+            .map(|entry| {
+                json!({
+                    "id": entry.id(),
+                    "depends_on": entry.depends_on,
+                    "metadata": entry.shift.metadata().redacted(),
+                })
+            })
+            .collect();
+        let facts: BTreeMap<_, _> = ctx.facts().iter().collect();
+
+        let mut bundle = Diagnostics::new();
+        bundle.add(
+            "error.txt",
+            format!("{}\n", render_error(err, &ConsoleStyle::default())),
+        );
+        bundle.add("run.log", log.join("\n") + "\n");
+        bundle.add_json("journal.json", journal);
+        bundle.add_json("failures.json", &failures);
+        bundle.add_json("plan.json", &shifts);
+        bundle.add_json("facts.json", &facts);
+        bundle
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, contents: impl Into<Vec<u8>>) {
+        self.files.insert(name.into(), contents.into());
+    }
+
+    pub fn add_json(&mut self, name: impl Into<String>, value: &impl Serialize) {
+        let text = serde_json::to_string_pretty(value).unwrap_or_default();
+        self.add(name, text);
+    }
+
+    /// Writes the bundle as a gzipped tarball, its files in one directory
+    /// named after `path`.
+    pub fn write(&self, path: &Path) -> ShiftResult<()> {
+        let dir = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(|name| name.trim_end_matches(".tar.gz").trim_end_matches(".tgz"))
+            .filter(|name| !name.is_empty())
+            .unwrap_or("diagnostics");
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let gz = GzEncoder::new(File::create(path)?, Compression::default());
+        let mut tar = tar::Builder::new(gz);
+        for (name, contents) in &self.files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(now);
+            tar.append_data(&mut header, format!("{dir}/{name}"), contents.as_slice())?;
+        }
+        tar.into_inner()?.finish()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,255 @@
+//! Reference documentation for shift types, generated from the registry
+//! for `skies docs`.
+//!
+//! A type's fields, and their defaults, come from its schema; what it
+//! counts as applied and what revert does come from its doc comment,
+//! which every built-in shift keeps up to date for this reason.
+
+use std::fmt;
+
+use schemars::generate::SchemaSettings;
+use schemars::SchemaGenerator;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::registry::Registry;
+
+/// How wide descriptions are filled, not counting their indent.
+const WIDTH: usize = 72;
+
+/// The documentation of one shift type.
+#[derive(Debug, Clone, Serialize)]
+pub struct TypeDoc {
+    /// The `type` plan files give it.
+    pub kind: String,
+    /// What the shift does, when it is applied and what revert does.
+    pub description: String,
+    pub fields: Vec<FieldDoc>,
+}
+
+/// One field of a shift type.
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldDoc {
+    pub name: String,
+    /// The field's type, like `string` or `list of string`.
+    #[serde(rename = "type")]
+    pub ty: String,
+    pub required: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// The documentation of every type in `registry`, by `type`.
+pub fn all(registry: &Registry) -> Vec<TypeDoc> {
+    registry
+        .kinds()
+        .filter_map(|kind| for_kind(registry, kind))
+        .collect()
+}
+
+/// The documentation of shift type `kind`, if `registry` has it.
+pub fn for_kind(registry: &Registry, kind: &str) -> Option<TypeDoc> {
+    let mut generator = SchemaSettings::draft07().into_generator();
+    let schema = registry.schema(kind, &mut generator)?.to_value();
+    let required: Vec<_> = schema["required"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect();
+    let fields = schema["properties"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .map(|(name, field)| FieldDoc {
+            name: name.clone(),
+            ty: type_name(&generator, field),
+            required: required.contains(&name.as_str()),
+            default: field
+                .get("default")
+                .filter(|value| !is_empty(value))
+                .cloned(),
+            description: field["description"].as_str().map(prose),
+        })
+        .collect();
+    Some(TypeDoc {
+        kind: kind.to_string(),
+        description: schema["description"]
+            .as_str()
+            .map(prose)
+            .unwrap_or_default(),
+        fields,
+    })
+}
+
+/// What is left of a doc comment for someone reading it outside rustdoc:
+/// links become their text, and a linked path just its last part.
+fn prose(doc: &str) -> String {
+    let mut out = String::new();
+    let mut rest = doc;
+    while let Some(start) = rest.find('[') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let Some(end) = after.find(']') else {
+            rest = &rest[start..];
+            break;
+        };
+        let text = &after[..end];
+        let mut tail = &after[end + 1..];
+        if tail.starts_with('(') {
+            if let Some(close) = tail.find(')') {
+                tail = &tail[close + 1..];
+            }
+        } else if let Some(path) = text.strip_prefix('`').and_then(|t| t.strip_suffix('`')) {
+            let name = path.rsplit("::").next().unwrap_or(path);
+            out.push_str(&format!("`{name}`"));
+            rest = tail;
+            continue;
+        }
+        out.push_str(text);
+        rest = tail;
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Defaults not worth showing: nothing, nothing in a list, and so on.
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::Array(items) => items.is_empty(),
+        Value::Object(fields) => fields.is_empty(),
+        _ => false,
+    }
+}
+
+/// How a schema reads to a plan author: `string`, `list of string`,
+/// `"user" | "system"` and the like. Optional values lose their `null`.
+fn type_name(generator: &SchemaGenerator, schema: &Value) -> String {
+    if let Some(reference) = schema["$ref"].as_str() {
+        let name = reference.rsplit('/').next().unwrap_or(reference);
+        return match generator.definitions().get(name) {
+            Some(definition) if is_choice(definition) => type_name(generator, definition),
+            _ => name.to_string(),
+        };
+    }
+    if let Some(values) = schema["enum"].as_array() {
+        let values: Vec<_> = values.iter().map(Value::to_string).collect();
+        return values.join(" | ");
+    }
+    if let Some(value) = schema.get("const") {
+        return value.to_string();
+    }
+    for key in ["anyOf", "oneOf"] {
+        if let Some(options) = schema[key].as_array() {
+            let options: Vec<_> = options
+                .iter()
+                .filter(|option| option["type"] != "null")
+                .map(|option| type_name(generator, option))
+                .collect();
+            let all_choices = schema[key].as_array().into_iter().flatten().all(is_choice);
+            return options.join(if all_choices { " | " } else { " or " });
+        }
+    }
+    let types: Vec<_> = match &schema["type"] {
+        Value::String(name) => vec![name.as_str()],
+        Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+        _ => return "any".to_string(),
+    };
+    let names: Vec<_> = types
+        .into_iter()
+        .filter(|name| *name != "null")
+        .map(|name| match name {
+            "array" => format!("list of {}", type_name(generator, &schema["items"])),
+            "object" => match &schema["additionalProperties"] {
+                Value::Object(_) => format!(
+                    "table of {}",
+                    type_name(generator, &schema["additionalProperties"])
+                ),
+                _ => "table".to_string(),
+            },
+            name => name.to_string(),
+        })
+        .collect();
+    names.join(" or ")
+}
+
+/// `text` refilled to [`WIDTH`], keeping its paragraphs and `- ` list
+/// items apart; links taken out by [`prose`] leave lines short otherwise.
+fn wrap(text: &str) -> Vec<String> {
+    let mut blocks: Vec<String> = Vec::new();
+    let mut fresh = true;
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            blocks.push(String::new());
+            fresh = true;
+        } else if fresh || line.starts_with("- ") {
+            blocks.push(line.to_string());
+            fresh = false;
+        } else if let Some(block) = blocks.last_mut() {
+            block.push(' ');
+            block.push_str(line);
+        }
+    }
+    let mut lines = Vec::new();
+    for block in blocks {
+        let hang = if block.starts_with("- ") { "  " } else { "" };
+        let mut line = String::new();
+        for word in block.split_whitespace() {
+            if !line.is_empty() && line.len() + 1 + word.len() > WIDTH {
+                lines.push(std::mem::take(&mut line));
+                line.push_str(hang);
+            } else if !line.is_empty() && line != hang {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Whether `schema` is a set of values to pick from, like a unit enum.
+fn is_choice(schema: &Value) -> bool {
+    if schema.get("enum").is_some() || schema.get("const").is_some() {
+        return true;
+    }
+    match schema["oneOf"].as_array() {
+        Some(options) => options.iter().all(is_choice),
+        None => false,
+    }
+}
+
+impl fmt::Display for TypeDoc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.kind)?;
+        for line in wrap(&self.description) {
+            match line.as_str() {
+                "" => writeln!(f)?,
+                line => writeln!(f, "  {line}")?,
+            }
+        }
+        if !self.fields.is_empty() {
+            writeln!(f, "\n  fields:")?;
+        }
+        for field in &self.fields {
+            let need = if field.required {
+                "required"
+            } else {
+                "optional"
+            };
+            write!(f, "    {} ({}, {need}", field.name, field.ty)?;
+            if let Some(default) = &field.default {
+                write!(f, ", default {default}")?;
+            }
+            writeln!(f, ")")?;
+            for line in field.description.iter().flat_map(|d| wrap(d)) {
+                writeln!(f, "      {line}")?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+//! Symlink plans built from a dotfiles tree.
+//!
+//! Every file under the source directory becomes a [`Symlink`] at the same
+//! relative path under the link directory (usually `~`). Directories are
+//! recreated rather than linked, so `~/.config` can mix managed and
+//! unmanaged files.
+//!
+//! A file named `name##os.<os>` or `name##hostname.<host>` is an alternate
+//! for `name` on that OS or host. The generated plan keeps every variant
+//! and uses the plan file's `os`/`hostname` conditions to pick one per
+//! machine; a host match beats an OS match, which beats the plain file.
+//!
+//! [`Symlink`]: crate::shifts::Symlink
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{ShiftError, ShiftResult};
+
+/// Names that are never linked.
+const ALWAYS_SKIPPED: &[&str] = &[".git", ".skies"];
+
+/// One link in a dotfiles plan.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    /// Where the link goes, relative to the link directory.
+    pub path: PathBuf,
+    /// The file it points to.
+    pub source: PathBuf,
+    /// Conditions in plan file syntax; empty means unconditional.
+    pub os: Vec<String>,
+    pub hostname: Vec<String>,
+}
+
+#[derive(Default)]
+struct Variants {
+    plain: Option<PathBuf>,
+    os: Vec<(String, PathBuf)>,
+    hostname: Vec<(String, PathBuf)>,
+}
+
+/// Collects the links for every file under `source`, skipping entries
+/// named in `exclude` at any depth.
+pub fn scan(source: &Path, exclude: &[String]) -> ShiftResult<Vec<Link>> {
+    let source = source
+        .canonicalize()
+        .map_err(|err| ShiftError::Custom(format!("cannot read {}: {err}", source.display())))?;
+    let mut files = BTreeMap::new();
+    walk(&source, Path::new(""), exclude, &mut files)?;
+
+    let mut links = Vec::new();
+    for (path, variants) in files {
+        let not = |names: &[(String, PathBuf)]| {
+            names
+                .iter()
+                .map(|(name, _)| format!("!{name}"))
+                .collect::<Vec<_>>()
+        };
+        let other_hosts = not(&variants.hostname);
+        for (host, file) in &variants.hostname {
+            links.push(Link {
+                path: path.clone(),
+                source: file.clone(),
+                os: Vec::new(),
+                hostname: vec![host.clone()],
+            });
+        }
+        for (os, file) in &variants.os {
+            links.push(Link {
+                path: path.clone(),
+                source: file.clone(),
+                os: vec![os.clone()],
+                hostname: other_hosts.clone(),
+            });
+        }
+        if let Some(file) = variants.plain {
+            links.push(Link {
+                path,
+                source: file,
+                os: not(&variants.os),
+                hostname: other_hosts,
+            });
+        }
+    }
+    Ok(links)
+}
+
+fn walk(
+    dir: &Path,
+    rel: &Path,
+    exclude: &[String],
+    files: &mut BTreeMap<PathBuf, Variants>,
+) -> ShiftResult<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if ALWAYS_SKIPPED.contains(&name.as_str()) || exclude.contains(&name) {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            walk(&entry.path(), &rel.join(&name), exclude, files)?;
+            continue;
+        }
+        let (base, alternate) = match name.split_once("##") {
+            Some((base, alternate)) => (base, Some(alternate)),
+            None => (name.as_str(), None),
+        };
+        let variants = files.entry(rel.join(base)).or_default();
+        match alternate.and_then(|alt| alt.split_once('.')) {
+            None if alternate.is_none() => variants.plain = Some(entry.path()),
+            Some(("os", os)) => variants.os.push((os.to_string(), entry.path())),
+            Some(("hostname", host)) => variants.hostname.push((host.to_string(), entry.path())),
+            _ => {
+                return Err(ShiftError::Custom(format!(
+                    "{}: expected `##os.<os>` or `##hostname.<host>`",
+                    entry.path().display()
+                )))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A plan file linking `links` into `into`.
+pub fn plan_toml(links: &[Link], into: &str) -> String {
+    let shifts = links
+        .iter()
+        .map(|link| {
+            let mut shift = toml::Table::new();
+            shift.insert("type".into(), "symlink".into());
+            shift.insert("path".into(), link.path.display().to_string().into());
+            shift.insert("target".into(), link.source.display().to_string().into());
+            shift.insert("tags".into(), vec!["dotfiles".to_string()].into());
+            if !link.os.is_empty() {
+                shift.insert("os".into(), link.os.clone().into());
+            }
+            if !link.hostname.is_empty() {
+                shift.insert("hostname".into(), link.hostname.clone().into());
+            }
+            toml::Value::Table(shift)
+        })
+        .collect::<Vec<_>>();
+    let mut plan = toml::Table::new();
+    plan.insert("root".into(), into.into());
+    plan.insert("shift".into(), shifts.into());
+    format!(
+        "# Generated by `skies link`.\n\n{}",
+        toml::to_string(&plan).unwrap_or_default()
+    )
+}
@@ -0,0 +1,162 @@
+//! Downloads shared between runs and plans, stored by content.
+//!
+//! The cache directory holds:
+//!
+//! - `blobs/<sha256>`: each file downloaded, named by the hash of its
+//!   contents, so the same release tarball fetched from two mirrors or by
+//!   two plans is stored once;
+//! - `urls/<hash>`: for each URL, the content hash it last served.
+//!
+//! A download pinned with a SHA-256 is taken from `blobs/` whatever URL
+//! it came from; an unpinned one is taken from the blob its URL last
+//! served, since shifts treat URLs as naming a fixed release. Using a blob
+//! marks it recently used, and [`DownloadCache::gc`] removes those unused
+//! for a while. Unlike the [artifact cache](crate::artifacts), this one is
+//! on by default and never required: anything missing is downloaded.
+
+use std::env;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::error::ShiftResult;
+use crate::hash::{sha256_hex, short_hash};
+
+/// Overrides where the cache lives; empty turns it off.
+pub const CACHE_DIR_ENV: &str = "SKIES_CACHE_DIR";
+
+/// A content-addressed download cache; see the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct DownloadCache {
+    dir: PathBuf,
+}
+
+/// What [`DownloadCache::gc`] removed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GcReport {
+    pub removed: usize,
+    pub freed: u64,
+    pub kept: usize,
+}
+
+impl DownloadCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        DownloadCache { dir: dir.into() }
+    }
+
+    /// `$SKIES_CACHE_DIR`, else `$XDG_CACHE_HOME/skies`, else
+    /// `~/.cache/skies`. `None` when `SKIES_CACHE_DIR` is empty or there
+    /// is no home directory.
+    pub fn default_dir() -> Option<PathBuf> {
+        if let Some(dir) = env::var_os(CACHE_DIR_ENV) {
+            return (!dir.is_empty()).then(|| PathBuf::from(dir));
+        }
+        match env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+            Some(dir) => Some(PathBuf::from(dir).join("skies")),
+            None => env::var_os("HOME")
+                .filter(|home| !home.is_empty())
+                .map(|home| PathBuf::from(home).join(".cache").join("skies")),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn blob_path(&self, sha256: &str) -> PathBuf {
+        self.dir.join("blobs").join(sha256.to_ascii_lowercase())
+    }
+
+    fn url_path(&self, url: &str) -> PathBuf {
+        self.dir.join("urls").join(short_hash(url, 32))
+    }
+
+    /// The cached file for `url`, or for `sha256` when the download is
+    /// pinned. A blob that no longer matches its name is dropped.
+    pub fn lookup(&self, url: &str, sha256: Option<&str>) -> Option<PathBuf> {
+        let sha256 = match sha256 {
+            Some(sha256) => sha256.to_ascii_lowercase(),
+            None => fs::read_to_string(self.url_path(url))
+                .ok()?
+                .trim()
+                .to_string(),
+        };
+        let blob = self.blob_path(&sha256);
+        let bytes = fs::read(&blob).ok()?;
+        if sha256_hex(&bytes) != sha256 {
+            let _ = fs::remove_file(&blob);
+            return None;
+        }
+        // Marks the blob used, for `gc`; failing that only makes it
+        // collected sooner.
+        let _ = File::options()
+            .write(true)
+            .open(&blob)
+            .and_then(|file| file.set_modified(SystemTime::now()));
+        Some(blob)
+    }
+
+    /// Adds the file at `path`, downloaded from `url`, and returns its
+    /// SHA-256.
+    pub fn store(&self, url: &str, path: &Path) -> ShiftResult<String> {
+        let bytes = fs::read(path)?;
+        let sha256 = sha256_hex(&bytes);
+        let blob = self.blob_path(&sha256);
+        if !blob.is_file() {
+            fs::create_dir_all(self.dir.join("blobs"))?;
+            let partial = blob.with_extension("partial");
+            fs::write(&partial, &bytes)?;
+            fs::rename(&partial, &blob)?;
+        }
+        let index = self.url_path(url);
+        fs::create_dir_all(self.dir.join("urls"))?;
+        fs::write(&index, format!("{sha256}\n"))?;
+        Ok(sha256)
+    }
+
+    /// Removes blobs not used for `unused_for`, the URLs that pointed at
+    /// them, and anything left from interrupted downloads. With `dry_run`
+    /// only reports what it would remove.
+    pub fn gc(&self, unused_for: Duration, dry_run: bool) -> ShiftResult<GcReport> {
+        let cutoff = SystemTime::now()
+            .checked_sub(unused_for)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        let mut report = GcReport::default();
+        let mut remove = |path: &Path, size: u64| -> ShiftResult<()> {
+            report.removed += 1;
+            report.freed += size;
+            if !dry_run {
+                fs::remove_file(path)?;
+            }
+            Ok(())
+        };
+        let mut kept_blobs = Vec::new();
+        for entry in read_dir(&self.dir.join("blobs"))? {
+            let meta = entry.metadata()?;
+            let partial = entry.path().extension().is_some_and(|ext| ext == "partial");
+            if partial || meta.modified()? < cutoff {
+                remove(&entry.path(), meta.len())?;
+            } else {
+                kept_blobs.push(entry.file_name());
+            }
+        }
+        for entry in read_dir(&self.dir.join("urls"))? {
+            let sha256 = fs::read_to_string(entry.path())?;
+            if !kept_blobs.iter().any(|blob| *blob == *sha256.trim()) {
+                remove(&entry.path(), entry.metadata()?.len())?;
+            }
+        }
+        report.kept = kept_blobs.len();
+        Ok(report)
+    }
+}
+
+/// The entries of `dir`, none if it does not exist.
+fn read_dir(dir: &Path) -> ShiftResult<Vec<fs::DirEntry>> {
+    match fs::read_dir(dir) {
+        Ok(entries) => Ok(entries.collect::<io::Result<_>>()?),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err.into()),
+    }
+}
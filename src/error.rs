@@ -0,0 +1,248 @@
+use std::fmt;
+use std::io;
+
+/// Errors produced while applying, reverting or inspecting shifts.
+#[derive(Debug)]
+pub enum ShiftError {
+    /// An underlying filesystem or process error.
+    Io(io::Error),
+    /// An external command ran but exited unsuccessfully.
+    Command {
+        command: String,
+        code: Option<i32>,
+        stderr: String,
+    },
+    /// The plan itself is malformed (unknown dependency, cycle, ...).
+    Plan(String),
+    /// Preflight validation failed; one `(shift id, error)` per problem.
+    Preflight(Vec<(String, ShiftError)>),
+    /// A command, shift or the whole plan ran past its time limit. The
+    /// shift may have been left half done.
+    TimedOut(String),
+    /// The run was cancelled through its
+    /// [`CancellationToken`](crate::CancellationToken).
+    Cancelled,
+    /// The shift cannot be reverted, e.g. a command with no undo command.
+    RevertUnsupported { shift: String, reason: String },
+    /// The shift cannot tell whether it is applied, e.g. a command that
+    /// creates nothing. Plans treat it as not applied.
+    CheckUnsupported { shift: String, reason: String },
+    /// Something already in place, or claimed by another shift, is in the
+    /// way of `resource`.
+    Conflict { resource: String, reason: String },
+    /// An [`Assert`](crate::shifts::Assert) shift found the machine not as
+    /// expected.
+    AssertionFailed(String),
+    /// A safety rule forbids the change, such as a path outside the plan
+    /// root. Failures reported by the OS stay [`Io`](ShiftError::Io) errors.
+    PermissionDenied(String),
+    /// Any other failure, such as a missing environment variable or a
+    /// field out of range. Its [`kind`](ShiftError::kind) is `other`, so
+    /// callers and `error_kind` in JSON reports cannot tell these apart;
+    /// use a specific variant where one fits.
+    Custom(String),
+    /// `source`, with what was being done when it happened (`applying
+    /// \`id\``, `writing /etc/hosts`) and perhaps a hint on how to fix it.
+    /// Built with [`ShiftError::context`] and [`ShiftError::hint`].
+    Context {
+        context: Option<String>,
+        hint: Option<String>,
+        source: Box<ShiftError>,
+    },
+}
+
+pub type ShiftResult<T> = Result<T, ShiftError>;
+
+impl ShiftError {
+    /// Wraps the error with what was being done when it happened.
+    pub fn context(self, context: impl Into<String>) -> Self {
+        ShiftError::Context {
+            context: Some(context.into()),
+            hint: None,
+            source: Box::new(self),
+        }
+    }
+
+    /// Attaches a remediation hint, e.g. "install git or add a Package
+    /// shift". An outer hint replaces none of the inner ones; all are shown.
+    pub fn hint(self, hint: impl Into<String>) -> Self {
+        match self {
+            ShiftError::Context {
+                context,
+                hint: None,
+                source,
+            } => ShiftError::Context {
+                context,
+                hint: Some(hint.into()),
+                source,
+            },
+            err => ShiftError::Context {
+                context: None,
+                hint: Some(hint.into()),
+                source: Box::new(err),
+            },
+        }
+    }
+
+    /// The error under any context, which says what kind of failure this is.
+    pub fn root(&self) -> &ShiftError {
+        match self {
+            ShiftError::Context { source, .. } => source.root(),
+            err => err,
+        }
+    }
+
+    /// A stable name for the kind of the [`root`](Self::root) error, such
+    /// as `conflict` or `timed_out`, for JSON output and logs.
+    pub fn kind(&self) -> &'static str {
+        match self.root() {
+            ShiftError::Io(_) => "io",
+            ShiftError::Command { .. } => "command",
+            ShiftError::Plan(_) => "plan",
+            ShiftError::Preflight(_) => "preflight",
+            ShiftError::TimedOut(_) => "timed_out",
+            ShiftError::Cancelled => "cancelled",
+            ShiftError::RevertUnsupported { .. } => "revert_unsupported",
+            ShiftError::CheckUnsupported { .. } => "check_unsupported",
+            ShiftError::Conflict { .. } => "conflict",
+            ShiftError::AssertionFailed(_) => "assertion_failed",
+            ShiftError::PermissionDenied(_) => "permission_denied",
+            ShiftError::Custom(_) | ShiftError::Context { .. } => "other",
+        }
+    }
+
+    /// The contexts, outermost first, followed by the root error.
+    pub fn chain(&self) -> Vec<String> {
+        let mut chain = Vec::new();
+        let mut err = self;
+        while let ShiftError::Context {
+            context, source, ..
+        } = err
+        {
+            chain.extend(context.clone());
+            err = source;
+        }
+        chain.push(err.to_string());
+        chain
+    }
+
+    /// Hints on how to fix the error, outermost first. Preflight problems
+    /// contribute theirs prefixed with the shift id, and a permission error
+    /// with no hint of its own gets a generic one.
+    pub fn hints(&self) -> Vec<String> {
+        let mut hints = Vec::new();
+        let mut err = self;
+        while let ShiftError::Context { hint, source, .. } = err {
+            hints.extend(hint.clone());
+            err = source;
+        }
+        match err {
+            ShiftError::Preflight(problems) => {
+                for (id, problem) in problems {
+                    hints.extend(
+                        problem
+                            .hints()
+                            .into_iter()
+                            .map(|hint| format!("{id}: {hint}")),
+                    );
+                }
+            }
+            ShiftError::Io(io)
+                if hints.is_empty() && io.kind() == io::ErrorKind::PermissionDenied =>
+            {
+                hints.push("run skies as a user allowed to change this, e.g. with sudo".into());
+            }
+            _ => {}
+        }
+        hints
+    }
+}
+
+/// [`ShiftError::context`] and [`ShiftError::hint`] for results.
+pub trait ResultExt<T> {
+    fn context(self, context: impl Into<String>) -> ShiftResult<T>;
+
+    /// Like [`context`](ResultExt::context), building the message only on
+    /// failure.
+    fn with_context<C: Into<String>>(self, context: impl FnOnce() -> C) -> ShiftResult<T>;
+
+    fn hint(self, hint: impl Into<String>) -> ShiftResult<T>;
+}
+
+impl<T, E: Into<ShiftError>> ResultExt<T> for Result<T, E> {
+    fn context(self, context: impl Into<String>) -> ShiftResult<T> {
+        self.map_err(|err| err.into().context(context))
+    }
+
+    fn with_context<C: Into<String>>(self, context: impl FnOnce() -> C) -> ShiftResult<T> {
+        self.map_err(|err| err.into().context(context()))
+    }
+
+    fn hint(self, hint: impl Into<String>) -> ShiftResult<T> {
+        self.map_err(|err| err.into().hint(hint))
+    }
+}
+
+impl fmt::Display for ShiftError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShiftError::Io(err) => write!(f, "io error: {err}"),
+            ShiftError::Command {
+                command,
+                code,
+                stderr,
+            } => {
+                match code {
+                    Some(code) => write!(f, "`{command}` exited with status {code}")?,
+                    None => write!(f, "`{command}` was terminated by a signal")?,
+                }
+                let stderr = stderr.trim();
+                if !stderr.is_empty() {
+                    write!(f, ": {stderr}")?;
+                }
+                Ok(())
+            }
+            ShiftError::Plan(msg) => write!(f, "invalid plan: {msg}"),
+            ShiftError::Preflight(problems) => {
+                write!(f, "preflight failed:")?;
+                for (id, err) in problems {
+                    write!(f, "\n  {id}: {err}")?;
+                }
+                Ok(())
+            }
+            ShiftError::Cancelled => f.write_str("cancelled"),
+            ShiftError::RevertUnsupported { shift, reason } => {
+                write!(f, "cannot revert {shift}: {reason}")
+            }
+            ShiftError::CheckUnsupported { shift, reason } => {
+                write!(f, "cannot tell whether {shift} is applied: {reason}")
+            }
+            ShiftError::Conflict { resource, reason } => write!(f, "{resource} {reason}"),
+            ShiftError::AssertionFailed(msg) => write!(f, "assertion failed: {msg}"),
+            ShiftError::PermissionDenied(msg) => write!(f, "not permitted: {msg}"),
+            ShiftError::TimedOut(msg) | ShiftError::Custom(msg) => f.write_str(msg),
+            ShiftError::Context {
+                context: Some(context),
+                source,
+                ..
+            } => write!(f, "{context}: {source}"),
+            ShiftError::Context { source, .. } => write!(f, "{source}"),
+        }
+    }
+}
+
+impl std::error::Error for ShiftError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ShiftError::Io(err) => Some(err),
+            ShiftError::Context { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ShiftError {
+    fn from(err: io::Error) -> Self {
+        ShiftError::Io(err)
+    }
+}
@@ -0,0 +1,244 @@
+//! Running external commands.
+//!
+//! [`Cmd`](crate::shifts::Cmd), and every shift built on it, runs commands
+//! through the context's [`Exec`]. [`RealExec`] starts processes;
+//! [`MockExec`] answers from a script and records what was asked, so plans
+//! can be tested without git or npm, and other targets can slot in their
+//! own way of running commands:
+//!
+//! ```
+//! use std::sync::Arc;
+//!
+//! use skies::exec::{CommandOutput, MockExec};
+//! use skies::shifts::Cmd;
+//! use skies::ExecutionContext;
+//!
+//! let exec = Arc::new(
+//!     MockExec::new().on(["git", "rev-parse"], CommandOutput::success("abc123\n")),
+//! );
+//! let ctx = ExecutionContext::new().with_executor(exec.clone());
+//! let head = Cmd::new("git").args(["rev-parse", "HEAD"]).output(&ctx).unwrap();
+//! assert_eq!(head, "abc123\n");
+//! assert_eq!(exec.command_lines(), ["git rev-parse HEAD"]);
+//! ```
+
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+
+/// A command to run, with everything about how to run it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandSpec {
+    pub program: String,
+    pub args: Vec<String>,
+    pub cwd: PathBuf,
+    /// Set on top of the inherited environment.
+    pub env: BTreeMap<String, String>,
+    /// Stop the command after this long.
+    pub timeout: Option<Duration>,
+}
+
+impl CommandSpec {
+    /// The program and its arguments, space separated.
+    pub fn command_line(&self) -> String {
+        std::iter::once(self.program.as_str())
+            .chain(self.args.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// How a command exited and what it printed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandOutput {
+    /// The exit status, or `None` if a signal ended the command.
+    pub code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl CommandOutput {
+    /// A successful run that printed `stdout`.
+    pub fn success(stdout: impl Into<String>) -> Self {
+        CommandOutput {
+            code: Some(0),
+            stdout: stdout.into(),
+            stderr: String::new(),
+        }
+    }
+
+    /// A run that exited with `code` after printing `stderr`.
+    pub fn failure(code: i32, stderr: impl Into<String>) -> Self {
+        CommandOutput {
+            code: Some(code),
+            stdout: String::new(),
+            stderr: stderr.into(),
+        }
+    }
+
+    pub fn succeeded(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+/// Runs commands for shifts.
+pub trait Exec: Send + Sync {
+    /// Runs `command` to completion. An unsuccessful exit is not an error
+    /// here; the output says how it went. Implementations that start real
+    /// work should stop it, with [`ShiftError::TimedOut`] or
+    /// [`ShiftError::Cancelled`], when its timeout or `ctx`'s deadline
+    /// passes or the run is cancelled.
+    fn run(&self, ctx: &ExecutionContext, command: &CommandSpec) -> ShiftResult<CommandOutput>;
+}
+
+/// Runs commands as local processes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RealExec;
+
+impl Exec for RealExec {
+    fn run(&self, ctx: &ExecutionContext, spec: &CommandSpec) -> ShiftResult<CommandOutput> {
+        let program = &spec.program;
+        let mut child = Command::new(program)
+            .args(&spec.args)
+            .envs(&spec.env)
+            .current_dir(&spec.cwd)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| {
+                let not_found = err.kind() == std::io::ErrorKind::NotFound;
+                let err = ShiftError::from(err).context(format!("running `{program}`"));
+                if not_found {
+                    err.hint(format!(
+                        "`{program}` is not installed or not on PATH; install it or add a shift that does"
+                    ))
+                } else {
+                    err
+                }
+            })?;
+        let stdout = drain(child.stdout.take());
+        let stderr = drain(child.stderr.take());
+
+        let started = Instant::now();
+        let own_deadline = spec
+            .timeout
+            .and_then(|timeout| started.checked_add(timeout));
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            let err = match (own_deadline, ctx.check_cancelled()) {
+                (Some(deadline), _) if Instant::now() >= deadline => ShiftError::TimedOut(format!(
+                    "`{}` timed out after {}s",
+                    spec.command_line(),
+                    spec.timeout.unwrap_or_default().as_secs_f32()
+                )),
+                (_, Err(err)) if matches!(err.root(), ShiftError::TimedOut(_)) => {
+                    ShiftError::TimedOut(format!(
+                        "`{}` was stopped: the shift ran out of time",
+                        spec.command_line()
+                    ))
+                }
+                (_, Err(err)) => err,
+                (_, Ok(())) => {
+                    thread::sleep(Duration::from_millis(20));
+                    continue;
+                }
+            };
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(err);
+        };
+        Ok(CommandOutput {
+            code: status.code(),
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+        })
+    }
+}
+
+/// Reads a child pipe to completion on a separate thread so a chatty command
+/// cannot block on a full pipe while we wait for it.
+fn drain<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut buf = String::new();
+        if let Some(mut pipe) = pipe {
+            let mut bytes = Vec::new();
+            let _ = pipe.read_to_end(&mut bytes);
+            buf = String::from_utf8_lossy(&bytes).into_owned();
+        }
+        buf
+    })
+}
+
+/// Answers commands from a script instead of running them.
+///
+/// Each command is matched against the responses added with
+/// [`on`](MockExec::on), most recent first; one that matches nothing
+/// succeeds with no output. Every command is recorded in
+/// [`calls`](MockExec::calls).
+#[derive(Default)]
+pub struct MockExec {
+    responses: Mutex<Vec<(Vec<String>, CommandOutput)>>,
+    calls: Mutex<Vec<CommandSpec>>,
+}
+
+impl MockExec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers commands whose program and leading arguments are `argv`
+    /// with `output`. `["git"]` matches every git command, `["git",
+    /// "clone"]` only clones.
+    pub fn on<I, A>(self, argv: I, output: CommandOutput) -> Self
+    where
+        I: IntoIterator<Item = A>,
+        A: Into<String>,
+    {
+        let argv = argv.into_iter().map(Into::into).collect();
+        lock(&self.responses).push((argv, output));
+        self
+    }
+
+    /// The commands run so far, in order.
+    pub fn calls(&self) -> Vec<CommandSpec> {
+        lock(&self.calls).clone()
+    }
+
+    /// The command lines run so far, as [`CommandSpec::command_line`]
+    /// gives them.
+    pub fn command_lines(&self) -> Vec<String> {
+        lock(&self.calls)
+            .iter()
+            .map(CommandSpec::command_line)
+            .collect()
+    }
+}
+
+impl Exec for MockExec {
+    fn run(&self, _ctx: &ExecutionContext, spec: &CommandSpec) -> ShiftResult<CommandOutput> {
+        lock(&self.calls).push(spec.clone());
+        let argv: Vec<&String> = std::iter::once(&spec.program).chain(&spec.args).collect();
+        Ok(lock(&self.responses)
+            .iter()
+            .rev()
+            .find(|(prefix, _)| {
+                prefix.len() <= argv.len() && prefix.iter().zip(&argv).all(|(a, b)| a == *b)
+            })
+            .map(|(_, output)| output.clone())
+            .unwrap_or_else(|| CommandOutput::success("")))
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|p| p.into_inner())
+}
@@ -0,0 +1,133 @@
+//! Plans as standalone bash scripts.
+//!
+//! The script stops at the first command that fails. Each shift is
+//! introduced by a comment with its ID and type and announced on stderr as
+//! it starts; what the export leaves out is a `# TODO` comment above it.
+
+use super::{comment, quote, quote_path, Exported};
+use crate::packages::PackageManager;
+
+/// The script for `exported`, converted from the plan at `source`.
+pub fn render(exported: &Exported, source: &str) -> String {
+    let mut out = String::from("#!/usr/bin/env bash\n");
+    out.push_str(&format!(
+        "# Exported by `skies export` from {}.\n",
+        comment(source)
+    ));
+    out.push_str(
+        "# Best effort: each shift runs once, in order, with no check of whether it is\n\
+         # already applied, so running it again may fail or repeat work.\n",
+    );
+    match exported.todo_count() {
+        0 => {}
+        1 => out.push_str("# Review it before running: 1 TODO below.\n"),
+        n => out.push_str(&format!("# Review it before running: {n} TODOs below.\n")),
+    }
+    for note in &exported.notes {
+        out.push_str(&format!("# TODO: {}\n", comment(note)));
+    }
+    out.push_str("set -euo pipefail\n");
+    if let Some(root) = &exported.root {
+        out.push_str(&format!("\ncd -- {}\n", quote_path(root)));
+    }
+    if exported.installs() {
+        out.push_str(&install_function());
+    }
+    for step in &exported.steps {
+        out.push('\n');
+        match &step.kind {
+            Some(kind) => out.push_str(&format!("# {} ({})\n", comment(&step.id), comment(kind))),
+            None => out.push_str(&format!("# {}\n", comment(&step.id))),
+        }
+        for todo in &step.todos {
+            out.push_str(&format!("# TODO: {}\n", comment(todo)));
+        }
+        for line in step.original.lines() {
+            out.push_str(&format!("#   {}\n", comment(line)));
+        }
+        if step.actions.is_empty() {
+            continue;
+        }
+        out.push_str(&format!(
+            "echo {} >&2\n",
+            quote(&format!("==> {}", step.id))
+        ));
+        for action in &step.actions {
+            out.push_str(&action.to_shell());
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// `install_packages`, which installs with the first package manager
+/// found, as the `package` shift does.
+fn install_function() -> String {
+    let mut out = String::from("\ninstall_packages() {\n");
+    for (idx, manager) in PackageManager::ALL.into_iter().enumerate() {
+        let keyword = if idx == 0 { "if" } else { "elif" };
+        let mut words: Vec<String> = manager
+            .env()
+            .iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect();
+        words.push(manager.program().into());
+        words.extend(manager.install_args().iter().map(|arg| arg.to_string()));
+        words.push("\"$@\"".into());
+        out.push_str(&format!(
+            "    {keyword} command -v {} >/dev/null 2>&1; then\n        {}\n",
+            manager.program(),
+            words.join(" ")
+        ));
+    }
+    let programs: Vec<&str> = PackageManager::ALL
+        .iter()
+        .map(|manager| manager.program())
+        .collect();
+    out.push_str(&format!(
+        "    else\n        echo 'no supported package manager ({}) found' >&2\n        return 1\n    fi\n}}\n",
+        programs.join(", ")
+    ));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render;
+    use crate::export::{export, Exported, Step};
+    use crate::plan::{PlanEntry, ShiftPlan};
+    use crate::shifts::CreateDir;
+
+    const ID: &str = "harmless\ntouch /tmp/skies-pwned\r";
+
+    #[test]
+    fn line_breaks_stay_inside_comments() {
+        let exported = Exported {
+            notes: vec!["note\nrm -rf ~".into()],
+            steps: vec![Step {
+                id: ID.into(),
+                kind: Some("create_dir\nreboot".into()),
+                todos: vec!["todo\nreboot".into()],
+                original: "path = 'app'\r\nreboot\r".into(),
+                ..Step::default()
+            }],
+            ..Exported::default()
+        };
+        let script = render(&exported, "skies.toml\nreboot");
+        for line in script.lines() {
+            assert!(!line.contains('\r'), "{line:?}");
+            assert!(
+                line.starts_with('#') || !line.contains("touch") && !line.contains("reboot"),
+                "{line:?} runs"
+            );
+        }
+        assert!(script.contains("# harmless\\ntouch /tmp/skies-pwned\\r (create_dir\\nreboot)\n"));
+    }
+
+    #[test]
+    fn ids_with_line_breaks_are_rejected() {
+        let mut plan = ShiftPlan::new();
+        plan.push(PlanEntry::new(CreateDir::new("app")).with_id(ID));
+        assert!(export(&plan).is_err());
+    }
+}
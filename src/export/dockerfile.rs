@@ -0,0 +1,237 @@
+//! Plans as Dockerfiles, to build an image set up the way the plan sets
+//! up a machine.
+//!
+//! Each shift becomes `RUN` instructions, in the shell form so paths with
+//! `~` expand to the image user's home, and files are written with
+//! heredocs, which need BuildKit. Shifts that run as another user switch to
+//! it with `USER`. Packages are installed with the package manager the
+//! base image's name suggests.
+
+use super::{comment, quote, Action, Exported, Step};
+use crate::packages::PackageManager;
+
+/// Image names, or parts of them, and the package manager images of that
+/// family have. Anything else is taken to be Debian-based, as most
+/// language images are.
+const FAMILIES: &[(&str, PackageManager)] = &[
+    ("alpine", PackageManager::Apk),
+    ("fedora", PackageManager::Dnf),
+    ("rockylinux", PackageManager::Dnf),
+    ("almalinux", PackageManager::Dnf),
+    ("centos", PackageManager::Dnf),
+    ("ubi", PackageManager::Dnf),
+    ("amazonlinux", PackageManager::Dnf),
+    ("archlinux", PackageManager::Pacman),
+    ("opensuse", PackageManager::Zypper),
+    ("debian", PackageManager::Apt),
+    ("ubuntu", PackageManager::Apt),
+    ("bookworm", PackageManager::Apt),
+    ("bullseye", PackageManager::Apt),
+    ("trixie", PackageManager::Apt),
+    ("jammy", PackageManager::Apt),
+    ("noble", PackageManager::Apt),
+];
+
+/// The Dockerfile for `exported`, converted from the plan at `source`, to
+/// build on `base`. What images cannot have is added to `exported` as
+/// TODOs, with the step's commands quoted instead of run.
+pub fn render(exported: &mut Exported, source: &str, base: &str) -> String {
+    let (manager, known) = package_manager(base);
+    if exported.installs() && !known {
+        exported.notes.push(format!(
+            "`{base}` is taken to be Debian-based; packages install with apt-get"
+        ));
+    }
+    for step in &mut exported.steps {
+        if let Some(reason) = unsupported(step) {
+            step.todos.push(reason);
+            let commands: Vec<String> = step.actions.drain(..).map(|a| a.to_shell()).collect();
+            step.original.push_str(&commands.join("\n"));
+        }
+    }
+    let mut out = String::from("# syntax=docker/dockerfile:1\n");
+    out.push_str(&format!(
+        "# Exported by `skies export` from {}.\n",
+        comment(source)
+    ));
+    match exported.todo_count() {
+        0 => {}
+        1 => out.push_str("# Review it before building: 1 TODO below.\n"),
+        n => out.push_str(&format!("# Review it before building: {n} TODOs below.\n")),
+    }
+    for note in &exported.notes {
+        out.push_str(&format!("# TODO: {}\n", comment(note)));
+    }
+    out.push_str(&format!("FROM {base}\n"));
+    if let Some(root) = &exported.root {
+        out.push_str(&format!("WORKDIR {root}\n"));
+    }
+    // The user the instructions so far leave `RUN` running as; `None` for
+    // root, as base images mostly run as.
+    let mut current: Option<&str> = None;
+    for step in &exported.steps {
+        out.push('\n');
+        match &step.kind {
+            Some(kind) => out.push_str(&format!("# {} ({})\n", comment(&step.id), comment(kind))),
+            None => out.push_str(&format!("# {}\n", comment(&step.id))),
+        }
+        for todo in &step.todos {
+            out.push_str(&format!("# TODO: {}\n", comment(todo)));
+        }
+        for line in step.original.lines() {
+            out.push_str(&format!("#   {}\n", comment(line)));
+        }
+        // A step's commands share a `RUN` until one ends in a heredoc or
+        // the user changes.
+        let mut run: Vec<String> = Vec::new();
+        for action in &step.actions {
+            let user = user(action);
+            if user != current {
+                flush(&mut out, &mut run);
+                out.push_str(&format!("USER {}\n", user.unwrap_or("root")));
+                current = user;
+            }
+            let line = match action {
+                Action::Install(packages) => install(manager, packages),
+                action => without_user(action).to_shell(),
+            };
+            let heredoc = line.contains('\n');
+            run.push(line);
+            if heredoc {
+                flush(&mut out, &mut run);
+            }
+        }
+        flush(&mut out, &mut run);
+    }
+    out
+}
+
+/// `commands` as one `RUN`, if there are any.
+fn flush(out: &mut String, commands: &mut Vec<String>) {
+    // `||` binds looser than `&&`, so joined commands that use it are
+    // grouped.
+    if commands.len() > 1 {
+        for command in commands.iter_mut() {
+            let (first, rest) = command.split_once('\n').unwrap_or((command, ""));
+            if first.contains(" || ") {
+                *command = match rest {
+                    "" => format!("{{ {first}; }}"),
+                    rest => format!("{{ {first}; }}\n{rest}"),
+                };
+            }
+        }
+    }
+    if !commands.is_empty() {
+        out.push_str(&format!("RUN {}\n", commands.join(" \\\n    && ")));
+        commands.clear();
+    }
+}
+
+/// The package manager images named `image` have, and whether that is
+/// known rather than assumed.
+fn package_manager(image: &str) -> (PackageManager, bool) {
+    // The registry does not count, but the tag does, as in
+    // `python:3.12-alpine`.
+    let name = image.rsplit('/').next().unwrap_or(image);
+    let name = name.split('@').next().unwrap_or(name).to_lowercase();
+    FAMILIES
+        .iter()
+        .find(|(family, _)| name.contains(family))
+        .map(|&(_, manager)| (manager, true))
+        .unwrap_or((PackageManager::Apt, false))
+}
+
+/// Installing `packages` with `manager`, refreshing its index first and
+/// leaving no cache in the layer.
+fn install(manager: PackageManager, packages: &[String]) -> String {
+    let mut words: Vec<String> = manager
+        .env()
+        .iter()
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect();
+    words.push(manager.program().into());
+    words.extend(manager.install_args().iter().map(|arg| arg.to_string()));
+    if manager == PackageManager::Apk {
+        words.push("--no-cache".into());
+    }
+    words.extend(packages.iter().map(|package| quote(package)));
+    let install = words.join(" ");
+    match manager {
+        PackageManager::Apt => {
+            format!("apt-get update && {install} && rm -rf /var/lib/apt/lists/*")
+        }
+        PackageManager::Dnf | PackageManager::Yum => {
+            format!("{install} && {} clean all", manager.program())
+        }
+        PackageManager::Pacman => format!("pacman -Sy && {install} && pacman -Scc --noconfirm"),
+        PackageManager::Zypper => format!("zypper --non-interactive refresh && {install}"),
+        PackageManager::Apk | PackageManager::Brew => install,
+    }
+}
+
+/// Why `step` is left out of an image, if it is.
+fn unsupported(step: &Step) -> Option<String> {
+    match step.kind.as_deref() {
+        Some("vscode_extension") => Some(
+            "images have no VS Code; list the extension in the dev container's \
+             `customizations.vscode.extensions` instead"
+                .into(),
+        ),
+        _ => None,
+    }
+}
+
+fn user(action: &Action) -> Option<&str> {
+    match action {
+        Action::Run(command) => command.user.as_deref(),
+        Action::Shell { user, .. } | Action::Write { user, .. } => user.as_deref(),
+        Action::Install(_) => None,
+    }
+}
+
+/// `action` run as whoever `RUN` runs as, since `USER` switches to its
+/// user.
+fn without_user(action: &Action) -> Action {
+    let mut action = action.clone();
+    match &mut action {
+        Action::Run(command) => command.user = None,
+        Action::Shell { user, .. } | Action::Write { user, .. } => *user = None,
+        Action::Install(_) => {}
+    }
+    action
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render;
+    use crate::export::{comment, Exported, Step};
+
+    #[test]
+    fn comment_escapes_line_breaks() {
+        assert_eq!(comment("a\nb\r\nc"), "a\\nb\\r\\nc");
+        assert_eq!(comment("plain # text"), "plain # text");
+    }
+
+    #[test]
+    fn line_breaks_stay_inside_comments() {
+        let mut exported = Exported {
+            notes: vec!["note\nRUN reboot".into()],
+            steps: vec![Step {
+                id: "harmless\nRUN touch /tmp/pwned".into(),
+                kind: Some("create_dir".into()),
+                todos: vec!["todo\rRUN reboot".into()],
+                original: "path = 'app'\nRUN reboot\r".into(),
+                ..Step::default()
+            }],
+            ..Exported::default()
+        };
+        let dockerfile = render(&mut exported, "skies.toml", "debian");
+        let instructions: Vec<&str> = dockerfile
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .filter(|line| !line.is_empty())
+            .collect();
+        assert_eq!(instructions, ["FROM debian"]);
+        assert!(!dockerfile.contains('\r'));
+    }
+}
@@ -0,0 +1,474 @@
+//! Plans rendered to run without skies, for `skies export`: as a script
+//! for machines skies cannot be installed on, or a Dockerfile for an image.
+//!
+//! Each shift of the plan becomes [`Action`]s any shell can take: commands
+//! to run, files to write and packages to install. The result is a best
+//! effort: every action runs once, in execution order, with no check of
+//! whether it is already applied and no way to revert it. Shifts with no
+//! equivalent, and the parts of a shift that are not exported, such as
+//! secrets, are left as TODOs with the shift's definition.
+
+pub mod bash;
+pub mod dockerfile;
+
+use std::path::Path;
+
+use crate::error::ShiftResult;
+use crate::metadata::REDACTED;
+use crate::plan::ShiftPlan;
+
+/// Characters a shell word can have without quoting.
+const SAFE: &str = "_@%+=:,./-";
+
+/// Ends a file's contents in a heredoc, unless the contents have a line
+/// like it; then a number is added.
+const DELIMITER: &str = "SKIES_EOF";
+
+/// A plan converted to actions.
+#[derive(Debug, Clone, Default)]
+pub struct Exported {
+    /// The plan's root, which relative paths are relative to.
+    pub root: Option<String>,
+    /// TODOs about the plan as a whole.
+    pub notes: Vec<String>,
+    /// One per shift, in execution order.
+    pub steps: Vec<Step>,
+}
+
+/// One shift of the plan.
+#[derive(Debug, Clone, Default)]
+pub struct Step {
+    pub id: String,
+    /// The shift's type, if it came from a plan file.
+    pub kind: Option<String>,
+    pub actions: Vec<Action>,
+    /// What was left out, to do by hand.
+    pub todos: Vec<String>,
+    /// The shift's definition, quoted with its TODOs.
+    pub original: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum Action {
+    Run(Command),
+    /// Shell text, already quoted, run as `user` if set.
+    Shell {
+        script: String,
+        user: Option<String>,
+    },
+    /// Writes `contents` to `path`, which may start with `~`.
+    Write {
+        path: String,
+        contents: String,
+        mode: Option<u32>,
+        user: Option<String>,
+    },
+    /// Installs system packages with whichever package manager there is.
+    Install(Vec<String>),
+}
+
+/// A program with its arguments.
+#[derive(Debug, Clone, Default)]
+pub struct Command {
+    /// Quoted for a shell, with paths quoted by [`quote_path`].
+    pub words: Vec<String>,
+    /// The directory to run in; `~` is left to expand.
+    pub cwd: Option<String>,
+    pub env: Vec<(String, String)>,
+    pub user: Option<String>,
+    /// Skip the command where this path exists; `~` is left to expand.
+    pub creates: Option<String>,
+}
+
+impl Action {
+    /// The action as one shell command, which may end in a heredoc.
+    /// Packages are installed with `install_packages`, which the script
+    /// has to define.
+    pub fn to_shell(&self) -> String {
+        match self {
+            Action::Run(command) => {
+                let mut line = command.to_shell();
+                if let Some(cwd) = &command.cwd {
+                    line = format!("(cd -- {} && {line})", quote_path(cwd));
+                }
+                if let Some(creates) = &command.creates {
+                    line = format!("[ -e {} ] || {line}", quote_path(creates));
+                }
+                line
+            }
+            Action::Shell { script, user: None } => script.clone(),
+            Action::Shell {
+                script,
+                user: Some(user),
+            } => format!("sudo -u {} -H -- sh -c {}", quote(user), quote(script)),
+            Action::Write {
+                path,
+                contents,
+                mode,
+                user,
+            } => {
+                let path = quote_path(path);
+                let sudo = match user {
+                    Some(user) => format!("sudo -u {} -- ", quote(user)),
+                    None => String::new(),
+                };
+                let writer = match user {
+                    Some(_) => format!("{sudo}tee -- {path} >/dev/null"),
+                    None => format!("cat > {path}"),
+                };
+                let chmod = match mode {
+                    Some(mode) => format!(" && {sudo}chmod {mode:o} -- {path}"),
+                    None => String::new(),
+                };
+                match contents.strip_suffix('\n') {
+                    Some(body) => {
+                        let delimiter = delimiter(contents);
+                        format!("{writer} <<'{delimiter}'{chmod}\n{body}\n{delimiter}")
+                    }
+                    None => format!("printf '%s' {} | {writer}{chmod}", quote(contents)),
+                }
+            }
+            Action::Install(packages) => {
+                let packages: Vec<String> = packages.iter().map(|package| quote(package)).collect();
+                format!("install_packages {}", packages.join(" "))
+            }
+        }
+    }
+}
+
+impl Command {
+    fn new(words: Vec<String>) -> Self {
+        Command {
+            words,
+            ..Command::default()
+        }
+    }
+
+    fn user(mut self, user: Option<&str>) -> Self {
+        self.user = user.map(str::to_string);
+        self
+    }
+
+    /// The command as shell text, without its `cwd` and `creates`.
+    pub fn to_shell(&self) -> String {
+        let mut words = Vec::new();
+        if let Some(user) = &self.user {
+            words.extend([
+                "sudo".into(),
+                "-u".into(),
+                quote(user),
+                "-H".into(),
+                "--".into(),
+            ]);
+        }
+        if !self.env.is_empty() {
+            words.push("env".into());
+            for (name, value) in &self.env {
+                words.push(quote(&format!("{name}={value}")));
+            }
+        }
+        words.extend(self.words.iter().cloned());
+        words.join(" ")
+    }
+}
+
+impl Exported {
+    /// How many TODOs there are.
+    pub fn todo_count(&self) -> usize {
+        self.notes.len()
+            + self
+                .steps
+                .iter()
+                .map(|step| step.todos.len())
+                .sum::<usize>()
+    }
+
+    /// Whether any step installs packages.
+    pub fn installs(&self) -> bool {
+        self.steps
+            .iter()
+            .flat_map(|step| &step.actions)
+            .any(|action| matches!(action, Action::Install(_)))
+    }
+}
+
+/// Converts `plan`, loaded from a plan file, shift by shift.
+pub fn export(plan: &ShiftPlan) -> ShiftResult<Exported> {
+    let mut exported = Exported {
+        root: plan.root().map(|root| root.display().to_string()),
+        ..Exported::default()
+    };
+    for idx in plan.execution_order()? {
+        let entry = &plan.entries()[idx];
+        let mut step = Step {
+            id: entry.id().to_string(),
+            ..Step::default()
+        };
+        match entry.definition() {
+            Some(mut fields) => {
+                let kind = match fields.remove("type") {
+                    Some(toml::Value::String(kind)) => kind,
+                    _ => String::new(),
+                };
+                if fields.values().any(redacted) {
+                    step.todos
+                        .push(format!("its secrets are left out as `{REDACTED}`"));
+                }
+                step.actions = convert(&kind, &fields, &mut step.todos);
+                if !step.todos.is_empty() {
+                    step.original = toml::to_string(&fields).unwrap_or_default();
+                }
+                step.kind = Some(kind);
+            }
+            None => step
+                .todos
+                .push("built in code, so there is no definition to export".into()),
+        }
+        exported.steps.push(step);
+    }
+    match plan.checks().len() {
+        0 => {}
+        1 => exported
+            .notes
+            .push("the plan's check is not exported".into()),
+        n => exported
+            .notes
+            .push(format!("the plan's {n} checks are not exported")),
+    }
+    Ok(exported)
+}
+
+/// A shift's actions, adding to `todos` what they leave out.
+fn convert(kind: &str, fields: &toml::Table, todos: &mut Vec<String>) -> Vec<Action> {
+    let text = |name: &str| fields.get(name).and_then(toml::Value::as_str);
+    let flag = |name: &str| fields.get(name).and_then(toml::Value::as_bool) == Some(true);
+    let mode = fields
+        .get("mode")
+        .and_then(toml::Value::as_integer)
+        .map(|mode| mode as u32);
+    let user = text("run_as");
+    let mut actions = Vec::new();
+    match kind {
+        "file" => {
+            let Some(path) = text("path") else {
+                return actions;
+            };
+            let contents = text("contents").unwrap_or_default();
+            if contents == REDACTED {
+                return actions;
+            }
+            actions.extend(parent(path, user));
+            actions.push(Action::Write {
+                path: path.to_string(),
+                contents: contents.to_string(),
+                mode,
+                user: user.map(str::to_string),
+            });
+        }
+        "create_dir" => {
+            let mut words = vec!["mkdir".to_string(), "-p".into()];
+            if let Some(mode) = mode {
+                words.extend(["-m".into(), format!("{mode:o}")]);
+            }
+            words.extend(["--".into(), quote_path(text("path").unwrap_or_default())]);
+            actions.push(Action::Run(Command::new(words).user(user)));
+        }
+        "symlink" => {
+            let (path, target) = (
+                text("path").unwrap_or_default(),
+                text("target").unwrap_or_default(),
+            );
+            actions.extend(parent(path, None));
+            let words = vec![
+                "ln".into(),
+                "-sfn".into(),
+                "--".into(),
+                quote_path(target),
+                quote_path(path),
+            ];
+            actions.push(Action::Run(Command::new(words)));
+        }
+        "cmd" => {
+            let mut words = Vec::new();
+            if let Some(secs) = fields.get("timeout").and_then(timeout) {
+                words.extend(["timeout".to_string(), secs]);
+            }
+            words.extend(text("program").map(quote));
+            words.extend(strings(fields.get("args")).iter().map(|arg| quote(arg)));
+            let env = match fields.get("env") {
+                Some(toml::Value::Table(env)) => env
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.as_str().unwrap_or_default().into()))
+                    .collect(),
+                _ => Vec::new(),
+            };
+            actions.push(Action::Run(Command {
+                cwd: text("cwd").map(str::to_string),
+                env,
+                creates: text("creates").map(str::to_string),
+                ..Command::new(words).user(user)
+            }));
+        }
+        "package" => actions.push(Action::Install(strings(fields.get("packages")))),
+        "line_in_file" => {
+            let (path, line) = (
+                text("path").unwrap_or_default(),
+                text("line").unwrap_or_default(),
+            );
+            if let Some(pattern) = text("matches") {
+                todos.push(format!(
+                    "the line is added rather than replacing the last one matching `{pattern}`"
+                ));
+            }
+            actions.push(Action::Shell {
+                script: format!(
+                    "grep -qxF -- {line} {path} 2>/dev/null || printf '%s\\n' {line} >> {path}",
+                    line = quote(line),
+                    path = quote_path(path)
+                ),
+                user: None,
+            });
+        }
+        "github_clone" => {
+            let repo = text("repo").unwrap_or_default();
+            let target = text("target").unwrap_or_default();
+            let url = match repo.contains("://") || repo.starts_with("git@") {
+                true => repo.to_string(),
+                false => format!("https://github.com/{repo}.git"),
+            };
+            let mut words = vec!["git".to_string(), "clone".into()];
+            if let Some(branch) = text("branch") {
+                words.extend(["--branch".into(), quote(branch)]);
+            }
+            for option in ["bare", "mirror"] {
+                if flag(option) {
+                    words.push(format!("--{option}"));
+                }
+            }
+            if let Some(reference) = text("reference") {
+                words.extend(["--reference".into(), quote_path(reference)]);
+            }
+            words.extend(["--".into(), quote(&url), quote_path(target)]);
+            if let Some(var) = text("token_env") {
+                todos.push(format!(
+                    "the clone needs the token in ${var}; give git credentials for it"
+                ));
+            }
+            actions.extend(parent(target, None));
+            actions.push(Action::Run(Command {
+                creates: Some(target.into()),
+                ..Command::new(words)
+            }));
+            if flag("lfs") {
+                let words = vec![
+                    "git".into(),
+                    "-C".into(),
+                    quote_path(target),
+                    "lfs".into(),
+                    "pull".into(),
+                ];
+                actions.push(Action::Run(Command::new(words)));
+            }
+        }
+        "vscode_extension" => {
+            let extension = text("extension").unwrap_or_default();
+            let wanted = match text("version") {
+                Some(version) => format!("{extension}@{version}"),
+                None => extension.to_string(),
+            };
+            let program = text("program").unwrap_or("code");
+            let words = vec![
+                quote(program),
+                "--install-extension".into(),
+                quote(&wanted),
+                "--force".into(),
+            ];
+            actions.push(Action::Run(Command::new(words)));
+        }
+        kind => todos.push(format!(
+            "`{kind}` shifts have no exported equivalent; apply it by hand"
+        )),
+    }
+    actions
+}
+
+/// Creates the directory `path` goes in, unless that is the root.
+fn parent(path: &str, user: Option<&str>) -> Option<Action> {
+    let parent = Path::new(path).parent()?.to_str()?;
+    if parent.is_empty() || parent == "." {
+        return None;
+    }
+    let words = vec!["mkdir".into(), "-p".into(), "--".into(), quote_path(parent)];
+    Some(Action::Run(Command::new(words).user(user)))
+}
+
+/// A `timeout` in seconds, as `timeout` takes it.
+fn timeout(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::Integer(secs) => Some(secs.to_string()),
+        toml::Value::Float(secs) => Some(secs.to_string()),
+        _ => None,
+    }
+}
+
+fn strings(value: Option<&toml::Value>) -> Vec<String> {
+    match value {
+        Some(toml::Value::Array(items)) => items
+            .iter()
+            .filter_map(|item| item.as_str().map(str::to_string))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn redacted(value: &toml::Value) -> bool {
+    match value {
+        toml::Value::String(text) => text == REDACTED,
+        toml::Value::Array(items) => items.iter().any(redacted),
+        toml::Value::Table(table) => table.values().any(redacted),
+        _ => false,
+    }
+}
+
+/// A heredoc delimiter that is not a line of `contents`.
+fn delimiter(contents: &str) -> String {
+    let taken = |delimiter: &str| contents.lines().any(|line| line == delimiter);
+    let mut delimiter = DELIMITER.to_string();
+    let mut n = 1;
+    while taken(&delimiter) {
+        n += 1;
+        delimiter = format!("{DELIMITER}_{n}");
+    }
+    delimiter
+}
+
+/// `text` made safe to put in a `#` comment: line breaks, which would end
+/// the comment and let the rest run, are written as `\n` and `\r`.
+pub fn comment(text: &str) -> String {
+    text.replace('\r', "\\r").replace('\n', "\\n")
+}
+
+/// `word` quoted for a POSIX shell, if it needs to be.
+pub fn quote(word: &str) -> String {
+    let safe = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || SAFE.contains(c));
+    match safe {
+        true => word.to_string(),
+        false => format!("'{}'", word.replace('\'', r"'\''")),
+    }
+}
+
+/// Like [`quote`], with a leading `~` left for the shell to expand, as
+/// skies expands it in paths.
+pub fn quote_path(path: &str) -> String {
+    match path.strip_prefix('~') {
+        Some("") => "\"$HOME\"".into(),
+        Some(rest) if rest.starts_with('/') => match &rest[1..] {
+            "" => "\"$HOME\"/".into(),
+            rest => format!("\"$HOME\"/{}", quote(rest)),
+        },
+        _ => quote(path),
+    }
+}
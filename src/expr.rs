@@ -0,0 +1,861 @@
+//! The small expression language of `when` conditions and `${...}`
+//! templates.
+//!
+//! ```toml
+//! [[shift]]
+//! type = "cmd"
+//! program = "nvidia-smi"
+//! when = "facts.os == 'linux' && (vars.gpu == true || contains(facts.hostname, 'gpu'))"
+//!
+//! [[shift]]
+//! type = "file"
+//! path = "/etc/motd"
+//! contents = "Welcome to ${upper(facts.hostname)}\n"
+//! ```
+//!
+//! An expression is built from:
+//!
+//! - literals: `'text'` or `"text"`, numbers such as `8080` or `1.5`,
+//!   `true` and `false`, and lists such as `['a', 'b']`;
+//! - references: a variable by name (`port`, or `vars.port`), a fact
+//!   (`facts.os`, `facts["label.role"]`), an environment variable
+//!   (`env.HOME`), an output of a shift from an earlier run
+//!   (`outputs.db.password`) or of a plan it
+//!   [requires](crate::monorepo) (`plans.db.server.port`);
+//! - comparisons `==`, `!=`, `<`, `<=`, `>`, `>=`, and `in` for membership
+//!   of a list or a string;
+//! - `&&` (or `and`), `||` (or `or`) and `!` (or `not`), with parentheses;
+//! - the functions `lower`, `upper`, `trim`, `len`, `contains`,
+//!   `starts_with`, `ends_with`, `matches` (with `*` wildcards),
+//!   `replace`, `defined` and `default`, as in `default(vars.port, 80)`.
+//!
+//! Variables and facts hold text; comparing one with a number or boolean
+//! compares its value as one, so `vars.port > 1024` works for `port =
+//! "8080"`, and text is true or false if it reads `true` or `false`.
+//! [`Expr::check`] works out types ahead of time, for `skies lint`: a
+//! typed [param](crate::params) has its type, other variables may be
+//! anything, facts and environment variables are text, which cannot be
+//! used as a condition, and outputs are whatever the shift produced.
+
+use std::fmt;
+
+use crate::error::{ShiftError, ShiftResult};
+
+/// A value an expression produces.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Str(String),
+    Num(f64),
+    Bool(bool),
+    List(Vec<Value>),
+}
+
+/// What [`Expr::check`] knows of a value ahead of time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Str,
+    Num,
+    Bool,
+    List,
+    /// Only known when the plan runs, like a variable's text, which may
+    /// be read as a number or boolean.
+    Any,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Type::Str => "string",
+            Type::Num => "number",
+            Type::Bool => "boolean",
+            Type::List => "list",
+            Type::Any => "any",
+        })
+    }
+}
+
+impl Value {
+    pub fn kind(&self) -> Type {
+        match self {
+            Value::Str(_) => Type::Str,
+            Value::Num(_) => Type::Num,
+            Value::Bool(_) => Type::Bool,
+            Value::List(_) => Type::List,
+        }
+    }
+
+    /// The value as the condition of a `when`. Text counts if it is
+    /// `true` or `false`.
+    pub fn truth(&self) -> ShiftResult<bool> {
+        match self {
+            Value::Bool(b) => Ok(*b),
+            Value::Str(s) if s == "true" => Ok(true),
+            Value::Str(s) if s == "false" => Ok(false),
+            other => Err(ShiftError::Plan(format!(
+                "expected a boolean, got {}",
+                other.describe()
+            ))),
+        }
+    }
+
+    /// The value as text in a template.
+    pub fn text(&self) -> ShiftResult<String> {
+        match self {
+            Value::List(_) => Err(ShiftError::Plan("a list cannot be put in text".into())),
+            other => Ok(other.to_string()),
+        }
+    }
+
+    fn as_num(&self) -> Option<f64> {
+        match self {
+            Value::Num(n) => Some(*n),
+            Value::Str(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> ShiftResult<&str> {
+        match self {
+            Value::Str(s) => Ok(s),
+            other => Err(ShiftError::Plan(format!(
+                "expected a string, got {}",
+                other.describe()
+            ))),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Value::Str(s) => format!("the string {s:?}"),
+            other => format!("the {} {other}", other.kind()),
+        }
+    }
+
+    /// `self == other`, reading text as the other side's type.
+    fn equals(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Str(_), Value::Num(n)) | (Value::Num(n), Value::Str(_)) => {
+                let text = if let Value::Num(_) = self {
+                    other
+                } else {
+                    self
+                };
+                text.as_num() == Some(*n)
+            }
+            (Value::Str(s), Value::Bool(b)) | (Value::Bool(b), Value::Str(s)) => {
+                *s == b.to_string()
+            }
+            (Value::List(a), Value::List(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.equals(b))
+            }
+            (a, b) => a == b,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Str(s) => f.write_str(s),
+            Value::Num(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", *n as i64),
+            Value::Num(n) => write!(f, "{n}"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::List(items) => {
+                let items: Vec<String> = items.iter().map(Value::to_string).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+        }
+    }
+}
+
+impl From<&serde_json::Value> for Value {
+    fn from(value: &serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::String(s) => Value::Str(s.clone()),
+            serde_json::Value::Bool(b) => Value::Bool(*b),
+            serde_json::Value::Number(n) => Value::Num(n.as_f64().unwrap_or_default()),
+            serde_json::Value::Array(items) => Value::List(items.iter().map(Value::from).collect()),
+            other => Value::Str(other.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    In,
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Op::Eq => "==",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::In => "in",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Literal(Value),
+    Ref(Vec<String>),
+    List(Vec<Node>),
+    Not(Box<Node>),
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Compare(Op, Box<Node>, Box<Node>),
+    Call(String, Vec<Node>),
+}
+
+/// A parsed expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    source: String,
+    node: Node,
+}
+
+/// The functions, with how many arguments each takes.
+const FUNCTIONS: &[(&str, usize)] = &[
+    ("lower", 1),
+    ("upper", 1),
+    ("trim", 1),
+    ("len", 1),
+    ("contains", 2),
+    ("starts_with", 2),
+    ("ends_with", 2),
+    ("matches", 2),
+    ("replace", 3),
+    ("defined", 1),
+    ("default", 2),
+];
+
+impl Expr {
+    pub fn parse(source: &str) -> ShiftResult<Expr> {
+        let tokens = tokenize(source).map_err(|msg| error(source, msg))?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let node = parser.or().map_err(|msg| error(source, msg))?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(error(source, format!("unexpected {}", token.describe())));
+        }
+        Ok(Expr {
+            source: source.to_string(),
+            node,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Evaluates the expression, looking references up with `lookup`.
+    pub fn eval(&self, lookup: &dyn Fn(&[String]) -> Option<Value>) -> ShiftResult<Value> {
+        eval(&self.node, lookup).map_err(|err| match err {
+            ShiftError::Plan(msg) => error(&self.source, msg),
+            other => other,
+        })
+    }
+
+    /// The expression's type, with references typed by `lookup`; an error
+    /// if it cannot work whatever the values are, or names something
+    /// `lookup` does not know.
+    pub fn check(&self, lookup: &dyn Fn(&[String]) -> Option<Type>) -> ShiftResult<Type> {
+        check(&self.node, lookup).map_err(|msg| error(&self.source, msg))
+    }
+}
+
+fn error(source: &str, msg: String) -> ShiftError {
+    ShiftError::Plan(format!("in `{source}`: {msg}"))
+}
+
+/// The error for a reference to nothing.
+pub fn undefined(path: &[String]) -> ShiftError {
+    ShiftError::Custom(format!("undefined variable `{}`", path.join(".")))
+}
+
+/// Where the `${` expression starting `text` ends: the index of its
+/// closing `}`, past any quoted text.
+pub fn closing_brace(text: &str) -> Option<usize> {
+    let mut quote = None;
+    let mut escaped = false;
+    for (pos, c) in text.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c == '}' => return Some(pos),
+            None => {}
+        }
+    }
+    None
+}
+
+/// The `${...}` expressions in `text`, skipping `$$`.
+pub fn templates(text: &str) -> ShiftResult<Vec<&str>> {
+    let mut found = Vec::new();
+    let mut rest = text;
+    while let Some(pos) = rest.find('$') {
+        rest = &rest[pos + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('{') {
+            let end = closing_brace(after)
+                .ok_or_else(|| ShiftError::Plan(format!("unterminated `${{` in \"{text}\"")))?;
+            found.push(after[..end].trim());
+            rest = &after[end + 1..];
+        }
+    }
+    Ok(found)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Str(String),
+    Num(f64),
+    Ident(String),
+    Sym(&'static str),
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Str(s) => format!("string {s:?}"),
+            Token::Num(n) => format!("number {}", Value::Num(*n)),
+            Token::Ident(name) => format!("`{name}`"),
+            Token::Sym(sym) => format!("`{sym}`"),
+        }
+    }
+}
+
+const SYMBOLS: &[&str] = &[
+    "==", "!=", "<=", ">=", "&&", "||", "<", ">", "!", "(", ")", "[", "]", ",", ".",
+];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = source;
+    loop {
+        rest = rest.trim_start();
+        let Some(c) = rest.chars().next() else {
+            return Ok(tokens);
+        };
+        if c == '\'' || c == '"' {
+            let mut text = String::new();
+            let mut chars = rest[1..].char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((pos, q)) if q == c => break pos + 2,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, 'n')) => text.push('\n'),
+                        Some((_, 't')) => text.push('\t'),
+                        Some((_, other)) => text.push(other),
+                        None => return Err("unterminated string".into()),
+                    },
+                    Some((_, other)) => text.push(other),
+                    None => return Err("unterminated string".into()),
+                }
+            };
+            tokens.push(Token::Str(text));
+            rest = &rest[end..];
+        } else if c.is_ascii_digit()
+            || (c == '-' && rest[1..].starts_with(|d: char| d.is_ascii_digit()))
+        {
+            let len = 1 + rest[1..]
+                .find(|d: char| !(d.is_ascii_digit() || d == '.'))
+                .unwrap_or(rest.len() - 1);
+            let number = rest[..len]
+                .parse()
+                .map_err(|_| format!("bad number `{}`", &rest[..len]))?;
+            tokens.push(Token::Num(number));
+            rest = &rest[len..];
+        } else if c.is_alphabetic() || c == '_' {
+            let len = rest
+                .find(|d: char| !(d.is_alphanumeric() || d == '_' || d == '-'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..len].to_string()));
+            rest = &rest[len..];
+        } else if let Some(sym) = SYMBOLS.iter().find(|sym| rest.starts_with(**sym)) {
+            tokens.push(Token::Sym(sym));
+            rest = &rest[sym.len()..];
+        } else {
+            return Err(format!("unexpected `{c}`"));
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    /// Takes the next token if it is the symbol or keyword `word`.
+    fn eat(&mut self, word: &str) -> bool {
+        let found = match self.peek() {
+            Some(Token::Sym(sym)) => *sym == word,
+            Some(Token::Ident(name)) => name == word,
+            _ => false,
+        };
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, sym: &str) -> Result<(), String> {
+        if self.eat(sym) {
+            return Ok(());
+        }
+        Err(match self.peek() {
+            Some(token) => format!("expected `{sym}`, found {}", token.describe()),
+            None => format!("expected `{sym}` at the end"),
+        })
+    }
+
+    /// Takes the `,` between items of a list ending with `close`.
+    fn separator(&mut self, close: &str) -> Result<(), String> {
+        if self.eat(",") {
+            return Ok(());
+        }
+        Err(match self.peek() {
+            Some(token) => format!("expected `,` or `{close}`, found {}", token.describe()),
+            None => format!("expected `{close}` at the end"),
+        })
+    }
+
+    fn or(&mut self) -> Result<Node, String> {
+        let mut node = self.and()?;
+        while self.eat("||") || self.eat("or") {
+            node = Node::Or(Box::new(node), Box::new(self.and()?));
+        }
+        Ok(node)
+    }
+
+    fn and(&mut self) -> Result<Node, String> {
+        let mut node = self.not()?;
+        while self.eat("&&") || self.eat("and") {
+            node = Node::And(Box::new(node), Box::new(self.not()?));
+        }
+        Ok(node)
+    }
+
+    fn not(&mut self) -> Result<Node, String> {
+        if self.eat("!") || self.eat("not") {
+            return Ok(Node::Not(Box::new(self.not()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Node, String> {
+        let left = self.primary()?;
+        let ops = [
+            ("==", Op::Eq),
+            ("!=", Op::Ne),
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+            ("in", Op::In),
+        ];
+        for (word, op) in ops {
+            if self.eat(word) {
+                let right = self.primary()?;
+                return Ok(Node::Compare(op, Box::new(left), Box::new(right)));
+            }
+        }
+        Ok(left)
+    }
+
+    fn primary(&mut self) -> Result<Node, String> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or("unexpected end of expression")?;
+        self.pos += 1;
+        match token {
+            Token::Str(s) => Ok(Node::Literal(Value::Str(s))),
+            Token::Num(n) => Ok(Node::Literal(Value::Num(n))),
+            Token::Ident(name) if name == "true" || name == "false" => {
+                Ok(Node::Literal(Value::Bool(name == "true")))
+            }
+            Token::Ident(name) if self.eat("(") => {
+                let mut args = Vec::new();
+                if !self.eat(")") {
+                    loop {
+                        args.push(self.or()?);
+                        if self.eat(")") {
+                            break;
+                        }
+                        self.separator(")")?;
+                    }
+                }
+                match FUNCTIONS.iter().find(|(known, _)| *known == name) {
+                    None => Err(format!("unknown function `{name}`")),
+                    Some((_, arity)) if *arity != args.len() => Err(format!(
+                        "`{name}` takes {arity} argument(s), got {}",
+                        args.len()
+                    )),
+                    Some(_)
+                        if matches!(name.as_str(), "defined" | "default")
+                            && !matches!(args[0], Node::Ref(_)) =>
+                    {
+                        Err(format!("`{name}` takes a variable, fact or output first"))
+                    }
+                    Some(_) => Ok(Node::Call(name, args)),
+                }
+            }
+            Token::Ident(name) => {
+                let mut path = vec![name];
+                loop {
+                    if self.eat(".") {
+                        match self.tokens.get(self.pos).cloned() {
+                            Some(Token::Ident(name)) => path.push(name),
+                            Some(Token::Num(n)) => path.push(Value::Num(n).to_string()),
+                            _ => return Err("expected a name after `.`".into()),
+                        }
+                        self.pos += 1;
+                    } else if self.eat("[") {
+                        match self.tokens.get(self.pos).cloned() {
+                            Some(Token::Str(name)) => path.push(name),
+                            _ => return Err("expected a quoted name in `[...]`".into()),
+                        }
+                        self.pos += 1;
+                        self.expect("]")?;
+                    } else {
+                        return Ok(Node::Ref(path));
+                    }
+                }
+            }
+            Token::Sym("(") => {
+                let node = self.or()?;
+                self.expect(")")?;
+                Ok(node)
+            }
+            Token::Sym("[") => {
+                let mut items = Vec::new();
+                if !self.eat("]") {
+                    loop {
+                        items.push(self.or()?);
+                        if self.eat("]") {
+                            break;
+                        }
+                        self.separator("]")?;
+                    }
+                }
+                Ok(Node::List(items))
+            }
+            other => Err(format!("unexpected {}", other.describe())),
+        }
+    }
+}
+
+fn eval(node: &Node, lookup: &dyn Fn(&[String]) -> Option<Value>) -> ShiftResult<Value> {
+    let eval_str = |node: &Node| -> ShiftResult<String> {
+        let value = eval(node, lookup)?;
+        match value {
+            Value::Str(s) => Ok(s),
+            Value::List(_) => value.as_str().map(String::from),
+            other => Ok(other.to_string()),
+        }
+    };
+    Ok(match node {
+        Node::Literal(value) => value.clone(),
+        Node::Ref(path) => lookup(path).ok_or_else(|| undefined(path))?,
+        Node::List(items) => Value::List(
+            items
+                .iter()
+                .map(|item| eval(item, lookup))
+                .collect::<ShiftResult<_>>()?,
+        ),
+        Node::Not(inner) => Value::Bool(!eval(inner, lookup)?.truth()?),
+        Node::And(a, b) => Value::Bool(eval(a, lookup)?.truth()? && eval(b, lookup)?.truth()?),
+        Node::Or(a, b) => Value::Bool(eval(a, lookup)?.truth()? || eval(b, lookup)?.truth()?),
+        Node::Compare(op, a, b) => {
+            let (a, b) = (eval(a, lookup)?, eval(b, lookup)?);
+            Value::Bool(compare(*op, &a, &b)?)
+        }
+        Node::Call(name, args) => match (name.as_str(), args.as_slice()) {
+            ("defined", [Node::Ref(path)]) => Value::Bool(lookup(path).is_some()),
+            ("default", [Node::Ref(path), fallback]) => match lookup(path) {
+                Some(value) => value,
+                None => eval(fallback, lookup)?,
+            },
+            ("len", [arg]) => match eval(arg, lookup)? {
+                Value::List(items) => Value::Num(items.len() as f64),
+                other => Value::Num(other.to_string().chars().count() as f64),
+            },
+            ("lower", [s]) => Value::Str(eval_str(s)?.to_lowercase()),
+            ("upper", [s]) => Value::Str(eval_str(s)?.to_uppercase()),
+            ("trim", [s]) => Value::Str(eval_str(s)?.trim().to_string()),
+            ("contains", [s, part]) => Value::Bool(eval_str(s)?.contains(&eval_str(part)?)),
+            ("starts_with", [s, part]) => Value::Bool(eval_str(s)?.starts_with(&eval_str(part)?)),
+            ("ends_with", [s, part]) => Value::Bool(eval_str(s)?.ends_with(&eval_str(part)?)),
+            ("matches", [s, pattern]) => Value::Bool(crate::plan_file::wildcard_match(
+                &eval_str(pattern)?,
+                &eval_str(s)?,
+            )),
+            ("replace", [s, from, to]) => {
+                Value::Str(eval_str(s)?.replace(&eval_str(from)?, &eval_str(to)?))
+            }
+            _ => unreachable!("calls are checked when parsed"),
+        },
+    })
+}
+
+fn compare(op: Op, a: &Value, b: &Value) -> ShiftResult<bool> {
+    let order = || -> ShiftResult<std::cmp::Ordering> {
+        let numbers = match (a, b) {
+            (Value::Str(a), Value::Str(b)) => return Ok(a.cmp(b)),
+            _ => a.as_num().zip(b.as_num()),
+        };
+        numbers.and_then(|(a, b)| a.partial_cmp(&b)).ok_or_else(|| {
+            ShiftError::Plan(format!(
+                "cannot compare {} with {}",
+                a.describe(),
+                b.describe()
+            ))
+        })
+    };
+    Ok(match op {
+        Op::Eq => a.equals(b),
+        Op::Ne => !a.equals(b),
+        Op::Lt => order()?.is_lt(),
+        Op::Le => order()?.is_le(),
+        Op::Gt => order()?.is_gt(),
+        Op::Ge => order()?.is_ge(),
+        Op::In => match b {
+            Value::List(items) => items.iter().any(|item| a.equals(item)),
+            Value::Str(s) => s.contains(&a.text()?),
+            other => {
+                return Err(ShiftError::Plan(format!(
+                    "`in` needs a list or a string, got {}",
+                    other.describe()
+                )))
+            }
+        },
+    })
+}
+
+/// Whether a value of type `found` can be used where `wanted` is.
+fn fits(found: Type, wanted: Type) -> bool {
+    found == wanted || found == Type::Any || wanted == Type::Any
+}
+
+fn check(node: &Node, lookup: &dyn Fn(&[String]) -> Option<Type>) -> Result<Type, String> {
+    let want = |node: &Node, wanted: Type, what: &str| -> Result<(), String> {
+        let found = check(node, lookup)?;
+        if fits(found, wanted) {
+            return Ok(());
+        }
+        Err(format!("{what} needs a {wanted}, got a {found}"))
+    };
+    Ok(match node {
+        Node::Literal(value) => value.kind(),
+        Node::Ref(path) => {
+            lookup(path).ok_or_else(|| format!("undefined variable `{}`", path.join(".")))?
+        }
+        Node::List(items) => {
+            for item in items {
+                check(item, lookup)?;
+            }
+            Type::List
+        }
+        Node::Not(inner) => {
+            want(inner, Type::Bool, "`!`")?;
+            Type::Bool
+        }
+        Node::And(a, b) | Node::Or(a, b) => {
+            let op = if matches!(node, Node::And(..)) {
+                "`&&`"
+            } else {
+                "`||`"
+            };
+            want(a, Type::Bool, op)?;
+            want(b, Type::Bool, op)?;
+            Type::Bool
+        }
+        Node::Compare(op, a, b) => {
+            let (a, b) = (check(a, lookup)?, check(b, lookup)?);
+            let ok = match op {
+                Op::In => match b {
+                    Type::List | Type::Any => true,
+                    Type::Str => fits(a, Type::Str),
+                    _ => false,
+                },
+                // Text compares with a number or boolean by its value.
+                Op::Eq | Op::Ne => fits(a, b) || a == Type::Str || b == Type::Str,
+                _ => [a, b].iter().all(|t| !matches!(t, Type::Bool | Type::List)),
+            };
+            if !ok {
+                return Err(format!("cannot compare a {a} with a {b} using `{op}`"));
+            }
+            Type::Bool
+        }
+        Node::Call(name, args) => match (name.as_str(), args.as_slice()) {
+            ("defined", [Node::Ref(_)]) => Type::Bool,
+            ("default", [Node::Ref(path), fallback]) => {
+                let fallback = check(fallback, lookup)?;
+                match lookup(path) {
+                    Some(found) if found == fallback => found,
+                    _ => Type::Any,
+                }
+            }
+            ("len", [arg]) => {
+                check(arg, lookup)?;
+                Type::Num
+            }
+            (name, args) => {
+                for arg in args {
+                    if check(arg, lookup)? == Type::List {
+                        return Err(format!("`{name}` takes strings, got a list"));
+                    }
+                }
+                match name {
+                    "lower" | "upper" | "trim" | "replace" => Type::Str,
+                    _ => Type::Bool,
+                }
+            }
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{closing_brace, templates, Expr, Type, Value};
+    use crate::context::ExecutionContext;
+
+    fn lookup(path: &[String]) -> Option<Value> {
+        let path: Vec<&str> = path.iter().map(String::as_str).collect();
+        match path.as_slice() {
+            ["port"] | ["vars", "port"] => Some(Value::Str("8080".into())),
+            ["name"] | ["vars", "name"] => Some(Value::Str("Web".into())),
+            ["facts", "os"] => Some(Value::Str("linux".into())),
+            _ => None,
+        }
+    }
+
+    fn eval(source: &str) -> Value {
+        Expr::parse(source)
+            .and_then(|expr| expr.eval(&lookup))
+            .unwrap_or_else(|err| panic!("{source}: {err}"))
+    }
+
+    fn fails(source: &str) -> String {
+        match Expr::parse(source).and_then(|expr| expr.eval(&lookup)) {
+            Ok(value) => panic!("{source} gave {value}"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(eval("true || false && false"), Value::Bool(true));
+        assert_eq!(eval("(true || false) && false"), Value::Bool(false));
+        assert_eq!(eval("false and true or true"), Value::Bool(true));
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and() {
+        assert_eq!(eval("!false && false"), Value::Bool(false));
+        assert_eq!(eval("!(false && false)"), Value::Bool(true));
+        assert_eq!(eval("not true or true"), Value::Bool(true));
+    }
+
+    #[test]
+    fn comparisons_bind_tighter_than_logic() {
+        assert_eq!(
+            eval("facts.os == 'linux' && port > 1024 || false"),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            eval("'a' in ['a', 'b'] && !('c' in ['a'])"),
+            Value::Bool(true)
+        );
+    }
+
+    #[test]
+    fn text_compares_as_the_other_side() {
+        assert_eq!(eval("port == 8080"), Value::Bool(true));
+        assert_eq!(eval("vars.port >= 9000"), Value::Bool(false));
+        assert_eq!(eval("'true' == true"), Value::Bool(true));
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(eval("lower(name)"), Value::Str("web".into()));
+        assert_eq!(eval("len([1, 2, 3])"), Value::Num(3.0));
+        assert_eq!(eval("matches(name, 'W*')"), Value::Bool(true));
+        assert_eq!(eval("default(vars.missing, 80)"), Value::Num(80.0));
+        assert_eq!(eval("defined(vars.missing)"), Value::Bool(false));
+    }
+
+    #[test]
+    fn parse_errors() {
+        for source in ["", "(true", "true &&", "'open", "1 2", "[1, 2", "a ==="] {
+            assert!(Expr::parse(source).is_err(), "{source} parsed");
+        }
+    }
+
+    #[test]
+    fn eval_errors() {
+        assert!(fails("missing").contains("missing"));
+        assert!(fails("nope(1)").contains("nope"));
+        assert!(fails("lower(1, 2)").contains("lower"));
+        assert!(fails("!name").contains("boolean"));
+    }
+
+    #[test]
+    fn check_types_ahead_of_time() {
+        let known = |path: &[String]| (path[0] == "facts").then_some(Type::Str);
+        let check = |source: &str| Expr::parse(source).unwrap().check(&known);
+        assert_eq!(check("len(facts.os) > 3").unwrap(), Type::Bool);
+        assert!(check("facts.os && true").is_err());
+        assert!(check("vars.unknown").is_err());
+    }
+
+    #[test]
+    fn braces_inside_quotes_do_not_close() {
+        assert_eq!(closing_brace("'}' }"), Some(4));
+        assert_eq!(closing_brace(r#""\"}" }"#), Some(6));
+        assert_eq!(closing_brace("'}'"), None);
+    }
+
+    #[test]
+    fn templates_skip_escaped_dollars() {
+        assert_eq!(templates("$${a} ${ b } $c ${d}").unwrap(), vec!["b", "d"]);
+        assert!(templates("${a").is_err());
+    }
+
+    #[test]
+    fn interpolation() {
+        let mut ctx = ExecutionContext::new();
+        ctx.set_var("name", "web");
+        let interpolate = |text: &str| ctx.interpolate(text);
+        assert_eq!(interpolate("${name}:${upper(name)}").unwrap(), "web:WEB");
+        assert_eq!(
+            interpolate("$${name} costs $5").unwrap(),
+            "${name} costs $5"
+        );
+        assert_eq!(interpolate("${ '}' }").unwrap(), "}");
+        assert_eq!(interpolate("trailing $").unwrap(), "trailing $");
+        assert!(interpolate("${name").is_err());
+        assert!(interpolate("${missing}").is_err());
+        assert!(interpolate("${['a']}").is_err());
+    }
+}
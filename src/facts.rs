@@ -0,0 +1,134 @@
+//! Read-only information about the machine a plan runs on.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+
+/// Named facts such as `os`, `arch` and `hostname`.
+///
+/// On Linux, GPUs found on the PCI bus add `gpu`, the vendor of the one
+/// to prefer for compute (`nvidia`, then `amd`, then `intel`), and
+/// `gpu.<vendor>`, how many of each there are. A loaded NVIDIA driver
+/// adds `gpu.nvidia.driver` with its version, a loaded `amdgpu` module
+/// `gpu.amd.driver`, and installed toolkits `cuda` and `rocm` with theirs.
+#[derive(Debug, Clone, Default)]
+pub struct Facts {
+    values: BTreeMap<String, String>,
+}
+
+impl Facts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Collects the built-in facts about the local machine.
+    pub fn gather() -> Self {
+        let mut facts = Facts::new();
+        facts.set("os", env::consts::OS);
+        facts.set("family", env::consts::FAMILY);
+        facts.set("arch", env::consts::ARCH);
+        if let Some(hostname) = hostname() {
+            facts.set("hostname", hostname);
+        }
+        if let Ok(user) = env::var("USER").or_else(|_| env::var("USERNAME")) {
+            facts.set("user", user);
+        }
+        if let Some(home) = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")) {
+            facts.set("home", home.to_string_lossy());
+        }
+        gather_gpus(&mut facts);
+        facts
+    }
+
+    pub fn set(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.values.insert(name.into(), value.into());
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.values.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+fn hostname() -> Option<String> {
+    let from_file = fs::read_to_string("/etc/hostname")
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
+    from_file
+        .or_else(|| env::var("HOSTNAME").ok())
+        .or_else(|| env::var("COMPUTERNAME").ok())
+}
+
+/// PCI vendor IDs of the GPU makers with a compute toolkit, in order of
+/// preference.
+const GPU_VENDORS: [(&str, &str); 3] =
+    [("0x10de", "nvidia"), ("0x1002", "amd"), ("0x8086", "intel")];
+
+fn gather_gpus(facts: &mut Facts) {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for device in fs::read_dir("/sys/bus/pci/devices")
+        .into_iter()
+        .flatten()
+        .flatten()
+    {
+        let read = |name: &str| fs::read_to_string(device.path().join(name)).unwrap_or_default();
+        // Class 0x03 is display controllers: VGA, 3D and others.
+        if !read("class").trim().starts_with("0x03") {
+            continue;
+        }
+        let vendor = read("vendor");
+        if let Some((_, name)) = GPU_VENDORS.iter().find(|(id, _)| *id == vendor.trim()) {
+            *counts.entry(name).or_default() += 1;
+        }
+    }
+    if let Some((_, name)) = GPU_VENDORS
+        .iter()
+        .find(|(_, name)| counts.contains_key(name))
+    {
+        facts.set("gpu", *name);
+    }
+    for (name, count) in &counts {
+        facts.set(format!("gpu.{name}"), count.to_string());
+    }
+
+    if let Ok(version) = fs::read_to_string("/sys/module/nvidia/version") {
+        facts.set("gpu.nvidia.driver", version.trim());
+    }
+    if fs::metadata("/sys/module/amdgpu").is_ok() {
+        let version = fs::read_to_string("/sys/module/amdgpu/version").unwrap_or_default();
+        let version = version.trim();
+        facts.set(
+            "gpu.amd.driver",
+            if version.is_empty() {
+                "amdgpu"
+            } else {
+                version
+            },
+        );
+    }
+    if let Some(version) = cuda_version() {
+        facts.set("cuda", version);
+    }
+    if let Ok(version) = fs::read_to_string("/opt/rocm/.info/version") {
+        // `6.1.0-82`: the build number after the dash is not wanted.
+        let version = version.trim();
+        facts.set("rocm", version.split('-').next().unwrap_or(version));
+    }
+}
+
+/// The version of the CUDA toolkit in `/usr/local/cuda`: from
+/// `version.json` since CUDA 11.1, `version.txt` before.
+fn cuda_version() -> Option<String> {
+    if let Ok(text) = fs::read_to_string("/usr/local/cuda/version.json") {
+        let json: serde_json::Value = serde_json::from_str(&text).ok()?;
+        return json["cuda"]["version"].as_str().map(str::to_string);
+    }
+    let text = fs::read_to_string("/usr/local/cuda/version.txt").ok()?;
+    text.trim()
+        .strip_prefix("CUDA Version ")
+        .map(str::to_string)
+}
@@ -0,0 +1,212 @@
+//! Applying a plan once, at a machine's first boot.
+//!
+//! [`FirstBoot::install`] stages the running `skies` binary and a plan's
+//! directory under [`HOME`] and enables a one-shot systemd unit that
+//! applies the plan on the next boot. After a successful apply the unit
+//! writes a `done` marker and disables itself; a failed apply is retried
+//! on the following boot. Everything goes under a root directory, so the
+//! unit can be baked into a mounted VM image without booting it.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::error::ShiftResult;
+use crate::run_target::copy_tree;
+
+/// Name of the systemd unit.
+pub const UNIT: &str = "skies-first-boot.service";
+/// Where the staged binary, plan, result and marker live.
+pub const HOME: &str = "/var/lib/skies/first-boot";
+/// Where the apply's output is appended by default.
+pub const DEFAULT_LOG: &str = "/var/log/skies-first-boot.log";
+
+const UNIT_DIR: &str = "/etc/systemd/system";
+const WANTED_BY: &str = "multi-user.target";
+
+/// Where a first-boot apply stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FirstBootStatus {
+    NotInstalled,
+    /// Installed and not yet applied successfully.
+    Pending,
+    Done,
+}
+
+/// A first-boot installation under a root directory.
+pub struct FirstBoot {
+    root: PathBuf,
+    log: PathBuf,
+}
+
+impl FirstBoot {
+    /// Installs into `root`: `/` for this machine, or a mounted image.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        FirstBoot {
+            root: root.into(),
+            log: PathBuf::from(DEFAULT_LOG),
+        }
+    }
+
+    /// Appends the apply's output to `log` (a path on the booted machine).
+    pub fn log(mut self, log: impl Into<PathBuf>) -> Self {
+        self.log = log.into();
+        self
+    }
+
+    /// Path of `plan` once staged, as the booted machine sees it.
+    pub fn plan_path(plan: &Path) -> PathBuf {
+        Path::new(HOME)
+            .join("plan")
+            .join(plan.file_name().unwrap_or_default())
+    }
+
+    fn binary() -> PathBuf {
+        Path::new(HOME).join("bin/skies")
+    }
+
+    fn marker() -> PathBuf {
+        Path::new(HOME).join("done")
+    }
+
+    /// Result file written in the provisioner format.
+    pub fn result_path() -> PathBuf {
+        Path::new(HOME).join("result.json")
+    }
+
+    /// `path` (absolute on the booted machine) under our root.
+    fn under_root(&self, path: &Path) -> PathBuf {
+        self.root.join(path.strip_prefix("/").unwrap_or(path))
+    }
+
+    fn unit_path(&self) -> PathBuf {
+        self.under_root(&Path::new(UNIT_DIR).join(UNIT))
+    }
+
+    fn wants_path(&self) -> PathBuf {
+        self.under_root(
+            &Path::new(UNIT_DIR)
+                .join(format!("{WANTED_BY}.wants"))
+                .join(UNIT),
+        )
+    }
+
+    /// The unit file that runs `skies apply` with `args`.
+    pub fn unit(&self, args: &[String]) -> String {
+        let home = Path::new(HOME);
+        let exec: Vec<String> = [Self::binary().display().to_string(), "apply".into()]
+            .iter()
+            .chain(args)
+            .map(|arg| quote(arg))
+            .collect();
+        let log = self.log.display();
+        format!(
+            "# Installed by `skies first-boot install`; remove with `skies first-boot uninstall`.
+[Unit]
+Description=Apply the skies first-boot plan
+Wants=network-online.target
+After=network-online.target
+ConditionPathExists=!{marker}
+
+[Service]
+Type=oneshot
+RemainAfterExit=yes
+TimeoutStartSec=infinity
+WorkingDirectory={plan_dir}
+Environment=SKIES_NON_INTERACTIVE=1
+Environment=SKIES_RESULT_FILE={result}
+ExecStart={exec}
+ExecStartPost=/bin/touch {marker}
+ExecStartPost=/bin/systemctl disable {UNIT}
+StandardOutput=append:{log}
+StandardError=append:{log}
+
+[Install]
+WantedBy={WANTED_BY}
+",
+            marker = Self::marker().display(),
+            plan_dir = home.join("plan").display(),
+            result = Self::result_path().display(),
+            exec = exec.join(" "),
+        )
+    }
+
+    /// Stages the running binary and the directory holding `plan`, writes
+    /// the unit and enables it. `args` are passed to `skies apply` and
+    /// should name the plan by its [`plan_path`](Self::plan_path).
+    ///
+    /// Reinstalling replaces the staged plan and clears the `done` marker.
+    pub fn install(&self, plan: &Path, args: &[String]) -> ShiftResult<()> {
+        let plan_dir = match plan.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let staged = self.under_root(&Path::new(HOME).join("plan"));
+        remove_dir(&staged)?;
+        copy_tree(&plan_dir, &staged)?;
+        let binary = self.under_root(&Self::binary());
+        fs::create_dir_all(binary.parent().unwrap_or(&self.root))?;
+        fs::copy(std::env::current_exe()?, &binary)?;
+        remove_file(&self.under_root(&Self::marker()))?;
+        if let Some(dir) = self.under_root(&self.log).parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let unit = self.unit_path();
+        fs::create_dir_all(unit.parent().unwrap_or(&self.root))?;
+        fs::write(&unit, self.unit(args))?;
+        // Enable by hand, as `systemctl enable` would, so this also works
+        // on an image that is not running systemd.
+        let wants = self.wants_path();
+        fs::create_dir_all(wants.parent().unwrap_or(&self.root))?;
+        remove_file(&wants)?;
+        std::os::unix::fs::symlink(Path::new(UNIT_DIR).join(UNIT), &wants)?;
+        Ok(())
+    }
+
+    /// Removes the unit and everything staged, including the result, but
+    /// not the log.
+    pub fn uninstall(&self) -> ShiftResult<()> {
+        remove_file(&self.wants_path())?;
+        remove_file(&self.unit_path())?;
+        remove_dir(&self.under_root(Path::new(HOME)))
+    }
+
+    pub fn status(&self) -> FirstBootStatus {
+        if self.under_root(&Self::marker()).exists() {
+            FirstBootStatus::Done
+        } else if self.unit_path().exists() {
+            FirstBootStatus::Pending
+        } else {
+            FirstBootStatus::NotInstalled
+        }
+    }
+}
+
+/// Quotes `arg` for a unit's `ExecStart=` line if it needs it.
+fn quote(arg: &str) -> String {
+    let escaped = arg
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%")
+        .replace('$', "$$");
+    if !arg.is_empty() && !arg.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'') {
+        escaped
+    } else {
+        format!("\"{escaped}\"")
+    }
+}
+
+fn remove_file(path: &Path) -> ShiftResult<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+fn remove_dir(path: &Path) -> ShiftResult<()> {
+    match fs::remove_dir_all(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
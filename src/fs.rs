@@ -0,0 +1,374 @@
+//! Filesystem access for shifts.
+//!
+//! File shifts go through the context's [`Fs`] rather than `std::fs`, so
+//! they can run against a [`MemoryFs`] in unit tests: quickly, without
+//! touching the host, and the same way every time:
+//!
+//! ```
+//! use std::path::Path;
+//! use std::sync::Arc;
+//!
+//! use skies::fs::{Fs, MemoryFs};
+//! use skies::shifts::CreateFile;
+//! use skies::{ExecutionContext, Shift};
+//!
+//! let fs = Arc::new(MemoryFs::new());
+//! fs.create_dir_all(Path::new("/project")).unwrap();
+//! let ctx = ExecutionContext::new()
+//!     .with_root("/project")
+//!     .with_fs(fs.clone());
+//! CreateFile::new("notes.txt", "hi").apply(&ctx).unwrap();
+//! assert_eq!(fs.read(Path::new("/project/notes.txt")).unwrap(), b"hi");
+//! ```
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::error::{ShiftError, ShiftResult};
+use crate::paths;
+use crate::permissions;
+
+/// The filesystem operations shifts need.
+pub trait Fs: Send + Sync {
+    fn exists(&self, path: &Path) -> bool;
+
+    fn is_dir(&self, path: &Path) -> bool;
+
+    fn read(&self, path: &Path) -> ShiftResult<Vec<u8>>;
+
+    /// Writes `contents` to `path`, creating the file with `mode` from the
+    /// start so it is never briefly readable with looser permissions.
+    fn write(&self, path: &Path, contents: &[u8], mode: Option<u32>) -> ShiftResult<()>;
+
+    fn create_dir_all(&self, path: &Path) -> ShiftResult<()>;
+
+    fn remove_file(&self, path: &Path) -> ShiftResult<()>;
+
+    fn remove_dir_all(&self, path: &Path) -> ShiftResult<()>;
+
+    /// Moves the file at `from` to `to`, replacing any file there.
+    fn rename(&self, from: &Path, to: &Path) -> ShiftResult<()>;
+
+    /// Where the link at `path` points, or `None` if `path` is not a link.
+    fn read_link(&self, path: &Path) -> ShiftResult<Option<PathBuf>>;
+
+    /// Creates a symbolic link at `path` pointing to `target`.
+    fn symlink(&self, target: &Path, path: &Path) -> ShiftResult<()>;
+
+    /// Permission bits of `path`, or `None` on platforms without them.
+    fn mode(&self, path: &Path) -> ShiftResult<Option<u32>>;
+
+    /// Sets the permission bits of `path`, where the platform has them.
+    fn set_mode(&self, path: &Path, mode: u32) -> ShiftResult<()>;
+}
+
+/// The machine's own filesystem.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RealFs;
+
+impl Fs for RealFs {
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir()
+    }
+
+    fn read(&self, path: &Path) -> ShiftResult<Vec<u8>> {
+        Ok(std::fs::read(path)?)
+    }
+
+    fn write(&self, path: &Path, contents: &[u8], mode: Option<u32>) -> ShiftResult<()> {
+        permissions::write_file(path, contents, mode)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> ShiftResult<()> {
+        Ok(std::fs::create_dir_all(path)?)
+    }
+
+    fn remove_file(&self, path: &Path) -> ShiftResult<()> {
+        Ok(std::fs::remove_file(path)?)
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> ShiftResult<()> {
+        Ok(std::fs::remove_dir_all(path)?)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> ShiftResult<()> {
+        Ok(std::fs::rename(from, to)?)
+    }
+
+    fn read_link(&self, path: &Path) -> ShiftResult<Option<PathBuf>> {
+        match std::fs::symlink_metadata(path) {
+            Ok(meta) if meta.file_type().is_symlink() => Ok(Some(std::fs::read_link(path)?)),
+            Ok(_) => Ok(None),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn symlink(&self, target: &Path, path: &Path) -> ShiftResult<()> {
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(target, path)?;
+        #[cfg(windows)]
+        std::os::windows::fs::symlink_file(target, path)?;
+        Ok(())
+    }
+
+    fn mode(&self, path: &Path) -> ShiftResult<Option<u32>> {
+        permissions::mode_of(path)
+    }
+
+    fn set_mode(&self, path: &Path, mode: u32) -> ShiftResult<()> {
+        permissions::set_mode(path, mode)
+    }
+}
+
+/// The text of the file at `path`, or `None` if there is none.
+pub fn read_text(fs: &dyn Fs, path: &Path) -> ShiftResult<Option<String>> {
+    if !fs.exists(path) {
+        return Ok(None);
+    }
+    String::from_utf8(fs.read(path)?)
+        .map(Some)
+        .map_err(|_| ShiftError::Custom(format!("{} is not text", path.display())))
+}
+
+/// Mode of files and directories created without one.
+const DEFAULT_FILE_MODE: u32 = 0o644;
+const DEFAULT_DIR_MODE: u32 = 0o755;
+
+#[derive(Debug, Clone)]
+enum Node {
+    File { contents: Vec<u8>, mode: u32 },
+    Dir { mode: u32 },
+    Link { target: PathBuf },
+}
+
+/// A filesystem held in memory, starting with just an empty `/`.
+///
+/// Paths are normalized but not resolved through links, which are kept
+/// but never followed. Permission bits are stored, never enforced.
+#[derive(Debug)]
+pub struct MemoryFs {
+    nodes: Mutex<BTreeMap<PathBuf, Node>>,
+}
+
+impl MemoryFs {
+    pub fn new() -> Self {
+        let mut nodes = BTreeMap::new();
+        nodes.insert(
+            PathBuf::from("/"),
+            Node::Dir {
+                mode: DEFAULT_DIR_MODE,
+            },
+        );
+        MemoryFs {
+            nodes: Mutex::new(nodes),
+        }
+    }
+
+    /// Every file and its contents, for asserting on what a shift left.
+    pub fn files(&self) -> BTreeMap<PathBuf, Vec<u8>> {
+        self.nodes()
+            .iter()
+            .filter_map(|(path, node)| match node {
+                Node::File { contents, .. } => Some((path.clone(), contents.clone())),
+                Node::Dir { .. } | Node::Link { .. } => None,
+            })
+            .collect()
+    }
+
+    fn nodes(&self) -> std::sync::MutexGuard<'_, BTreeMap<PathBuf, Node>> {
+        self.nodes.lock().unwrap_or_else(|p| p.into_inner())
+    }
+
+    fn get(&self, path: &Path) -> Option<Node> {
+        self.nodes().get(&paths::normalize(path)).cloned()
+    }
+}
+
+impl Default for MemoryFs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{} does not exist", path.display()),
+    )
+}
+
+fn wrong_kind(path: &Path, what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{} is {what}", path.display()),
+    )
+}
+
+impl Fs for MemoryFs {
+    fn exists(&self, path: &Path) -> bool {
+        self.get(path).is_some()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        matches!(self.get(path), Some(Node::Dir { .. }))
+    }
+
+    fn read(&self, path: &Path) -> ShiftResult<Vec<u8>> {
+        match self.get(path) {
+            Some(Node::File { contents, .. }) => Ok(contents),
+            Some(Node::Dir { .. }) => Err(wrong_kind(path, "a directory").into()),
+            Some(Node::Link { .. }) => Err(wrong_kind(path, "a link").into()),
+            None => Err(not_found(path).into()),
+        }
+    }
+
+    fn write(&self, path: &Path, contents: &[u8], mode: Option<u32>) -> ShiftResult<()> {
+        let path = paths::normalize(path);
+        let mut nodes = self.nodes();
+        match path.parent().map(|parent| nodes.get(parent)) {
+            Some(Some(Node::Dir { .. })) => {}
+            _ => return Err(not_found(path.parent().unwrap_or(&path)).into()),
+        }
+        let mode = match nodes.get(&path) {
+            Some(Node::Dir { .. }) => return Err(wrong_kind(&path, "a directory").into()),
+            Some(Node::Link { .. }) => return Err(wrong_kind(&path, "a link").into()),
+            Some(Node::File { mode: old, .. }) => mode.unwrap_or(*old),
+            None => mode.unwrap_or(DEFAULT_FILE_MODE),
+        };
+        nodes.insert(
+            path,
+            Node::File {
+                contents: contents.to_vec(),
+                mode,
+            },
+        );
+        Ok(())
+    }
+
+    fn create_dir_all(&self, path: &Path) -> ShiftResult<()> {
+        let path = paths::normalize(path);
+        let mut nodes = self.nodes();
+        for dir in path.ancestors().collect::<Vec<_>>().into_iter().rev() {
+            match nodes.get(dir) {
+                Some(Node::Dir { .. }) => {}
+                Some(Node::File { .. }) => return Err(wrong_kind(dir, "a file").into()),
+                Some(Node::Link { .. }) => return Err(wrong_kind(dir, "a link").into()),
+                None => {
+                    nodes.insert(
+                        dir.to_path_buf(),
+                        Node::Dir {
+                            mode: DEFAULT_DIR_MODE,
+                        },
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> ShiftResult<()> {
+        let path = paths::normalize(path);
+        let mut nodes = self.nodes();
+        match nodes.get(&path) {
+            Some(Node::File { .. } | Node::Link { .. }) => {
+                nodes.remove(&path);
+                Ok(())
+            }
+            Some(Node::Dir { .. }) => Err(wrong_kind(&path, "a directory").into()),
+            None => Err(not_found(&path).into()),
+        }
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> ShiftResult<()> {
+        let path = paths::normalize(path);
+        let mut nodes = self.nodes();
+        match nodes.get(&path) {
+            Some(Node::Dir { .. }) => {
+                nodes.retain(|other, _| !other.starts_with(&path));
+                Ok(())
+            }
+            Some(Node::File { .. }) => Err(wrong_kind(&path, "a file").into()),
+            Some(Node::Link { .. }) => Err(wrong_kind(&path, "a link").into()),
+            None => Err(not_found(&path).into()),
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> ShiftResult<()> {
+        let (from, to) = (paths::normalize(from), paths::normalize(to));
+        let mut nodes = self.nodes();
+        match to.parent().map(|parent| nodes.get(parent)) {
+            Some(Some(Node::Dir { .. })) => {}
+            _ => return Err(not_found(to.parent().unwrap_or(&to)).into()),
+        }
+        if let Some(Node::Dir { .. }) = nodes.get(&to) {
+            return Err(wrong_kind(&to, "a directory").into());
+        }
+        match nodes.remove(&from) {
+            Some(Node::Dir { mode }) => {
+                nodes.insert(from.clone(), Node::Dir { mode });
+                Err(wrong_kind(&from, "a directory").into())
+            }
+            Some(node) => {
+                nodes.insert(to, node);
+                Ok(())
+            }
+            None => Err(not_found(&from).into()),
+        }
+    }
+
+    fn read_link(&self, path: &Path) -> ShiftResult<Option<PathBuf>> {
+        match self.get(path) {
+            Some(Node::Link { target }) => Ok(Some(target)),
+            _ => Ok(None),
+        }
+    }
+
+    fn symlink(&self, target: &Path, path: &Path) -> ShiftResult<()> {
+        let path = paths::normalize(path);
+        let mut nodes = self.nodes();
+        match path.parent().map(|parent| nodes.get(parent)) {
+            Some(Some(Node::Dir { .. })) => {}
+            _ => return Err(not_found(path.parent().unwrap_or(&path)).into()),
+        }
+        if nodes.contains_key(&path) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", path.display()),
+            )
+            .into());
+        }
+        nodes.insert(
+            path,
+            Node::Link {
+                target: target.to_path_buf(),
+            },
+        );
+        Ok(())
+    }
+
+    fn mode(&self, path: &Path) -> ShiftResult<Option<u32>> {
+        match self.get(path) {
+            Some(Node::File { mode, .. } | Node::Dir { mode }) => Ok(Some(mode)),
+            Some(Node::Link { .. }) => Ok(Some(0o777)),
+            None => Err(not_found(path).into()),
+        }
+    }
+
+    fn set_mode(&self, path: &Path, mode: u32) -> ShiftResult<()> {
+        match self.nodes().get_mut(&paths::normalize(path)) {
+            Some(Node::File { mode: old, .. } | Node::Dir { mode: old }) => {
+                *old = mode;
+                Ok(())
+            }
+            Some(Node::Link { .. }) => Err(wrong_kind(path, "a link").into()),
+            None => Err(not_found(path).into()),
+        }
+    }
+}
@@ -0,0 +1,18 @@
+//! Stable content hashing.
+//!
+//! Hashes end up in plan files and the journal, so they must not change
+//! between releases or platforms; `std::hash` gives no such guarantee.
+
+use sha2::{Digest, Sha256};
+
+/// Hex-encoded SHA-256 of `bytes`.
+pub fn sha256_hex(bytes: impl AsRef<[u8]>) -> String {
+    format!("{:x}", Sha256::digest(bytes.as_ref()))
+}
+
+/// First `len` hex characters of the SHA-256 of `bytes`.
+pub fn short_hash(bytes: impl AsRef<[u8]>, len: usize) -> String {
+    let mut hex = sha256_hex(bytes);
+    hex.truncate(len);
+    hex
+}
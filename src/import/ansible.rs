@@ -0,0 +1,1007 @@
+//! Ansible playbooks, or task files, converted to plans.
+//!
+//! Tasks using `file`, `copy`, `template`, `command`, `shell`, `git`,
+//! `lineinfile` or a package module (`package`, `apt`, `dnf`, `yum`,
+//! `apk`, `pacman`, `zypper`) become shifts; other modules, handlers and
+//! roles become TODOs. A play's `vars` become the plan's `[vars]`, and
+//! Jinja's `{{ ... }}` in arguments, templates and `when` become `${...}`
+//! and skies expressions where skies has the same thing. A loop over a
+//! list written in the task becomes a shift per item, and
+//! `import_tasks` of a file is followed.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+use serde_yaml::{Mapping, Value as Yaml};
+
+use super::{literal, octal_mode, split_plan_words, Imported, Step};
+use crate::error::{ShiftError, ShiftResult};
+use crate::expr::Expr;
+
+/// Task keys that are not the module.
+const KEYWORDS: &[&str] = &[
+    "name",
+    "when",
+    "become",
+    "become_user",
+    "become_method",
+    "loop",
+    "with_items",
+    "with_list",
+    "loop_control",
+    "tags",
+    "notify",
+    "register",
+    "ignore_errors",
+    "changed_when",
+    "failed_when",
+    "args",
+    "environment",
+    "vars",
+    "no_log",
+    "delegate_to",
+    "run_once",
+    "check_mode",
+    "diff",
+    "until",
+    "retries",
+    "delay",
+    "listen",
+    "timeout",
+    "throttle",
+    "any_errors_fatal",
+    "rescue",
+    "always",
+];
+
+/// Keywords skies has nothing like, and what to say about them.
+const IGNORED: &[(&str, &str)] = &[
+    ("register", "the result is not registered"),
+    ("changed_when", "`changed_when` is not converted"),
+    ("failed_when", "`failed_when` is not converted"),
+    (
+        "ignore_errors",
+        "errors are not ignored; skies stops the plan",
+    ),
+    ("until", "the task is not retried"),
+    (
+        "delegate_to",
+        "the task runs here, not on the delegated host",
+    ),
+    ("vars", "task `vars` are not converted"),
+];
+
+/// Modules that install packages, each becoming a `package` shift.
+const PACKAGE_MODULES: &[&str] = &["package", "apt", "dnf", "yum", "apk", "pacman", "zypper"];
+
+/// Shift types that can run as another user.
+const RUN_AS: &[&str] = &["cmd", "file", "create_dir"];
+
+/// How deep `import_tasks` is followed.
+const MAX_DEPTH: usize = 8;
+
+/// Converts the playbook or task file at `path`.
+pub fn import(path: &Path) -> ShiftResult<Imported> {
+    let mut importer = Importer {
+        imported: Imported {
+            source: path.display().to_string(),
+            ..Imported::default()
+        },
+        becomes: false,
+    };
+    importer.file(path, &Inherited::default(), 0)?;
+    if importer.becomes {
+        importer
+            .imported
+            .notes
+            .push("the playbook uses `become`; apply the plan as root, such as with sudo".into());
+    }
+    importer.imported.allow_outside_root();
+    Ok(importer.imported)
+}
+
+struct Importer {
+    imported: Imported,
+    /// Whether anything uses `become`.
+    becomes: bool,
+}
+
+/// What a task takes from the blocks and imports around it.
+#[derive(Clone, Default)]
+struct Inherited {
+    when: Vec<String>,
+    tags: Vec<String>,
+    become_user: Option<String>,
+}
+
+impl Importer {
+    fn file(&mut self, path: &Path, inherited: &Inherited, depth: usize) -> ShiftResult<()> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| ShiftError::Custom(format!("cannot read {}: {err}", path.display())))?;
+        let doc: Yaml = serde_yaml::from_str(&text)
+            .map_err(|err| ShiftError::Custom(format!("{}: {err}", path.display())))?;
+        let Yaml::Sequence(items) = doc else {
+            return Err(ShiftError::Custom(format!(
+                "{}: expected a list of plays or tasks",
+                path.display()
+            )));
+        };
+        let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        for item in &items {
+            if is_play(item) {
+                self.play(item, &dir, depth)?;
+            } else {
+                self.task(item, &dir, inherited, depth)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn play(&mut self, play: &Yaml, dir: &Path, depth: usize) -> ShiftResult<()> {
+        if let Some(playbook) = play.get("import_playbook") {
+            return match text(playbook).filter(|path| !path.contains("{{")) {
+                Some(path) if depth < MAX_DEPTH => {
+                    self.file(&dir.join(path), &Inherited::default(), depth + 1)
+                }
+                _ => {
+                    self.push(play, Step::todo("the imported playbook was not followed"));
+                    Ok(())
+                }
+            };
+        }
+        let notes = &mut self.imported.notes;
+        if let Some(Yaml::Mapping(vars)) = play.get("vars") {
+            for (name, value) in vars {
+                let Some(name) = text(name) else { continue };
+                match text(value).map(|value| template(&value)) {
+                    Some(Ok(mut value)) => {
+                        // A plan sets its vars in name order, not the
+                        // order written, so earlier ones are filled in.
+                        for (earlier, earlier_value) in &self.imported.vars {
+                            value = value.replace(&format!("${{{earlier}}}"), earlier_value);
+                        }
+                        self.imported.vars.push((name, value));
+                    }
+                    Some(Err(reason)) => {
+                        notes.push(format!("var `{name}` {reason}; set it by hand"))
+                    }
+                    None => notes.push(format!(
+                        "var `{name}` is a list or mapping, and skies variables are text"
+                    )),
+                }
+            }
+        }
+        if play.get("vars_files").is_some() {
+            notes.push("`vars_files` are not imported; copy their variables into `[vars]`".into());
+        }
+        if let Some(Yaml::Sequence(roles)) = play.get("roles") {
+            for role in roles {
+                let name = text(role)
+                    .or_else(|| role.get("role").and_then(text))
+                    .unwrap_or_else(|| "?".into());
+                notes.push(format!(
+                    "role `{name}` is not imported; import its tasks/main.yml and add them here"
+                ));
+            }
+        }
+        let mut inherited = Inherited::default();
+        if play.get("become").is_some_and(truthy) {
+            self.becomes = true;
+            inherited.become_user = play.get("become_user").and_then(text);
+        }
+        inherited.tags = list(play.get("tags"));
+        for section in ["pre_tasks", "tasks", "post_tasks"] {
+            if let Some(Yaml::Sequence(tasks)) = play.get(section) {
+                for task in tasks {
+                    self.task(task, dir, &inherited, depth)?;
+                }
+            }
+        }
+        if let Some(Yaml::Sequence(handlers)) = play.get("handlers") {
+            for handler in handlers {
+                let step = Step::todo(
+                    "a handler, which runs when notified; skies has no handlers, so add \
+                     what it does where it is needed",
+                );
+                self.push(handler, step);
+            }
+        }
+        Ok(())
+    }
+
+    fn task(
+        &mut self,
+        task: &Yaml,
+        dir: &Path,
+        inherited: &Inherited,
+        depth: usize,
+    ) -> ShiftResult<()> {
+        let Yaml::Mapping(fields) = task else {
+            self.push(task, Step::todo("not a task"));
+            return Ok(());
+        };
+        let mut inherited = inherited.clone();
+        if task.get("become").is_some_and(truthy) {
+            self.becomes = true;
+            if let Some(user) = task.get("become_user").and_then(text) {
+                inherited.become_user = Some(user);
+            }
+        }
+
+        // What a block or included file passes on to its tasks; a task's
+        // own are added when it is converted, after its loop item is.
+        let mut scope = inherited.clone();
+        if let Some(when) = task.get("when") {
+            scope.when.extend(conditions(when));
+        }
+        scope.tags.extend(list(task.get("tags")));
+
+        if let Some(Yaml::Sequence(block)) = task.get("block") {
+            for inner in block {
+                self.task(inner, dir, &scope, depth)?;
+            }
+            for section in ["rescue", "always"] {
+                if task.get(section).is_some() {
+                    let step = Step::todo(format!(
+                        "the block's `{section}` tasks were left out; skies stops at the first \
+                         failure and revert undoes the run"
+                    ));
+                    self.push(task, step);
+                }
+            }
+            return Ok(());
+        }
+
+        let modules: Vec<&str> = fields
+            .keys()
+            .filter_map(Yaml::as_str)
+            .filter(|key| !KEYWORDS.contains(key))
+            .collect();
+        let [module_key] = modules[..] else {
+            self.push(task, Step::todo("no single module to convert"));
+            return Ok(());
+        };
+        let module = module_key
+            .strip_prefix("ansible.builtin.")
+            .or_else(|| module_key.strip_prefix("ansible.legacy."))
+            .unwrap_or(module_key);
+
+        if matches!(module, "import_tasks" | "include_tasks" | "include") {
+            let file =
+                text(&task[module_key]).or_else(|| task[module_key].get("file").and_then(text));
+            return match file.filter(|file| !file.contains("{{")) {
+                Some(file) if depth < MAX_DEPTH => self.file(&dir.join(file), &scope, depth + 1),
+                _ => {
+                    self.push(task, Step::todo("the included tasks were not followed"));
+                    Ok(())
+                }
+            };
+        }
+
+        let items = match task.get("loop").or_else(|| task.get("with_items")) {
+            None => vec![None],
+            Some(Yaml::Sequence(items)) => items.iter().map(Some).collect(),
+            Some(_) => {
+                self.push(
+                    task,
+                    Step::todo("loops over a variable; list its items in the task by hand"),
+                );
+                return Ok(());
+            }
+        };
+        if let Some(task) = package_list(task, module, module_key, &items) {
+            let step = self.convert(&task, module, module_key, dir, &inherited);
+            self.push(&task, step);
+            return Ok(());
+        }
+        for item in items {
+            let task = match item {
+                Some(item) => with_item(task, item),
+                None => task.clone(),
+            };
+            let step = self.convert(&task, module, module_key, dir, &inherited);
+            self.push(&task, step);
+        }
+        Ok(())
+    }
+
+    /// Converts one task, its loop item already filled in.
+    fn convert(
+        &self,
+        task: &Yaml,
+        module: &str,
+        module_key: &str,
+        dir: &Path,
+        inherited: &Inherited,
+    ) -> Step {
+        let (args, free_form) = match &task[module_key] {
+            Yaml::Mapping(args) => (args.clone(), None),
+            Yaml::Null => (Mapping::new(), None),
+            value if matches!(module, "command" | "shell") => (Mapping::new(), text(value)),
+            value => (key_values(&text(value).unwrap_or_default()), None),
+        };
+        let mut args = Args {
+            args,
+            todos: Vec::new(),
+        };
+        if let Some(Yaml::Mapping(extra)) = task.get("args") {
+            args.args.extend(extra.clone());
+        }
+        let converted = match module {
+            "file" => args.file(),
+            "copy" => args.copy(dir),
+            "template" => args.template(dir),
+            "command" | "shell" => args.command(module, free_form),
+            "git" => args.git(),
+            module if PACKAGE_MODULES.contains(&module) => args.package(module),
+            "lineinfile" => args.lineinfile(),
+            _ => Err(format!("`{module_key}` has no skies equivalent yet")),
+        };
+        let (kind, mut fields) = match converted {
+            Ok(converted) => converted,
+            Err(reason) => return Step::todo(reason),
+        };
+        let mut todos = args.todos;
+
+        let mut when = inherited.when.clone();
+        if let Some(condition) = task.get("when") {
+            when.extend(conditions(condition));
+        }
+        if !when.is_empty() {
+            let joined = match when.as_slice() {
+                [one] => one.clone(),
+                many => many
+                    .iter()
+                    .map(|c| format!("({c})"))
+                    .collect::<Vec<_>>()
+                    .join(" and "),
+            };
+            match expression(&joined) {
+                Ok(condition) => {
+                    fields.insert("when".into(), condition.into());
+                }
+                Err(reason) => todos.push(format!("the `when` condition {reason}")),
+            }
+        }
+        let mut tags = inherited.tags.clone();
+        tags.extend(list(task.get("tags")));
+        if !tags.is_empty() {
+            fields.insert("tags".into(), tags.into());
+        }
+        let user = task
+            .get("become_user")
+            .and_then(text)
+            .or(inherited.become_user.clone());
+        if let Some(user) = user.filter(|user| user != "root") {
+            if RUN_AS.contains(&kind) {
+                fields.insert("run_as".into(), user.into());
+            } else {
+                todos.push(format!(
+                    "runs as `{user}` in Ansible, and as the plan's user here"
+                ));
+            }
+        }
+        if let Some(Yaml::Mapping(environment)) = task.get("environment") {
+            if kind == "cmd" {
+                let mut env = toml::Table::new();
+                for (name, value) in environment {
+                    if let (Some(name), Some(value)) = (text(name), text(value)) {
+                        env.insert(name, converted_text(&value, &mut todos).into());
+                    }
+                }
+                fields.insert("env".into(), env.into());
+            } else {
+                todos.push("its `environment` is not converted".into());
+            }
+        }
+        if let Some(handlers) = task.get("notify") {
+            todos.push(format!(
+                "notifies {}; do what the handler does as a shift after this one",
+                list(Some(handlers)).join(", ")
+            ));
+        }
+        for (keyword, todo) in IGNORED {
+            if task.get(*keyword).is_some() {
+                todos.push((*todo).into());
+            }
+        }
+        let mut step = Step::shift(kind, fields);
+        step.todos = todos;
+        step
+    }
+
+    fn push(&mut self, original: &Yaml, mut step: Step) {
+        step.name = original.get("name").and_then(text);
+        step.original = serde_yaml::to_string(original).unwrap_or_default();
+        self.imported.steps.push(step);
+    }
+}
+
+/// A module's arguments, and the TODOs converting them turns up.
+struct Args {
+    args: Mapping,
+    todos: Vec<String>,
+}
+
+type Converted = Result<(&'static str, toml::Table), String>;
+
+impl Args {
+    fn get(&self, names: &[&str]) -> Option<String> {
+        names
+            .iter()
+            .find_map(|name| self.args.get(*name).and_then(text))
+    }
+
+    /// An argument with its Jinja converted, noting a TODO where it
+    /// cannot be.
+    fn converted(&mut self, names: &[&str]) -> Option<String> {
+        let value = self.get(names)?;
+        Some(converted_text(&value, &mut self.todos))
+    }
+
+    fn required(&mut self, names: &[&str]) -> Result<String, String> {
+        self.converted(names)
+            .ok_or_else(|| format!("has no `{}` argument", names[0]))
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.args.get(name).is_some_and(truthy)
+    }
+
+    /// Adds `mode` and `run_as` from the module's `mode`, `owner` and
+    /// `group`.
+    fn ownership(&mut self, fields: &mut toml::Table) {
+        if let Some(mode) = self.get(&["mode"]) {
+            match octal_mode(&mode) {
+                Some(mode) => {
+                    fields.insert("mode".into(), i64::from(mode).into());
+                }
+                None => self
+                    .todos
+                    .push(format!("mode `{mode}` is not converted; give it in octal")),
+            }
+        }
+        let owner = self.get(&["owner"]);
+        if let Some(owner) = owner.clone().filter(|owner| owner != "root") {
+            fields.insert("run_as".into(), owner.into());
+        }
+        if let Some(group) = self.get(&["group"]) {
+            if Some(&group) != owner.as_ref() && group != "root" {
+                self.todos.push(format!(
+                    "group `{group}` is not set; the owner's login group is used"
+                ));
+            }
+        }
+    }
+
+    fn file(&mut self) -> Converted {
+        let path = self.required(&["path", "dest", "name"])?;
+        let state = self.get(&["state"]).unwrap_or_else(|| "file".into());
+        let mut fields = toml::Table::new();
+        fields.insert("path".into(), path.into());
+        match state.as_str() {
+            "directory" => {
+                self.ownership(&mut fields);
+                if self.flag("recurse") {
+                    self.todos.push(
+                        "`recurse` is not converted; only the directory gets the mode".into(),
+                    );
+                }
+                Ok(("create_dir", fields))
+            }
+            "link" => {
+                let target = self.required(&["src"])?;
+                fields.insert("target".into(), target.into());
+                Ok(("symlink", fields))
+            }
+            "absent" => Err(
+                "removes a path, which no shift does; reverting the shift that made it removes it"
+                    .into(),
+            ),
+            "touch" => {
+                Err("touches a file; use a `file` shift with the contents it should have".into())
+            }
+            "hard" => Err("makes a hard link, which no shift does".into()),
+            _ => Err(
+                "only changes a file's attributes; set them on the shift that writes the file"
+                    .into(),
+            ),
+        }
+    }
+
+    fn copy(&mut self, dir: &Path) -> Converted {
+        let mut dest = self.required(&["dest"])?;
+        // Ansible templates `content` like any argument, but not `src`.
+        let contents = match (self.converted(&["content"]), self.get(&["src"])) {
+            (Some(content), _) => content,
+            (None, Some(src)) => {
+                if self.flag("remote_src") {
+                    return Err("copies a file already on the machine, which no shift does".into());
+                }
+                if dest.ends_with('/') {
+                    let name = Path::new(&src).file_name().unwrap_or_default();
+                    dest.push_str(&name.to_string_lossy());
+                }
+                literal(&read_source(dir, "files", &src)?)
+            }
+            (None, None) => return Err("has neither `content` nor `src`".into()),
+        };
+        let mut fields = toml::Table::new();
+        fields.insert("path".into(), dest.into());
+        fields.insert("contents".into(), contents.into());
+        self.ownership(&mut fields);
+        Ok(("file", fields))
+    }
+
+    fn template(&mut self, dir: &Path) -> Converted {
+        let dest = self.required(&["dest"])?;
+        let src = self.get(&["src"]).ok_or("has no `src` argument")?;
+        let contents = template(&read_source(dir, "templates", &src)?)
+            .map_err(|reason| format!("template `{src}` {reason}; convert it by hand"))?;
+        let mut fields = toml::Table::new();
+        fields.insert("path".into(), dest.into());
+        fields.insert("contents".into(), contents.into());
+        self.ownership(&mut fields);
+        Ok(("file", fields))
+    }
+
+    fn command(&mut self, module: &str, free_form: Option<String>) -> Converted {
+        let mut fields = toml::Table::new();
+        let words = match self.args.get("argv") {
+            Some(Yaml::Sequence(argv)) => {
+                let argv: Vec<String> = argv.iter().filter_map(text).collect();
+                Some(
+                    argv.iter()
+                        .map(|arg| converted_text(arg, &mut self.todos))
+                        .collect(),
+                )
+            }
+            _ => {
+                let mut line = free_form
+                    .or_else(|| self.get(&["cmd", "_raw_params"]))
+                    .ok_or("has no command")?;
+                // Old-style `creates=...` and the like, after the command.
+                if module == "command" {
+                    let legacy = Regex::new(r"\s+(creates|removes|chdir)=((?:\{\{.*?\}\}|\S)+)")
+                        .expect("the legacy argument pattern is valid");
+                    for caps in legacy.captures_iter(&line) {
+                        self.args.insert(caps[1].into(), caps[2].into());
+                    }
+                    line = legacy.replace_all(&line, "").into_owned();
+                }
+                match template(&line).map(|line| split_plan_words(&line)) {
+                    Ok(Some(words)) => Some(words),
+                    Ok(None) => {
+                        let program = self.get(&["executable"]).unwrap_or_else(|| "sh".into());
+                        let script = converted_text(&line, &mut self.todos);
+                        fields.insert("program".into(), program.into());
+                        fields.insert("args".into(), vec!["-c".to_string(), script].into());
+                        if module == "command" {
+                            self.todos.push(
+                                "runs through `sh -c`; check the quoting, since `command` does \
+                                 not use a shell"
+                                    .into(),
+                            );
+                        }
+                        None
+                    }
+                    Err(reason) => return Err(format!("the command {reason}")),
+                }
+            }
+        };
+        if let Some(words) = words {
+            let mut words = words.into_iter();
+            let program: String = words.next().ok_or("has an empty command")?;
+            fields.insert("program".into(), program.into());
+            let args: Vec<String> = words.collect();
+            if !args.is_empty() {
+                fields.insert("args".into(), args.into());
+            }
+        }
+        if let Some(cwd) = self.converted(&["chdir"]) {
+            fields.insert("cwd".into(), cwd.into());
+        }
+        if let Some(creates) = self.converted(&["creates"]) {
+            fields.insert("creates".into(), creates.into());
+        }
+        if self.args.contains_key("removes") {
+            self.todos
+                .push("`removes` is not converted; the command always runs".into());
+        }
+        if self.args.contains_key("stdin") {
+            self.todos.push("`stdin` is not converted".into());
+        }
+        Ok(("cmd", fields))
+    }
+
+    fn git(&mut self) -> Converted {
+        let repo = self.required(&["repo"])?;
+        let dest = self.required(&["dest"])?;
+        let mut fields = toml::Table::new();
+        fields.insert("repo".into(), repo.into());
+        fields.insert("target".into(), dest.into());
+        match self.converted(&["version"]) {
+            Some(version) if is_commit(&version) => self.todos.push(format!(
+                "pinned to commit {version}; pin it in skies.lock with `skies update-lock`"
+            )),
+            Some(version) if version != "HEAD" => {
+                fields.insert("branch".into(), version.into());
+            }
+            _ => {}
+        }
+        if self.args.contains_key("key_file") {
+            self.todos
+                .push("`key_file` is not converted; set `token_env` or use an SSH agent".into());
+        }
+        Ok(("github_clone", fields))
+    }
+
+    fn package(&mut self, module: &str) -> Converted {
+        let names = match self.args.get("name").or_else(|| self.args.get("pkg")) {
+            Some(Yaml::Sequence(names)) => names.iter().filter_map(text).collect(),
+            Some(name) => text(name)
+                .map(|names| names.split(',').map(|n| n.trim().to_string()).collect())
+                .unwrap_or_default(),
+            None => Vec::new(),
+        };
+        if names.is_empty() {
+            return Err(match self.args.contains_key("update_cache") {
+                true => "only updates the package cache, which no shift does".into(),
+                false => "names no packages".into(),
+            });
+        }
+        match self.get(&["state"]).as_deref() {
+            None | Some("present" | "installed") => {}
+            Some("latest") => self.todos.push(
+                "`state: latest` is not converted; packages are installed, not upgraded".into(),
+            ),
+            Some(state) => return Err(format!("`state: {state}` is not converted")),
+        }
+        if self.flag("update_cache") {
+            self.todos
+                .push("the package cache is not updated before installing".into());
+        }
+        let packages: Vec<String> = names
+            .iter()
+            .map(|name| converted_text(name, &mut self.todos))
+            .collect();
+        if module != "package" {
+            self.todos.push(format!(
+                "used `{module}`; the shift uses whichever package manager the machine has"
+            ));
+        }
+        let mut fields = toml::Table::new();
+        fields.insert("packages".into(), packages.into());
+        Ok(("package", fields))
+    }
+
+    fn lineinfile(&mut self) -> Converted {
+        let path = self.required(&["path", "dest", "name"])?;
+        if self.get(&["state"]).as_deref() == Some("absent") {
+            return Err("removes a line, which no shift does".into());
+        }
+        let line = self.required(&["line"])?;
+        let mut fields = toml::Table::new();
+        fields.insert("path".into(), path.into());
+        fields.insert("line".into(), line.into());
+        if let Some(pattern) = self.get(&["regexp", "regex"]) {
+            fields.insert("matches".into(), literal(&pattern).into());
+        }
+        if self.flag("create") {
+            fields.insert("create".into(), true.into());
+        }
+        for name in ["insertafter", "insertbefore"] {
+            if self.args.contains_key(name) {
+                self.todos.push(format!(
+                    "`{name}` is not converted; the line goes at the end"
+                ));
+            }
+        }
+        if self.flag("backrefs") {
+            self.todos.push("`backrefs` is not converted".into());
+        }
+        if ["mode", "owner", "group"]
+            .iter()
+            .any(|name| self.args.contains_key(*name))
+        {
+            self.todos
+                .push("the file's mode and owner are not converted".into());
+        }
+        Ok(("line_in_file", fields))
+    }
+}
+
+fn is_play(item: &Yaml) -> bool {
+    ["hosts", "tasks", "roles", "pre_tasks", "import_playbook"]
+        .iter()
+        .any(|key| item.get(*key).is_some())
+}
+
+/// Free-form `path=/etc/x state=directory` arguments as a mapping.
+fn key_values(line: &str) -> Mapping {
+    let pair = Regex::new(r#"(\w+)=("[^"]*"|'[^']*'|(?:\{\{.*?\}\}|\S)+)"#)
+        .expect("the argument pattern is valid");
+    pair.captures_iter(line)
+        .map(|caps| {
+            let value = caps[2].trim_matches(|c| c == '"' || c == '\'');
+            (Yaml::from(&caps[1]), Yaml::from(value))
+        })
+        .collect()
+}
+
+/// A scalar as text.
+fn text(value: &Yaml) -> Option<String> {
+    match value {
+        Yaml::String(s) => Some(s.clone()),
+        Yaml::Number(n) => Some(n.to_string()),
+        Yaml::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Whether a YAML value means yes, the ways Ansible takes one.
+fn truthy(value: &Yaml) -> bool {
+    match value {
+        Yaml::Bool(b) => *b,
+        Yaml::String(s) => matches!(s.to_lowercase().as_str(), "yes" | "true" | "on" | "1"),
+        Yaml::Number(n) => n.as_i64() == Some(1),
+        _ => false,
+    }
+}
+
+/// A string or list of them, such as `tags`.
+fn list(value: Option<&Yaml>) -> Vec<String> {
+    match value {
+        Some(Yaml::Sequence(items)) => items.iter().filter_map(text).collect(),
+        Some(value) => text(value)
+            .map(|value| value.split(',').map(|v| v.trim().to_string()).collect())
+            .unwrap_or_default(),
+        None => Vec::new(),
+    }
+}
+
+/// A `when`, one condition or a list of them that must all hold.
+fn conditions(when: &Yaml) -> Vec<String> {
+    match when {
+        Yaml::Sequence(items) => items.iter().filter_map(text).collect(),
+        when => text(when).into_iter().collect(),
+    }
+}
+
+/// `task` with `{{ item }}`, and `{{ item.name }}` for a mapping, filled
+/// in with `item`.
+fn with_item(task: &Yaml, item: &Yaml) -> Yaml {
+    let pattern = Regex::new(r#"\{\{\s*item(?:\.(\w+)|\[\s*['"](\w+)['"]\s*\])?\s*\}\}"#)
+        .expect("the item pattern is valid");
+    let fill = |text: &str| {
+        pattern
+            .replace_all(text, |caps: &regex::Captures<'_>| {
+                let value = match caps.get(1).or(caps.get(2)) {
+                    Some(key) => item.get(key.as_str()),
+                    None => Some(item),
+                };
+                value
+                    .and_then(self::text)
+                    .unwrap_or_else(|| caps[0].to_string())
+            })
+            .into_owned()
+    };
+    fn walk(value: &Yaml, fill: &dyn Fn(&str) -> String) -> Yaml {
+        match value {
+            Yaml::String(s) => Yaml::String(fill(s)),
+            Yaml::Sequence(items) => Yaml::Sequence(items.iter().map(|v| walk(v, fill)).collect()),
+            Yaml::Mapping(map) => Yaml::Mapping(
+                map.iter()
+                    .filter(|(key, _)| !matches!(key.as_str(), Some("loop" | "with_items")))
+                    .map(|(key, value)| (key.clone(), walk(value, fill)))
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+    // A bare `when: item == "x"` names the item without braces.
+    let mut task = walk(task, &fill);
+    if let Some(when) = task.get_mut("when") {
+        let bare = Regex::new(r"\bitem\b").expect("the item pattern is valid");
+        let quoted = text(item).map(|item| match item.contains('\'') {
+            true => format!("{item:?}"),
+            false => format!("'{item}'"),
+        });
+        if let (Some(when_text), Some(quoted)) = (text(when), quoted) {
+            *when = Yaml::String(bare.replace_all(&when_text, quoted.as_str()).into_owned());
+        }
+    }
+    task
+}
+
+/// A package task looping over package names, as one task installing
+/// them all, the way Ansible runs it.
+fn package_list(
+    task: &Yaml,
+    module: &str,
+    module_key: &str,
+    items: &[Option<&Yaml>],
+) -> Option<Yaml> {
+    let item = Regex::new(r"^\{\{\s*item\s*\}\}$").expect("the item pattern is valid");
+    let names: Vec<Yaml> = items
+        .iter()
+        .map(|item| item.filter(|item| text(item).is_some()).cloned())
+        .collect::<Option<_>>()?;
+    let name = task[module_key].get("name").and_then(text)?;
+    if !PACKAGE_MODULES.contains(&module) || names.is_empty() || !item.is_match(&name) {
+        return None;
+    }
+    let mut task = task.clone();
+    task[module_key]["name"] = Yaml::Sequence(names);
+    if let Yaml::Mapping(fields) = &mut task {
+        fields.remove("loop");
+        fields.remove("with_items");
+    }
+    Some(task)
+}
+
+/// Where a `copy` or `template` source lives: the role-style `files` or
+/// `templates` directory next to the playbook, or the playbook's own.
+fn read_source(dir: &Path, kind: &str, src: &str) -> Result<String, String> {
+    let candidates: Vec<PathBuf> = match Path::new(src).is_absolute() {
+        true => vec![PathBuf::from(src)],
+        false => vec![dir.join(kind).join(src), dir.join(src)],
+    };
+    let path = candidates
+        .iter()
+        .find(|path| path.is_file())
+        .ok_or_else(|| format!("has a `src` that was not found: {src}"))?;
+    let bytes = fs::read(path).map_err(|err| format!("cannot read {}: {err}", path.display()))?;
+    String::from_utf8(bytes).map_err(|_| format!("copies {src}, which is not text"))
+}
+
+/// `text` with its Jinja converted, or taken literally with a TODO.
+fn converted_text(text: &str, todos: &mut Vec<String>) -> String {
+    match template(text) {
+        Ok(converted) => converted,
+        Err(reason) => {
+            todos.push(format!("`{text}` {reason}"));
+            literal(text)
+        }
+    }
+}
+
+/// Jinja text as plan file text: `{{ expr }}` becomes `${expr}` and the
+/// rest is taken literally.
+fn template(text: &str) -> Result<String, String> {
+    if text.contains("{%") || text.contains("{#") {
+        return Err("uses Jinja statements or comments".into());
+    }
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&literal(&rest[..start]));
+        let end = rest[start..].find("}}").ok_or("has an unclosed `{{`")?;
+        let jinja = &rest[start + 2..start + end];
+        out.push_str(&format!("${{{}}}", expression(jinja)?));
+        rest = &rest[start + end + 2..];
+    }
+    out.push_str(&literal(rest));
+    Ok(out)
+}
+
+/// A Jinja expression as a skies one, or why it has none.
+fn expression(jinja: &str) -> Result<String, String> {
+    let jinja = jinja.trim();
+    for (word, what) in [
+        ("ansible_", "Ansible facts"),
+        ("hostvars", "`hostvars`"),
+        ("lookup(", "a lookup"),
+        ("query(", "a lookup"),
+        ("item", "a loop item"),
+    ] {
+        if jinja.contains(word) {
+            return Err(format!("uses {what}, which skies does not have"));
+        }
+    }
+    // A filter takes the operand just before it, `a == b | lower` being
+    // `a == (b | lower)`, and what follows its name goes on as it was.
+    let operand = Regex::new(r#"(!?[\w.]+|'[^']*'|"[^"]*"|\w+\([^()]*\))\s*$"#)
+        .expect("the operand pattern is valid");
+    let filter = Regex::new(r#"^\s*(\w+)(?:\(((?:'[^']*'|"[^"]*"|[^'"()])*)\))?"#)
+        .expect("the filter pattern is valid");
+    let mut parts = split_filters(jinja).into_iter();
+    let mut out = parts.next().unwrap_or_default().trim_end().to_string();
+    for part in parts {
+        let caps = filter
+            .captures(part)
+            .ok_or("has a `|` that is not a filter")?;
+        let at = operand
+            .find(&out)
+            .ok_or("has a filter with nothing to filter")?;
+        let value = out[at.start()..].trim_end().to_string();
+        let name = &caps[1];
+        let arg = caps.get(2).map(|arg| arg.as_str().trim());
+        let filtered = match (name, arg) {
+            ("default" | "d", Some(arg)) => format!("default({value}, {arg})"),
+            ("lower" | "upper" | "trim", None) => format!("{name}({value})"),
+            ("length" | "count", None) => format!("len({value})"),
+            ("string" | "bool", None) => value,
+            _ => return Err(format!("uses the `{name}` filter")),
+        };
+        out = format!("{}{filtered}{}", &out[..at.start()], &part[caps[0].len()..]);
+        out.truncate(out.trim_end().len());
+    }
+    let tests = [
+        (r"([\w.]+)\s+is\s+not\s+defined", "!defined($1)"),
+        (r"([\w.]+)\s+is\s+undefined", "!defined($1)"),
+        (r"([\w.]+)\s+is\s+defined", "defined($1)"),
+        (r"\bTrue\b", "true"),
+        (r"\bFalse\b", "false"),
+    ];
+    for (pattern, replacement) in tests {
+        let pattern = Regex::new(pattern).expect("the test patterns are valid");
+        out = pattern.replace_all(&out, replacement).into_owned();
+    }
+    Expr::parse(&out).map_err(|_| format!("has `{jinja}`, which is not a skies expression"))?;
+    Ok(out)
+}
+
+/// `jinja` split at each `|` starting a filter, outside quotes.
+fn split_filters(jinja: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut quote = None;
+    let mut start = 0;
+    for (pos, c) in jinja.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c == '|' => {
+                parts.push(&jinja[start..pos]);
+                start = pos + 1;
+            }
+            None => {}
+        }
+    }
+    parts.push(&jinja[start..]);
+    parts
+}
+
+fn is_commit(version: &str) -> bool {
+    (7..=40).contains(&version.len()) && version.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use crate::context::ExecutionContext;
+    use crate::error::ShiftError;
+    use crate::plan_file;
+    use crate::registry::Registry;
+
+    const FIXTURES: &str = "tests/fixtures/import/ansible";
+
+    #[test]
+    fn playbook_converts_to_the_expected_plan() {
+        let imported = super::import(&Path::new(FIXTURES).join("playbook.yml")).unwrap();
+        let plan = imported.to_toml();
+        let expected = fs::read_to_string(Path::new(FIXTURES).join("expected.toml")).unwrap();
+        assert_eq!(plan, expected);
+        assert_eq!(imported.todo_count(), 4);
+
+        let mut ctx = ExecutionContext::new();
+        let parsed = plan_file::parse(&plan, &Registry::builtin(), &mut ctx).unwrap();
+        assert_eq!(parsed.entries().len(), imported.shift_count());
+        assert_eq!(
+            ctx.interpolate("${app_dir}/${app_user}").unwrap(),
+            "/opt/app/deploy"
+        );
+        // Preflight may find the machine lacks a package manager, but
+        // not a path the plan is refused.
+        if let Err(ShiftError::Preflight(problems)) = parsed.preflight(&ctx) {
+            for (id, err) in problems {
+                let err = err.to_string();
+                assert!(!err.contains("outside the plan root"), "{id}: {err}");
+            }
+        }
+    }
+}
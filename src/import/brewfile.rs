@@ -0,0 +1,209 @@
+//! Brewfiles converted to plans.
+//!
+//! Formulae become one `package` shift, which installs with Homebrew where
+//! the machine has no system package manager, as on macOS. Casks and taps
+//! become `brew` commands, App Store apps `mas` commands and VS Code
+//! extensions `vscode_extension` shifts. Options on an entry, and entries
+//! of other kinds, are left as TODOs.
+
+use std::fs;
+use std::path::Path;
+
+use regex::Regex;
+
+use super::{Imported, Step};
+use crate::error::{ShiftError, ShiftResult};
+
+/// Converts the Brewfile at `path`.
+pub fn import(path: &Path) -> ShiftResult<Imported> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| ShiftError::Custom(format!("cannot read {}: {err}", path.display())))?;
+    let entry = Regex::new(r#"^(\w+)\s+["']([^"']+)["']\s*(?:,\s*(.*))?$"#)
+        .expect("the entry pattern is valid");
+    let mut imported = Imported {
+        source: path.display().to_string(),
+        ..Imported::default()
+    };
+    // Formulae are gathered into the step at `formulae`.
+    let mut formulae: Option<usize> = None;
+    let mut comment: Option<String> = None;
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            comment = None;
+            continue;
+        }
+        if let Some(text) = trimmed.strip_prefix('#') {
+            comment = Some(text.trim().to_string());
+            continue;
+        }
+        let name = comment.take();
+        let Some(caps) = entry.captures(without_comment(trimmed)) else {
+            imported.steps.push(Step {
+                original: line.to_string(),
+                ..Step::todo("not an entry this converts; Ruby in a Brewfile is left out")
+            });
+            continue;
+        };
+        let (kind, value) = (&caps[1], caps[2].to_string());
+        let options = caps.get(3).map(|options| options.as_str().trim());
+        if kind == "brew" && options.is_none() {
+            match formulae {
+                Some(index) => {
+                    let step = &mut imported.steps[index];
+                    if let Some(toml::Value::Array(packages)) = step
+                        .shift
+                        .as_mut()
+                        .and_then(|shift| shift.get_mut("packages"))
+                    {
+                        packages.push(value.into());
+                    }
+                    step.original.push('\n');
+                    step.original.push_str(line);
+                }
+                None => {
+                    let mut fields = toml::Table::new();
+                    fields.insert("packages".into(), vec![value].into());
+                    formulae = Some(imported.steps.len());
+                    imported.steps.push(Step {
+                        name: name.or_else(|| Some("Homebrew formulae".into())),
+                        original: line.to_string(),
+                        ..Step::shift("package", fields)
+                    });
+                }
+            }
+            continue;
+        }
+        let mut step = convert(kind, &value, options);
+        step.name = name;
+        step.original = line.to_string();
+        imported.steps.push(step);
+    }
+    if formulae.is_some() {
+        imported.notes.push(
+            "formulae install with Homebrew only where there is no system package manager; \
+             on Linux, check each has the same name there"
+                .into(),
+        );
+    }
+    imported.allow_outside_root();
+    Ok(imported)
+}
+
+/// `line` up to a `#` comment, which Ruby starts anywhere outside quotes.
+fn without_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (at, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '#') => return line[..at].trim_end(),
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), c) if c == open => quote = None,
+            _ => {}
+        }
+    }
+    line
+}
+
+/// One entry, other than a formula without options.
+fn convert(kind: &str, value: &str, options: Option<&str>) -> Step {
+    if kind == "mas" {
+        let id = Regex::new(r"\bid:\s*(\d+)").expect("the id pattern is valid");
+        return match options.and_then(|options| id.captures(options)) {
+            Some(caps) => {
+                let mut fields = toml::Table::new();
+                fields.insert("program".into(), "mas".into());
+                fields.insert("args".into(), vec!["install", &caps[1]].into());
+                fields.insert(
+                    "creates".into(),
+                    format!("/Applications/{value}.app").into(),
+                );
+                fields.insert("network".into(), true.into());
+                Step::shift("cmd", fields)
+            }
+            None => Step::todo(format!("installs `{value}` from the App Store, with no id")),
+        };
+    }
+    let mut step = entry(kind, value);
+    if let (Some(options), Some(_)) = (options, &step.shift) {
+        step.todos
+            .push(format!("its options are not converted: {options}"));
+    }
+    step
+}
+
+fn entry(kind: &str, value: &str) -> Step {
+    let brew = |args: &[&str], undo: &[&str]| {
+        let mut fields = toml::Table::new();
+        fields.insert("program".into(), "brew".into());
+        fields.insert("args".into(), args.to_vec().into());
+        fields.insert("undo".into(), undo.to_vec().into());
+        fields.insert("network".into(), true.into());
+        Step::shift("cmd", fields)
+    };
+    match kind {
+        "brew" => {
+            let mut fields = toml::Table::new();
+            fields.insert("packages".into(), vec![value].into());
+            Step::shift("package", fields)
+        }
+        "tap" => brew(&["tap", value], &["brew", "untap", value]),
+        "cask" => brew(
+            &["install", "--cask", value],
+            &["brew", "uninstall", "--cask", value],
+        ),
+        "vscode" => {
+            let mut fields = toml::Table::new();
+            fields.insert("extension".into(), value.into());
+            Step::shift("vscode_extension", fields)
+        }
+        kind => Step::todo(format!("`{kind}` entries have no skies equivalent yet")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::without_comment;
+    use crate::context::ExecutionContext;
+    use crate::error::ShiftError;
+    use crate::plan_file;
+    use crate::registry::Registry;
+
+    const FIXTURES: &str = "tests/fixtures/import/brewfile";
+
+    #[test]
+    fn brewfile_converts_to_the_expected_plan() {
+        let imported = super::import(&Path::new(FIXTURES).join("Brewfile")).unwrap();
+        let plan = imported.to_toml();
+        let expected = fs::read_to_string(Path::new(FIXTURES).join("expected.toml")).unwrap();
+        assert_eq!(plan, expected);
+        assert_eq!(imported.todo_count(), 4);
+
+        let mut ctx = ExecutionContext::new();
+        let parsed = plan_file::parse(&plan, &Registry::builtin(), &mut ctx).unwrap();
+        assert_eq!(parsed.entries().len(), imported.shift_count());
+        // Preflight may find the machine lacks a package manager, but
+        // not a path the plan is refused.
+        if let Err(ShiftError::Preflight(problems)) = parsed.preflight(&ctx) {
+            for (id, err) in problems {
+                let err = err.to_string();
+                assert!(!err.contains("outside the plan root"), "{id}: {err}");
+            }
+        }
+    }
+
+    #[test]
+    fn trailing_comments_are_not_part_of_the_entry() {
+        assert_eq!(
+            without_comment(r#"brew "ripgrep" # cli"#),
+            r#"brew "ripgrep""#
+        );
+        assert_eq!(
+            without_comment(r#"tap "a/b", "https://x/#y""#),
+            r#"tap "a/b", "https://x/#y""#
+        );
+        assert_eq!(without_comment("cask 'firefox'#browser"), "cask 'firefox'");
+    }
+}
@@ -0,0 +1,429 @@
+//! Dockerfiles converted to plans that set up the machine itself the way
+//! the image is built.
+//!
+//! `RUN` commands are converted as a shell script's are, see
+//! [`super::shell`], with `WORKDIR` as their `cd` and `USER` as who they
+//! run as. `ENV` and `ARG` become `[vars]`, and `COPY` and `ADD` of files
+//! in the build context `file` shifts. What only means something for a
+//! container, like `CMD`, `EXPOSE` or `VOLUME`, is left as TODOs, and
+//! commands that only keep the image small, like removing apt's lists,
+//! are left out.
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use regex::Regex;
+
+use super::shell::Importer;
+use super::{literal, octal_mode, Imported, Step};
+use crate::error::{ShiftError, ShiftResult};
+
+/// Commands that only clean up after a build to keep the image small.
+const CLEANUP: &[&str] = &[
+    "rm -rf /var/lib/apt/lists",
+    "apt-get clean",
+    "apt clean",
+    "dnf clean",
+    "yum clean",
+    "apk cache clean",
+    "rm -rf /var/cache",
+    "rm -rf /tmp/*",
+];
+
+/// Converts the Dockerfile at `path`, whose directory is the build
+/// context.
+pub fn import(path: &Path) -> ShiftResult<Imported> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| ShiftError::Custom(format!("cannot read {}: {err}", path.display())))?;
+    let context = path.parent().unwrap_or(Path::new(""));
+    let mut shell = Importer::new(path);
+    shell.ignored = CLEANUP;
+    let mut stages = 0;
+    let mut as_root = false;
+    // A `cd` in a `RUN` lasts only to its end; `WORKDIR` lasts.
+    let mut workdir = None;
+    for instruction in instructions(&text) {
+        let Instruction {
+            keyword,
+            args,
+            comment,
+            original,
+        } = instruction;
+        let todo = |reason: String| Step {
+            name: comment.clone(),
+            original: original.clone(),
+            ..Step::todo(reason)
+        };
+        let notes = &mut shell.imported.notes;
+        match keyword.as_str() {
+            "FROM" => {
+                stages += 1;
+                match stages {
+                    1 => notes.push(format!(
+                        "the image starts FROM {args}; the plan runs on the machine as it is, \
+                         so check it has what that image does"
+                    )),
+                    _ => notes.push(format!(
+                        "a later build stage, FROM {args}, is converted onto the same machine"
+                    )),
+                }
+            }
+            "RUN" => {
+                let script = match exec_form(&args) {
+                    Some(words) => words
+                        .iter()
+                        .map(|word| quote(word))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    None => strip_flags(&args).to_string(),
+                };
+                if script.starts_with("<<") {
+                    shell.imported.steps.push(todo(
+                        "runs a heredoc as a script; copy its commands in by hand".into(),
+                    ));
+                    continue;
+                }
+                as_root |= shell.user.is_none();
+                let script = match &comment {
+                    Some(comment) => format!("# {comment}\n{script}"),
+                    None => script,
+                };
+                shell.run(&script, Some(&original));
+                shell.cwd.clone_from(&workdir);
+            }
+            "ENV" | "ARG" => {
+                let export = keyword == "ENV";
+                for (name, value) in pairs(&args, export) {
+                    let step = match value {
+                        Some(value) => shell.assign(&name, &value, export),
+                        None => Some(todo(format!(
+                            "build argument `{name}` has no default; add it to `[vars]`"
+                        ))),
+                    };
+                    shell.imported.steps.extend(step.map(|step| Step {
+                        original: original.clone(),
+                        ..step
+                    }));
+                }
+            }
+            "WORKDIR" => {
+                let script = format!("mkdir -p {args}\ncd {args}");
+                let script = match &comment {
+                    Some(comment) => format!("# {comment}\n{script}"),
+                    None => script,
+                };
+                shell.run(&script, Some(&original));
+                workdir.clone_from(&shell.cwd);
+            }
+            "USER" => {
+                let user = args.split(':').next().unwrap_or_default().trim();
+                shell.user = match user {
+                    "root" | "0" => None,
+                    user => Some(user.to_string()),
+                };
+            }
+            "COPY" | "ADD" => {
+                for step in copy(&mut shell, context, &keyword, &args) {
+                    shell.imported.steps.push(Step {
+                        name: comment.clone(),
+                        original: original.clone(),
+                        ..step
+                    });
+                }
+            }
+            "CMD" | "ENTRYPOINT" => shell.imported.steps.push(todo(format!(
+                "`{keyword}` starts the container's process; run it as a service, such as a \
+                 systemd unit, by hand"
+            ))),
+            "EXPOSE" => shell.imported.steps.push(todo(format!(
+                "exposes {args}; open it with a `firewall_rule` shift if the machine needs it"
+            ))),
+            "LABEL" | "MAINTAINER" => {}
+            keyword => shell
+                .imported
+                .steps
+                .push(todo(format!("`{keyword}` has no skies equivalent"))),
+        }
+    }
+    if as_root {
+        shell.imported.notes.push(
+            "`RUN` commands ran as root in the image; apply the plan as root, such as with sudo"
+                .into(),
+        );
+    }
+    let mut imported = shell.finish();
+    imported.allow_outside_root();
+    Ok(imported)
+}
+
+struct Instruction {
+    /// Upper-cased, like `RUN`.
+    keyword: String,
+    args: String,
+    comment: Option<String>,
+    original: String,
+}
+
+/// The Dockerfile's instructions, continuation lines joined and heredocs
+/// gathered.
+fn instructions(text: &str) -> Vec<Instruction> {
+    let heredoc = Regex::new(r#"<<-?\s*['"]?(\w+)"#).expect("the heredoc pattern is valid");
+    let mut instructions = Vec::new();
+    let mut comment: Option<String> = None;
+    let mut lines = text.lines();
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            comment = None;
+            continue;
+        }
+        if let Some(text) = trimmed.strip_prefix('#') {
+            // Parser directives, like `# syntax=...`, are not comments.
+            if !instructions.is_empty() || !text.contains('=') {
+                comment = Some(text.trim().to_string());
+            }
+            continue;
+        }
+        let mut original = vec![line];
+        let mut joined = trimmed.to_string();
+        while joined.ends_with('\\') {
+            joined.pop();
+            // Comments and blank lines inside an instruction are skipped.
+            let next = lines.by_ref().find(|next| {
+                let next = next.trim();
+                !next.is_empty() && !next.starts_with('#')
+            });
+            let Some(next) = next else { break };
+            original.push(next);
+            joined.push(' ');
+            joined.push_str(next.trim());
+        }
+        let delimiters: Vec<String> = heredoc
+            .captures_iter(&joined)
+            .map(|caps| caps[1].to_string())
+            .collect();
+        for delimiter in delimiters {
+            for next in lines.by_ref() {
+                original.push(next);
+                joined.push('\n');
+                joined.push_str(next);
+                if next.trim() == delimiter {
+                    break;
+                }
+            }
+        }
+        let (keyword, args) = joined
+            .split_once(char::is_whitespace)
+            .unwrap_or((&joined, ""));
+        instructions.push(Instruction {
+            keyword: keyword.to_uppercase(),
+            args: args.trim().to_string(),
+            comment: comment.take(),
+            original: original.join("\n"),
+        });
+    }
+    instructions
+}
+
+/// `["a", "b"]` arguments, if given that way.
+fn exec_form(args: &str) -> Option<Vec<String>> {
+    args.starts_with('[')
+        .then(|| serde_json::from_str(args).ok())
+        .flatten()
+}
+
+/// `args` without the `--mount=...` and like flags before a `RUN`'s
+/// command.
+fn strip_flags(mut args: &str) -> &str {
+    while args.starts_with("--") {
+        args = args
+            .split_once(char::is_whitespace)
+            .map_or("", |(_, rest)| rest.trim_start());
+    }
+    args
+}
+
+/// `word` quoted for the shell.
+fn quote(word: &str) -> String {
+    format!("'{}'", word.replace('\'', r"'\''"))
+}
+
+/// `ENV` or `ARG` arguments as names and values, `None` for an `ARG`
+/// without a default.
+fn pairs(args: &str, env: bool) -> Vec<(String, Option<String>)> {
+    // The old `ENV NAME value with spaces` form sets one variable.
+    if let Some((name, value)) = args.split_once(char::is_whitespace) {
+        if env && !name.contains('=') {
+            let value = value.trim().replace('\\', "\\\\").replace('"', "\\\"");
+            return vec![(name.to_string(), Some(format!("\"{value}\"")))];
+        }
+    }
+    let mut pairs = Vec::new();
+    let mut rest = args.trim();
+    while !rest.is_empty() {
+        let end = word_end(rest);
+        let word = &rest[..end];
+        pairs.push(match word.split_once('=') {
+            Some((name, value)) => (name.to_string(), Some(value.to_string())),
+            None => (word.to_string(), None),
+        });
+        rest = rest[end..].trim_start();
+    }
+    pairs
+}
+
+/// Where the shell word starting `text` ends, past any quotes.
+fn word_end(text: &str) -> usize {
+    let mut quote = None;
+    let mut escaped = false;
+    for (pos, c) in text.char_indices() {
+        match quote {
+            _ if escaped => escaped = false,
+            _ if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c.is_whitespace() => return pos,
+            None => {}
+        }
+    }
+    text.len()
+}
+
+/// `COPY` or `ADD` of files in the build context as `file` shifts, and
+/// TODOs for the rest.
+fn copy(shell: &mut Importer, context: &Path, keyword: &str, args: &str) -> Vec<Step> {
+    let mut mode = None;
+    let mut owner = None;
+    let mut rest = args;
+    while let Some(flag) = rest.strip_prefix("--") {
+        let (flag, after) = flag.split_once(char::is_whitespace).unwrap_or((flag, ""));
+        match flag.split_once('=') {
+            Some(("chmod", value)) => mode = octal_mode(value),
+            Some(("chown", value)) => owner = value.split(':').next().map(String::from),
+            Some(("from", stage)) => {
+                return vec![Step::todo(format!(
+                    "copies from the build stage or image `{stage}`; install what it copies \
+                     by hand"
+                ))]
+            }
+            _ => {}
+        }
+        rest = after.trim_start();
+    }
+    let paths =
+        exec_form(rest).unwrap_or_else(|| rest.split_whitespace().map(String::from).collect());
+    let Some((dest, sources)) = paths.split_last() else {
+        return vec![Step::todo("copies nothing")];
+    };
+    let into_dir = dest.ends_with('/') || sources.len() > 1;
+    let dest = match dest.starts_with('/') || shell.cwd.is_some() {
+        true => shell.path(dest),
+        false => format!("/{dest}"),
+    };
+    let mut steps = Vec::new();
+    for source in sources {
+        if source.contains("://") {
+            let target = match into_dir {
+                true => {
+                    let name = source.rsplit('/').next().unwrap_or_default();
+                    format!("{}/{name}", dest.trim_end_matches('/'))
+                }
+                false => dest.clone(),
+            };
+            let script = format!("curl -fsSL -o \"{target}\" {source}");
+            shell.run(&script, None);
+            steps.extend(shell.imported.steps.pop());
+            continue;
+        }
+        if source.contains(['*', '?', '[', '$']) {
+            steps.push(Step::todo(format!(
+                "copies `{source}`, a pattern; add a `file` shift for each file it matches"
+            )));
+            continue;
+        }
+        let path = context.join(source);
+        if path.is_dir() {
+            steps.push(Step::todo(format!(
+                "copies the directory `{source}`; `skies capture {} --into {dest}` prints the \
+                 shifts recreating it",
+                path.display()
+            )));
+            continue;
+        }
+        let archive = [".tar", ".tgz", ".tar.gz", ".tar.xz", ".tar.bz2"];
+        if keyword == "ADD" && archive.iter().any(|ext| source.ends_with(ext)) {
+            steps.push(Step::todo(format!(
+                "unpacks the archive `{source}`; add a `cmd` shift running tar"
+            )));
+            continue;
+        }
+        let contents = fs::read(&path)
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok());
+        let Some(contents) = contents else {
+            steps.push(Step::todo(format!(
+                "copies `{source}`, which is missing or not text"
+            )));
+            continue;
+        };
+        let target = match into_dir {
+            true => {
+                let name = Path::new(source).file_name().unwrap_or_default();
+                format!("{}/{}", dest.trim_end_matches('/'), name.to_string_lossy())
+            }
+            false => dest.clone(),
+        };
+        let mut fields = toml::Table::new();
+        fields.insert("path".into(), target.into());
+        fields.insert("contents".into(), literal(&contents).into());
+        let executable = fs::metadata(&path)
+            .map(|meta| meta.permissions().mode() & 0o7777)
+            .ok()
+            .filter(|mode| mode & 0o111 != 0);
+        if let Some(mode) = mode.or(executable) {
+            fields.insert("mode".into(), i64::from(mode).into());
+        }
+        if let Some(owner) = owner.as_ref().filter(|owner| *owner != "root") {
+            fields.insert("run_as".into(), owner.clone().into());
+        }
+        steps.push(Step::shift("file", fields));
+    }
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use crate::context::ExecutionContext;
+    use crate::error::ShiftError;
+    use crate::plan_file;
+    use crate::registry::Registry;
+
+    const FIXTURES: &str = "tests/fixtures/import/dockerfile";
+
+    #[test]
+    fn dockerfile_converts_to_the_expected_plan() {
+        let imported = super::import(&Path::new(FIXTURES).join("Dockerfile")).unwrap();
+        let plan = imported.to_toml();
+        let expected = fs::read_to_string(Path::new(FIXTURES).join("expected.toml")).unwrap();
+        assert_eq!(plan, expected);
+        assert_eq!(imported.todo_count(), 6);
+
+        let mut ctx = ExecutionContext::new();
+        let parsed = plan_file::parse(&plan, &Registry::builtin(), &mut ctx).unwrap();
+        assert_eq!(parsed.entries().len(), imported.shift_count());
+        assert_eq!(ctx.interpolate("${APP_DIR}").unwrap(), "/opt/app");
+        // Preflight may find the machine lacks a package manager, but
+        // not a path the plan is refused.
+        if let Err(ShiftError::Preflight(problems)) = parsed.preflight(&ctx) {
+            for (id, err) in problems {
+                let err = err.to_string();
+                assert!(!err.contains("outside the plan root"), "{id}: {err}");
+            }
+        }
+    }
+}
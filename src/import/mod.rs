@@ -0,0 +1,318 @@
+//! Plans converted from other tools, for `skies import`, to start managing
+//! a machine with skies from what already sets it up.
+//!
+//! A converter turns each step of the source it understands into a shift,
+//! and leaves a `# TODO` comment with the original step for the rest, so
+//! nothing is silently dropped. Steps it converts only in part carry
+//! TODOs too. The result is a plan to review, not one to apply as it is;
+//! `skies lint` and `skies validate` are a good next step.
+
+pub mod ansible;
+pub mod brewfile;
+pub mod dockerfile;
+pub mod shell;
+
+use crate::expr;
+
+/// The first of the private-use characters [`split_plan_words`] masks
+/// with.
+const PLACEHOLDER: u32 = 0xE000;
+
+/// Fields written first, in this order, as people write shifts by hand;
+/// the rest follow alphabetically.
+const LEADING: &[&str] = &[
+    "type", "path", "program", "args", "repo", "target", "packages", "line",
+];
+
+/// Fields that hold a path the shift resolves against the plan root.
+const PATHS: &[&str] = &["path", "target", "cwd", "creates"];
+
+/// A plan converted from another tool.
+#[derive(Debug, Clone, Default)]
+pub struct Imported {
+    /// What it was converted from, for the header comment.
+    pub source: String,
+    /// The plan's `[vars]`.
+    pub vars: Vec<(String, String)>,
+    /// TODOs about the source as a whole.
+    pub notes: Vec<String>,
+    pub steps: Vec<Step>,
+}
+
+/// One step of the source, such as an Ansible task.
+#[derive(Debug, Clone, Default)]
+pub struct Step {
+    /// What the source calls it, if anything.
+    pub name: Option<String>,
+    /// The shift's fields, `type` included, ready to write out; `None`
+    /// for a step left to convert by hand.
+    pub shift: Option<toml::Table>,
+    /// What to check or finish by hand; for a step with no shift, why.
+    pub todos: Vec<String>,
+    /// The original step, quoted with its TODOs.
+    pub original: String,
+}
+
+impl Step {
+    /// A step converted to a shift of type `kind`.
+    pub fn shift(kind: &str, fields: toml::Table) -> Self {
+        let mut shift = toml::Table::new();
+        shift.insert("type".into(), kind.into());
+        shift.extend(fields);
+        Step {
+            shift: Some(shift),
+            ..Step::default()
+        }
+    }
+
+    /// A step that could not be converted, and why.
+    pub fn todo(reason: impl Into<String>) -> Self {
+        Step {
+            todos: vec![reason.into()],
+            ..Step::default()
+        }
+    }
+}
+
+impl Imported {
+    /// How many TODOs the plan has.
+    pub fn todo_count(&self) -> usize {
+        self.notes.len()
+            + self
+                .steps
+                .iter()
+                .map(|step| step.todos.len())
+                .sum::<usize>()
+    }
+
+    /// How many steps became shifts.
+    pub fn shift_count(&self) -> usize {
+        self.steps
+            .iter()
+            .filter(|step| step.shift.is_some())
+            .count()
+    }
+
+    /// Marks `allow_outside_root` on each shift with a path outside the
+    /// directory the plan is applied from, such as `/etc/hosts` or
+    /// `${facts.home}/.bashrc`: the tools converted from have no plan root,
+    /// so without it preflight refuses them.
+    pub fn allow_outside_root(&mut self) {
+        let vars = &self.vars;
+        for shift in self.steps.iter_mut().filter_map(|step| step.shift.as_mut()) {
+            let outside = PATHS
+                .iter()
+                .filter_map(|name| shift.get(*name)?.as_str())
+                .any(|path| outside_root(path, vars));
+            if outside {
+                shift.insert("allow_outside_root".into(), true.into());
+            }
+        }
+    }
+
+    /// The plan file, with a TODO comment wherever something needs a look.
+    pub fn to_toml(&self) -> String {
+        let mut out = format!("# Imported by `skies import` from {}.\n", self.source);
+        match self.todo_count() {
+            0 => {}
+            1 => out.push_str("# Review it before applying: 1 TODO below.\n"),
+            n => out.push_str(&format!("# Review it before applying: {n} TODOs below.\n")),
+        }
+        for note in &self.notes {
+            out.push_str(&format!("# TODO: {note}\n"));
+        }
+        if !self.vars.is_empty() {
+            out.push_str("\n[vars]\n");
+            for (name, value) in &self.vars {
+                out.push_str(&format!("{} = {}\n", key(name), string(value)));
+            }
+        }
+        for step in &self.steps {
+            out.push('\n');
+            if let Some(name) = &step.name {
+                out.push_str(&format!("# {}\n", one_line(name)));
+            }
+            for todo in &step.todos {
+                out.push_str(&format!("# TODO: {}\n", one_line(todo)));
+            }
+            if !step.todos.is_empty() || step.shift.is_none() {
+                for line in step.original.lines() {
+                    match line.trim().is_empty() {
+                        true => out.push_str("#\n"),
+                        false => out.push_str(&format!("#   {line}\n")),
+                    }
+                }
+            }
+            if let Some(shift) = &step.shift {
+                out.push_str("[[shift]]\n");
+                let mut keys: Vec<&str> = shift.keys().map(String::as_str).collect();
+                keys.sort_by_key(|name| {
+                    LEADING
+                        .iter()
+                        .position(|k| k == name)
+                        .unwrap_or(LEADING.len())
+                });
+                for name in keys {
+                    out.push_str(&format!("{} = {}\n", key(name), value(name, &shift[name])));
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Whether `path`, with the plan's `vars` filled in, may point outside the
+/// plan root: it is absolute, starts from home or from an expression such
+/// as `${facts.home}`, or climbs out with `..`.
+fn outside_root(path: &str, vars: &[(String, String)]) -> bool {
+    let mut path = path.to_string();
+    for (name, value) in vars {
+        path = path.replace(&format!("${{{name}}}"), value);
+    }
+    path.starts_with(['/', '~', '$']) || path.split('/').any(|part| part == "..")
+}
+
+/// `text` as it has to be written in a plan file to mean exactly `text`:
+/// with `$` doubled, so it is not taken for a variable.
+pub fn literal(text: &str) -> String {
+    text.replace('$', "$$")
+}
+
+/// Splits a command line into words the way a POSIX shell would for a
+/// simple command, or `None` if it uses anything more: pipes, redirects,
+/// substitutions, globs, variables or several commands.
+pub fn split_words(line: &str) -> Option<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = line.trim().chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' => match chars.next()? {
+                            c @ ('"' | '\\' | '$' | '`') => word.push(c),
+                            c => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                        },
+                        '$' | '`' => return None,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                word.push(chars.next()?);
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            '|' | '&' | ';' | '<' | '>' | '(' | ')' | '$' | '`' | '*' | '?' | '[' | '#' => {
+                return None;
+            }
+            '~' if !in_word => return None,
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    Some(words)
+}
+
+/// Splits plan file text, with `${...}` and `$$` in it, into words the
+/// way [`split_words`] does, keeping those intact; `None` if it needs a
+/// shell.
+pub fn split_plan_words(text: &str) -> Option<Vec<String>> {
+    // They stand in as private-use characters while splitting, since a
+    // bare `$` means the line needs a shell.
+    let mut pieces = Vec::new();
+    let mut masked = String::new();
+    let mut rest = text;
+    while let Some(pos) = rest.find('$') {
+        masked.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+        let len = match after.strip_prefix('{').map(expr::closing_brace) {
+            Some(Some(close)) => close + 3,
+            _ => 2.min(rest.len() - pos),
+        };
+        pieces.push(rest[pos..pos + len].to_string());
+        masked.push(char::from_u32(PLACEHOLDER + pieces.len() as u32 - 1)?);
+        rest = &rest[pos + len..];
+    }
+    masked.push_str(rest);
+    let words = split_words(&masked)?;
+    let unmask = |c: char| match (c as u32).checked_sub(PLACEHOLDER) {
+        Some(idx) if (idx as usize) < pieces.len() => pieces[idx as usize].clone(),
+        _ => c.to_string(),
+    };
+    Some(
+        words
+            .into_iter()
+            .map(|word| word.chars().map(unmask).collect())
+            .collect(),
+    )
+}
+
+/// A file mode written the ways other tools take one, `0644`, `"0o644"`
+/// or `644`, all meaning octal.
+pub fn octal_mode(text: &str) -> Option<u32> {
+    let text = text.trim();
+    let digits = text.strip_prefix("0o").unwrap_or(text);
+    if digits.is_empty() || !digits.bytes().all(|b| (b'0'..=b'7').contains(&b)) {
+        return None;
+    }
+    let digits = digits.trim_start_matches('0');
+    match digits.len() {
+        0 => Some(0),
+        1..=4 => u32::from_str_radix(digits, 8).ok(),
+        _ => None,
+    }
+}
+
+fn one_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn key(name: &str) -> String {
+    let bare = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    match bare {
+        true => name.to_string(),
+        false => string(name),
+    }
+}
+
+fn string(text: &str) -> String {
+    toml::Value::String(text.to_string()).to_string()
+}
+
+/// A field's value, with modes in octal as people write them.
+fn value(name: &str, value: &toml::Value) -> String {
+    match value {
+        toml::Value::Integer(mode) if name == "mode" => format!("0o{mode:o}"),
+        value => value.to_string(),
+    }
+}
@@ -0,0 +1,1003 @@
+//! Shell setup scripts converted to plans, heuristically.
+//!
+//! Simple commands that `mkdir`, `cp`, `ln -s`, `git clone`, download
+//! with `curl` or `wget`, install packages, or write a file with `echo`,
+//! `printf` or a heredoc become the shifts that do the same; `cd` sets
+//! the `cwd` of what follows and `chmod` the mode of the file just
+//! written. Everything else becomes a `cmd` shift, run through `sh -c`
+//! when it needs a shell, and loops and conditionals are kept whole that
+//! way; functions, and their calls, are left as TODOs.
+//!
+//! Variables the script sets become the plan's `[vars]`, `$HOME` and `~`
+//! the `home` fact, and other variables `${env.NAME}`.
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use regex::Regex;
+
+use super::{literal, octal_mode, split_plan_words, Imported, Step};
+use crate::error::{ShiftError, ShiftResult};
+
+/// Words that open a compound command, kept whole.
+const OPENERS: &[&str] = &["if", "for", "while", "until", "case", "function", "{", "("];
+
+/// Shift types that can run as another user.
+const RUN_AS: &[&str] = &["cmd", "file", "create_dir"];
+
+/// Package managers' install commands, and the options they take that are
+/// not package names.
+const INSTALLERS: &[(&str, &[&str], &[&str])] = &[
+    (
+        "apt-get",
+        &["install"],
+        &["-y", "--yes", "-q", "-qq", "--no-install-recommends"],
+    ),
+    (
+        "apt",
+        &["install"],
+        &["-y", "--yes", "-q", "--no-install-recommends"],
+    ),
+    ("dnf", &["install"], &["-y", "-q"]),
+    ("yum", &["install"], &["-y", "-q"]),
+    (
+        "pacman",
+        &["-S", "-Sy", "-Syu"],
+        &["--noconfirm", "--needed"],
+    ),
+    (
+        "zypper",
+        &["install", "in"],
+        &["-y", "-n", "--non-interactive"],
+    ),
+    ("apk", &["add"], &["--no-cache", "-q"]),
+];
+
+/// Converts the script at `path`.
+pub fn import(path: &Path) -> ShiftResult<Imported> {
+    let script = fs::read_to_string(path)
+        .map_err(|err| ShiftError::Custom(format!("cannot read {}: {err}", path.display())))?;
+    let mut importer = Importer::new(path);
+    importer.run(&script, None);
+    let mut imported = importer.finish();
+    imported.allow_outside_root();
+    Ok(imported)
+}
+
+/// One command of the script, its continuation lines joined.
+struct Command {
+    text: String,
+    /// A loop, conditional, function or the like, kept whole.
+    compound: bool,
+    heredoc: Option<Heredoc>,
+    /// The comment just before it.
+    comment: Option<String>,
+    original: String,
+}
+
+struct Heredoc {
+    body: String,
+    /// Whether the delimiter was quoted, leaving the body unexpanded.
+    quoted: bool,
+}
+
+/// How [`Importer::expand`] takes `$`.
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    /// As the shell would, for a command skies runs itself.
+    Words,
+    /// Only the script's own variables, for text still run by a shell.
+    Script,
+    /// As in a heredoc body, where quotes are text.
+    Text,
+}
+
+/// Shell commands converted to steps, for a script or for the commands
+/// another format runs, like a Dockerfile's `RUN`.
+pub(super) struct Importer {
+    pub(super) imported: Imported,
+    /// Where the script is, for the files it copies.
+    dir: std::path::PathBuf,
+    /// Where the last `cd` went.
+    pub(super) cwd: Option<String>,
+    /// Who commands run as, if not the plan's user.
+    pub(super) user: Option<String>,
+    /// Commands left out, by how they start.
+    pub(super) ignored: &'static [&'static str],
+    functions: Vec<String>,
+    exported: Vec<String>,
+    sudo: bool,
+    /// Whether a `cmd` shift without `creates` was made.
+    repeated: bool,
+}
+
+impl Importer {
+    /// An importer for commands from the file at `path`.
+    pub(super) fn new(path: &Path) -> Self {
+        Importer {
+            imported: Imported {
+                source: path.display().to_string(),
+                ..Imported::default()
+            },
+            dir: path.parent().unwrap_or(Path::new("")).to_path_buf(),
+            cwd: None,
+            user: None,
+            ignored: &[],
+            functions: Vec::new(),
+            exported: Vec::new(),
+            sudo: false,
+            repeated: false,
+        }
+    }
+
+    /// Converts the commands in `script`; quoting `original` with their
+    /// TODOs, if given, rather than their own lines.
+    pub(super) fn run(&mut self, script: &str, original: Option<&str>) {
+        for mut command in commands(script) {
+            if let Some(original) = original {
+                command.original = original.to_string();
+            }
+            self.command(command);
+        }
+    }
+
+    /// The plan, with notes on what applies to all of it.
+    pub(super) fn finish(mut self) -> Imported {
+        let notes = &mut self.imported.notes;
+        if self.sudo {
+            notes.push("the script uses sudo; apply the plan as root, such as with sudo".into());
+        }
+        if !self.exported.is_empty() {
+            notes.push(format!(
+                "exported variables ({}) are not in commands' environment; give the commands \
+                 that read them an `env`",
+                self.exported.join(", ")
+            ));
+        }
+        if self.repeated {
+            notes.push(
+                "commands kept as `cmd` shifts run on every apply; give them a `creates` path \
+                 to run once"
+                    .into(),
+            );
+        }
+        self.imported
+    }
+
+    fn command(&mut self, command: Command) {
+        if command.compound {
+            let function = Regex::new(r"^(?:function\s+)?([\w-]+)\s*\(\s*\)")
+                .expect("the function pattern is valid");
+            let step = match function.captures(&command.text) {
+                Some(caps) => {
+                    self.functions.push(caps[1].to_string());
+                    Step::todo(format!(
+                        "defines the function `{}`; plans have none, so its calls below are \
+                         left to convert by hand",
+                        &caps[1]
+                    ))
+                }
+                None => self.script(&command.text),
+            };
+            self.push(&command, step);
+            return;
+        }
+        let masked = mask_quotes(&command.text);
+        let mut start = 0;
+        let mut parts = Vec::new();
+        for (pos, _) in masked.match_indices([';', '&']) {
+            if masked[pos..].starts_with("&&") {
+                parts.push(&command.text[start..pos]);
+                start = pos + 2;
+            } else if masked[pos..].starts_with(';') {
+                parts.push(&command.text[start..pos]);
+                start = pos + 1;
+            }
+        }
+        parts.push(&command.text[start..]);
+        // Only the part with the `<<` gets the heredoc.
+        for part in parts.into_iter().map(str::trim).filter(|p| !p.is_empty()) {
+            let heredoc = command.heredoc.as_ref().filter(|_| part.contains("<<"));
+            if let Some(step) = self.line(part, heredoc) {
+                self.push(&command, step);
+            }
+        }
+    }
+
+    fn push(&mut self, command: &Command, mut step: Step) {
+        let shift = step.shift.as_mut().filter(|shift| {
+            let kind = shift["type"].as_str().unwrap_or_default();
+            RUN_AS.contains(&kind) && !shift.contains_key("run_as")
+        });
+        if let (Some(user), Some(shift)) = (&self.user, shift) {
+            shift.insert("run_as".into(), user.clone().into());
+        }
+        step.name = command.comment.clone();
+        step.original = command.original.clone();
+        self.imported.steps.push(step);
+    }
+
+    /// Converts one simple command; `None` for one that only changes the
+    /// script's state, like `cd`.
+    fn line(&mut self, line: &str, heredoc: Option<&Heredoc>) -> Option<Step> {
+        let assignment = Regex::new(r"^(export\s+)?([A-Za-z_]\w*)=(\S*)$")
+            .expect("the assignment pattern is valid");
+        let masked = mask_quotes(line);
+        if let Some(caps) = assignment.captures(&masked) {
+            let value = &line[caps.get(3)?.range()];
+            return self.assign(&caps[2], value, caps.get(1).is_some());
+        }
+        let mut words = masked.split_whitespace();
+        match words.next() {
+            Some("set" | "shopt" | "true" | ":") => return None,
+            Some("export") => {
+                self.exported.extend(words.map(String::from));
+                return None;
+            }
+            Some("cd") => return self.cd(line),
+            _ => {}
+        }
+
+        let sudo =
+            Regex::new(r"^sudo((?:\s+-[A-Za-z]+)*?)(?:\s+-u\s+(\S+))?((?:\s+-[A-Za-z]+)*)\s+")
+                .expect("the sudo pattern is valid");
+        let mut run_as = None;
+        let mut line = line;
+        if let Some(caps) = sudo.captures(line) {
+            self.sudo = true;
+            run_as = caps.get(2).map(|user| user.as_str().to_string());
+            line = &line[caps[0].len()..];
+        }
+        if self.chmod(line) || self.ignored.iter().any(|ignored| line.starts_with(ignored)) {
+            return None;
+        }
+        let mut todos = Vec::new();
+        let mut step = match self
+            .write(line, heredoc, &mut todos)
+            .or_else(|| self.structured(line, &mut todos))
+        {
+            Some(step) => step,
+            None => {
+                todos.clear();
+                self.script(line)
+            }
+        };
+        if let Some(user) = run_as {
+            let shift = step.shift.as_mut().filter(|shift| {
+                let kind = shift["type"].as_str().unwrap_or_default();
+                RUN_AS.contains(&kind)
+            });
+            match shift {
+                Some(shift) => {
+                    shift.insert("run_as".into(), user.into());
+                }
+                None => todos.push(format!("ran as `{user}`")),
+            }
+        }
+        for todo in todos {
+            if !step.todos.contains(&todo) {
+                step.todos.push(todo);
+            }
+        }
+        Some(step)
+    }
+
+    /// Sets a variable to `value`, shell text, as a plan var; a TODO step
+    /// if it cannot be one.
+    pub(super) fn assign(&mut self, name: &str, value: &str, export: bool) -> Option<Step> {
+        if export {
+            self.exported.push(name.to_string());
+        }
+        if self.imported.vars.iter().any(|(var, _)| var == name) {
+            return Some(Step::todo(format!(
+                "sets `{name}` again; the plan keeps the first value, so check what uses it"
+            )));
+        }
+        let mut todos = Vec::new();
+        let words = self
+            .expand(value, Mode::Words, &mut todos)
+            .and_then(|value| split_plan_words(&value));
+        let mut value = match words.as_deref() {
+            Some([]) => String::new(),
+            Some([value]) => value.clone(),
+            _ => {
+                return Some(Step::todo(format!(
+                    "sets `{name}` from a command; add it to `[vars]` by hand"
+                )))
+            }
+        };
+        // A plan sets its vars in name order, not the order written, so
+        // earlier ones are filled in.
+        for (earlier, earlier_value) in &self.imported.vars {
+            value = value.replace(&format!("${{{earlier}}}"), earlier_value);
+        }
+        self.imported.vars.push((name.to_string(), value));
+        self.imported.notes.extend(todos);
+        None
+    }
+
+    fn cd(&mut self, line: &str) -> Option<Step> {
+        let mut todos = Vec::new();
+        let words = self
+            .expand(line, Mode::Words, &mut todos)
+            .and_then(|line| split_plan_words(&line));
+        match words.as_deref() {
+            Some([_]) => self.cwd = Some("${facts.home}".into()),
+            Some([_, dir]) if dir != "-" => self.cwd = Some(self.path(dir)),
+            _ => {
+                return Some(Step::todo(
+                    "changes directory in a way not followed; check the `cwd` of what follows",
+                ))
+            }
+        }
+        None
+    }
+
+    /// A path as the script meant it, after its `cd`s.
+    pub(super) fn path(&self, path: &str) -> String {
+        match &self.cwd {
+            Some(cwd) if !path.starts_with('/') && !path.starts_with("${") => {
+                format!("{cwd}/{}", path.trim_start_matches("./"))
+            }
+            _ => path.to_string(),
+        }
+    }
+
+    /// `echo`, `printf` or `cat` with a heredoc writing a file, with `>`,
+    /// `>>` or `tee`.
+    fn write(
+        &mut self,
+        line: &str,
+        heredoc: Option<&Heredoc>,
+        todos: &mut Vec<String>,
+    ) -> Option<Step> {
+        let tee = Regex::new(
+            r"^(?:(.*?)\|\s*)?(?:sudo\s+(?:-\S+\s+)*)?tee(\s+-a)?\s+(\S+)(?:\s*>\s*/dev/null)?\s*$",
+        )
+        .expect("the tee pattern is valid");
+        let redirect =
+            Regex::new(r"^(.*?[^\d&])(>>?)\s*(\S+)\s*$").expect("the redirect pattern is valid");
+        // The heredoc's `<<EOF` is in the way of the redirect after it.
+        let line = match heredoc {
+            Some(_) => heredoc_operator().replace(line, "").trim().to_string(),
+            None => line.to_string(),
+        };
+        let line = line.as_str();
+        let masked = mask_quotes(line);
+        let (producer, append, file) = match (tee.captures(&masked), redirect.captures(&masked)) {
+            (Some(caps), _) => (
+                caps.get(1).map_or(0..0, |producer| producer.range()),
+                caps.get(2).is_some(),
+                caps.get(3)?,
+            ),
+            (None, Some(caps)) => (caps.get(1)?.range(), &caps[2] == ">>", caps.get(3)?),
+            (None, None) => return None,
+        };
+        let path = self
+            .expand(&line[file.range()], Mode::Words, todos)
+            .and_then(|file| split_plan_words(&file))
+            .and_then(|words| <[String; 1]>::try_from(words).ok())?;
+        let [path] = path;
+        let producer = line[producer].trim();
+        let contents = self.contents(producer, heredoc, todos)?;
+        let path = self.path(&path);
+        let mut fields = toml::Table::new();
+        fields.insert("path".into(), path.into());
+        if !append {
+            fields.insert("contents".into(), contents.into());
+            return Some(Step::shift("file", fields));
+        }
+        let line = contents.strip_suffix('\n')?;
+        if line.contains('\n') {
+            return None;
+        }
+        fields.insert("line".into(), line.into());
+        fields.insert("create".into(), true.into());
+        Some(Step::shift("line_in_file", fields))
+    }
+
+    /// What `echo`, `printf` or `cat <<EOF` prints, as plan file text.
+    fn contents(
+        &mut self,
+        producer: &str,
+        heredoc: Option<&Heredoc>,
+        todos: &mut Vec<String>,
+    ) -> Option<String> {
+        if let Some(heredoc) = heredoc {
+            if !matches!(producer, "" | "cat") {
+                return None;
+            }
+            return match heredoc.quoted {
+                true => Some(literal(&heredoc.body)),
+                false => self.expand(&heredoc.body, Mode::Text, todos),
+            };
+        }
+        let words = self
+            .expand(producer, Mode::Words, todos)
+            .and_then(|producer| split_plan_words(&producer))?;
+        match words.split_first()? {
+            (program, args) if program == "echo" => {
+                let (newline, args) = match args.split_first() {
+                    Some((flag, args)) if flag == "-n" => ("", args),
+                    _ => ("\n", args),
+                };
+                if args
+                    .iter()
+                    .any(|arg| arg.contains('\\') || arg.starts_with('-'))
+                {
+                    return None;
+                }
+                Some(format!("{}{newline}", args.join(" ")))
+            }
+            (program, [format]) if program == "printf" && !format.contains('%') => {
+                let text = format.replace("\\n", "\n").replace("\\t", "\t");
+                (!text.contains('\\')).then_some(text)
+            }
+            _ => None,
+        }
+    }
+
+    /// A command split into words, as the shift that does the same or a
+    /// `cmd` shift running it; `None` if it needs a shell.
+    fn structured(&mut self, line: &str, todos: &mut Vec<String>) -> Option<Step> {
+        let words = self
+            .expand(line, Mode::Words, todos)
+            .and_then(|line| split_plan_words(&line))?;
+        let (program, args) = words.split_first()?;
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        if self.functions.contains(program) {
+            return Some(Step::todo(format!(
+                "calls the script's function `{program}`; add the shifts doing what it does"
+            )));
+        }
+        let converted = match program.as_str() {
+            "mkdir" => self.mkdir(&args),
+            "cp" => self.cp(&args),
+            "ln" => self.ln(&args),
+            "git" => self.git_clone(&args, todos),
+            "curl" | "wget" => self.download(program, &args),
+            "touch" if args.len() == 1 && !args[0].starts_with('-') => {
+                let mut fields = self.cmd(&words);
+                fields.insert("creates".into(), self.path(args[0]).into());
+                Some(Step::shift("cmd", fields))
+            }
+            program => INSTALLERS
+                .iter()
+                .find(|(installer, ..)| *installer == program)
+                .and_then(|(_, commands, options)| install(&args, commands, options, todos)),
+        };
+        converted.or_else(|| {
+            self.repeated = true;
+            let mut fields = self.cmd(&words);
+            if ["curl", "wget", "git"].contains(&program.as_str()) {
+                fields.insert("network".into(), true.into());
+            }
+            Some(Step::shift("cmd", fields))
+        })
+    }
+
+    fn mkdir(&mut self, args: &[&str]) -> Option<Step> {
+        let mut mode = None;
+        let mut dirs = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match *arg {
+                "-p" | "--parents" | "-v" => {}
+                "-m" => mode = Some(octal_mode(args.next()?)?),
+                arg if arg.starts_with("--mode=") => mode = Some(octal_mode(&arg[7..])?),
+                arg if arg.starts_with('-') => return None,
+                dir => dirs.push(dir),
+            }
+        }
+        let (last, rest) = dirs.split_last()?;
+        for dir in rest {
+            let step = Step::shift("create_dir", self.dir_fields(dir, mode));
+            self.imported.steps.push(step);
+        }
+        Some(Step::shift("create_dir", self.dir_fields(last, mode)))
+    }
+
+    fn dir_fields(&self, dir: &str, mode: Option<u32>) -> toml::Table {
+        let mut fields = toml::Table::new();
+        fields.insert("path".into(), self.path(dir).into());
+        if let Some(mode) = mode {
+            fields.insert("mode".into(), i64::from(mode).into());
+        }
+        fields
+    }
+
+    /// `cp` of one file next to the script, as a `file` shift with its
+    /// contents.
+    fn cp(&mut self, args: &[&str]) -> Option<Step> {
+        let paths: Vec<&str> = args
+            .iter()
+            .copied()
+            .filter(|arg| !matches!(*arg, "-f" | "-p" | "-v" | "--"))
+            .collect();
+        let [src, dest] = paths[..] else { return None };
+        if src.starts_with('-') || src.contains('$') {
+            return None;
+        }
+        let source = match &self.cwd {
+            Some(cwd) if !cwd.contains('$') => self.dir.join(cwd).join(src),
+            _ => self.dir.join(src),
+        };
+        let contents = String::from_utf8(fs::read(&source).ok()?).ok()?;
+        let created_dir = self.imported.steps.iter().any(|step| {
+            step.shift.as_ref().is_some_and(|shift| {
+                shift["type"].as_str() == Some("create_dir")
+                    && shift["path"].as_str() == Some(&self.path(dest))
+            })
+        });
+        let mut dest = self.path(dest);
+        if dest.ends_with('/') || created_dir {
+            let name = Path::new(src).file_name()?.to_string_lossy();
+            dest = format!("{}/{name}", dest.trim_end_matches('/'));
+        }
+        let mut fields = toml::Table::new();
+        fields.insert("path".into(), dest.into());
+        fields.insert("contents".into(), literal(&contents).into());
+        let mode = fs::metadata(&source).ok()?.permissions().mode() & 0o7777;
+        if mode & 0o111 != 0 {
+            fields.insert("mode".into(), i64::from(mode).into());
+        }
+        Some(Step::shift("file", fields))
+    }
+
+    fn ln(&mut self, args: &[&str]) -> Option<Step> {
+        let flags: Vec<&str> = args
+            .iter()
+            .copied()
+            .filter(|a| a.starts_with('-'))
+            .collect();
+        let paths: Vec<&str> = args
+            .iter()
+            .copied()
+            .filter(|a| !a.starts_with('-'))
+            .collect();
+        let symbolic = flags.iter().any(|flag| {
+            flag.starts_with("--symbolic") || (!flag.starts_with("--") && flag.contains('s'))
+        });
+        let known = flags.iter().all(|flag| {
+            flag.trim_start_matches('-')
+                .chars()
+                .all(|c| "sfnv".contains(c))
+        });
+        if !symbolic || !known {
+            return None;
+        }
+        let (target, link) = match paths[..] {
+            [target, link] => (target, self.path(link)),
+            [target] => (target, self.path(Path::new(target).file_name()?.to_str()?)),
+            _ => return None,
+        };
+        let mut fields = toml::Table::new();
+        fields.insert("path".into(), link.into());
+        fields.insert("target".into(), target.into());
+        Some(Step::shift("symlink", fields))
+    }
+
+    fn git_clone(&mut self, args: &[&str], todos: &mut Vec<String>) -> Option<Step> {
+        let ("clone", args) = args.split_first().map(|(c, rest)| (*c, rest))? else {
+            return None;
+        };
+        let mut branch = None;
+        let mut paths = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match *arg {
+                "-b" | "--branch" => branch = Some(args.next()?.to_string()),
+                "--depth" => {
+                    args.next()?;
+                }
+                "-q" | "--quiet" | "--single-branch" => {}
+                "--recursive" | "--recurse-submodules" => {
+                    todos.push("submodules are not cloned".into());
+                }
+                arg if arg.starts_with("--branch=") => branch = Some(arg[9..].to_string()),
+                arg if arg.starts_with("--depth=") => {}
+                arg if arg.starts_with('-') => return None,
+                path => paths.push(path),
+            }
+        }
+        let (repo, target) = match paths[..] {
+            [repo, target] => (repo, self.path(target)),
+            [repo] => {
+                let name = repo.trim_end_matches('/').rsplit(['/', ':']).next()?;
+                (repo, self.path(name.trim_end_matches(".git")))
+            }
+            _ => return None,
+        };
+        let mut fields = toml::Table::new();
+        fields.insert("repo".into(), repo.into());
+        fields.insert("target".into(), target.into());
+        if let Some(branch) = branch {
+            fields.insert("branch".into(), branch.into());
+        }
+        Some(Step::shift("github_clone", fields))
+    }
+
+    /// A download to a file, as a command that runs until the file exists.
+    fn download(&mut self, program: &str, args: &[&str]) -> Option<Step> {
+        let url = args.iter().find(|arg| arg.contains("://"))?;
+        let output = ["-o", "--output", "-O", "--output-document"];
+        let named = args
+            .iter()
+            .position(|arg| output.contains(arg))
+            .filter(|_| !(program == "curl" && args.contains(&"-O")));
+        let file = match named {
+            Some(pos) => args.get(pos + 1)?.to_string(),
+            None if program == "wget" || args.contains(&"-O") => url
+                .rsplit('/')
+                .next()
+                .filter(|name| !name.is_empty())?
+                .to_string(),
+            None => return None,
+        };
+        let file = self.path(&file);
+        let words: Vec<String> = std::iter::once(program)
+            .chain(args.iter().copied())
+            .map(String::from)
+            .collect();
+        let mut fields = self.cmd(&words);
+        fields.insert("creates".into(), file.clone().into());
+        fields.insert("network".into(), true.into());
+        fields.insert("undo".into(), vec!["rm".into(), "-f".into(), file].into());
+        Some(Step::shift("cmd", fields))
+    }
+
+    /// Sets the mode of the file or directory the last shift made, for a
+    /// `chmod` of it; whether it was one.
+    fn chmod(&mut self, line: &str) -> bool {
+        let words = self
+            .expand(line, Mode::Words, &mut Vec::new())
+            .and_then(|line| split_plan_words(&line));
+        let Some([program, mode, path]) = words.as_deref() else {
+            return false;
+        };
+        let mode = match mode.as_str() {
+            "+x" | "a+x" | "u+x" => Some(0o755),
+            mode => octal_mode(mode),
+        };
+        let path = self.path(path);
+        let shift = self
+            .imported
+            .steps
+            .last_mut()
+            .and_then(|step| step.shift.as_mut());
+        let (Some(mode), Some(shift)) = (mode.filter(|_| program == "chmod"), shift) else {
+            return false;
+        };
+        let kind = shift["type"].as_str().unwrap_or_default();
+        if !["file", "create_dir"].contains(&kind) || shift["path"].as_str() != Some(&path) {
+            return false;
+        }
+        shift.insert("mode".into(), i64::from(mode).into());
+        true
+    }
+
+    /// A `cmd` shift's fields for `words`, in the current directory.
+    fn cmd(&self, words: &[String]) -> toml::Table {
+        let mut fields = toml::Table::new();
+        if let Some((program, args)) = words.split_first() {
+            fields.insert("program".into(), program.clone().into());
+            if !args.is_empty() {
+                fields.insert("args".into(), args.to_vec().into());
+            }
+        }
+        if let Some(cwd) = &self.cwd {
+            fields.insert("cwd".into(), cwd.clone().into());
+        }
+        fields
+    }
+
+    /// `text` run by `sh -c`, as a `cmd` shift.
+    fn script(&mut self, text: &str) -> Step {
+        let script = self
+            .expand(text, Mode::Script, &mut Vec::new())
+            .unwrap_or_else(|| literal(text));
+        let mut fields = self.cmd(&["sh".into(), "-c".into(), script]);
+        let masked = mask_quotes(text);
+        if masked.contains("curl ") || masked.contains("wget ") {
+            fields.insert("network".into(), true.into());
+        }
+        self.repeated = true;
+        Step::shift("cmd", fields)
+    }
+
+    /// `text` as plan file text, its `$` references converted as `mode`
+    /// says; `None` if it uses what skies has nothing like, such as `$1`
+    /// or `$(...)`.
+    fn expand(&self, text: &str, mode: Mode, todos: &mut Vec<String>) -> Option<String> {
+        let mut out = String::new();
+        let mut chars = text.chars().peekable();
+        let mut single = false;
+        let mut double = false;
+        let mut word_start = true;
+        while let Some(c) = chars.next() {
+            let quotes = mode != Mode::Text;
+            match c {
+                '\'' if quotes && !double => single = !single,
+                '"' if quotes && !single => double = !double,
+                '`' if mode == Mode::Text => return None,
+                '\\' if !single => {
+                    // A quoted `$` becomes `$$`, quoted enough; otherwise
+                    // the backslash is left for the shell, or the
+                    // splitting after, to take out, except in a heredoc.
+                    let kept = match chars.peek() {
+                        Some('$') => mode == Mode::Script,
+                        Some('`' | '\\') => mode != Mode::Text,
+                        _ => true,
+                    };
+                    if kept {
+                        out.push(c);
+                    }
+                    match chars.next() {
+                        Some('$') => out.push_str("$$"),
+                        Some(next) => out.push(next),
+                        None => {}
+                    }
+                    word_start = false;
+                    continue;
+                }
+                '~' if quotes && !single && !double && word_start && mode == Mode::Words => {
+                    if matches!(chars.peek(), None | Some('/' | ' ')) {
+                        out.push_str("${facts.home}");
+                        word_start = false;
+                        continue;
+                    }
+                }
+                '$' if !single => {
+                    let braced = chars.peek() == Some(&'{');
+                    let name: String = {
+                        let mut lookahead = chars.clone();
+                        if braced {
+                            lookahead.next();
+                        }
+                        lookahead
+                            .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+                            .collect()
+                    };
+                    let closes = !braced || {
+                        let mut lookahead = chars.clone().skip(name.len() + 1);
+                        lookahead.next() == Some('}')
+                    };
+                    let is_name = name
+                        .chars()
+                        .next()
+                        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+                    let known = self.imported.vars.iter().any(|(var, _)| *var == name);
+                    let converted = match (is_name && closes, mode) {
+                        (true, _) if known => format!("${{{name}}}"),
+                        (_, Mode::Script) => {
+                            out.push_str("$$");
+                            continue;
+                        }
+                        (false, _) => return None,
+                        (true, _) => match name.as_str() {
+                            "HOME" => "${facts.home}".to_string(),
+                            "USER" => "${facts.user}".to_string(),
+                            _ => {
+                                todos.push(format!(
+                                    "takes `${name}` from the environment `skies apply` runs in"
+                                ));
+                                format!("${{env.{name}}}")
+                            }
+                        },
+                    };
+                    out.push_str(&converted);
+                    let skip = name.len() + if braced { 2 } else { 0 };
+                    for _ in 0..skip {
+                        chars.next();
+                    }
+                    word_start = false;
+                    continue;
+                }
+                '$' => {
+                    out.push_str("$$");
+                    continue;
+                }
+                _ => {}
+            }
+            word_start = c.is_whitespace() && !single && !double;
+            out.push(c);
+        }
+        Some(out)
+    }
+}
+
+/// A package manager's arguments as a `package` shift, if they install
+/// packages with no options it does not know.
+fn install(
+    args: &[&str],
+    commands: &[&str],
+    options: &[&str],
+    todos: &mut Vec<String>,
+) -> Option<Step> {
+    let mut args = args.iter().filter(|arg| !options.contains(arg));
+    if !commands.contains(args.next()?) {
+        return None;
+    }
+    let mut packages = Vec::new();
+    for arg in args {
+        match *arg {
+            arg if arg.starts_with('-') => return None,
+            package => packages.push(package.to_string()),
+        }
+    }
+    if packages.is_empty() {
+        return None;
+    }
+    if packages.iter().any(|package| package.contains('$')) {
+        todos.push("names packages with a variable; check the plan's value for it".into());
+    }
+    let mut fields = toml::Table::new();
+    fields.insert("packages".into(), packages.into());
+    Some(Step::shift("package", fields))
+}
+
+/// The script's commands: continuation lines joined, compound commands
+/// and heredocs gathered, and comments kept as names.
+fn commands(script: &str) -> Vec<Command> {
+    let heredoc = heredoc_operator();
+    let mut commands = Vec::new();
+    let mut comment: Option<String> = None;
+    let mut lines = script.lines().enumerate();
+    while let Some((number, line)) = lines.next() {
+        let trimmed = line.trim();
+        if number == 0 && trimmed.starts_with("#!") {
+            continue;
+        }
+        if trimmed.is_empty() {
+            comment = None;
+            continue;
+        }
+        if let Some(text) = trimmed.strip_prefix('#') {
+            let text = text.trim();
+            comment = Some(match comment.take() {
+                Some(earlier) => format!("{earlier} {text}"),
+                None => text.to_string(),
+            });
+            continue;
+        }
+        let mut original = vec![line];
+        let mut text = strip_comment(trimmed);
+        while text.ends_with('\\') {
+            text.pop();
+            let Some((_, next)) = lines.next() else { break };
+            original.push(next);
+            text.push(' ');
+            text.push_str(&strip_comment(next.trim()));
+        }
+        let masked = mask_quotes(&text);
+        let first = masked.split_whitespace().next().unwrap_or_default();
+        let compound = OPENERS.contains(&first) || first.starts_with('(') || masked.contains("()");
+        let mut depth = nesting(&masked);
+        while depth > 0 {
+            let Some((_, next)) = lines.next() else { break };
+            original.push(next);
+            text.push('\n');
+            text.push_str(next);
+            depth += nesting(&mask_quotes(next));
+        }
+        let mut body = None;
+        if let Some(caps) = heredoc.captures(&text).filter(|_| !compound) {
+            let dash = !caps[1].is_empty();
+            let delimiter = caps[3].to_string();
+            let mut lines_of_body = Vec::new();
+            for (_, next) in lines.by_ref() {
+                original.push(next);
+                let end = if dash {
+                    next.trim_start_matches('\t')
+                } else {
+                    next
+                };
+                if end == delimiter {
+                    break;
+                }
+                lines_of_body.push(format!("{end}\n"));
+            }
+            body = Some(Heredoc {
+                body: lines_of_body.concat(),
+                quoted: !caps[2].is_empty(),
+            });
+        }
+        commands.push(Command {
+            text,
+            compound,
+            heredoc: body,
+            comment: comment.take(),
+            original: original.join("\n"),
+        });
+    }
+    commands
+}
+
+/// `<<EOF`, `<<-EOF` or `<<'EOF'`.
+fn heredoc_operator() -> Regex {
+    Regex::new(r#"<<(-?)\s*(['"]?)([\w.-]+)['"]?"#).expect("the heredoc pattern is valid")
+}
+
+/// How many compound commands `line` opens, less those it closes.
+fn nesting(masked: &str) -> i32 {
+    masked
+        .split(|c: char| c.is_whitespace() || c == ';' || c == '&' || c == '|')
+        .map(|word| match word {
+            "if" | "case" | "for" | "while" | "until" | "{" => 1,
+            "fi" | "esac" | "done" | "}" => -1,
+            _ => 0,
+        })
+        .sum()
+}
+
+/// `line` with what is inside quotes replaced by `_`, byte for byte, to
+/// find its operators.
+fn mask_quotes(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut quote = None;
+    let mut escaped = false;
+    for c in line.chars() {
+        let inside = quote.is_some();
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quote != Some('\'') => escaped = true,
+            '\'' | '"' if quote == Some(c) => quote = None,
+            '\'' | '"' if quote.is_none() => quote = Some(c),
+            _ => {}
+        }
+        match inside && quote.is_some() {
+            true => out.extend(std::iter::repeat_n('_', c.len_utf8())),
+            false => out.push(c),
+        }
+    }
+    out
+}
+
+/// `line` without a trailing `# comment`.
+fn strip_comment(line: &str) -> String {
+    let masked = mask_quotes(line);
+    let cut = masked
+        .char_indices()
+        .find(|&(pos, c)| c == '#' && (pos == 0 || masked[..pos].ends_with(char::is_whitespace)))
+        .map(|(pos, _)| pos);
+    match cut {
+        Some(pos) => line[..pos].trim_end().to_string(),
+        None => line.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use crate::context::ExecutionContext;
+    use crate::error::ShiftError;
+    use crate::plan_file;
+    use crate::registry::Registry;
+
+    const FIXTURES: &str = "tests/fixtures/import/shell";
+
+    #[test]
+    fn script_converts_to_the_expected_plan() {
+        let imported = super::import(&Path::new(FIXTURES).join("setup.sh")).unwrap();
+        let plan = imported.to_toml();
+        let expected = fs::read_to_string(Path::new(FIXTURES).join("expected.toml")).unwrap();
+        assert_eq!(plan, expected);
+        assert_eq!(imported.todo_count(), 2);
+
+        let mut ctx = ExecutionContext::new();
+        let parsed = plan_file::parse(&plan, &Registry::builtin(), &mut ctx).unwrap();
+        assert_eq!(parsed.entries().len(), imported.shift_count());
+        assert_eq!(ctx.interpolate("${APP_DIR}").unwrap(), "/opt/app");
+        // Preflight may find the machine lacks a package manager, but
+        // not a path the plan is refused.
+        if let Err(ShiftError::Preflight(problems)) = parsed.preflight(&ctx) {
+            for (id, err) in problems {
+                let err = err.to_string();
+                assert!(!err.contains("outside the plan root"), "{id}: {err}");
+            }
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! What a plan takes from outside the machine, and whether each is pinned
+//! to exact bytes, so a run can be repeated and its results traced.
+//!
+//! Each shift lists its own through [`Shift::inputs`], which by default
+//! follows its [artifacts](crate::artifacts). An input is pinned when the
+//! plan fixes it: a download with a SHA-256, a clone locked to a commit, a
+//! toolchain at a full version, a snap at a revision. Downloads and clones
+//! the [lockfile](crate::lockfile) has count as pinned by it. What floats
+//! is a branch, a channel such as `stable`, a version such as `20` or
+//! `latest`, and a package installed at whatever version its repository
+//! has.
+//!
+//! [`Shift::inputs`]: crate::Shift::inputs
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::artifacts::Artifact;
+use crate::context::ExecutionContext;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputKind {
+    Git,
+    Download,
+    Package,
+    Toolchain,
+    App,
+    Extension,
+    /// Something a shift fetches without saying what.
+    Network,
+}
+
+impl fmt::Display for InputKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            InputKind::Git => "git",
+            InputKind::Download => "download",
+            InputKind::Package => "package",
+            InputKind::Toolchain => "toolchain",
+            InputKind::App => "app",
+            InputKind::Extension => "extension",
+            InputKind::Network => "network",
+        })
+    }
+}
+
+/// Something a shift fetches.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExternalInput {
+    pub kind: InputKind,
+    /// A URL, or a package, app or toolchain name.
+    pub name: String,
+    /// What the plan asks for, if anything: a branch, channel or version.
+    pub requested: Option<String>,
+    /// What fixes the input exactly: a commit, digest, revision or full
+    /// version. `None` means the input floats.
+    pub pinned: Option<String>,
+}
+
+impl ExternalInput {
+    pub fn new(kind: InputKind, name: impl Into<String>) -> Self {
+        ExternalInput {
+            kind,
+            name: name.into(),
+            requested: None,
+            pinned: None,
+        }
+    }
+
+    pub fn requested(mut self, requested: Option<impl Into<String>>) -> Self {
+        self.requested = requested.map(Into::into);
+        self
+    }
+
+    pub fn pinned(mut self, pinned: Option<impl Into<String>>) -> Self {
+        self.pinned = pinned.map(Into::into);
+        self
+    }
+
+    /// A clone of `url` at `branch`, pinned by the context's lockfile.
+    pub fn git(ctx: &ExecutionContext, url: &str, branch: Option<&str>) -> Self {
+        let commit = ctx.lock().and_then(|lock| lock.commit(url, branch));
+        ExternalInput::new(InputKind::Git, url)
+            .requested(branch)
+            .pinned(commit)
+    }
+
+    /// A download of `url`, pinned by `sha256` or else the context's
+    /// lockfile.
+    pub fn download(ctx: &ExecutionContext, url: &str, sha256: Option<&str>) -> Self {
+        let sha256 = sha256
+            .map(String::from)
+            .or_else(|| ctx.lock().and_then(|lock| lock.sha256(url)));
+        ExternalInput::new(InputKind::Download, url)
+            .pinned(sha256.map(|sha| format!("sha256:{sha}")))
+    }
+
+    /// A toolchain at `version`, pinned if that names one release.
+    pub fn toolchain(name: &str, version: &str) -> Self {
+        ExternalInput::new(InputKind::Toolchain, name)
+            .requested(Some(version))
+            .pinned(is_exact_version(version).then_some(version))
+    }
+
+    /// What `artifact` is as an input. Packages float.
+    pub fn from_artifact(ctx: &ExecutionContext, artifact: &Artifact) -> Vec<Self> {
+        match artifact {
+            Artifact::File { url } => vec![ExternalInput::download(ctx, url, None)],
+            Artifact::Git { url, branch, .. } => {
+                vec![ExternalInput::git(ctx, url, branch.as_deref())]
+            }
+            Artifact::Packages(names) => names
+                .iter()
+                .map(|name| ExternalInput::new(InputKind::Package, name))
+                .collect(),
+        }
+    }
+
+    pub fn is_pinned(&self) -> bool {
+        self.pinned.is_some()
+    }
+
+    /// The input in a line, for messages and reports.
+    pub fn describe(&self) -> String {
+        match &self.requested {
+            Some(requested) => format!("{} {} ({requested})", self.kind, self.name),
+            None => format!("{} {}", self.kind, self.name),
+        }
+    }
+}
+
+/// Whether `version` names a single release: `1.76.0`, `v20.11.1`,
+/// `3.12.2`, or a dated build such as `nightly-2024-05-01`, rather than a
+/// line such as `20`, `3.12`, `lts` or `stable`.
+pub fn is_exact_version(version: &str) -> bool {
+    let version = version.strip_prefix('v').unwrap_or(version);
+    let numbers = |text: &str, count: usize| {
+        let parts: Vec<&str> = text.split(['.', '-']).collect();
+        parts.len() >= count
+            && parts[..count]
+                .iter()
+                .all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()))
+    };
+    match version.split_once('-') {
+        Some((channel, date)) if !channel.starts_with(|c: char| c.is_ascii_digit()) => {
+            numbers(date, 3)
+        }
+        _ => numbers(version, 3),
+    }
+}
@@ -0,0 +1,592 @@
+//! Record of past runs, stored as JSON next to the plan in `.skies/`.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::hash::sha256_hex;
+use crate::machine::Machine;
+use crate::outputs::{Output, Outputs};
+use crate::permissions;
+use crate::plan::ShiftPlan;
+use crate::report::{PlanEvent, Reporter};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Journal {
+    pub runs: Vec<RunRecord>,
+    /// The ID and labels of the machine the plan runs on.
+    #[serde(default, skip_serializing_if = "Machine::is_empty")]
+    pub machine: Machine,
+    /// What the plan was last agreed to be allowed to do, for a plan
+    /// applied as untrusted; `None` until it first is.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub consent: Option<Consent>,
+}
+
+/// Capabilities agreed to for an untrusted plan.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Consent {
+    pub capabilities: BTreeSet<Capability>,
+    /// The plan as it was when they were agreed to.
+    pub plan_hash: String,
+    pub at: u64,
+}
+
+impl Consent {
+    pub fn new(capabilities: BTreeSet<Capability>, plan_hash: String) -> Self {
+        Consent {
+            capabilities,
+            plan_hash,
+            at: now(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    Apply,
+    Revert,
+    /// `skies adopt`: shifts found in place were recorded as applied.
+    Adopt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunOutcome {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub operation: Operation,
+    /// Seconds since the Unix epoch.
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+    pub outcome: RunOutcome,
+    pub shifts: Vec<ShiftRecord>,
+    /// [`ShiftPlan::content_hash`](crate::ShiftPlan::content_hash) of the
+    /// plan as it was run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plan_hash: Option<String>,
+    /// Version of the migration this run applied or reverted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub migration: Option<u64>,
+    /// Scratch workspace kept for debugging after a failed run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temp_dir: Option<PathBuf>,
+    /// When each shift running now started.
+    #[serde(skip)]
+    running: HashMap<String, Instant>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShiftStatus {
+    Applied,
+    Skipped,
+    Failed,
+    TimedOut,
+    RolledBack,
+    Reverted,
+    /// Found in place by `skies adopt`.
+    Adopted,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShiftRecord {
+    pub id: String,
+    pub status: ShiftStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Seconds since the Unix epoch, when the shift finished.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<u64>,
+    /// How long the shift took, in milliseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    /// What an applied shift produced; see [`crate::outputs`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<Output>,
+    /// SHA-256 of each target file's contents when the shift was adopted.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub baselines: BTreeMap<PathBuf, String>,
+    /// [`PlanEntry::input_hash_in`](crate::PlanEntry::input_hash_in) of a
+    /// shift the run left in place.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_hash: Option<String>,
+    /// Hash of the outputs in effect for the shift then, from whichever
+    /// run applied it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outputs_hash: Option<String>,
+    /// How each of its targets looked then, as its type, size,
+    /// modification time and mode; see [`Journal::unchanged`].
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub stamps: BTreeMap<PathBuf, String>,
+}
+
+impl Journal {
+    /// Where the journal for the plan at `plan_path` lives.
+    pub fn path_for(plan_path: &Path) -> PathBuf {
+        Self::path_in(plan_path.parent().unwrap_or(Path::new(".")))
+    }
+
+    /// Where the journal for plans in `dir` lives.
+    pub fn path_in(dir: &Path) -> PathBuf {
+        dir.join(".skies").join("journal.json")
+    }
+
+    /// Loads a journal, treating a missing file as an empty one.
+    pub fn load(path: &Path) -> ShiftResult<Journal> {
+        match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text).map_err(|err| {
+                ShiftError::Custom(format!("corrupt journal {}: {err}", path.display()))
+            }),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Journal::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> ShiftResult<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text = serde_json::to_string_pretty(self)
+            .map_err(|err| ShiftError::Custom(format!("cannot encode journal: {err}")))?;
+        fs::write(path, text)?;
+        Ok(())
+    }
+
+    pub fn last_run(&self) -> Option<&RunRecord> {
+        self.runs.last()
+    }
+
+    /// Versions of the migrations currently applied, by replaying the
+    /// successful migration runs.
+    pub fn applied_migrations(&self) -> BTreeSet<u64> {
+        let mut applied = BTreeSet::new();
+        for run in &self.runs {
+            let Some(version) = run.migration else {
+                continue;
+            };
+            match (run.operation, run.outcome) {
+                (Operation::Apply, RunOutcome::Succeeded) => {
+                    applied.insert(version);
+                }
+                (Operation::Revert, RunOutcome::Succeeded) => {
+                    applied.remove(&version);
+                }
+                _ => {}
+            }
+        }
+        applied
+    }
+
+    /// Outputs of the shifts still in place, by shift ID: each from the
+    /// last run that applied the shift, dropped once it is reverted or
+    /// rolled back.
+    pub fn outputs(&self) -> BTreeMap<&str, &[Output]> {
+        let mut outputs = BTreeMap::new();
+        for record in self.runs.iter().flat_map(|run| &run.shifts) {
+            match record.status {
+                ShiftStatus::Applied => {
+                    outputs.insert(record.id.as_str(), record.outputs.as_slice());
+                }
+                ShiftStatus::RolledBack | ShiftStatus::Reverted => {
+                    outputs.remove(record.id.as_str());
+                }
+                _ => {}
+            }
+        }
+        outputs.retain(|_, list| !list.is_empty());
+        outputs
+    }
+
+    /// IDs of `plan`'s shifts an incremental apply can leave out without
+    /// checking them: those whose last run left them in place with the
+    /// inputs and plan settings they have now, whose targets still look
+    /// and whose outputs are still as that run left them, and whose
+    /// dependencies can be left out too.
+    ///
+    /// Only the targets are looked at, so what a shift changes elsewhere,
+    /// such as a package it installs, is taken to be as it was. Shifts
+    /// without targets, with nothing to look at, always count as changed.
+    pub fn unchanged(
+        &self,
+        plan: &ShiftPlan,
+        ctx: &ExecutionContext,
+    ) -> ShiftResult<HashSet<String>> {
+        let mut last: HashMap<&str, &ShiftRecord> = HashMap::new();
+        for record in self.runs.iter().flat_map(|run| &run.shifts) {
+            match record.status {
+                ShiftStatus::Applied | ShiftStatus::Skipped | ShiftStatus::Adopted => {
+                    last.insert(record.id.as_str(), record);
+                }
+                _ => {
+                    last.remove(record.id.as_str());
+                }
+            }
+        }
+        let ctx = plan.context(ctx);
+        let outputs = self.outputs();
+        let mut unchanged = HashSet::new();
+        for idx in plan.execution_order()? {
+            let entry = &plan.entries()[idx];
+            let Some(record) = last.get(entry.id()) else {
+                continue;
+            };
+            let outputs = outputs.get(entry.id()).copied().unwrap_or_default();
+            let same = !record.stamps.is_empty()
+                && record.input_hash.as_deref() == Some(entry.input_hash_in(&ctx).as_str())
+                && record.outputs_hash.as_deref() == Some(outputs_hash(outputs).as_str())
+                && entry.depends_on.iter().all(|dep| unchanged.contains(dep))
+                && record
+                    .stamps
+                    .iter()
+                    .all(|(target, stamp)| *stamp == self::stamp(&ctx.join_root(target)));
+            if same {
+                unchanged.insert(entry.id().to_string());
+            }
+        }
+        Ok(unchanged)
+    }
+
+    /// Notes, for each shift the run at `run` left in place, its input
+    /// hash, a hash of its outputs in effect and a stamp of each of its
+    /// targets, as found under `plan`'s context in `ctx`, for
+    /// [`Journal::unchanged`].
+    pub fn record_inputs(&mut self, run: usize, plan: &ShiftPlan, ctx: &ExecutionContext) {
+        let ctx = plan.context(ctx);
+        let outputs: HashMap<String, String> = self
+            .outputs()
+            .into_iter()
+            .map(|(id, list)| (id.to_string(), outputs_hash(list)))
+            .collect();
+        let Some(run) = self.runs.get_mut(run) else {
+            return;
+        };
+        for record in &mut run.shifts {
+            if !matches!(record.status, ShiftStatus::Applied | ShiftStatus::Skipped) {
+                continue;
+            }
+            let Some(entry) = plan.entry(&record.id) else {
+                continue;
+            };
+            record.input_hash = Some(entry.input_hash_in(&ctx));
+            record.outputs_hash = Some(
+                outputs
+                    .get(&record.id)
+                    .cloned()
+                    .unwrap_or_else(|| outputs_hash(&[])),
+            );
+            record.stamps = entry
+                .shift
+                .metadata()
+                .targets
+                .into_iter()
+                .map(|target| {
+                    let stamp = stamp(&ctx.join_root(&target));
+                    (target, stamp)
+                })
+                .collect();
+        }
+    }
+
+    /// The run that put the machine in its current state as far as the
+    /// journal knows: the last successful run, if it was an apply or an
+    /// adopt. `None` if nothing was applied or the plan was reverted since.
+    pub fn last_apply(&self) -> Option<&RunRecord> {
+        self.runs
+            .iter()
+            .rev()
+            .find(|run| run.outcome == RunOutcome::Succeeded)
+            .filter(|run| matches!(run.operation, Operation::Apply | Operation::Adopt))
+    }
+}
+
+impl RunRecord {
+    pub fn start(operation: Operation) -> Self {
+        RunRecord {
+            operation,
+            started_at: now(),
+            finished_at: None,
+            outcome: RunOutcome::Running,
+            shifts: Vec::new(),
+            plan_hash: None,
+            migration: None,
+            temp_dir: None,
+            running: HashMap::new(),
+        }
+    }
+
+    pub fn finish(&mut self, succeeded: bool) {
+        self.finished_at = Some(now());
+        self.outcome = if succeeded {
+            RunOutcome::Succeeded
+        } else {
+            RunOutcome::Failed
+        };
+    }
+
+    /// Moves the outputs reported during the run onto the records of the
+    /// shifts that applied.
+    pub fn take_outputs(&mut self, outputs: &Outputs) {
+        for record in &mut self.shifts {
+            if record.status == ShiftStatus::Applied {
+                record.outputs = outputs.take(&record.id);
+            }
+        }
+    }
+
+    /// Notes, for each adopted shift, the hash of every target that is a
+    /// file, as found under `plan`'s context in `ctx`.
+    pub fn record_baselines(&mut self, plan: &ShiftPlan, ctx: &ExecutionContext) {
+        let ctx = plan.context(ctx);
+        for record in &mut self.shifts {
+            let Some(entry) = plan.entry(&record.id) else {
+                continue;
+            };
+            if record.status != ShiftStatus::Adopted {
+                continue;
+            }
+            for target in entry.shift.metadata().targets {
+                let path = ctx.join_root(&target);
+                if path.is_file() {
+                    if let Ok(contents) = fs::read(&path) {
+                        record.baselines.insert(target, sha256_hex(contents));
+                    }
+                }
+            }
+        }
+    }
+
+    /// IDs of shifts this run left in place (applied or already satisfied).
+    ///
+    /// A shift that was rolled back later in the run does not count.
+    pub fn completed_ids(&self) -> HashSet<&str> {
+        let mut done = HashSet::new();
+        for record in &self.shifts {
+            match record.status {
+                ShiftStatus::Applied | ShiftStatus::Skipped => {
+                    done.insert(record.id.as_str());
+                }
+                _ => {
+                    done.remove(record.id.as_str());
+                }
+            }
+        }
+        done
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Operation::Apply => "apply",
+            Operation::Revert => "revert",
+            Operation::Adopt => "adopt",
+        })
+    }
+}
+
+impl fmt::Display for RunOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            RunOutcome::Running => "running",
+            RunOutcome::Succeeded => "succeeded",
+            RunOutcome::Failed => "failed",
+        })
+    }
+}
+
+impl fmt::Display for ShiftStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            ShiftStatus::Applied => "applied",
+            ShiftStatus::Skipped => "skipped",
+            ShiftStatus::Failed => "failed",
+            ShiftStatus::TimedOut => "timed out",
+            ShiftStatus::RolledBack => "rolled back",
+            ShiftStatus::Reverted => "reverted",
+            ShiftStatus::Adopted => "adopted",
+        })
+    }
+}
+
+/// Appends every plan event to a [`RunRecord`].
+impl Reporter for RunRecord {
+    fn report(&mut self, event: &PlanEvent<'_>) {
+        let id = event.entry().id();
+        let status = match event {
+            PlanEvent::Started(_) => {
+                self.running.insert(id.to_string(), Instant::now());
+                return;
+            }
+            PlanEvent::Skipped(_) => ShiftStatus::Skipped,
+            PlanEvent::Applied(_) => ShiftStatus::Applied,
+            PlanEvent::Failed(..) | PlanEvent::RollbackFailed(..) => ShiftStatus::Failed,
+            PlanEvent::TimedOut(..) => ShiftStatus::TimedOut,
+            PlanEvent::RolledBack(_) => ShiftStatus::RolledBack,
+            PlanEvent::Reverted(_) => ShiftStatus::Reverted,
+            PlanEvent::Adopted(_) => ShiftStatus::Adopted,
+            PlanEvent::WouldApply(_) | PlanEvent::WouldRevert(_) | PlanEvent::Verified(_) => return,
+        };
+        let duration = self.running.remove(id).map(|started| started.elapsed());
+        self.shifts.push(ShiftRecord {
+            id: id.to_string(),
+            status,
+            error: event.error().map(ToString::to_string),
+            finished_at: Some(now()),
+            duration_ms: duration.map(|took| took.as_millis() as u64),
+            outputs: Vec::new(),
+            baselines: BTreeMap::new(),
+            input_hash: None,
+            outputs_hash: None,
+            stamps: BTreeMap::new(),
+        });
+    }
+}
+
+/// Formats seconds since the Unix epoch as `YYYY-MM-DD HH:MM:SS` (UTC).
+pub fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    // Howard Hinnant's civil-from-days algorithm.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
+fn outputs_hash(outputs: &[Output]) -> String {
+    sha256_hex(serde_json::to_string(outputs).unwrap_or_default())
+}
+
+/// What `path` is, cheaply: missing, a link and where to, a directory, or
+/// a file with its size and modification time; with its mode.
+fn stamp(path: &Path) -> String {
+    let Ok(meta) = fs::symlink_metadata(path) else {
+        return "missing".into();
+    };
+    if meta.is_symlink() {
+        let target = fs::read_link(path).unwrap_or_default();
+        return format!("link {}", target.display());
+    }
+    let mode = permissions::mode_of(path)
+        .ok()
+        .flatten()
+        .map(|mode| format!(" {mode:o}"))
+        .unwrap_or_default();
+    if meta.is_dir() {
+        return format!("dir{mode}");
+    }
+    let modified = meta
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|since| since.as_nanos())
+        .unwrap_or(0);
+    format!("file {} {modified}{mode}", meta.len())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Journal, Operation, RunRecord};
+    use crate::context::ExecutionContext;
+    use crate::outputs::Output;
+    use crate::permissions::PermissionPolicy;
+    use crate::plan::{PlanEntry, ShiftPlan};
+    use crate::shifts::{Cmd, CreateFile};
+    use crate::testing::Sandbox;
+
+    fn plan(file_mode: u32) -> ShiftPlan {
+        let mut plan = ShiftPlan::new();
+        plan.push(PlanEntry::new(CreateFile::new("app.conf", "port=80\n")).with_id("conf"));
+        plan.set_permissions(PermissionPolicy {
+            file_mode: Some(file_mode),
+            ..PermissionPolicy::default()
+        });
+        plan
+    }
+
+    /// A journal of one run applying `plan`, recorded for incremental
+    /// applies.
+    fn applied(plan: &ShiftPlan, ctx: &ExecutionContext) -> Journal {
+        let mut run = RunRecord::start(Operation::Apply);
+        plan.apply_with(ctx, &mut run).unwrap();
+        run.finish(true);
+        let mut journal = Journal::default();
+        journal.runs.push(run);
+        journal.record_inputs(0, plan, ctx);
+        journal
+    }
+
+    #[test]
+    fn shifts_as_left_are_unchanged() {
+        let sandbox = Sandbox::new().unwrap();
+        let ctx = sandbox.context();
+        let journal = applied(&plan(0o644), &ctx);
+        let unchanged = journal.unchanged(&plan(0o644), &ctx).unwrap();
+        assert!(unchanged.contains("conf"));
+    }
+
+    #[test]
+    fn changed_permission_policy_counts_as_changed() {
+        let sandbox = Sandbox::new().unwrap();
+        let ctx = sandbox.context();
+        let journal = applied(&plan(0o644), &ctx);
+        let unchanged = journal.unchanged(&plan(0o600), &ctx).unwrap();
+        assert!(!unchanged.contains("conf"));
+    }
+
+    #[test]
+    fn changed_outputs_count_as_changed() {
+        let sandbox = Sandbox::new().unwrap();
+        let ctx = sandbox.context();
+        let mut journal = applied(&plan(0o644), &ctx);
+        journal.runs[0].shifts[0].outputs.push(Output {
+            name: "port".into(),
+            value: 80.into(),
+            sensitive: false,
+        });
+        let unchanged = journal.unchanged(&plan(0o644), &ctx).unwrap();
+        assert!(!unchanged.contains("conf"));
+    }
+
+    #[test]
+    fn shifts_without_targets_count_as_changed() {
+        let sandbox = Sandbox::new().unwrap();
+        let ctx = sandbox.context();
+        let mut plan = ShiftPlan::new();
+        plan.push(PlanEntry::new(Cmd::new("true")).with_id("noop"));
+        let journal = applied(&plan, &ctx);
+        assert!(journal.unchanged(&plan, &ctx).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,125 @@
+//! A run as a JUnit XML report, for CI systems such as Jenkins and GitLab
+//! to show in their test views.
+//!
+//! The run is a test suite and each shift a test case, with how long it
+//! took. A shift that failed or timed out is a failure carrying its error;
+//! one rolled back after a later failure is skipped. A shift that was
+//! already in place passes.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::error::ShiftError;
+use crate::journal::{format_timestamp, Operation, RunRecord, ShiftRecord, ShiftStatus};
+
+/// The report for `run`, the journal record of a local run, if any, which
+/// ended with `error`. Without a record, the run is a single test case.
+pub fn render(operation: Operation, run: Option<&RunRecord>, error: Option<&ShiftError>) -> String {
+    // A retried shift appears more than once; its last record counts.
+    let mut last: BTreeMap<&str, &ShiftRecord> = BTreeMap::new();
+    let mut order = Vec::new();
+    for shift in run.map_or(&[][..], |run| &run.shifts) {
+        if last.insert(shift.id.as_str(), shift).is_none() {
+            order.push(shift.id.as_str());
+        }
+    }
+    let classname = format!("skies.{operation}");
+    let mut cases = String::new();
+    let (mut failures, mut skipped) = (0, 0);
+    for id in &order {
+        let shift = last[id];
+        let time = shift.duration_ms.unwrap_or(0) as f64 / 1000.0;
+        let _ = write!(
+            cases,
+            "    <testcase name=\"{}\" classname=\"{classname}\" time=\"{time:.3}\"",
+            escape(id)
+        );
+        match shift.status {
+            ShiftStatus::Failed | ShiftStatus::TimedOut => {
+                failures += 1;
+                let message = shift.error.as_deref().unwrap_or("failed");
+                let _ = write!(
+                    cases,
+                    ">\n      <failure message=\"{}\" type=\"{}\">{}</failure>\n    </testcase>\n",
+                    escape(first_line(message)),
+                    if shift.status == ShiftStatus::TimedOut {
+                        "timed_out"
+                    } else {
+                        "failed"
+                    },
+                    escape(message)
+                );
+            }
+            ShiftStatus::RolledBack => {
+                skipped += 1;
+                cases.push_str(
+                    ">\n      <skipped message=\"rolled back after a later shift failed\"/>\n    </testcase>\n",
+                );
+            }
+            ShiftStatus::Skipped => cases
+                .push_str(">\n      <system-out>already in place</system-out>\n    </testcase>\n"),
+            _ => cases.push_str("/>\n"),
+        }
+    }
+    // An error outside any shift, such as a failed preflight, still has
+    // to fail the report.
+    let unattributed = error.filter(|_| failures == 0);
+    if let Some(err) = unattributed {
+        failures += 1;
+        let message = err.to_string();
+        let _ = writeln!(
+            cases,
+            "    <testcase name=\"{operation}\" classname=\"{classname}\" time=\"0.000\">\n      \
+             <failure message=\"{}\" type=\"error\">{}</failure>\n    </testcase>",
+            escape(first_line(&message)),
+            escape(&message)
+        );
+    } else if run.is_none() {
+        let _ = writeln!(
+            cases,
+            "    <testcase name=\"{operation}\" classname=\"{classname}\" time=\"0.000\"/>"
+        );
+    }
+    let tests = order.len() + usize::from(unattributed.is_some() || run.is_none());
+    let time = run
+        .and_then(|run| {
+            run.finished_at
+                .map(|end| end.saturating_sub(run.started_at))
+        })
+        .unwrap_or(0);
+    let timestamp = run
+        .map(|run| {
+            format!(
+                " timestamp=\"{}\"",
+                format_timestamp(run.started_at).replace(' ', "T")
+            )
+        })
+        .unwrap_or_default();
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <testsuites name=\"skies\" tests=\"{tests}\" failures=\"{failures}\" skipped=\"{skipped}\" time=\"{time}\">\n  \
+         <testsuite name=\"skies {operation}\" tests=\"{tests}\" failures=\"{failures}\" errors=\"0\" skipped=\"{skipped}\" time=\"{time}\"{timestamp}>\n\
+         {cases}  </testsuite>\n</testsuites>\n"
+    )
+}
+
+fn first_line(text: &str) -> &str {
+    text.lines().next().unwrap_or_default()
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            // Not allowed in XML 1.0 at all.
+            c if c.is_control() && !matches!(c, '\n' | '\t' | '\r') => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,73 @@
+//! skies: declarative, reversible machine setup.
+//!
+//! A [`ShiftPlan`] is an ordered set of [`Shift`]s, each of which knows how
+//! to apply itself, revert itself and tell whether it is already in place.
+
+pub mod artifacts;
+pub mod builder;
+pub mod cancel;
+pub mod capability;
+pub mod capture;
+pub mod context;
+pub mod devcontainer;
+pub mod diagnostics;
+pub mod docs;
+pub mod dotfiles;
+pub mod download_cache;
+pub mod error;
+pub mod exec;
+pub mod export;
+pub mod expr;
+pub mod facts;
+pub mod first_boot;
+pub mod fs;
+pub mod hash;
+pub mod import;
+pub mod inputs;
+pub mod journal;
+pub mod junit;
+pub mod lockfile;
+pub mod machine;
+pub mod matrix;
+pub mod metadata;
+pub mod migrations;
+pub mod monorepo;
+pub mod network;
+pub mod outputs;
+pub mod packages;
+pub mod params;
+pub mod paths;
+pub mod permissions;
+pub mod plan;
+pub mod plan_file;
+pub mod policy;
+pub mod recording;
+pub mod registry;
+pub mod report;
+pub mod resource;
+pub mod run_as;
+pub mod run_target;
+pub mod sandbox;
+pub mod script;
+pub mod service;
+pub mod shift;
+pub mod shifts;
+pub mod starlark_file;
+pub mod state;
+pub mod summary;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod testing;
+pub mod transient;
+pub mod validate;
+pub mod workspace;
+
+pub use builder::{PlanBuilder, StepBuilder};
+pub use cancel::CancellationToken;
+pub use capability::Capability;
+pub use context::ExecutionContext;
+pub use error::{ResultExt, ShiftError, ShiftResult};
+pub use metadata::ShiftMetadata;
+pub use plan::{ApplyOptions, FailureAction, PlanEntry, RunControl, ShiftPlan};
+pub use registry::Registry;
+pub use resource::{Access, Claim, Resource};
+pub use shift::{Shift, ShiftOutcome};
@@ -0,0 +1,156 @@
+//! `skies.lock`: what a plan's downloads and clones resolved to on its
+//! first apply, so later applies get the same bytes.
+//!
+//! ```toml
+//! [git]
+//! "https://github.com/owner/repo.git#main" = "3f1c…"
+//!
+//! [downloads]
+//! "https://github.com/owner/tool/releases/download/v1.2/tool.tar.gz" = "9b0e…"
+//! ```
+//!
+//! `git` maps a repository URL, with `#branch` when the clone names one, to
+//! the commit a fresh clone is checked out at; `downloads` maps a URL, such
+//! as a release asset, to the SHA-256 of its contents, which a download
+//! must match unless its shift gives its own. Whatever a run resolves that
+//! the lockfile does not have yet is added to it. Mirrors are not locked,
+//! since they copy every ref. `skies update-lock` resolves everything again
+//! from the network.
+//!
+//! The lockfile lives next to the plan and is meant to be committed with
+//! it.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ShiftError, ShiftResult};
+
+pub const LOCKFILE: &str = "skies.lock";
+
+const HEADER: &str = "# Written by skies; refresh with `skies update-lock`.\n\n";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Lockfile {
+    /// Commits, by [`Lockfile::git_key`].
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub git: BTreeMap<String, String>,
+    /// SHA-256 digests, by URL.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub downloads: BTreeMap<String, String>,
+}
+
+impl Lockfile {
+    /// Where the lockfile for the plan at `plan_path` lives.
+    pub fn path_for(plan_path: &Path) -> PathBuf {
+        plan_path.parent().unwrap_or(Path::new(".")).join(LOCKFILE)
+    }
+
+    /// The key of the clone of `url` at `branch`, or at the remote's
+    /// default branch.
+    pub fn git_key(url: &str, branch: Option<&str>) -> String {
+        match branch {
+            Some(branch) => format!("{url}#{branch}"),
+            None => url.to_string(),
+        }
+    }
+
+    /// Loads a lockfile, treating a missing file as an empty one.
+    pub fn load(path: &Path) -> ShiftResult<Lockfile> {
+        match fs::read_to_string(path) {
+            Ok(text) => toml::from_str(&text)
+                .map_err(|err| ShiftError::Plan(format!("{}: {err}", path.display()))),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Lockfile::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> ShiftResult<()> {
+        let text = toml::to_string(self)
+            .map_err(|err| ShiftError::Custom(format!("cannot encode lockfile: {err}")))?;
+        fs::write(path, format!("{HEADER}{text}"))?;
+        Ok(())
+    }
+}
+
+/// A lockfile in use by a run: what it pins, and what the run resolved.
+#[derive(Debug)]
+pub struct Lock {
+    path: PathBuf,
+    locked: Lockfile,
+    resolved: Mutex<Lockfile>,
+}
+
+impl Lock {
+    /// Opens the lockfile at `path`, which need not exist yet.
+    pub fn open(path: impl Into<PathBuf>) -> ShiftResult<Lock> {
+        let path = path.into();
+        let locked = Lockfile::load(&path)?;
+        Ok(Lock {
+            path,
+            locked,
+            resolved: Mutex::new(Lockfile::default()),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn resolved(&self) -> MutexGuard<'_, Lockfile> {
+        self.resolved.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The digest `url` is locked to, or was resolved to earlier in the
+    /// run.
+    pub fn sha256(&self, url: &str) -> Option<String> {
+        let resolved = self.resolved();
+        resolved
+            .downloads
+            .get(url)
+            .or_else(|| self.locked.downloads.get(url))
+            .cloned()
+    }
+
+    /// The commit a clone of `url` at `branch` is locked to, or was
+    /// resolved to earlier in the run.
+    pub fn commit(&self, url: &str, branch: Option<&str>) -> Option<String> {
+        let key = Lockfile::git_key(url, branch);
+        let resolved = self.resolved();
+        resolved
+            .git
+            .get(&key)
+            .or_else(|| self.locked.git.get(&key))
+            .cloned()
+    }
+
+    pub fn record_download(&self, url: &str, sha256: &str) {
+        let mut resolved = self.resolved();
+        resolved.downloads.insert(url.into(), sha256.into());
+    }
+
+    pub fn record_commit(&self, url: &str, branch: Option<&str>, commit: &str) {
+        let mut resolved = self.resolved();
+        resolved
+            .git
+            .insert(Lockfile::git_key(url, branch), commit.into());
+    }
+
+    /// Writes what the run resolved into the lockfile, if that changes it.
+    /// Returns whether it did.
+    pub fn save(&self) -> ShiftResult<bool> {
+        let resolved = self.resolved();
+        let mut merged = self.locked.clone();
+        merged.git.extend(resolved.git.clone());
+        merged.downloads.extend(resolved.downloads.clone());
+        if merged == self.locked {
+            return Ok(false);
+        }
+        merged.save(&self.path)?;
+        Ok(true)
+    }
+}
@@ -0,0 +1,82 @@
+//! The identity of the machine a plan runs on: a stable ID and free-form
+//! labels, kept in the plan's journal and exposed to the plan as facts.
+//!
+//! `machine_id` is the ID and `label.<name>` each label, so an entry can be
+//! limited to labelled machines with `facts = { "label.role" = "build" }`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ShiftError, ShiftResult};
+use crate::facts::Facts;
+use crate::hash::short_hash;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Machine {
+    /// Empty until the journal is first saved; see [`Machine::ensure_id`].
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+}
+
+impl Machine {
+    pub fn is_empty(&self) -> bool {
+        self.id.is_empty() && self.labels.is_empty()
+    }
+
+    /// Gives the machine an ID if it has none yet: systemd's
+    /// `/etc/machine-id` where there is one, else a random one.
+    pub fn ensure_id(&mut self) -> &str {
+        if self.id.is_empty() {
+            self.id = fs::read_to_string("/etc/machine-id")
+                .ok()
+                .map(|id| id.trim().to_string())
+                .filter(|id| !id.is_empty())
+                .unwrap_or_else(random_id);
+        }
+        &self.id
+    }
+
+    /// Sets a label, checking its name.
+    pub fn label(&mut self, name: &str, value: &str) -> ShiftResult<()> {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+        if !valid {
+            return Err(ShiftError::Custom(format!(
+                "invalid label name `{name}`: use letters, digits, `_`, `-` and `.`"
+            )));
+        }
+        self.labels.insert(name.to_string(), value.to_string());
+        Ok(())
+    }
+
+    /// Adds the `machine_id` and `label.<name>` facts.
+    pub fn add_facts(&self, facts: &mut Facts) {
+        if !self.id.is_empty() {
+            facts.set("machine_id", &self.id);
+        }
+        for (name, value) in &self.labels {
+            facts.set(format!("label.{name}"), value);
+        }
+    }
+}
+
+/// 32 hex digits that differ between machines and runs. Not for secrets.
+fn random_id() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos())
+        .unwrap_or_default();
+    let seed = format!(
+        "{nanos}:{}:{:?}",
+        std::process::id(),
+        Facts::gather().get("hostname")
+    );
+    short_hash(seed, 32)
+}
@@ -1,3 +1,212 @@
-fn main() {
-    println!("Hello, world!");
+mod commands;
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use clap::{ArgAction, Parser, Subcommand};
+use skies::context::{FileLogger, LogLevel, Logger};
+use skies::report::render_error;
+use skies::ShiftResult;
+
+use commands::adopt::AdoptArgs;
+use commands::agent::AgentArgs;
+use commands::attach::AttachArgs;
+use commands::cache::CacheCommand;
+use commands::capture::CaptureArgs;
+use commands::devcontainer::DevcontainerArgs;
+use commands::export::ExportArgs;
+use commands::fetch::FetchArgs;
+use commands::first_boot::FirstBootCommand;
+use commands::history::HistoryCommand;
+use commands::hook::HookCommand;
+use commands::import::ImportArgs;
+use commands::link::LinkArgs;
+use commands::lock::UpdateLockArgs;
+use commands::machine::MachineCommand;
+use commands::migrate::{DownArgs, MigrationsDir, UpArgs};
+use commands::outputs::OutputsArgs;
+use commands::run::{ApplyArgs, RevertArgs};
+use commands::serve::ServeArgs;
+use commands::target::Target;
+use commands::{
+    adopt, agent, attach, cache, capture, devcontainer, export, fetch, first_boot, history, hook,
+    import, inspect, link, lock, machine, migrate, outputs, provision, run, serve, Color, Format,
+    Verbosity,
+};
+
+#[derive(Parser)]
+#[command(
+    name = "skies",
+    version,
+    about = "Declarative, reversible machine setup"
+)]
+struct Cli {
+    /// Output for humans or as JSON (one object per line for runs).
+    #[arg(long, global = true, value_enum, default_value_t = Format::Human)]
+    format: Format,
+    /// Only print errors and a one-line summary.
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Print debug messages; twice to also print what commands print.
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+    /// Also append everything, at full detail, to this file.
+    #[arg(long, global = true, value_name = "PATH")]
+    log_file: Option<PathBuf>,
+    /// Color and symbols: only on a terminal (and without `NO_COLOR`), or
+    /// always, or never.
+    #[arg(long, global = true, value_enum, default_value_t = Color::Auto)]
+    color: Color,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Apply every shift that is not already in place.
+    Apply(ApplyArgs),
+    /// Revert applied shifts, last to first.
+    Revert(RevertArgs),
+    /// Follow a run started with `apply --detach` until it finishes.
+    Attach(AttachArgs),
+    /// Record the shifts already in place as applied, without changing
+    /// anything, to start managing an existing machine.
+    Adopt(AdoptArgs),
+    /// Show each shift's ID and whether it is applied.
+    Status(Target),
+    /// Run preflight checks without changing anything.
+    Validate(Target),
+    /// Type-check the plan's `when` conditions and `${...}` expressions
+    /// without evaluating them.
+    Lint(Target),
+    /// Show what each shift does, touches and depends on.
+    Describe(Target),
+    /// Print a JSON Schema of plan files, for editors and for checking
+    /// plans in CI.
+    Schema,
+    /// Show each shift type's fields and defaults, when it counts as
+    /// applied and what revert does.
+    Docs {
+        /// Only this shift type, as plan files name it.
+        #[arg(value_name = "TYPE")]
+        kind: Option<String>,
+    },
+    /// List what the plan fetches, such as clones, downloads, packages and
+    /// toolchains, and what each is pinned to.
+    Inputs(Target),
+    /// Print the plan with roles, conditions and variables resolved, as
+    /// the shifts that would run.
+    Explain(Target),
+    /// Print a plan that symlinks a dotfiles tree into place.
+    Link(LinkArgs),
+    /// Print a plan that recreates an existing directory tree.
+    Capture(CaptureArgs),
+    /// Convert another tool's setup, such as an Ansible playbook or a
+    /// shell script, into a plan, with TODOs for what has no skies equivalent.
+    Import(ImportArgs),
+    /// Render the plan to run without skies, as a standalone bash script or
+    /// a Dockerfile, with TODOs for what does not carry over.
+    Export(ExportArgs),
+    /// Download what the plan needs into a cache, for `apply --offline`.
+    Fetch(FetchArgs),
+    /// Resolve the plan's downloads and clones from the network again and
+    /// write them to its skies.lock.
+    UpdateLock(UpdateLockArgs),
+    /// Manage the cache of downloads shared by every plan.
+    #[command(subcommand)]
+    Cache(CacheCommand),
+    /// Show what applied shifts produced, such as paths and generated IDs.
+    Outputs(OutputsArgs),
+    /// Inspect past runs recorded in the journal.
+    #[command(subcommand)]
+    History(HistoryCommand),
+    /// Show or label the machine, as recorded in the journal.
+    #[command(subcommand)]
+    Machine(MachineCommand),
+    /// Serve an HTTP API to submit plans, start runs and follow them.
+    Serve(ServeArgs),
+    /// Pull this machine's plan from a repository and apply it on an
+    /// interval, correcting drift.
+    Agent(AgentArgs),
+    /// Apply a plan once, at a machine's (or image's) first boot.
+    #[command(subcommand)]
+    FirstBoot(FirstBootCommand),
+    /// Set up the project's dev container, as in Codespaces, to install
+    /// skies and apply the plan when the container is created.
+    Devcontainer(DevcontainerArgs),
+    /// Check a project's plan on entering its directory, from a shell or
+    /// direnv, optionally offering to apply it, or before a git commit.
+    #[command(subcommand)]
+    Hook(HookCommand),
+    /// Apply pending migrations from a directory of numbered plans.
+    Up(UpArgs),
+    /// Revert the newest applied migration, or down to `--to`.
+    Down(DownArgs),
+    /// Revert the newest applied migration and apply it again.
+    Redo(MigrationsDir),
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    commands::set_color(cli.color);
+    let result = cli
+        .log_file
+        .as_deref()
+        .map(FileLogger::append)
+        .transpose()
+        .and_then(|log_file| {
+            commands::set_verbosity(Verbosity {
+                quiet: cli.quiet,
+                verbose: cli.verbose,
+                log_file: log_file.map(Arc::new),
+            });
+            dispatch(cli.command, cli.format)
+        });
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            if let Some(log_file) = &commands::verbosity().log_file {
+                log_file.log(LogLevel::Warn, &format!("error: {err}"));
+            }
+            let error = commands::error_style().paint("1;31", "error");
+            eprintln!("{error}: {}", render_error(&err, &commands::error_style()));
+            ExitCode::from(provision::exit_code(&err))
+        }
+    }
+}
+
+fn dispatch(command: Command, format: Format) -> ShiftResult<()> {
+    match command {
+        Command::Apply(args) => run::apply(args, format),
+        Command::Revert(args) => run::revert(args, format),
+        Command::Attach(args) => attach::attach(args),
+        Command::Adopt(args) => adopt::adopt(args, format),
+        Command::Status(target) => inspect::status(target, format),
+        Command::Validate(target) => inspect::validate(target),
+        Command::Describe(target) => inspect::describe(target, format),
+        Command::Explain(target) => inspect::explain(target, format),
+        Command::Inputs(target) => inspect::inputs(target, format),
+        Command::Lint(target) => inspect::lint(target),
+        Command::Schema => inspect::schema(),
+        Command::Docs { kind } => inspect::docs(kind, format),
+        Command::Link(args) => link::link(args),
+        Command::Capture(args) => capture::capture(args),
+        Command::Import(args) => import::import(args),
+        Command::Export(args) => export::export(args),
+        Command::Fetch(args) => fetch::fetch(args, format),
+        Command::UpdateLock(args) => lock::update_lock(args, format),
+        Command::Cache(command) => cache::run(command, format),
+        Command::Outputs(args) => outputs::outputs(args, format),
+        Command::History(command) => history::run(command, format),
+        Command::Machine(command) => machine::run(command, format),
+        Command::Serve(args) => serve::serve(args),
+        Command::Agent(args) => agent::agent(args, format),
+        Command::FirstBoot(command) => first_boot::run(command, format),
+        Command::Devcontainer(args) => devcontainer::devcontainer(args),
+        Command::Hook(command) => hook::run(command),
+        Command::Up(args) => migrate::up(args, format),
+        Command::Down(args) => migrate::down(args, format),
+        Command::Redo(args) => migrate::redo(args, format),
+    }
 }
@@ -0,0 +1,125 @@
+//! Running one plan once per combination of variables.
+//!
+//! A plan file's `[matrix]` table gives each of some variables a list of
+//! values:
+//!
+//! ```toml
+//! [matrix]
+//! node = ["18", "20"]
+//! distro = ["bookworm", "jammy"]
+//!
+//! [[shift]]
+//! type = "create_dir"
+//! path = "checkouts/node-${node}-${distro}"
+//! ```
+//!
+//! `skies apply` then runs the plan once for each combination, four times
+//! here, and sums up how each run went. Setting one of the variables with
+//! `--var` narrows the matrix to that value.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::plan_file::scalar;
+use crate::starlark_file;
+
+/// The variables of one run: a value for each axis of the matrix.
+pub type Combination = BTreeMap<String, String>;
+
+/// A plan's `[matrix]`: variable names and the values each takes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Matrix {
+    axes: BTreeMap<String, Vec<String>>,
+}
+
+/// Just the part of a plan file this module reads.
+#[derive(Deserialize)]
+struct RawMatrix {
+    #[serde(default)]
+    matrix: Option<BTreeMap<String, Vec<toml::Value>>>,
+}
+
+impl Matrix {
+    /// The matrix of the plan file at `path`, if it has one.
+    pub fn load(path: &Path) -> ShiftResult<Option<Matrix>> {
+        let in_file = |err: String| ShiftError::Plan(format!("{}: {err}", path.display()));
+        if starlark_file::is_starlark(path) {
+            // The matrix decides the variables, so the file runs without.
+            let document = starlark_file::evaluate(path, &ExecutionContext::new())?;
+            let raw = toml::Value::Table(document)
+                .try_into()
+                .map_err(|err: toml::de::Error| in_file(err.message().to_string()))?;
+            return Self::from_raw(raw).map_err(in_file);
+        }
+        let source = fs::read_to_string(path)
+            .map_err(|err| ShiftError::Plan(format!("cannot read {}: {err}", path.display())))?;
+        Self::parse(&source).map_err(in_file)
+    }
+
+    /// The matrix in plan file source, if it has one.
+    pub fn parse(source: &str) -> Result<Option<Matrix>, String> {
+        let raw = toml::from_str(source).map_err(|err| err.message().to_string())?;
+        Self::from_raw(raw)
+    }
+
+    fn from_raw(raw: RawMatrix) -> Result<Option<Matrix>, String> {
+        let Some(matrix) = raw.matrix else {
+            return Ok(None);
+        };
+        let mut axes = BTreeMap::new();
+        for (name, values) in matrix {
+            if values.is_empty() {
+                return Err(format!("matrix variable `{name}` has no values"));
+            }
+            let values = values
+                .iter()
+                .map(|value| {
+                    scalar(value).ok_or_else(|| {
+                        format!("matrix values of `{name}` must be strings, numbers or booleans")
+                    })
+                })
+                .collect::<Result<_, _>>()?;
+            axes.insert(name, values);
+        }
+        Ok(Some(Matrix { axes }))
+    }
+
+    /// Narrows the axis `name`, if there is one, to the single `value`.
+    pub fn pin(&mut self, name: &str, value: &str) {
+        if let Some(values) = self.axes.get_mut(name) {
+            *values = vec![value.to_string()];
+        }
+    }
+
+    /// Every combination of values, varying the last axis (by name) fastest.
+    pub fn combinations(&self) -> Vec<Combination> {
+        let mut combinations = vec![Combination::new()];
+        for (name, values) in &self.axes {
+            combinations = combinations
+                .into_iter()
+                .flat_map(|base| {
+                    values.iter().map(move |value| {
+                        let mut combination = base.clone();
+                        combination.insert(name.clone(), value.clone());
+                        combination
+                    })
+                })
+                .collect();
+        }
+        combinations
+    }
+}
+
+/// `combination` as `name=value` pairs, e.g. `distro=jammy, node=20`.
+pub fn label(combination: &Combination) -> String {
+    combination
+        .iter()
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
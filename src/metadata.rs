@@ -0,0 +1,116 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+/// Structured description of a shift, used for display, JSON output,
+/// derived IDs and redaction.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ShiftMetadata {
+    /// Shift type, matching the `type` used in plan files.
+    pub kind: String,
+    /// One-line human readable summary.
+    pub summary: String,
+    /// Paths the shift creates or modifies.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<PathBuf>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<Input>,
+    /// Programs the shift installs, which later shifts may run.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub programs: Vec<String>,
+}
+
+/// A named value the shift's behaviour depends on.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Input {
+    pub name: String,
+    pub value: serde_json::Value,
+    /// Secrets, credentials and the like; shown as `[redacted]`.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub sensitive: bool,
+}
+
+pub(crate) const REDACTED: &str = "[redacted]";
+
+impl ShiftMetadata {
+    pub fn new(kind: impl Into<String>, summary: impl Into<String>) -> Self {
+        ShiftMetadata {
+            kind: kind.into(),
+            summary: summary.into(),
+            targets: Vec::new(),
+            inputs: Vec::new(),
+            programs: Vec::new(),
+        }
+    }
+
+    pub fn target(mut self, path: impl Into<PathBuf>) -> Self {
+        self.targets.push(path.into());
+        self
+    }
+
+    pub fn program(mut self, name: impl Into<String>) -> Self {
+        self.programs.push(name.into());
+        self
+    }
+
+    pub fn input(self, name: impl Into<String>, value: impl Serialize) -> Self {
+        self.push_input(name.into(), value, false)
+    }
+
+    pub fn sensitive_input(self, name: impl Into<String>, value: impl Serialize) -> Self {
+        self.push_input(name.into(), value, true)
+    }
+
+    fn push_input(mut self, name: String, value: impl Serialize, sensitive: bool) -> Self {
+        let value = serde_json::to_value(value).unwrap_or(serde_json::Value::Null);
+        self.inputs.push(Input {
+            name,
+            value,
+            sensitive,
+        });
+        self
+    }
+
+    pub fn input_value(&self, name: &str) -> Option<&serde_json::Value> {
+        self.inputs
+            .iter()
+            .find(|i| i.name == name)
+            .map(|i| &i.value)
+    }
+
+    pub fn is_sensitive(&self) -> bool {
+        self.inputs.iter().any(|i| i.sensitive)
+    }
+
+    /// A copy with every sensitive input value masked.
+    pub fn redacted(&self) -> ShiftMetadata {
+        let mut copy = self.clone();
+        for input in copy.inputs.iter_mut().filter(|i| i.sensitive) {
+            input.value = serde_json::Value::String(REDACTED.into());
+        }
+        copy
+    }
+}
+
+impl fmt::Display for ShiftMetadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.summary)
+    }
+}
+
+/// Heuristic for environment variables and the like that hold secrets.
+pub fn looks_secret(name: &str) -> bool {
+    let upper = name.to_ascii_uppercase();
+    [
+        "TOKEN",
+        "SECRET",
+        "PASSWORD",
+        "PASSWD",
+        "API_KEY",
+        "PRIVATE_KEY",
+        "CREDENTIAL",
+    ]
+    .iter()
+    .any(|marker| upper.contains(marker))
+}
@@ -0,0 +1,150 @@
+//! Numbered plan files applied in order, like database migrations.
+//!
+//! A migrations directory holds plan files named `<version>_<name>.toml`,
+//! such as `001_init.toml` and `002_add_cache.toml`. Migrations are
+//! applied in version order and reverted newest first; the directory's
+//! journal records which are applied (see
+//! [`Journal::applied_migrations`]).
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{ShiftError, ShiftResult};
+use crate::journal::Journal;
+
+/// One migration plan file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migration {
+    pub version: u64,
+    /// The part of the file name after the version, e.g. `add_cache`.
+    pub name: String,
+    pub path: PathBuf,
+}
+
+impl Migration {
+    /// Parses a file name like `002_add_cache.toml`.
+    fn from_path(path: &Path) -> Option<Migration> {
+        if path.extension()? != "toml" {
+            return None;
+        }
+        let stem = path.file_stem()?.to_str()?;
+        let (version, name) = stem.split_once('_').unwrap_or((stem, ""));
+        if version.is_empty() || !version.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        Some(Migration {
+            version: version.parse().ok()?,
+            name: name.to_string(),
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+/// The migrations in a directory, in version order.
+pub struct Migrations {
+    dir: PathBuf,
+    migrations: Vec<Migration>,
+}
+
+impl Migrations {
+    /// Finds the migrations in `dir`. Other files are ignored; two files
+    /// with the same version are an error.
+    pub fn discover(dir: impl Into<PathBuf>) -> ShiftResult<Migrations> {
+        let dir = dir.into();
+        let mut migrations = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            if let Some(migration) = Migration::from_path(&entry?.path()) {
+                migrations.push(migration);
+            }
+        }
+        migrations.sort_by_key(|migration| migration.version);
+        if let Some(pair) = migrations
+            .windows(2)
+            .find(|pair| pair[0].version == pair[1].version)
+        {
+            return Err(ShiftError::Plan(format!(
+                "{} and {} have the same migration version",
+                pair[0].path.display(),
+                pair[1].path.display()
+            )));
+        }
+        if migrations.is_empty() {
+            return Err(ShiftError::Plan(format!(
+                "no migrations (files like 001_init.toml) in {}",
+                dir.display()
+            )));
+        }
+        Ok(Migrations { dir, migrations })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn all(&self) -> &[Migration] {
+        &self.migrations
+    }
+
+    pub fn journal_path(&self) -> PathBuf {
+        Journal::path_in(&self.dir)
+    }
+
+    /// The version the directory is at: the newest applied migration, or
+    /// 0 if none is.
+    pub fn current(applied: &BTreeSet<u64>) -> u64 {
+        applied.last().copied().unwrap_or(0)
+    }
+
+    /// Migrations to apply, oldest first, to go from `applied` up to
+    /// version `to` (the newest if `None`).
+    ///
+    /// Migrations apply strictly in order, so one that is not applied but
+    /// older than the current version (say, added after a newer one was
+    /// applied) is an error rather than being applied out of order.
+    pub fn pending(
+        &self,
+        applied: &BTreeSet<u64>,
+        to: Option<u64>,
+    ) -> ShiftResult<Vec<&Migration>> {
+        let current = Self::current(applied);
+        if let Some(skipped) = self
+            .migrations
+            .iter()
+            .find(|m| m.version < current && !applied.contains(&m.version))
+        {
+            return Err(ShiftError::Plan(format!(
+                "{} is older than the current version {current} but was never applied; \
+                 revert to before it first",
+                skipped.path.display()
+            )));
+        }
+        let to = to.unwrap_or(u64::MAX);
+        Ok(self
+            .migrations
+            .iter()
+            .filter(|m| m.version > current && m.version <= to)
+            .collect())
+    }
+
+    /// Applied migrations to revert, newest first, to go down to version
+    /// `to`. An applied version whose file is gone is an error.
+    pub fn to_revert(&self, applied: &BTreeSet<u64>, to: u64) -> ShiftResult<Vec<&Migration>> {
+        applied
+            .iter()
+            .rev()
+            .take_while(|&&version| version > to)
+            .map(|&version| {
+                self.migrations
+                    .iter()
+                    .find(|m| m.version == version)
+                    .ok_or_else(|| {
+                        ShiftError::Plan(format!(
+                            "migration {version} is applied but its file is missing from {}",
+                            self.dir.display()
+                        ))
+                    })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,266 @@
+//! Workspaces holding several plans, such as a monorepo with a setup plan
+//! per service.
+//!
+//! Every `skies.toml` under the workspace directory is one of its plans,
+//! except in hidden directories and in `node_modules`, `target` and
+//! `vendor`. A plan lists the plans it needs applied first in a top-level
+//! `requires`, by their file or directory, relative to its own directory:
+//!
+//! ```toml
+//! # services/api/skies.toml
+//! requires = ["../db"]
+//!
+//! [[shift]]
+//! type = "cmd"
+//! program = "npm"
+//! args = ["ci"]
+//! ```
+//!
+//! `skies apply --recursive` applies them in that order, and otherwise
+//! outermost first, then in path order. A plan also inherits the `[vars]`
+//! of the plans in the directories above it, the nearest winning, as
+//! defaults for the variables it does not set itself; a `skies.toml` at
+//! the top of the workspace can hold what every service shares.
+//!
+//! Expressions in a plan can use the outputs of the plans it requires as
+//! of their last apply, as `plans.<plan>.<shift>.<name>`, where `<plan>`
+//! is the name of the required plan's directory:
+//!
+//! ```toml
+//! requires = ["../db"]
+//!
+//! [[shift]]
+//! type = "file"
+//! path = ".env"
+//! contents = "DATABASE_PORT=${plans.db.server.port}\n"
+//! ```
+//!
+//! Since those values are part of the plan as loaded, a plan whose
+//! required plans produce something new no longer counts as unchanged for
+//! `skies apply --recursive --skip-unchanged`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::error::{ShiftError, ShiftResult};
+use crate::journal::Journal;
+use crate::outputs::Output;
+use crate::paths;
+
+/// The name of the files that are a workspace's plans.
+pub const PLAN_FILE: &str = "skies.toml";
+
+/// Directories not searched for plans, besides hidden ones.
+const SKIPPED: &[&str] = &["node_modules", "target", "vendor"];
+
+/// One plan of a workspace.
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub path: PathBuf,
+    /// The plan files it requires, as found in the workspace, with the
+    /// names its expressions know them by.
+    pub requires: Vec<(String, PathBuf)>,
+    vars: BTreeMap<String, String>,
+    /// Names it gives a value itself, in `[vars]` or `[params]`.
+    own: BTreeSet<String>,
+}
+
+/// Just the parts of a plan file this module reads.
+#[derive(Deserialize)]
+struct RawMember {
+    #[serde(default)]
+    requires: Vec<PathBuf>,
+    #[serde(default)]
+    vars: BTreeMap<String, String>,
+    #[serde(default)]
+    params: toml::Table,
+}
+
+impl Member {
+    /// Reads what the workspace needs of the plan file at `path`.
+    pub fn load(path: PathBuf) -> ShiftResult<Member> {
+        let source = fs::read_to_string(&path)
+            .map_err(|err| ShiftError::Plan(format!("cannot read {}: {err}", path.display())))?;
+        let raw: RawMember = toml::from_str(&source)
+            .map_err(|err| ShiftError::Plan(format!("{}: {}", path.display(), err.message())))?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let mut requires: Vec<(String, PathBuf)> = Vec::new();
+        for required in &raw.requires {
+            let required = paths::normalize(&dir.join(required));
+            let required = match required.is_dir() {
+                true => required.join(PLAN_FILE),
+                false => required,
+            };
+            let absolute = paths::normalize(&std::path::absolute(&required)?);
+            let name = absolute
+                .parent()
+                .and_then(Path::file_name)
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            if requires.iter().any(|(other, _)| *other == name) {
+                return Err(ShiftError::Plan(format!(
+                    "{}: requires two plans in directories named `{name}`",
+                    path.display()
+                ))
+                .hint("`plans.<name>` could not tell them apart; rename one directory"));
+            }
+            requires.push((name, required));
+        }
+        let own = raw.vars.keys().chain(raw.params.keys()).cloned().collect();
+        Ok(Member {
+            path,
+            requires,
+            vars: raw.vars,
+            own,
+        })
+    }
+
+    fn dir(&self) -> &Path {
+        self.path.parent().unwrap_or(Path::new(""))
+    }
+
+    /// The outputs in effect of each plan it requires, by name, from their
+    /// journals.
+    pub fn required_outputs(&self) -> ShiftResult<BTreeMap<String, BTreeMap<String, Vec<Output>>>> {
+        let mut by_plan = BTreeMap::new();
+        for (name, path) in &self.requires {
+            let journal = Journal::load(&Journal::path_for(path))?;
+            let outputs = journal
+                .outputs()
+                .into_iter()
+                .map(|(id, list)| (id.to_string(), list.to_vec()))
+                .collect();
+            by_plan.insert(name.clone(), outputs);
+        }
+        Ok(by_plan)
+    }
+}
+
+/// The plans of a workspace, in the order they apply.
+pub struct Monorepo {
+    root: PathBuf,
+    members: Vec<Member>,
+}
+
+impl Monorepo {
+    /// Finds the plans under `root` and orders them. A `requires` naming a
+    /// plan outside the workspace, or plans requiring each other, are
+    /// errors.
+    pub fn discover(root: impl Into<PathBuf>) -> ShiftResult<Monorepo> {
+        let root = root.into();
+        if !root.is_dir() {
+            return Err(
+                ShiftError::Plan(format!("{} is not a directory", root.display()))
+                    .hint("--recursive takes the workspace's directory instead of a plan file"),
+            );
+        }
+        let mut found = Vec::new();
+        search(&root, &mut found)?;
+        if found.is_empty() {
+            return Err(ShiftError::Plan(format!(
+                "no {PLAN_FILE} under {}",
+                root.display()
+            )));
+        }
+        // Outer plans first, as they tend to hold what the others share.
+        found.sort_by_key(|path| (path.components().count(), path.clone()));
+        let members = found
+            .into_iter()
+            .map(Member::load)
+            .collect::<ShiftResult<Vec<_>>>()?;
+        Ok(Monorepo {
+            members: order(members)?,
+            root,
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The plans, each after the plans it requires.
+    pub fn members(&self) -> &[Member] {
+        &self.members
+    }
+
+    /// The variables `member` inherits from the plans in the directories
+    /// above it, leaving out those it sets itself.
+    pub fn inherited_vars(&self, member: &Member) -> BTreeMap<String, String> {
+        let mut ancestors: Vec<&Member> = self
+            .members
+            .iter()
+            .filter(|other| other.path != member.path && member.dir().starts_with(other.dir()))
+            .collect();
+        // Outermost first, so nearer plans overwrite.
+        ancestors.sort_by_key(|other| other.dir().components().count());
+        let mut vars = BTreeMap::new();
+        for ancestor in ancestors {
+            vars.extend(ancestor.vars.clone());
+        }
+        vars.retain(|name, _| !member.own.contains(name));
+        vars
+    }
+}
+
+fn search(dir: &Path, found: &mut Vec<PathBuf>) -> ShiftResult<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if entry.file_type()?.is_dir() {
+            if !name.starts_with('.') && !SKIPPED.contains(&name.as_ref()) {
+                search(&path, found)?;
+            }
+        } else if name == PLAN_FILE {
+            found.push(paths::normalize(&path));
+        }
+    }
+    Ok(())
+}
+
+/// `members`, in discovery order, put in an order where each comes after
+/// what it requires and otherwise keeps its place.
+fn order(members: Vec<Member>) -> ShiftResult<Vec<Member>> {
+    for member in &members {
+        if let Some(missing) = member
+            .requires
+            .iter()
+            .map(|(_, required)| required)
+            .find(|required| !members.iter().any(|other| &other.path == *required))
+        {
+            return Err(ShiftError::Plan(format!(
+                "{} requires {}, which is not a plan in the workspace",
+                member.path.display(),
+                missing.display()
+            )));
+        }
+    }
+    let mut pending = members;
+    let mut ordered: Vec<Member> = Vec::with_capacity(pending.len());
+    while !pending.is_empty() {
+        let ready = pending.iter().position(|member| {
+            member
+                .requires
+                .iter()
+                .all(|(_, required)| ordered.iter().any(|done| &done.path == required))
+        });
+        match ready {
+            Some(idx) => ordered.push(pending.remove(idx)),
+            None => {
+                let cycle: Vec<String> = pending
+                    .iter()
+                    .map(|member| member.path.display().to_string())
+                    .collect();
+                return Err(ShiftError::Plan(format!(
+                    "these plans require each other: {}",
+                    cycle.join(", ")
+                )));
+            }
+        }
+    }
+    Ok(ordered)
+}
@@ -0,0 +1,180 @@
+//! Plan-wide limits on downloads and clones.
+//!
+//! A `[network]` table in the plan file, or `skies apply --max-transfers`
+//! and `--rate-limit`, keeps a run from taking the whole connection:
+//!
+//! ```toml
+//! [network]
+//! max_transfers = 2
+//! rate_limit = "1M"
+//! ```
+//!
+//! `max_transfers` caps how many downloads and clones run at once, across
+//! parallel shifts; the rest wait their turn. `rate_limit` (bytes per
+//! second, with an optional `K`, `M` or `G` suffix) is shared evenly
+//! between those transfers, one if `max_transfers` is not set. Downloads
+//! honour it; git has no way to throttle a clone, so clones only count
+//! toward `max_transfers`.
+
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::context::ExecutionContext;
+use crate::error::ShiftResult;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkPolicy {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_transfers: Option<usize>,
+    /// Bytes per second.
+    #[serde(
+        default,
+        deserialize_with = "de_rate",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schemars(schema_with = "rate_schema")]
+    pub rate_limit: Option<u64>,
+}
+
+impl NetworkPolicy {
+    /// Fields set in `overrides` replace the ones in `self`.
+    pub fn merged(self, overrides: NetworkPolicy) -> NetworkPolicy {
+        NetworkPolicy {
+            max_transfers: overrides.max_transfers.or(self.max_transfers),
+            rate_limit: overrides.rate_limit.or(self.rate_limit),
+        }
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.max_transfers.is_none() && self.rate_limit.is_none()
+    }
+
+    /// How many transfers may run at once, if limited.
+    fn slots(&self) -> Option<usize> {
+        match (self.max_transfers, self.rate_limit) {
+            (Some(max), _) => Some(max.max(1)),
+            (None, Some(_)) => Some(1),
+            (None, None) => None,
+        }
+    }
+
+    /// Each transfer's share of `rate_limit`.
+    fn rate_per_transfer(&self) -> Option<u64> {
+        let slots = self.slots()? as u64;
+        self.rate_limit.map(|rate| (rate / slots).max(1))
+    }
+}
+
+/// Parses a rate such as `500K` or `2M` into bytes per second.
+pub fn parse_rate(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let (digits, unit) = match text.char_indices().last() {
+        Some((idx, c)) if c.is_ascii_alphabetic() => (&text[..idx], c.to_ascii_uppercase()),
+        _ => (text, 'B'),
+    };
+    let factor: u64 = match unit {
+        'B' => 1,
+        'K' => 1 << 10,
+        'M' => 1 << 20,
+        'G' => 1 << 30,
+        _ => return Err(format!("`{text}` has an unknown unit; use K, M or G")),
+    };
+    match digits.trim().parse::<u64>() {
+        Ok(value) if value > 0 => value
+            .checked_mul(factor)
+            .ok_or_else(|| format!("`{text}` is too large")),
+        _ => Err(format!(
+            "`{text}` is not a rate such as `500K` or `2M` (bytes per second)"
+        )),
+    }
+}
+
+/// A number of bytes, or a rate such as `500K`; see [`parse_rate`].
+fn rate_schema(_: &mut SchemaGenerator) -> Schema {
+    json_schema!({ "type": ["integer", "string"], "minimum": 1 })
+}
+
+fn de_rate<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Rate {
+        Bytes(u64),
+        Text(String),
+    }
+    match Rate::deserialize(deserializer)? {
+        Rate::Bytes(0) => Err(serde::de::Error::custom("`rate_limit` must be above 0")),
+        Rate::Bytes(bytes) => Ok(Some(bytes)),
+        Rate::Text(text) => parse_rate(&text)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+    }
+}
+
+/// The transfers running under one policy, shared by every shift of a run.
+#[derive(Debug)]
+pub struct Transfers {
+    policy: NetworkPolicy,
+    active: Mutex<usize>,
+    freed: Condvar,
+}
+
+impl Transfers {
+    pub fn new(policy: NetworkPolicy) -> Self {
+        Transfers {
+            policy,
+            active: Mutex::new(0),
+            freed: Condvar::new(),
+        }
+    }
+
+    pub fn policy(&self) -> &NetworkPolicy {
+        &self.policy
+    }
+
+    /// Waits for a free slot, then runs `transfer` with its rate limit in
+    /// bytes per second, if any.
+    fn run<T>(
+        &self,
+        ctx: &ExecutionContext,
+        transfer: impl FnOnce(Option<u64>) -> ShiftResult<T>,
+    ) -> ShiftResult<T> {
+        let Some(slots) = self.policy.slots() else {
+            return transfer(None);
+        };
+        {
+            let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+            if *active >= slots {
+                ctx.debug("waiting for another download or clone to finish");
+            }
+            while *active >= slots {
+                ctx.check_cancelled()?;
+                active = self
+                    .freed
+                    .wait_timeout(active, Duration::from_millis(200))
+                    .unwrap_or_else(|e| e.into_inner())
+                    .0;
+            }
+            *active += 1;
+        }
+        let result = transfer(self.policy.rate_per_transfer());
+        *self.active.lock().unwrap_or_else(|e| e.into_inner()) -= 1;
+        self.freed.notify_one();
+        result
+    }
+}
+
+/// Runs `transfer`, a download or clone, within the context's network
+/// limits; it gets the rate to hold to, in bytes per second, if any.
+pub fn transfer<T>(
+    ctx: &ExecutionContext,
+    transfer: impl FnOnce(Option<u64>) -> ShiftResult<T>,
+) -> ShiftResult<T> {
+    match ctx.transfers() {
+        Some(transfers) => transfers.run(ctx, transfer),
+        None => transfer(None),
+    }
+}
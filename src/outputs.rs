@@ -0,0 +1,77 @@
+//! Artifacts shifts produce, like where a repository was cloned or a
+//! generated password, for tooling that runs after skies.
+//!
+//! A shift reports them from `apply` with [`ExecutionContext::output`];
+//! each run's outputs are kept in the journal with the shift that
+//! produced them, and [`Journal::outputs`] gathers the ones still in
+//! effect.
+//!
+//! [`ExecutionContext::output`]: crate::ExecutionContext::output
+//! [`Journal::outputs`]: crate::journal::Journal::outputs
+
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard};
+
+use serde::{Deserialize, Serialize};
+
+const REDACTED: &str = "[redacted]";
+
+/// A named value a shift produced.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Output {
+    pub name: String,
+    pub value: serde_json::Value,
+    /// Credentials and the like; shown as `[redacted]` unless asked for.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sensitive: bool,
+}
+
+impl Output {
+    /// The value for display: the value itself, or a placeholder if it is
+    /// sensitive and `reveal` is not set.
+    pub fn shown(&self, reveal: bool) -> serde_json::Value {
+        if self.sensitive && !reveal {
+            serde_json::Value::String(REDACTED.into())
+        } else {
+            self.value.clone()
+        }
+    }
+}
+
+/// Outputs reported during a run, by shift ID.
+#[derive(Debug, Default)]
+pub struct Outputs {
+    by_shift: Mutex<BTreeMap<String, Vec<Output>>>,
+}
+
+impl Outputs {
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<String, Vec<Output>>> {
+        self.by_shift
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Records `output` for `shift`, replacing an earlier one of the same
+    /// name.
+    pub fn record(&self, shift: &str, output: Output) {
+        let mut by_shift = self.lock();
+        let outputs = by_shift.entry(shift.to_string()).or_default();
+        outputs.retain(|existing| existing.name != output.name);
+        outputs.push(output);
+    }
+
+    /// Removes and returns the outputs of `shift`, including those of the
+    /// sub-shifts scoped under it (`shift/inner`).
+    pub fn take(&self, shift: &str) -> Vec<Output> {
+        let nested = format!("{shift}/");
+        let mut by_shift = self.lock();
+        let ids: Vec<String> = by_shift
+            .keys()
+            .filter(|id| *id == shift || id.starts_with(&nested))
+            .cloned()
+            .collect();
+        ids.iter()
+            .flat_map(|id| by_shift.remove(id).unwrap_or_default())
+            .collect()
+    }
+}
@@ -0,0 +1,146 @@
+//! Installing system packages with whichever package manager the machine
+//! has, for shifts that need a daemon or tool before configuring it.
+
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::shifts::Cmd;
+use crate::validate::find_program;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackageManager {
+    Apt,
+    Dnf,
+    Yum,
+    Pacman,
+    Zypper,
+    Apk,
+    /// Homebrew, for macOS; on Linux only without any of the others.
+    Brew,
+}
+
+impl PackageManager {
+    /// Every supported package manager, in the order [`detect`](Self::detect)
+    /// looks for them.
+    pub const ALL: [PackageManager; 7] = [
+        PackageManager::Apt,
+        PackageManager::Dnf,
+        PackageManager::Yum,
+        PackageManager::Pacman,
+        PackageManager::Zypper,
+        PackageManager::Apk,
+        PackageManager::Brew,
+    ];
+
+    /// The first package manager found on `PATH`.
+    pub fn detect() -> Option<PackageManager> {
+        Self::ALL
+            .into_iter()
+            .find(|manager| find_program(manager.program()).is_some())
+    }
+
+    /// Like [`detect`](Self::detect), failing if there is none.
+    pub fn require() -> ShiftResult<PackageManager> {
+        Self::detect().ok_or_else(|| {
+            ShiftError::Custom(
+                "no supported package manager (apt-get, dnf, yum, pacman, zypper, apk, brew) found"
+                    .into(),
+            )
+        })
+    }
+
+    pub fn program(self) -> &'static str {
+        match self {
+            PackageManager::Apt => "apt-get",
+            PackageManager::Dnf => "dnf",
+            PackageManager::Yum => "yum",
+            PackageManager::Pacman => "pacman",
+            PackageManager::Zypper => "zypper",
+            PackageManager::Apk => "apk",
+            PackageManager::Brew => "brew",
+        }
+    }
+
+    /// Whether `package` is installed.
+    pub fn is_installed(self, ctx: &ExecutionContext, package: &str) -> ShiftResult<bool> {
+        let query = match self {
+            PackageManager::Apt => Cmd::new("dpkg-query").args(["-W", "-f=${Status}", package]),
+            PackageManager::Dnf | PackageManager::Yum | PackageManager::Zypper => {
+                Cmd::new("rpm").args(["-q", package])
+            }
+            PackageManager::Pacman => Cmd::new("pacman").args(["-Q", package]),
+            PackageManager::Apk => Cmd::new("apk").args(["info", "-e", package]),
+            PackageManager::Brew => Cmd::new("brew").args(["list", "--versions", package]),
+        };
+        match query.output(ctx) {
+            // dpkg also knows removed packages whose config is left.
+            Ok(out) if self == PackageManager::Apt => Ok(out.contains("install ok installed")),
+            Ok(_) => Ok(true),
+            Err(ShiftError::Command { .. }) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// The arguments to [`program`](Self::program) that install packages
+    /// without prompting, before the packages themselves.
+    pub fn install_args(self) -> &'static [&'static str] {
+        match self {
+            PackageManager::Apt => &["install", "-y", "--no-install-recommends"],
+            PackageManager::Dnf | PackageManager::Yum => &["install", "-y"],
+            PackageManager::Pacman => &["-S", "--noconfirm", "--needed"],
+            PackageManager::Zypper => &["--non-interactive", "install"],
+            PackageManager::Apk => &["add"],
+            PackageManager::Brew => &["install"],
+        }
+    }
+
+    /// Environment the package manager needs to run without prompting.
+    pub fn env(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            PackageManager::Apt => &[("DEBIAN_FRONTEND", "noninteractive")],
+            _ => &[],
+        }
+    }
+
+    /// Installs `packages` without prompting.
+    pub fn install(self, ctx: &ExecutionContext, packages: &[&str]) -> ShiftResult<()> {
+        let mut cmd = Cmd::new(self.program()).args(self.install_args().iter().copied());
+        for (name, value) in self.env() {
+            cmd = cmd.env(*name, *value);
+        }
+        // apt takes what `skies fetch` cached; see `crate::artifacts`.
+        let cmd = match ctx.artifacts() {
+            Some(cache) if self == PackageManager::Apt => {
+                let cmd = cmd.args(cache.apt_options()?);
+                if cache.is_offline() {
+                    cmd.arg("--no-download")
+                } else {
+                    cmd
+                }
+            }
+            Some(cache) if cache.is_offline() => {
+                return Err(ShiftError::Custom(format!(
+                    "cannot install {} offline: only apt packages are cached",
+                    packages.join(" ")
+                )));
+            }
+            _ => cmd,
+        };
+        cmd.args(packages.iter().copied()).output(ctx).map(drop)
+    }
+
+    /// Removes `packages` without prompting.
+    pub fn remove(self, ctx: &ExecutionContext, packages: &[&str]) -> ShiftResult<()> {
+        let cmd = match self {
+            PackageManager::Apt => Cmd::new("apt-get")
+                .args(["remove", "-y"])
+                .env("DEBIAN_FRONTEND", "noninteractive"),
+            PackageManager::Dnf => Cmd::new("dnf").args(["remove", "-y"]),
+            PackageManager::Yum => Cmd::new("yum").args(["remove", "-y"]),
+            PackageManager::Pacman => Cmd::new("pacman").args(["-R", "--noconfirm"]),
+            PackageManager::Zypper => Cmd::new("zypper").args(["--non-interactive", "remove"]),
+            PackageManager::Apk => Cmd::new("apk").arg("del"),
+            PackageManager::Brew => Cmd::new("brew").arg("uninstall"),
+        };
+        cmd.args(packages.iter().copied()).output(ctx).map(drop)
+    }
+}
@@ -0,0 +1,188 @@
+//! Typed plan parameters.
+//!
+//! A plan file declares parameters in a `[params]` table. Each is a
+//! variable (`${port}`) whose value, from `--param`, `--var` or the
+//! default, is checked against its type and rules before anything runs:
+//!
+//! ```toml
+//! [params.port]
+//! type = "int"
+//! default = 8080
+//! min = 1
+//! max = 65535
+//! description = "Port the app listens on"
+//!
+//! [params.env]
+//! type = "enum"
+//! values = ["dev", "staging", "prod"]
+//!
+//! [params.checkout]
+//! type = "path"
+//! exists = true
+//! ```
+//!
+//! Types are `string` (the default, optionally with a regex `pattern`),
+//! `int` (with `min` and `max`), `bool`, `enum` (one of `values`) and
+//! `path` (which must exist with `exists = true`). A parameter without a
+//! default is required.
+
+use std::fmt;
+use std::path::Path;
+
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::context::ExecutionContext;
+
+/// What values a parameter takes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ParamType {
+    #[default]
+    String,
+    Int,
+    Bool,
+    Enum,
+    Path,
+}
+
+impl fmt::Display for ParamType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            ParamType::String => "string",
+            ParamType::Int => "int",
+            ParamType::Bool => "bool",
+            ParamType::Enum => "enum",
+            ParamType::Path => "path",
+        })
+    }
+}
+
+/// A declared parameter and its rules.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Param {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: ParamType,
+    /// Used when no value is given; without one the parameter is required.
+    pub default: Option<String>,
+    pub description: Option<String>,
+    /// Bounds for `int` parameters.
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+    /// The allowed values of an `enum` parameter.
+    pub values: Vec<String>,
+    /// A regex `string` values must match.
+    pub pattern: Option<String>,
+    /// Whether a `path` must exist.
+    pub exists: bool,
+}
+
+impl Param {
+    pub fn new(name: impl Into<String>, kind: ParamType) -> Self {
+        Param {
+            name: name.into(),
+            kind,
+            default: None,
+            description: None,
+            min: None,
+            max: None,
+            values: Vec::new(),
+            pattern: None,
+            exists: false,
+        }
+    }
+
+    /// Checks the rules themselves make sense for the type.
+    pub fn validate(&self) -> Result<(), String> {
+        let only = |rule: &str, kind: ParamType| Err(format!("{rule} only for {kind} parameters"));
+        if (self.min.is_some() || self.max.is_some()) && self.kind != ParamType::Int {
+            return only("`min` and `max` are", ParamType::Int);
+        }
+        if !self.values.is_empty() && self.kind != ParamType::Enum {
+            return only("`values` is", ParamType::Enum);
+        }
+        if self.pattern.is_some() && self.kind != ParamType::String {
+            return only("`pattern` is", ParamType::String);
+        }
+        if self.exists && self.kind != ParamType::Path {
+            return only("`exists` is", ParamType::Path);
+        }
+        if self.kind == ParamType::Enum && self.values.is_empty() {
+            return Err("enum parameters need `values`".into());
+        }
+        if let Some(pattern) = &self.pattern {
+            Regex::new(pattern).map_err(|err| format!("invalid pattern `{pattern}`: {err}"))?;
+        }
+        Ok(())
+    }
+
+    /// `value` checked against the parameter's type and rules, in its
+    /// canonical form (`true`/`false` for booleans, the number for ints).
+    pub fn check(&self, value: &str, ctx: &ExecutionContext) -> Result<String, String> {
+        match self.kind {
+            ParamType::String => {
+                if let Some(pattern) = &self.pattern {
+                    let re = Regex::new(pattern).map_err(|err| err.to_string())?;
+                    if !re.is_match(value) {
+                        return Err(format!("`{value}` does not match `{pattern}`"));
+                    }
+                }
+                Ok(value.to_string())
+            }
+            ParamType::Int => {
+                let n: i64 = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("expected an integer, got `{value}`"))?;
+                match (self.min, self.max) {
+                    (Some(min), _) if n < min => Err(format!("{n} is below the minimum of {min}")),
+                    (_, Some(max)) if n > max => Err(format!("{n} is above the maximum of {max}")),
+                    _ => Ok(n.to_string()),
+                }
+            }
+            ParamType::Bool => match value.trim().to_ascii_lowercase().as_str() {
+                "true" | "yes" | "on" | "1" => Ok("true".into()),
+                "false" | "no" | "off" | "0" => Ok("false".into()),
+                _ => Err(format!("expected true or false, got `{value}`")),
+            },
+            ParamType::Enum => {
+                if self.values.iter().any(|allowed| allowed == value) {
+                    Ok(value.to_string())
+                } else {
+                    Err(format!(
+                        "`{value}` is not one of {}",
+                        self.values
+                            .iter()
+                            .map(|v| format!("`{v}`"))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ))
+                }
+            }
+            ParamType::Path => {
+                let path = ctx.expand_home(Path::new(value));
+                if self.exists && !ctx.join_root(&path).exists() {
+                    return Err(format!("{} does not exist", path.display()));
+                }
+                Ok(path.display().to_string())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Param {
+    /// `name (type, default value): description`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}", self.name, self.kind)?;
+        match &self.default {
+            Some(default) => write!(f, ", default {default})")?,
+            None => write!(f, ", required)")?,
+        }
+        if let Some(description) = &self.description {
+            write!(f, ": {description}")?;
+        }
+        Ok(())
+    }
+}
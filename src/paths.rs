@@ -0,0 +1,103 @@
+//! Path normalization used to keep shifts inside the plan root.
+
+use std::path::{Component, Path, PathBuf};
+
+/// Removes `.` and resolves `..` components without touching the disk.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !out.pop() {
+                    out.push(component);
+                }
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// Resolves symlinks in the longest existing prefix of `path` and appends
+/// the components that do not exist yet.
+pub fn real_path(path: &Path) -> PathBuf {
+    let mut existing = path;
+    let mut missing = Vec::new();
+    loop {
+        if let Ok(canonical) = existing.canonicalize() {
+            let mut out = canonical;
+            out.extend(missing.iter().rev());
+            return out;
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name);
+                existing = parent;
+            }
+            _ => return path.to_path_buf(),
+        }
+    }
+}
+
+/// `path` as the kernel would follow it: symlinks resolved component by
+/// component, each `..` applied to what came before it resolved to. The
+/// components past the deepest one that exists are appended with `.` and
+/// `..` resolved lexically.
+pub fn resolve(path: &Path) -> PathBuf {
+    resolve_within(path, 0)
+}
+
+/// How many dangling symlinks [`resolve`] follows before giving up, as
+/// with `ELOOP`.
+const MAX_LINKS: usize = 40;
+
+fn resolve_within(path: &Path, links: usize) -> PathBuf {
+    let mut out = PathBuf::new();
+    let mut components = path.components();
+    while let Some(component) = components.next() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !out.pop() && !out.has_root() {
+                    out.push(component);
+                }
+            }
+            Component::Normal(name) => {
+                let next = out.join(name);
+                if let Ok(real) = next.canonicalize() {
+                    out = real;
+                    continue;
+                }
+                // A dangling symlink still leads wherever it points once
+                // something is created through it.
+                if links < MAX_LINKS && next.is_symlink() {
+                    if let Ok(target) = next.read_link() {
+                        let followed = resolve_within(&out.join(target), links + 1);
+                        return normalize(&followed.join(components.as_path()));
+                    }
+                }
+                return normalize(&next.join(components.as_path()));
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// `path` with `..`, `.` and symlinks resolved as far as the disk allows.
+pub fn canonical(path: &Path) -> PathBuf {
+    real_path(&normalize(path))
+}
+
+/// Replaces a leading `~` component with `home`. Paths without one, and
+/// all paths when `home` is unknown, are returned unchanged.
+pub fn expand_home(path: &Path, home: Option<&Path>) -> PathBuf {
+    let mut components = path.components();
+    match (components.next(), home) {
+        (Some(Component::Normal(first)), Some(home)) if first == "~" => {
+            home.join(components.as_path())
+        }
+        _ => path.to_path_buf(),
+    }
+}
@@ -0,0 +1,105 @@
+//! Plan-wide defaults for the permissions of created files and directories.
+
+use std::path::Path;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ResultExt, ShiftResult};
+use crate::fs::Fs;
+
+/// Default modes for file-creating shifts. A mode set on the shift itself
+/// always wins; otherwise `file_mode`/`dir_mode` apply, and failing those
+/// the `umask` is applied to `0o666` (files) or `0o777` (directories).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PermissionPolicy {
+    #[serde(default)]
+    pub umask: Option<u32>,
+    #[serde(default)]
+    pub file_mode: Option<u32>,
+    #[serde(default)]
+    pub dir_mode: Option<u32>,
+}
+
+impl PermissionPolicy {
+    /// Fields set in `overrides` replace the ones in `self`.
+    pub fn merged(self, overrides: PermissionPolicy) -> PermissionPolicy {
+        PermissionPolicy {
+            umask: overrides.umask.or(self.umask),
+            file_mode: overrides.file_mode.or(self.file_mode),
+            dir_mode: overrides.dir_mode.or(self.dir_mode),
+        }
+    }
+
+    /// Mode a file should get, or `None` to leave the OS default.
+    pub fn file_mode(&self, explicit: Option<u32>) -> Option<u32> {
+        explicit
+            .or(self.file_mode)
+            .or(self.umask.map(|umask| 0o666 & !umask))
+    }
+
+    /// Mode a directory should get, or `None` to leave the OS default.
+    pub fn dir_mode(&self, explicit: Option<u32>) -> Option<u32> {
+        explicit
+            .or(self.dir_mode)
+            .or(self.umask.map(|umask| 0o777 & !umask))
+    }
+}
+
+/// Permission bits of `path`. Always `None` off Unix.
+#[cfg(unix)]
+pub fn mode_of(path: &Path) -> ShiftResult<Option<u32>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    Ok(Some(std::fs::metadata(path)?.permissions().mode() & 0o7777))
+}
+
+#[cfg(not(unix))]
+pub fn mode_of(_path: &Path) -> ShiftResult<Option<u32>> {
+    Ok(None)
+}
+
+/// Sets the permission bits of `path`. A no-op off Unix.
+#[cfg(unix)]
+pub fn set_mode(path: &Path, mode: u32) -> ShiftResult<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn set_mode(_path: &Path, _mode: u32) -> ShiftResult<()> {
+    Ok(())
+}
+
+/// Writes `contents` to `path`, creating the file with `mode` from the
+/// start so it is never briefly readable with looser permissions.
+pub fn write_file(path: &Path, contents: &[u8], mode: Option<u32>) -> ShiftResult<()> {
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    if let Some(mode) = mode {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(mode);
+    }
+    let writing = || format!("writing {}", path.display());
+    let mut file = options.open(path).with_context(writing)?;
+    // `mode` only applies to new files and is subject to the process umask.
+    if let Some(mode) = mode {
+        set_mode(path, mode).with_context(writing)?;
+    }
+    file.write_all(contents).with_context(writing)
+}
+
+/// Whether `path` on `fs` has `mode`, treating "no mode wanted" and
+/// platforms without modes as a match.
+pub fn mode_matches(fs: &dyn Fs, path: &Path, mode: Option<u32>) -> ShiftResult<bool> {
+    Ok(match (mode, fs.mode(path)?) {
+        (Some(want), Some(have)) => want == have,
+        _ => true,
+    })
+}
@@ -0,0 +1,1226 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::artifacts::Artifact;
+use crate::builder::PlanBuilder;
+use crate::cancel::CancellationToken;
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::hash::{sha256_hex, short_hash};
+use crate::inputs::ExternalInput;
+use crate::metadata::{ShiftMetadata, REDACTED};
+use crate::network::{NetworkPolicy, Transfers};
+use crate::params::Param;
+use crate::permissions::PermissionPolicy;
+use crate::report::{NullReporter, PlanEvent, Reporter};
+use crate::resource::Claim;
+use crate::shift::{Shift, ShiftOutcome};
+use crate::validate::ValidationContext;
+
+/// A shift together with the bookkeeping the plan needs to schedule it.
+pub struct PlanEntry {
+    pub shift: Box<dyn Shift>,
+    id: String,
+    pub tags: Vec<String>,
+    /// IDs of entries that must be applied before this one.
+    pub depends_on: Vec<String>,
+    /// Lets the shift touch paths outside the plan root.
+    pub allow_outside_root: bool,
+    /// How long the shift may take, including its `is_applied` check.
+    pub time_limit: Option<Duration>,
+    /// Shifts sharing a group never run at the same time, even in a
+    /// parallel apply (e.g. `apt`, which holds a lock).
+    pub serial_group: Option<String>,
+    /// Checks that must pass once the plan has applied, such as an
+    /// [`Assert`](crate::shifts::Assert) that a service answers. See
+    /// [`ShiftPlan::apply_with_options`].
+    pub verify: Vec<Box<dyn Shift>>,
+    definition: Option<toml::Table>,
+    origin: Option<String>,
+}
+
+impl PlanEntry {
+    pub fn new(shift: impl Shift + 'static) -> Self {
+        Self::boxed(Box::new(shift))
+    }
+
+    pub fn boxed(shift: Box<dyn Shift>) -> Self {
+        PlanEntry {
+            shift,
+            id: String::new(),
+            tags: Vec::new(),
+            depends_on: Vec::new(),
+            allow_outside_root: false,
+            time_limit: None,
+            serial_group: None,
+            verify: Vec::new(),
+            definition: None,
+            origin: None,
+        }
+    }
+
+    /// Records the plan-file fields the entry was loaded from.
+    pub(crate) fn with_definition(mut self, definition: toml::Table) -> Self {
+        self.definition = Some(definition);
+        self
+    }
+
+    /// Records the [script](crate::script) that generated the entry.
+    pub(crate) fn with_origin(mut self, origin: impl Into<String>) -> Self {
+        self.origin = Some(origin.into());
+        self
+    }
+
+    /// The script that generated the entry, if a script did.
+    pub fn origin(&self) -> Option<&str> {
+        self.origin.as_deref()
+    }
+
+    /// The plan-file fields (`type` and the shift's own, plus `verify`)
+    /// this entry was loaded from, with roles and variables resolved and
+    /// sensitive inputs masked. `None` for entries built in code.
+    pub fn definition(&self) -> Option<toml::Table> {
+        let mut definition = self.definition.clone()?;
+        for input in self.shift.metadata().inputs {
+            if !input.sensitive {
+                continue;
+            }
+            let mut table = &mut definition;
+            let mut path = input.name.split('.').peekable();
+            while let Some(key) = path.next() {
+                if path.peek().is_none() {
+                    if let Some(value) = table.get_mut(key) {
+                        *value = toml::Value::String(REDACTED.into());
+                    }
+                } else if let Some(toml::Value::Table(inner)) = table.get_mut(key) {
+                    table = inner;
+                } else {
+                    break;
+                }
+            }
+        }
+        Some(definition)
+    }
+
+    /// Gives the entry an explicit ID instead of a derived one.
+    pub fn with_id(mut self, id: impl Into<String>) -> Self {
+        self.id = id.into();
+        self
+    }
+
+    /// Stable identifier used by the journal, filters and `depends_on`.
+    ///
+    /// Explicit IDs are kept as given; otherwise the plan derives one from
+    /// the shift's description when the entry is added.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+
+    /// Hash of what decides what applying this entry does, its part of
+    /// [`ShiftPlan::content_hash`]: its ID, its dependencies and the
+    /// shift's full metadata, which holds its interpolated inputs.
+    pub fn input_hash(&self) -> String {
+        sha256_hex(self.fingerprint().to_string())
+    }
+
+    /// [`input_hash`](Self::input_hash) together with what in `ctx`, the
+    /// context of the plan's shifts, also decides what applying the entry
+    /// does: the root, the permission policy and the network limits.
+    pub fn input_hash_in(&self, ctx: &ExecutionContext) -> String {
+        let canonical = serde_json::json!({
+            "entry": self.fingerprint(),
+            "root": ctx.root(),
+            "permissions": ctx.permissions(),
+            "network": ctx.transfers().map(|transfers| transfers.policy()),
+        });
+        sha256_hex(canonical.to_string())
+    }
+
+    fn fingerprint(&self) -> serde_json::Value {
+        serde_json::json!({
+            "id": self.id,
+            "depends_on": self.depends_on,
+            "allow_outside_root": self.allow_outside_root,
+            "shift": self.shift.metadata(),
+        })
+    }
+
+    /// What the shift and its verify checks need to be allowed to do, in
+    /// `ctx`: what they declare, plus
+    /// [`WritesOutsideRoot`](Capability::WritesOutsideRoot) if the entry
+    /// may leave the root or names a target outside it.
+    pub fn capabilities(&self, ctx: &ExecutionContext) -> BTreeSet<Capability> {
+        let shifts = || std::iter::once(&self.shift).chain(&self.verify);
+        let mut capabilities: BTreeSet<_> = shifts().flat_map(|s| s.capabilities()).collect();
+        let root = crate::paths::canonical(ctx.root());
+        let outside = shifts()
+            .flat_map(|s| s.metadata().targets)
+            .any(|target| !crate::paths::canonical(&ctx.join_root(&target)).starts_with(&root));
+        if self.allow_outside_root || outside {
+            capabilities.insert(Capability::WritesOutsideRoot);
+        }
+        capabilities
+    }
+}
+
+/// Knobs for [`ShiftPlan::apply_with_options`].
+pub struct ApplyOptions {
+    /// Undo this run's shifts when one fails.
+    pub rollback: bool,
+    /// IDs known to be done already (e.g. by an interrupted run); these are
+    /// skipped without consulting `is_applied`.
+    pub completed: HashSet<String>,
+    /// How many shifts may run at once. Independent shifts (no
+    /// `depends_on` path between them, different serial groups) may then
+    /// run in any order.
+    pub jobs: usize,
+    /// Asked before each shift and after each failure; see [`RunControl`].
+    pub control: Option<Arc<dyn RunControl>>,
+}
+
+impl Default for ApplyOptions {
+    fn default() -> Self {
+        ApplyOptions {
+            rollback: true,
+            completed: HashSet::new(),
+            jobs: 1,
+            control: None,
+        }
+    }
+}
+
+impl ApplyOptions {
+    /// Whether the control says to leave `entry` out.
+    fn skips(&self, entry: &PlanEntry) -> bool {
+        self.control
+            .as_ref()
+            .is_some_and(|control| control.skip(entry))
+    }
+
+    /// What the control says to do about `result`, if it is a failure
+    /// other than cancelling.
+    fn recovery(
+        &self,
+        entry: &PlanEntry,
+        result: &ShiftResult<PlanEvent<'_>>,
+    ) -> Option<FailureAction> {
+        match (&self.control, result) {
+            (Some(control), Err(err)) if !matches!(err.root(), ShiftError::Cancelled) => {
+                Some(control.on_failure(entry, err))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// What to do about a failed shift, as a [`RunControl`] decides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureAction {
+    /// Fail the run, as without a control.
+    Abort,
+    /// Run the shift again.
+    Retry,
+    /// Leave the shift failed and go on with the rest of the plan.
+    Skip,
+}
+
+/// Steers an apply shift by shift, e.g. from an interactive UI.
+pub trait RunControl: Send + Sync {
+    /// Whether to leave `entry` out, asked just before it would start.
+    /// Nothing is reported for an entry left out.
+    fn skip(&self, entry: &PlanEntry) -> bool {
+        let _ = entry;
+        false
+    }
+
+    /// What to do now that `entry` failed with `err`; the run waits for
+    /// the answer. The failure is reported either way.
+    fn on_failure(&self, entry: &PlanEntry, err: &ShiftError) -> FailureAction {
+        let _ = (entry, err);
+        FailureAction::Abort
+    }
+}
+
+/// An ordered collection of shifts applied (and rolled back) as a unit.
+#[derive(Default)]
+pub struct ShiftPlan {
+    entries: Vec<PlanEntry>,
+    root: Option<PathBuf>,
+    permissions: PermissionPolicy,
+    network: NetworkPolicy,
+    deadline: Option<Duration>,
+    checks: Vec<PlanEntry>,
+    params: Vec<Param>,
+}
+
+impl ShiftPlan {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts a fluent [`PlanBuilder`].
+    pub fn builder() -> PlanBuilder {
+        PlanBuilder::new()
+    }
+
+    /// Adds an entry, deriving its ID from its content if it has none.
+    ///
+    /// Derived IDs look like `create-directory-app-1f3a9c2e`: a slug of the
+    /// summary plus a hash of the (redacted) metadata, with a numeric suffix
+    /// when identical shifts appear more than once.
+    pub fn push(&mut self, mut entry: PlanEntry) {
+        if entry.id.is_empty() {
+            let meta = entry.shift.metadata().redacted();
+            let canonical = serde_json::to_string(&meta).unwrap_or_default();
+            let base = format!("{}-{}", slug(&meta.summary), short_hash(canonical, 8));
+            let mut id = base.clone();
+            let mut n = 2;
+            while self.entry(&id).is_some() {
+                id = format!("{base}-{n}");
+                n += 1;
+            }
+            entry.id = id;
+        }
+        self.entries.push(entry);
+    }
+
+    pub fn add(&mut self, shift: impl Shift + 'static) {
+        self.push(PlanEntry::new(shift));
+    }
+
+    pub fn entries(&self) -> &[PlanEntry] {
+        &self.entries
+    }
+
+    pub(crate) fn entries_mut(&mut self) -> &mut [PlanEntry] {
+        &mut self.entries
+    }
+
+    /// Adds a check for the verify phase that is not tied to one shift,
+    /// e.g. a health check of the whole stack. Checks get the IDs
+    /// `verify-1`, `verify-2` and so on.
+    pub fn add_check(&mut self, check: impl Shift + 'static) {
+        self.push_check(PlanEntry::new(check));
+    }
+
+    pub(crate) fn push_check(&mut self, check: PlanEntry) {
+        let id = format!("verify-{}", self.checks.len() + 1);
+        self.checks.push(check.with_id(id));
+    }
+
+    /// The plan-wide checks, in the order they run.
+    pub fn checks(&self) -> &[PlanEntry] {
+        &self.checks
+    }
+
+    /// What each entry and check needs to be allowed to do when run from
+    /// `ctx`, in execution order; see [`PlanEntry::capabilities`].
+    pub fn capabilities(
+        &self,
+        ctx: &ExecutionContext,
+    ) -> ShiftResult<Vec<(&PlanEntry, BTreeSet<Capability>)>> {
+        let ctx = self.context(ctx);
+        let order = self.execution_order()?;
+        let entries = order.into_iter().map(|idx| &self.entries[idx]);
+        Ok(entries
+            .chain(&self.checks)
+            .map(|entry| (entry, entry.capabilities(&Self::entry_context(&ctx, entry))))
+            .collect())
+    }
+
+    /// What each entry and check fetches when run from `ctx`, in execution
+    /// order; see [`crate::inputs`].
+    pub fn external_inputs(
+        &self,
+        ctx: &ExecutionContext,
+    ) -> ShiftResult<Vec<(&PlanEntry, Vec<ExternalInput>)>> {
+        let ctx = self.context(ctx);
+        let order = self.execution_order()?;
+        let entries = order.into_iter().map(|idx| &self.entries[idx]);
+        entries
+            .chain(&self.checks)
+            .map(|entry| {
+                let ctx = Self::entry_context(&ctx, entry);
+                let mut inputs = entry.shift.inputs(&ctx)?;
+                for check in &entry.verify {
+                    inputs.extend(check.inputs(&ctx)?);
+                }
+                Ok((entry, inputs))
+            })
+            .collect()
+    }
+
+    /// Directory the plan's shifts are confined to. Relative roots are
+    /// resolved against the root of the context the plan runs in.
+    pub fn set_root(&mut self, root: impl Into<PathBuf>) {
+        self.root = Some(root.into());
+    }
+
+    pub fn root(&self) -> Option<&Path> {
+        self.root.as_deref()
+    }
+
+    /// Default modes for the files and directories this plan creates.
+    /// Unset fields fall back to the policy of the surrounding context.
+    pub fn set_permissions(&mut self, permissions: PermissionPolicy) {
+        self.permissions = permissions;
+    }
+
+    pub fn permissions(&self) -> &PermissionPolicy {
+        &self.permissions
+    }
+
+    /// Limits on the plan's downloads and clones; see [`crate::network`].
+    pub fn set_network(&mut self, network: NetworkPolicy) {
+        self.network = network;
+    }
+
+    pub fn network(&self) -> &NetworkPolicy {
+        &self.network
+    }
+
+    /// Records the parameters the plan declares. Their values are already
+    /// variables by then; this is for tools that list them.
+    pub fn set_params(&mut self, params: Vec<Param>) {
+        self.params = params;
+    }
+
+    pub fn params(&self) -> &[Param] {
+        &self.params
+    }
+
+    /// Hash of everything that decides what applying the plan does: the
+    /// root, the permission defaults and, for each entry, its ID, its
+    /// dependencies and the shift's full (unredacted) metadata. Plans are
+    /// interpolated as they load, so a changed variable changes the hash;
+    /// tags, time limits and other run-time knobs do not.
+    pub fn content_hash(&self) -> String {
+        let entries: Vec<_> = self.entries.iter().map(PlanEntry::fingerprint).collect();
+        let canonical = serde_json::json!({
+            "root": self.root,
+            "permissions": self.permissions,
+            "entries": entries,
+        });
+        sha256_hex(canonical.to_string())
+    }
+
+    /// How long a whole apply or revert may take. Shifts not started by
+    /// then are not started at all.
+    pub fn set_deadline(&mut self, deadline: Duration) {
+        self.deadline = Some(deadline);
+    }
+
+    pub fn deadline(&self) -> Option<Duration> {
+        self.deadline
+    }
+
+    /// The context this plan's shifts run in: `ctx`, re-rooted if the plan
+    /// has a root of its own and with the plan's permission policy and
+    /// network limits, which its shifts share.
+    pub fn context(&self, ctx: &ExecutionContext) -> ExecutionContext {
+        let permissions = ctx.permissions().merged(self.permissions);
+        let mut ctx = ctx.clone().with_permissions(permissions);
+        if !self.network.is_unlimited() {
+            let inherited = ctx.transfers().map(|t| *t.policy()).unwrap_or_default();
+            ctx = ctx.with_transfers(Transfers::new(inherited.merged(self.network)));
+        }
+        match &self.root {
+            Some(root) => {
+                let root = ctx.join_root(root);
+                ctx.with_root(root)
+            }
+            None => ctx,
+        }
+    }
+
+    fn entry_context(ctx: &ExecutionContext, entry: &PlanEntry) -> ExecutionContext {
+        ctx.for_shift(&entry.id)
+            .allowing_outside_root(entry.allow_outside_root)
+    }
+
+    /// The context `entry` runs in under `clock`, or an error if the run
+    /// was cancelled or the plan deadline passed before it could start.
+    fn timed_context(
+        ctx: &ExecutionContext,
+        entry: &PlanEntry,
+        clock: &Clock,
+    ) -> ShiftResult<ExecutionContext> {
+        let ctx = Self::entry_context(ctx, entry);
+        if ctx.cancellation().is_cancelled() {
+            return Err(ShiftError::Cancelled);
+        }
+        if clock.plan_deadline.is_some_and(|d| Instant::now() >= d) {
+            return Err(clock.plan_expired());
+        }
+        // A limit too far off to count as an instant is no limit.
+        let limit = entry
+            .time_limit
+            .and_then(|limit| Instant::now().checked_add(limit));
+        Ok(match limit.into_iter().chain(clock.plan_deadline).min() {
+            Some(deadline) => ctx.with_deadline(deadline),
+            None => ctx,
+        })
+    }
+
+    /// `result`, or a timeout if the shift ran past the deadline in `ctx`
+    /// even though it finished. Commands are stopped at the deadline, but
+    /// work done in-process can only be checked afterwards.
+    fn check_overrun<T>(
+        result: ShiftResult<T>,
+        ctx: &ExecutionContext,
+        entry: &PlanEntry,
+        clock: &Clock,
+    ) -> ShiftResult<T> {
+        match (result, ctx.deadline()) {
+            (Ok(_), Some(deadline)) if Instant::now() > deadline => {
+                if clock.plan_deadline == Some(deadline) {
+                    Err(clock.plan_expired())
+                } else {
+                    Err(ShiftError::TimedOut(format!(
+                        "exceeded its time limit of {}s",
+                        entry.time_limit.unwrap_or_default().as_secs_f32()
+                    )))
+                }
+            }
+            (result, _) => result,
+        }
+    }
+
+    pub fn entry(&self, id: &str) -> Option<&PlanEntry> {
+        self.entries.iter().find(|e| e.id == id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Entries carrying `tag`, in declaration order.
+    pub fn tagged<'a>(&'a self, tag: &'a str) -> impl Iterator<Item = &'a PlanEntry> + 'a {
+        self.entries.iter().filter(move |e| e.has_tag(tag))
+    }
+
+    /// Restricts the plan to the given IDs and everything they depend on.
+    pub fn only(&mut self, ids: &[String]) -> ShiftResult<()> {
+        if let Some(unknown) = ids.iter().find(|id| self.entry(id).is_none()) {
+            return Err(ShiftError::Plan(format!("no shift with id `{unknown}`")));
+        }
+        self.select(|entry| ids.iter().any(|id| id == entry.id()));
+        Ok(())
+    }
+
+    /// Keeps entries matching `keep`, plus their transitive dependencies.
+    pub fn select(&mut self, keep: impl Fn(&PlanEntry) -> bool) {
+        let mut wanted: HashSet<String> = HashSet::new();
+        let mut stack: Vec<&str> = self
+            .entries
+            .iter()
+            .filter(|e| keep(e))
+            .map(|e| e.id.as_str())
+            .collect();
+        while let Some(id) = stack.pop() {
+            if wanted.insert(id.to_string()) {
+                if let Some(entry) = self.entry(id) {
+                    stack.extend(entry.depends_on.iter().map(String::as_str));
+                }
+            }
+        }
+        self.entries.retain(|e| wanted.contains(&e.id));
+    }
+
+    /// Checks IDs are unique and every dependency resolves without cycles.
+    pub fn validate(&self) -> ShiftResult<()> {
+        self.execution_order().map(|_| ())
+    }
+
+    /// For each entry, the indices of the entries it depends on.
+    fn dependencies(&self) -> ShiftResult<Vec<Vec<usize>>> {
+        let mut by_id = HashMap::new();
+        for (idx, entry) in self.entries.iter().enumerate() {
+            check_id(&entry.id)?;
+            if by_id.insert(entry.id.as_str(), idx).is_some() {
+                return Err(ShiftError::Plan(format!(
+                    "duplicate shift id `{}`",
+                    entry.id
+                )));
+            }
+        }
+
+        let mut deps = Vec::with_capacity(self.entries.len());
+        for entry in &self.entries {
+            let mut resolved = Vec::with_capacity(entry.depends_on.len());
+            for dep in &entry.depends_on {
+                match by_id.get(dep.as_str()) {
+                    Some(&idx) => resolved.push(idx),
+                    None => {
+                        return Err(ShiftError::Plan(format!(
+                            "`{}` depends on unknown shift `{dep}`",
+                            entry.id
+                        )))
+                    }
+                }
+            }
+            deps.push(resolved);
+        }
+        Ok(deps)
+    }
+
+    /// Each entry's resource claims, with paths resolved against `base`.
+    fn claims(&self, base: &ExecutionContext) -> Vec<Vec<Claim>> {
+        self.entries
+            .iter()
+            .map(|entry| {
+                let ctx = Self::entry_context(base, entry);
+                entry
+                    .shift
+                    .resources()
+                    .into_iter()
+                    .map(|claim| Claim {
+                        resource: claim.resource.resolved(&ctx),
+                        ..claim
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Indices of the entries in the order they must be applied.
+    ///
+    /// Entries keep their declaration order unless a `depends_on` forces a
+    /// dependency to run first.
+    pub fn execution_order(&self) -> ShiftResult<Vec<usize>> {
+        let deps = self.dependencies()?;
+        let mut done = vec![false; self.entries.len()];
+        let mut order = Vec::with_capacity(self.entries.len());
+        while order.len() < self.entries.len() {
+            let next = (0..self.entries.len())
+                .find(|&idx| !done[idx] && deps[idx].iter().all(|&d| done[d]));
+            match next {
+                Some(idx) => {
+                    done[idx] = true;
+                    order.push(idx);
+                }
+                None => {
+                    let stuck: Vec<_> = (0..self.entries.len())
+                        .filter(|&idx| !done[idx])
+                        .map(|idx| self.entries[idx].id.as_str())
+                        .collect();
+                    return Err(ShiftError::Plan(format!(
+                        "dependency cycle between: {}",
+                        stuck.join(", ")
+                    )));
+                }
+            }
+        }
+        Ok(order)
+    }
+
+    /// Validates every shift, in execution order, before anything runs.
+    ///
+    /// All problems are collected into a single [`ShiftError::Preflight`].
+    pub fn preflight(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        self.preflight_into(&mut ValidationContext::new(ctx))
+    }
+
+    fn preflight_into(&self, ctx: &mut ValidationContext) -> ShiftResult<()> {
+        let base = self.context(ctx.exec());
+        let mut problems = Vec::new();
+        for idx in self.execution_order()? {
+            let entry = &self.entries[idx];
+            ctx.set_exec(Self::entry_context(&base, entry));
+            if let Err(err) = entry.shift.validate(ctx) {
+                problems.push((entry.id.clone(), err));
+            }
+            let shifts = std::iter::once(&entry.shift).chain(&entry.verify);
+            for shift in shifts {
+                if let Err(err) = Self::keeps_policies(shift.as_ref(), ctx.exec()) {
+                    problems.push((entry.id.clone(), err));
+                }
+            }
+            let metadata = entry.shift.metadata();
+            for target in metadata.targets {
+                ctx.plan(target);
+            }
+            for program in metadata.programs {
+                ctx.plan_program(program);
+            }
+            for check in &entry.verify {
+                if let Err(err) = check.validate(ctx) {
+                    problems.push((entry.id.clone(), err));
+                }
+            }
+        }
+        for check in &self.checks {
+            ctx.set_exec(Self::entry_context(&base, check));
+            if let Err(err) = check.shift.validate(ctx) {
+                problems.push((check.id.clone(), err));
+            }
+            if let Err(err) = Self::keeps_policies(check.shift.as_ref(), ctx.exec()) {
+                problems.push((check.id.clone(), err));
+            }
+        }
+        let claims = self.claims(&base);
+        for (idx, entry) in self.entries.iter().enumerate() {
+            let owner = claims[..idx]
+                .iter()
+                .enumerate()
+                .find_map(|(other, theirs)| {
+                    claims[idx].iter().find_map(|claim| {
+                        theirs
+                            .iter()
+                            .any(|their| claim.conflicts(their))
+                            .then_some((other, &claim.resource))
+                    })
+                });
+            if let Some((other, resource)) = owner {
+                problems.push((
+                    entry.id.clone(),
+                    ShiftError::Conflict {
+                        resource: resource.to_string(),
+                        reason: format!("is already owned by `{}`", self.entries[other].id),
+                    },
+                ));
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ShiftError::Preflight(problems))
+        }
+    }
+
+    /// Fails if `shift` would break one of the context's policies.
+    fn keeps_policies(shift: &dyn Shift, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let meta = shift.metadata();
+        ctx.policies()
+            .iter()
+            .try_for_each(|policy| policy.check(&meta, ctx))
+    }
+
+    /// Applies every shift that is not already in place.
+    ///
+    /// If a shift fails, the shifts applied earlier in this run are reverted
+    /// in reverse order and the original error is returned. In a dry run,
+    /// shifts that would be applied are only reported.
+    pub fn apply_with(
+        &self,
+        ctx: &ExecutionContext,
+        reporter: &mut dyn Reporter,
+    ) -> ShiftResult<()> {
+        self.apply_with_options(ctx, &ApplyOptions::default(), reporter)
+    }
+
+    /// Like [`apply_with`](Self::apply_with), but with `options`. With
+    /// more than one job, a failure's rollback undoes shifts in reverse
+    /// order of completion. Revert always runs one shift at a time.
+    ///
+    /// Once every shift is in place, a verify phase runs each entry's
+    /// [`verify`](PlanEntry::verify) checks in execution order, whether or
+    /// not the entry changed anything, and then the plan's own
+    /// [`checks`](Self::checks). A failing check fails the run like a
+    /// failing shift, rollback included. Dry runs skip the phase.
+    pub fn apply_with_options(
+        &self,
+        ctx: &ExecutionContext,
+        options: &ApplyOptions,
+        reporter: &mut dyn Reporter,
+    ) -> ShiftResult<()> {
+        let order = self.execution_order()?;
+        self.preflight(ctx)?;
+        let clock = Clock::start(self.deadline);
+        let base = self.context(ctx);
+        let mut applied = Vec::new();
+        let result = if options.jobs > 1 {
+            self.apply_parallel(&base, options, &clock, &mut applied, reporter)
+        } else {
+            order.into_iter().try_for_each(|idx| {
+                let entry = &self.entries[idx];
+                if options.completed.contains(&entry.id) {
+                    reporter.report(&PlanEvent::Skipped(entry));
+                    return Ok(());
+                }
+                if options.skips(entry) {
+                    return Ok(());
+                }
+                loop {
+                    reporter.report(&PlanEvent::Started(entry));
+                    let (result, started) = self.apply_entry(&base, entry, &clock);
+                    let Some(action) = options.recovery(entry, &result) else {
+                        return self.settle(idx, result, started, &mut applied, reporter);
+                    };
+                    if action == FailureAction::Abort {
+                        return self.settle(idx, result, started, &mut applied, reporter);
+                    }
+                    if let Err(err) = &result {
+                        Self::report_failure(entry, err, reporter);
+                    }
+                    if action == FailureAction::Skip {
+                        return Ok(());
+                    }
+                }
+            })
+        };
+        let result = match result {
+            Ok(()) if !base.is_dry_run() => self.verify(&base, &clock, reporter),
+            result => result,
+        };
+        if result.is_err() && options.rollback {
+            // Rolling back is what cancelling asks for, so it must not see
+            // the cancelled token.
+            let base = base.clone().with_cancellation(CancellationToken::new());
+            self.rollback(&base, &applied, reporter);
+        }
+        result
+    }
+
+    /// Runs up to `options.jobs` shifts at a time. A shift starts once its
+    /// dependencies, and every shift before it in execution order whose
+    /// resources overlap its own, are done and no shift of its serial group
+    /// is running; among those, declaration order decides. After a failure
+    /// nothing new starts, and the shifts already running are waited for.
+    fn apply_parallel(
+        &self,
+        base: &ExecutionContext,
+        options: &ApplyOptions,
+        clock: &Clock,
+        applied: &mut Vec<usize>,
+        reporter: &mut dyn Reporter,
+    ) -> ShiftResult<()> {
+        let mut deps = self.dependencies()?;
+        let order = self.execution_order()?;
+        let claims = self.claims(base);
+        for (pos, &idx) in order.iter().enumerate() {
+            for &earlier in &order[..pos] {
+                let overlap = claims[idx].iter().any(|claim| {
+                    claims[earlier]
+                        .iter()
+                        .any(|their| claim.resource.overlaps(&their.resource))
+                });
+                if overlap {
+                    deps[idx].push(earlier);
+                }
+            }
+        }
+        let mut done = vec![false; self.entries.len()];
+        let mut started = vec![false; self.entries.len()];
+        for idx in order {
+            let entry = &self.entries[idx];
+            if options.completed.contains(&entry.id) {
+                reporter.report(&PlanEvent::Skipped(entry));
+                done[idx] = true;
+                started[idx] = true;
+            }
+        }
+        let mut busy_groups: HashSet<&str> = HashSet::new();
+        let mut failure = None;
+        thread::scope(|scope| {
+            let (tx, rx) = mpsc::channel();
+            let mut running = 0;
+            loop {
+                for (idx, entry) in self.entries.iter().enumerate() {
+                    if failure.is_some() || running >= options.jobs {
+                        break;
+                    }
+                    let group = entry.serial_group.as_deref();
+                    if started[idx]
+                        || !deps[idx].iter().all(|&dep| done[dep])
+                        || group.is_some_and(|group| busy_groups.contains(group))
+                    {
+                        continue;
+                    }
+                    started[idx] = true;
+                    if options.skips(entry) {
+                        done[idx] = true;
+                        continue;
+                    }
+                    busy_groups.extend(group);
+                    reporter.report(&PlanEvent::Started(entry));
+                    running += 1;
+                    let tx = tx.clone();
+                    scope.spawn(move || {
+                        let _ = tx.send((idx, self.apply_entry(base, entry, clock)));
+                    });
+                }
+                if running == 0 {
+                    break;
+                }
+                let Ok((idx, (result, got_started))) = rx.recv() else {
+                    break;
+                };
+                running -= 1;
+                done[idx] = true;
+                let entry = &self.entries[idx];
+                if let Some(group) = &entry.serial_group {
+                    busy_groups.remove(group.as_str());
+                }
+                match (options.recovery(entry, &result), &result) {
+                    (Some(action @ (FailureAction::Retry | FailureAction::Skip)), Err(err)) => {
+                        Self::report_failure(entry, err, reporter);
+                        if action == FailureAction::Retry {
+                            done[idx] = false;
+                            started[idx] = false;
+                        }
+                    }
+                    _ => {
+                        if let Err(err) = self.settle(idx, result, got_started, applied, reporter) {
+                            failure.get_or_insert(err);
+                        }
+                    }
+                }
+            }
+        });
+        failure.map_or(Ok(()), Err)
+    }
+
+    /// Checks and applies one entry. Also says whether `apply` got
+    /// underway, so a shift stopped midway can be rolled back.
+    fn apply_entry<'a>(
+        &self,
+        base: &ExecutionContext,
+        entry: &'a PlanEntry,
+        clock: &Clock,
+    ) -> (ShiftResult<PlanEvent<'a>>, bool) {
+        let mut started = false;
+        let result = Self::timed_context(base, entry, clock).and_then(|ctx| {
+            if is_applied(entry, &ctx)? {
+                return Ok(PlanEvent::Skipped(entry));
+            }
+            if ctx.is_dry_run() {
+                return Ok(PlanEvent::WouldApply(entry));
+            }
+            started = true;
+            let result = entry.shift.apply(&ctx);
+            // `is_applied` can be conservative; apply may find nothing to do.
+            Ok(match Self::check_overrun(result, &ctx, entry, clock)? {
+                ShiftOutcome::Changed => PlanEvent::Applied(entry),
+                ShiftOutcome::Unchanged => PlanEvent::Skipped(entry),
+            })
+        });
+        (result, started)
+    }
+
+    /// Runs the verify phase: every entry's checks, then the plan's.
+    fn verify(
+        &self,
+        base: &ExecutionContext,
+        clock: &Clock,
+        reporter: &mut dyn Reporter,
+    ) -> ShiftResult<()> {
+        let mut checked: Vec<(&PlanEntry, &[Box<dyn Shift>])> = Vec::new();
+        for idx in self.execution_order()? {
+            let entry = &self.entries[idx];
+            if !entry.verify.is_empty() {
+                checked.push((entry, &entry.verify));
+            }
+        }
+        for check in &self.checks {
+            checked.push((check, std::slice::from_ref(&check.shift)));
+        }
+        for (entry, checks) in checked {
+            let result = Self::timed_context(base, entry, clock).and_then(|ctx| {
+                checks.iter().try_for_each(|check| {
+                    let result = check.apply(&ctx).map(drop);
+                    Self::check_overrun(result, &ctx, entry, clock)
+                })
+            });
+            match result {
+                Ok(()) => reporter.report(&PlanEvent::Verified(entry)),
+                Err(err) => {
+                    match err.root() {
+                        ShiftError::TimedOut(_) => {
+                            reporter.report(&PlanEvent::TimedOut(entry, &err))
+                        }
+                        _ => reporter.report(&PlanEvent::Failed(entry, &err)),
+                    }
+                    return Err(err.context(format!("verifying `{}`", entry.id)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reports how entry `idx` went and notes it in `applied` if a
+    /// rollback would need to undo it.
+    fn settle(
+        &self,
+        idx: usize,
+        result: ShiftResult<PlanEvent<'_>>,
+        started: bool,
+        applied: &mut Vec<usize>,
+        reporter: &mut dyn Reporter,
+    ) -> ShiftResult<()> {
+        let entry = &self.entries[idx];
+        match result {
+            Ok(event) => {
+                if let PlanEvent::Applied(_) = event {
+                    applied.push(idx);
+                }
+                reporter.report(&event);
+                Ok(())
+            }
+            Err(err) => {
+                Self::report_failure(entry, &err, reporter);
+                // A shift stopped midway may have got partway; undo that too.
+                let stopped = matches!(err.root(), ShiftError::TimedOut(_) | ShiftError::Cancelled);
+                if stopped && started {
+                    applied.push(idx);
+                }
+                Err(err.context(format!("applying `{}`", entry.id)))
+            }
+        }
+    }
+
+    fn report_failure(entry: &PlanEntry, err: &ShiftError, reporter: &mut dyn Reporter) {
+        match err.root() {
+            ShiftError::TimedOut(_) => reporter.report(&PlanEvent::TimedOut(entry, err)),
+            _ => reporter.report(&PlanEvent::Failed(entry, err)),
+        }
+    }
+
+    /// Takes stock of a machine set up before skies managed it, changing
+    /// nothing: each shift in place is reported as [`PlanEvent::Adopted`]
+    /// and each other as [`PlanEvent::WouldApply`]. A shift that cannot
+    /// be checked is reported as failed, the rest are still checked, and
+    /// the first such error is returned.
+    pub fn adopt(&self, ctx: &ExecutionContext, reporter: &mut dyn Reporter) -> ShiftResult<()> {
+        let base = self.context(ctx);
+        let mut failure = None;
+        for idx in self.execution_order()? {
+            let entry = &self.entries[idx];
+            match is_applied(entry, &Self::entry_context(&base, entry)) {
+                Ok(true) => reporter.report(&PlanEvent::Adopted(entry)),
+                Ok(false) => reporter.report(&PlanEvent::WouldApply(entry)),
+                Err(err) => {
+                    reporter.report(&PlanEvent::Failed(entry, &err));
+                    failure.get_or_insert(err.context(format!("checking `{}`", entry.id)));
+                }
+            }
+        }
+        failure.map_or(Ok(()), Err)
+    }
+
+    /// Reverts every applied shift, last to first.
+    pub fn revert_with(
+        &self,
+        ctx: &ExecutionContext,
+        reporter: &mut dyn Reporter,
+    ) -> ShiftResult<()> {
+        let clock = Clock::start(self.deadline);
+        let base = self.context(ctx);
+        for idx in self.execution_order()?.into_iter().rev() {
+            let entry = &self.entries[idx];
+            let result = Self::timed_context(&base, entry, &clock).and_then(|ctx| {
+                if !is_applied(entry, &ctx)? {
+                    return Ok(None);
+                }
+                if ctx.is_dry_run() {
+                    return Ok(Some(PlanEvent::WouldRevert(entry)));
+                }
+                let result = entry.shift.revert(&ctx);
+                Self::check_overrun(result, &ctx, entry, &clock)?;
+                Ok(Some(PlanEvent::Reverted(entry)))
+            });
+            match result {
+                Ok(Some(event)) => reporter.report(&event),
+                Ok(None) => {}
+                Err(err) => {
+                    match err.root() {
+                        ShiftError::TimedOut(_) => {
+                            reporter.report(&PlanEvent::TimedOut(entry, &err))
+                        }
+                        _ => reporter.report(&PlanEvent::Failed(entry, &err)),
+                    }
+                    return Err(err.context(format!("reverting `{}`", entry.id)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn rollback(&self, ctx: &ExecutionContext, applied: &[usize], reporter: &mut dyn Reporter) {
+        for &idx in applied.iter().rev() {
+            let entry = &self.entries[idx];
+            match entry.shift.revert(&Self::entry_context(ctx, entry)) {
+                Ok(()) => reporter.report(&PlanEvent::RolledBack(entry)),
+                Err(err) => reporter.report(&PlanEvent::RollbackFailed(entry, &err)),
+            }
+        }
+    }
+}
+
+impl Shift for ShiftPlan {
+    fn metadata(&self) -> ShiftMetadata {
+        let mut meta = ShiftMetadata::new("plan", format!("plan of {} shifts", self.entries.len()));
+        for entry in &self.entries {
+            meta.targets.extend(entry.shift.metadata().targets);
+        }
+        meta
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        self.entries
+            .iter()
+            .flat_map(|entry| entry.shift.resources())
+            .collect()
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        self.preflight_into(&mut ctx.clone())
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let mut changes = Changes(false);
+        self.apply_with(ctx, &mut changes)?;
+        Ok(ShiftOutcome::changed_if(changes.0))
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        self.revert_with(ctx, &mut NullReporter)
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        let base = self.context(ctx);
+        for entry in &self.entries {
+            if !is_applied(entry, &Self::entry_context(&base, entry))? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn artifacts(&self, ctx: &ExecutionContext) -> ShiftResult<Vec<Artifact>> {
+        let base = self.context(ctx);
+        let mut artifacts = Vec::new();
+        for entry in &self.entries {
+            for artifact in entry.shift.artifacts(&Self::entry_context(&base, entry))? {
+                if !artifacts.contains(&artifact) {
+                    artifacts.push(artifact);
+                }
+            }
+        }
+        Ok(artifacts)
+    }
+
+    fn inputs(&self, ctx: &ExecutionContext) -> ShiftResult<Vec<ExternalInput>> {
+        let mut inputs = Vec::new();
+        for (_, entry_inputs) in self.external_inputs(ctx)? {
+            for input in entry_inputs {
+                if !inputs.contains(&input) {
+                    inputs.push(input);
+                }
+            }
+        }
+        Ok(inputs)
+    }
+}
+
+/// Notes whether any shift in a run changed something.
+struct Changes(bool);
+
+impl Reporter for Changes {
+    fn report(&mut self, event: &PlanEvent<'_>) {
+        if let PlanEvent::Applied(_) = event {
+            self.0 = true;
+        }
+    }
+}
+
+/// Whether `entry` is applied. A shift that cannot tell counts as not
+/// applied, so apply runs it and revert leaves it alone.
+fn is_applied(entry: &PlanEntry, ctx: &ExecutionContext) -> ShiftResult<bool> {
+    match entry.shift.is_applied(ctx) {
+        Err(err) if matches!(err.root(), ShiftError::CheckUnsupported { .. }) => Ok(false),
+        result => result,
+    }
+}
+
+/// When a run started and when its plan deadline passes.
+struct Clock {
+    deadline: Option<Duration>,
+    plan_deadline: Option<Instant>,
+}
+
+impl Clock {
+    fn start(deadline: Option<Duration>) -> Self {
+        Clock {
+            deadline,
+            plan_deadline: deadline.and_then(|d| Instant::now().checked_add(d)),
+        }
+    }
+
+    fn plan_expired(&self) -> ShiftError {
+        ShiftError::TimedOut(format!(
+            "the plan deadline of {}s passed",
+            self.deadline.unwrap_or_default().as_secs_f32()
+        ))
+    }
+}
+
+/// Fails if `id` has control characters, such as a line break, which
+/// would let it break out of the logs, comments and scripts it is written
+/// into.
+pub(crate) fn check_id(id: &str) -> ShiftResult<()> {
+    match id.chars().any(char::is_control) {
+        true => Err(ShiftError::Plan(format!(
+            "shift id {id:?} has control characters"
+        ))),
+        false => Ok(()),
+    }
+}
+
+/// Lowercase alphanumeric words joined by `-`, capped at 40 characters.
+fn slug(text: &str) -> String {
+    let mut slug = String::new();
+    for word in text
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+    {
+        if slug.len() + word.len() + 1 > 40 {
+            break;
+        }
+        if !slug.is_empty() {
+            slug.push('-');
+        }
+        slug.push_str(&word.to_ascii_lowercase());
+    }
+    if slug.is_empty() {
+        slug.push_str("shift");
+    }
+    slug
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::plan_file;
+    use crate::registry::Registry;
+    use crate::report::NullReporter;
+    use crate::testing::Sandbox;
+
+    #[test]
+    fn limits_too_far_off_are_no_limits() {
+        let sandbox = Sandbox::new().unwrap();
+        let mut ctx = sandbox.context();
+        let source = r#"
+            deadline = 1e19
+
+            [[shift]]
+            type = "cmd"
+            program = "true"
+            timeout = 1e19
+            time_limit = 1e19
+        "#;
+        let plan = plan_file::parse(source, &Registry::builtin(), &mut ctx).unwrap();
+        plan.apply_with(&ctx, &mut NullReporter).unwrap();
+    }
+}
@@ -0,0 +1,1176 @@
+//! TOML plan files.
+//!
+//! ```toml
+//! [[shift]]
+//! id = "app"
+//! type = "create_dir"
+//! path = "app"
+//!
+//! [[shift]]
+//! type = "cmd"
+//! program = "npm"
+//! args = ["init", "-y"]
+//! cwd = "app"
+//! depends_on = ["app"]
+//! tags = ["node"]
+//! ```
+//!
+//! `id`, `tags`, `depends_on`, `allow_outside_root`, `time_limit` and
+//! `serial_group` are common to every entry; the remaining fields belong
+//! to the shift named by `type`.
+//!
+//! `time_limit` (seconds) bounds one shift and a top-level `deadline`
+//! (seconds) bounds the whole run; see [`ShiftPlan::set_deadline`].
+//!
+//! With `skies apply --jobs N`, entries sharing a `serial_group` (say
+//! `"apt"`) still run one at a time while the rest run in parallel.
+//!
+//! A `[permissions]` table (`umask`, `file_mode`, `dir_mode`) sets default
+//! modes for every file and directory the plan creates; a `mode` on the
+//! shift itself still wins.
+//!
+//! A `[network]` table (`max_transfers`, `rate_limit`) limits downloads
+//! and clones; see [`network`](crate::network).
+//!
+//! A top-level `root = "dir"` confines every shift to `dir`; relative roots
+//! are resolved against the directory containing the plan file.
+//!
+//! String fields may reference variables as `${name}`, or hold any
+//! [expression](crate::expr) such as `${lower(facts.hostname)}` (see
+//! [`ExecutionContext::interpolate`]). Defaults come from a `[vars]` table;
+//! variables already set on the context, e.g. from the command line, win.
+//! A `[hosts.<hostname>]` table overrides `[vars]` on that host.
+//!
+//! `os` and `hostname` keep an entry only on matching machines. Each takes
+//! a name or a list of names; a name starting with `!` excludes instead:
+//!
+//! ```toml
+//! [[shift]]
+//! type = "symlink"
+//! path = "~/.gitconfig"
+//! target = "~/dotfiles/gitconfig-work"
+//! hostname = ["work-laptop", "work-desktop"]
+//!
+//! [[shift]]
+//! type = "symlink"
+//! path = "~/.gitconfig"
+//! target = "~/dotfiles/gitconfig"
+//! hostname = ["!work-laptop", "!work-desktop"]
+//! ```
+//!
+//! A `facts` table does the same for any fact, including the machine
+//! labels set with `skies machine label` (see [`crate::machine`]). In all
+//! three, `*` in a name matches any run of characters:
+//!
+//! ```toml
+//! [[shift]]
+//! type = "cmd"
+//! program = "ccache"
+//! args = ["--max-size", "50G"]
+//! facts = { "label.role" = "build", hostname = "build-*" }
+//! ```
+//!
+//! `when` keeps an entry only where an [expression](crate::expr) holds:
+//!
+//! ```toml
+//! [[shift]]
+//! type = "cmd"
+//! program = "nvidia-smi"
+//! when = "facts.os == 'linux' && vars.gpu == true"
+//! ```
+//!
+//! `skies lint` checks every `when` and `${...}` ahead of time; see
+//! [`lint`].
+//!
+//! Dependencies on an `id` whose entry was left out this way are dropped.
+//! Path fields may start with `~` for the home directory.
+//!
+//! A `[roles.<name>]` table defines a reusable group of shifts, either
+//! inline or from a `file` (relative to the plan file) holding `[vars]`
+//! and `[[shift]]`s. An entry with `role = "<name>"` instead of a `type`
+//! expands in place into the role's shifts. Its other fields become
+//! variables for them, over the role's `[vars]` defaults, and `${id}` is
+//! the instance's `id` (by default `<name>-1`, `<name>-2`, ...):
+//!
+//! ```toml
+//! [roles.service]
+//! vars = { port = "8080" }
+//!
+//! [[roles.service.shift]]
+//! id = "dir"
+//! type = "create_dir"
+//! path = "/srv/${id}"
+//!
+//! [[roles.service.shift]]
+//! type = "file"
+//! path = "/srv/${id}/.env"
+//! contents = "PORT=${port}\n"
+//! depends_on = ["dir"]
+//!
+//! [[shift]]
+//! id = "api"
+//! role = "service"
+//! port = 3000
+//!
+//! [[shift]]
+//! id = "worker"
+//! role = "service"
+//! depends_on = ["api"]
+//! ```
+//!
+//! An instance's `tags`, `depends_on` and other common fields carry over
+//! to each of its shifts, and depending on an instance means depending on
+//! all of them. Explicit IDs inside a role are prefixed with the instance
+//! ID (`api.dir`).
+//!
+//! A `[params]` table declares typed, validated variables; see
+//! [`params`](crate::params).
+//!
+//! A `[matrix]` table runs the plan once per combination of variables;
+//! see [`matrix`](crate::matrix).
+//!
+//! A top-level `requires` lists other plans of a workspace to apply first;
+//! see [`monorepo`](crate::monorepo).
+//!
+//! A top-level `script = "file.rhai"` generates more shifts with a
+//! script; see [`script`](crate::script).
+//!
+//! A plan file ending in `.star` is instead a Starlark program that builds
+//! the same document; see [`starlark_file`](crate::starlark_file).
+//!
+//! A `verify` list on an entry, or top-level `[[verify]]` tables, hold
+//! checks with the fields of an `assert` shift. They run after the plan
+//! has applied, and a failing one fails the run:
+//!
+//! ```toml
+//! [[shift]]
+//! type = "cmd"
+//! program = "docker"
+//! args = ["compose", "up", "-d"]
+//! verify = [{ command = ["curl", "-fsS", "localhost:3000/health"] }]
+//!
+//! [[verify]]
+//! port = 5432
+//! ```
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use schemars::generate::SchemaSettings;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::expr::{self, Expr, Type};
+use crate::network::NetworkPolicy;
+use crate::params::{Param, ParamType};
+use crate::permissions::PermissionPolicy;
+use crate::plan::{self, PlanEntry, ShiftPlan};
+use crate::registry::Registry;
+use crate::script;
+use crate::starlark_file;
+
+/// Shift type of `verify` checks.
+const CHECK_KIND: &str = "assert";
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[schemars(rename = "Plan")]
+struct RawPlan {
+    #[serde(default)]
+    root: Option<String>,
+    #[serde(default)]
+    permissions: PermissionPolicy,
+    #[serde(default)]
+    network: NetworkPolicy,
+    #[serde(default, deserialize_with = "de::opt_secs")]
+    #[schemars(with = "Option<f64>")]
+    deadline: Option<Duration>,
+    #[serde(default)]
+    vars: BTreeMap<String, String>,
+    #[serde(default)]
+    hosts: BTreeMap<String, BTreeMap<String, String>>,
+    #[serde(default, rename = "shift")]
+    shifts: Vec<RawEntry>,
+    #[serde(default)]
+    #[schemars(with = "Vec<crate::shifts::Assert>")]
+    verify: Vec<toml::Table>,
+    #[serde(default)]
+    roles: BTreeMap<String, RawRole>,
+    /// Read by [`Matrix`](crate::matrix::Matrix); the variables are set by
+    /// the time the plan is parsed.
+    #[serde(default, rename = "matrix")]
+    #[schemars(description = "Variables to run the plan once for each combination of.")]
+    #[schemars(with = "Option<BTreeMap<String, Vec<serde_json::Value>>>")]
+    _matrix: Option<toml::Table>,
+    #[serde(default)]
+    params: BTreeMap<String, RawParam>,
+    /// Read by [`Monorepo`](crate::monorepo::Monorepo), which orders the
+    /// plans of a workspace.
+    #[serde(default, rename = "requires")]
+    #[schemars(
+        description = "Plans in the workspace to apply before this one with `skies apply --recursive`."
+    )]
+    _requires: Vec<PathBuf>,
+    /// A [script](crate::script) generating more shifts, relative to the
+    /// plan file.
+    #[serde(default)]
+    #[schemars(description = "A Rhai script generating more shifts, relative to the plan file.")]
+    script: Option<PathBuf>,
+}
+
+/// A `[params.<name>]` table; see [`params`](crate::params).
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[schemars(rename = "Param", description = "A parameter the plan takes.")]
+struct RawParam {
+    #[serde(default, rename = "type")]
+    kind: ParamType,
+    #[serde(default)]
+    #[schemars(with = "Option<serde_json::Value>")]
+    default: Option<toml::Value>,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    min: Option<i64>,
+    #[serde(default)]
+    max: Option<i64>,
+    #[serde(default)]
+    values: Vec<String>,
+    #[serde(default)]
+    pattern: Option<String>,
+    #[serde(default)]
+    exists: bool,
+}
+
+/// A named group of shifts, instantiated by entries with `role = "name"`.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[schemars(rename = "Role")]
+struct RawRole {
+    /// A TOML file holding the role's `[vars]` and `[[shift]]`s instead.
+    #[serde(default)]
+    file: Option<PathBuf>,
+    /// Parameter defaults.
+    #[serde(default)]
+    vars: BTreeMap<String, String>,
+    #[serde(default, rename = "shift")]
+    shifts: Vec<RawEntry>,
+}
+
+#[derive(Deserialize, Clone, JsonSchema)]
+#[schemars(rename = "Entry")]
+struct RawEntry {
+    #[serde(default, rename = "type")]
+    kind: Option<String>,
+    #[serde(default)]
+    role: Option<String>,
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    depends_on: Vec<String>,
+    #[serde(default)]
+    allow_outside_root: bool,
+    #[serde(default, deserialize_with = "de::opt_secs")]
+    #[schemars(with = "Option<f64>")]
+    time_limit: Option<Duration>,
+    #[serde(default)]
+    serial_group: Option<String>,
+    #[serde(default)]
+    os: Option<Condition>,
+    #[serde(default)]
+    hostname: Option<Condition>,
+    #[serde(default)]
+    facts: BTreeMap<String, Condition>,
+    #[serde(default)]
+    when: Option<String>,
+    /// The shift's own fields, or a role instance's variables.
+    #[serde(flatten)]
+    #[schemars(with = "BTreeMap<String, serde_json::Value>")]
+    fields: toml::Table,
+}
+
+/// Names a fact must (or, prefixed with `!`, must not) match.
+#[derive(Deserialize, Clone, JsonSchema)]
+#[serde(untagged)]
+enum Condition {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Condition {
+    /// Whether `value` passes: it matches one of the plain names, if there
+    /// are any, and none of the `!` names.
+    fn matches(&self, value: Option<&str>) -> bool {
+        let names = match self {
+            Condition::One(name) => std::slice::from_ref(name),
+            Condition::Many(names) => names.as_slice(),
+        };
+        let (excluded, wanted): (Vec<&str>, Vec<&str>) = names
+            .iter()
+            .map(String::as_str)
+            .partition(|name| name.starts_with('!'));
+        let matches = |name: &str| value.is_some_and(|value| wildcard_match(name, value));
+        let excluded = excluded.iter().any(|name| matches(&name[1..]));
+        let wanted = wanted.is_empty() || wanted.iter().any(|name| matches(name));
+        wanted && !excluded
+    }
+}
+
+/// Whether `value` matches `pattern`, where `*` stands for any run of
+/// characters.
+pub(crate) fn wildcard_match(pattern: &str, value: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = value.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// Reads and parses the plan file at `path` with the built-in registry.
+/// Role files are found relative to the plan file's directory.
+pub fn load(path: &Path, ctx: &mut ExecutionContext) -> ShiftResult<ShiftPlan> {
+    ctx.enter_plan_file(path)?;
+    let raw = read(path, ctx)?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut plan = build(raw, dir, &Registry::builtin(), ctx)
+        .map_err(|err| ShiftError::Plan(format!("{}: {}", path.display(), plain(err))))?;
+    if let Some(root) = plan.root() {
+        let root = ctx.join_root(&dir.join(ctx.expand_home(root)));
+        plan.set_root(root);
+    }
+    Ok(plan)
+}
+
+/// Parses plan file source, resolving shift types through `registry` and
+/// variables through `ctx`. The file's `[vars]` defaults are added to `ctx`.
+/// Role files are found relative to the current directory.
+pub fn parse(
+    source: &str,
+    registry: &Registry,
+    ctx: &mut ExecutionContext,
+) -> ShiftResult<ShiftPlan> {
+    parse_in(source, Path::new(""), registry, ctx)
+}
+
+fn parse_in(
+    source: &str,
+    dir: &Path,
+    registry: &Registry,
+    ctx: &mut ExecutionContext,
+) -> ShiftResult<ShiftPlan> {
+    let raw = toml::from_str(source).map_err(|err| ShiftError::Plan(err.message().to_string()))?;
+    build(raw, dir, registry, ctx)
+}
+
+/// The plan file at `path`, run first if it is a
+/// [Starlark](crate::starlark_file) one.
+fn read(path: &Path, ctx: &ExecutionContext) -> ShiftResult<RawPlan> {
+    let in_file =
+        |err: toml::de::Error| ShiftError::Plan(format!("{}: {}", path.display(), err.message()));
+    if starlark_file::is_starlark(path) {
+        let document = toml::Value::Table(starlark_file::evaluate(path, ctx)?);
+        return document.try_into().map_err(in_file);
+    }
+    let source = fs::read_to_string(path)
+        .map_err(|err| ShiftError::Plan(format!("cannot read {}: {err}", path.display())))?;
+    toml::from_str(&source).map_err(in_file)
+}
+
+fn build(
+    mut raw: RawPlan,
+    dir: &Path,
+    registry: &Registry,
+    ctx: &mut ExecutionContext,
+) -> ShiftResult<ShiftPlan> {
+    let host = ctx.facts().get("hostname").unwrap_or_default().to_string();
+    if let Some(overrides) = raw.hosts.remove(&host) {
+        raw.vars.extend(overrides);
+    }
+    let mut params = Vec::new();
+    for (name, raw_param) in raw.params {
+        let context = |msg: String| ShiftError::Plan(format!("param `{name}`: {msg}"));
+        if raw.vars.contains_key(&name) {
+            return Err(context("also defined in `[vars]`".into()));
+        }
+        let default = match &raw_param.default {
+            Some(value) => {
+                let value = scalar(value).ok_or_else(|| {
+                    context("the default must be a string, number or boolean".into())
+                })?;
+                Some(ctx.interpolate(&value).map_err(|err| context(plain(err)))?)
+            }
+            None => None,
+        };
+        let param = Param {
+            name: name.clone(),
+            kind: raw_param.kind,
+            default,
+            description: raw_param.description,
+            min: raw_param.min,
+            max: raw_param.max,
+            values: raw_param.values,
+            pattern: raw_param.pattern,
+            exists: raw_param.exists,
+        };
+        param.validate().map_err(context)?;
+        let value = match ctx.var(&name).or(param.default.as_deref()) {
+            Some(value) => param.check(value, ctx).map_err(context)?,
+            None => {
+                return Err(context(format!(
+                    "required; pass it with `--param {name}=VALUE`"
+                )))
+            }
+        };
+        ctx.set_var(name, value);
+        params.push(param);
+    }
+    for (name, value) in raw.vars {
+        if ctx.var(&name).is_none() {
+            let value = ctx
+                .interpolate(&value)
+                .map_err(|err| ShiftError::Plan(format!("var `{name}`: {}", plain(err))))?;
+            ctx.set_var(name, value);
+        }
+    }
+    let roles = raw
+        .roles
+        .into_iter()
+        .map(|(name, role)| {
+            let role = role
+                .resolve(dir)
+                .map_err(|err| ShiftError::Plan(format!("role `{name}`: {}", plain(err))))?;
+            Ok((name, role))
+        })
+        .collect::<ShiftResult<BTreeMap<_, _>>>()?;
+    let declared = raw.shifts.len();
+    let script = raw.script.as_ref().map(|file| file.display().to_string());
+    if let Some(file) = &raw.script {
+        for (idx, table) in script::run(&dir.join(file), ctx)?.into_iter().enumerate() {
+            let entry = toml::Value::Table(table)
+                .try_into()
+                .map_err(|err: toml::de::Error| {
+                    let msg = err.message().to_string();
+                    ShiftError::Plan(format!("{}: shift #{}: {msg}", file.display(), idx + 1))
+                })?;
+            raw.shifts.push(entry);
+        }
+    }
+    // Where an entry came from, for messages: its place among the file's
+    // shifts or the script's.
+    let number = |idx: usize| match &script {
+        Some(script) if idx >= declared => format!("{script} shift #{}", idx - declared + 1),
+        _ => format!("shift #{}", idx + 1),
+    };
+
+    let facts = ctx.facts().clone();
+    let included = |raw: &RawEntry, ctx: &ExecutionContext| -> ShiftResult<bool> {
+        let matched = raw.os.as_ref().is_none_or(|c| c.matches(facts.get("os")))
+            && raw
+                .hostname
+                .as_ref()
+                .is_none_or(|c| c.matches(facts.get("hostname")))
+            && raw.facts.iter().all(|(name, c)| c.matches(facts.get(name)));
+        match &raw.when {
+            Some(when) if matched => ctx
+                .eval(when)?
+                .truth()
+                .map_err(|err| ShiftError::Plan(format!("`when = \"{when}\"`: {}", plain(err)))),
+            _ => Ok(matched),
+        }
+    };
+    let mut excluded = HashSet::new();
+    let mut shifts = Vec::new();
+    // Role entries expand in place into their roles' shifts.
+    let mut instances: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    let mut uses: BTreeMap<&str, usize> = BTreeMap::new();
+    for (idx, raw) in raw.shifts.into_iter().enumerate() {
+        let Some(role_name) = raw.role.clone() else {
+            let label = format!("{} ({})", number(idx), raw.kind.as_deref().unwrap_or("?"));
+            shifts.push((label, raw, None, idx));
+            continue;
+        };
+        let context =
+            |msg: String| ShiftError::Plan(format!("{} (role {role_name}): {msg}", number(idx)));
+        let Some((name, role)) = roles.get_key_value(&role_name) else {
+            return Err(context(format!("unknown role `{role_name}`")));
+        };
+        if raw.kind.is_some() {
+            return Err(context("give either a `type` or a `role`, not both".into()));
+        }
+        let n = uses.entry(name).or_default();
+        *n += 1;
+        let id = raw.id.clone().unwrap_or_else(|| format!("{name}-{n}"));
+        if !included(&raw, ctx).map_err(|err| context(plain(err)))? {
+            excluded.insert(id);
+            continue;
+        }
+        let scope = role
+            .scope(&id, &raw.fields, ctx)
+            .map_err(|err| context(plain(err)))?;
+        let members = instances.entry(id.clone()).or_default();
+        for (n, member) in role.instantiate(&id, &raw).into_iter().enumerate() {
+            let label = format!(
+                "{} (role {name}), role shift #{} ({})",
+                number(idx),
+                n + 1,
+                member.kind.as_deref().unwrap_or("?")
+            );
+            members.push(shifts.len());
+            shifts.push((label, member, Some(scope.clone()), idx));
+        }
+    }
+    let mut kept = Vec::new();
+    for (pos, (label, raw, scope, idx)) in shifts.into_iter().enumerate() {
+        let shift_ctx = scope.as_ref().unwrap_or(ctx);
+        let include = included(&raw, shift_ctx)
+            .map_err(|err| ShiftError::Plan(format!("{label}: {}", plain(err))))?;
+        if include {
+            kept.push((pos, label, raw, scope, idx));
+        } else {
+            excluded.extend(raw.id);
+        }
+    }
+
+    let mut plan = ShiftPlan::new();
+    let mut positions = BTreeMap::new();
+    for (pos, label, raw, scope, idx) in kept {
+        let context = |err| ShiftError::Plan(format!("{label}: {}", plain(err)));
+        let ctx = scope.as_ref().unwrap_or(ctx);
+        let Some(kind) = &raw.kind else {
+            return Err(context(ShiftError::Plan(
+                "needs a `type` or a `role`".into(),
+            )));
+        };
+        let mut fields = toml::Value::Table(raw.fields);
+        interpolate(&mut fields, ctx).map_err(context)?;
+        let toml::Value::Table(mut fields) = fields else {
+            unreachable!("interpolation preserves the value's shape")
+        };
+        let mut definition = fields.clone();
+        definition.insert("type".into(), toml::Value::String(kind.clone()));
+        let verify = match fields.remove("verify") {
+            Some(toml::Value::Array(checks)) => checks,
+            Some(_) => {
+                return Err(context(ShiftError::Plan(
+                    "`verify` must be a list of checks".into(),
+                )))
+            }
+            None => Vec::new(),
+        };
+        let shift = registry.build(kind, fields).map_err(context)?;
+        let mut entry = PlanEntry::boxed(shift).with_definition(definition);
+        for check in verify {
+            let toml::Value::Table(check) = check else {
+                return Err(context(ShiftError::Plan(
+                    "each `verify` check must be a table".into(),
+                )));
+            };
+            entry
+                .verify
+                .push(registry.build(CHECK_KIND, check).map_err(context)?);
+        }
+        if let Some(id) = raw.id {
+            plan::check_id(&id).map_err(context)?;
+            entry = entry.with_id(id);
+        }
+        if let Some(script) = script.as_ref().filter(|_| idx >= declared) {
+            entry = entry.with_origin(script.clone());
+        }
+        entry.tags = raw.tags;
+        entry.depends_on = raw
+            .depends_on
+            .into_iter()
+            .filter(|dep| !excluded.contains(dep))
+            .collect();
+        entry.allow_outside_root = raw.allow_outside_root;
+        entry.time_limit = raw.time_limit;
+        entry.serial_group = raw.serial_group;
+        positions.insert(pos, plan.len());
+        plan.push(entry);
+    }
+    // Depending on a role instance means depending on all of its shifts.
+    let members: BTreeMap<String, Vec<String>> = instances
+        .into_iter()
+        .map(|(id, members)| {
+            let ids = members
+                .iter()
+                .filter_map(|pos| positions.get(pos))
+                .map(|&idx| plan.entries()[idx].id().to_string())
+                .collect();
+            (id, ids)
+        })
+        .collect();
+    if !members.is_empty() {
+        for entry in plan.entries_mut() {
+            entry.depends_on = std::mem::take(&mut entry.depends_on)
+                .into_iter()
+                .flat_map(|dep| members.get(&dep).cloned().unwrap_or_else(|| vec![dep]))
+                .collect();
+        }
+    }
+    for (idx, check) in raw.verify.into_iter().enumerate() {
+        let context = |err| ShiftError::Plan(format!("verify #{}: {}", idx + 1, plain(err)));
+        let mut check = toml::Value::Table(check);
+        interpolate(&mut check, ctx).map_err(context)?;
+        let toml::Value::Table(check) = check else {
+            unreachable!("interpolation preserves the value's shape")
+        };
+        let shift = registry.build(CHECK_KIND, check.clone()).map_err(context)?;
+        plan.push_check(PlanEntry::boxed(shift).with_definition(check));
+    }
+    if let Some(root) = raw.root {
+        plan.set_root(ctx.interpolate(&root)?);
+    }
+    plan.set_params(params);
+    plan.set_permissions(raw.permissions);
+    plan.set_network(raw.network);
+    if let Some(deadline) = raw.deadline {
+        plan.set_deadline(deadline);
+    }
+    plan.validate()?;
+    Ok(plan)
+}
+
+/// Checks every expression in the plan file at `path`, in `when`
+/// conditions and `${...}` templates, without evaluating any: that each
+/// parses, names only variables the plan could have, and is used as the
+/// right type. Returns a line for each problem. Variables set on `ctx`
+/// count as defined.
+pub fn lint(path: &Path, ctx: &ExecutionContext) -> ShiftResult<Vec<String>> {
+    let raw = read(path, ctx)?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut names: BTreeMap<String, Type> = ctx
+        .vars()
+        .keys()
+        .chain(raw.vars.keys())
+        .chain(raw.hosts.values().flat_map(BTreeMap::keys))
+        .map(|name| (name.clone(), Type::Any))
+        .collect();
+    for (name, param) in &raw.params {
+        let kind = match param.kind {
+            ParamType::Int => Type::Num,
+            ParamType::Bool => Type::Bool,
+            _ => Type::Str,
+        };
+        names.insert(name.clone(), kind);
+    }
+    let mut lint = Lint::default();
+    for (name, value) in raw.vars.iter().chain(raw.hosts.values().flatten()) {
+        lint.text(&format!("var `{name}`"), value, &names);
+    }
+    for (name, param) in &raw.params {
+        if let Some(default) = param.default.as_ref().and_then(scalar) {
+            lint.text(&format!("param `{name}`"), &default, &names);
+        }
+    }
+    if let Some(root) = &raw.root {
+        lint.text("root", root, &names);
+    }
+    let mut roles = BTreeMap::new();
+    for (name, role) in raw.roles {
+        match role.resolve(dir) {
+            Ok(role) => {
+                roles.insert(name, role);
+            }
+            Err(err) => lint.problem(format!("role `{name}`: {}", plain(err))),
+        }
+    }
+    for (idx, entry) in raw.shifts.iter().enumerate() {
+        let Some(role_name) = &entry.role else {
+            let label = format!(
+                "shift #{} ({})",
+                idx + 1,
+                entry.kind.as_deref().unwrap_or("?")
+            );
+            lint.entry(&label, entry, &names);
+            continue;
+        };
+        let label = format!("shift #{} (role {role_name})", idx + 1);
+        lint.entry(&label, entry, &names);
+        let Some(role) = roles.get(role_name) else {
+            continue;
+        };
+        let mut scope = names.clone();
+        let params = entry.fields.keys().chain(role.vars.keys());
+        scope.extend(params.map(|name| (name.clone(), Type::Any)));
+        scope.insert("id".into(), Type::Str);
+        for (name, value) in &role.vars {
+            lint.text(&format!("role `{role_name}`: var `{name}`"), value, &scope);
+        }
+        for (n, member) in role.shifts.iter().enumerate() {
+            let label = format!(
+                "role `{role_name}`, shift #{} ({})",
+                n + 1,
+                member.kind.as_deref().unwrap_or("?")
+            );
+            lint.entry(&label, member, &scope);
+        }
+    }
+    for (idx, check) in raw.verify.iter().enumerate() {
+        let check = toml::Value::Table(check.clone());
+        lint.value(&format!("verify #{}", idx + 1), &check, &names);
+    }
+    Ok(lint.problems)
+}
+
+/// Problems [`lint`] has found so far, without repeats, as when several
+/// instances of a role share one.
+#[derive(Default)]
+struct Lint {
+    problems: Vec<String>,
+}
+
+impl Lint {
+    fn problem(&mut self, problem: String) {
+        if !self.problems.contains(&problem) {
+            self.problems.push(problem);
+        }
+    }
+
+    fn entry(&mut self, label: &str, entry: &RawEntry, names: &BTreeMap<String, Type>) {
+        if let Some(when) = &entry.when {
+            let checked = Expr::parse(when).and_then(|expr| expr.check(&lookup(names)));
+            match checked {
+                Ok(Type::Bool | Type::Any) => {}
+                Ok(found) => self.problem(format!(
+                    "{label}: `when = \"{when}\"` must be a boolean, not a {found}"
+                )),
+                Err(err) => self.problem(format!("{label}: `when`: {}", plain(err))),
+            }
+        }
+        for (name, value) in &entry.fields {
+            self.value(&format!("{label}: `{name}`"), value, names);
+        }
+    }
+
+    /// Checks the templates in every string in `value`.
+    fn value(&mut self, label: &str, value: &toml::Value, names: &BTreeMap<String, Type>) {
+        match value {
+            toml::Value::String(text) => self.text(label, text, names),
+            toml::Value::Array(items) => {
+                for item in items {
+                    self.value(label, item, names);
+                }
+            }
+            toml::Value::Table(table) => {
+                for (key, item) in table {
+                    self.value(&format!("{label}.{key}"), item, names);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn text(&mut self, label: &str, text: &str, names: &BTreeMap<String, Type>) {
+        let templates = match expr::templates(text) {
+            Ok(templates) => templates,
+            Err(err) => return self.problem(format!("{label}: {}", plain(err))),
+        };
+        for template in templates {
+            match Expr::parse(template).and_then(|expr| expr.check(&lookup(names))) {
+                Ok(Type::List) => self.problem(format!(
+                    "{label}: `${{{template}}}` is a list, which cannot be put in text"
+                )),
+                Ok(_) => {}
+                Err(err) => self.problem(format!("{label}: {}", plain(err))),
+            }
+        }
+    }
+}
+
+/// The types of references for [`Expr::check`], given the variables a
+/// plan defines.
+fn lookup(names: &BTreeMap<String, Type>) -> impl Fn(&[String]) -> Option<Type> + '_ {
+    |path| match path[0].as_str() {
+        "facts" | "env" if path.len() > 1 => Some(Type::Str),
+        "outputs" if path.len() > 2 => Some(Type::Any),
+        "plans" if path.len() > 3 => Some(Type::Any),
+        "vars" if path.len() > 1 => names.get(&path[1..].join(".")).copied(),
+        _ => names.get(&path.join(".")).copied(),
+    }
+}
+
+impl RawRole {
+    /// The role with its `file`, if any, read in.
+    fn resolve(self, dir: &Path) -> ShiftResult<RawRole> {
+        let Some(file) = &self.file else {
+            return Ok(self);
+        };
+        let path = dir.join(file);
+        let source = fs::read_to_string(&path)
+            .map_err(|err| ShiftError::Plan(format!("cannot read {}: {err}", path.display())))?;
+        let mut role: RawRole = toml::from_str(&source)
+            .map_err(|err| ShiftError::Plan(format!("{}: {}", path.display(), err.message())))?;
+        if role.file.is_some() || !self.shifts.is_empty() {
+            return Err(ShiftError::Plan(
+                "a role takes its shifts from either `file` or `[[shift]]`, not both".into(),
+            ));
+        }
+        role.vars.extend(self.vars);
+        Ok(role)
+    }
+
+    /// The context an instance's shifts are interpolated in: `ctx` with
+    /// `id` set to the instance ID, the instance's other fields as
+    /// variables and the role's defaults for the rest.
+    fn scope(
+        &self,
+        id: &str,
+        params: &toml::Table,
+        ctx: &ExecutionContext,
+    ) -> ShiftResult<ExecutionContext> {
+        let mut scope = ctx.clone();
+        scope.set_var("id", id);
+        for (name, value) in params {
+            let Some(value) = scalar(value) else {
+                return Err(ShiftError::Plan(format!(
+                    "parameter `{name}` must be a string, number or boolean"
+                )));
+            };
+            scope.set_var(name, scope.interpolate(&value)?);
+        }
+        for (name, value) in &self.vars {
+            if !params.contains_key(name) {
+                let value = scope
+                    .interpolate(value)
+                    .map_err(|err| ShiftError::Plan(format!("var `{name}`: {}", plain(err))))?;
+                scope.set_var(name, value);
+            }
+        }
+        Ok(scope)
+    }
+
+    /// The role's shifts for the instance `id`, carrying the instance's
+    /// common fields. Explicit IDs, and dependencies on them, get an
+    /// `id.` prefix so instances do not collide.
+    fn instantiate(&self, id: &str, instance: &RawEntry) -> Vec<RawEntry> {
+        let local: HashSet<&str> = self.shifts.iter().filter_map(|s| s.id.as_deref()).collect();
+        self.shifts
+            .iter()
+            .map(|shift| {
+                let mut shift = shift.clone();
+                shift.id = shift.id.map(|own| format!("{id}.{own}"));
+                for dep in &mut shift.depends_on {
+                    if local.contains(dep.as_str()) {
+                        *dep = format!("{id}.{dep}");
+                    }
+                }
+                shift.depends_on.extend(instance.depends_on.iter().cloned());
+                shift.tags.extend(instance.tags.iter().cloned());
+                shift.allow_outside_root |= instance.allow_outside_root;
+                shift.time_limit = shift.time_limit.or(instance.time_limit);
+                shift.serial_group = shift.serial_group.or(instance.serial_group.clone());
+                shift
+            })
+            .collect()
+    }
+}
+
+/// A JSON Schema of plan files, with an entry for each shift type in
+/// `registry`, for editors and for checking plans without skies.
+///
+/// An entry is one of the shift types, told apart by `type`, each with
+/// its own fields and the common ones, or a role instance.
+pub fn schema(registry: &Registry) -> serde_json::Value {
+    let settings = SchemaSettings::draft07();
+    let definitions = settings.definitions_path.to_string();
+    let mut generator = settings.into_generator();
+    let common = RawEntry::json_schema(&mut generator).to_value();
+    let mut common_fields = common["properties"]
+        .as_object()
+        .cloned()
+        .unwrap_or_default();
+    common_fields.remove("type");
+    common_fields.remove("role");
+    let verify = generator
+        .subschema_for::<crate::shifts::Assert>()
+        .to_value();
+    common_fields.insert("verify".into(), json!({ "type": "array", "items": verify }));
+
+    let mut entries = Vec::new();
+    for kind in registry.kinds() {
+        let Some(shift) = registry.schema(kind, &mut generator) else {
+            continue;
+        };
+        let mut shift = shift.to_value();
+        let fields = shift["properties"].as_object().cloned().unwrap_or_default();
+        let mut properties = common_fields.clone();
+        properties.extend(fields);
+        properties.insert("type".into(), json!({ "const": kind }));
+        let mut required = shift["required"].as_array().cloned().unwrap_or_default();
+        required.push(json!("type"));
+        shift["properties"] = properties.into();
+        shift["required"] = required.into();
+        entries.push(shift);
+    }
+    let mut instance = common_fields;
+    instance.insert("role".into(), json!({ "type": "string" }));
+    entries.push(json!({
+        "description": "An instance of a role; its other fields become the role's variables.",
+        "type": "object",
+        "properties": instance,
+        "required": ["role"],
+        "not": { "required": ["type"] },
+        "additionalProperties": { "type": ["string", "number", "boolean"] },
+    }));
+
+    let mut root = generator.into_root_schema_for::<RawPlan>().to_value();
+    root["title"] = json!("skies plan");
+    let entry = json!({
+        "description": common["description"],
+        "oneOf": entries,
+    });
+    if let Some(serde_json::Value::Object(definitions)) = root.pointer_mut(&definitions) {
+        definitions.insert("Entry".into(), entry);
+    }
+    root
+}
+
+/// `plan` as a plan file with nothing left to resolve: `vars` as its
+/// `[vars]`, and each entry in execution order with its ID, dependencies
+/// and [`definition`](PlanEntry::definition) spelled out. Entries built in
+/// code, which have no definition, appear as comments.
+pub fn render(plan: &ShiftPlan, vars: &BTreeMap<String, String>) -> ShiftResult<String> {
+    let mut out = String::new();
+    if let Some(root) = plan.root() {
+        let root = root.display().to_string().replace('$', "$$");
+        out.push_str(&format!("root = {}\n", toml_str(&root)));
+    }
+    if let Some(deadline) = plan.deadline() {
+        out.push_str(&format!("deadline = {}\n", deadline.as_secs_f64()));
+    }
+    let permissions = toml::Table::try_from(plan.permissions())
+        .map_err(|err| ShiftError::Custom(err.to_string()))?;
+    if !permissions.is_empty() {
+        out.push_str(&format!("\n[permissions]\n{}", lines(&permissions)));
+    }
+    let network =
+        toml::Table::try_from(plan.network()).map_err(|err| ShiftError::Custom(err.to_string()))?;
+    if !network.is_empty() {
+        out.push_str(&format!("\n[network]\n{}", lines(&network)));
+    }
+    if !vars.is_empty() {
+        out.push_str("\n[vars]\n");
+        for (name, value) in vars {
+            out.push_str(&format!(
+                "{} = {}\n",
+                toml_key(name),
+                toml_str(&value.replace('$', "$$"))
+            ));
+        }
+    }
+    for idx in plan.execution_order()? {
+        let entry = &plan.entries()[idx];
+        let Some(mut definition) = entry.definition() else {
+            out.push_str(&format!("\n# `{}` was built in code\n", entry.id()));
+            continue;
+        };
+        if let Some(origin) = entry.origin() {
+            out.push_str(&format!("\n# generated by {origin}"));
+        }
+        let kind = definition.remove("type");
+        let mut common = toml::Table::new();
+        common.insert("id".into(), entry.id().into());
+        common.extend(kind.map(|kind| ("type".to_string(), kind)));
+        if !entry.depends_on.is_empty() {
+            common.insert("depends_on".into(), entry.depends_on.clone().into());
+        }
+        if !entry.tags.is_empty() {
+            common.insert("tags".into(), entry.tags.clone().into());
+        }
+        if entry.allow_outside_root {
+            common.insert("allow_outside_root".into(), true.into());
+        }
+        if let Some(limit) = entry.time_limit {
+            common.insert("time_limit".into(), limit.as_secs_f64().into());
+        }
+        if let Some(group) = &entry.serial_group {
+            common.insert("serial_group".into(), group.clone().into());
+        }
+        out.push_str(&format!(
+            "\n[[shift]]\n{}{}",
+            lines_in_order(&common, &["id", "type"]),
+            lines(&definition)
+        ));
+    }
+    for check in plan.checks() {
+        match check.definition() {
+            Some(definition) => out.push_str(&format!("\n[[verify]]\n{}", lines(&definition))),
+            None => out.push_str(&format!("\n# `{}` was built in code\n", check.id())),
+        }
+    }
+    Ok(out.trim_start().to_string())
+}
+
+/// `table` as `key = value` lines, with the `first` keys first.
+fn lines_in_order(table: &toml::Table, first: &[&str]) -> String {
+    let keys = first
+        .iter()
+        .copied()
+        .filter(|key| table.contains_key(*key))
+        .chain(
+            table
+                .keys()
+                .map(String::as_str)
+                .filter(|key| !first.contains(key)),
+        );
+    keys.map(|key| format!("{} = {}\n", toml_key(key), escaped(&table[key])))
+        .collect()
+}
+
+/// `value` with every `$` doubled, so loading it again does not
+/// interpolate what was already interpolated.
+fn escaped(value: &toml::Value) -> toml::Value {
+    match value {
+        toml::Value::String(s) => toml::Value::String(s.replace('$', "$$")),
+        toml::Value::Array(items) => toml::Value::Array(items.iter().map(escaped).collect()),
+        toml::Value::Table(table) => toml::Value::Table(
+            table
+                .iter()
+                .map(|(key, value)| (key.clone(), escaped(value)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+fn lines(table: &toml::Table) -> String {
+    lines_in_order(table, &[])
+}
+
+fn toml_str(text: &str) -> String {
+    toml::Value::String(text.to_string()).to_string()
+}
+
+/// `key`, quoted if it is not a bare TOML key.
+fn toml_key(key: &str) -> String {
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if bare {
+        key.to_string()
+    } else {
+        toml_str(key)
+    }
+}
+
+/// Interpolates every string inside `value` in place.
+fn interpolate(value: &mut toml::Value, ctx: &ExecutionContext) -> ShiftResult<()> {
+    match value {
+        toml::Value::String(s) => *s = ctx.interpolate(s)?,
+        toml::Value::Array(items) => {
+            for item in items {
+                interpolate(item, ctx)?;
+            }
+        }
+        toml::Value::Table(table) => {
+            for (_, item) in table.iter_mut() {
+                interpolate(item, ctx)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// A string, number or boolean as the text a variable holds.
+pub(crate) fn scalar(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(s) => Some(s.clone()),
+        toml::Value::Integer(_) | toml::Value::Float(_) | toml::Value::Boolean(_) => {
+            Some(value.to_string())
+        }
+        _ => None,
+    }
+}
+
+fn plain(err: ShiftError) -> String {
+    match err {
+        ShiftError::Plan(msg) => msg,
+        other => other.to_string(),
+    }
+}
+
+/// Deserialization helpers for shift fields.
+pub(crate) mod de {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer};
+
+    /// An optional duration written as (fractional) seconds.
+    pub fn opt_secs<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Duration>, D::Error> {
+        Option::<f64>::deserialize(d)?
+            .map(Duration::try_from_secs_f64)
+            .transpose()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde::Deserialize;
+
+    use crate::context::ExecutionContext;
+    use crate::registry::Registry;
+
+    #[derive(Deserialize)]
+    struct Timeout {
+        #[serde(default, deserialize_with = "super::de::opt_secs")]
+        timeout: Option<Duration>,
+    }
+
+    fn timeout(source: &str) -> Result<Option<Duration>, toml::de::Error> {
+        toml::from_str::<Timeout>(source).map(|parsed| parsed.timeout)
+    }
+
+    #[test]
+    fn ids_with_control_characters_are_rejected() {
+        let source = r#"
+            [[shift]]
+            id = "app\ntouch /tmp/pwned"
+            type = "create_dir"
+            path = "app"
+        "#;
+        let err = super::parse(source, &Registry::builtin(), &mut ExecutionContext::new())
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("control characters"), "{err}");
+    }
+
+    #[test]
+    fn seconds_parse_as_durations() {
+        assert_eq!(timeout("").unwrap(), None);
+        assert_eq!(
+            timeout("timeout = 1.5").unwrap(),
+            Some(Duration::from_millis(1500))
+        );
+    }
+
+    #[test]
+    fn out_of_range_seconds_are_errors() {
+        assert!(timeout("timeout = -1.0").is_err());
+        assert!(timeout("timeout = 1e30").is_err());
+        assert!(timeout("timeout = nan").is_err());
+    }
+}
@@ -0,0 +1,198 @@
+//! Limits on what a plan may do, set by whoever runs it rather than by the
+//! plan, so a shared or downloaded plan can be applied knowing it cannot,
+//! say, write outside `$HOME` or run a shell.
+//!
+//! ```toml
+//! # /etc/skies/policy.toml
+//! sudo = false
+//!
+//! [types]
+//! deny = ["sudoers", "firewall"]
+//!
+//! [paths]
+//! allow = ["~"]
+//! deny = ["~/.ssh"]
+//!
+//! [commands]
+//! deny = ["sh", "bash", "curl"]
+//! ```
+//!
+//! Each table may have an `allow` list, which what the plan does must
+//! match, and a `deny` list, which it must not; `deny` wins. `types` are
+//! shift types; `paths` are prefixes of the paths shifts create or modify,
+//! with `~` for the home directory; `commands` are programs the plan names,
+//! such as a `cmd`'s `program` and `undo` or an `assert`'s command, by
+//! name or full path. Shifts that run programs for themselves, such as
+//! `apt-get` for a package, are held by `types` only. `sudo = false` forbids
+//! `run_as` and running `sudo`, `doas`, `su` or `pkexec`. `sandbox = true`
+//! runs commands in a [sandbox](crate::sandbox), failing where there is
+//! none.
+//!
+//! Policies are checked in preflight, so a plan that breaks one changes
+//! nothing. Plans applied from within a plan are held by the same
+//! policies.
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::metadata::ShiftMetadata;
+use crate::paths;
+
+/// Applies to every run on the machine, whether or not `--policy` is given.
+pub const SYSTEM_POLICY: &str = "/etc/skies/policy.toml";
+
+/// Programs that run something as another user.
+const ESCALATORS: &[&str] = &["sudo", "doas", "su", "pkexec"];
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    /// Whether shifts may run things as another user.
+    #[serde(default = "default_sudo")]
+    pub sudo: bool,
+    /// Whether commands must run in a [sandbox](crate::sandbox).
+    #[serde(default)]
+    pub sandbox: bool,
+    #[serde(default)]
+    pub types: Rules,
+    #[serde(default)]
+    pub paths: Rules,
+    #[serde(default)]
+    pub commands: Rules,
+    /// The file the policy was loaded from, for messages.
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
+}
+
+fn default_sudo() -> bool {
+    true
+}
+
+/// An allow list and a deny list.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rules {
+    /// When set, only what matches one of these is allowed.
+    #[serde(default)]
+    pub allow: Option<Vec<String>>,
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+impl Rules {
+    /// Why `value` is not allowed, if it is not.
+    fn check(&self, value: &str, matches: impl Fn(&str, &str) -> bool) -> Option<&'static str> {
+        if self.deny.iter().any(|rule| matches(rule, value)) {
+            return Some("denied");
+        }
+        match &self.allow {
+            Some(allow) if !allow.iter().any(|rule| matches(rule, value)) => Some("not allowed"),
+            _ => None,
+        }
+    }
+}
+
+impl Policy {
+    /// Loads the policy at `path`, which must be locked: not writable by
+    /// anyone but its owner, so the user whose plans it holds cannot loosen
+    /// it unless they own it.
+    pub fn load(path: &Path) -> ShiftResult<Policy> {
+        let context = || format!("loading policy {}", path.display());
+        let metadata =
+            std::fs::metadata(path).map_err(|err| ShiftError::from(err).context(context()))?;
+        if metadata.permissions().mode() & 0o022 != 0 {
+            return Err(ShiftError::PermissionDenied(format!(
+                "policy {} is writable by other users",
+                path.display()
+            ))
+            .hint(format!("run `chmod go-w {}`", path.display())));
+        }
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| ShiftError::from(err).context(context()))?;
+        let mut policy: Policy = toml::from_str(&text)
+            .map_err(|err| ShiftError::Plan(format!("{}: {err}", path.display())))?;
+        policy.source = Some(path.to_path_buf());
+        Ok(policy)
+    }
+
+    /// The system policy, if the machine has one.
+    pub fn system() -> ShiftResult<Option<Policy>> {
+        let path = Path::new(SYSTEM_POLICY);
+        if path.exists() {
+            Policy::load(path).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Fails with what a shift described by `meta` would do that the
+    /// policy forbids, with paths resolved against `ctx`.
+    pub fn check(&self, meta: &ShiftMetadata, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let mut problems = Vec::new();
+        if let Some(why) = self.types.check(&meta.kind, |rule, kind| rule == kind) {
+            problems.push(format!("shift type `{}` is {why}", meta.kind));
+        }
+        for target in &meta.targets {
+            let path = paths::canonical(&ctx.join_root(target));
+            let matches = |rule: &str, _: &str| {
+                let prefix = paths::canonical(&ctx.expand_home(Path::new(rule)));
+                path.starts_with(prefix)
+            };
+            if let Some(why) = self.paths.check(&path.to_string_lossy(), matches) {
+                problems.push(format!("path {} is {why}", path.display()));
+            }
+        }
+        let programs = commands(meta);
+        for program in &programs {
+            let matches = |rule: &str, program: &str| {
+                rule == program
+                    || Path::new(program)
+                        .file_name()
+                        .is_some_and(|name| name == rule)
+            };
+            if let Some(why) = self.commands.check(program, matches) {
+                problems.push(format!("command `{program}` is {why}"));
+            }
+        }
+        if !self.sudo {
+            if let Some(user) = meta.input_value("run_as").and_then(|user| user.as_str()) {
+                problems.push(format!("running as `{user}` is not allowed"));
+            }
+            let escalates = programs.iter().find(|program| {
+                Path::new(program)
+                    .file_name()
+                    .is_some_and(|name| ESCALATORS.iter().any(|escalator| name == *escalator))
+            });
+            if let Some(program) = escalates {
+                problems.push(format!("running `{program}` is not allowed"));
+            }
+        }
+        if problems.is_empty() {
+            return Ok(());
+        }
+        let source = self.source.as_deref().map_or_else(
+            || "the policy".to_string(),
+            |path| path.display().to_string(),
+        );
+        Err(ShiftError::PermissionDenied(format!(
+            "{} by {source}",
+            problems.join("; ")
+        )))
+    }
+}
+
+/// The programs the plan names in `meta`'s inputs.
+fn commands(meta: &ShiftMetadata) -> Vec<String> {
+    ["program", "command", "undo"]
+        .iter()
+        .filter_map(|name| match meta.input_value(name)? {
+            serde_json::Value::String(program) => Some(program.clone()),
+            serde_json::Value::Array(argv) => argv.first()?.as_str().map(String::from),
+            _ => None,
+        })
+        .collect()
+}
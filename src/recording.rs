@@ -0,0 +1,166 @@
+//! Recording the commands a run executes, and replaying them.
+//!
+//! A [`RecordingExec`] passes commands through to another [`Exec`] and
+//! keeps each one with its output. A [`ReplayExec`] answers from such a
+//! [`Recording`] instead of running anything, so a changed plan can be
+//! checked against a known-good run, or a plan demoed, without git, apt
+//! or the network.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::exec::{CommandOutput, CommandSpec, Exec};
+
+/// One command and what it gave back. Environment variables are left out:
+/// they are not needed to replay and may hold credentials.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedCommand {
+    pub program: String,
+    pub args: Vec<String>,
+    pub cwd: PathBuf,
+    pub code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl RecordedCommand {
+    fn output(&self) -> CommandOutput {
+        CommandOutput {
+            code: self.code,
+            stdout: self.stdout.clone(),
+            stderr: self.stderr.clone(),
+        }
+    }
+}
+
+/// The commands of a run, in the order they ran.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Recording {
+    pub commands: Vec<RecordedCommand>,
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> ShiftResult<Recording> {
+        let text = fs::read_to_string(path)?;
+        serde_json::from_str(&text).map_err(|err| {
+            ShiftError::Custom(format!("corrupt recording {}: {err}", path.display()))
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> ShiftResult<()> {
+        let text = serde_json::to_string_pretty(self)
+            .map_err(|err| ShiftError::Custom(format!("cannot encode recording: {err}")))?;
+        fs::write(path, text + "\n")?;
+        Ok(())
+    }
+}
+
+/// Runs commands through `inner` and records them.
+pub struct RecordingExec {
+    inner: Arc<dyn Exec>,
+    recording: Mutex<Recording>,
+}
+
+impl RecordingExec {
+    pub fn new(inner: Arc<dyn Exec>) -> Self {
+        RecordingExec {
+            inner,
+            recording: Mutex::new(Recording::default()),
+        }
+    }
+
+    /// What has been recorded so far.
+    pub fn recording(&self) -> Recording {
+        self.recording
+            .lock()
+            .unwrap_or_else(|p| p.into_inner())
+            .clone()
+    }
+}
+
+impl Exec for RecordingExec {
+    fn run(&self, ctx: &ExecutionContext, spec: &CommandSpec) -> ShiftResult<CommandOutput> {
+        let output = self.inner.run(ctx, spec)?;
+        self.recording
+            .lock()
+            .unwrap_or_else(|p| p.into_inner())
+            .commands
+            .push(RecordedCommand {
+                program: spec.program.clone(),
+                args: spec.args.clone(),
+                cwd: spec.cwd.clone(),
+                code: output.code,
+                stdout: output.stdout.clone(),
+                stderr: output.stderr.clone(),
+            });
+        Ok(output)
+    }
+}
+
+/// Answers commands from a [`Recording`].
+///
+/// Each command takes the first recorded command with the same program and
+/// arguments that has not been used yet, preferring one run in the same
+/// directory. A command the recording does not have is an error.
+pub struct ReplayExec {
+    /// Recorded commands and whether each has been replayed.
+    commands: Mutex<Vec<(RecordedCommand, bool)>>,
+}
+
+impl ReplayExec {
+    pub fn new(recording: Recording) -> Self {
+        ReplayExec {
+            commands: Mutex::new(
+                recording
+                    .commands
+                    .into_iter()
+                    .map(|command| (command, false))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Recorded commands nothing asked for, e.g. because the plan changed.
+    pub fn unused(&self) -> Vec<RecordedCommand> {
+        self.commands
+            .lock()
+            .unwrap_or_else(|p| p.into_inner())
+            .iter()
+            .filter(|(_, used)| !used)
+            .map(|(command, _)| command.clone())
+            .collect()
+    }
+}
+
+impl Exec for ReplayExec {
+    fn run(&self, _ctx: &ExecutionContext, spec: &CommandSpec) -> ShiftResult<CommandOutput> {
+        let mut commands = self.commands.lock().unwrap_or_else(|p| p.into_inner());
+        let same = |command: &RecordedCommand| {
+            command.program == spec.program && command.args == spec.args
+        };
+        let found = commands
+            .iter()
+            .position(|(command, used)| !used && same(command) && command.cwd == spec.cwd)
+            .or_else(|| {
+                commands
+                    .iter()
+                    .position(|(command, used)| !used && same(command))
+            });
+        match found {
+            Some(idx) => {
+                commands[idx].1 = true;
+                Ok(commands[idx].0.output())
+            }
+            None => Err(ShiftError::Custom(format!(
+                "`{}` is not in the recording",
+                spec.command_line()
+            ))
+            .hint("record the plan again with `skies apply --record`")),
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::BTreeMap;
+
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+use serde::de::DeserializeOwned;
+
+use crate::error::{ShiftError, ShiftResult};
+use crate::shift::Shift;
+use crate::shifts::{
+    AcmeCert, AppImage, ApplyPlanFile, Assert, BackupJob, BrewBundle, CargoAddDependency, CargoNew,
+    CargoWorkspaceMember, Cmd, CreateDir, CreateFile, DconfSetting, FirewallRule, FlatpakInstall,
+    Font, GitHook, GitHubClone, GpuToolkit, Hostname, LineInFile, Locale, Mount, MysqlDatabase,
+    MysqlUser, NeovimPlugins, NixProfileInstall, NodeInstall, NodeProjectInit, NodeVersion,
+    Package, PgDatabase, PgExtension, PgRole, PythonVersion, RedisConfig, RedisReady,
+    RustToolchain, SnapInstall, SudoersRule, SwapFile, Symlink, Sysctl, SystemdTimer,
+    TailscaleJoin, TimeSync, Timezone, TlsCert, VsCodeExtension, VsCodeSettings, WebVhost,
+    WireguardInterface, Workspace,
+};
+
+type Factory = fn(toml::Table) -> ShiftResult<Box<dyn Shift>>;
+
+type SchemaFn = fn(&mut SchemaGenerator) -> Schema;
+
+/// Maps the `type` of a plan file entry to the shift it builds.
+pub struct Registry {
+    kinds: BTreeMap<String, (Factory, SchemaFn)>,
+}
+
+impl Registry {
+    /// A registry with no shift types at all.
+    pub fn empty() -> Self {
+        Registry {
+            kinds: BTreeMap::new(),
+        }
+    }
+
+    /// A registry with every shift that ships with skies.
+    pub fn builtin() -> Self {
+        let mut registry = Self::empty();
+        registry.register::<CreateDir>("create_dir");
+        registry.register::<CreateFile>("file");
+        registry.register::<LineInFile>("line_in_file");
+        registry.register::<Cmd>("cmd");
+        registry.register::<GitHubClone>("github_clone");
+        registry.register::<GitHook>("git_hook");
+        registry.register::<Workspace>("workspace");
+        registry.register::<ApplyPlanFile>("apply_plan_file");
+        registry.register::<Package>("package");
+        registry.register::<BrewBundle>("brew_bundle");
+        registry.register::<NixProfileInstall>("nix_profile_install");
+        registry.register::<FlatpakInstall>("flatpak");
+        registry.register::<SnapInstall>("snap");
+        registry.register::<AppImage>("appimage");
+        registry.register::<VsCodeExtension>("vscode_extension");
+        registry.register::<VsCodeSettings>("vscode_settings");
+        registry.register::<NeovimPlugins>("neovim_plugins");
+        registry.register::<DconfSetting>("dconf");
+        registry.register::<Font>("font");
+        registry.register::<Timezone>("timezone");
+        registry.register::<Locale>("locale");
+        registry.register::<Hostname>("hostname");
+        registry.register::<TimeSync>("timesync");
+        registry.register::<SudoersRule>("sudoers");
+        registry.register::<PgRole>("pg_role");
+        registry.register::<PgDatabase>("pg_database");
+        registry.register::<PgExtension>("pg_extension");
+        registry.register::<MysqlDatabase>("mysql_database");
+        registry.register::<MysqlUser>("mysql_user");
+        registry.register::<RedisConfig>("redis_config");
+        registry.register::<RedisReady>("redis_ready");
+        registry.register::<WebVhost>("web_vhost");
+        registry.register::<AcmeCert>("acme_cert");
+        registry.register::<WireguardInterface>("wireguard");
+        registry.register::<TailscaleJoin>("tailscale");
+        registry.register::<SystemdTimer>("systemd_timer");
+        registry.register::<BackupJob>("backup");
+        registry.register::<GpuToolkit>("gpu_toolkit");
+        registry.register::<Symlink>("symlink");
+        registry.register::<TlsCert>("tls_cert");
+        registry.register::<FirewallRule>("firewall_rule");
+        registry.register::<Mount>("mount");
+        registry.register::<SwapFile>("swap_file");
+        registry.register::<Sysctl>("sysctl");
+        registry.register::<RustToolchain>("rust_toolchain");
+        registry.register::<CargoNew>("cargo_new");
+        registry.register::<CargoAddDependency>("cargo_add_dependency");
+        registry.register::<CargoWorkspaceMember>("cargo_workspace_member");
+        registry.register::<NodeVersion>("node_version");
+        registry.register::<NodeProjectInit>("node_project_init");
+        registry.register::<NodeInstall>("node_install");
+        registry.register::<PythonVersion>("python_version");
+        registry.register::<Assert>("assert");
+        registry
+    }
+
+    /// Makes `S` available under `kind`, replacing any previous registration.
+    pub fn register<S>(&mut self, kind: &str)
+    where
+        S: Shift + DeserializeOwned + JsonSchema + 'static,
+    {
+        fn build<S: Shift + DeserializeOwned + 'static>(
+            fields: toml::Table,
+        ) -> ShiftResult<Box<dyn Shift>> {
+            let shift: S = fields
+                .try_into()
+                .map_err(|err: toml::de::Error| ShiftError::Plan(err.message().to_string()))?;
+            Ok(Box::new(shift))
+        }
+        self.kinds
+            .insert(kind.to_string(), (build::<S>, S::json_schema));
+    }
+
+    pub fn kinds(&self) -> impl Iterator<Item = &str> {
+        self.kinds.keys().map(String::as_str)
+    }
+
+    /// Builds a shift of type `kind` from its plan file fields.
+    pub fn build(&self, kind: &str, fields: toml::Table) -> ShiftResult<Box<dyn Shift>> {
+        match self.kinds.get(kind) {
+            Some((factory, _)) => factory(fields),
+            None => Err(ShiftError::Plan(format!("unknown shift type `{kind}`"))),
+        }
+    }
+
+    /// The JSON Schema of the fields of shift type `kind`, with the types
+    /// it refers to added to `generator`.
+    pub fn schema(&self, kind: &str, generator: &mut SchemaGenerator) -> Option<Schema> {
+        let (_, schema) = self.kinds.get(kind)?;
+        Some(schema(generator))
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
@@ -0,0 +1,385 @@
+use std::io::{IsTerminal, Write};
+use std::sync::Arc;
+
+use serde_json::json;
+
+use crate::context::{LogLevel, Logger};
+use crate::error::ShiftError;
+use crate::plan::PlanEntry;
+
+/// Progress notifications emitted while a plan runs.
+pub enum PlanEvent<'a> {
+    /// The shift is about to be checked and, if not in place, applied.
+    Started(&'a PlanEntry),
+    /// The shift was already in place and was left alone.
+    Skipped(&'a PlanEntry),
+    Applied(&'a PlanEntry),
+    Failed(&'a PlanEntry, &'a ShiftError),
+    /// The shift ran out of time (or the plan did before it started).
+    TimedOut(&'a PlanEntry, &'a ShiftError),
+    /// The shift was undone because a later shift failed.
+    RolledBack(&'a PlanEntry),
+    RollbackFailed(&'a PlanEntry, &'a ShiftError),
+    Reverted(&'a PlanEntry),
+    /// Dry run: the shift is not in place and would be applied.
+    WouldApply(&'a PlanEntry),
+    /// Dry run: the shift is in place and would be reverted.
+    WouldRevert(&'a PlanEntry),
+    /// `skies adopt` found the shift in place and recorded it as applied.
+    Adopted(&'a PlanEntry),
+    /// The entry's verify checks passed after the plan applied (or, for
+    /// one of the plan's own checks, that check did).
+    Verified(&'a PlanEntry),
+}
+
+impl<'a> PlanEvent<'a> {
+    pub fn entry(&self) -> &'a PlanEntry {
+        match *self {
+            PlanEvent::Started(entry)
+            | PlanEvent::Skipped(entry)
+            | PlanEvent::Applied(entry)
+            | PlanEvent::Failed(entry, _)
+            | PlanEvent::TimedOut(entry, _)
+            | PlanEvent::RolledBack(entry)
+            | PlanEvent::RollbackFailed(entry, _)
+            | PlanEvent::Reverted(entry)
+            | PlanEvent::WouldApply(entry)
+            | PlanEvent::WouldRevert(entry)
+            | PlanEvent::Verified(entry)
+            | PlanEvent::Adopted(entry) => entry,
+        }
+    }
+
+    pub fn error(&self) -> Option<&'a ShiftError> {
+        match *self {
+            PlanEvent::Failed(_, err)
+            | PlanEvent::TimedOut(_, err)
+            | PlanEvent::RollbackFailed(_, err) => Some(err),
+            _ => None,
+        }
+    }
+
+    /// Stable snake_case name, as used in JSON output.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PlanEvent::Started(_) => "started",
+            PlanEvent::Skipped(_) => "skipped",
+            PlanEvent::Applied(_) => "applied",
+            PlanEvent::Failed(..) => "failed",
+            PlanEvent::TimedOut(..) => "timed_out",
+            PlanEvent::RolledBack(_) => "rolled_back",
+            PlanEvent::RollbackFailed(..) => "rollback_failed",
+            PlanEvent::Reverted(_) => "reverted",
+            PlanEvent::WouldApply(_) => "would_apply",
+            PlanEvent::WouldRevert(_) => "would_revert",
+            PlanEvent::Verified(_) => "verified",
+            PlanEvent::Adopted(_) => "adopted",
+        }
+    }
+}
+
+/// Receives plan progress, e.g. to print it.
+pub trait Reporter {
+    fn report(&mut self, event: &PlanEvent<'_>);
+}
+
+/// Discards every event.
+pub struct NullReporter;
+
+impl Reporter for NullReporter {
+    fn report(&mut self, _event: &PlanEvent<'_>) {}
+}
+
+/// When to use color and symbols on the console.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Only on a terminal, and not if `NO_COLOR` is set or `TERM=dumb`.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// How console output looks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConsoleStyle {
+    /// ANSI colors.
+    pub color: bool,
+    /// `✓`/`✗` and friends rather than plain words, which read better in
+    /// logs and pipes.
+    pub symbols: bool,
+}
+
+impl ConsoleStyle {
+    /// The style for stdout under `choice`.
+    pub fn stdout(choice: ColorChoice) -> Self {
+        Self::detect(choice, std::io::stdout().is_terminal())
+    }
+
+    /// The style for stderr under `choice`.
+    pub fn stderr(choice: ColorChoice) -> Self {
+        Self::detect(choice, std::io::stderr().is_terminal())
+    }
+
+    fn detect(choice: ColorChoice, terminal: bool) -> Self {
+        let color_allowed = std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+            && std::env::var_os("TERM").is_none_or(|term| term != "dumb");
+        match choice {
+            ColorChoice::Always => ConsoleStyle {
+                color: true,
+                symbols: true,
+            },
+            ColorChoice::Never => ConsoleStyle {
+                color: false,
+                symbols: terminal,
+            },
+            ColorChoice::Auto => ConsoleStyle {
+                color: terminal && color_allowed,
+                symbols: terminal,
+            },
+        }
+    }
+
+    /// `text` in the ANSI color `code`, if colors are on.
+    pub fn paint(&self, code: &str, text: &str) -> String {
+        if self.color {
+            format!("\x1b[{code}m{text}\x1b[0m")
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+/// `err` as the CLI shows it: the outermost context, each cause indented
+/// below it and then any hints.
+pub fn render_error(err: &ShiftError, style: &ConsoleStyle) -> String {
+    let chain = err.chain();
+    let mut out = chain[0].clone();
+    for cause in &chain[1..] {
+        out.push_str(&format!("\n  caused by: {cause}"));
+    }
+    for hint in err.hints() {
+        out.push_str(&format!("\n  {}: {hint}", style.paint(CYAN, "hint")));
+    }
+    out
+}
+
+/// Sets `error` (the one-line message), `error_kind`, `error_chain` and
+/// `hints` on the JSON object `value`.
+pub fn add_error_json(value: &mut serde_json::Value, err: &ShiftError) {
+    value["error"] = json!(err.to_string());
+    value["error_kind"] = json!(err.kind());
+    value["error_chain"] = json!(err.chain());
+    value["hints"] = json!(err.hints());
+}
+
+const GREEN: &str = "32";
+const RED: &str = "31";
+const YELLOW: &str = "33";
+const CYAN: &str = "36";
+const DIM: &str = "2";
+
+/// Prints one line per event to stdout.
+pub struct ConsoleReporter {
+    style: ConsoleStyle,
+    quiet: bool,
+}
+
+/// Styled for stdout with [`ColorChoice::Auto`].
+impl Default for ConsoleReporter {
+    fn default() -> Self {
+        ConsoleReporter::new(ConsoleStyle::stdout(ColorChoice::Auto))
+    }
+}
+
+impl ConsoleReporter {
+    pub fn new(style: ConsoleStyle) -> Self {
+        ConsoleReporter {
+            style,
+            quiet: false,
+        }
+    }
+
+    /// Prints only failures.
+    pub fn quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// The event's marker: a symbol or, without symbols, a word.
+    fn marker(&self, event: &PlanEvent<'_>) -> String {
+        let (symbol, word, color) = match event {
+            PlanEvent::Started(_) => ("…", "start", DIM),
+            PlanEvent::Skipped(_) => ("-", "skip", DIM),
+            PlanEvent::Applied(_) | PlanEvent::Verified(_) => ("✓", "ok", GREEN),
+            PlanEvent::Adopted(_) => ("✓", "adopt", GREEN),
+            PlanEvent::Failed(..) | PlanEvent::RollbackFailed(..) => ("✗", "FAIL", RED),
+            PlanEvent::TimedOut(..) => ("⏱", "TIMEOUT", YELLOW),
+            PlanEvent::RolledBack(_) | PlanEvent::Reverted(_) => ("↺", "undo", CYAN),
+            PlanEvent::WouldApply(_) => ("+", "plan", GREEN),
+            PlanEvent::WouldRevert(_) => ("-", "plan", CYAN),
+        };
+        let marker = if self.style.symbols { symbol } else { word };
+        self.style.paint(color, marker)
+    }
+}
+
+impl Reporter for ConsoleReporter {
+    fn report(&mut self, event: &PlanEvent<'_>) {
+        // Each shift gets its line once it is done.
+        if matches!(event, PlanEvent::Started(_)) || self.quiet && event.error().is_none() {
+            return;
+        }
+        let summary = event.entry().shift.metadata().summary;
+        let marker = self.marker(event);
+        match event {
+            PlanEvent::Started(_) => {}
+            PlanEvent::Skipped(_) => println!("{marker} {summary} (already applied)"),
+            PlanEvent::Applied(_) | PlanEvent::Reverted(_) => println!("{marker} {summary}"),
+            PlanEvent::Failed(_, err) | PlanEvent::TimedOut(_, err) => {
+                println!("{marker} {summary}: {err}")
+            }
+            PlanEvent::RolledBack(_) => println!("{marker} {summary} (rolled back)"),
+            PlanEvent::RollbackFailed(_, err) => {
+                println!("{marker} {summary} (rollback failed: {err})")
+            }
+            PlanEvent::WouldApply(_) => println!("{marker} {summary} (would apply)"),
+            PlanEvent::WouldRevert(_) => println!("{marker} {summary} (would revert)"),
+            PlanEvent::Verified(_) => println!("{marker} {summary} (verified)"),
+            PlanEvent::Adopted(_) => println!("{marker} {summary} (adopted)"),
+        }
+    }
+}
+
+/// Prints events as GitHub Actions workflow commands: each shift's output
+/// folds into a `::group::`, with the [`ConsoleReporter`] line inside it,
+/// and failures become `::error` annotations, which show on the workflow
+/// run and on the pull request. A rollback is a `::warning`.
+///
+/// With shifts running in parallel, only one group is open at a time and
+/// the others' output lands in it.
+pub struct GhaReporter {
+    console: ConsoleReporter,
+    /// The shift whose group is open.
+    group: Option<String>,
+}
+
+impl GhaReporter {
+    pub fn new(console: ConsoleReporter) -> Self {
+        GhaReporter {
+            console,
+            group: None,
+        }
+    }
+}
+
+impl Reporter for GhaReporter {
+    fn report(&mut self, event: &PlanEvent<'_>) {
+        let entry = event.entry();
+        let id = entry.id();
+        if let PlanEvent::Started(_) = event {
+            if self.group.is_none() {
+                println!("::group::{}", gha_data(&entry.shift.metadata().summary));
+                self.group = Some(id.to_string());
+            }
+            return;
+        }
+        self.console.report(event);
+        if self.group.as_deref() == Some(id) {
+            println!("::endgroup::");
+            self.group = None;
+        }
+        match event {
+            PlanEvent::Failed(_, err)
+            | PlanEvent::TimedOut(_, err)
+            | PlanEvent::RollbackFailed(_, err) => {
+                let title = match event {
+                    PlanEvent::TimedOut(..) => format!("{id} timed out"),
+                    PlanEvent::RollbackFailed(..) => format!("{id} could not be rolled back"),
+                    _ => format!("{id} failed"),
+                };
+                println!(
+                    "::error title={}::{}",
+                    gha_property(&title),
+                    gha_data(&err.to_string())
+                );
+            }
+            PlanEvent::RolledBack(_) => println!(
+                "::warning title={}::rolled back after a later shift failed",
+                gha_property(&format!("{id} rolled back"))
+            ),
+            _ => {}
+        }
+    }
+}
+
+/// Escapes a workflow command's message.
+pub fn gha_data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escapes a workflow command's property, such as `title`.
+pub fn gha_property(text: &str) -> String {
+    gha_data(text).replace(':', "%3A").replace(',', "%2C")
+}
+
+/// Logs one line per event at [`LogLevel::Info`], e.g. so a log file
+/// holds the run's progress alongside the shifts' own messages.
+pub struct LogReporter(pub Arc<dyn Logger>);
+
+impl Reporter for LogReporter {
+    fn report(&mut self, event: &PlanEvent<'_>) {
+        if let PlanEvent::Started(_) = event {
+            return;
+        }
+        let entry = event.entry();
+        let summary = entry.shift.metadata().summary;
+        let message = match event.error() {
+            Some(err) => format!("{} {}: {summary}: {err}", event.name(), entry.id()),
+            None => format!("{} {}: {summary}", event.name(), entry.id()),
+        };
+        self.0.log(LogLevel::Info, &message);
+    }
+}
+
+/// Writes one JSON object per event (JSON Lines) to a writer.
+///
+/// Each line carries the event name, the shift ID and the shift's metadata
+/// with sensitive inputs redacted.
+pub struct JsonReporter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> JsonReporter<W> {
+    pub fn new(out: W) -> Self {
+        JsonReporter { out }
+    }
+}
+
+impl<W: Write> Reporter for JsonReporter<W> {
+    fn report(&mut self, event: &PlanEvent<'_>) {
+        let entry = event.entry();
+        let mut line = json!({
+            "event": event.name(),
+            "id": entry.id(),
+            "metadata": entry.shift.metadata().redacted(),
+        });
+        if let Some(err) = event.error() {
+            add_error_json(&mut line, err);
+        }
+        let _ = writeln!(self.out, "{line}");
+    }
+}
+
+/// Forwards every event to several reporters.
+pub struct Fanout<'a>(pub Vec<&'a mut dyn Reporter>);
+
+impl Reporter for Fanout<'_> {
+    fn report(&mut self, event: &PlanEvent<'_>) {
+        for reporter in &mut self.0 {
+            reporter.report(event);
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! What shifts touch, so a plan can tell when two of them would fight.
+//!
+//! Every shift claims [`Resource`]s through [`Shift::resources`]. A shift
+//! that owns a resource outright (the file it writes, the mount point it
+//! mounts on) claims it [`Exclusive`](Access::Exclusive)ly; preflight
+//! rejects two exclusive claims on the same resource. A parallel apply
+//! never runs two shifts with overlapping claims at once, whatever their
+//! access, and keeps them in execution order.
+//!
+//! [`Shift::resources`]: crate::Shift::resources
+
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::context::ExecutionContext;
+use crate::paths;
+
+/// Something a shift reads or changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resource {
+    /// A file or directory, including everything below it.
+    Path(PathBuf),
+    /// A TCP or UDP port.
+    Port(u16),
+    /// A named lock that is not a path, such as a package manager (`apt`)
+    /// or a kernel setting.
+    Lock(String),
+}
+
+impl Resource {
+    pub fn path(path: impl Into<PathBuf>) -> Self {
+        Resource::Path(path.into())
+    }
+
+    pub fn lock(name: impl Into<String>) -> Self {
+        Resource::Lock(name.into())
+    }
+
+    /// Whether using both at once could race: the same port or lock, or
+    /// paths one of which is inside the other.
+    pub fn overlaps(&self, other: &Resource) -> bool {
+        match (self, other) {
+            (Resource::Path(a), Resource::Path(b)) => a.starts_with(b) || b.starts_with(a),
+            _ => self == other,
+        }
+    }
+
+    /// The same resource with a path resolved against `ctx`'s root.
+    pub(crate) fn resolved(&self, ctx: &ExecutionContext) -> Resource {
+        match self {
+            Resource::Path(path) => Resource::Path(paths::normalize(&ctx.join_root(path))),
+            other => other.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Resource::Path(path) => write!(f, "{}", path.display()),
+            Resource::Port(port) => write!(f, "port {port}"),
+            Resource::Lock(name) => write!(f, "`{name}`"),
+        }
+    }
+}
+
+/// How a shift uses a resource.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Other shifts may use it too, one at a time (e.g. each adding a line
+    /// to the same file).
+    Shared,
+    /// The shift owns it; no other shift may claim it exclusively.
+    Exclusive,
+}
+
+/// A resource and how a shift uses it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Claim {
+    pub resource: Resource,
+    pub access: Access,
+}
+
+impl Claim {
+    pub fn shared(resource: Resource) -> Self {
+        Claim {
+            resource,
+            access: Access::Shared,
+        }
+    }
+
+    pub fn exclusive(resource: Resource) -> Self {
+        Claim {
+            resource,
+            access: Access::Exclusive,
+        }
+    }
+
+    /// Whether this and `other` both own the same resource.
+    pub fn conflicts(&self, other: &Claim) -> bool {
+        self.access == Access::Exclusive
+            && other.access == Access::Exclusive
+            && self.resource == other.resource
+    }
+}
@@ -0,0 +1,113 @@
+//! Running steps of a root-run plan as another user.
+//!
+//! Commands switch users through `sudo -u`, keeping the variables the shift
+//! sets. Files and directories are created as usual and then handed to the
+//! user and their login group. Both need a Unix system; elsewhere a shift
+//! with `run_as` fails preflight.
+
+use std::path::Path;
+
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::shifts::Cmd;
+use crate::validate::ValidationContext;
+
+/// Numeric user and group ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ids {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+/// Fails unless shifts can run as `user` here.
+pub fn check(ctx: &ValidationContext, user: &str, needs_sudo: bool) -> ShiftResult<()> {
+    if !cfg!(unix) {
+        return Err(ShiftError::Custom(format!(
+            "cannot run as `{user}`: `run_as` is only supported on Unix"
+        )));
+    }
+    if user.is_empty() || user.starts_with('-') {
+        return Err(ShiftError::Custom(format!("`{user}` is not a user name")));
+    }
+    ctx.require_program("id")?;
+    if needs_sudo {
+        ctx.require_program("sudo")?;
+    }
+    Ok(())
+}
+
+/// The IDs of `user`, looked up with `id`.
+pub fn ids(ctx: &ExecutionContext, user: &str) -> ShiftResult<Ids> {
+    let id = |flag: &str| -> ShiftResult<u32> {
+        let out = Cmd::new("id").args([flag, user]).output(ctx)?;
+        out.trim()
+            .parse()
+            .map_err(|_| ShiftError::Custom(format!("unexpected output from `id {flag} {user}`")))
+    };
+    Ok(Ids {
+        uid: id("-u")?,
+        gid: id("-g")?,
+    })
+}
+
+/// Whether the process already runs as `user`, so no switch is needed.
+pub fn is_current(ctx: &ExecutionContext, user: &str) -> ShiftResult<bool> {
+    let current = Cmd::new("id").arg("-u").output(ctx)?;
+    Ok(current.trim() == ids(ctx, user)?.uid.to_string())
+}
+
+/// `program` and `args` wrapped to run as `user`, passing on the variables
+/// named in `env`. Non-interactive runs fail rather than prompt for a
+/// password.
+pub fn sudo(
+    ctx: &ExecutionContext,
+    user: &str,
+    env: impl IntoIterator<Item = impl AsRef<str>>,
+    program: &str,
+    args: &[String],
+) -> (String, Vec<String>) {
+    let mut argv = vec!["-u".to_string(), user.to_string()];
+    if !ctx.is_interactive() {
+        argv.push("-n".into());
+    }
+    let keep: Vec<String> = env
+        .into_iter()
+        .map(|key| key.as_ref().to_string())
+        .collect();
+    if !keep.is_empty() {
+        argv.push(format!("--preserve-env={}", keep.join(",")));
+    }
+    argv.push("--".into());
+    argv.push(program.to_string());
+    argv.extend(args.iter().cloned());
+    ("sudo".to_string(), argv)
+}
+
+/// Whether `path` belongs to `ids`. Always true off Unix.
+#[cfg(unix)]
+pub fn owned_by(path: &Path, ids: Ids) -> ShiftResult<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let meta = std::fs::symlink_metadata(path)?;
+    Ok(meta.uid() == ids.uid && meta.gid() == ids.gid)
+}
+
+#[cfg(not(unix))]
+pub fn owned_by(_path: &Path, _ids: Ids) -> ShiftResult<bool> {
+    Ok(true)
+}
+
+/// Hands `path` to `ids`.
+#[cfg(unix)]
+pub fn chown(path: &Path, ids: Ids) -> ShiftResult<()> {
+    std::os::unix::fs::lchown(path, Some(ids.uid), Some(ids.gid))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn chown(path: &Path, _ids: Ids) -> ShiftResult<()> {
+    Err(ShiftError::Custom(format!(
+        "cannot change the owner of {}: `run_as` is only supported on Unix",
+        path.display()
+    )))
+}
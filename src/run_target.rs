@@ -0,0 +1,226 @@
+//! Running a plan somewhere other than the local machine.
+//!
+//! A [`RunTarget`] does not apply shifts itself. It stages the running
+//! `skies` binary and the plan's directory inside the target and runs
+//! `skies` there, so the plan sees the target's filesystem, facts and
+//! state exactly as it would when run locally.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+use crate::error::{ShiftError, ShiftResult};
+use crate::workspace::TempWorkspace;
+
+/// Where the staged plan directory lives inside a target.
+pub const PLAN_DIR: &str = "/skies/plan";
+/// Where the staged `skies` binary lives inside a target.
+pub const BINARY: &str = "/skies/bin/skies";
+
+/// Where a plan runs.
+///
+/// Parsed from `local`, `chroot:///path/to/root` or `docker://image`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunTarget {
+    Local,
+    /// A directory tree entered with `chroot(8)`. Needs root, and the
+    /// `skies` binary must be able to run inside it (static, or with
+    /// matching libraries).
+    Chroot(PathBuf),
+    /// A fresh container from `image`, run with `docker`.
+    Docker {
+        image: String,
+    },
+}
+
+impl FromStr for RunTarget {
+    type Err = ShiftError;
+
+    fn from_str(spec: &str) -> ShiftResult<Self> {
+        if spec == "local" {
+            return Ok(RunTarget::Local);
+        }
+        let invalid = |why: &str| ShiftError::Custom(format!("invalid target `{spec}`: {why}"));
+        match spec.split_once("://") {
+            Some(("chroot", path)) if path.starts_with('/') => Ok(RunTarget::Chroot(path.into())),
+            Some(("chroot", _)) => Err(invalid("the chroot path must be absolute")),
+            Some(("docker", image)) if !image.is_empty() => Ok(RunTarget::Docker {
+                image: image.to_string(),
+            }),
+            Some(("docker", _)) => Err(invalid("missing image")),
+            _ => Err(invalid(
+                "expected `local`, `chroot:///path` or `docker://image`",
+            )),
+        }
+    }
+}
+
+impl fmt::Display for RunTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunTarget::Local => f.write_str("local"),
+            RunTarget::Chroot(path) => write!(f, "chroot://{}", path.display()),
+            RunTarget::Docker { image } => write!(f, "docker://{image}"),
+        }
+    }
+}
+
+/// Options for [`RunTarget::run`].
+#[derive(Debug, Default, Clone)]
+pub struct RemoteRun {
+    /// Arguments for the inner `skies`. Refer to the plan file as
+    /// [`RunTarget::plan_path`] returns it.
+    pub args: Vec<String>,
+    /// For docker targets, save the container as this image afterwards,
+    /// whether or not the run succeeded.
+    pub commit: Option<String>,
+}
+
+impl RunTarget {
+    /// Path of `plan` as the inner `skies` sees it.
+    pub fn plan_path(&self, plan: &Path) -> PathBuf {
+        match self {
+            RunTarget::Local => plan.to_path_buf(),
+            _ => Path::new(PLAN_DIR).join(plan.file_name().unwrap_or_default()),
+        }
+    }
+
+    /// Stages this binary and the directory holding `plan` in the target
+    /// and runs `skies` there with `run.args`.
+    ///
+    /// The local journal and state (`.skies/`) are not copied; the target
+    /// keeps its own under [`PLAN_DIR`].
+    pub fn run(&self, plan: &Path, run: &RemoteRun) -> ShiftResult<()> {
+        let exe = std::env::current_exe()?;
+        let plan_dir = match plan.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let what = format!(
+            "skies {} in {self}",
+            run.args.first().map(String::as_str).unwrap_or_default()
+        );
+        match self {
+            RunTarget::Local => Err(ShiftError::Custom(
+                "the local target runs in-process".into(),
+            )),
+            RunTarget::Chroot(root) => {
+                if run.commit.is_some() {
+                    return Err(ShiftError::Custom(
+                        "--commit only applies to docker targets".into(),
+                    ));
+                }
+                let inner = |path: &str| root.join(path.trim_start_matches('/'));
+                copy_tree(&plan_dir, &inner(PLAN_DIR))?;
+                fs::create_dir_all(inner(BINARY).parent().unwrap_or(root))?;
+                fs::copy(&exe, inner(BINARY))?;
+                let mut cmd = Command::new("chroot");
+                cmd.arg(root)
+                    .args([
+                        "/bin/sh",
+                        "-c",
+                        "cd \"$0\" && exec \"$@\"",
+                        PLAN_DIR,
+                        BINARY,
+                    ])
+                    .args(&run.args);
+                status(&mut cmd, &what)
+            }
+            RunTarget::Docker { image } => {
+                let name = format!("skies-{}", std::process::id());
+                let stage = TempWorkspace::new();
+                let staged = stage.subdir("target")?;
+                let inner = |path: &str| staged.join(path.trim_start_matches('/'));
+                copy_tree(&plan_dir, &inner(PLAN_DIR))?;
+                fs::create_dir_all(inner(BINARY).parent().unwrap_or(&staged))?;
+                fs::copy(&exe, inner(BINARY))?;
+                let result = (|| {
+                    let mut create = Command::new("docker");
+                    create
+                        .args(["create", "--name", &name, "--workdir", PLAN_DIR, image])
+                        .args([BINARY])
+                        .args(&run.args);
+                    output(&mut create)?;
+                    let mut copy = Command::new("docker");
+                    copy.arg("cp")
+                        .arg(staged.join("skies"))
+                        .arg(format!("{name}:/"));
+                    output(&mut copy)?;
+                    let result = status(
+                        Command::new("docker").args(["start", "--attach", &name]),
+                        &what,
+                    );
+                    if let Some(tag) = &run.commit {
+                        output(Command::new("docker").args(["commit", &name, tag]))?;
+                    }
+                    result
+                })();
+                stage.finish(true);
+                let _ = Command::new("docker")
+                    .args(["rm", "--force", &name])
+                    .output();
+                result
+            }
+        }
+    }
+}
+
+/// Copies `from` into `to`, skipping the local `.skies` directory.
+pub(crate) fn copy_tree(from: &Path, to: &Path) -> ShiftResult<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        if entry.file_name() == ".skies" {
+            continue;
+        }
+        let dest = to.join(entry.file_name());
+        let kind = entry.file_type()?;
+        if kind.is_dir() {
+            copy_tree(&entry.path(), &dest)?;
+        } else if kind.is_symlink() {
+            let _ = fs::remove_file(&dest);
+            std::os::unix::fs::symlink(fs::read_link(entry.path())?, &dest)?;
+        } else {
+            fs::copy(entry.path(), &dest)?;
+        }
+    }
+    Ok(())
+}
+
+/// Runs `cmd` with inherited output, failing as `what` on a non-zero exit.
+fn status(cmd: &mut Command, what: &str) -> ShiftResult<()> {
+    let status = cmd.status()?;
+    if status.success() {
+        return Ok(());
+    }
+    Err(ShiftError::Command {
+        command: what.to_string(),
+        code: status.code(),
+        stderr: String::new(),
+    })
+}
+
+/// Runs `cmd` quietly, failing with its stderr on a non-zero exit.
+fn output(cmd: &mut Command) -> ShiftResult<()> {
+    let output = cmd.output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+    Err(ShiftError::Command {
+        command: describe(cmd),
+        code: output.status.code(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+    })
+}
+
+fn describe(cmd: &Command) -> String {
+    let mut words = vec![cmd.get_program().to_string_lossy().into_owned()];
+    words.extend(
+        cmd.get_args()
+            .take(2)
+            .map(|a| a.to_string_lossy().into_owned()),
+    );
+    words.join(" ")
+}
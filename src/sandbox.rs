@@ -0,0 +1,149 @@
+//! Running a plan's commands in a [bubblewrap] sandbox, for plans that are
+//! not fully trusted.
+//!
+//! A sandboxed command sees the whole filesystem read-only, except for what
+//! the shift declares it works on: the plan root, the command's `cwd` and
+//! the paths it `creates`. It gets a private `/tmp`, its own process, IPC
+//! and network namespaces and so no network unless the shift asks for it,
+//! and it dies with skies.
+//!
+//! Bubblewrap needs Linux and the `bwrap` program. Where either is
+//! missing, [`SandboxMode::Auto`] runs commands as usual and warns once;
+//! [`SandboxMode::Required`] fails the plan in preflight.
+//!
+//! [bubblewrap]: https://github.com/containers/bubblewrap
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::paths;
+use crate::validate::find_program;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SandboxMode {
+    /// Sandbox commands where possible; run them as usual elsewhere.
+    Auto,
+    /// Refuse to run commands that cannot be sandboxed.
+    Required,
+}
+
+pub struct Sandbox {
+    mode: SandboxMode,
+    /// Set once the fallback has been reported.
+    warned: AtomicBool,
+}
+
+/// What a sandboxed command may reach.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Access {
+    /// Directories the command may write in, besides its own `/tmp`.
+    pub writable: Vec<PathBuf>,
+    pub network: bool,
+}
+
+impl Sandbox {
+    pub fn new(mode: SandboxMode) -> Self {
+        Sandbox {
+            mode,
+            warned: AtomicBool::new(false),
+        }
+    }
+
+    pub fn mode(&self) -> SandboxMode {
+        self.mode
+    }
+
+    /// The `bwrap` to run commands with, or why there is none.
+    pub fn bwrap() -> Result<PathBuf, String> {
+        if !cfg!(target_os = "linux") {
+            return Err("sandboxing needs Linux".into());
+        }
+        find_program("bwrap").ok_or_else(|| "bwrap is not installed".into())
+    }
+
+    /// Fails if commands must be sandboxed and cannot be here.
+    pub fn check(&self) -> ShiftResult<()> {
+        match Self::bwrap() {
+            Err(reason) if self.mode == SandboxMode::Required => Err(ShiftError::Custom(format!(
+                "cannot sandbox commands: {reason}"
+            ))
+            .hint("install bubblewrap, or run without --sandbox=require")),
+            _ => Ok(()),
+        }
+    }
+
+    /// The command to run in place of `program` with `args` so that it can
+    /// only reach `access`, or the command itself where there is no
+    /// sandbox.
+    pub fn wrap(
+        &self,
+        ctx: &ExecutionContext,
+        program: &str,
+        args: &[String],
+        cwd: &Path,
+        access: &Access,
+    ) -> ShiftResult<(String, Vec<String>)> {
+        let bwrap = match Self::bwrap() {
+            Ok(bwrap) => bwrap,
+            Err(reason) => {
+                self.check()?;
+                if !self.warned.swap(true, Ordering::Relaxed) {
+                    ctx.warn(&format!("{reason}; running commands without a sandbox"));
+                }
+                return Ok((program.to_string(), args.to_vec()));
+            }
+        };
+        let mut argv: Vec<String> = [
+            "--die-with-parent",
+            "--unshare-all",
+            "--ro-bind",
+            "/",
+            "/",
+            "--dev",
+            "/dev",
+            "--proc",
+            "/proc",
+            "--tmpfs",
+            "/tmp",
+        ]
+        .map(String::from)
+        .into();
+        if access.network {
+            argv.push("--share-net".into());
+        }
+        // Later mounts win, so these show through the read-only root and
+        // the fresh /tmp.
+        let mut bound: Vec<PathBuf> = Vec::new();
+        for dir in &access.writable {
+            let dir = paths::canonical(dir);
+            let Some(dir) = existing(&dir) else {
+                continue;
+            };
+            if bound.iter().any(|outer| dir.starts_with(outer)) {
+                continue;
+            }
+            bound.push(dir.to_path_buf());
+            let dir = dir.display().to_string();
+            argv.extend(["--bind".to_string(), dir.clone(), dir]);
+        }
+        argv.extend([
+            "--chdir".to_string(),
+            cwd.display().to_string(),
+            "--".to_string(),
+            program.to_string(),
+        ]);
+        argv.extend(args.iter().cloned());
+        Ok((bwrap.display().to_string(), argv))
+    }
+}
+
+/// `path`, or its parent if only that exists yet.
+fn existing(path: &Path) -> Option<&Path> {
+    if path.exists() {
+        return Some(path);
+    }
+    path.parent()
+        .filter(|parent| parent.exists() && *parent != Path::new("/"))
+}
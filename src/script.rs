@@ -0,0 +1,88 @@
+//! Plan scripts: a [Rhai](https://rhai.rs) script that generates shifts,
+//! for plans a TOML file cannot express, such as one shift per item of a
+//! list worked out at load time.
+//!
+//! ```toml
+//! script = "users.rhai"
+//! ```
+//!
+//! ```rhai
+//! for user in vars.users.split(",") {
+//!     shift(#{ id: `home-${user}`, type: "create_dir", path: `/home/${user}/src` });
+//! }
+//! if facts.os == "linux" {
+//!     shift(#{ type: "cmd", program: "systemctl", args: ["daemon-reload"],
+//!              depends_on: ["home-alice"] });
+//! }
+//! ```
+//!
+//! The script sees the plan's variables as `vars` and the machine's facts
+//! as `facts`, and `shift(#{ ... })` adds an entry with the fields of a
+//! `[[shift]]` table, `role`, `when` and `${...}` included. Generated
+//! entries follow the file's own, and `skies explain` marks each with the
+//! script it came from. `print` logs at info level.
+//!
+//! Needs skies built with the `script` feature.
+
+use std::path::Path;
+
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+
+/// Runs the script at `path` and returns the entries it generated.
+#[cfg(feature = "script")]
+pub fn run(path: &Path, ctx: &ExecutionContext) -> ShiftResult<Vec<toml::Table>> {
+    use std::sync::{Arc, Mutex};
+
+    use rhai::{Dynamic, Engine, Map, Scope};
+
+    /// Enough for any reasonable generator, while a runaway loop still
+    /// fails the load.
+    const MAX_OPERATIONS: u64 = 50_000_000;
+
+    let context = |msg: String| ShiftError::Plan(format!("{}: {msg}", path.display()));
+    let source = std::fs::read_to_string(path)
+        .map_err(|err| ShiftError::Plan(format!("cannot read {}: {err}", path.display())))?;
+    let emitted: Arc<Mutex<Vec<Map>>> = Arc::default();
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    let sink = emitted.clone();
+    engine.register_fn("shift", move |entry: Map| sink.lock().unwrap().push(entry));
+    let logger = ctx.clone();
+    engine.on_print(move |text| logger.info(text));
+    let logger = ctx.clone();
+    engine.on_debug(move |text, _, _| logger.debug(text));
+
+    let to_map = |pairs: Vec<(&str, &str)>| -> Map {
+        pairs
+            .into_iter()
+            .map(|(name, value)| (name.into(), Dynamic::from(value.to_string())))
+            .collect()
+    };
+    let mut scope = Scope::new();
+    let vars = ctx.vars().iter().map(|(k, v)| (k.as_str(), v.as_str()));
+    scope.push_constant("vars", to_map(vars.collect()));
+    scope.push_constant("facts", to_map(ctx.facts().iter().collect()));
+    engine
+        .run_with_scope(&mut scope, &source)
+        .map_err(|err| context(err.to_string()))?;
+
+    let emitted = std::mem::take(&mut *emitted.lock().unwrap());
+    emitted
+        .into_iter()
+        .enumerate()
+        .map(|(idx, entry)| {
+            rhai::serde::from_dynamic(&Dynamic::from_map(entry))
+                .map_err(|err| context(format!("shift #{}: {err}", idx + 1)))
+        })
+        .collect()
+}
+
+/// Fails: plan scripts need the `script` feature.
+#[cfg(not(feature = "script"))]
+pub fn run(path: &Path, _ctx: &ExecutionContext) -> ShiftResult<Vec<toml::Table>> {
+    Err(ShiftError::Plan(format!(
+        "{}: plan scripts need skies built with the `script` feature",
+        path.display()
+    )))
+}
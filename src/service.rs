@@ -0,0 +1,93 @@
+//! Managing systemd services, for shifts that configure a daemon.
+
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::shifts::Cmd;
+
+/// Makes systemd re-read unit files after they are written or removed.
+pub fn daemon_reload(ctx: &ExecutionContext) -> ShiftResult<()> {
+    Cmd::new("systemctl")
+        .arg("daemon-reload")
+        .output(ctx)
+        .map(drop)
+}
+
+/// A systemd unit, by name (`chrony`, `nginx.service`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Service {
+    name: String,
+}
+
+impl Service {
+    pub fn new(name: impl Into<String>) -> Self {
+        Service { name: name.into() }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Runs `systemctl <verb> <unit>`, treating a non-zero exit as `false`.
+    fn check(&self, ctx: &ExecutionContext, verb: &str) -> ShiftResult<bool> {
+        match Cmd::new("systemctl")
+            .args([verb, "--quiet", &self.name])
+            .output(ctx)
+        {
+            Ok(_) => Ok(true),
+            Err(ShiftError::Command { .. }) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn is_active(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        self.check(ctx, "is-active")
+    }
+
+    pub fn is_enabled(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        self.check(ctx, "is-enabled")
+    }
+
+    fn systemctl(&self, ctx: &ExecutionContext, args: &[&str]) -> ShiftResult<()> {
+        Cmd::new("systemctl")
+            .args(args.iter().copied())
+            .arg(&self.name)
+            .output(ctx)
+            .map(drop)
+    }
+
+    /// Enables the unit at boot and starts it now.
+    pub fn enable_now(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        self.systemctl(ctx, &["enable", "--now"])
+    }
+
+    /// Stops the unit and disables it at boot.
+    pub fn disable_now(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        self.systemctl(ctx, &["disable", "--now"])
+    }
+
+    pub fn restart(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        self.systemctl(ctx, &["restart"])
+    }
+
+    /// Asks the unit to reload its config without a restart.
+    pub fn reload(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        self.systemctl(ctx, &["reload"])
+    }
+
+    /// Reloads the unit if it is running; a stopped unit reads its config
+    /// when it starts.
+    pub fn reload_if_active(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        if self.is_active(ctx)? {
+            self.reload(ctx)?;
+        }
+        Ok(())
+    }
+
+    /// Restarts the unit if it is running, so it picks up new config.
+    pub fn restart_if_active(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        if self.is_active(ctx)? {
+            self.restart(ctx)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+use crate::artifacts::Artifact;
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::ShiftResult;
+use crate::inputs::{ExternalInput, InputKind};
+use crate::metadata::ShiftMetadata;
+use crate::resource::{Claim, Resource};
+use crate::validate::ValidationContext;
+
+/// What an [`apply`](Shift::apply) did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShiftOutcome {
+    /// The shift changed the machine.
+    Changed,
+    /// Everything was already in place, so nothing was done.
+    Unchanged,
+}
+
+impl ShiftOutcome {
+    /// [`Changed`](ShiftOutcome::Changed) if `changed`, else
+    /// [`Unchanged`](ShiftOutcome::Unchanged).
+    pub fn changed_if(changed: bool) -> Self {
+        if changed {
+            ShiftOutcome::Changed
+        } else {
+            ShiftOutcome::Unchanged
+        }
+    }
+
+    pub fn is_changed(self) -> bool {
+        self == ShiftOutcome::Changed
+    }
+}
+
+/// A single, reversible change to the machine.
+///
+/// Shifts are expected to be idempotent: `apply` on an already applied shift
+/// should leave the system unchanged, and `is_applied` lets the plan skip
+/// work that is already done. Relative paths are resolved against the
+/// context's root.
+pub trait Shift: Send + Sync {
+    /// What kind of shift this is, what it touches and what it depends on.
+    fn metadata(&self) -> ShiftMetadata;
+
+    /// What the shift touches; see [`crate::resource`]. Defaults to a
+    /// shared claim on each of the metadata's targets.
+    fn resources(&self) -> Vec<Claim> {
+        self.metadata()
+            .targets
+            .into_iter()
+            .map(|path| Claim::shared(Resource::Path(path)))
+            .collect()
+    }
+
+    /// Checks, without changing anything, that `apply` can succeed: inputs
+    /// are well formed and the files, programs and credentials it needs
+    /// exist or will be created by earlier shifts.
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        let _ = ctx;
+        Ok(())
+    }
+
+    /// Makes the change, saying whether there was anything to do.
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome>;
+
+    /// Undoes a previous `apply`.
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()>;
+
+    /// Reports whether the change is already in place.
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool>;
+
+    /// What `apply` downloads, for `skies fetch` to cache ahead of an
+    /// offline run; see [`crate::artifacts`].
+    fn artifacts(&self, ctx: &ExecutionContext) -> ShiftResult<Vec<Artifact>> {
+        let _ = ctx;
+        Ok(Vec::new())
+    }
+
+    /// What the shift needs to be allowed to do; see [`crate::capability`].
+    /// Writing outside the plan root is worked out by the plan and need
+    /// not be declared.
+    fn capabilities(&self) -> Vec<Capability> {
+        Vec::new()
+    }
+
+    /// What the shift fetches from outside the machine, and whether the
+    /// plan pins it; see [`crate::inputs`]. Defaults to its artifacts, or
+    /// for a shift that uses the network without any, one input naming
+    /// the shift.
+    fn inputs(&self, ctx: &ExecutionContext) -> ShiftResult<Vec<ExternalInput>> {
+        let mut inputs: Vec<ExternalInput> = self
+            .artifacts(ctx)?
+            .iter()
+            .flat_map(|artifact| ExternalInput::from_artifact(ctx, artifact))
+            .collect();
+        if inputs.is_empty() && self.capabilities().contains(&Capability::Network) {
+            inputs.push(ExternalInput::new(
+                InputKind::Network,
+                self.metadata().summary,
+            ));
+        }
+        Ok(inputs)
+    }
+}
+
+impl<S: Shift + ?Sized> Shift for Box<S> {
+    fn metadata(&self) -> ShiftMetadata {
+        (**self).metadata()
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        (**self).resources()
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        (**self).validate(ctx)
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        (**self).apply(ctx)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        (**self).revert(ctx)
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        (**self).is_applied(ctx)
+    }
+
+    fn artifacts(&self, ctx: &ExecutionContext) -> ShiftResult<Vec<Artifact>> {
+        (**self).artifacts(ctx)
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        (**self).capabilities()
+    }
+
+    fn inputs(&self, ctx: &ExecutionContext) -> ShiftResult<Vec<ExternalInput>> {
+        (**self).inputs(ctx)
+    }
+}
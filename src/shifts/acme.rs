@@ -0,0 +1,370 @@
+use std::path::PathBuf;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::metadata::ShiftMetadata;
+use crate::resource::{Claim, Resource};
+use crate::shift::{Shift, ShiftOutcome};
+use crate::shifts::tls_cert;
+use crate::shifts::Cmd;
+use crate::validate::ValidationContext;
+
+/// How an [`AcmeCert`] proves control of its domains.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AcmeChallenge {
+    /// Serves a token over plain HTTP on port 80.
+    #[default]
+    Http01,
+    /// Publishes a token in a DNS TXT record, through a certbot DNS plugin.
+    Dns01,
+}
+
+/// Obtains a certificate for `domains` from Let's Encrypt, or another ACME
+/// CA, with `certbot`.
+///
+/// With the `http01` challenge, the default, certbot answers on port 80
+/// itself unless `webroot` names the directory a running web server
+/// serves the domain from. With `dns01`, `dns_plugin` names the certbot
+/// plugin for the DNS provider (`cloudflare`, `route53`, ...) and
+/// `dns_credentials` its credentials file, if it takes one. `staging`
+/// uses the CA's staging environment, for trying a plan out without
+/// hitting rate limits; `server` points certbot at another ACME directory.
+///
+/// Applied while the certificate names exactly `domains` and does not
+/// expire within `renew_days`; otherwise apply renews it. The certificate,
+/// key and chain paths are recorded as the outputs `cert`, `key` and
+/// `chain` for later shifts, such as a `web_vhost`. Revert deletes the
+/// certificate from certbot if this shift obtained it first; it is not
+/// revoked.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AcmeCert {
+    domains: Vec<String>,
+    #[serde(default)]
+    email: Option<String>,
+    #[serde(default)]
+    challenge: AcmeChallenge,
+    #[serde(default)]
+    webroot: Option<PathBuf>,
+    #[serde(default)]
+    dns_plugin: Option<String>,
+    #[serde(default)]
+    dns_credentials: Option<PathBuf>,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    staging: bool,
+    #[serde(default)]
+    server: Option<String>,
+    #[serde(default = "default_renew_days")]
+    renew_days: u32,
+    #[serde(default = "default_config_dir")]
+    config_dir: PathBuf,
+}
+
+fn default_renew_days() -> u32 {
+    30
+}
+
+fn default_config_dir() -> PathBuf {
+    PathBuf::from("/etc/letsencrypt")
+}
+
+impl AcmeCert {
+    pub fn new(domains: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        AcmeCert {
+            domains: domains.into_iter().map(Into::into).collect(),
+            email: None,
+            challenge: AcmeChallenge::default(),
+            webroot: None,
+            dns_plugin: None,
+            dns_credentials: None,
+            name: None,
+            staging: false,
+            server: None,
+            renew_days: default_renew_days(),
+            config_dir: default_config_dir(),
+        }
+    }
+
+    /// The account's contact address, for expiry notices.
+    pub fn email(mut self, email: impl Into<String>) -> Self {
+        self.email = Some(email.into());
+        self
+    }
+
+    /// Answers the HTTP challenge by writing into `webroot`.
+    pub fn webroot(mut self, webroot: impl Into<PathBuf>) -> Self {
+        self.webroot = Some(webroot.into());
+        self
+    }
+
+    /// Uses the DNS challenge through the certbot plugin `plugin`.
+    pub fn dns(mut self, plugin: impl Into<String>, credentials: Option<PathBuf>) -> Self {
+        self.challenge = AcmeChallenge::Dns01;
+        self.dns_plugin = Some(plugin.into());
+        self.dns_credentials = credentials;
+        self
+    }
+
+    /// certbot's name for the certificate, by default the first domain.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn staging(mut self, staging: bool) -> Self {
+        self.staging = staging;
+        self
+    }
+
+    /// Uses the ACME directory at `url` instead of Let's Encrypt.
+    pub fn server(mut self, url: impl Into<String>) -> Self {
+        self.server = Some(url.into());
+        self
+    }
+
+    /// Renews when the certificate expires within this many days.
+    pub fn renew_days(mut self, days: u32) -> Self {
+        self.renew_days = days;
+        self
+    }
+
+    pub fn config_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config_dir = dir.into();
+        self
+    }
+
+    fn cert_name(&self) -> &str {
+        self.name
+            .as_deref()
+            .or(self.domains.first().map(String::as_str))
+            .unwrap_or_default()
+    }
+
+    fn live_dir(&self) -> PathBuf {
+        self.config_dir.join("live").join(self.cert_name())
+    }
+
+    fn certbot(&self, ctx: &ExecutionContext, verb: &str) -> ShiftResult<Cmd> {
+        Ok(Cmd::new("certbot")
+            .arg(verb)
+            .args(["--non-interactive", "--config-dir"])
+            .arg(ctx.resolve(&self.config_dir)?.display().to_string())
+            .args(["--cert-name", self.cert_name()]))
+    }
+
+    fn obtain(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let mut cmd = self
+            .certbot(ctx, "certonly")?
+            .args(["--agree-tos", "--force-renewal"]);
+        cmd = match &self.email {
+            Some(email) => cmd.args(["--email", email]),
+            None => cmd.arg("--register-unsafely-without-email"),
+        };
+        if self.staging {
+            cmd = cmd.arg("--test-cert");
+        }
+        if let Some(server) = &self.server {
+            cmd = cmd.args(["--server", server]);
+        }
+        cmd = match (self.challenge, &self.webroot, &self.dns_plugin) {
+            (AcmeChallenge::Http01, Some(webroot), _) => cmd
+                .args(["--webroot", "--webroot-path"])
+                .arg(ctx.resolve(webroot)?.display().to_string()),
+            (AcmeChallenge::Http01, None, _) => cmd.arg("--standalone"),
+            (AcmeChallenge::Dns01, _, Some(plugin)) => {
+                let cmd = cmd.arg(format!("--dns-{plugin}"));
+                match &self.dns_credentials {
+                    Some(credentials) => cmd
+                        .arg(format!("--dns-{plugin}-credentials"))
+                        .arg(ctx.resolve(credentials)?.display().to_string()),
+                    None => cmd,
+                }
+            }
+            (AcmeChallenge::Dns01, _, None) => {
+                return Err(ShiftError::Custom(
+                    "the dns01 challenge needs a `dns_plugin`".into(),
+                ))
+            }
+        };
+        for domain in &self.domains {
+            cmd = cmd.args(["-d", domain]);
+        }
+        cmd.output(ctx)
+            .map_err(|err| {
+                let hint = match (self.challenge, &self.webroot) {
+                    (AcmeChallenge::Http01, None) => {
+                        "the domains must resolve to this machine and port 80 must be free and reachable"
+                    }
+                    (AcmeChallenge::Http01, Some(_)) => {
+                        "the domains must resolve to this machine and the web server must serve `webroot` at /.well-known/acme-challenge/"
+                    }
+                    (AcmeChallenge::Dns01, _) => {
+                        "check the DNS plugin is installed and its credentials can edit the zone"
+                    }
+                };
+                err.context(format!("obtaining a certificate for {}", self.domains.join(", ")))
+                    .hint(hint)
+            })
+            .map(drop)
+    }
+
+    fn record_outputs(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let live = ctx.resolve(&self.live_dir())?;
+        ctx.output("cert", live.join("fullchain.pem"));
+        ctx.output("key", live.join("privkey.pem"));
+        ctx.output("chain", live.join("chain.pem"));
+        Ok(())
+    }
+}
+
+impl Shift for AcmeCert {
+    fn metadata(&self) -> ShiftMetadata {
+        let live = self.live_dir();
+        let challenge = match self.challenge {
+            AcmeChallenge::Http01 => "http-01",
+            AcmeChallenge::Dns01 => "dns-01",
+        };
+        let mut meta = ShiftMetadata::new(
+            "acme_cert",
+            format!(
+                "obtain a certificate for {} with {challenge}",
+                self.domains.join(", ")
+            ),
+        )
+        .target(live.join("fullchain.pem"))
+        .target(live.join("privkey.pem"))
+        .input("domains", &self.domains)
+        .input("challenge", challenge)
+        .input("renew_days", self.renew_days);
+        if self.staging {
+            meta = meta.input("staging", true);
+        }
+        meta
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        let mut claims = vec![
+            Claim::exclusive(Resource::path(self.live_dir())),
+            Claim::exclusive(Resource::lock("certbot")),
+        ];
+        if self.challenge == AcmeChallenge::Http01 && self.webroot.is_none() {
+            claims.push(Claim::exclusive(Resource::Port(80)));
+        }
+        claims
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        if self.domains.is_empty() {
+            return Err(ShiftError::Custom("no domains given".into()));
+        }
+        if let Some(bad) = self.domains.iter().find(|domain| {
+            let body = domain.strip_prefix("*.").unwrap_or(domain);
+            body.is_empty()
+                || !body
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.'))
+        }) {
+            return Err(ShiftError::Custom(format!("`{bad}` is not a domain name")));
+        }
+        match self.challenge {
+            AcmeChallenge::Http01 => {
+                if self.domains.iter().any(|domain| domain.starts_with("*.")) {
+                    return Err(ShiftError::Custom(
+                        "wildcard certificates need the dns01 challenge".into(),
+                    ));
+                }
+                if self.dns_plugin.is_some() || self.dns_credentials.is_some() {
+                    return Err(ShiftError::Custom(
+                        "`dns_plugin` and `dns_credentials` need `challenge = \"dns01\"`".into(),
+                    ));
+                }
+                if let Some(webroot) = &self.webroot {
+                    ctx.exec().resolve(webroot)?;
+                    if !ctx.will_exist(webroot) {
+                        return Err(ShiftError::Custom(format!(
+                            "{} does not exist and no earlier shift creates it",
+                            webroot.display()
+                        )));
+                    }
+                }
+            }
+            AcmeChallenge::Dns01 => {
+                let Some(plugin) = &self.dns_plugin else {
+                    return Err(ShiftError::Custom(
+                        "the dns01 challenge needs a `dns_plugin`".into(),
+                    ));
+                };
+                if !plugin
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-')
+                {
+                    return Err(ShiftError::Custom(format!(
+                        "`{plugin}` is not a certbot DNS plugin name"
+                    )));
+                }
+                if self.webroot.is_some() {
+                    return Err(ShiftError::Custom(
+                        "`webroot` only applies to the http01 challenge".into(),
+                    ));
+                }
+                if let Some(credentials) = &self.dns_credentials {
+                    ctx.exec().resolve(credentials)?;
+                    if !ctx.will_exist(credentials) {
+                        return Err(ShiftError::Custom(format!(
+                            "{} does not exist and no earlier shift creates it",
+                            credentials.display()
+                        )));
+                    }
+                }
+            }
+        }
+        ctx.require_program("certbot")?;
+        ctx.require_program("openssl")?;
+        ctx.exec().resolve(&self.config_dir)?;
+        Ok(())
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network, Capability::Sudo]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        if self.is_applied(ctx)? {
+            self.record_outputs(ctx)?;
+            return Ok(ShiftOutcome::Unchanged);
+        }
+        let existed = ctx.resolve(&self.live_dir())?.exists();
+        self.obtain(ctx)?;
+        if !existed && ctx.get_state("created").is_none() {
+            ctx.set_state("created", json!(true));
+        }
+        self.record_outputs(ctx)?;
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        if ctx.get_state("created") == Some(json!(true)) && ctx.resolve(&self.live_dir())?.exists()
+        {
+            self.certbot(ctx, "delete")?.output(ctx)?;
+        }
+        ctx.clear_state("created");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        let live = ctx.resolve(&self.live_dir())?;
+        let cert = live.join("fullchain.pem");
+        if !cert.exists() || !live.join("privkey.pem").exists() {
+            return Ok(false);
+        }
+        tls_cert::is_current(ctx, &cert, &self.domains, self.renew_days)
+    }
+}
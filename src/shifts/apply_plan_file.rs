@@ -0,0 +1,167 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::artifacts::Artifact;
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::inputs::ExternalInput;
+use crate::metadata::ShiftMetadata;
+use crate::plan::ShiftPlan;
+use crate::plan_file;
+use crate::report::{PlanEvent, Reporter};
+use crate::shift::{Shift, ShiftOutcome};
+use crate::validate::ValidationContext;
+
+/// Applies another plan file, such as the `skies.toml` inside a repository
+/// an earlier shift cloned.
+///
+/// The plan runs rooted at its own directory with only the variables in
+/// `vars` set (its `[vars]` fill in the rest), and is loaded when the
+/// shift runs, so the file may come from an earlier shift. Its shifts are
+/// logged indented under this one. Plans that apply each other in a cycle
+/// are an error. Applied once every shift in the plan is; revert reverts
+/// the plan.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ApplyPlanFile {
+    path: PathBuf,
+    #[serde(default)]
+    vars: BTreeMap<String, String>,
+}
+
+impl ApplyPlanFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        ApplyPlanFile {
+            path: path.into(),
+            vars: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the nested plan's variable `name`.
+    pub fn var(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.vars.insert(name.into(), value.into());
+        self
+    }
+
+    /// The nested plan and the context it runs in.
+    fn load(&self, ctx: &ExecutionContext) -> ShiftResult<(ShiftPlan, ExecutionContext)> {
+        let path = ctx.resolve(&self.path)?;
+        let mut nested = ctx
+            .clone()
+            .with_root(path.parent().unwrap_or(Path::new("/")));
+        nested.clear_vars();
+        for (name, value) in &self.vars {
+            nested.set_var(name, value);
+        }
+        let plan = plan_file::load(&path, &mut nested)?;
+        Ok((plan, nested))
+    }
+}
+
+/// Logs a nested plan's progress under the shift running it.
+struct Nested<'a> {
+    ctx: &'a ExecutionContext,
+    changed: bool,
+}
+
+impl Reporter for Nested<'_> {
+    fn report(&mut self, event: &PlanEvent<'_>) {
+        self.changed |= matches!(event, PlanEvent::Applied(_));
+        if let PlanEvent::Started(_) = event {
+            return;
+        }
+        let summary = event.entry().shift.metadata().summary;
+        let line = match event.error() {
+            Some(err) => format!("  {} {summary}: {err}", event.name()),
+            None => format!("  {} {summary}", event.name()),
+        };
+        self.ctx.info(&line);
+    }
+}
+
+impl Shift for ApplyPlanFile {
+    fn metadata(&self) -> ShiftMetadata {
+        let mut meta = ShiftMetadata::new(
+            "apply_plan_file",
+            format!("apply the plan in {}", self.path.display()),
+        )
+        .input("path", &self.path);
+        for (name, value) in &self.vars {
+            meta = meta.input(format!("vars.{name}"), value);
+        }
+        meta
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        ctx.exec().resolve(&self.path)?;
+        // A directory an earlier shift produces, such as a clone, may hold
+        // the file; what it will contain cannot be known yet.
+        let produced = self.path.ancestors().skip(1).any(|dir| {
+            !dir.as_os_str().is_empty()
+                && !ctx.exec().join_root(dir).exists()
+                && ctx.will_exist(dir)
+        });
+        if !produced && !ctx.will_exist(&self.path) {
+            return Err(ShiftError::Custom(format!(
+                "plan file {} does not exist and no earlier shift creates it",
+                self.path.display()
+            )));
+        }
+        Ok(())
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::RunsArbitraryCode]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let (plan, nested) = self.load(ctx)?;
+        let mut reporter = Nested {
+            ctx,
+            changed: false,
+        };
+        plan.apply_with(&nested, &mut reporter)?;
+        Ok(ShiftOutcome::changed_if(reporter.changed))
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let (plan, nested) = self.load(ctx)?;
+        plan.revert_with(
+            &nested,
+            &mut Nested {
+                ctx,
+                changed: false,
+            },
+        )
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        if !ctx.resolve(&self.path)?.exists() {
+            return Ok(false);
+        }
+        let (plan, nested) = self.load(ctx)?;
+        plan.is_applied(&nested)
+    }
+
+    /// The nested plan's artifacts, if the file is there already.
+    fn artifacts(&self, ctx: &ExecutionContext) -> ShiftResult<Vec<Artifact>> {
+        if !ctx.resolve(&self.path)?.exists() {
+            return Ok(Vec::new());
+        }
+        let (plan, nested) = self.load(ctx)?;
+        plan.artifacts(&nested)
+    }
+
+    /// The nested plan's inputs, if the file is there already.
+    fn inputs(&self, ctx: &ExecutionContext) -> ShiftResult<Vec<ExternalInput>> {
+        if !ctx.resolve(&self.path)?.exists() {
+            return Ok(Vec::new());
+        }
+        let (plan, nested) = self.load(ctx)?;
+        plan.inputs(&nested)
+    }
+}
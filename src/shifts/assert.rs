@@ -0,0 +1,325 @@
+use std::fs;
+use std::net::{Ipv4Addr, SocketAddr, TcpStream};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::metadata::ShiftMetadata;
+use crate::resource::{Claim, Resource};
+use crate::shift::{Shift, ShiftOutcome};
+use crate::shifts::Cmd;
+use crate::validate::ValidationContext;
+
+/// Files listing TCP sockets, IPv4 and IPv6.
+const PROC_NET_TCP: [&str; 2] = ["/proc/net/tcp", "/proc/net/tcp6"];
+
+/// State of a listening socket in `/proc/net/tcp`.
+const TCP_LISTEN: &str = "0A";
+
+/// Checks the machine without changing it, failing the plan with
+/// [`ShiftError::AssertionFailed`] when the check does not hold.
+///
+/// Placed after the shifts that do the real work, asserts let a plan verify
+/// its own result. One never counts as applied, so every apply checks it
+/// again; revert leaves it alone. In plan files, give exactly one of:
+///
+/// - `file` and `matches`: the file's contents match the regex;
+/// - `command` and optionally `output`: the command succeeds and prints
+///   `output` (compared without surrounding whitespace);
+/// - `port`: something listens on the TCP port;
+/// - `env`: the environment variable is set and not empty.
+#[derive(Deserialize, JsonSchema)]
+#[serde(try_from = "RawAssert")]
+#[schemars(with = "RawAssert")]
+pub struct Assert {
+    check: Check,
+}
+
+enum Check {
+    FileMatches {
+        path: PathBuf,
+        pattern: String,
+    },
+    CommandOutput {
+        argv: Vec<String>,
+        output: Option<String>,
+    },
+    PortListening(u16),
+    EnvSet(String),
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct RawAssert {
+    #[serde(default)]
+    file: Option<PathBuf>,
+    #[serde(default)]
+    matches: Option<String>,
+    #[serde(default)]
+    command: Option<Vec<String>>,
+    #[serde(default)]
+    output: Option<String>,
+    #[serde(default)]
+    port: Option<u16>,
+    #[serde(default)]
+    env: Option<String>,
+}
+
+impl TryFrom<RawAssert> for Assert {
+    type Error = String;
+
+    fn try_from(raw: RawAssert) -> Result<Self, String> {
+        let check = match raw {
+            RawAssert {
+                file: Some(path),
+                matches: Some(pattern),
+                command: None,
+                output: None,
+                port: None,
+                env: None,
+            } => Check::FileMatches { path, pattern },
+            RawAssert {
+                file: Some(_),
+                matches: None,
+                ..
+            } => return Err("`file` needs a `matches` regex".into()),
+            RawAssert {
+                file: None,
+                matches: None,
+                command: Some(argv),
+                output,
+                port: None,
+                env: None,
+            } => Check::CommandOutput { argv, output },
+            RawAssert {
+                file: None,
+                matches: None,
+                command: None,
+                output: None,
+                port: Some(port),
+                env: None,
+            } => Check::PortListening(port),
+            RawAssert {
+                file: None,
+                matches: None,
+                command: None,
+                output: None,
+                port: None,
+                env: Some(name),
+            } => Check::EnvSet(name),
+            _ => {
+                return Err(
+                    "give exactly one of `file` (with `matches`), `command`, `port` or `env`"
+                        .into(),
+                )
+            }
+        };
+        Ok(Assert { check })
+    }
+}
+
+impl Assert {
+    /// The file at `path` has contents matching the regex `pattern`.
+    pub fn file_matches(path: impl Into<PathBuf>, pattern: impl Into<String>) -> Self {
+        Assert {
+            check: Check::FileMatches {
+                path: path.into(),
+                pattern: pattern.into(),
+            },
+        }
+    }
+
+    /// The command `argv` succeeds.
+    pub fn command_succeeds<I, A>(argv: I) -> Self
+    where
+        I: IntoIterator<Item = A>,
+        A: Into<String>,
+    {
+        Assert {
+            check: Check::CommandOutput {
+                argv: argv.into_iter().map(Into::into).collect(),
+                output: None,
+            },
+        }
+    }
+
+    /// Expect the command to print `output`, ignoring surrounding
+    /// whitespace. Only for [`Assert::command_succeeds`].
+    pub fn output(mut self, expected: impl Into<String>) -> Self {
+        if let Check::CommandOutput { output, .. } = &mut self.check {
+            *output = Some(expected.into());
+        }
+        self
+    }
+
+    /// Something listens on TCP `port`.
+    pub fn port_listening(port: u16) -> Self {
+        Assert {
+            check: Check::PortListening(port),
+        }
+    }
+
+    /// The environment variable `name` is set and not empty.
+    pub fn env_set(name: impl Into<String>) -> Self {
+        Assert {
+            check: Check::EnvSet(name.into()),
+        }
+    }
+
+    fn regex(pattern: &str) -> ShiftResult<Regex> {
+        Regex::new(pattern)
+            .map_err(|err| ShiftError::Custom(format!("invalid regex `{pattern}`: {err}")))
+    }
+
+    /// Why the check fails, or `None` if it holds.
+    fn failure(&self, ctx: &ExecutionContext) -> ShiftResult<Option<String>> {
+        Ok(match &self.check {
+            Check::FileMatches { path, pattern } => {
+                let resolved = ctx.resolve(path)?;
+                let fs = ctx.fs();
+                if !fs.exists(&resolved) {
+                    Some(format!("{} does not exist", path.display()))
+                } else {
+                    let contents = fs.read(&resolved)?;
+                    let matched =
+                        Self::regex(pattern)?.is_match(&String::from_utf8_lossy(&contents));
+                    (!matched).then(|| format!("{} does not match `{pattern}`", path.display()))
+                }
+            }
+            Check::CommandOutput { argv, output } => {
+                let Some((program, args)) = argv.split_first() else {
+                    return Err(ShiftError::Custom("assert `command` is empty".into()));
+                };
+                match Cmd::new(program).args(args).output(ctx) {
+                    Err(err @ ShiftError::Command { .. }) => Some(err.to_string()),
+                    Err(err) => return Err(err),
+                    Ok(printed) => match output {
+                        Some(expected) if printed.trim() != expected.trim() => Some(format!(
+                            "`{}` printed `{}`, expected `{}`",
+                            argv.join(" "),
+                            printed.trim(),
+                            expected.trim()
+                        )),
+                        _ => None,
+                    },
+                }
+            }
+            Check::PortListening(port) => {
+                (!listening(*port)).then(|| format!("nothing is listening on port {port}"))
+            }
+            Check::EnvSet(name) => match std::env::var_os(name) {
+                Some(value) if !value.is_empty() => None,
+                _ => Some(format!("environment variable {name} is not set")),
+            },
+        })
+    }
+}
+
+/// Whether a TCP socket listens on `port`, from `/proc/net` where there
+/// is one and by connecting to localhost otherwise.
+fn listening(port: u16) -> bool {
+    let wanted = format!("{port:04X}");
+    let mut readable = false;
+    for table in PROC_NET_TCP {
+        let Ok(text) = fs::read_to_string(table) else {
+            continue;
+        };
+        readable = true;
+        let found = text.lines().skip(1).any(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            matches!(fields.as_slice(), [_, local, _, state, ..]
+                if *state == TCP_LISTEN && local.rsplit(':').next() == Some(wanted.as_str()))
+        });
+        if found {
+            return true;
+        }
+    }
+    !readable
+        && TcpStream::connect_timeout(
+            &SocketAddr::from((Ipv4Addr::LOCALHOST, port)),
+            Duration::from_secs(1),
+        )
+        .is_ok()
+}
+
+impl Shift for Assert {
+    fn metadata(&self) -> ShiftMetadata {
+        match &self.check {
+            Check::FileMatches { path, pattern } => ShiftMetadata::new(
+                "assert",
+                format!("assert {} matches `{pattern}`", path.display()),
+            )
+            .target(path)
+            .input("matches", pattern),
+            Check::CommandOutput { argv, output } => {
+                let command = argv.join(" ");
+                let meta = match output {
+                    Some(output) => ShiftMetadata::new(
+                        "assert",
+                        format!("assert `{command}` prints `{}`", output.trim()),
+                    )
+                    .input("output", output),
+                    None => ShiftMetadata::new("assert", format!("assert `{command}` succeeds")),
+                };
+                meta.input("command", argv)
+            }
+            Check::PortListening(port) => {
+                ShiftMetadata::new("assert", format!("assert port {port} is listening"))
+                    .input("port", port)
+            }
+            Check::EnvSet(name) => {
+                ShiftMetadata::new("assert", format!("assert {name} is set")).input("env", name)
+            }
+        }
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        match &self.check {
+            Check::FileMatches { path, .. } => vec![Claim::shared(Resource::path(path))],
+            Check::PortListening(port) => vec![Claim::shared(Resource::Port(*port))],
+            Check::CommandOutput { .. } | Check::EnvSet(_) => Vec::new(),
+        }
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        match &self.check {
+            Check::FileMatches { path, pattern } => {
+                ctx.exec().resolve(path)?;
+                Self::regex(pattern).map(drop)
+            }
+            Check::CommandOutput { argv, .. } => match argv.first() {
+                Some(program) => ctx.require_program(program),
+                None => Err(ShiftError::Custom("assert `command` is empty".into())),
+            },
+            Check::PortListening(_) | Check::EnvSet(_) => Ok(()),
+        }
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        match self.check {
+            Check::CommandOutput { .. } => vec![Capability::RunsArbitraryCode],
+            _ => Vec::new(),
+        }
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        match self.failure(ctx)? {
+            Some(why) => Err(ShiftError::AssertionFailed(why)),
+            None => Ok(ShiftOutcome::Unchanged),
+        }
+    }
+
+    fn revert(&self, _ctx: &ExecutionContext) -> ShiftResult<()> {
+        Ok(())
+    }
+
+    fn is_applied(&self, _ctx: &ExecutionContext) -> ShiftResult<bool> {
+        Ok(false)
+    }
+}
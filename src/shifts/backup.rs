@@ -0,0 +1,420 @@
+//! Scheduled backups with restic or borg.
+
+use std::path::{Path, PathBuf};
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::json;
+use serde_json::Value;
+
+use crate::artifacts::Artifact;
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::fs::read_text;
+use crate::metadata::ShiftMetadata;
+use crate::packages::PackageManager;
+use crate::permissions;
+use crate::resource::{Claim, Resource};
+use crate::shift::{Shift, ShiftOutcome};
+use crate::shifts::{Cmd, SystemdTimer};
+use crate::validate::{find_program, ValidationContext};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BackupTool {
+    #[default]
+    Restic,
+    Borg,
+}
+
+impl BackupTool {
+    pub fn program(self) -> &'static str {
+        match self {
+            BackupTool::Restic => "restic",
+            BackupTool::Borg => "borg",
+        }
+    }
+
+    fn package(self, manager: PackageManager) -> &'static str {
+        match (self, manager) {
+            (BackupTool::Restic, _) => "restic",
+            (BackupTool::Borg, PackageManager::Pacman) => "borg",
+            (BackupTool::Borg, _) => "borgbackup",
+        }
+    }
+
+    /// The variables the tool reads its repository and password from.
+    fn env_names(self) -> (&'static str, &'static str) {
+        match self {
+            BackupTool::Restic => ("RESTIC_REPOSITORY", "RESTIC_PASSWORD"),
+            BackupTool::Borg => ("BORG_REPO", "BORG_PASSPHRASE"),
+        }
+    }
+}
+
+/// Backs up `paths` to `repository` on a schedule with restic or borg.
+///
+/// Installs the tool if it is missing (unless `install` is off), creates
+/// the repository if it does not exist yet (borg with `repokey`
+/// encryption), writes the `exclude` patterns to
+/// `<config_dir>/<name>.exclude` and runs the backup on `schedule`,
+/// `daily` by default, with the [`SystemdTimer`] `backup-<name>`.
+/// The repository password comes from the environment variable
+/// `password_env` and reaches the timer through `<config_dir>/<name>.env`,
+/// readable only by root. `config_dir` is `/etc/skies/backup` by default.
+///
+/// Applied while the tool is installed, the repository opens with the
+/// password and the files and timer are current. Revert removes the timer
+/// and files and uninstalls the tool if this shift installed it; the
+/// repository and its snapshots are always kept.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct BackupJob {
+    name: String,
+    #[serde(default)]
+    tool: BackupTool,
+    repository: String,
+    paths: Vec<PathBuf>,
+    #[serde(default)]
+    exclude: Vec<String>,
+    password_env: String,
+    #[serde(default = "default_schedule")]
+    schedule: String,
+    #[serde(default = "default_install")]
+    install: bool,
+    #[serde(default = "default_config_dir")]
+    config_dir: PathBuf,
+}
+
+fn default_schedule() -> String {
+    "daily".to_string()
+}
+
+fn default_install() -> bool {
+    true
+}
+
+fn default_config_dir() -> PathBuf {
+    PathBuf::from("/etc/skies/backup")
+}
+
+impl BackupJob {
+    pub fn new<I, P>(
+        name: impl Into<String>,
+        repository: impl Into<String>,
+        paths: I,
+        password_env: impl Into<String>,
+    ) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        BackupJob {
+            name: name.into(),
+            tool: BackupTool::default(),
+            repository: repository.into(),
+            paths: paths.into_iter().map(Into::into).collect(),
+            exclude: Vec::new(),
+            password_env: password_env.into(),
+            schedule: default_schedule(),
+            install: default_install(),
+            config_dir: default_config_dir(),
+        }
+    }
+
+    pub fn tool(mut self, tool: BackupTool) -> Self {
+        self.tool = tool;
+        self
+    }
+
+    /// Skips paths matching `pattern`, in the tool's exclude syntax.
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude.push(pattern.into());
+        self
+    }
+
+    /// When to back up, as a systemd `OnCalendar` expression.
+    pub fn schedule(mut self, schedule: impl Into<String>) -> Self {
+        self.schedule = schedule.into();
+        self
+    }
+
+    pub fn install(mut self, on: bool) -> Self {
+        self.install = on;
+        self
+    }
+
+    pub fn config_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config_dir = dir.into();
+        self
+    }
+
+    fn exclude_path(&self) -> PathBuf {
+        self.config_dir.join(format!("{}.exclude", self.name))
+    }
+
+    fn env_path(&self) -> PathBuf {
+        self.config_dir.join(format!("{}.env", self.name))
+    }
+
+    /// The timer running the backup, reading its config from
+    /// `exclude_file` and `env_file`.
+    fn timer_with(&self, exclude_file: &Path, env_file: &Path) -> SystemdTimer {
+        let program = self.tool.program();
+        let exclude = exclude_file.display().to_string();
+        let mut command: Vec<String> = match self.tool {
+            BackupTool::Restic => vec![program.into(), "backup".into(), "--exclude-file".into()],
+            BackupTool::Borg => vec![program.into(), "create".into(), "--exclude-from".into()],
+        };
+        command.push(exclude);
+        if self.tool == BackupTool::Borg {
+            command.push("::{hostname}-{now}".into());
+        }
+        command.extend(self.paths.iter().map(|p| p.display().to_string()));
+        SystemdTimer::new(format!("backup-{}", self.name), &self.schedule, command)
+            .description(format!("Back up {} with {program}", self.name))
+            .environment_file(env_file)
+    }
+
+    fn timer(&self, ctx: &ExecutionContext) -> ShiftResult<SystemdTimer> {
+        Ok(self.timer_with(
+            &ctx.resolve(&self.exclude_path())?,
+            &ctx.resolve(&self.env_path())?,
+        ))
+    }
+
+    fn password(&self) -> ShiftResult<String> {
+        std::env::var(&self.password_env).map_err(|_| {
+            ShiftError::Custom(format!(
+                "environment variable `{}` is not set",
+                self.password_env
+            ))
+        })
+    }
+
+    fn render_exclude(&self) -> String {
+        let mut text = String::from("# Managed by skies.\n");
+        for pattern in &self.exclude {
+            text.push_str(pattern);
+            text.push('\n');
+        }
+        text
+    }
+
+    fn render_env(&self, password: &str) -> String {
+        let quote = |value: &str| value.replace('\\', "\\\\").replace('"', "\\\"");
+        let (repo_var, password_var) = self.tool.env_names();
+        format!(
+            "# Managed by skies.\n{repo_var}=\"{}\"\n{password_var}=\"{}\"\n",
+            quote(&self.repository),
+            quote(password)
+        )
+    }
+
+    /// The files this shift writes, with their contents and modes.
+    fn files(&self, ctx: &ExecutionContext) -> ShiftResult<[(PathBuf, String, u32); 2]> {
+        let password = self.password()?;
+        Ok([
+            (
+                ctx.resolve(&self.exclude_path())?,
+                self.render_exclude(),
+                0o644,
+            ),
+            (
+                ctx.resolve(&self.env_path())?,
+                self.render_env(&password),
+                0o600,
+            ),
+        ])
+    }
+
+    /// The tool, with the repository and password in its environment.
+    fn tool_cmd(&self) -> ShiftResult<Cmd> {
+        let (repo_var, password_var) = self.tool.env_names();
+        Ok(Cmd::new(self.tool.program())
+            .env(repo_var, &self.repository)
+            .env(password_var, self.password()?))
+    }
+
+    /// Whether the repository exists and opens with the password.
+    fn initialized(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        let check = match self.tool {
+            BackupTool::Restic => self.tool_cmd()?.args(["cat", "config"]),
+            BackupTool::Borg => self.tool_cmd()?.arg("info"),
+        };
+        match check.output(ctx) {
+            Ok(_) => Ok(true),
+            Err(ShiftError::Command { .. }) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn init(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let init = match self.tool {
+            BackupTool::Restic => self.tool_cmd()?.arg("init"),
+            BackupTool::Borg => self.tool_cmd()?.args(["init", "--encryption=repokey"]),
+        };
+        init.output(ctx).map(drop).map_err(|err| {
+            err.context(format!("initializing repository {}", self.repository))
+                .hint(format!(
+                    "if the repository already exists, check the password in `{}`",
+                    self.password_env
+                ))
+        })
+    }
+}
+
+impl Shift for BackupJob {
+    fn metadata(&self) -> ShiftMetadata {
+        ShiftMetadata::new(
+            "backup",
+            format!(
+                "back up {} paths to {} with {} ({})",
+                self.paths.len(),
+                self.repository,
+                self.tool.program(),
+                self.schedule
+            ),
+        )
+        .target(self.env_path())
+        .input("name", &self.name)
+        .input("repository", &self.repository)
+        .input("paths", &self.paths)
+        .input("exclude", &self.exclude)
+        .input("schedule", &self.schedule)
+        .input("password_env", &self.password_env)
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        let mut claims = vec![
+            Claim::exclusive(Resource::path(self.exclude_path())),
+            Claim::exclusive(Resource::path(self.env_path())),
+            Claim::exclusive(Resource::lock(format!("backup {}", self.repository))),
+        ];
+        claims.extend(
+            self.timer_with(&self.exclude_path(), &self.env_path())
+                .resources(),
+        );
+        claims
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        let valid_name = !self.name.is_empty()
+            && self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+        if !valid_name {
+            return Err(ShiftError::Custom(format!(
+                "`{}` is not a usable backup name",
+                self.name
+            )));
+        }
+        if self.repository.trim().is_empty() || self.repository.contains('\n') {
+            return Err(ShiftError::Custom(format!(
+                "`{}` is not a repository",
+                self.repository
+            )));
+        }
+        if self.paths.is_empty() {
+            return Err(ShiftError::Custom("no `paths` to back up".into()));
+        }
+        if let Some(bad) = self.paths.iter().find(|path| !path.is_absolute()) {
+            return Err(ShiftError::Custom(format!(
+                "`{}` is not an absolute path; the timer runs from /",
+                bad.display()
+            )));
+        }
+        if let Some(bad) = self.exclude.iter().find(|pattern| pattern.contains('\n')) {
+            return Err(ShiftError::Custom(format!(
+                "exclude pattern `{bad}` spans several lines"
+            )));
+        }
+        ctx.require_env(&self.password_env)?;
+        if self.install && find_program(self.tool.program()).is_none() {
+            PackageManager::require()?;
+        } else {
+            ctx.require_program(self.tool.program())?;
+        }
+        ctx.exec().resolve(&self.env_path())?;
+        self.timer(ctx.exec())?.validate(ctx)
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network, Capability::Sudo]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let mut changed = false;
+        let program = self.tool.program();
+        if self.install && find_program(program).is_none() {
+            let manager = PackageManager::require()?;
+            let package = self.tool.package(manager);
+            manager.install(ctx, &[package])?;
+            ctx.set_state("installed", json!(package));
+            changed = true;
+        }
+
+        for (path, rendered, mode) in self.files(ctx)? {
+            let current = read_text(ctx.fs(), &path)?;
+            if current.as_deref() != Some(rendered.as_str())
+                || !permissions::mode_matches(ctx.fs(), &path, Some(mode))?
+            {
+                if let Some(dir) = path.parent() {
+                    ctx.fs().create_dir_all(dir)?;
+                }
+                ctx.fs().write(&path, rendered.as_bytes(), Some(mode))?;
+                changed = true;
+            }
+        }
+
+        if !self.initialized(ctx)? {
+            self.init(ctx)?;
+            changed = true;
+        }
+
+        let timer = self.timer(ctx)?.apply(&ctx.for_shift("timer"))?;
+        Ok(ShiftOutcome::changed_if(
+            changed || timer == ShiftOutcome::Changed,
+        ))
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        self.timer(ctx)?.revert(&ctx.for_shift("timer"))?;
+        for path in [self.exclude_path(), self.env_path()] {
+            let path = ctx.resolve(&path)?;
+            if ctx.fs().exists(&path) {
+                ctx.fs().remove_file(&path)?;
+            }
+        }
+        if let Some(Value::String(package)) = ctx.get_state("installed") {
+            PackageManager::require()?.remove(ctx, &[&package])?;
+        }
+        ctx.clear_state("installed");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        if find_program(self.tool.program()).is_none() {
+            return Ok(false);
+        }
+        for (path, rendered, mode) in self.files(ctx)? {
+            if read_text(ctx.fs(), &path)?.as_deref() != Some(rendered.as_str())
+                || !permissions::mode_matches(ctx.fs(), &path, Some(mode))?
+            {
+                return Ok(false);
+            }
+        }
+        Ok(self.initialized(ctx)? && self.timer(ctx)?.is_applied(&ctx.for_shift("timer"))?)
+    }
+
+    fn artifacts(&self, _ctx: &ExecutionContext) -> ShiftResult<Vec<Artifact>> {
+        Ok(match (self.install, PackageManager::detect()) {
+            (true, Some(manager)) => {
+                vec![Artifact::Packages(vec![self.tool.package(manager).into()])]
+            }
+            _ => Vec::new(),
+        })
+    }
+}
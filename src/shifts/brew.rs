@@ -0,0 +1,167 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::metadata::ShiftMetadata;
+use crate::resource::{Claim, Resource};
+use crate::shift::{Shift, ShiftOutcome};
+use crate::shifts::Cmd;
+use crate::validate::ValidationContext;
+
+/// Installs everything in a `Brewfile` with `brew bundle`.
+///
+/// Applied when `brew bundle check` finds nothing missing. The formulae
+/// and casks that were not installed before are kept in the shift's state,
+/// and revert uninstalls only those. With `cleanup`, apply also removes
+/// whatever the Brewfile does not list; that cannot be reverted.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct BrewBundle {
+    brewfile: PathBuf,
+    #[serde(default)]
+    cleanup: bool,
+}
+
+impl BrewBundle {
+    pub fn new(brewfile: impl Into<PathBuf>) -> Self {
+        BrewBundle {
+            brewfile: brewfile.into(),
+            cleanup: false,
+        }
+    }
+
+    /// Uninstalls everything the Brewfile does not list.
+    pub fn cleanup(mut self, cleanup: bool) -> Self {
+        self.cleanup = cleanup;
+        self
+    }
+
+    fn file_arg(&self, ctx: &ExecutionContext) -> ShiftResult<String> {
+        Ok(format!("--file={}", ctx.resolve(&self.brewfile)?.display()))
+    }
+
+    /// Names the Brewfile lists of one `kind` (`formula` or `cask`).
+    fn listed(&self, ctx: &ExecutionContext, kind: &str) -> ShiftResult<BTreeSet<String>> {
+        let out = Cmd::new("brew")
+            .args(["bundle".to_string(), "list".into(), self.file_arg(ctx)?])
+            .arg(format!("--{kind}"))
+            .output(ctx)?;
+        Ok(out.lines().map(|line| line.trim().to_string()).collect())
+    }
+
+    /// Names installed of one `kind`.
+    fn installed(ctx: &ExecutionContext, kind: &str) -> ShiftResult<BTreeSet<String>> {
+        let out = Cmd::new("brew")
+            .args(["list", "-1", &format!("--{kind}")])
+            .output(ctx)?;
+        Ok(out.lines().map(|line| line.trim().to_string()).collect())
+    }
+}
+
+const KINDS: [&str; 2] = ["formula", "cask"];
+
+impl Shift for BrewBundle {
+    fn metadata(&self) -> ShiftMetadata {
+        ShiftMetadata::new(
+            "brew_bundle",
+            format!("install the bundle in {}", self.brewfile.display()),
+        )
+        .input("brewfile", &self.brewfile)
+        .input("cleanup", self.cleanup)
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![Claim::shared(Resource::lock("brew"))]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        ctx.require_program("brew")?;
+        ctx.exec().resolve(&self.brewfile)?;
+        if !ctx.will_exist(&self.brewfile) {
+            return Err(ShiftError::Custom(format!(
+                "{} does not exist and no earlier shift creates it",
+                self.brewfile.display()
+            )));
+        }
+        Ok(())
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        if self.is_applied(ctx)? && !self.cleanup {
+            return Ok(ShiftOutcome::Unchanged);
+        }
+        let mut missing = serde_json::Map::new();
+        for kind in KINDS {
+            let installed = Self::installed(ctx, kind)?;
+            let new: Vec<String> = self
+                .listed(ctx, kind)?
+                .into_iter()
+                .filter(|name| !installed.contains(name))
+                .collect();
+            missing.insert(kind.to_string(), json!(new));
+        }
+        let mut install = Cmd::new("brew").args(["bundle".to_string(), "install".into()]);
+        install = install.arg(self.file_arg(ctx)?);
+        if self.cleanup {
+            install = install.arg("--cleanup");
+        }
+        install.output(ctx)?;
+        // Keep the first record: a later apply must not forget what an
+        // earlier one installed.
+        if ctx.get_state("installed").is_none() {
+            ctx.set_state("installed", json!(missing));
+        }
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let recorded = ctx.get_state("installed").unwrap_or_default();
+        // Casks first: some depend on formulae.
+        for kind in KINDS.iter().rev() {
+            let names: Vec<&str> = recorded[kind]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|name| name.as_str())
+                .collect();
+            let installed = Self::installed(ctx, kind)?;
+            let names: Vec<&str> = names
+                .into_iter()
+                .filter(|name| installed.contains(*name))
+                .collect();
+            if !names.is_empty() {
+                Cmd::new("brew")
+                    .args(["uninstall", &format!("--{kind}")])
+                    .args(names)
+                    .output(ctx)?;
+            }
+        }
+        ctx.clear_state("installed");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        if crate::validate::find_program("brew").is_none() {
+            return Ok(false);
+        }
+        let check = Cmd::new("brew")
+            .args(["bundle".to_string(), "check".into(), "--no-upgrade".into()])
+            .arg(self.file_arg(ctx)?)
+            .output(ctx);
+        match check {
+            Ok(_) => Ok(true),
+            Err(ShiftError::Command { .. }) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+}
@@ -0,0 +1,598 @@
+//! Rust project scaffolding that edits `Cargo.toml` structurally.
+//!
+//! Manifests are edited with `toml_edit`, so comments, ordering and
+//! formatting the user wrote are kept and the resulting diffs stay small.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::json;
+use toml_edit::{Array, DocumentMut, InlineTable, Item, Table, Value};
+
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::metadata::ShiftMetadata;
+use crate::permissions;
+use crate::shift::{Shift, ShiftOutcome};
+use crate::validate::ValidationContext;
+
+fn default_manifest() -> PathBuf {
+    PathBuf::from("Cargo.toml")
+}
+
+/// `path`, or `path/Cargo.toml` if `path` does not name a manifest.
+fn manifest_path(path: &Path) -> PathBuf {
+    if path.file_name().is_some_and(|name| name == "Cargo.toml") {
+        path.to_path_buf()
+    } else {
+        path.join("Cargo.toml")
+    }
+}
+
+fn load(path: &Path) -> ShiftResult<DocumentMut> {
+    let text = fs::read_to_string(path)?;
+    text.parse()
+        .map_err(|err| ShiftError::Custom(format!("{}: {err}", path.display())))
+}
+
+fn save(path: &Path, doc: &DocumentMut) -> ShiftResult<()> {
+    fs::write(path, doc.to_string())?;
+    Ok(())
+}
+
+/// Whether two TOML items hold the same data, ignoring formatting.
+fn same(a: &Item, b: &Item) -> bool {
+    let parse = |item: &Item| {
+        let mut doc = DocumentMut::new();
+        doc["v"] = item.clone();
+        doc.to_string().parse::<toml::Table>().ok()
+    };
+    parse(a) == parse(b)
+}
+
+/// Creates a new Cargo package, like `cargo new`, without running cargo.
+///
+/// Applied while `path` holds a manifest for a package of that name.
+/// Existing source files are left alone; an existing manifest for a
+/// different package fails preflight. Revert removes only the files that
+/// still hold what this shift wrote.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CargoNew {
+    path: PathBuf,
+    /// Defaults to the directory name.
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    lib: bool,
+    #[serde(default)]
+    edition: Option<String>,
+}
+
+impl CargoNew {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        CargoNew {
+            path: path.into(),
+            name: None,
+            lib: false,
+            edition: None,
+        }
+    }
+
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Creates a library rather than a binary package.
+    pub fn lib(mut self) -> Self {
+        self.lib = true;
+        self
+    }
+
+    pub fn edition(mut self, edition: impl Into<String>) -> Self {
+        self.edition = Some(edition.into());
+        self
+    }
+
+    pub fn package_name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| {
+            self.path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        })
+    }
+
+    fn manifest(&self) -> String {
+        let mut doc = DocumentMut::new();
+        let mut package = Table::new();
+        package["name"] = toml_edit::value(self.package_name());
+        package["version"] = toml_edit::value("0.1.0");
+        package["edition"] = toml_edit::value(self.edition.as_deref().unwrap_or("2021"));
+        doc["package"] = Item::Table(package);
+        doc["dependencies"] = Item::Table(Table::new());
+        doc.to_string()
+    }
+
+    fn source(&self) -> (&'static str, &'static str) {
+        if self.lib {
+            (
+                "src/lib.rs",
+                "pub fn add(left: u64, right: u64) -> u64 {\n    left + right\n}\n",
+            )
+        } else {
+            (
+                "src/main.rs",
+                "fn main() {\n    println!(\"Hello, world!\");\n}\n",
+            )
+        }
+    }
+
+    /// The package name in an existing manifest at `path`, if any.
+    fn existing_name(path: &Path) -> ShiftResult<Option<String>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let doc = load(path)?;
+        Ok(doc
+            .get("package")
+            .and_then(|package| package.get("name"))
+            .and_then(Item::as_str)
+            .map(String::from))
+    }
+}
+
+impl Shift for CargoNew {
+    fn metadata(&self) -> ShiftMetadata {
+        let kind = if self.lib { "library" } else { "binary" };
+        ShiftMetadata::new(
+            "cargo_new",
+            format!(
+                "create Rust {kind} {} in {}",
+                self.package_name(),
+                self.path.display()
+            ),
+        )
+        .target(self.path.join("Cargo.toml"))
+        .target(self.path.join(self.source().0))
+        .input("name", self.package_name())
+        .input("lib", self.lib)
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        if self.package_name().is_empty() {
+            return Err(ShiftError::Custom(format!(
+                "cannot derive a package name from {}",
+                self.path.display()
+            )));
+        }
+        let manifest = ctx.exec().resolve(&self.path.join("Cargo.toml"))?;
+        match Self::existing_name(&manifest)? {
+            Some(name) if name != self.package_name() => Err(ShiftError::Conflict {
+                resource: manifest.display().to_string(),
+                reason: format!("already defines package `{name}`"),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let dir = ctx.resolve(&self.path)?;
+        let (source_path, source) = self.source();
+        let source_path = dir.join(source_path);
+        if let Some(parent) = source_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mode = ctx.permissions().file_mode(None);
+        let manifest = dir.join("Cargo.toml");
+        let mut changed = false;
+        if !manifest.exists() {
+            permissions::write_file(&manifest, self.manifest().as_bytes(), mode)?;
+            changed = true;
+        }
+        if !source_path.exists() {
+            permissions::write_file(&source_path, source.as_bytes(), mode)?;
+            changed = true;
+        }
+        Ok(ShiftOutcome::changed_if(changed))
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let dir = ctx.resolve(&self.path)?;
+        let (source_path, source) = self.source();
+        for (path, contents) in [
+            (dir.join("Cargo.toml"), self.manifest()),
+            (dir.join(source_path), source.to_string()),
+        ] {
+            if fs::read_to_string(&path).is_ok_and(|existing| existing == contents) {
+                fs::remove_file(&path)?;
+            }
+        }
+        // Only succeeds if nothing else lives there.
+        let _ = fs::remove_dir(dir.join("src"));
+        let _ = fs::remove_dir(&dir);
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        let manifest = ctx.resolve(&self.path.join("Cargo.toml"))?;
+        Ok(Self::existing_name(&manifest)?.is_some_and(|name| name == self.package_name()))
+    }
+}
+
+/// Which dependency table a [`CargoAddDependency`] edits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DependencyKind {
+    #[default]
+    Normal,
+    Dev,
+    Build,
+}
+
+impl DependencyKind {
+    fn table(self) -> &'static str {
+        match self {
+            DependencyKind::Normal => "dependencies",
+            DependencyKind::Dev => "dev-dependencies",
+            DependencyKind::Build => "build-dependencies",
+        }
+    }
+}
+
+/// Adds (or updates) a dependency in a `Cargo.toml`.
+///
+/// Applied when the dependency's entry matches exactly. Revert restores
+/// whatever entry was there before, or removes it, unless it has been
+/// changed since.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CargoAddDependency {
+    /// The manifest, or the directory holding it.
+    #[serde(default = "default_manifest")]
+    manifest: PathBuf,
+    name: String,
+    #[serde(default)]
+    version: Option<String>,
+    #[serde(default)]
+    kind: DependencyKind,
+    #[serde(default)]
+    features: Vec<String>,
+    #[serde(default)]
+    default_features: Option<bool>,
+    #[serde(default)]
+    optional: bool,
+    #[serde(default)]
+    path: Option<PathBuf>,
+    #[serde(default)]
+    git: Option<String>,
+    /// Inherit the dependency from `[workspace.dependencies]`.
+    #[serde(default)]
+    workspace: bool,
+}
+
+impl CargoAddDependency {
+    pub fn new(manifest: impl Into<PathBuf>, name: impl Into<String>) -> Self {
+        CargoAddDependency {
+            manifest: manifest.into(),
+            name: name.into(),
+            version: None,
+            kind: DependencyKind::Normal,
+            features: Vec::new(),
+            default_features: None,
+            optional: false,
+            path: None,
+            git: None,
+            workspace: false,
+        }
+    }
+
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    pub fn kind(mut self, kind: DependencyKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn feature(mut self, feature: impl Into<String>) -> Self {
+        self.features.push(feature.into());
+        self
+    }
+
+    pub fn default_features(mut self, enabled: bool) -> Self {
+        self.default_features = Some(enabled);
+        self
+    }
+
+    pub fn optional(mut self) -> Self {
+        self.optional = true;
+        self
+    }
+
+    pub fn path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    pub fn git(mut self, url: impl Into<String>) -> Self {
+        self.git = Some(url.into());
+        self
+    }
+
+    pub fn workspace(mut self) -> Self {
+        self.workspace = true;
+        self
+    }
+
+    /// The entry as it should appear: a bare version string when that is
+    /// all there is, otherwise an inline table.
+    fn entry(&self) -> Item {
+        let simple = self.features.is_empty()
+            && self.default_features.is_none()
+            && !self.optional
+            && self.path.is_none()
+            && self.git.is_none()
+            && !self.workspace;
+        if let (true, Some(version)) = (simple, &self.version) {
+            return toml_edit::value(version.as_str());
+        }
+        let mut table = InlineTable::new();
+        if self.workspace {
+            table.insert("workspace", true.into());
+        }
+        if let Some(version) = &self.version {
+            table.insert("version", version.as_str().into());
+        }
+        if let Some(path) = &self.path {
+            table.insert("path", path.display().to_string().into());
+        }
+        if let Some(git) = &self.git {
+            table.insert("git", git.as_str().into());
+        }
+        if let Some(enabled) = self.default_features {
+            table.insert("default-features", enabled.into());
+        }
+        if !self.features.is_empty() {
+            let features: Array = self.features.iter().map(String::as_str).collect();
+            table.insert("features", Value::Array(features));
+        }
+        if self.optional {
+            table.insert("optional", true.into());
+        }
+        Item::Value(Value::InlineTable(table))
+    }
+
+    fn current(&self, doc: &DocumentMut) -> Option<Item> {
+        doc.get(self.kind.table())
+            .and_then(|deps| deps.get(&self.name))
+            .cloned()
+    }
+}
+
+impl Shift for CargoAddDependency {
+    fn metadata(&self) -> ShiftMetadata {
+        let manifest = manifest_path(&self.manifest);
+        let mut meta = ShiftMetadata::new(
+            "cargo_add_dependency",
+            format!(
+                "add {} to [{}] in {}",
+                self.name,
+                self.kind.table(),
+                manifest.display()
+            ),
+        )
+        .target(&manifest)
+        .input("name", &self.name);
+        if let Some(version) = &self.version {
+            meta = meta.input("version", version);
+        }
+        if !self.features.is_empty() {
+            meta = meta.input("features", &self.features);
+        }
+        meta
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        if self.version.is_none() && self.path.is_none() && self.git.is_none() && !self.workspace {
+            return Err(ShiftError::Custom(format!(
+                "dependency `{}` needs a version, path, git or workspace source",
+                self.name
+            )));
+        }
+        let manifest = manifest_path(&self.manifest);
+        if !ctx.will_exist(&manifest) {
+            return Err(ShiftError::Custom(format!(
+                "{} does not exist and no earlier shift creates it",
+                manifest.display()
+            )));
+        }
+        Ok(())
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let path = ctx.resolve(&manifest_path(&self.manifest))?;
+        let mut doc = load(&path)?;
+        let previous = self.current(&doc);
+        if ctx.get_state("previous").is_none() {
+            ctx.set_state(
+                "previous",
+                json!(previous.as_ref().map(|item| item.to_string())),
+            );
+        }
+        let deps = doc
+            .entry(self.kind.table())
+            .or_insert_with(|| Item::Table(Table::new()));
+        let Some(deps) = deps.as_table_like_mut() else {
+            return Err(ShiftError::Custom(format!(
+                "[{}] in {} is not a table",
+                self.kind.table(),
+                path.display()
+            )));
+        };
+        deps.insert(&self.name, self.entry());
+        save(&path, &doc)?;
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let path = ctx.resolve(&manifest_path(&self.manifest))?;
+        if !path.exists() {
+            return Ok(());
+        }
+        let mut doc = load(&path)?;
+        if !self
+            .current(&doc)
+            .is_some_and(|item| same(&item, &self.entry()))
+        {
+            return Ok(());
+        }
+        let previous = ctx
+            .get_state("previous")
+            .and_then(|v| v.as_str().map(String::from))
+            .and_then(|text| format!("v = {text}").parse::<DocumentMut>().ok())
+            .and_then(|prev| prev.get("v").cloned());
+        if let Some(deps) = doc
+            .get_mut(self.kind.table())
+            .and_then(Item::as_table_like_mut)
+        {
+            match previous {
+                Some(item) => {
+                    deps.insert(&self.name, item);
+                }
+                None => {
+                    deps.remove(&self.name);
+                }
+            }
+        }
+        ctx.clear_state("previous");
+        save(&path, &doc)
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        let path = ctx.resolve(&manifest_path(&self.manifest))?;
+        if !path.exists() {
+            return Ok(false);
+        }
+        Ok(self
+            .current(&load(&path)?)
+            .is_some_and(|item| same(&item, &self.entry())))
+    }
+}
+
+/// Adds a member to a workspace's `[workspace] members`, creating the
+/// `[workspace]` table if needed.
+///
+/// Applied while the member is listed. Revert takes it off the list and
+/// leaves the rest of the manifest alone.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CargoWorkspaceMember {
+    /// The workspace manifest, or the directory holding it.
+    #[serde(default = "default_manifest")]
+    manifest: PathBuf,
+    member: String,
+}
+
+impl CargoWorkspaceMember {
+    pub fn new(manifest: impl Into<PathBuf>, member: impl Into<String>) -> Self {
+        CargoWorkspaceMember {
+            manifest: manifest.into(),
+            member: member.into(),
+        }
+    }
+
+    fn has_member(&self, doc: &DocumentMut) -> bool {
+        doc.get("workspace")
+            .and_then(|ws| ws.get("members"))
+            .and_then(Item::as_array)
+            .is_some_and(|members| {
+                members
+                    .iter()
+                    .any(|m| m.as_str() == Some(self.member.as_str()))
+            })
+    }
+}
+
+impl Shift for CargoWorkspaceMember {
+    fn metadata(&self) -> ShiftMetadata {
+        let manifest = manifest_path(&self.manifest);
+        ShiftMetadata::new(
+            "cargo_workspace_member",
+            format!(
+                "add {} to the workspace in {}",
+                self.member,
+                manifest.display()
+            ),
+        )
+        .target(&manifest)
+        .input("member", &self.member)
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        let manifest = manifest_path(&self.manifest);
+        if !ctx.will_exist(&manifest) {
+            return Err(ShiftError::Custom(format!(
+                "{} does not exist and no earlier shift creates it",
+                manifest.display()
+            )));
+        }
+        Ok(())
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let path = ctx.resolve(&manifest_path(&self.manifest))?;
+        let mut doc = load(&path)?;
+        if self.has_member(&doc) {
+            return Ok(ShiftOutcome::Unchanged);
+        }
+        let workspace = doc
+            .entry("workspace")
+            .or_insert_with(|| Item::Table(Table::new()));
+        let members = workspace
+            .as_table_like_mut()
+            .ok_or_else(|| ShiftError::Custom("[workspace] is not a table".into()))?
+            .entry("members")
+            .or_insert(Item::Value(Value::Array(Array::new())));
+        let Some(members) = members.as_array_mut() else {
+            return Err(ShiftError::Custom(
+                "workspace.members is not an array".into(),
+            ));
+        };
+        members.push(self.member.as_str());
+        save(&path, &doc)?;
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let path = ctx.resolve(&manifest_path(&self.manifest))?;
+        if !path.exists() {
+            return Ok(());
+        }
+        let mut doc = load(&path)?;
+        let members = doc
+            .get_mut("workspace")
+            .and_then(|ws| ws.get_mut("members"))
+            .and_then(Item::as_array_mut);
+        if let Some(members) = members {
+            members.retain(|m| m.as_str() != Some(self.member.as_str()));
+            save(&path, &doc)?;
+        }
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        let path = ctx.resolve(&manifest_path(&self.manifest))?;
+        Ok(path.exists() && self.has_member(&load(&path)?))
+    }
+}
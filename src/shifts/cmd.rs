@@ -0,0 +1,338 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::exec::CommandSpec;
+use crate::metadata::{looks_secret, ShiftMetadata};
+use crate::plan_file::de;
+use crate::run_as;
+use crate::sandbox::Access;
+use crate::shift::{Shift, ShiftOutcome};
+use crate::transient;
+use crate::validate::ValidationContext;
+
+/// Set for commands in non-interactive runs unless the shift overrides them.
+const NON_INTERACTIVE_ENV: &[(&str, &str)] = &[
+    ("GIT_TERMINAL_PROMPT", "0"),
+    ("DEBIAN_FRONTEND", "noninteractive"),
+];
+
+/// Runs an external command.
+///
+/// Commands are opaque to skies, so by default a `Cmd` cannot tell whether
+/// it is applied ([`ShiftError::CheckUnsupported`]) and plans always run
+/// it. Set [`Cmd::creates`] to a path the command produces to make it
+/// idempotent, and [`Cmd::undo`] to give it a revert. With
+/// [`Cmd::run_as`], a plan run as root runs the command (and its undo) as
+/// another user.
+///
+/// In a context [with a sandbox](ExecutionContext::with_sandbox), the
+/// command can only write to the plan root, its `cwd` and its `creates`
+/// path, and has no network unless [`Cmd::network`] is set.
+///
+/// With [`Cmd::transient`] the command runs as a transient systemd unit
+/// instead of a child of skies; see [`crate::transient`]. Meant for long
+/// commands in remote applies, which then survive the SSH session
+/// dropping: applying again waits for the unit, or takes its result if it
+/// has finished, rather than running the command a second time.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Cmd {
+    program: String,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    cwd: Option<PathBuf>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    /// Seconds in plan files.
+    #[serde(default, deserialize_with = "de::opt_secs")]
+    #[schemars(with = "Option<f64>")]
+    timeout: Option<Duration>,
+    #[serde(default)]
+    creates: Option<PathBuf>,
+    #[serde(default)]
+    undo: Option<Vec<String>>,
+    #[serde(default)]
+    run_as: Option<String>,
+    #[serde(default)]
+    transient: bool,
+    #[serde(default)]
+    network: bool,
+}
+
+impl Cmd {
+    pub fn new(program: impl Into<String>) -> Self {
+        Cmd {
+            program: program.into(),
+            args: Vec::new(),
+            cwd: None,
+            env: BTreeMap::new(),
+            timeout: None,
+            creates: None,
+            undo: None,
+            run_as: None,
+            transient: false,
+            network: false,
+        }
+    }
+
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn args<I, A>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = A>,
+        A: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Working directory for the command.
+    pub fn cwd(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cwd = Some(dir.into());
+        self
+    }
+
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.insert(key.into(), value.into());
+        self
+    }
+
+    /// Kills the command if it runs longer than `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Treats the command as applied once `path` exists.
+    pub fn creates(mut self, path: impl Into<PathBuf>) -> Self {
+        self.creates = Some(path.into());
+        self
+    }
+
+    /// Command (program followed by arguments) that undoes this one.
+    pub fn undo<I, A>(mut self, argv: I) -> Self
+    where
+        I: IntoIterator<Item = A>,
+        A: Into<String>,
+    {
+        self.undo = Some(argv.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Runs the command as `user`, through `sudo` unless that is who the
+    /// plan already runs as.
+    pub fn run_as(mut self, user: impl Into<String>) -> Self {
+        self.run_as = Some(user.into());
+        self
+    }
+
+    /// Runs the command with `systemd-run`, as its own unit.
+    pub fn transient(mut self, on: bool) -> Self {
+        self.transient = on;
+        self
+    }
+
+    /// Lets the command reach the network from inside a sandbox.
+    pub fn network(mut self, on: bool) -> Self {
+        self.network = on;
+        self
+    }
+
+    /// What the command may reach in a sandbox.
+    fn access(&self, ctx: &ExecutionContext) -> ShiftResult<Access> {
+        let mut writable = vec![ctx.root().to_path_buf()];
+        if let Some(cwd) = &self.cwd {
+            writable.push(ctx.resolve(cwd)?);
+        }
+        if let Some(creates) = &self.creates {
+            writable.push(ctx.resolve(creates)?);
+        }
+        Ok(Access {
+            writable,
+            network: self.network,
+        })
+    }
+
+    pub fn program(&self) -> &str {
+        &self.program
+    }
+
+    pub fn working_dir(&self) -> Option<&Path> {
+        self.cwd.as_deref()
+    }
+
+    fn command_line(&self) -> String {
+        std::iter::once(self.program.as_str())
+            .chain(self.args.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Runs the command and returns what it printed to stdout. Meant for
+    /// read-only queries, e.g. from another shift's `is_applied`.
+    pub fn output(&self, ctx: &ExecutionContext) -> ShiftResult<String> {
+        self.run(ctx, &self.program, &self.args)
+    }
+
+    fn run(&self, ctx: &ExecutionContext, program: &str, args: &[String]) -> ShiftResult<String> {
+        let mut env = self.env.clone();
+        if !ctx.is_interactive() {
+            for (key, value) in NON_INTERACTIVE_ENV {
+                env.entry(key.to_string())
+                    .or_insert_with(|| value.to_string());
+            }
+        }
+        let cwd = match &self.cwd {
+            Some(cwd) => ctx.resolve(cwd)?,
+            None => ctx.root().to_path_buf(),
+        };
+        // Sandboxed inside sudo: bubblewrap stops sudo from switching users.
+        let (program, args) = match ctx.sandbox() {
+            Some(sandbox) => sandbox.wrap(ctx, program, args, &cwd, &self.access(ctx)?)?,
+            None => (program.to_string(), args.to_vec()),
+        };
+        let (program, args) = match &self.run_as {
+            Some(user) if !run_as::is_current(ctx, user)? => {
+                run_as::sudo(ctx, user, env.keys(), &program, &args)
+            }
+            _ => (program, args),
+        };
+        let spec = CommandSpec {
+            program,
+            args,
+            cwd,
+            env,
+            timeout: self.timeout,
+        };
+        ctx.debug(&format!(
+            "running `{}` in {}",
+            spec.command_line(),
+            spec.cwd.display()
+        ));
+
+        let output = if self.transient {
+            transient::run(ctx, &transient::unit_name(ctx, &spec), &spec)?
+        } else {
+            ctx.executor().run(ctx, &spec)?
+        };
+        for line in output.stdout.lines().chain(output.stderr.lines()) {
+            ctx.trace(&format!("  {line}"));
+        }
+        if output.succeeded() {
+            Ok(output.stdout)
+        } else {
+            Err(ShiftError::Command {
+                command: spec.command_line(),
+                code: output.code,
+                stderr: output.stderr,
+            })
+        }
+    }
+}
+
+impl Shift for Cmd {
+    fn metadata(&self) -> ShiftMetadata {
+        let mut meta = ShiftMetadata::new("cmd", format!("run `{}`", self.command_line()))
+            .input("program", &self.program)
+            .input("args", &self.args);
+        if let Some(cwd) = &self.cwd {
+            meta = meta.input("cwd", cwd);
+        }
+        for (key, value) in &self.env {
+            let name = format!("env.{key}");
+            meta = if looks_secret(key) {
+                meta.sensitive_input(name, value)
+            } else {
+                meta.input(name, value)
+            };
+        }
+        if let Some(creates) = &self.creates {
+            meta = meta.target(creates);
+        }
+        if let Some(undo) = &self.undo {
+            meta = meta.input("undo", undo);
+        }
+        if let Some(user) = &self.run_as {
+            meta = meta.input("run_as", user);
+        }
+        if self.transient {
+            meta = meta.input("transient", true);
+        }
+        if self.network {
+            meta = meta.input("network", true);
+        }
+        meta
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        if let Some(cwd) = &self.cwd {
+            ctx.require_dir(cwd)?;
+        }
+        if let Some(creates) = &self.creates {
+            ctx.exec().resolve(creates)?;
+        }
+        ctx.require_program(&self.program)?;
+        if let Some([program, ..]) = self.undo.as_deref() {
+            ctx.require_program(program)?;
+        }
+        if let Some(user) = &self.run_as {
+            run_as::check(ctx, user, true)?;
+        }
+        if let Some(sandbox) = ctx.exec().sandbox() {
+            sandbox.check()?;
+        }
+        if self.transient {
+            for program in ["systemd-run", "systemctl", "journalctl"] {
+                ctx.require_program(program)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        let mut capabilities = vec![Capability::RunsArbitraryCode];
+        if self.network {
+            capabilities.push(Capability::Network);
+        }
+        if self.run_as.is_some() {
+            capabilities.push(Capability::Sudo);
+        }
+        capabilities
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        self.run(ctx, &self.program, &self.args)?;
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        match self.undo.as_deref() {
+            Some([program, args @ ..]) => self.run(ctx, program, args).map(drop),
+            _ => Err(ShiftError::RevertUnsupported {
+                shift: format!("`{}`", self.command_line()),
+                reason: "it has no undo command".into(),
+            }),
+        }
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        match &self.creates {
+            Some(path) => Ok(ctx.resolve(path)?.exists()),
+            None => Err(ShiftError::CheckUnsupported {
+                shift: format!("`{}`", self.command_line()),
+                reason: "it has no `creates` path".into(),
+            }),
+        }
+    }
+}
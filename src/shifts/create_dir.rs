@@ -0,0 +1,181 @@
+use std::path::{Path, PathBuf};
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::metadata::ShiftMetadata;
+use crate::paths;
+use crate::permissions;
+use crate::run_as;
+use crate::shift::{Shift, ShiftOutcome};
+use crate::validate::ValidationContext;
+
+/// Ensures a directory (and its parents) exists.
+///
+/// Directories it creates get `mode`, or the plan's default directory mode.
+/// With `run_as`, the directory and any parents it creates are handed to
+/// that user and their login group. Applied while the directory exists
+/// with that mode and owner. Reverting removes the directory and
+/// everything inside it.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CreateDir {
+    path: PathBuf,
+    #[serde(default)]
+    mode: Option<u32>,
+    #[serde(default)]
+    run_as: Option<String>,
+}
+
+impl CreateDir {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        CreateDir {
+            path: path.into(),
+            mode: None,
+            run_as: None,
+        }
+    }
+
+    /// Unix permission bits, e.g. `0o755`. Ignored on other platforms.
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Makes `user` own the directory.
+    pub fn run_as(mut self, user: impl Into<String>) -> Self {
+        self.run_as = Some(user.into());
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Shift for CreateDir {
+    fn metadata(&self) -> ShiftMetadata {
+        let meta = ShiftMetadata::new(
+            "create_dir",
+            format!("create directory {}", self.path.display()),
+        )
+        .target(&self.path);
+        let meta = match self.mode {
+            Some(mode) => meta.input("mode", format!("{mode:o}")),
+            None => meta,
+        };
+        match &self.run_as {
+            Some(user) => meta.input("run_as", user),
+            None => meta,
+        }
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        let path = ctx.exec().resolve(&self.path)?;
+        let fs = ctx.exec().fs();
+        if fs.exists(&path) && !fs.is_dir(&path) {
+            return Err(ShiftError::Conflict {
+                resource: path.display().to_string(),
+                reason: "exists and is not a directory".into(),
+            });
+        }
+        if let Some(user) = &self.run_as {
+            run_as::check(ctx, user, false)?;
+        }
+        Ok(())
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        self.run_as.iter().map(|_| Capability::Sudo).collect()
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        if self.is_applied(ctx)? {
+            return Ok(ShiftOutcome::Unchanged);
+        }
+        let path = ctx.resolve(&self.path)?;
+        let fs = ctx.fs();
+        let missing: Vec<PathBuf> = path
+            .ancestors()
+            .take_while(|dir| !fs.exists(dir))
+            .map(Path::to_path_buf)
+            .collect();
+        fs.create_dir_all(&path)?;
+        if let Some(mode) = ctx.permissions().dir_mode(self.mode) {
+            // The leaf always gets the mode; parents only if we created them.
+            if !missing.contains(&path) {
+                fs.set_mode(&path, mode)?;
+            }
+            for dir in missing.iter().rev() {
+                fs.set_mode(dir, mode)?;
+            }
+        }
+        if let Some(user) = &self.run_as {
+            let ids = run_as::ids(ctx, user)?;
+            run_as::chown(&path, ids)?;
+            for dir in &missing {
+                run_as::chown(dir, ids)?;
+            }
+        }
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let path = ctx.resolve(&self.path)?;
+        if is_protected(ctx, &path) {
+            return Err(ShiftError::PermissionDenied(format!(
+                "refusing to remove {}: it is the plan root, the home directory \
+                 or one of their parents",
+                path.display()
+            )));
+        }
+        if ctx.fs().exists(&path) {
+            ctx.fs().remove_dir_all(&path)?;
+        }
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        let path = ctx.resolve(&self.path)?;
+        if !ctx.fs().is_dir(&path) {
+            return Ok(false);
+        }
+        if let Some(user) = &self.run_as {
+            if !run_as::owned_by(&path, run_as::ids(ctx, user)?)? {
+                return Ok(false);
+            }
+        }
+        permissions::mode_matches(ctx.fs(), &path, ctx.permissions().dir_mode(self.mode))
+    }
+}
+
+/// Directories a revert must never delete wholesale, whatever the plan says.
+fn is_protected(ctx: &ExecutionContext, path: &Path) -> bool {
+    let path = paths::canonical(path);
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let guarded = [Some(ctx.root().to_path_buf()), home];
+    path.parent().is_none()
+        || guarded
+            .iter()
+            .flatten()
+            .any(|dir| paths::canonical(dir).starts_with(&path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CreateDir;
+    use crate::testing::assert_idempotent;
+
+    #[test]
+    fn create_dir_is_idempotent() {
+        assert_idempotent(&CreateDir::new("cache"));
+    }
+
+    #[test]
+    fn nested_create_dir_is_idempotent() {
+        assert_idempotent(&CreateDir::new("a/b/c"));
+    }
+}
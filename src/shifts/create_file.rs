@@ -0,0 +1,168 @@
+use std::path::{Path, PathBuf};
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::metadata::ShiftMetadata;
+use crate::permissions;
+use crate::resource::{Claim, Resource};
+use crate::run_as;
+use crate::shift::{Shift, ShiftOutcome};
+use crate::validate::ValidationContext;
+
+/// Writes a file with the given contents.
+///
+/// The file gets `mode` if set, otherwise the plan's default file mode.
+/// With `run_as`, it is handed to that user and their login group.
+/// Applied while the file holds exactly `contents`, with that mode and
+/// owner. Revert removes the file.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CreateFile {
+    path: PathBuf,
+    #[serde(default)]
+    contents: String,
+    #[serde(default)]
+    mode: Option<u32>,
+    /// Keeps the contents out of output and reports.
+    #[serde(default)]
+    sensitive: bool,
+    #[serde(default)]
+    run_as: Option<String>,
+}
+
+impl CreateFile {
+    pub fn new(path: impl Into<PathBuf>, contents: impl Into<String>) -> Self {
+        CreateFile {
+            path: path.into(),
+            contents: contents.into(),
+            mode: None,
+            sensitive: false,
+            run_as: None,
+        }
+    }
+
+    pub fn contents(mut self, contents: impl Into<String>) -> Self {
+        self.contents = contents.into();
+        self
+    }
+
+    /// Unix permission bits, e.g. `0o644`. Ignored on other platforms.
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Marks the contents as secret.
+    pub fn sensitive(mut self) -> Self {
+        self.sensitive = true;
+        self
+    }
+
+    /// Makes `user` own the file.
+    pub fn run_as(mut self, user: impl Into<String>) -> Self {
+        self.run_as = Some(user.into());
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Shift for CreateFile {
+    fn metadata(&self) -> ShiftMetadata {
+        let meta = ShiftMetadata::new("file", format!("create file {}", self.path.display()))
+            .target(&self.path);
+        let meta = if self.sensitive {
+            meta.sensitive_input("contents", &self.contents)
+        } else {
+            meta.input("contents", &self.contents)
+        };
+        let meta = match self.mode {
+            Some(mode) => meta.input("mode", format!("{mode:o}")),
+            None => meta,
+        };
+        match &self.run_as {
+            Some(user) => meta.input("run_as", user),
+            None => meta,
+        }
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![Claim::exclusive(Resource::path(&self.path))]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        let path = ctx.exec().resolve(&self.path)?;
+        if ctx.exec().fs().is_dir(&path) {
+            return Err(ShiftError::Conflict {
+                resource: path.display().to_string(),
+                reason: "is a directory".into(),
+            });
+        }
+        if let Some(user) = &self.run_as {
+            run_as::check(ctx, user, false)?;
+        }
+        ctx.require_parent(&self.path)
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        self.run_as.iter().map(|_| Capability::Sudo).collect()
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        if self.is_applied(ctx)? {
+            return Ok(ShiftOutcome::Unchanged);
+        }
+        let path = ctx.resolve(&self.path)?;
+        let mode = ctx.permissions().file_mode(self.mode);
+        ctx.fs().write(&path, self.contents.as_bytes(), mode)?;
+        if let Some(user) = &self.run_as {
+            run_as::chown(&path, run_as::ids(ctx, user)?)?;
+        }
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let path = ctx.resolve(&self.path)?;
+        if ctx.fs().exists(&path) {
+            ctx.fs().remove_file(&path)?;
+        }
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        let path = ctx.resolve(&self.path)?;
+        let fs = ctx.fs();
+        if !fs.exists(&path) {
+            return Ok(false);
+        }
+        if let Some(user) = &self.run_as {
+            if !run_as::owned_by(&path, run_as::ids(ctx, user)?)? {
+                return Ok(false);
+            }
+        }
+        Ok(fs.read(&path)? == self.contents.as_bytes()
+            && permissions::mode_matches(fs, &path, ctx.permissions().file_mode(self.mode))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CreateFile;
+    use crate::testing::assert_idempotent;
+
+    #[test]
+    fn create_file_is_idempotent() {
+        assert_idempotent(&CreateFile::new("greeting.txt", "hello\n"));
+    }
+
+    #[test]
+    fn create_file_with_mode_is_idempotent() {
+        assert_idempotent(&CreateFile::new("secret.txt", "hush\n").mode(0o600));
+    }
+}
@@ -0,0 +1,164 @@
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::metadata::ShiftMetadata;
+use crate::resource::{Claim, Resource};
+use crate::shift::{Shift, ShiftOutcome};
+use crate::shifts::Cmd;
+use crate::validate::ValidationContext;
+
+/// Sets a dconf key, the store behind GNOME's gsettings, e.g.
+/// `/org/gnome/desktop/interface/color-scheme = 'prefer-dark'`.
+///
+/// `value` is typed from TOML: strings, integers, floats, booleans, and
+/// arrays of those become the matching GVariant. For any other type give
+/// `variant` instead, in GVariant text as `dconf read` prints it, e.g.
+/// `"uint32 300"`. Applied while `dconf read` reports the value, so a key
+/// changed from the settings app counts as drift. The value before the
+/// first apply is kept in the shift's state; revert writes it back, or
+/// resets the key if it was unset. Writing needs the user's D-Bus session.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DconfSetting {
+    key: String,
+    #[serde(default)]
+    #[schemars(with = "Option<serde_json::Value>")]
+    value: Option<toml::Value>,
+    #[serde(default)]
+    variant: Option<String>,
+}
+
+impl DconfSetting {
+    pub fn new(key: impl Into<String>, value: impl Into<toml::Value>) -> Self {
+        DconfSetting {
+            key: key.into(),
+            value: Some(value.into()),
+            variant: None,
+        }
+    }
+
+    /// Sets `key` to `variant`, written in GVariant text.
+    pub fn variant(key: impl Into<String>, variant: impl Into<String>) -> Self {
+        DconfSetting {
+            key: key.into(),
+            value: None,
+            variant: Some(variant.into()),
+        }
+    }
+
+    /// The value to write, in GVariant text.
+    fn wanted(&self) -> ShiftResult<String> {
+        match (&self.value, &self.variant) {
+            (Some(value), None) => to_variant(value),
+            (None, Some(variant)) => Ok(variant.trim().to_string()),
+            _ => Err(ShiftError::Custom(
+                "set exactly one of `value` and `variant`".into(),
+            )),
+        }
+    }
+
+    /// The current value, or `None` if the key is unset.
+    fn read(&self, ctx: &ExecutionContext) -> ShiftResult<Option<String>> {
+        let out = Cmd::new("dconf").args(["read", &self.key]).output(ctx)?;
+        let out = out.trim();
+        Ok((!out.is_empty()).then(|| out.to_string()))
+    }
+
+    fn write(&self, ctx: &ExecutionContext, variant: &str) -> ShiftResult<()> {
+        Cmd::new("dconf")
+            .args(["write", &self.key, variant])
+            .output(ctx)
+            .map(drop)
+    }
+}
+
+/// `value` in GVariant text, formatted the way `dconf read` prints it.
+fn to_variant(value: &toml::Value) -> ShiftResult<String> {
+    Ok(match value {
+        toml::Value::String(s) => format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'")),
+        toml::Value::Integer(n) => n.to_string(),
+        toml::Value::Float(x) => format!("{x:?}"),
+        toml::Value::Boolean(b) => b.to_string(),
+        toml::Value::Array(items) if items.is_empty() => {
+            return Err(ShiftError::Custom(
+                "an empty array has no type; use `variant`, e.g. \"@as []\"".into(),
+            ))
+        }
+        toml::Value::Array(items) => {
+            let items = items
+                .iter()
+                .map(to_variant)
+                .collect::<ShiftResult<Vec<_>>>()?;
+            format!("[{}]", items.join(", "))
+        }
+        toml::Value::Datetime(_) | toml::Value::Table(_) => {
+            return Err(ShiftError::Custom(
+                "dates and tables have no dconf type; use `variant`".into(),
+            ))
+        }
+    })
+}
+
+impl Shift for DconfSetting {
+    fn metadata(&self) -> ShiftMetadata {
+        let wanted = self.wanted().unwrap_or_default();
+        ShiftMetadata::new("dconf", format!("set {} to {wanted}", self.key)).input("value", wanted)
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![Claim::exclusive(Resource::lock(format!(
+            "dconf {}",
+            self.key
+        )))]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        let valid = self.key.starts_with('/')
+            && !self.key.ends_with('/')
+            && !self.key.contains("//")
+            && !self.key.contains(char::is_whitespace);
+        if !valid {
+            return Err(ShiftError::Custom(format!(
+                "invalid dconf key `{}`: expected a path like /org/gnome/desktop/interface/color-scheme",
+                self.key
+            )));
+        }
+        self.wanted()?;
+        ctx.require_program("dconf")
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let wanted = self.wanted()?;
+        let current = self.read(ctx)?;
+        if current.as_deref() == Some(wanted.as_str()) {
+            return Ok(ShiftOutcome::Unchanged);
+        }
+        if ctx.get_state("previous").is_none() {
+            ctx.set_state("previous", json!(current));
+        }
+        self.write(ctx, &wanted)?;
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        match ctx.get_state("previous") {
+            Some(serde_json::Value::String(previous)) => self.write(ctx, &previous)?,
+            Some(_) => {
+                Cmd::new("dconf").args(["reset", &self.key]).output(ctx)?;
+            }
+            None => {}
+        }
+        ctx.clear_state("previous");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        if crate::validate::find_program("dconf").is_none() {
+            return Ok(false);
+        }
+        Ok(self.read(ctx)? == Some(self.wanted()?))
+    }
+}
@@ -0,0 +1,553 @@
+//! Desktop applications from Flatpak, Snap, or a downloaded AppImage.
+//!
+//! Each shift remembers in its state whether it installed the app, so
+//! revert only removes apps that were not there before.
+
+use std::fs;
+use std::path::PathBuf;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::artifacts::{self, Artifact};
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::hash::sha256_hex;
+use crate::inputs::{ExternalInput, InputKind};
+use crate::metadata::ShiftMetadata;
+use crate::permissions;
+use crate::resource::{Claim, Resource};
+use crate::shift::{Shift, ShiftOutcome};
+use crate::shifts::Cmd;
+use crate::validate::{find_program, ValidationContext};
+
+/// Installs a Flatpak application from a remote.
+///
+/// `commit` pins the app to that commit (see `flatpak remote-info --log`).
+/// Installs system-wide unless `user` is set. Applied while the app is
+/// installed, at `commit` if given. Revert uninstalls it, but only if this
+/// shift installed it.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct FlatpakInstall {
+    app: String,
+    #[serde(default = "default_remote")]
+    remote: String,
+    #[serde(default)]
+    commit: Option<String>,
+    #[serde(default)]
+    user: bool,
+}
+
+fn default_remote() -> String {
+    "flathub".into()
+}
+
+impl FlatpakInstall {
+    pub fn new(app: impl Into<String>) -> Self {
+        FlatpakInstall {
+            app: app.into(),
+            remote: default_remote(),
+            commit: None,
+            user: false,
+        }
+    }
+
+    pub fn remote(mut self, remote: impl Into<String>) -> Self {
+        self.remote = remote.into();
+        self
+    }
+
+    /// Pins the app to `commit`.
+    pub fn commit(mut self, commit: impl Into<String>) -> Self {
+        self.commit = Some(commit.into());
+        self
+    }
+
+    /// Installs for the current user only.
+    pub fn user(mut self, user: bool) -> Self {
+        self.user = user;
+        self
+    }
+
+    fn flatpak(&self, args: &[&str]) -> Cmd {
+        let scope = if self.user { "--user" } else { "--system" };
+        Cmd::new("flatpak")
+            .args(args.iter().copied())
+            .arg(scope)
+            .args(["--noninteractive", "-y"])
+    }
+
+    /// The installed commit, if the app is installed.
+    fn installed_commit(&self, ctx: &ExecutionContext) -> ShiftResult<Option<String>> {
+        let scope = if self.user { "--user" } else { "--system" };
+        let info = Cmd::new("flatpak")
+            .args(["info", scope, "--show-commit", &self.app])
+            .output(ctx);
+        match info {
+            Ok(commit) => Ok(Some(commit.trim().to_string())),
+            Err(ShiftError::Command { .. }) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl Shift for FlatpakInstall {
+    fn metadata(&self) -> ShiftMetadata {
+        let meta = ShiftMetadata::new("flatpak", format!("install {} with flatpak", self.app))
+            .input("app", &self.app)
+            .input("remote", &self.remote)
+            .input("user", self.user);
+        match &self.commit {
+            Some(commit) => meta.input("commit", commit),
+            None => meta,
+        }
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![Claim::shared(Resource::lock("flatpak"))]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        ctx.require_program("flatpak")
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        match self.user {
+            true => vec![Capability::Network],
+            false => vec![Capability::Network, Capability::Sudo],
+        }
+    }
+
+    fn inputs(&self, _ctx: &ExecutionContext) -> ShiftResult<Vec<ExternalInput>> {
+        Ok(vec![ExternalInput::new(InputKind::App, &self.app)
+            .requested(Some(&self.remote))
+            .pinned(self.commit.as_ref())])
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let installed = self.installed_commit(ctx)?;
+        let mut changed = false;
+        if installed.is_none() {
+            self.flatpak(&["install"])
+                .args([&self.remote, &self.app])
+                .output(ctx)?;
+            ctx.set_state("installed", json!(true));
+            changed = true;
+        }
+        if let Some(commit) = &self.commit {
+            let current = self.installed_commit(ctx)?.unwrap_or_default();
+            if !current.starts_with(commit.as_str()) {
+                self.flatpak(&["update"])
+                    .arg(format!("--commit={commit}"))
+                    .arg(&self.app)
+                    .output(ctx)?;
+                changed = true;
+            }
+        }
+        Ok(ShiftOutcome::changed_if(changed))
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        if ctx.get_state("installed") == Some(json!(true)) && self.installed_commit(ctx)?.is_some()
+        {
+            self.flatpak(&["uninstall"]).arg(&self.app).output(ctx)?;
+        }
+        ctx.clear_state("installed");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        if find_program("flatpak").is_none() {
+            return Ok(false);
+        }
+        Ok(match (self.installed_commit(ctx)?, &self.commit) {
+            (Some(current), Some(commit)) => current.starts_with(commit.as_str()),
+            (installed, None) => installed.is_some(),
+            (None, Some(_)) => false,
+        })
+    }
+}
+
+/// Installs a snap, tracking `channel` (e.g. `stable`, `22/edge`) or held
+/// at `revision`.
+///
+/// Applied while the snap is installed with that channel and revision.
+/// Revert removes it, but only if this shift installed it.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SnapInstall {
+    name: String,
+    #[serde(default)]
+    channel: Option<String>,
+    #[serde(default)]
+    revision: Option<String>,
+    #[serde(default)]
+    classic: bool,
+}
+
+/// A row of `snap list`.
+struct Snap {
+    revision: String,
+    tracking: String,
+}
+
+impl SnapInstall {
+    pub fn new(name: impl Into<String>) -> Self {
+        SnapInstall {
+            name: name.into(),
+            channel: None,
+            revision: None,
+            classic: false,
+        }
+    }
+
+    pub fn channel(mut self, channel: impl Into<String>) -> Self {
+        self.channel = Some(channel.into());
+        self
+    }
+
+    /// Pins the snap to `revision`.
+    pub fn revision(mut self, revision: impl Into<String>) -> Self {
+        self.revision = Some(revision.into());
+        self
+    }
+
+    /// Installs with classic confinement.
+    pub fn classic(mut self, classic: bool) -> Self {
+        self.classic = classic;
+        self
+    }
+
+    fn installed(&self, ctx: &ExecutionContext) -> ShiftResult<Option<Snap>> {
+        let list = match Cmd::new("snap").args(["list", &self.name]).output(ctx) {
+            Ok(list) => list,
+            Err(ShiftError::Command { .. }) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        // Name  Version  Rev  Tracking  Publisher  Notes
+        Ok(list.lines().skip(1).find_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                [name, _, revision, tracking, ..] if *name == self.name => Some(Snap {
+                    revision: revision.to_string(),
+                    tracking: tracking.to_string(),
+                }),
+                _ => None,
+            }
+        }))
+    }
+
+    /// `channel` as `snap list` shows it: `stable` tracks `latest/stable`.
+    fn tracking(&self) -> Option<String> {
+        self.channel
+            .as_ref()
+            .map(|channel| match channel.contains('/') {
+                true => channel.clone(),
+                false => format!("latest/{channel}"),
+            })
+    }
+
+    fn matches(&self, snap: &Snap) -> bool {
+        self.revision
+            .as_ref()
+            .is_none_or(|revision| &snap.revision == revision)
+            && self
+                .tracking()
+                .is_none_or(|tracking| snap.tracking == tracking)
+    }
+
+    fn pins(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(channel) = &self.channel {
+            args.push(format!("--channel={channel}"));
+        }
+        if let Some(revision) = &self.revision {
+            args.push(format!("--revision={revision}"));
+        }
+        args
+    }
+}
+
+impl Shift for SnapInstall {
+    fn metadata(&self) -> ShiftMetadata {
+        let mut meta = ShiftMetadata::new("snap", format!("install {} with snap", self.name))
+            .input("name", &self.name)
+            .input("classic", self.classic);
+        if let Some(channel) = &self.channel {
+            meta = meta.input("channel", channel);
+        }
+        if let Some(revision) = &self.revision {
+            meta = meta.input("revision", revision);
+        }
+        meta
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![Claim::shared(Resource::lock("snap"))]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        ctx.require_program("snap")
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network, Capability::Sudo]
+    }
+
+    fn inputs(&self, _ctx: &ExecutionContext) -> ShiftResult<Vec<ExternalInput>> {
+        Ok(vec![ExternalInput::new(InputKind::App, &self.name)
+            .requested(self.channel.as_ref())
+            .pinned(
+                self.revision
+                    .as_ref()
+                    .map(|revision| format!("revision {revision}")),
+            )])
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        match self.installed(ctx)? {
+            Some(snap) if self.matches(&snap) => return Ok(ShiftOutcome::Unchanged),
+            Some(_) => {
+                Cmd::new("snap")
+                    .args(["refresh", &self.name])
+                    .args(self.pins())
+                    .output(ctx)?;
+            }
+            None => {
+                let mut install = Cmd::new("snap")
+                    .args(["install", &self.name])
+                    .args(self.pins());
+                if self.classic {
+                    install = install.arg("--classic");
+                }
+                install.output(ctx)?;
+                ctx.set_state("installed", json!(true));
+            }
+        }
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        if ctx.get_state("installed") == Some(json!(true)) && self.installed(ctx)?.is_some() {
+            Cmd::new("snap").args(["remove", &self.name]).output(ctx)?;
+        }
+        ctx.clear_state("installed");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        if find_program("snap").is_none() {
+            return Ok(false);
+        }
+        Ok(self.installed(ctx)?.is_some_and(|snap| self.matches(&snap)))
+    }
+}
+
+/// Downloads an AppImage and adds a desktop entry for it.
+///
+/// The image is saved executable at `path`, by default
+/// `~/Applications/<name>.AppImage`, and the entry goes in
+/// `~/.local/share/applications` unless `desktop_entry` is off. `url`
+/// pins the version; with `sha256` the download is checked against it, and
+/// the image is only applied while its hash matches. Without one, it is
+/// applied while the image exists and came from `url`. Revert removes the
+/// image and its desktop entry.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AppImage {
+    name: String,
+    url: String,
+    #[serde(default)]
+    sha256: Option<String>,
+    #[serde(default)]
+    path: Option<PathBuf>,
+    #[serde(default = "default_desktop_entry")]
+    desktop_entry: bool,
+    #[serde(default)]
+    icon: Option<String>,
+}
+
+fn default_desktop_entry() -> bool {
+    true
+}
+
+const APPLICATIONS: &str = "~/.local/share/applications";
+
+impl AppImage {
+    pub fn new(name: impl Into<String>, url: impl Into<String>) -> Self {
+        AppImage {
+            name: name.into(),
+            url: url.into(),
+            sha256: None,
+            path: None,
+            desktop_entry: default_desktop_entry(),
+            icon: None,
+        }
+    }
+
+    /// Expected SHA-256 of the download, in hex.
+    pub fn sha256(mut self, sha256: impl Into<String>) -> Self {
+        self.sha256 = Some(sha256.into());
+        self
+    }
+
+    pub fn path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    pub fn desktop_entry(mut self, desktop_entry: bool) -> Self {
+        self.desktop_entry = desktop_entry;
+        self
+    }
+
+    /// Icon name or path for the desktop entry.
+    pub fn icon(mut self, icon: impl Into<String>) -> Self {
+        self.icon = Some(icon.into());
+        self
+    }
+
+    fn image_path(&self) -> PathBuf {
+        self.path
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(format!("~/Applications/{}.AppImage", self.name)))
+    }
+
+    fn entry_path(&self) -> PathBuf {
+        PathBuf::from(APPLICATIONS).join(format!("{}.desktop", self.name))
+    }
+
+    fn entry(&self, image: &std::path::Path) -> String {
+        let mut entry = format!(
+            "[Desktop Entry]\nType=Application\nName={}\nExec=\"{}\" %U\nTerminal=false\n",
+            self.name,
+            image.display()
+        );
+        if let Some(icon) = &self.icon {
+            entry.push_str(&format!("Icon={icon}\n"));
+        }
+        entry
+    }
+}
+
+impl Shift for AppImage {
+    fn metadata(&self) -> ShiftMetadata {
+        let mut meta =
+            ShiftMetadata::new("appimage", format!("install the {} AppImage", self.name))
+                .target(self.image_path())
+                .input("url", &self.url);
+        if self.desktop_entry {
+            meta = meta.target(self.entry_path());
+        }
+        if let Some(sha256) = &self.sha256 {
+            meta = meta.input("sha256", sha256);
+        }
+        meta
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        let mut claims = vec![Claim::exclusive(Resource::path(self.image_path()))];
+        if self.desktop_entry {
+            claims.push(Claim::exclusive(Resource::path(self.entry_path())));
+        }
+        claims
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        if self.name.is_empty() || self.name.contains('/') {
+            return Err(ShiftError::Custom(format!(
+                "`{}` is not a usable app name",
+                self.name
+            )));
+        }
+        if let Some(sha256) = &self.sha256 {
+            if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(ShiftError::Custom(format!(
+                    "`{sha256}` is not a SHA-256 hash"
+                )));
+            }
+        }
+        ctx.exec().resolve(&self.image_path())?;
+        if self.desktop_entry {
+            ctx.exec().resolve(&self.entry_path())?;
+        }
+        if ctx.exec().is_offline() {
+            return Ok(());
+        }
+        ctx.require_program("curl")
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network]
+    }
+
+    fn inputs(&self, ctx: &ExecutionContext) -> ShiftResult<Vec<ExternalInput>> {
+        Ok(vec![ExternalInput::download(
+            ctx,
+            &self.url,
+            self.sha256.as_deref(),
+        )])
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        if self.is_applied(ctx)? {
+            return Ok(ShiftOutcome::Unchanged);
+        }
+        let image = ctx.resolve(&self.image_path())?;
+        let download = ctx.temp_dir()?.join(format!("{}.AppImage", self.name));
+        artifacts::download(ctx, &self.url, self.sha256.as_deref(), &download)?;
+        let bytes = fs::read(&download)?;
+        if let Some(dir) = image.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        permissions::write_file(&image, &bytes, Some(0o755))?;
+        if self.desktop_entry {
+            let entry = ctx.resolve(&self.entry_path())?;
+            if let Some(dir) = entry.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            permissions::write_file(&entry, self.entry(&image).as_bytes(), Some(0o644))?;
+        }
+        ctx.set_state("url", json!(self.url));
+        ctx.output("path", &image);
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let mut paths = vec![ctx.resolve(&self.image_path())?];
+        if self.desktop_entry {
+            paths.push(ctx.resolve(&self.entry_path())?);
+        }
+        for path in paths {
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+        ctx.clear_state("url");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        let image = ctx.resolve(&self.image_path())?;
+        if !image.is_file() {
+            return Ok(false);
+        }
+        if self.desktop_entry {
+            let entry = ctx.resolve(&self.entry_path())?;
+            if fs::read_to_string(entry).ok() != Some(self.entry(&image)) {
+                return Ok(false);
+            }
+        }
+        Ok(match &self.sha256 {
+            Some(expected) => sha256_hex(fs::read(&image)?).eq_ignore_ascii_case(expected),
+            None => ctx.get_state("url") == Some(json!(self.url)),
+        })
+    }
+
+    fn artifacts(&self, _ctx: &ExecutionContext) -> ShiftResult<Vec<Artifact>> {
+        Ok(vec![Artifact::file(&self.url)])
+    }
+}
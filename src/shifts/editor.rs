@@ -0,0 +1,517 @@
+//! Editor setup: VS Code extensions and settings, and Neovim plugins.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::inputs::{ExternalInput, InputKind};
+use crate::metadata::ShiftMetadata;
+use crate::network;
+use crate::permissions;
+use crate::resource::{Claim, Resource};
+use crate::shift::{Shift, ShiftOutcome};
+use crate::shifts::Cmd;
+use crate::validate::{find_program, ValidationContext};
+
+/// Installs a VS Code `extension`, given by its ID, optionally at a pinned `version`.
+///
+/// `program` is the editor's command line, `code` by default; set it to
+/// `codium` or `code-insiders` for those. Applied while the extension is
+/// installed, at `version` if given. Revert uninstalls the extension only
+/// if this shift installed it.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct VsCodeExtension {
+    extension: String,
+    #[serde(default)]
+    version: Option<String>,
+    #[serde(default = "default_program")]
+    program: String,
+}
+
+fn default_program() -> String {
+    "code".into()
+}
+
+impl VsCodeExtension {
+    pub fn new(extension: impl Into<String>) -> Self {
+        VsCodeExtension {
+            extension: extension.into(),
+            version: None,
+            program: default_program(),
+        }
+    }
+
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    pub fn program(mut self, program: impl Into<String>) -> Self {
+        self.program = program.into();
+        self
+    }
+
+    /// The installed version, if the extension is installed.
+    fn installed(&self, ctx: &ExecutionContext) -> ShiftResult<Option<String>> {
+        let list = Cmd::new(&self.program)
+            .args(["--list-extensions", "--show-versions"])
+            .output(ctx)?;
+        Ok(list.lines().find_map(|line| {
+            let (id, version) = line.trim().split_once('@')?;
+            id.eq_ignore_ascii_case(&self.extension)
+                .then(|| version.to_string())
+        }))
+    }
+}
+
+impl Shift for VsCodeExtension {
+    fn metadata(&self) -> ShiftMetadata {
+        let meta = ShiftMetadata::new(
+            "vscode_extension",
+            format!("install the {} extension", self.extension),
+        )
+        .input("extension", &self.extension)
+        .input("program", &self.program);
+        match &self.version {
+            Some(version) => meta.input("version", version),
+            None => meta,
+        }
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![Claim::shared(Resource::lock(format!(
+            "{}-extensions",
+            self.program
+        )))]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        if !self.extension.contains('.') {
+            return Err(ShiftError::Custom(format!(
+                "`{}` is not an extension ID; expected publisher.name",
+                self.extension
+            )));
+        }
+        ctx.require_program(&self.program)
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network]
+    }
+
+    fn inputs(&self, _ctx: &ExecutionContext) -> ShiftResult<Vec<ExternalInput>> {
+        Ok(vec![ExternalInput::new(
+            InputKind::Extension,
+            &self.extension,
+        )
+        .pinned(self.version.as_ref())])
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let installed = self.installed(ctx)?;
+        let wanted = match (&installed, &self.version) {
+            (Some(_), None) => return Ok(ShiftOutcome::Unchanged),
+            (Some(have), Some(want)) if have == want => return Ok(ShiftOutcome::Unchanged),
+            (_, Some(version)) => format!("{}@{version}", self.extension),
+            (None, None) => self.extension.clone(),
+        };
+        Cmd::new(&self.program)
+            .args(["--install-extension", &wanted, "--force"])
+            .output(ctx)?;
+        if installed.is_none() {
+            ctx.set_state("installed", json!(true));
+        }
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        if ctx.get_state("installed") == Some(json!(true)) && self.installed(ctx)?.is_some() {
+            Cmd::new(&self.program)
+                .args(["--uninstall-extension", &self.extension])
+                .output(ctx)?;
+        }
+        ctx.clear_state("installed");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        if find_program(&self.program).is_none() {
+            return Ok(false);
+        }
+        Ok(match (self.installed(ctx)?, &self.version) {
+            (Some(have), Some(want)) => &have == want,
+            (installed, None) => installed.is_some(),
+            (None, Some(_)) => false,
+        })
+    }
+}
+
+/// Sets keys in a VS Code `settings.json`, leaving the others alone.
+///
+/// Keys are VS Code's dotted setting names, e.g. `"editor.fontSize"`. The
+/// file defaults to the user settings of the platform's VS Code. Comments
+/// and trailing commas, which VS Code allows, are accepted, but comments
+/// are not kept when the file is rewritten. Applied while every key holds
+/// its value. The previous value of every key is kept in the shift's
+/// state, and revert restores them.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct VsCodeSettings {
+    settings: Map<String, Value>,
+    #[serde(default)]
+    path: Option<PathBuf>,
+}
+
+impl VsCodeSettings {
+    pub fn new() -> Self {
+        VsCodeSettings {
+            settings: Map::new(),
+            path: None,
+        }
+    }
+
+    pub fn set(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.settings.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    fn settings_path(&self, os: Option<&str>) -> PathBuf {
+        match (&self.path, os) {
+            (Some(path), _) => path.clone(),
+            (None, Some("macos")) => {
+                PathBuf::from("~/Library/Application Support/Code/User/settings.json")
+            }
+            (None, _) => PathBuf::from("~/.config/Code/User/settings.json"),
+        }
+    }
+
+    fn resolved(&self, ctx: &ExecutionContext) -> ShiftResult<PathBuf> {
+        ctx.resolve(&self.settings_path(ctx.facts().get("os")))
+    }
+}
+
+impl Default for VsCodeSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The settings object in `path`, or an empty one if there is no file.
+fn read_settings(path: &Path) -> ShiftResult<Map<String, Value>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Map::new()),
+        Err(err) => return Err(err.into()),
+    };
+    if text.trim().is_empty() {
+        return Ok(Map::new());
+    }
+    match serde_json::from_str(&strip_jsonc(&text)) {
+        Ok(Value::Object(settings)) => Ok(settings),
+        Ok(_) => Err(ShiftError::Custom(format!(
+            "{} does not hold a JSON object",
+            path.display()
+        ))),
+        Err(err) => Err(ShiftError::Custom(format!(
+            "cannot parse {}: {err}",
+            path.display()
+        ))),
+    }
+}
+
+fn write_settings(path: &Path, settings: &Map<String, Value>) -> ShiftResult<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut text = serde_json::to_string_pretty(settings)
+        .map_err(|err| ShiftError::Custom(format!("cannot encode settings: {err}")))?;
+    text.push('\n');
+    permissions::write_file(path, text.as_bytes(), None)
+}
+
+/// `text` without `//` and `/* */` comments or trailing commas, so it
+/// parses as plain JSON.
+fn strip_jsonc(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            match c {
+                '\\' => out.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                out.push(c);
+            }
+            ('/', Some('/')) => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        out.push('\n');
+                        break;
+                    }
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut last = ' ';
+                for c in chars.by_ref() {
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    last = c;
+                }
+            }
+            (',', _) => {
+                let rest: String = chars.clone().collect();
+                let next = strip_jsonc(&rest);
+                if !matches!(next.trim_start().chars().next(), Some('}' | ']')) {
+                    out.push(',');
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+impl Shift for VsCodeSettings {
+    fn metadata(&self) -> ShiftMetadata {
+        let path = self.settings_path(Some(std::env::consts::OS));
+        let keys: Vec<&str> = self.settings.keys().map(String::as_str).collect();
+        ShiftMetadata::new(
+            "vscode_settings",
+            format!("set {} in {}", keys.join(", "), path.display()),
+        )
+        .target(&path)
+        .input("settings", &self.settings)
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        let path = self.settings_path(Some(std::env::consts::OS));
+        vec![Claim::shared(Resource::path(path))]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        if self.settings.is_empty() {
+            return Err(ShiftError::Custom("no `settings` to set".into()));
+        }
+        self.resolved(ctx.exec()).map(drop)
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let path = self.resolved(ctx)?;
+        let mut settings = read_settings(&path)?;
+        if self
+            .settings
+            .iter()
+            .all(|(key, value)| settings.get(key) == Some(value))
+        {
+            return Ok(ShiftOutcome::Unchanged);
+        }
+        if ctx.get_state("previous").is_none() {
+            let previous: Map<String, Value> = self
+                .settings
+                .keys()
+                .map(|key| {
+                    (
+                        key.clone(),
+                        settings.get(key).cloned().unwrap_or(Value::Null),
+                    )
+                })
+                .collect();
+            ctx.set_state("previous", Value::Object(previous));
+        }
+        for (key, value) in &self.settings {
+            settings.insert(key.clone(), value.clone());
+        }
+        write_settings(&path, &settings)?;
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let Some(Value::Object(previous)) = ctx.get_state("previous") else {
+            return Ok(());
+        };
+        let path = self.resolved(ctx)?;
+        let mut settings = read_settings(&path)?;
+        for (key, value) in previous {
+            match value {
+                Value::Null => settings.remove(&key),
+                value => settings.insert(key, value),
+            };
+        }
+        write_settings(&path, &settings)?;
+        ctx.clear_state("previous");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        let settings = read_settings(&self.resolved(ctx)?)?;
+        Ok(self
+            .settings
+            .iter()
+            .all(|(key, value)| settings.get(key) == Some(value)))
+    }
+}
+
+/// Bootstraps Neovim plugins with lazy.nvim.
+///
+/// Clones lazy.nvim into Neovim's data directory if it is missing, then
+/// installs plugins headlessly: `Lazy! restore` to the versions in
+/// `lazy-lock.json` when the config has one, else `Lazy! sync`. Applied
+/// once lazy.nvim and every plugin in the lockfile are present. Revert
+/// removes the plugins directory if this shift created it.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct NeovimPlugins {
+    #[serde(default = "default_nvim_config")]
+    config: PathBuf,
+    #[serde(default = "default_nvim_data")]
+    data: PathBuf,
+}
+
+fn default_nvim_config() -> PathBuf {
+    PathBuf::from("~/.config/nvim")
+}
+
+fn default_nvim_data() -> PathBuf {
+    PathBuf::from("~/.local/share/nvim")
+}
+
+const LAZY_REPO: &str = "https://github.com/folke/lazy.nvim.git";
+
+impl NeovimPlugins {
+    pub fn new() -> Self {
+        NeovimPlugins {
+            config: default_nvim_config(),
+            data: default_nvim_data(),
+        }
+    }
+
+    /// Neovim's config directory, holding `init.lua` and `lazy-lock.json`.
+    pub fn config(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config = dir.into();
+        self
+    }
+
+    /// Neovim's data directory, where plugins are installed.
+    pub fn data(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.data = dir.into();
+        self
+    }
+
+    fn plugins_dir(&self) -> PathBuf {
+        self.data.join("lazy")
+    }
+
+    /// Plugins pinned in `lazy-lock.json`, if there is one.
+    fn locked(&self, ctx: &ExecutionContext) -> ShiftResult<Option<Vec<String>>> {
+        let path = ctx.resolve(&self.config.join("lazy-lock.json"))?;
+        let Ok(text) = fs::read_to_string(&path) else {
+            return Ok(None);
+        };
+        let lock: Map<String, Value> = serde_json::from_str(&text)
+            .map_err(|err| ShiftError::Custom(format!("cannot parse {}: {err}", path.display())))?;
+        Ok(Some(lock.keys().cloned().collect()))
+    }
+}
+
+impl Default for NeovimPlugins {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shift for NeovimPlugins {
+    fn metadata(&self) -> ShiftMetadata {
+        ShiftMetadata::new(
+            "neovim_plugins",
+            format!("install Neovim plugins for {}", self.config.display()),
+        )
+        .target(self.plugins_dir())
+        .input("config", &self.config)
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![Claim::exclusive(Resource::path(self.plugins_dir()))]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        ctx.require_program("nvim")?;
+        ctx.require_program("git")?;
+        ctx.require_dir(&self.config)?;
+        ctx.exec().resolve(&self.plugins_dir()).map(drop)
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        if self.is_applied(ctx)? {
+            return Ok(ShiftOutcome::Unchanged);
+        }
+        let plugins = ctx.resolve(&self.plugins_dir())?;
+        if !plugins.exists() {
+            fs::create_dir_all(&plugins)?;
+            ctx.set_state("created", json!(true));
+        }
+        let lazy = plugins.join("lazy.nvim");
+        if !lazy.exists() {
+            network::transfer(ctx, |_| {
+                Cmd::new("git")
+                    .args(["clone", "--filter=blob:none", "--branch=stable", LAZY_REPO])
+                    .arg(lazy.display().to_string())
+                    .output(ctx)
+            })?;
+        }
+        let command = match self.locked(ctx)? {
+            Some(_) => "+Lazy! restore",
+            None => "+Lazy! sync",
+        };
+        Cmd::new("nvim")
+            .args(["--headless", command, "+qa"])
+            .output(ctx)?;
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let plugins = ctx.resolve(&self.plugins_dir())?;
+        if ctx.get_state("created") == Some(json!(true)) && plugins.exists() {
+            fs::remove_dir_all(&plugins)?;
+        }
+        ctx.clear_state("created");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        let plugins = ctx.resolve(&self.plugins_dir())?;
+        if !plugins.join("lazy.nvim").is_dir() {
+            return Ok(false);
+        }
+        Ok(match self.locked(ctx)? {
+            Some(names) => names.iter().all(|name| plugins.join(name).is_dir()),
+            // Without a lockfile there is no telling what `sync` would add.
+            None => ctx.get_state("created").is_some(),
+        })
+    }
+}
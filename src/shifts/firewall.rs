@@ -0,0 +1,352 @@
+use std::net::IpAddr;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::hash::short_hash;
+use crate::metadata::ShiftMetadata;
+use crate::resource::{Claim, Resource};
+use crate::shift::{Shift, ShiftOutcome};
+use crate::shifts::Cmd;
+use crate::validate::{find_program, ValidationContext};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FirewallAction {
+    Allow,
+    Deny,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Protocol {
+    #[default]
+    Tcp,
+    Udp,
+    /// Both TCP and UDP.
+    Any,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FirewallBackend {
+    Ufw,
+    Nftables,
+}
+
+/// Name of the nftables table holding every skies rule.
+const NFT_TABLE: &str = "skies";
+
+/// Allows or denies incoming traffic to a port.
+///
+/// Rules carry a `skies:<hash>` comment derived from the rule itself, which
+/// is how they are found again: `is_applied` looks for it, and revert only
+/// ever removes rules with it.
+///
+/// The backend is `ufw` if installed, else nftables. With nftables, rules
+/// live in their own `inet skies` table, which is not persisted across
+/// reboots; and because every table's input hook runs, an `allow` here
+/// cannot override a drop in another table.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct FirewallRule {
+    action: FirewallAction,
+    /// A port (`22`) or an inclusive range (`8000-8100`).
+    port: String,
+    #[serde(default)]
+    protocol: Protocol,
+    /// Address or CIDR the traffic must come from; anywhere if unset.
+    #[serde(default)]
+    source: Option<String>,
+    #[serde(default)]
+    backend: Option<FirewallBackend>,
+}
+
+impl FirewallRule {
+    pub fn new(action: FirewallAction, port: impl Into<String>) -> Self {
+        FirewallRule {
+            action,
+            port: port.into(),
+            protocol: Protocol::default(),
+            source: None,
+            backend: None,
+        }
+    }
+
+    pub fn allow(port: impl Into<String>) -> Self {
+        Self::new(FirewallAction::Allow, port)
+    }
+
+    pub fn deny(port: impl Into<String>) -> Self {
+        Self::new(FirewallAction::Deny, port)
+    }
+
+    pub fn protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    pub fn source(mut self, source: impl Into<String>) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+
+    pub fn backend(mut self, backend: FirewallBackend) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    fn backend_or_detect(&self) -> FirewallBackend {
+        self.backend.unwrap_or_else(|| {
+            if find_program("ufw").is_some() {
+                FirewallBackend::Ufw
+            } else {
+                FirewallBackend::Nftables
+            }
+        })
+    }
+
+    fn describe(&self) -> String {
+        let action = match self.action {
+            FirewallAction::Allow => "allow",
+            FirewallAction::Deny => "deny",
+        };
+        let protocol = match self.protocol {
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
+            Protocol::Any => "tcp+udp",
+        };
+        let source = self.source.as_deref().unwrap_or("anywhere");
+        format!("{action} {protocol} port {} from {source}", self.port)
+    }
+
+    /// The comment that marks this rule as managed by skies.
+    fn tag(&self) -> String {
+        format!("skies:{}", short_hash(self.describe(), 8))
+    }
+
+    fn port_range(&self) -> ShiftResult<(u16, Option<u16>)> {
+        let invalid = || {
+            ShiftError::Custom(format!(
+                "invalid port `{}`: expected 1-65535 or a range like 8000-8100",
+                self.port
+            ))
+        };
+        let parse = |s: &str| s.trim().parse::<u16>().ok().filter(|p| *p > 0);
+        match self.port.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (
+                    parse(start).ok_or_else(invalid)?,
+                    parse(end).ok_or_else(invalid)?,
+                );
+                if start >= end {
+                    return Err(invalid());
+                }
+                Ok((start, Some(end)))
+            }
+            None => Ok((parse(&self.port).ok_or_else(invalid)?, None)),
+        }
+    }
+
+    /// `ufw` rule arguments, without the leading `allow`/`deny` and comment.
+    fn ufw_spec(&self) -> ShiftResult<Vec<String>> {
+        let (start, end) = self.port_range()?;
+        let mut spec = Vec::new();
+        match self.protocol {
+            Protocol::Tcp => spec.extend(["proto".into(), "tcp".into()]),
+            Protocol::Udp => spec.extend(["proto".into(), "udp".into()]),
+            Protocol::Any => {}
+        }
+        spec.extend([
+            "from".into(),
+            self.source.clone().unwrap_or_else(|| "any".into()),
+            "to".into(),
+            "any".into(),
+            "port".into(),
+            match end {
+                Some(end) => format!("{start}:{end}"),
+                None => start.to_string(),
+            },
+        ]);
+        Ok(spec)
+    }
+
+    fn ufw_action(&self) -> &'static str {
+        match self.action {
+            FirewallAction::Allow => "allow",
+            FirewallAction::Deny => "deny",
+        }
+    }
+
+    /// The nftables rule expression, without the comment.
+    fn nft_rule(&self) -> ShiftResult<Vec<String>> {
+        let (start, end) = self.port_range()?;
+        let mut rule = Vec::new();
+        if let Some(source) = &self.source {
+            let family = if source.contains(':') { "ip6" } else { "ip" };
+            rule.extend([family.to_string(), "saddr".into(), source.clone()]);
+        }
+        let ports = match end {
+            Some(end) => format!("{start}-{end}"),
+            None => start.to_string(),
+        };
+        match self.protocol {
+            Protocol::Tcp => rule.extend(["tcp".into(), "dport".into(), ports]),
+            Protocol::Udp => rule.extend(["udp".into(), "dport".into(), ports]),
+            Protocol::Any => rule.extend([
+                "meta".into(),
+                "l4proto".into(),
+                "{ tcp, udp }".into(),
+                "th".into(),
+                "dport".into(),
+                ports,
+            ]),
+        }
+        rule.push(
+            match self.action {
+                FirewallAction::Allow => "accept",
+                FirewallAction::Deny => "drop",
+            }
+            .into(),
+        );
+        Ok(rule)
+    }
+
+    /// Handles of the nftables rules carrying our tag.
+    fn nft_handles(&self, ctx: &ExecutionContext) -> ShiftResult<Vec<String>> {
+        let Ok(listing) = Cmd::new("nft")
+            .args(["-a", "list", "chain", "inet", NFT_TABLE, "input"])
+            .output(ctx)
+        else {
+            // No table or chain yet.
+            return Ok(Vec::new());
+        };
+        let tag = format!("comment \"{}\"", self.tag());
+        Ok(listing
+            .lines()
+            .filter(|line| line.contains(&tag))
+            .filter_map(|line| line.rsplit_once("# handle "))
+            .map(|(_, handle)| handle.trim().to_string())
+            .collect())
+    }
+}
+
+impl Shift for FirewallRule {
+    fn metadata(&self) -> ShiftMetadata {
+        let mut meta = ShiftMetadata::new("firewall_rule", self.describe())
+            .input("port", &self.port)
+            .input("tag", self.tag());
+        if let Some(backend) = self.backend {
+            meta = meta.input("backend", format!("{backend:?}").to_lowercase());
+        }
+        meta
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        // ufw and nft both rewrite whole rulesets, so rules go one at a time.
+        let mut claims = vec![Claim::shared(Resource::lock("firewall"))];
+        if let Ok((port, None)) = self.port_range() {
+            claims.push(Claim::shared(Resource::Port(port)));
+        }
+        claims
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        self.port_range()?;
+        if let Some(source) = &self.source {
+            let (addr, prefix) = source.split_once('/').unwrap_or((source, "0"));
+            if addr.parse::<IpAddr>().is_err() || prefix.parse::<u8>().is_err() {
+                return Err(ShiftError::Custom(format!(
+                    "invalid source `{source}`: expected an address or CIDR"
+                )));
+            }
+        }
+        let backend = self.backend_or_detect();
+        if backend == FirewallBackend::Ufw
+            && self.protocol == Protocol::Any
+            && self.port.contains('-')
+        {
+            return Err(ShiftError::Custom(
+                "ufw needs a protocol for port ranges".into(),
+            ));
+        }
+        match backend {
+            FirewallBackend::Ufw => ctx.require_program("ufw"),
+            FirewallBackend::Nftables => ctx.require_program("nft"),
+        }
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Sudo]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        match self.backend_or_detect() {
+            FirewallBackend::Ufw => {
+                Cmd::new("ufw")
+                    .arg(self.ufw_action())
+                    .args(self.ufw_spec()?)
+                    .args(["comment".to_string(), self.tag()])
+                    .output(ctx)?;
+            }
+            FirewallBackend::Nftables => {
+                Cmd::new("nft")
+                    .args(["add", "table", "inet", NFT_TABLE])
+                    .output(ctx)?;
+                Cmd::new("nft")
+                    .args([
+                        "add",
+                        "chain",
+                        "inet",
+                        NFT_TABLE,
+                        "input",
+                        "{ type filter hook input priority 0 ; policy accept ; }",
+                    ])
+                    .output(ctx)?;
+                Cmd::new("nft")
+                    .args(["add", "rule", "inet", NFT_TABLE, "input"])
+                    .args(self.nft_rule()?)
+                    .args(["comment".to_string(), format!("\"{}\"", self.tag())])
+                    .output(ctx)?;
+            }
+        }
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        match self.backend_or_detect() {
+            FirewallBackend::Ufw => {
+                if self.is_applied(ctx)? {
+                    Cmd::new("ufw")
+                        .args(["--force", "delete", self.ufw_action()])
+                        .args(self.ufw_spec()?)
+                        .output(ctx)?;
+                }
+                Ok(())
+            }
+            FirewallBackend::Nftables => {
+                for handle in self.nft_handles(ctx)? {
+                    Cmd::new("nft")
+                        .args(["delete", "rule", "inet", NFT_TABLE, "input", "handle"])
+                        .arg(handle)
+                        .output(ctx)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        match self.backend_or_detect() {
+            FirewallBackend::Ufw => {
+                // Lists added rules with their comments, even while inactive.
+                let added = Cmd::new("ufw").args(["show", "added"]).output(ctx)?;
+                Ok(added.lines().any(|line| line.contains(&self.tag())))
+            }
+            FirewallBackend::Nftables => Ok(!self.nft_handles(ctx)?.is_empty()),
+        }
+    }
+}
@@ -0,0 +1,311 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::artifacts::{self, Artifact};
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::inputs::ExternalInput;
+use crate::metadata::ShiftMetadata;
+use crate::permissions;
+use crate::resource::{Claim, Resource};
+use crate::shift::{Shift, ShiftOutcome};
+use crate::shifts::Cmd;
+use crate::validate::{find_program, ValidationContext};
+
+/// Installs a font family for the current user.
+///
+/// `url` is a font file or an archive of them (`.zip`, `.tar.gz`,
+/// `.tar.xz`), like a Nerd Fonts release; every `.ttf`, `.otf` and `.ttc`
+/// in it goes into a directory for the family under the user's fonts,
+/// `~/.local/share/fonts` or `~/Library/Fonts` on macOS, and the font
+/// cache is refreshed where fontconfig is installed. The installed files
+/// are kept in the shift's state with `version`, or `url` without one;
+/// applied while they are all present and the version matches. A family
+/// that fontconfig already lists counts as applied when there is no
+/// `version` to check. Revert removes only the files this shift installed.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Font {
+    family: String,
+    url: String,
+    #[serde(default)]
+    sha256: Option<String>,
+    #[serde(default)]
+    version: Option<String>,
+    #[serde(default)]
+    dir: Option<PathBuf>,
+}
+
+const EXTENSIONS: [&str; 3] = ["ttf", "otf", "ttc"];
+
+impl Font {
+    pub fn new(family: impl Into<String>, url: impl Into<String>) -> Self {
+        Font {
+            family: family.into(),
+            url: url.into(),
+            sha256: None,
+            version: None,
+            dir: None,
+        }
+    }
+
+    /// Expected SHA-256 of the download, in hex.
+    pub fn sha256(mut self, sha256: impl Into<String>) -> Self {
+        self.sha256 = Some(sha256.into());
+        self
+    }
+
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    /// Installs into `dir` instead of the family's directory under the
+    /// user's fonts.
+    pub fn dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = Some(dir.into());
+        self
+    }
+
+    fn family_dir(&self, os: Option<&str>) -> PathBuf {
+        match (&self.dir, os) {
+            (Some(dir), _) => dir.clone(),
+            (None, Some("macos")) => PathBuf::from("~/Library/Fonts").join(&self.family),
+            (None, _) => PathBuf::from("~/.local/share/fonts").join(&self.family),
+        }
+    }
+
+    fn resolved_dir(&self, ctx: &ExecutionContext) -> ShiftResult<PathBuf> {
+        ctx.resolve(&self.family_dir(ctx.facts().get("os")))
+    }
+
+    /// What identifies the installed release.
+    fn release(&self) -> &str {
+        self.version.as_deref().unwrap_or(&self.url)
+    }
+
+    fn file_name(&self) -> &str {
+        let path = self.url.split(['?', '#']).next().unwrap_or_default();
+        path.rsplit('/').next().unwrap_or_default()
+    }
+
+    fn is_zip(&self) -> bool {
+        self.file_name().to_ascii_lowercase().ends_with(".zip")
+    }
+
+    fn is_tar(&self) -> bool {
+        let name = self.file_name().to_ascii_lowercase();
+        [".tar", ".tar.gz", ".tgz", ".tar.xz", ".tar.bz2"]
+            .iter()
+            .any(|ext| name.ends_with(ext))
+    }
+
+    /// The file names recorded by the last apply.
+    fn recorded(ctx: &ExecutionContext) -> Option<(String, Vec<String>)> {
+        let installed = ctx.get_state("installed")?;
+        let release = installed["release"].as_str()?.to_string();
+        let files = serde_json::from_value(installed["files"].clone()).ok()?;
+        Some((release, files))
+    }
+
+    /// Downloads `url` and returns the font files it holds.
+    fn fetch(&self, ctx: &ExecutionContext) -> ShiftResult<Vec<PathBuf>> {
+        let temp = ctx.temp_dir()?;
+        let download = temp.join(self.file_name());
+        artifacts::download(ctx, &self.url, self.sha256.as_deref(), &download)?;
+        let extract = temp.join("extract");
+        fs::create_dir_all(&extract)?;
+        if self.is_zip() {
+            Cmd::new("unzip")
+                .args(["-q", "-o"])
+                .arg(download.display().to_string())
+                .args(["-d".to_string(), extract.display().to_string()])
+                .output(ctx)?;
+        } else if self.is_tar() {
+            Cmd::new("tar")
+                .arg("-xf")
+                .arg(download.display().to_string())
+                .args(["-C".to_string(), extract.display().to_string()])
+                .output(ctx)?;
+        } else {
+            fs::rename(&download, extract.join(self.file_name()))?;
+        }
+        let mut fonts = Vec::new();
+        collect_fonts(&extract, &mut fonts)?;
+        if fonts.is_empty() {
+            return Err(ShiftError::Custom(format!(
+                "{} holds no .ttf, .otf or .ttc files",
+                self.url
+            )));
+        }
+        fonts.sort();
+        Ok(fonts)
+    }
+
+    fn refresh_cache(ctx: &ExecutionContext, dir: &Path) -> ShiftResult<()> {
+        if find_program("fc-cache").is_some() {
+            Cmd::new("fc-cache")
+                .args(["-f".to_string(), dir.display().to_string()])
+                .output(ctx)?;
+        }
+        Ok(())
+    }
+
+    /// Whether fontconfig lists the family.
+    fn listed(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        if find_program("fc-list").is_none() {
+            return Ok(false);
+        }
+        let out = Cmd::new("fc-list").args([":", "family"]).output(ctx)?;
+        Ok(out
+            .lines()
+            .flat_map(|line| line.split(','))
+            .any(|family| family.trim().eq_ignore_ascii_case(&self.family)))
+    }
+}
+
+fn collect_fonts(dir: &Path, fonts: &mut Vec<PathBuf>) -> ShiftResult<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_fonts(&path, fonts)?;
+        } else if path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+        {
+            fonts.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn remove_files(dir: &Path, files: &[String]) -> ShiftResult<()> {
+    for file in files {
+        let path = dir.join(file);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+impl Shift for Font {
+    fn metadata(&self) -> ShiftMetadata {
+        let dir = self.family_dir(Some(std::env::consts::OS));
+        ShiftMetadata::new("font", format!("install the {} font", self.family))
+            .target(dir)
+            .input("url", &self.url)
+            .input("release", self.release())
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        let dir = self.family_dir(Some(std::env::consts::OS));
+        vec![Claim::exclusive(Resource::path(dir))]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        if self.family.is_empty() || self.family.contains('/') {
+            return Err(ShiftError::Custom(format!(
+                "`{}` is not a usable font family name",
+                self.family
+            )));
+        }
+        if self.file_name().is_empty() {
+            return Err(ShiftError::Custom(format!(
+                "{} does not name a file to download",
+                self.url
+            )));
+        }
+        if let Some(sha256) = &self.sha256 {
+            if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(ShiftError::Custom(format!(
+                    "`{sha256}` is not a SHA-256 hash"
+                )));
+            }
+        }
+        self.resolved_dir(ctx.exec())?;
+        if !ctx.exec().is_offline() {
+            ctx.require_program("curl")?;
+        }
+        if self.is_zip() {
+            ctx.require_program("unzip")?;
+        } else if self.is_tar() {
+            ctx.require_program("tar")?;
+        }
+        Ok(())
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network]
+    }
+
+    fn inputs(&self, ctx: &ExecutionContext) -> ShiftResult<Vec<ExternalInput>> {
+        Ok(vec![ExternalInput::download(
+            ctx,
+            &self.url,
+            self.sha256.as_deref(),
+        )])
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        if self.is_applied(ctx)? {
+            return Ok(ShiftOutcome::Unchanged);
+        }
+        let fonts = self.fetch(ctx)?;
+        let dir = self.resolved_dir(ctx)?;
+        // Clear out an older release so its files don't linger.
+        if let Some((_, files)) = Self::recorded(ctx) {
+            remove_files(&dir, &files)?;
+        }
+        fs::create_dir_all(&dir)?;
+        let mut files = Vec::new();
+        for font in &fonts {
+            let Some(name) = font.file_name() else {
+                continue;
+            };
+            permissions::write_file(&dir.join(name), &fs::read(font)?, Some(0o644))?;
+            files.push(name.to_string_lossy().into_owned());
+        }
+        ctx.set_state(
+            "installed",
+            json!({ "release": self.release(), "files": files }),
+        );
+        Self::refresh_cache(ctx, &dir)?;
+        ctx.output("files", files.len());
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        if let Some((_, files)) = Self::recorded(ctx) {
+            let dir = self.resolved_dir(ctx)?;
+            remove_files(&dir, &files)?;
+            if fs::read_dir(&dir).is_ok_and(|mut entries| entries.next().is_none()) {
+                fs::remove_dir(&dir)?;
+            }
+            Self::refresh_cache(ctx, dir.parent().unwrap_or(&dir))?;
+        }
+        ctx.clear_state("installed");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        match Self::recorded(ctx) {
+            Some((release, files)) => {
+                let dir = self.resolved_dir(ctx)?;
+                Ok(release == self.release() && files.iter().all(|file| dir.join(file).is_file()))
+            }
+            None if self.version.is_none() => self.listed(ctx),
+            None => Ok(false),
+        }
+    }
+
+    fn artifacts(&self, _ctx: &ExecutionContext) -> ShiftResult<Vec<Artifact>> {
+        Ok(vec![Artifact::file(&self.url)])
+    }
+}
@@ -0,0 +1,98 @@
+//! fstab entries owned by skies.
+//!
+//! Each entry is preceded by a `# skies: <key>` marker line, so it can be
+//! found, updated and removed again without touching the entries around it.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::error::ShiftResult;
+use crate::permissions;
+
+/// Prefix of the comment line skies writes above each entry it owns.
+const MARKER: &str = "# skies:";
+
+fn marker(key: &str) -> String {
+    format!("{MARKER} {key}")
+}
+
+/// The contents of `path`, or nothing if it does not exist yet.
+fn read(path: &Path) -> ShiftResult<String> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(text),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn write(path: &Path, text: &str) -> ShiftResult<()> {
+    // Keep the existing mode; fstab must stay world-readable.
+    let mode = if path.exists() {
+        permissions::mode_of(path)?
+    } else {
+        Some(0o644)
+    };
+    permissions::write_file(path, text.as_bytes(), mode)
+}
+
+/// `text` without the marker for `key` and the entry following it.
+fn without(text: &str, key: &str) -> String {
+    let marker = marker(key);
+    let mut out = String::with_capacity(text.len());
+    let mut lines = text.lines();
+    while let Some(line) = lines.next() {
+        if line.trim_end() == marker {
+            lines.next();
+            continue;
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+/// The entry marked with `key` in the fstab at `path`, if any.
+pub(super) fn entry(path: &Path, key: &str) -> ShiftResult<Option<String>> {
+    let marker = marker(key);
+    let text = read(path)?;
+    let mut lines = text.lines();
+    if lines.any(|line| line.trim_end() == marker) {
+        Ok(lines.next().map(String::from))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Writes `line` as the entry marked with `key`, replacing any earlier one.
+pub(super) fn set_entry(path: &Path, key: &str, line: &str) -> ShiftResult<()> {
+    if entry(path, key)?.as_deref() == Some(line) {
+        return Ok(());
+    }
+    let mut text = without(&read(path)?, key);
+    text.push_str(&format!("{}\n{line}\n", marker(key)));
+    write(path, &text)
+}
+
+/// Removes the entry marked with `key`, if there is one.
+pub(super) fn remove_entry(path: &Path, key: &str) -> ShiftResult<()> {
+    if entry(path, key)?.is_some() {
+        write(path, &without(&read(path)?, key))?;
+    }
+    Ok(())
+}
+
+/// Escapes whitespace and backslashes the way fstab and `/proc` do.
+pub(super) fn escape(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            ' ' => out.push_str("\\040"),
+            '\t' => out.push_str("\\011"),
+            '\n' => out.push_str("\\012"),
+            '\\' => out.push_str("\\134"),
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,206 @@
+use std::path::PathBuf;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::fs::read_text;
+use crate::metadata::ShiftMetadata;
+use crate::paths;
+use crate::permissions;
+use crate::resource::{Claim, Resource};
+use crate::shift::{Shift, ShiftOutcome};
+use crate::shifts::Cmd;
+use crate::validate::ValidationContext;
+
+/// Installs `script` as the git hook `hook` of the repository at `repo`,
+/// the plan root by default.
+///
+/// The hook goes where git looks for it: `.git/hooks`, or the directory
+/// `core.hooksPath` names. The script is marked as managed by skies on the
+/// line after its `#!`, with `#!/bin/sh` added if it has none, and made
+/// executable. A hook that is already there without the mark is left
+/// alone, and the shift fails rather than replace it. Revert removes the
+/// hook if skies manages it.
+///
+/// A `pre-commit` hook that runs `skies hook pre-commit` stops commits
+/// while the repository's plan is not applied.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct GitHook {
+    hook: String,
+    script: String,
+    #[serde(default = "default_repo")]
+    repo: PathBuf,
+}
+
+fn default_repo() -> PathBuf {
+    PathBuf::from(".")
+}
+
+const MARKER: &str = "# Managed by skies.";
+const MODE: u32 = 0o755;
+
+/// The hooks git runs, from githooks(5).
+const HOOKS: &[&str] = &[
+    "applypatch-msg",
+    "pre-applypatch",
+    "post-applypatch",
+    "pre-commit",
+    "pre-merge-commit",
+    "prepare-commit-msg",
+    "commit-msg",
+    "post-commit",
+    "pre-rebase",
+    "post-checkout",
+    "post-merge",
+    "pre-push",
+    "pre-receive",
+    "update",
+    "proc-receive",
+    "post-receive",
+    "post-update",
+    "reference-transaction",
+    "push-to-checkout",
+    "pre-auto-gc",
+    "post-rewrite",
+    "sendemail-validate",
+    "fsmonitor-watchman",
+    "p4-changelist",
+    "p4-prepare-changelist",
+    "p4-post-changelist",
+    "p4-pre-submit",
+    "post-index-change",
+];
+
+impl GitHook {
+    pub fn new(hook: impl Into<String>, script: impl Into<String>) -> Self {
+        GitHook {
+            hook: hook.into(),
+            script: script.into(),
+            repo: default_repo(),
+        }
+    }
+
+    pub fn repo(mut self, repo: impl Into<PathBuf>) -> Self {
+        self.repo = repo.into();
+        self
+    }
+
+    /// The script as installed, with the mark.
+    fn render(&self) -> String {
+        let mut text = match self.script.split_once('\n') {
+            Some((shebang, rest)) if shebang.starts_with("#!") => {
+                format!("{shebang}\n{MARKER}\n{rest}")
+            }
+            None if self.script.starts_with("#!") => format!("{}\n{MARKER}\n", self.script),
+            _ => format!("#!/bin/sh\n{MARKER}\n{}", self.script),
+        };
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Where the hook goes, or `None` while `repo` does not exist.
+    fn path(&self, ctx: &ExecutionContext) -> ShiftResult<Option<PathBuf>> {
+        let repo = ctx.resolve(&self.repo)?;
+        if !ctx.fs().is_dir(&repo) {
+            return Ok(None);
+        }
+        // Relative to `repo`, and honouring `core.hooksPath`.
+        let hooks = Cmd::new("git")
+            .args(["rev-parse", "--git-path", "hooks"])
+            .cwd(&repo)
+            .output(ctx)
+            .map_err(|err| err.context(format!("finding the hooks of {}", repo.display())))?;
+        Ok(Some(
+            paths::normalize(&repo.join(hooks.trim())).join(&self.hook),
+        ))
+    }
+
+    fn managed(text: &str) -> bool {
+        text.lines().take(2).any(|line| line == MARKER)
+    }
+}
+
+impl Shift for GitHook {
+    fn metadata(&self) -> ShiftMetadata {
+        ShiftMetadata::new(
+            "git_hook",
+            format!("install the {} hook in {}", self.hook, self.repo.display()),
+        )
+        .target(&self.repo)
+        .input("script", self.render())
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![Claim::exclusive(Resource::lock(format!(
+            "git_hook:{}:{}",
+            self.repo.display(),
+            self.hook
+        )))]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        if !HOOKS.contains(&self.hook.as_str()) {
+            return Err(
+                ShiftError::Custom(format!("`{}` is not a hook git runs", self.hook))
+                    .hint("see `man githooks` for their names, such as pre-commit or pre-push"),
+            );
+        }
+        ctx.require_program("git")?;
+        ctx.exec().resolve(&self.repo)?;
+        ctx.require_dir(&self.repo)
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::RunsArbitraryCode]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        if self.is_applied(ctx)? {
+            return Ok(ShiftOutcome::Unchanged);
+        }
+        let Some(path) = self.path(ctx)? else {
+            return Err(ShiftError::Custom(format!(
+                "repository {} does not exist",
+                self.repo.display()
+            )));
+        };
+        if read_text(ctx.fs(), &path)?.is_some_and(|text| !Self::managed(&text)) {
+            return Err(ShiftError::Conflict {
+                resource: path.display().to_string(),
+                reason: "already exists and is not managed by skies; move it aside first".into(),
+            });
+        }
+        if let Some(dir) = path.parent() {
+            ctx.fs().create_dir_all(dir)?;
+        }
+        ctx.fs()
+            .write(&path, self.render().as_bytes(), Some(MODE))?;
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let Some(path) = self.path(ctx)? else {
+            return Ok(());
+        };
+        if read_text(ctx.fs(), &path)?.is_some_and(|text| Self::managed(&text)) {
+            ctx.fs().remove_file(&path)?;
+        }
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        let Some(path) = self.path(ctx)? else {
+            return Ok(false);
+        };
+        if read_text(ctx.fs(), &path)?.as_deref() != Some(self.render().as_str()) {
+            return Ok(false);
+        }
+        permissions::mode_matches(ctx.fs(), &path, Some(MODE))
+    }
+}
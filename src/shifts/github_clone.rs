@@ -0,0 +1,544 @@
+use std::path::{Path, PathBuf};
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::artifacts::{self, Artifact};
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::lockfile::Lock;
+use crate::metadata::ShiftMetadata;
+use crate::network;
+use crate::plan::ShiftPlan;
+use crate::resource::{Claim, Resource};
+use crate::shift::{Shift, ShiftOutcome};
+use crate::shifts::{Cmd, CreateDir};
+use crate::validate::ValidationContext;
+
+/// Clones a GitHub repository into a local directory.
+///
+/// `repo` is either `owner/name` or a full git URL. Private repositories
+/// can be cloned over HTTPS by naming an environment variable that holds a
+/// token in `token_env`; the token is handed to git through its environment,
+/// never on the command line.
+///
+/// With `bare` the clone has no working tree, and with `mirror` it is a
+/// bare clone that copies every ref. `reference` names a local repository
+/// to borrow objects from, such as a CI cache, so fewer are fetched.
+/// With `lfs`, Git LFS objects are pulled after cloning, and the clone is
+/// only applied once all of them are present.
+///
+/// Revert removes the clone, but refuses to while a working tree has
+/// uncommitted changes or commits on a local branch that no remote has,
+/// unless the context is [forced](ExecutionContext::with_force).
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct GitHubClone {
+    repo: String,
+    target: PathBuf,
+    #[serde(default)]
+    branch: Option<String>,
+    #[serde(default)]
+    token_env: Option<String>,
+    #[serde(default)]
+    bare: bool,
+    #[serde(default)]
+    mirror: bool,
+    #[serde(default)]
+    reference: Option<PathBuf>,
+    #[serde(default)]
+    lfs: bool,
+}
+
+impl GitHubClone {
+    pub fn new(repo: impl Into<String>, target: impl Into<PathBuf>) -> Self {
+        GitHubClone {
+            repo: repo.into(),
+            target: target.into(),
+            branch: None,
+            token_env: None,
+            bare: false,
+            mirror: false,
+            reference: None,
+            lfs: false,
+        }
+    }
+
+    pub fn branch(mut self, branch: impl Into<String>) -> Self {
+        self.branch = Some(branch.into());
+        self
+    }
+
+    /// Reads an access token from the environment variable `var`.
+    pub fn token_env(mut self, var: impl Into<String>) -> Self {
+        self.token_env = Some(var.into());
+        self
+    }
+
+    /// Clones without a working tree.
+    pub fn bare(mut self, bare: bool) -> Self {
+        self.bare = bare;
+        self
+    }
+
+    /// Clones bare, copying every ref of the remote.
+    pub fn mirror(mut self, mirror: bool) -> Self {
+        self.mirror = mirror;
+        self
+    }
+
+    /// Borrows objects from the local repository at `path`.
+    pub fn reference(mut self, path: impl Into<PathBuf>) -> Self {
+        self.reference = Some(path.into());
+        self
+    }
+
+    /// Pulls Git LFS objects after cloning. Needs a working tree.
+    pub fn lfs(mut self, lfs: bool) -> Self {
+        self.lfs = lfs;
+        self
+    }
+
+    fn is_bare(&self) -> bool {
+        self.bare || self.mirror
+    }
+
+    fn what(&self) -> &'static str {
+        match (self.mirror, self.bare) {
+            (true, _) => "mirror",
+            (false, true) => "bare clone",
+            (false, false) => "clone",
+        }
+    }
+
+    pub fn target(&self) -> &Path {
+        &self.target
+    }
+
+    pub fn url(&self) -> String {
+        if self.repo.contains("://") || self.repo.starts_with("git@") {
+            self.repo.clone()
+        } else {
+            format!("https://github.com/{}.git", self.repo)
+        }
+    }
+
+    /// The primitive shifts a clone is made of: creating the directory the
+    /// clone goes in, then cloning into `target` from there. LFS objects
+    /// are pulled separately, once the clone exists.
+    pub fn build_plan(&self, ctx: &ExecutionContext) -> ShiftPlan {
+        self.clone_plan(ctx, &self.url())
+    }
+
+    /// The commit a fresh clone would be at now: the tip of `branch`, or
+    /// of the remote's default branch.
+    pub fn remote_commit(&self, ctx: &ExecutionContext) -> ShiftResult<String> {
+        let url = self.url();
+        let refs = match &self.branch {
+            Some(branch) => vec![branch.clone(), format!("{branch}^{{}}")],
+            None => vec!["HEAD".to_string()],
+        };
+        let listing = network::transfer(ctx, |_| {
+            self.authenticated(
+                Cmd::new("git")
+                    .arg("ls-remote")
+                    .arg(&url)
+                    .args(refs.clone()),
+            )
+            .output(ctx)
+        })?;
+        let refs: Vec<(&str, &str)> = listing
+            .lines()
+            .filter_map(|line| line.split_once('\t'))
+            .collect();
+        // A tag's own commit rather than the tag, and a branch over a tag
+        // of the same name, as `git clone --branch` does.
+        let found = |suffix: &str| {
+            refs.iter()
+                .find(|(_, name)| name.ends_with(suffix))
+                .map(|(commit, _)| commit.to_string())
+        };
+        let found = match &self.branch {
+            Some(branch) => found(&format!("refs/heads/{branch}"))
+                .or_else(|| found(&format!("refs/tags/{branch}^{{}}")))
+                .or_else(|| found(&format!("refs/tags/{branch}"))),
+            None => found("HEAD"),
+        };
+        found.ok_or_else(|| {
+            ShiftError::Custom(format!(
+                "{url} has no {}",
+                self.branch.as_deref().unwrap_or("HEAD")
+            ))
+        })
+    }
+
+    /// Moves a fresh clone at `path` to the commit `lock` has for it, or
+    /// records the commit it got if there is none.
+    fn pin(&self, ctx: &ExecutionContext, lock: &Lock, path: &Path) -> ShiftResult<()> {
+        let url = self.url();
+        let branch = self.branch.as_deref();
+        let Some(commit) = lock.commit(&url, branch) else {
+            let head = Cmd::new("git")
+                .args(["rev-parse", "HEAD"])
+                .cwd(path)
+                .output(ctx)?;
+            lock.record_commit(&url, branch, head.trim());
+            return Ok(());
+        };
+        let mode = if self.bare { "--soft" } else { "--hard" };
+        Cmd::new("git")
+            .args(["reset", "-q", mode, &commit])
+            .cwd(path)
+            .output(ctx)
+            .map_err(|err| {
+                err.context(format!("checking out locked commit {commit} of {url}"))
+                    .hint("if the commit is gone from the repository, run `skies update-lock`")
+            })?;
+        lock.record_commit(&url, branch, &commit);
+        Ok(())
+    }
+
+    /// [`build_plan`](Self::build_plan), cloning from `source` instead of
+    /// the repository's URL.
+    fn clone_plan(&self, ctx: &ExecutionContext, source: &str) -> ShiftPlan {
+        let mut clone = Cmd::new("git").arg("clone");
+        if self.mirror {
+            clone = clone.arg("--mirror");
+        } else if self.bare {
+            clone = clone.arg("--bare");
+        }
+        if let Some(branch) = &self.branch {
+            clone = clone.args(["--branch", branch]);
+        }
+        if let Some(reference) = &self.reference {
+            let reference = ctx.join_root(reference);
+            clone = clone.args(["--reference".to_string(), reference.display().to_string()]);
+        }
+        let parent = self
+            .target
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty());
+        let mut clone = clone.arg(source);
+        clone = match (parent, self.target.file_name()) {
+            (Some(parent), Some(name)) => clone.arg(name.to_string_lossy()).cwd(parent),
+            _ => clone.arg(self.target.display().to_string()),
+        };
+        let mut plan = ShiftPlan::new();
+        if let Some(parent) = parent {
+            plan.add(CreateDir::new(parent));
+        }
+        plan.add(self.authenticated(clone));
+        plan
+    }
+
+    fn lfs_pull(&self) -> Cmd {
+        self.authenticated(Cmd::new("git").args(["lfs", "pull"]).cwd(&self.target))
+    }
+
+    /// `cmd` with the token from `token_env`, if set, as an HTTP header.
+    fn authenticated(&self, cmd: Cmd) -> Cmd {
+        let Some(token) = self
+            .token_env
+            .as_ref()
+            .and_then(|var| std::env::var(var).ok())
+        else {
+            return cmd;
+        };
+        let credentials = base64(format!("x-access-token:{token}").as_bytes());
+        cmd.env("GIT_CONFIG_COUNT", "1")
+            .env("GIT_CONFIG_KEY_0", "http.extraHeader")
+            .env(
+                "GIT_CONFIG_VALUE_0",
+                format!("Authorization: Basic {credentials}"),
+            )
+    }
+
+    /// Whether `path` is a clone of the right kind (bare, mirror or with a
+    /// working tree) whose `origin` is `repo`.
+    fn cloned(&self, ctx: &ExecutionContext, path: &Path) -> bool {
+        if !is_repository(path) {
+            return false;
+        }
+        let git = |args: &[&str]| {
+            Cmd::new("git")
+                .args(args.iter().copied())
+                .cwd(path)
+                .output(ctx)
+                .map(|out| out.trim().to_string())
+        };
+        let Ok(bare) = git(&["rev-parse", "--is-bare-repository"]) else {
+            return false;
+        };
+        let mirror = git(&["config", "--get", "remote.origin.mirror"]);
+        let origin = git(&["remote", "get-url", "origin"]);
+        (bare == "true") == self.is_bare()
+            && mirror.is_ok_and(|mirror| mirror == "true") == self.mirror
+            && origin.is_ok_and(|origin| same_remote(&origin, &self.url()))
+    }
+
+    /// Whether every LFS object the checkout points to has been downloaded.
+    /// `git lfs ls-files` marks those that are only pointers with `-`.
+    fn lfs_complete(ctx: &ExecutionContext, path: &Path) -> ShiftResult<bool> {
+        let files = Cmd::new("git")
+            .args(["lfs", "ls-files"])
+            .cwd(path)
+            .output(ctx)?;
+        Ok(!files
+            .lines()
+            .any(|line| line.split_whitespace().nth(1) == Some("-")))
+    }
+}
+
+impl Shift for GitHubClone {
+    fn metadata(&self) -> ShiftMetadata {
+        let meta = ShiftMetadata::new(
+            "github_clone",
+            format!(
+                "{} {} into {}",
+                self.what(),
+                self.repo,
+                self.target.display()
+            ),
+        )
+        .target(&self.target)
+        .input("url", self.url())
+        .input("bare", self.bare)
+        .input("mirror", self.mirror)
+        .input("lfs", self.lfs);
+        let meta = match &self.reference {
+            Some(reference) => meta.input("reference", reference),
+            None => meta,
+        };
+        let meta = match &self.branch {
+            Some(branch) => meta.input("branch", branch),
+            None => meta,
+        };
+        match &self.token_env {
+            Some(var) => meta.input("token_env", var),
+            None => meta,
+        }
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![Claim::exclusive(Resource::path(&self.target))]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        check_repo(&self.repo).map_err(ShiftError::Custom)?;
+        ctx.require_program("git")?;
+        if let Some(var) = &self.token_env {
+            ctx.require_env(var)?;
+        }
+        if self.lfs {
+            if self.is_bare() {
+                return Err(ShiftError::Custom(
+                    "`lfs` needs a working tree; it cannot be combined with `bare` or `mirror`"
+                        .into(),
+                ));
+            }
+            ctx.require_program("git-lfs")?;
+        }
+        let target = ctx.exec().resolve(&self.target)?;
+        if target.exists() && !target.is_dir() {
+            return Err(ShiftError::Conflict {
+                resource: target.display().to_string(),
+                reason: "exists and is not a directory".into(),
+            });
+        }
+        Ok(())
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let path = ctx.resolve(&self.target)?;
+        if self.lfs && ctx.is_offline() {
+            return Err(ShiftError::Custom(
+                "Git LFS objects are not cached, so `lfs` clones need the network".into(),
+            ));
+        }
+        let outcome = if !is_repository(&path) {
+            let outcome = match artifacts::git_source(ctx, &self.url())? {
+                Some(bundle) => {
+                    let outcome = self
+                        .clone_plan(ctx, &bundle.display().to_string())
+                        .apply(ctx)?;
+                    Cmd::new("git")
+                        .args(["remote", "set-url", "origin", &self.url()])
+                        .cwd(&path)
+                        .output(ctx)?;
+                    outcome
+                }
+                None => network::transfer(ctx, |_| self.build_plan(ctx).apply(ctx))?,
+            };
+            if let Some(lock) = ctx.lock().filter(|_| !self.mirror) {
+                self.pin(ctx, lock, &path)?;
+            }
+            if self.lfs {
+                network::transfer(ctx, |_| self.lfs_pull().apply(ctx))?;
+            }
+            outcome
+        } else if !self.cloned(ctx, &path) {
+            return Err(ShiftError::Conflict {
+                resource: path.display().to_string(),
+                reason: format!(
+                    "already holds a repository that is not a {} of {}",
+                    self.what(),
+                    self.url()
+                ),
+            });
+        } else if self.lfs && !Self::lfs_complete(ctx, &path)? {
+            network::transfer(ctx, |_| self.lfs_pull().apply(ctx))?
+        } else {
+            ShiftOutcome::Unchanged
+        };
+        if let Ok(commit) = Cmd::new("git")
+            .args(["rev-parse", "HEAD"])
+            .cwd(&path)
+            .output(ctx)
+        {
+            ctx.output("commit", commit.trim());
+        }
+        ctx.output("path", path);
+        Ok(outcome)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let path = ctx.resolve(&self.target)?;
+        // A bare clone has nothing uncommitted, and no remote-tracking refs
+        // to tell pushed commits by.
+        if !self.is_bare() && path.join(".git").exists() && !ctx.is_forced() {
+            if let Some(reason) = local_work(ctx, &path)? {
+                return Err(ShiftError::Conflict {
+                    resource: path.display().to_string(),
+                    reason,
+                }
+                .hint(
+                    "push or discard the work first, or pass --force to remove the clone anyway",
+                ));
+            }
+        }
+        // Only the clone itself: its parent may have been there before.
+        CreateDir::new(&self.target).revert(ctx)
+    }
+
+    /// Applied once `target` is a clone of the right kind (bare, mirror or
+    /// with a working tree) whose `origin` is `repo`, and with `lfs` all
+    /// its LFS objects are present.
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        let path = ctx.resolve(&self.target)?;
+        if !self.cloned(ctx, &path) {
+            return Ok(false);
+        }
+        if self.lfs {
+            return Self::lfs_complete(ctx, &path);
+        }
+        Ok(true)
+    }
+
+    fn artifacts(&self, _ctx: &ExecutionContext) -> ShiftResult<Vec<Artifact>> {
+        Ok(vec![Artifact::Git {
+            url: self.url(),
+            branch: self.branch.clone(),
+            token_env: self.token_env.clone(),
+        }])
+    }
+}
+
+/// Whether `path` holds a repository, with a working tree or bare.
+fn is_repository(path: &Path) -> bool {
+    path.join(".git").exists() || (path.join("HEAD").is_file() && path.join("objects").is_dir())
+}
+
+/// Whether two remote URLs name the same repository, ignoring a trailing
+/// slash or `.git`.
+fn same_remote(a: &str, b: &str) -> bool {
+    let bare = |url: &str| {
+        let url = url.trim_end_matches('/');
+        url.strip_suffix(".git").unwrap_or(url).to_string()
+    };
+    bare(a) == bare(b)
+}
+
+/// Why removing the clone at `path` would lose work, if it would.
+fn local_work(ctx: &ExecutionContext, path: &Path) -> ShiftResult<Option<String>> {
+    let git = |args: &[&str]| {
+        Cmd::new("git")
+            .args(args.iter().copied())
+            .cwd(path)
+            .output(ctx)
+    };
+    let changed = git(&["status", "--porcelain"])?.lines().count();
+    if changed > 0 {
+        return Ok(Some(format!(
+            "has uncommitted changes to {changed} path(s)"
+        )));
+    }
+    let unpushed = git(&["log", "--oneline", "--branches", "--not", "--remotes"])?
+        .lines()
+        .count();
+    if unpushed > 0 {
+        return Ok(Some(format!("has {unpushed} unpushed commit(s)")));
+    }
+    Ok(None)
+}
+
+/// Accepts `owner/name`, `scheme://host/path` and scp-style `user@host:path`.
+fn check_repo(repo: &str) -> Result<(), String> {
+    let bad = |why: &str| Err(format!("`{repo}` is not a valid repository: {why}"));
+    if let Some((scheme, rest)) = repo.split_once("://") {
+        if !matches!(scheme, "https" | "http" | "ssh" | "git" | "file") {
+            return bad("unsupported URL scheme");
+        }
+        if scheme == "file" {
+            return Ok(());
+        }
+        let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+        if host.is_empty() || host.contains(char::is_whitespace) {
+            return bad("missing host");
+        }
+        if path.trim_matches('/').is_empty() {
+            return bad("missing repository path");
+        }
+        return Ok(());
+    }
+    if let Some((host, path)) = repo.split_once(':') {
+        if host.contains('@') && !path.is_empty() {
+            return Ok(());
+        }
+        return bad("expected user@host:path");
+    }
+    let valid_segment = |s: &str| {
+        !s.is_empty()
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    };
+    match repo.split_once('/') {
+        Some((owner, name)) if valid_segment(owner) && valid_segment(name) => Ok(()),
+        _ => bad("expected owner/name or a git URL"),
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(TABLE[(n >> (18 - 6 * i)) as usize & 63] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
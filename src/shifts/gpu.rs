@@ -0,0 +1,278 @@
+//! GPU compute toolkits: CUDA on NVIDIA GPUs, ROCm on AMD ones.
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::artifacts::Artifact;
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::metadata::ShiftMetadata;
+use crate::packages::PackageManager;
+use crate::resource::{Claim, Resource};
+use crate::shift::{Shift, ShiftOutcome};
+use crate::validate::ValidationContext;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ComputeToolkit {
+    /// CUDA with an NVIDIA GPU, ROCm with an AMD one, else nothing.
+    #[default]
+    Auto,
+    Cuda,
+    Rocm,
+}
+
+impl ComputeToolkit {
+    fn name(self) -> &'static str {
+        match self {
+            ComputeToolkit::Auto => "auto",
+            ComputeToolkit::Cuda => "cuda",
+            ComputeToolkit::Rocm => "rocm",
+        }
+    }
+
+    /// Where the toolkit installs itself.
+    fn home(self) -> &'static str {
+        match self {
+            ComputeToolkit::Rocm => "/opt/rocm",
+            _ => "/usr/local/cuda",
+        }
+    }
+}
+
+/// Installs the CUDA or ROCm toolkit, and optionally the GPU driver.
+///
+/// With `toolkit = "auto"`, the default, the `gpu` fact picks: CUDA for an
+/// NVIDIA GPU, ROCm for an AMD one, and on a machine with neither the
+/// shift does nothing, so one plan serves workstations and laptops alike.
+/// Packages come from the vendor repositories (NVIDIA's CUDA repository,
+/// AMD's ROCm repository), which must already be set up; `version` picks
+/// a CUDA release such as `12.4`, and `driver` adds the driver packages.
+/// On Arch the distribution's `cuda` and `rocm-hip-sdk` are used instead.
+/// `packages` replaces the package list entirely.
+///
+/// Records the toolkit chosen as the output `toolkit` and its install
+/// directory as `home`. Applied while every package is installed. Revert
+/// removes the packages this shift installed.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct GpuToolkit {
+    #[serde(default)]
+    toolkit: ComputeToolkit,
+    #[serde(default)]
+    version: Option<String>,
+    #[serde(default)]
+    driver: bool,
+    #[serde(default)]
+    packages: Vec<String>,
+}
+
+impl GpuToolkit {
+    pub fn new(toolkit: ComputeToolkit) -> Self {
+        GpuToolkit {
+            toolkit,
+            version: None,
+            driver: false,
+            packages: Vec::new(),
+        }
+    }
+
+    /// The CUDA release to install, `12.4`.
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    pub fn driver(mut self, on: bool) -> Self {
+        self.driver = on;
+        self
+    }
+
+    /// Installs `package` instead of the default packages.
+    pub fn package(mut self, package: impl Into<String>) -> Self {
+        self.packages.push(package.into());
+        self
+    }
+
+    /// The toolkit for this machine, if any.
+    fn resolved(&self, ctx: &ExecutionContext) -> Option<ComputeToolkit> {
+        match self.toolkit {
+            ComputeToolkit::Auto => match ctx.facts().get("gpu") {
+                Some("nvidia") => Some(ComputeToolkit::Cuda),
+                Some("amd") => Some(ComputeToolkit::Rocm),
+                _ => None,
+            },
+            toolkit => Some(toolkit),
+        }
+    }
+
+    fn package_list(
+        &self,
+        toolkit: ComputeToolkit,
+        manager: PackageManager,
+    ) -> ShiftResult<Vec<String>> {
+        if !self.packages.is_empty() {
+            return Ok(self.packages.clone());
+        }
+        if matches!(manager, PackageManager::Apk | PackageManager::Brew) {
+            return Err(ShiftError::Custom(format!(
+                "no {} packages for {}; set `packages` to install others",
+                toolkit.name(),
+                manager.program()
+            )));
+        }
+        let arch = manager == PackageManager::Pacman;
+        let mut packages = match (toolkit, arch) {
+            (ComputeToolkit::Cuda, true) => vec!["cuda".to_string()],
+            (ComputeToolkit::Cuda, false) => vec![match &self.version {
+                Some(version) => format!("cuda-toolkit-{}", version.replace('.', "-")),
+                None => "cuda-toolkit".to_string(),
+            }],
+            (ComputeToolkit::Rocm, true) => vec!["rocm-hip-sdk".to_string()],
+            (ComputeToolkit::Rocm, false) => vec!["rocm".to_string()],
+            (ComputeToolkit::Auto, _) => Vec::new(),
+        };
+        if self.driver {
+            match (toolkit, arch) {
+                (ComputeToolkit::Cuda, true) => packages.push("nvidia".into()),
+                (ComputeToolkit::Cuda, false) => packages.push("cuda-drivers".into()),
+                // Arch's kernel already has amdgpu.
+                (ComputeToolkit::Rocm, true) => {}
+                (ComputeToolkit::Rocm, false) => packages.push("amdgpu-dkms".into()),
+                (ComputeToolkit::Auto, _) => {}
+            }
+        }
+        Ok(packages)
+    }
+
+    fn missing(
+        &self,
+        ctx: &ExecutionContext,
+        manager: PackageManager,
+        packages: &[String],
+    ) -> ShiftResult<Vec<String>> {
+        let mut missing = Vec::new();
+        for package in packages {
+            if !manager.is_installed(ctx, package)? {
+                missing.push(package.clone());
+            }
+        }
+        Ok(missing)
+    }
+}
+
+impl Shift for GpuToolkit {
+    fn metadata(&self) -> ShiftMetadata {
+        let summary = match (self.toolkit, &self.version) {
+            (ComputeToolkit::Auto, _) => "install the GPU compute toolkit".to_string(),
+            (toolkit, Some(version)) => format!("install {} {version}", toolkit.name()),
+            (toolkit, None) => format!("install {}", toolkit.name()),
+        };
+        let mut meta = ShiftMetadata::new("gpu_toolkit", summary)
+            .input("toolkit", self.toolkit.name())
+            .input("driver", self.driver);
+        if let Some(version) = &self.version {
+            meta = meta.input("version", version);
+        }
+        if !self.packages.is_empty() {
+            meta = meta.input("packages", &self.packages);
+        }
+        meta
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![Claim::exclusive(Resource::lock("packages"))]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        if let Some(version) = &self.version {
+            let numeric = !version.is_empty()
+                && version
+                    .split('.')
+                    .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()));
+            if !numeric {
+                return Err(ShiftError::Custom(format!(
+                    "`{version}` is not a CUDA version such as `12.4`"
+                )));
+            }
+            if self.toolkit == ComputeToolkit::Rocm {
+                return Err(ShiftError::Custom(
+                    "`version` picks a CUDA release; for ROCm, point AMD's repository at the \
+                     release instead"
+                        .into(),
+                ));
+            }
+        }
+        if self.resolved(ctx.exec()).is_some() {
+            PackageManager::require()?;
+        }
+        Ok(())
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network, Capability::Sudo]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let Some(toolkit) = self.resolved(ctx) else {
+            return Ok(ShiftOutcome::Unchanged);
+        };
+        ctx.output("toolkit", toolkit.name());
+        ctx.output("home", toolkit.home());
+        let manager = PackageManager::require()?;
+        let packages = self.package_list(toolkit, manager)?;
+        let missing = self.missing(ctx, manager, &packages)?;
+        if missing.is_empty() {
+            return Ok(ShiftOutcome::Unchanged);
+        }
+        let names: Vec<&str> = missing.iter().map(String::as_str).collect();
+        manager.install(ctx, &names).map_err(|err| {
+            let hint = match toolkit {
+                ComputeToolkit::Rocm => "add AMD's ROCm repository (https://rocm.docs.amd.com)",
+                _ => "add NVIDIA's CUDA repository (https://developer.nvidia.com/cuda-downloads)",
+            };
+            err.context(format!("installing {}", toolkit.name()))
+                .hint(format!("{hint} or set `packages`"))
+        })?;
+        let mut installed: Vec<Value> = match ctx.get_state("installed") {
+            Some(Value::Array(installed)) => installed,
+            _ => Vec::new(),
+        };
+        installed.extend(missing.into_iter().map(Value::String));
+        ctx.set_state("installed", json!(installed));
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        if let Some(Value::Array(installed)) = ctx.get_state("installed") {
+            let names: Vec<&str> = installed.iter().filter_map(Value::as_str).collect();
+            if !names.is_empty() {
+                PackageManager::require()?.remove(ctx, &names)?;
+            }
+        }
+        ctx.clear_state("installed");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        let Some(toolkit) = self.resolved(ctx) else {
+            return Ok(true);
+        };
+        let Some(manager) = PackageManager::detect() else {
+            return Ok(false);
+        };
+        let packages = self.package_list(toolkit, manager)?;
+        Ok(self.missing(ctx, manager, &packages)?.is_empty())
+    }
+
+    fn artifacts(&self, ctx: &ExecutionContext) -> ShiftResult<Vec<Artifact>> {
+        match (self.resolved(ctx), PackageManager::detect()) {
+            (Some(toolkit), Some(manager)) => Ok(vec![Artifact::Packages(
+                self.package_list(toolkit, manager)?,
+            )]),
+            _ => Ok(Vec::new()),
+        }
+    }
+}
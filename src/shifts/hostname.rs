@@ -0,0 +1,212 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::metadata::ShiftMetadata;
+use crate::permissions;
+use crate::resource::{Claim, Resource};
+use crate::shift::{Shift, ShiftOutcome};
+use crate::shifts::Cmd;
+use crate::validate::{find_program, ValidationContext};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HostnameBackend {
+    /// systemd's `hostnamectl set-hostname`.
+    Hostnamectl,
+    /// Writes `/etc/hostname` and sets the running name with `hostname`.
+    File,
+    /// macOS's `scutil --set`, for `HostName` and `LocalHostName`.
+    Scutil,
+}
+
+const HOSTNAME_FILE: &str = "/etc/hostname";
+
+/// Sets the machine's hostname, both the persistent one read at boot and
+/// the transient one of the running system.
+///
+/// The backend is `scutil` on macOS, `hostnamectl` where systemd is
+/// running, and `/etc/hostname` otherwise. `/etc/hosts` is left alone.
+/// Applied while both names match. The persistent name before the first
+/// apply is kept in the shift's state and restored by revert. The
+/// `hostname` fact is read when the plan loads, so it only reflects the
+/// new name on later runs.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Hostname {
+    name: String,
+    #[serde(default)]
+    backend: Option<HostnameBackend>,
+}
+
+impl Hostname {
+    pub fn new(name: impl Into<String>) -> Self {
+        Hostname {
+            name: name.into(),
+            backend: None,
+        }
+    }
+
+    pub fn backend(mut self, backend: HostnameBackend) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    fn backend_or_detect(&self) -> HostnameBackend {
+        self.backend.unwrap_or_else(|| {
+            if find_program("scutil").is_some() {
+                HostnameBackend::Scutil
+            } else if find_program("hostnamectl").is_some()
+                && Path::new("/run/systemd/system").is_dir()
+            {
+                HostnameBackend::Hostnamectl
+            } else {
+                HostnameBackend::File
+            }
+        })
+    }
+
+    /// The name the system keeps across reboots, if it has one.
+    fn persistent(&self, ctx: &ExecutionContext) -> ShiftResult<Option<String>> {
+        let name = match self.backend_or_detect() {
+            HostnameBackend::Hostnamectl => Cmd::new("hostnamectl").arg("--static").output(ctx)?,
+            HostnameBackend::File => {
+                match fs::read_to_string(ctx.resolve(Path::new(HOSTNAME_FILE))?) {
+                    Ok(text) => text,
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+                    Err(err) => return Err(err.into()),
+                }
+            }
+            HostnameBackend::Scutil => {
+                match Cmd::new("scutil").args(["--get", "HostName"]).output(ctx) {
+                    Ok(name) => name,
+                    // Unset: `scutil --get` fails.
+                    Err(ShiftError::Command { .. }) => String::new(),
+                    Err(err) => return Err(err),
+                }
+            }
+        };
+        let name = name.trim();
+        Ok((!name.is_empty()).then(|| name.to_string()))
+    }
+
+    /// The name of the running system.
+    fn transient(ctx: &ExecutionContext) -> ShiftResult<String> {
+        Ok(Cmd::new("hostname").output(ctx)?.trim().to_string())
+    }
+
+    fn set(&self, ctx: &ExecutionContext, name: &str) -> ShiftResult<()> {
+        match self.backend_or_detect() {
+            HostnameBackend::Hostnamectl => {
+                Cmd::new("hostnamectl")
+                    .args(["set-hostname", name])
+                    .output(ctx)?;
+            }
+            HostnameBackend::File => {
+                let file = ctx.resolve(Path::new(HOSTNAME_FILE))?;
+                let mode = match file.exists() {
+                    true => permissions::mode_of(&file)?,
+                    false => ctx.permissions().file_mode(Some(0o644)),
+                };
+                permissions::write_file(&file, format!("{name}\n").as_bytes(), mode)?;
+                Cmd::new("hostname").arg(name).output(ctx)?;
+            }
+            HostnameBackend::Scutil => {
+                // LocalHostName is the Bonjour name, which cannot hold dots.
+                let local = name.split('.').next().unwrap_or(name);
+                Cmd::new("scutil")
+                    .args(["--set", "HostName", name])
+                    .output(ctx)?;
+                Cmd::new("scutil")
+                    .args(["--set", "LocalHostName", local])
+                    .output(ctx)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether `name` is a valid hostname under RFC 1123.
+fn valid_hostname(name: &str) -> bool {
+    name.len() <= 253
+        && name.split('.').all(|label| {
+            (1..=63).contains(&label.len())
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+impl Shift for Hostname {
+    fn metadata(&self) -> ShiftMetadata {
+        let meta = ShiftMetadata::new("hostname", format!("set the hostname to {}", self.name))
+            .input("name", &self.name);
+        if self.backend_or_detect() == HostnameBackend::File {
+            meta.target(HOSTNAME_FILE)
+        } else {
+            meta
+        }
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![Claim::exclusive(Resource::lock("hostname"))]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        if !valid_hostname(&self.name) {
+            return Err(ShiftError::Custom(format!(
+                "`{}` is not a valid hostname",
+                self.name
+            )));
+        }
+        ctx.require_program("hostname")?;
+        match self.backend_or_detect() {
+            HostnameBackend::Hostnamectl => ctx.require_program("hostnamectl"),
+            HostnameBackend::File => ctx.exec().resolve(Path::new(HOSTNAME_FILE)).map(drop),
+            HostnameBackend::Scutil => ctx.require_program("scutil"),
+        }
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Sudo]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        if self.is_applied(ctx)? {
+            return Ok(ShiftOutcome::Unchanged);
+        }
+        if ctx.get_state("previous").is_none() {
+            let previous = match self.persistent(ctx)? {
+                Some(name) => name,
+                None => Self::transient(ctx)?,
+            };
+            ctx.set_state("previous", json!(previous));
+        }
+        self.set(ctx, &self.name)?;
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        if let Some(previous) = ctx
+            .get_state("previous")
+            .and_then(|value| value.as_str().map(String::from))
+            .filter(|name| !name.is_empty())
+        {
+            self.set(ctx, &previous)?;
+        }
+        ctx.clear_state("previous");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        Ok(self.persistent(ctx)?.as_deref() == Some(self.name.as_str())
+            && Self::transient(ctx)? == self.name)
+    }
+}
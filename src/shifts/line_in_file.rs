@@ -0,0 +1,179 @@
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::metadata::ShiftMetadata;
+use crate::resource::{Claim, Resource};
+use crate::shift::{Shift, ShiftOutcome};
+use crate::validate::ValidationContext;
+
+/// Makes sure a file has a line, leaving the rest of it alone.
+///
+/// With `matches`, a regex, the last line it matches is replaced by
+/// `line`; otherwise, or if none does, `line` is added at the end. The
+/// file must exist unless `create` is set. Applied while the file has
+/// `line`. Revert puts back the line that was replaced, or removes the one
+/// that was added, and removes the file if this shift created it and
+/// nothing else has been written to it since.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct LineInFile {
+    path: PathBuf,
+    line: String,
+    #[serde(default)]
+    matches: Option<String>,
+    #[serde(default)]
+    create: bool,
+}
+
+impl LineInFile {
+    pub fn new(path: impl Into<PathBuf>, line: impl Into<String>) -> Self {
+        LineInFile {
+            path: path.into(),
+            line: line.into(),
+            matches: None,
+            create: false,
+        }
+    }
+
+    /// Replaces the last line matching `pattern` rather than adding one.
+    pub fn matches(mut self, pattern: impl Into<String>) -> Self {
+        self.matches = Some(pattern.into());
+        self
+    }
+
+    /// Creates the file if it is missing.
+    pub fn create(mut self) -> Self {
+        self.create = true;
+        self
+    }
+
+    fn regex(&self) -> ShiftResult<Option<Regex>> {
+        self.matches
+            .as_deref()
+            .map(|pattern| {
+                Regex::new(pattern)
+                    .map_err(|err| ShiftError::Custom(format!("invalid regex `{pattern}`: {err}")))
+            })
+            .transpose()
+    }
+
+    /// The file's lines, or none if it does not exist.
+    fn lines(ctx: &ExecutionContext, path: &Path) -> ShiftResult<Option<Vec<String>>> {
+        if !ctx.fs().exists(path) {
+            return Ok(None);
+        }
+        let text = String::from_utf8(ctx.fs().read(path)?)
+            .map_err(|_| ShiftError::Custom(format!("{} is not text", path.display())))?;
+        Ok(Some(text.lines().map(String::from).collect()))
+    }
+
+    fn write(ctx: &ExecutionContext, path: &Path, lines: &[String]) -> ShiftResult<()> {
+        let mode = match ctx.fs().exists(path) {
+            true => ctx.fs().mode(path)?,
+            false => ctx.permissions().file_mode(None),
+        };
+        let text: String = lines.iter().map(|line| format!("{line}\n")).collect();
+        ctx.fs().write(path, text.as_bytes(), mode)
+    }
+}
+
+impl Shift for LineInFile {
+    fn metadata(&self) -> ShiftMetadata {
+        let meta = ShiftMetadata::new(
+            "line_in_file",
+            format!("set a line in {}", self.path.display()),
+        )
+        .target(&self.path)
+        .input("line", &self.line);
+        match &self.matches {
+            Some(pattern) => meta.input("matches", pattern),
+            None => meta,
+        }
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![Claim::exclusive(Resource::path(&self.path))]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        if self.line.contains('\n') {
+            return Err(ShiftError::Custom("`line` cannot span lines".into()));
+        }
+        self.regex()?;
+        ctx.exec().resolve(&self.path)?;
+        if self.create {
+            ctx.require_parent(&self.path)
+        } else if ctx.will_exist(&self.path) {
+            Ok(())
+        } else {
+            let err = ShiftError::Custom(format!(
+                "{} does not exist and no earlier shift creates it",
+                self.path.display()
+            ));
+            Err(err.hint("set `create` to create it"))
+        }
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let path = ctx.resolve(&self.path)?;
+        let existing = Self::lines(ctx, &path)?;
+        if existing.is_none() && !self.create {
+            return Err(ShiftError::Custom(format!(
+                "{} does not exist",
+                path.display()
+            )));
+        }
+        if existing.is_none() {
+            ctx.set_state("created", json!(true));
+        }
+        let mut lines = existing.unwrap_or_default();
+        if lines.contains(&self.line) {
+            return Ok(ShiftOutcome::Unchanged);
+        }
+        let regex = self.regex()?;
+        let matched = regex.and_then(|regex| lines.iter().rposition(|line| regex.is_match(line)));
+        match matched {
+            Some(idx) => {
+                let replaced = std::mem::replace(&mut lines[idx], self.line.clone());
+                ctx.set_state("replaced", json!(replaced));
+            }
+            None => lines.push(self.line.clone()),
+        }
+        Self::write(ctx, &path, &lines)?;
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let path = ctx.resolve(&self.path)?;
+        if let Some(mut lines) = Self::lines(ctx, &path)? {
+            if let Some(idx) = lines.iter().rposition(|line| line == &self.line) {
+                match ctx.get_state("replaced") {
+                    Some(Value::String(replaced)) => lines[idx] = replaced,
+                    _ => {
+                        lines.remove(idx);
+                    }
+                }
+            }
+            let created = ctx.get_state("created") == Some(json!(true));
+            if created && lines.iter().all(|line| line.trim().is_empty()) {
+                ctx.fs().remove_file(&path)?;
+            } else {
+                Self::write(ctx, &path, &lines)?;
+            }
+        }
+        ctx.clear_state("replaced");
+        ctx.clear_state("created");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        let path = ctx.resolve(&self.path)?;
+        Ok(Self::lines(ctx, &path)?.is_some_and(|lines| lines.contains(&self.line)))
+    }
+}
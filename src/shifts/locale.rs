@@ -0,0 +1,479 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::metadata::ShiftMetadata;
+use crate::permissions;
+use crate::resource::{Claim, Resource};
+use crate::shift::{Shift, ShiftOutcome};
+use crate::shifts::Cmd;
+use crate::validate::{find_program, ValidationContext};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TimezoneBackend {
+    /// systemd's `timedatectl set-timezone`.
+    Timedatectl,
+    /// Points `/etc/localtime` into `/usr/share/zoneinfo`, for systems
+    /// without a running systemd, like containers.
+    Localtime,
+    /// macOS's `systemsetup -settimezone`.
+    Systemsetup,
+}
+
+const LOCALTIME: &str = "/etc/localtime";
+const ZONEINFO: &str = "/usr/share/zoneinfo";
+
+/// Whether systemd is the running init, so its `*ctl` tools work.
+fn systemd_running() -> bool {
+    Path::new("/run/systemd/system").is_dir()
+}
+
+/// Sets the system time zone, e.g. `Europe/Berlin` or `UTC`.
+///
+/// The backend is `systemsetup` on macOS, `timedatectl` where systemd is
+/// running, and the `/etc/localtime` link otherwise. Applied while the
+/// backend reports the zone, so a zone changed by hand counts as drift.
+/// The zone before the first apply is kept in the shift's state and
+/// restored by revert.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Timezone {
+    name: String,
+    #[serde(default)]
+    backend: Option<TimezoneBackend>,
+}
+
+impl Timezone {
+    pub fn new(name: impl Into<String>) -> Self {
+        Timezone {
+            name: name.into(),
+            backend: None,
+        }
+    }
+
+    pub fn backend(mut self, backend: TimezoneBackend) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    fn backend_or_detect(&self) -> TimezoneBackend {
+        self.backend.unwrap_or_else(|| {
+            if find_program("systemsetup").is_some() {
+                TimezoneBackend::Systemsetup
+            } else if find_program("timedatectl").is_some() && systemd_running() {
+                TimezoneBackend::Timedatectl
+            } else {
+                TimezoneBackend::Localtime
+            }
+        })
+    }
+
+    /// The current zone, if one is set.
+    fn current(&self, ctx: &ExecutionContext) -> ShiftResult<Option<String>> {
+        let zone = match self.backend_or_detect() {
+            TimezoneBackend::Timedatectl => Cmd::new("timedatectl")
+                .args(["show", "-p", "Timezone", "--value"])
+                .output(ctx)?,
+            TimezoneBackend::Localtime => match fs::read_link(ctx.resolve(Path::new(LOCALTIME))?) {
+                Ok(target) => target
+                    .to_string_lossy()
+                    .split_once("zoneinfo/")
+                    .map(|(_, zone)| zone.to_string())
+                    .unwrap_or_default(),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+                // A copied zone file rather than a link names no zone.
+                Err(err) if err.kind() == io::ErrorKind::InvalidInput => String::new(),
+                Err(err) => return Err(err.into()),
+            },
+            TimezoneBackend::Systemsetup => {
+                let out = Cmd::new("systemsetup").arg("-gettimezone").output(ctx)?;
+                out.split_once(':')
+                    .map(|(_, zone)| zone.to_string())
+                    .unwrap_or_default()
+            }
+        };
+        let zone = zone.trim();
+        Ok((!zone.is_empty()).then(|| zone.to_string()))
+    }
+
+    fn set(&self, ctx: &ExecutionContext, zone: &str) -> ShiftResult<()> {
+        match self.backend_or_detect() {
+            TimezoneBackend::Timedatectl => {
+                Cmd::new("timedatectl")
+                    .args(["set-timezone", zone])
+                    .output(ctx)?;
+            }
+            TimezoneBackend::Localtime => {
+                let link = ctx.resolve(Path::new(LOCALTIME))?;
+                if link.symlink_metadata().is_ok() {
+                    fs::remove_file(&link)?;
+                }
+                #[cfg(unix)]
+                std::os::unix::fs::symlink(Path::new(ZONEINFO).join(zone), &link)?;
+                // Debian-based systems also read the zone from here.
+                let timezone = ctx.resolve(Path::new("/etc/timezone"))?;
+                if timezone.exists() {
+                    let mode = permissions::mode_of(&timezone)?;
+                    permissions::write_file(&timezone, format!("{zone}\n").as_bytes(), mode)?;
+                }
+            }
+            TimezoneBackend::Systemsetup => {
+                Cmd::new("systemsetup")
+                    .args(["-settimezone", zone])
+                    .output(ctx)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Shift for Timezone {
+    fn metadata(&self) -> ShiftMetadata {
+        ShiftMetadata::new("timezone", format!("set the time zone to {}", self.name))
+            .input("name", &self.name)
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![Claim::exclusive(Resource::lock("timezone"))]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        let valid = !self.name.is_empty()
+            && !self.name.starts_with('/')
+            && self
+                .name
+                .split('/')
+                .all(|part| !part.is_empty() && part != "..");
+        if !valid {
+            return Err(ShiftError::Custom(format!(
+                "`{}` is not a time zone name",
+                self.name
+            )));
+        }
+        let backend = self.backend_or_detect();
+        if backend != TimezoneBackend::Systemsetup
+            && Path::new(ZONEINFO).is_dir()
+            && !Path::new(ZONEINFO).join(&self.name).is_file()
+        {
+            return Err(ShiftError::Custom(format!(
+                "unknown time zone `{}`: not in {ZONEINFO}",
+                self.name
+            )));
+        }
+        match backend {
+            TimezoneBackend::Timedatectl => ctx.require_program("timedatectl"),
+            TimezoneBackend::Localtime if !cfg!(unix) => Err(ShiftError::Custom(
+                "the localtime backend is only supported on Unix".into(),
+            )),
+            TimezoneBackend::Localtime => ctx.exec().resolve(Path::new(LOCALTIME)).map(drop),
+            TimezoneBackend::Systemsetup => ctx.require_program("systemsetup"),
+        }
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Sudo]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let current = self.current(ctx)?;
+        if current.as_deref() == Some(self.name.as_str()) {
+            return Ok(ShiftOutcome::Unchanged);
+        }
+        if ctx.get_state("previous").is_none() {
+            ctx.set_state("previous", json!(current));
+        }
+        self.set(ctx, &self.name)?;
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        if let Some(previous) = ctx
+            .get_state("previous")
+            .and_then(|value| value.as_str().map(String::from))
+        {
+            self.set(ctx, &previous)?;
+        }
+        ctx.clear_state("previous");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        Ok(self.current(ctx)?.as_deref() == Some(self.name.as_str()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LocaleBackend {
+    /// systemd's `localectl set-locale`.
+    Localectl,
+    /// The `LANG=` line of a locale file, `/etc/locale.conf` or Debian's
+    /// `/etc/default/locale`.
+    File,
+    /// macOS's `AppleLocale` user default.
+    Defaults,
+}
+
+/// Sets the system locale's `LANG`, e.g. `en_US.UTF-8`.
+///
+/// The backend is `defaults` on macOS, `localectl` where systemd is
+/// running, and a locale file otherwise: `file` if set, else
+/// `/etc/default/locale` where it exists, else `/etc/locale.conf`. On
+/// Linux, a locale missing from `locale -a` is first built with
+/// `locale-gen`, which revert leaves in place. Applied while the backend
+/// reports the locale; the `LANG` before the first apply is kept in the
+/// shift's state and restored by revert.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Locale {
+    lang: String,
+    #[serde(default)]
+    backend: Option<LocaleBackend>,
+    #[serde(default)]
+    file: Option<PathBuf>,
+}
+
+impl Locale {
+    pub fn new(lang: impl Into<String>) -> Self {
+        Locale {
+            lang: lang.into(),
+            backend: None,
+            file: None,
+        }
+    }
+
+    pub fn backend(mut self, backend: LocaleBackend) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    /// The locale file for the `file` backend.
+    pub fn file(mut self, file: impl Into<PathBuf>) -> Self {
+        self.file = Some(file.into());
+        self
+    }
+
+    fn backend_or_detect(&self) -> LocaleBackend {
+        self.backend.unwrap_or_else(|| {
+            if self.file.is_some() {
+                LocaleBackend::File
+            } else if find_program("defaults").is_some() && find_program("systemsetup").is_some() {
+                LocaleBackend::Defaults
+            } else if find_program("localectl").is_some() && systemd_running() {
+                LocaleBackend::Localectl
+            } else {
+                LocaleBackend::File
+            }
+        })
+    }
+
+    fn locale_file(&self) -> PathBuf {
+        match &self.file {
+            Some(file) => file.clone(),
+            None if Path::new("/etc/default/locale").exists() => "/etc/default/locale".into(),
+            None => "/etc/locale.conf".into(),
+        }
+    }
+
+    /// The lang as macOS spells it: `en_US`, without a codeset.
+    fn apple_locale(lang: &str) -> &str {
+        lang.split('.').next().unwrap_or(lang)
+    }
+
+    /// The wanted value in the backend's own terms.
+    fn wanted(&self) -> &str {
+        match self.backend_or_detect() {
+            LocaleBackend::Defaults => Self::apple_locale(&self.lang),
+            _ => &self.lang,
+        }
+    }
+
+    fn current(&self, ctx: &ExecutionContext) -> ShiftResult<Option<String>> {
+        let lang = match self.backend_or_detect() {
+            LocaleBackend::Localectl => {
+                let out = Cmd::new("localectl").arg("status").output(ctx)?;
+                out.lines()
+                    .find_map(|line| line.trim().strip_prefix("System Locale:"))
+                    .and_then(|vars| lang_in(vars.split_whitespace()))
+            }
+            LocaleBackend::File => {
+                let file = ctx.resolve(&self.locale_file())?;
+                match fs::read_to_string(&file) {
+                    Ok(text) => lang_in(text.lines()),
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+                    Err(err) => return Err(err.into()),
+                }
+            }
+            LocaleBackend::Defaults => {
+                match Cmd::new("defaults")
+                    .args(["read", "-g", "AppleLocale"])
+                    .output(ctx)
+                {
+                    Ok(out) => Some(out.trim().to_string()),
+                    // Unset: `defaults read` fails.
+                    Err(ShiftError::Command { .. }) => None,
+                    Err(err) => return Err(err),
+                }
+            }
+        };
+        Ok(lang.filter(|lang| !lang.is_empty()))
+    }
+
+    /// Sets `LANG`, or unsets it for `None`.
+    fn set(&self, ctx: &ExecutionContext, lang: Option<&str>) -> ShiftResult<()> {
+        match self.backend_or_detect() {
+            LocaleBackend::Localectl => {
+                let vars = lang.map(|lang| format!("LANG={lang}")).unwrap_or_default();
+                Cmd::new("localectl")
+                    .args(["set-locale".to_string(), vars])
+                    .output(ctx)?;
+            }
+            LocaleBackend::File => {
+                let file = ctx.resolve(&self.locale_file())?;
+                let (text, mode) = match fs::read_to_string(&file) {
+                    Ok(text) => (text, permissions::mode_of(&file)?),
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                        (String::new(), ctx.permissions().file_mode(Some(0o644)))
+                    }
+                    Err(err) => return Err(err.into()),
+                };
+                let mut lines: Vec<String> = text
+                    .lines()
+                    .filter(|line| !line.trim_start().starts_with("LANG="))
+                    .map(String::from)
+                    .collect();
+                if let Some(lang) = lang {
+                    lines.insert(0, format!("LANG={lang}"));
+                }
+                if lines.iter().all(|line| line.trim().is_empty()) {
+                    if file.exists() {
+                        fs::remove_file(&file)?;
+                    }
+                    return Ok(());
+                }
+                permissions::write_file(&file, format!("{}\n", lines.join("\n")).as_bytes(), mode)?;
+            }
+            LocaleBackend::Defaults => {
+                let args = match lang {
+                    Some(lang) => vec!["write", "-g", "AppleLocale", lang],
+                    None => vec!["delete", "-g", "AppleLocale"],
+                };
+                Cmd::new("defaults").args(args).output(ctx)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds the locale with `locale-gen` if `locale -a` lacks it.
+    fn generate(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        if self.backend_or_detect() == LocaleBackend::Defaults
+            || self.lang == "C"
+            || self.lang == "POSIX"
+            || find_program("locale").is_none()
+            || find_program("locale-gen").is_none()
+        {
+            return Ok(());
+        }
+        let available = Cmd::new("locale").arg("-a").output(ctx)?;
+        let wanted = normalize_locale(&self.lang);
+        if available
+            .lines()
+            .any(|line| normalize_locale(line) == wanted)
+        {
+            return Ok(());
+        }
+        Cmd::new("locale-gen").arg(&self.lang).output(ctx)?;
+        Ok(())
+    }
+}
+
+/// The value of `LANG=` among `vars`, unquoted.
+fn lang_in<'a>(mut vars: impl Iterator<Item = &'a str>) -> Option<String> {
+    vars.find_map(|var| var.trim().strip_prefix("LANG="))
+        .map(|lang| lang.trim_matches('"').to_string())
+}
+
+/// `en_US.UTF-8` and `en_US.utf8` name the same locale.
+fn normalize_locale(name: &str) -> String {
+    name.trim().to_ascii_lowercase().replace('-', "")
+}
+
+impl Shift for Locale {
+    fn metadata(&self) -> ShiftMetadata {
+        let meta = ShiftMetadata::new("locale", format!("set the locale to {}", self.lang))
+            .input("lang", &self.lang);
+        if self.backend_or_detect() == LocaleBackend::File {
+            meta.target(self.locale_file())
+        } else {
+            meta
+        }
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        match self.backend_or_detect() {
+            LocaleBackend::File => vec![Claim::exclusive(Resource::path(self.locale_file()))],
+            _ => vec![Claim::exclusive(Resource::lock("locale"))],
+        }
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        let valid = !self.lang.is_empty()
+            && self
+                .lang
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-' | '@'));
+        if !valid {
+            return Err(ShiftError::Custom(format!(
+                "`{}` is not a locale name",
+                self.lang
+            )));
+        }
+        match self.backend_or_detect() {
+            LocaleBackend::Localectl => ctx.require_program("localectl"),
+            LocaleBackend::File => {
+                ctx.exec().resolve(&self.locale_file())?;
+                ctx.require_parent(&self.locale_file())
+            }
+            LocaleBackend::Defaults => ctx.require_program("defaults"),
+        }
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Sudo]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let current = self.current(ctx)?;
+        if current.as_deref() == Some(self.wanted()) {
+            return Ok(ShiftOutcome::Unchanged);
+        }
+        self.generate(ctx)?;
+        if ctx.get_state("previous").is_none() {
+            ctx.set_state("previous", json!(current));
+        }
+        self.set(ctx, Some(self.wanted()))?;
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        match ctx.get_state("previous") {
+            Some(serde_json::Value::String(previous)) => self.set(ctx, Some(&previous))?,
+            Some(_) => self.set(ctx, None)?,
+            None => {}
+        }
+        ctx.clear_state("previous");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        Ok(self.current(ctx)?.as_deref() == Some(self.wanted()))
+    }
+}
@@ -0,0 +1,80 @@
+//! Built-in shifts.
+
+mod acme;
+mod apply_plan_file;
+mod assert;
+mod backup;
+mod brew;
+mod cargo;
+mod cmd;
+mod create_dir;
+mod create_file;
+mod dconf;
+mod desktop_app;
+mod editor;
+mod firewall;
+mod font;
+mod fstab;
+mod git_hook;
+mod github_clone;
+mod gpu;
+mod hostname;
+mod line_in_file;
+mod locale;
+mod mount;
+mod mysql;
+mod nix;
+mod node;
+mod package;
+mod postgres;
+mod redis;
+mod sudoers;
+mod swap;
+mod symlink;
+mod sysctl;
+mod timer;
+mod timesync;
+mod tls_cert;
+mod toolchain;
+mod vhost;
+mod vpn;
+mod workspace;
+
+pub use acme::{AcmeCert, AcmeChallenge};
+pub use apply_plan_file::ApplyPlanFile;
+pub use assert::Assert;
+pub use backup::{BackupJob, BackupTool};
+pub use brew::BrewBundle;
+pub use cargo::{CargoAddDependency, CargoNew, CargoWorkspaceMember, DependencyKind};
+pub use cmd::Cmd;
+pub use create_dir::CreateDir;
+pub use create_file::CreateFile;
+pub use dconf::DconfSetting;
+pub use desktop_app::{AppImage, FlatpakInstall, SnapInstall};
+pub use editor::{NeovimPlugins, VsCodeExtension, VsCodeSettings};
+pub use firewall::{FirewallAction, FirewallBackend, FirewallRule, Protocol};
+pub use font::Font;
+pub use git_hook::GitHook;
+pub use github_clone::GitHubClone;
+pub use gpu::{ComputeToolkit, GpuToolkit};
+pub use hostname::{Hostname, HostnameBackend};
+pub use line_in_file::LineInFile;
+pub use locale::{Locale, LocaleBackend, Timezone, TimezoneBackend};
+pub use mount::Mount;
+pub use mysql::{MysqlConnection, MysqlDatabase, MysqlUser};
+pub use nix::NixProfileInstall;
+pub use node::{NodeInstall, NodeProjectInit, PackageManager};
+pub use package::Package;
+pub use postgres::{PgConnection, PgDatabase, PgExtension, PgRole};
+pub use redis::{RedisConfig, RedisConnection, RedisReady};
+pub use sudoers::SudoersRule;
+pub use swap::SwapFile;
+pub use symlink::Symlink;
+pub use sysctl::Sysctl;
+pub use timer::SystemdTimer;
+pub use timesync::{TimeSync, TimeSyncDaemon};
+pub use tls_cert::{CertProvider, TlsCert};
+pub use toolchain::{NodeManager, NodeVersion, PythonVersion, RustToolchain};
+pub use vhost::{WebServer, WebVhost};
+pub use vpn::{TailscaleJoin, WireguardInterface, WireguardPeer};
+pub use workspace::{Workspace, WorkspaceRepo};
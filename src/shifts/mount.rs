@@ -0,0 +1,237 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::metadata::ShiftMetadata;
+use crate::resource::{Claim, Resource};
+use crate::shift::{Shift, ShiftOutcome};
+use crate::shifts::{fstab, Cmd};
+use crate::validate::ValidationContext;
+
+/// Where the kernel lists what is mounted.
+const PROC_MOUNTS: &str = "/proc/mounts";
+
+/// Mounts `source` at `path`, creating the mount point if needed.
+///
+/// With `persist`, an fstab entry is also written, preceded by a
+/// `# skies: <path>` marker line so it can be found and updated later;
+/// entries without the marker are never touched. Applied while `path` is
+/// a mount point in `/proc/mounts` (of `fstype`, if given) and, when
+/// persisting, the marked entry matches. Revert unmounts and removes the
+/// marked entry. `path` and `fstab` are usually outside the plan root, so
+/// the entry needs `allow_outside_root`.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Mount {
+    /// Device, `UUID=...`/`LABEL=...`, or remote (`host:/export`).
+    source: String,
+    path: PathBuf,
+    #[serde(default)]
+    fstype: Option<String>,
+    #[serde(default)]
+    options: Vec<String>,
+    #[serde(default)]
+    persist: bool,
+    /// fsck order for the fstab entry: 0 to skip, 1 for `/`, 2 otherwise.
+    #[serde(default)]
+    pass: u8,
+    #[serde(default = "default_fstab")]
+    fstab: PathBuf,
+}
+
+fn default_fstab() -> PathBuf {
+    PathBuf::from("/etc/fstab")
+}
+
+impl Mount {
+    pub fn new(source: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        Mount {
+            source: source.into(),
+            path: path.into(),
+            fstype: None,
+            options: Vec::new(),
+            persist: false,
+            pass: 0,
+            fstab: default_fstab(),
+        }
+    }
+
+    pub fn fstype(mut self, fstype: impl Into<String>) -> Self {
+        self.fstype = Some(fstype.into());
+        self
+    }
+
+    pub fn option(mut self, option: impl Into<String>) -> Self {
+        self.options.push(option.into());
+        self
+    }
+
+    /// Also write an fstab entry so the mount survives a reboot.
+    pub fn persist(mut self) -> Self {
+        self.persist = true;
+        self
+    }
+
+    pub fn pass(mut self, pass: u8) -> Self {
+        self.pass = pass;
+        self
+    }
+
+    pub fn fstab(mut self, fstab: impl Into<PathBuf>) -> Self {
+        self.fstab = fstab.into();
+        self
+    }
+
+    /// Key of our fstab entry.
+    fn fstab_key(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    /// The fstab line for `mount_point`.
+    fn fstab_entry(&self, mount_point: &Path) -> String {
+        let options = if self.options.is_empty() {
+            "defaults".to_string()
+        } else {
+            self.options.join(",")
+        };
+        format!(
+            "{} {} {} {options} 0 {}",
+            fstab::escape(&self.source),
+            fstab::escape(&mount_point.display().to_string()),
+            self.fstype.as_deref().unwrap_or("auto"),
+            self.pass
+        )
+    }
+
+    /// The filesystem type mounted at `mount_point`, if anything is.
+    fn mounted(mount_point: &Path) -> ShiftResult<Option<String>> {
+        let wanted = fstab::escape(&mount_point.display().to_string());
+        let mounts = fs::read_to_string(PROC_MOUNTS)?;
+        // Later lines are mounted over earlier ones.
+        Ok(mounts.lines().rev().find_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (_, point, fstype) = (fields.next()?, fields.next()?, fields.next()?);
+            (point == wanted).then(|| fstype.to_string())
+        }))
+    }
+}
+
+impl Shift for Mount {
+    fn metadata(&self) -> ShiftMetadata {
+        let mut meta = ShiftMetadata::new(
+            "mount",
+            format!("mount {} at {}", self.source, self.path.display()),
+        )
+        .target(&self.path)
+        .input("source", &self.source)
+        .input("options", &self.options)
+        .input("persist", self.persist);
+        if let Some(fstype) = &self.fstype {
+            meta = meta.input("fstype", fstype);
+        }
+        if self.persist {
+            meta = meta.input("pass", self.pass);
+        }
+        meta
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        let mut claims = vec![Claim::exclusive(Resource::path(&self.path))];
+        if self.persist {
+            claims.push(Claim::shared(Resource::path(&self.fstab)));
+        }
+        claims
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        if self.source.trim().is_empty() {
+            return Err(ShiftError::Custom("mount source is empty".into()));
+        }
+        if self
+            .options
+            .iter()
+            .any(|option| option.contains([',', ' ']))
+        {
+            return Err(ShiftError::Custom(
+                "mount options must be given one per entry".into(),
+            ));
+        }
+        ctx.require_program("mount")?;
+        ctx.require_program("umount")?;
+        ctx.exec().resolve(&self.path)?;
+        if self.persist {
+            ctx.exec().resolve(&self.fstab)?;
+        }
+        Ok(())
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Sudo]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let mount_point = ctx.resolve(&self.path)?;
+        let mut changed = false;
+        if self.persist {
+            let fstab = ctx.resolve(&self.fstab)?;
+            let entry = self.fstab_entry(&mount_point);
+            if fstab::entry(&fstab, &self.fstab_key())?.as_ref() != Some(&entry) {
+                fstab::set_entry(&fstab, &self.fstab_key(), &entry)?;
+                changed = true;
+            }
+        }
+        match Self::mounted(&mount_point)? {
+            Some(fstype) if self.fstype.as_ref().is_none_or(|wanted| *wanted == fstype) => {
+                return Ok(ShiftOutcome::changed_if(changed));
+            }
+            Some(fstype) => {
+                return Err(ShiftError::Conflict {
+                    resource: mount_point.display().to_string(),
+                    reason: format!("already has a {fstype} filesystem mounted; unmount it first"),
+                });
+            }
+            None => {}
+        }
+        fs::create_dir_all(&mount_point)?;
+        let mut cmd = Cmd::new("mount");
+        if let Some(fstype) = &self.fstype {
+            cmd = cmd.args(["-t", fstype]);
+        }
+        if !self.options.is_empty() {
+            cmd = cmd.args(["-o".to_string(), self.options.join(",")]);
+        }
+        cmd.arg(&self.source)
+            .arg(mount_point.display().to_string())
+            .output(ctx)?;
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let mount_point = ctx.resolve(&self.path)?;
+        if Self::mounted(&mount_point)?.is_some() {
+            Cmd::new("umount")
+                .arg(mount_point.display().to_string())
+                .output(ctx)?;
+        }
+        if !self.persist {
+            return Ok(());
+        }
+        fstab::remove_entry(&ctx.resolve(&self.fstab)?, &self.fstab_key())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        let mount_point = ctx.resolve(&self.path)?;
+        let mounted = Self::mounted(&mount_point)?
+            .is_some_and(|fstype| self.fstype.as_ref().is_none_or(|wanted| *wanted == fstype));
+        if !mounted || !self.persist {
+            return Ok(mounted);
+        }
+        let entry = fstab::entry(&ctx.resolve(&self.fstab)?, &self.fstab_key())?;
+        Ok(entry == Some(self.fstab_entry(&mount_point)))
+    }
+}
@@ -0,0 +1,594 @@
+//! MySQL and MariaDB databases and users, managed through the `mysql`
+//! client.
+//!
+//! Every shift takes a `connection` table: `host`, `port` and `user` as
+//! for `mysql`, `password_env` naming the variable that holds the password,
+//! and `run_as` to connect as another system user, e.g. `root` for socket
+//! authentication on a local server. Left empty, `mysql` uses its defaults
+//! and option files.
+//!
+//! Statements carrying a password are passed to `mysql` in a file readable
+//! only by the connecting user, never on the command line.
+
+use std::fs;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::metadata::ShiftMetadata;
+use crate::permissions;
+use crate::resource::{Claim, Resource};
+use crate::run_as;
+use crate::shift::{Shift, ShiftOutcome};
+use crate::shifts::Cmd;
+use crate::validate::ValidationContext;
+
+/// Where and how to connect.
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MysqlConnection {
+    #[serde(default)]
+    host: Option<String>,
+    #[serde(default)]
+    port: Option<u16>,
+    #[serde(default)]
+    user: Option<String>,
+    #[serde(default)]
+    password_env: Option<String>,
+    #[serde(default)]
+    run_as: Option<String>,
+}
+
+impl MysqlConnection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn host(mut self, host: impl Into<String>) -> Self {
+        self.host = Some(host.into());
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    pub fn user(mut self, user: impl Into<String>) -> Self {
+        self.user = Some(user.into());
+        self
+    }
+
+    /// Reads the password from the environment variable `var`.
+    pub fn password_env(mut self, var: impl Into<String>) -> Self {
+        self.password_env = Some(var.into());
+        self
+    }
+
+    /// Connects as the system user `user`.
+    pub fn run_as(mut self, user: impl Into<String>) -> Self {
+        self.run_as = Some(user.into());
+        self
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        ctx.require_program("mysql")?;
+        if let Some(var) = &self.password_env {
+            ctx.require_env(var)?;
+        }
+        if let Some(user) = &self.run_as {
+            run_as::check(ctx, user, true)?;
+        }
+        Ok(())
+    }
+
+    fn mysql(&self) -> Cmd {
+        let mut cmd = Cmd::new("mysql").args(["--batch", "--skip-column-names"]);
+        if let Some(host) = &self.host {
+            cmd = cmd.arg(format!("--host={host}"));
+        }
+        if let Some(port) = self.port {
+            cmd = cmd.arg(format!("--port={port}"));
+        }
+        if let Some(user) = &self.user {
+            cmd = cmd.arg(format!("--user={user}"));
+        }
+        if let Some(password) = self
+            .password_env
+            .as_ref()
+            .and_then(|var| std::env::var(var).ok())
+        {
+            cmd = cmd.env("MYSQL_PWD", password);
+        }
+        if let Some(user) = &self.run_as {
+            cmd = cmd.run_as(user);
+        }
+        cmd
+    }
+
+    /// Runs `sql` and returns its output, one tab-separated row per line.
+    fn query(&self, ctx: &ExecutionContext, sql: &str) -> ShiftResult<String> {
+        let out = self.mysql().arg("--execute").arg(sql).output(ctx)?;
+        Ok(out.trim().to_string())
+    }
+
+    /// Runs `sql`, which holds a secret, from a private file.
+    fn execute_secret(&self, ctx: &ExecutionContext, sql: &str) -> ShiftResult<()> {
+        let file = ctx.temp_dir()?.join("statement.sql");
+        permissions::write_file(&file, sql.as_bytes(), Some(0o600))?;
+        if let Some(user) = &self.run_as {
+            if !run_as::is_current(ctx, user)? {
+                run_as::chown(&file, run_as::ids(ctx, user)?)?;
+            }
+        }
+        let result = self
+            .mysql()
+            .arg("--execute")
+            .arg(format!("source {}", file.display()))
+            .output(ctx);
+        fs::remove_file(&file)?;
+        result.map(drop)
+    }
+
+    fn describe(&self) -> String {
+        match (&self.host, self.port) {
+            (Some(host), Some(port)) => format!("{host}:{port}"),
+            (Some(host), None) => host.clone(),
+            (None, _) => "local".to_string(),
+        }
+    }
+}
+
+/// `name` as an SQL identifier.
+fn ident(name: &str) -> String {
+    format!("`{}`", name.replace('`', "``"))
+}
+
+/// `text` as an SQL string literal.
+fn literal(text: &str) -> String {
+    format!("'{}'", text.replace('\\', "\\\\").replace('\'', "''"))
+}
+
+fn check_name(what: &str, name: &str, max: usize) -> ShiftResult<()> {
+    if name.is_empty() || name.len() > max || name.contains('\0') {
+        return Err(ShiftError::Custom(format!(
+            "`{name}` is not a usable {what} name"
+        )));
+    }
+    Ok(())
+}
+
+/// A MySQL database, optionally with a default `charset` and `collation`.
+///
+/// Applied while the database exists with those defaults. Revert drops a
+/// database this shift created, but refuses while it holds tables unless
+/// `--force` is passed, or else puts the previous defaults back.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MysqlDatabase {
+    name: String,
+    #[serde(default)]
+    charset: Option<String>,
+    #[serde(default)]
+    collation: Option<String>,
+    #[serde(default)]
+    connection: MysqlConnection,
+}
+
+impl MysqlDatabase {
+    pub fn new(name: impl Into<String>) -> Self {
+        MysqlDatabase {
+            name: name.into(),
+            charset: None,
+            collation: None,
+            connection: MysqlConnection::default(),
+        }
+    }
+
+    pub fn charset(mut self, charset: impl Into<String>) -> Self {
+        self.charset = Some(charset.into());
+        self
+    }
+
+    pub fn collation(mut self, collation: impl Into<String>) -> Self {
+        self.collation = Some(collation.into());
+        self
+    }
+
+    pub fn connection(mut self, connection: MysqlConnection) -> Self {
+        self.connection = connection;
+        self
+    }
+
+    /// The database's charset and collation, or `None` if it does not exist.
+    fn defaults(&self, ctx: &ExecutionContext) -> ShiftResult<Option<(String, String)>> {
+        let sql = format!(
+            "SELECT DEFAULT_CHARACTER_SET_NAME, DEFAULT_COLLATION_NAME \
+             FROM information_schema.SCHEMATA WHERE SCHEMA_NAME = {}",
+            literal(&self.name)
+        );
+        let out = self.connection.query(ctx, &sql)?;
+        Ok(out
+            .split_once('\t')
+            .map(|(charset, collation)| (charset.to_string(), collation.to_string())))
+    }
+
+    fn matches(&self, (charset, collation): &(String, String)) -> bool {
+        self.charset.as_ref().is_none_or(|wanted| wanted == charset)
+            && self
+                .collation
+                .as_ref()
+                .is_none_or(|wanted| wanted == collation)
+    }
+
+    fn options(charset: Option<&str>, collation: Option<&str>) -> String {
+        let mut options = String::new();
+        if let Some(charset) = charset {
+            options.push_str(&format!(" CHARACTER SET {}", literal(charset)));
+        }
+        if let Some(collation) = collation {
+            options.push_str(&format!(" COLLATE {}", literal(collation)));
+        }
+        options
+    }
+
+    fn tables(&self, ctx: &ExecutionContext) -> ShiftResult<u64> {
+        let sql = format!(
+            "SELECT COUNT(*) FROM information_schema.TABLES WHERE TABLE_SCHEMA = {}",
+            literal(&self.name)
+        );
+        Ok(self
+            .connection
+            .query(ctx, &sql)?
+            .parse()
+            .unwrap_or_default())
+    }
+}
+
+impl Shift for MysqlDatabase {
+    fn metadata(&self) -> ShiftMetadata {
+        let mut meta = ShiftMetadata::new(
+            "mysql_database",
+            format!("create the MySQL database {}", self.name),
+        )
+        .input("name", &self.name)
+        .input("server", self.connection.describe());
+        if let Some(charset) = &self.charset {
+            meta = meta.input("charset", charset);
+        }
+        if let Some(collation) = &self.collation {
+            meta = meta.input("collation", collation);
+        }
+        meta
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![Claim::exclusive(Resource::lock(format!(
+            "mysql database {}",
+            self.name
+        )))]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        check_name("database", &self.name, 64)?;
+        self.connection.validate(ctx)
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let options = Self::options(self.charset.as_deref(), self.collation.as_deref());
+        match self.defaults(ctx)? {
+            Some(current) if self.matches(&current) => Ok(ShiftOutcome::Unchanged),
+            Some(current) => {
+                if ctx.get_state("previous").is_none() {
+                    ctx.set_state("previous", json!(current));
+                }
+                let sql = format!("ALTER DATABASE {}{options}", ident(&self.name));
+                self.connection.query(ctx, &sql)?;
+                Ok(ShiftOutcome::Changed)
+            }
+            None => {
+                let sql = format!("CREATE DATABASE {}{options}", ident(&self.name));
+                self.connection.query(ctx, &sql)?;
+                ctx.set_state("created", json!(true));
+                Ok(ShiftOutcome::Changed)
+            }
+        }
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let exists = self.defaults(ctx)?.is_some();
+        if ctx.get_state("created") == Some(json!(true)) && exists {
+            let tables = self.tables(ctx)?;
+            if tables > 0 && !ctx.is_forced() {
+                return Err(ShiftError::Conflict {
+                    resource: format!("database {}", self.name),
+                    reason: format!("still holds {tables} tables"),
+                }
+                .hint("back up or drop them first, or pass --force to drop the database anyway"));
+            }
+            let sql = format!("DROP DATABASE {}", ident(&self.name));
+            self.connection.query(ctx, &sql)?;
+        } else if let (Some(previous), true) = (
+            ctx.get_state("previous")
+                .and_then(|value| serde_json::from_value::<(String, String)>(value).ok()),
+            exists,
+        ) {
+            let options = Self::options(Some(&previous.0), Some(&previous.1));
+            let sql = format!("ALTER DATABASE {}{options}", ident(&self.name));
+            self.connection.query(ctx, &sql)?;
+        }
+        ctx.clear_state("created");
+        ctx.clear_state("previous");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        Ok(self
+            .defaults(ctx)?
+            .is_some_and(|current| self.matches(&current)))
+    }
+}
+
+/// What `ALL PRIVILEGES` on a database is checked against; the server
+/// lists the privileges separately.
+const ALL_PRIVILEGES: [&str; 8] = [
+    "SELECT", "INSERT", "UPDATE", "DELETE", "CREATE", "DROP", "ALTER", "INDEX",
+];
+
+/// A MySQL user account, `name@host`, optionally granted `privileges` on
+/// `database`.
+///
+/// `host` defaults to `localhost` and `privileges` to `ALL PRIVILEGES`.
+/// With `password_env`, a new user gets the password in that variable; an
+/// existing user's password is left alone, since it cannot be checked.
+/// Applied while the user exists and holds the privileges. Revert drops a
+/// user this shift created, or else revokes the privileges it granted.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MysqlUser {
+    name: String,
+    #[serde(default = "default_host")]
+    host: String,
+    #[serde(default)]
+    password_env: Option<String>,
+    #[serde(default)]
+    database: Option<String>,
+    #[serde(default = "default_privileges")]
+    privileges: Vec<String>,
+    #[serde(default)]
+    connection: MysqlConnection,
+}
+
+fn default_host() -> String {
+    "localhost".to_string()
+}
+
+fn default_privileges() -> Vec<String> {
+    vec!["ALL PRIVILEGES".to_string()]
+}
+
+impl MysqlUser {
+    pub fn new(name: impl Into<String>) -> Self {
+        MysqlUser {
+            name: name.into(),
+            host: default_host(),
+            password_env: None,
+            database: None,
+            privileges: default_privileges(),
+            connection: MysqlConnection::default(),
+        }
+    }
+
+    pub fn host(mut self, host: impl Into<String>) -> Self {
+        self.host = host.into();
+        self
+    }
+
+    /// Gives a new user the password in the environment variable `var`.
+    pub fn password_env(mut self, var: impl Into<String>) -> Self {
+        self.password_env = Some(var.into());
+        self
+    }
+
+    /// Grants the user's privileges on `database`.
+    pub fn database(mut self, database: impl Into<String>) -> Self {
+        self.database = Some(database.into());
+        self
+    }
+
+    pub fn privileges<I, S>(mut self, privileges: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.privileges = privileges.into_iter().map(Into::into).collect();
+        self
+    }
+
+    fn account(&self) -> String {
+        format!("{}@{}", literal(&self.name), literal(&self.host))
+    }
+
+    /// The privileges to grant, upper-cased, with `ALL` spelled out.
+    fn wanted(&self) -> Vec<String> {
+        let mut wanted = Vec::new();
+        for privilege in &self.privileges {
+            let privilege = privilege.trim().to_uppercase();
+            if privilege == "ALL" || privilege == "ALL PRIVILEGES" {
+                wanted.extend(ALL_PRIVILEGES.iter().map(|p| p.to_string()));
+            } else {
+                wanted.push(privilege);
+            }
+        }
+        wanted.sort();
+        wanted.dedup();
+        wanted
+    }
+
+    fn exists(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        let sql = format!(
+            "SELECT 1 FROM mysql.user WHERE User = {} AND Host = {}",
+            literal(&self.name),
+            literal(&self.host)
+        );
+        Ok(!self.connection.query(ctx, &sql)?.is_empty())
+    }
+
+    /// The wanted privileges on `database` the user does not hold yet.
+    fn missing(&self, ctx: &ExecutionContext, database: &str) -> ShiftResult<Vec<String>> {
+        let grantee = format!("'{}'@'{}'", self.name, self.host);
+        let sql = format!(
+            "SELECT PRIVILEGE_TYPE FROM information_schema.SCHEMA_PRIVILEGES \
+             WHERE GRANTEE = {} AND TABLE_SCHEMA = {}",
+            literal(&grantee),
+            literal(database)
+        );
+        let out = self.connection.query(ctx, &sql)?;
+        let held: Vec<&str> = out.lines().map(str::trim).collect();
+        Ok(self
+            .wanted()
+            .into_iter()
+            .filter(|privilege| !held.contains(&privilege.as_str()))
+            .collect())
+    }
+
+    fn on(database: &str) -> String {
+        format!("{}.*", ident(database))
+    }
+}
+
+impl Shift for MysqlUser {
+    fn metadata(&self) -> ShiftMetadata {
+        let mut meta = ShiftMetadata::new(
+            "mysql_user",
+            format!("create the MySQL user {}@{}", self.name, self.host),
+        )
+        .input("name", &self.name)
+        .input("host", &self.host)
+        .input("server", self.connection.describe());
+        if let Some(database) = &self.database {
+            meta = meta
+                .input("database", database)
+                .input("privileges", &self.privileges);
+        }
+        if let Some(var) = &self.password_env {
+            meta = meta.input("password_env", var);
+        }
+        meta
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![Claim::exclusive(Resource::lock(format!(
+            "mysql user {}@{}",
+            self.name, self.host
+        )))]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        check_name("user", &self.name, 32)?;
+        check_name("host", &self.host, 255)?;
+        if let Some(database) = &self.database {
+            check_name("database", database, 64)?;
+        }
+        if let Some(bad) = self.privileges.iter().find(|privilege| {
+            privilege.is_empty()
+                || !privilege
+                    .chars()
+                    .all(|c| c.is_ascii_alphabetic() || c == ' ' || c == '_')
+        }) {
+            return Err(ShiftError::Custom(format!(
+                "`{bad}` is not a MySQL privilege"
+            )));
+        }
+        if let Some(var) = &self.password_env {
+            ctx.require_env(var)?;
+        }
+        self.connection.validate(ctx)
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let mut changed = false;
+        if !self.exists(ctx)? {
+            let mut sql = format!("CREATE USER IF NOT EXISTS {}", self.account());
+            match &self.password_env {
+                Some(var) => {
+                    let password = std::env::var(var).map_err(|_| {
+                        ShiftError::Custom(format!("environment variable `{var}` is not set"))
+                    })?;
+                    sql.push_str(&format!(" IDENTIFIED BY {};\n", literal(&password)));
+                    self.connection.execute_secret(ctx, &sql)?;
+                }
+                None => {
+                    self.connection.query(ctx, &sql)?;
+                }
+            }
+            ctx.set_state("created", json!(true));
+            changed = true;
+        }
+        if let Some(database) = &self.database {
+            let missing = self.missing(ctx, database)?;
+            if !missing.is_empty() {
+                let sql = format!(
+                    "GRANT {} ON {} TO {}",
+                    missing.join(", "),
+                    Self::on(database),
+                    self.account()
+                );
+                self.connection.query(ctx, &sql)?;
+                let mut granted: Vec<String> = match ctx.get_state("granted") {
+                    Some(value) => serde_json::from_value(value).unwrap_or_default(),
+                    None => Vec::new(),
+                };
+                granted.extend(missing);
+                ctx.set_state("granted", json!(granted));
+                changed = true;
+            }
+        }
+        Ok(ShiftOutcome::changed_if(changed))
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        if ctx.get_state("created") == Some(json!(true)) {
+            let sql = format!("DROP USER IF EXISTS {}", self.account());
+            self.connection.query(ctx, &sql)?;
+        } else if let (Some(Value::Array(granted)), Some(database)) =
+            (ctx.get_state("granted"), &self.database)
+        {
+            let granted: Vec<&str> = granted.iter().filter_map(Value::as_str).collect();
+            if !granted.is_empty() && self.exists(ctx)? {
+                let sql = format!(
+                    "REVOKE {} ON {} FROM {}",
+                    granted.join(", "),
+                    Self::on(database),
+                    self.account()
+                );
+                self.connection.query(ctx, &sql)?;
+            }
+        }
+        ctx.clear_state("created");
+        ctx.clear_state("granted");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        if !self.exists(ctx)? {
+            return Ok(false);
+        }
+        match &self.database {
+            Some(database) => Ok(self.missing(ctx, database)?.is_empty()),
+            None => Ok(true),
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::inputs::{ExternalInput, InputKind};
+use crate::metadata::ShiftMetadata;
+use crate::resource::{Claim, Resource};
+use crate::shift::{Shift, ShiftOutcome};
+use crate::shifts::Cmd;
+use crate::validate::ValidationContext;
+
+/// Lets `nix` commands use flakes whatever the local configuration says.
+const FEATURES: [&str; 2] = ["--extra-experimental-features", "nix-command flakes"];
+
+/// Installs packages into the user's Nix profile with `nix profile install`.
+///
+/// `packages` are installables: a bare name such as `ripgrep` means
+/// `nixpkgs#ripgrep`, and anything with a `#` is used as is. `flake` adds
+/// one more, a flake reference whose default package is installed, e.g. a
+/// flake of dev tools. Applied when the profile has an element for every
+/// one of them. Revert removes only the elements this shift installed.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct NixProfileInstall {
+    #[serde(default)]
+    packages: Vec<String>,
+    #[serde(default)]
+    flake: Option<String>,
+}
+
+/// An element of `nix profile list --json`.
+struct Element {
+    /// What `nix profile remove` takes: the name in newer versions of Nix,
+    /// the index in older ones.
+    handle: String,
+    url: String,
+    attr_path: String,
+}
+
+impl Element {
+    /// Whether this element was installed from `installable`.
+    fn is(&self, installable: &str) -> bool {
+        let (flake, attr) = installable
+            .split_once('#')
+            .unwrap_or((installable, "default"));
+        let url = self.url.strip_prefix("flake:").unwrap_or(&self.url);
+        url == flake && (self.attr_path == attr || self.attr_path.ends_with(&format!(".{attr}")))
+    }
+}
+
+impl NixProfileInstall {
+    pub fn new() -> Self {
+        NixProfileInstall {
+            packages: Vec::new(),
+            flake: None,
+        }
+    }
+
+    pub fn package(mut self, package: impl Into<String>) -> Self {
+        self.packages.push(package.into());
+        self
+    }
+
+    /// Also installs the default package of the flake at `reference`.
+    pub fn flake(mut self, reference: impl Into<String>) -> Self {
+        self.flake = Some(reference.into());
+        self
+    }
+
+    fn installables(&self) -> Vec<String> {
+        self.packages
+            .iter()
+            .map(|package| match package.contains('#') {
+                true => package.clone(),
+                false => format!("nixpkgs#{package}"),
+            })
+            .chain(self.flake.clone())
+            .collect()
+    }
+
+    fn elements(ctx: &ExecutionContext) -> ShiftResult<Vec<Element>> {
+        let out = Cmd::new("nix")
+            .args(FEATURES)
+            .args(["profile", "list", "--json"])
+            .output(ctx)?;
+        let list: Value = serde_json::from_str(&out).map_err(|err| {
+            ShiftError::Custom(format!("cannot parse `nix profile list --json`: {err}"))
+        })?;
+        let element = |handle: String, value: &Value| Element {
+            handle,
+            url: value["originalUrl"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            attr_path: value["attrPath"].as_str().unwrap_or_default().to_string(),
+        };
+        Ok(match &list["elements"] {
+            Value::Object(named) => named
+                .iter()
+                .map(|(name, value)| element(name.clone(), value))
+                .collect(),
+            Value::Array(indexed) => indexed
+                .iter()
+                .enumerate()
+                .map(|(index, value)| element(index.to_string(), value))
+                .collect(),
+            _ => Vec::new(),
+        })
+    }
+
+    fn missing(&self, ctx: &ExecutionContext) -> ShiftResult<Vec<String>> {
+        let elements = Self::elements(ctx)?;
+        Ok(self
+            .installables()
+            .into_iter()
+            .filter(|installable| !elements.iter().any(|element| element.is(installable)))
+            .collect())
+    }
+}
+
+impl Default for NixProfileInstall {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shift for NixProfileInstall {
+    fn metadata(&self) -> ShiftMetadata {
+        let mut meta = ShiftMetadata::new(
+            "nix_profile_install",
+            format!("install {} with nix", self.installables().join(", ")),
+        )
+        .input("installables", self.installables());
+        // Most packages install a program of the same name.
+        for package in &self.packages {
+            let name = package
+                .rsplit_once('#')
+                .map_or(package.as_str(), |(_, name)| name);
+            meta = meta.program(name);
+        }
+        meta
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![Claim::shared(Resource::lock("nix-profile"))]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        if self.installables().is_empty() {
+            return Err(ShiftError::Custom(
+                "nothing to install: set `packages`, `flake`, or both".into(),
+            ));
+        }
+        ctx.require_program("nix")
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network]
+    }
+
+    /// An installable is pinned when its flake reference names a revision.
+    fn inputs(&self, _ctx: &ExecutionContext) -> ShiftResult<Vec<ExternalInput>> {
+        Ok(self
+            .installables()
+            .into_iter()
+            .map(|installable| {
+                let (flake, _) = installable.split_once('#').unwrap_or((&installable, ""));
+                let rev = flake
+                    .split(['/', '?', '&', '='])
+                    .find(|part| part.len() == 40 && part.bytes().all(|b| b.is_ascii_hexdigit()))
+                    .map(String::from);
+                ExternalInput::new(InputKind::Package, &installable).pinned(rev)
+            })
+            .collect())
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let missing = self.missing(ctx)?;
+        if missing.is_empty() {
+            return Ok(ShiftOutcome::Unchanged);
+        }
+        Cmd::new("nix")
+            .args(FEATURES)
+            .args(["profile", "install"])
+            .args(&missing)
+            .output(ctx)?;
+        let mut installed: Vec<Value> = ctx
+            .get_state("installed")
+            .and_then(|value| value.as_array().cloned())
+            .unwrap_or_default();
+        installed.extend(missing.into_iter().map(Value::from));
+        ctx.set_state("installed", json!(installed));
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let installed: Vec<String> = ctx
+            .get_state("installed")
+            .and_then(|value| serde_json::from_value(value).ok())
+            .unwrap_or_default();
+        let elements = Self::elements(ctx)?;
+        let handles: Vec<&str> = elements
+            .iter()
+            .filter(|element| installed.iter().any(|installable| element.is(installable)))
+            .map(|element| element.handle.as_str())
+            .collect();
+        if !handles.is_empty() {
+            Cmd::new("nix")
+                .args(FEATURES)
+                .args(["profile", "remove"])
+                .args(handles)
+                .output(ctx)?;
+        }
+        ctx.clear_state("installed");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        if crate::validate::find_program("nix").is_none() {
+            return Ok(false);
+        }
+        Ok(self.missing(ctx)?.is_empty())
+    }
+}
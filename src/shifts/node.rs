@@ -0,0 +1,342 @@
+//! Node.js projects: `package.json` scaffolding and dependency installs.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::hash::sha256_hex;
+use crate::metadata::ShiftMetadata;
+use crate::permissions;
+use crate::shift::{Shift, ShiftOutcome};
+use crate::shifts::Cmd;
+use crate::validate::ValidationContext;
+
+/// A Node.js package manager.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PackageManager {
+    Npm,
+    Yarn,
+    Pnpm,
+    Bun,
+}
+
+impl PackageManager {
+    /// Lockfiles, most specific first.
+    const LOCKFILES: [(&'static str, PackageManager); 5] = [
+        ("pnpm-lock.yaml", PackageManager::Pnpm),
+        ("yarn.lock", PackageManager::Yarn),
+        ("bun.lockb", PackageManager::Bun),
+        ("bun.lock", PackageManager::Bun),
+        ("package-lock.json", PackageManager::Npm),
+    ];
+
+    /// The manager a project in `dir` uses: the `packageManager` field of
+    /// `package.json`, else the lockfile present, else npm.
+    pub fn detect(dir: &Path) -> PackageManager {
+        let declared = fs::read_to_string(dir.join("package.json"))
+            .ok()
+            .and_then(|text| serde_json::from_str::<serde_json::Value>(&text).ok())
+            .and_then(|package| {
+                let spec = package.get("packageManager")?.as_str()?.to_string();
+                PackageManager::from_name(spec.split('@').next()?)
+            });
+        declared
+            .or_else(|| {
+                Self::LOCKFILES
+                    .iter()
+                    .find(|(file, _)| dir.join(file).exists())
+                    .map(|(_, manager)| *manager)
+            })
+            .unwrap_or(PackageManager::Npm)
+    }
+
+    fn from_name(name: &str) -> Option<PackageManager> {
+        match name {
+            "npm" => Some(PackageManager::Npm),
+            "yarn" => Some(PackageManager::Yarn),
+            "pnpm" => Some(PackageManager::Pnpm),
+            "bun" => Some(PackageManager::Bun),
+            _ => None,
+        }
+    }
+
+    pub fn program(self) -> &'static str {
+        match self {
+            PackageManager::Npm => "npm",
+            PackageManager::Yarn => "yarn",
+            PackageManager::Pnpm => "pnpm",
+            PackageManager::Bun => "bun",
+        }
+    }
+
+    /// The lockfile this manager writes in `dir`, if there is one.
+    pub fn lockfile(self, dir: &Path) -> Option<PathBuf> {
+        Self::LOCKFILES
+            .iter()
+            .filter(|(_, manager)| *manager == self)
+            .map(|(file, _)| dir.join(file))
+            .find(|path| path.exists())
+    }
+
+    /// Install arguments. `frozen` installs exactly what the lockfile says
+    /// and fails if it is out of date.
+    fn install_args(self, dir: &Path, frozen: bool) -> Vec<&'static str> {
+        match (self, frozen) {
+            (PackageManager::Npm, true) => vec!["ci"],
+            (PackageManager::Npm, false) => vec!["install"],
+            // Yarn 2+ ("berry") projects carry a .yarnrc.yml and renamed the flag.
+            (PackageManager::Yarn, true) if dir.join(".yarnrc.yml").exists() => {
+                vec!["install", "--immutable"]
+            }
+            (PackageManager::Pnpm | PackageManager::Yarn | PackageManager::Bun, true) => {
+                vec!["install", "--frozen-lockfile"]
+            }
+            (_, false) => vec!["install"],
+        }
+    }
+}
+
+/// Creates a minimal `package.json`, without running a package manager.
+///
+/// An existing `package.json` for the same package counts as applied;
+/// one for another package fails preflight. Revert removes the file only
+/// if it is still exactly what this shift wrote.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct NodeProjectInit {
+    path: PathBuf,
+    /// Defaults to the directory name.
+    #[serde(default)]
+    name: Option<String>,
+}
+
+impl NodeProjectInit {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        NodeProjectInit {
+            path: path.into(),
+            name: None,
+        }
+    }
+
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn package_name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| {
+            self.path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        })
+    }
+
+    fn contents(&self) -> String {
+        let package = json!({
+            "name": self.package_name(),
+            "version": "1.0.0",
+            "private": true,
+        });
+        format!("{package:#}\n")
+    }
+
+    fn existing_name(path: &Path) -> ShiftResult<Option<String>> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let package: serde_json::Value = serde_json::from_str(&text)
+            .map_err(|err| ShiftError::Custom(format!("{}: {err}", path.display())))?;
+        Ok(package
+            .get("name")
+            .and_then(|name| name.as_str())
+            .map(String::from))
+    }
+}
+
+impl Shift for NodeProjectInit {
+    fn metadata(&self) -> ShiftMetadata {
+        ShiftMetadata::new(
+            "node_project_init",
+            format!(
+                "create Node package {} in {}",
+                self.package_name(),
+                self.path.display()
+            ),
+        )
+        .target(self.path.join("package.json"))
+        .input("name", self.package_name())
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        let manifest = ctx.exec().resolve(&self.path.join("package.json"))?;
+        match Self::existing_name(&manifest)? {
+            Some(name) if name != self.package_name() => Err(ShiftError::Conflict {
+                resource: manifest.display().to_string(),
+                reason: format!("already defines package `{name}`"),
+            }),
+            _ => ctx.require_dir(&self.path),
+        }
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let manifest = ctx.resolve(&self.path.join("package.json"))?;
+        if manifest.exists() {
+            return Ok(ShiftOutcome::Unchanged);
+        }
+        let mode = ctx.permissions().file_mode(None);
+        permissions::write_file(&manifest, self.contents().as_bytes(), mode)?;
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let manifest = ctx.resolve(&self.path.join("package.json"))?;
+        if fs::read_to_string(&manifest).is_ok_and(|text| text == self.contents()) {
+            fs::remove_file(manifest)?;
+        }
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        let manifest = ctx.resolve(&self.path.join("package.json"))?;
+        Ok(Self::existing_name(&manifest)?.is_some_and(|name| name == self.package_name()))
+    }
+}
+
+/// Installs a project's dependencies with its package manager.
+///
+/// The manager is detected unless set. With a lockfile the install is
+/// frozen (`npm ci`, `--frozen-lockfile`, ...) unless `frozen = false`.
+/// After a successful install, a hash of `package.json` and the lockfile
+/// is written to `node_modules/.skies-install`; the shift is applied while
+/// that still matches, so changing either file reinstalls. Revert removes
+/// `node_modules`.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct NodeInstall {
+    path: PathBuf,
+    #[serde(default)]
+    manager: Option<PackageManager>,
+    #[serde(default)]
+    frozen: Option<bool>,
+}
+
+/// Marker file inside `node_modules` holding the hash of the inputs.
+const MARKER: &str = ".skies-install";
+
+impl NodeInstall {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        NodeInstall {
+            path: path.into(),
+            manager: None,
+            frozen: None,
+        }
+    }
+
+    pub fn manager(mut self, manager: PackageManager) -> Self {
+        self.manager = Some(manager);
+        self
+    }
+
+    pub fn frozen(mut self, frozen: bool) -> Self {
+        self.frozen = Some(frozen);
+        self
+    }
+
+    fn manager_for(&self, dir: &Path) -> PackageManager {
+        self.manager.unwrap_or_else(|| PackageManager::detect(dir))
+    }
+
+    /// Hash of what the install depends on.
+    fn inputs_hash(&self, dir: &Path) -> ShiftResult<String> {
+        let manager = self.manager_for(dir);
+        let mut bytes = fs::read(dir.join("package.json"))?;
+        if let Some(lockfile) = manager.lockfile(dir) {
+            bytes.extend(fs::read(lockfile)?);
+        }
+        bytes.extend(manager.program().as_bytes());
+        Ok(sha256_hex(bytes))
+    }
+}
+
+impl Shift for NodeInstall {
+    fn metadata(&self) -> ShiftMetadata {
+        let mut meta = ShiftMetadata::new(
+            "node_install",
+            format!("install Node dependencies in {}", self.path.display()),
+        )
+        .target(self.path.join("node_modules"));
+        if let Some(manager) = self.manager {
+            meta = meta.input("manager", manager.program());
+        }
+        if let Some(frozen) = self.frozen {
+            meta = meta.input("frozen", frozen);
+        }
+        meta
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        if !ctx.will_exist(&self.path.join("package.json")) {
+            return Err(ShiftError::Custom(format!(
+                "{} does not exist and no earlier shift creates it",
+                self.path.join("package.json").display()
+            )));
+        }
+        let dir = ctx.exec().resolve(&self.path)?;
+        ctx.require_program(self.manager_for(&dir).program())
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network, Capability::RunsArbitraryCode]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let dir = ctx.resolve(&self.path)?;
+        let manager = self.manager_for(&dir);
+        let frozen = self
+            .frozen
+            .unwrap_or_else(|| manager.lockfile(&dir).is_some());
+        ctx.info(&format!(
+            "installing with {}{}",
+            manager.program(),
+            if frozen { " (frozen lockfile)" } else { "" }
+        ));
+        Cmd::new(manager.program())
+            .args(manager.install_args(&dir, frozen))
+            .cwd(&self.path)
+            .output(ctx)?;
+        // A non-frozen install may have written the lockfile, so hash after.
+        let modules = dir.join("node_modules");
+        fs::create_dir_all(&modules)?;
+        fs::write(modules.join(MARKER), self.inputs_hash(&dir)?)?;
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let modules = ctx.resolve(&self.path.join("node_modules"))?;
+        if modules.exists() {
+            fs::remove_dir_all(modules)?;
+        }
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        let dir = ctx.resolve(&self.path)?;
+        let Ok(recorded) = fs::read_to_string(dir.join("node_modules").join(MARKER)) else {
+            return Ok(false);
+        };
+        if !dir.join("package.json").exists() {
+            return Ok(false);
+        }
+        Ok(recorded == self.inputs_hash(&dir)?)
+    }
+}
@@ -0,0 +1,120 @@
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::artifacts::Artifact;
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::metadata::ShiftMetadata;
+use crate::packages::PackageManager;
+use crate::resource::{Claim, Resource};
+use crate::shift::{Shift, ShiftOutcome};
+use crate::validate::ValidationContext;
+
+/// Installs system packages with whichever package manager the machine
+/// has: apt, dnf, yum, pacman, zypper or apk, or Homebrew on macOS.
+///
+/// `packages` are the distribution's names, passed to the package manager
+/// as they are. Applied while every one of them is installed. Revert
+/// removes only the packages this shift installed.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Package {
+    packages: Vec<String>,
+}
+
+impl Package {
+    pub fn new(packages: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Package {
+            packages: packages.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    fn missing(&self, ctx: &ExecutionContext, manager: PackageManager) -> ShiftResult<Vec<&str>> {
+        let mut missing = Vec::new();
+        for package in &self.packages {
+            if !manager.is_installed(ctx, package)? {
+                missing.push(package.as_str());
+            }
+        }
+        Ok(missing)
+    }
+}
+
+impl Shift for Package {
+    fn metadata(&self) -> ShiftMetadata {
+        let mut meta =
+            ShiftMetadata::new("package", format!("install {}", self.packages.join(", ")))
+                .input("packages", &self.packages);
+        // Most packages install a program of the same name.
+        for package in &self.packages {
+            meta = meta.program(package);
+        }
+        meta
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![Claim::exclusive(Resource::lock("packages"))]
+    }
+
+    fn validate(&self, _ctx: &ValidationContext) -> ShiftResult<()> {
+        if self.packages.is_empty() {
+            return Err(ShiftError::Custom(
+                "nothing to install: `packages` is empty".into(),
+            ));
+        }
+        if let Some(package) = self
+            .packages
+            .iter()
+            .find(|package| package.is_empty() || package.starts_with('-'))
+        {
+            return Err(ShiftError::Custom(format!(
+                "`{package}` is not a package name"
+            )));
+        }
+        PackageManager::require().map(drop)
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network, Capability::Sudo]
+    }
+
+    fn artifacts(&self, _ctx: &ExecutionContext) -> ShiftResult<Vec<Artifact>> {
+        Ok(vec![Artifact::Packages(self.packages.clone())])
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let manager = PackageManager::require()?;
+        let missing = self.missing(ctx, manager)?;
+        if missing.is_empty() {
+            return Ok(ShiftOutcome::Unchanged);
+        }
+        manager.install(ctx, &missing)?;
+        let mut installed: Vec<Value> = match ctx.get_state("installed") {
+            Some(Value::Array(installed)) => installed,
+            _ => Vec::new(),
+        };
+        installed.extend(missing.into_iter().map(Value::from));
+        ctx.set_state("installed", json!(installed));
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        if let Some(Value::Array(installed)) = ctx.get_state("installed") {
+            let names: Vec<&str> = installed.iter().filter_map(Value::as_str).collect();
+            if !names.is_empty() {
+                PackageManager::require()?.remove(ctx, &names)?;
+            }
+        }
+        ctx.clear_state("installed");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        let Some(manager) = PackageManager::detect() else {
+            return Ok(false);
+        };
+        Ok(self.missing(ctx, manager)?.is_empty())
+    }
+}
@@ -0,0 +1,696 @@
+//! PostgreSQL roles, databases and extensions, managed through `psql`.
+//!
+//! Every shift takes a `connection` table: `host`, `port` and `user` as
+//! for `psql`, `password_env` naming the variable that holds the password,
+//! and `run_as` to connect as another system user, typically `postgres`
+//! for peer authentication on a local server. Left empty, `psql` uses its
+//! defaults and the `PG*` environment variables.
+//!
+//! Statements carrying a password are passed to `psql` in a file readable
+//! only by the connecting user, never on the command line.
+
+use std::fs;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::metadata::ShiftMetadata;
+use crate::permissions;
+use crate::resource::{Claim, Resource};
+use crate::run_as;
+use crate::shift::{Shift, ShiftOutcome};
+use crate::shifts::Cmd;
+use crate::validate::ValidationContext;
+
+/// Where and how to connect.
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PgConnection {
+    #[serde(default)]
+    host: Option<String>,
+    #[serde(default)]
+    port: Option<u16>,
+    #[serde(default)]
+    user: Option<String>,
+    #[serde(default)]
+    password_env: Option<String>,
+    #[serde(default)]
+    run_as: Option<String>,
+}
+
+impl PgConnection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn host(mut self, host: impl Into<String>) -> Self {
+        self.host = Some(host.into());
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    pub fn user(mut self, user: impl Into<String>) -> Self {
+        self.user = Some(user.into());
+        self
+    }
+
+    /// Reads the password from the environment variable `var`.
+    pub fn password_env(mut self, var: impl Into<String>) -> Self {
+        self.password_env = Some(var.into());
+        self
+    }
+
+    /// Connects as the system user `user`, e.g. `postgres`.
+    pub fn run_as(mut self, user: impl Into<String>) -> Self {
+        self.run_as = Some(user.into());
+        self
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        ctx.require_program("psql")?;
+        if let Some(var) = &self.password_env {
+            ctx.require_env(var)?;
+        }
+        if let Some(user) = &self.run_as {
+            run_as::check(ctx, user, true)?;
+        }
+        Ok(())
+    }
+
+    fn psql(&self, database: &str) -> Cmd {
+        let mut cmd = Cmd::new("psql").args(["-X", "-q", "-A", "-t", "-v", "ON_ERROR_STOP=1"]);
+        if let Some(host) = &self.host {
+            cmd = cmd.args(["-h", host]);
+        }
+        if let Some(port) = self.port {
+            cmd = cmd.arg("-p").arg(port.to_string());
+        }
+        if let Some(user) = &self.user {
+            cmd = cmd.args(["-U", user]);
+        }
+        cmd = cmd.args(["-d", database]);
+        if let Some(password) = self
+            .password_env
+            .as_ref()
+            .and_then(|var| std::env::var(var).ok())
+        {
+            cmd = cmd.env("PGPASSWORD", password);
+        }
+        if let Some(user) = &self.run_as {
+            cmd = cmd.run_as(user);
+        }
+        cmd
+    }
+
+    /// Runs `sql` in `database` and returns its output, one row per line.
+    fn query(&self, ctx: &ExecutionContext, database: &str, sql: &str) -> ShiftResult<String> {
+        let out = self.psql(database).args(["-c", sql]).output(ctx)?;
+        Ok(out.trim().to_string())
+    }
+
+    /// Runs `sql`, which holds a secret, from a private file.
+    fn execute_secret(&self, ctx: &ExecutionContext, database: &str, sql: &str) -> ShiftResult<()> {
+        let file = ctx.temp_dir()?.join("statement.sql");
+        permissions::write_file(&file, sql.as_bytes(), Some(0o600))?;
+        if let Some(user) = &self.run_as {
+            if !run_as::is_current(ctx, user)? {
+                run_as::chown(&file, run_as::ids(ctx, user)?)?;
+            }
+        }
+        let result = self
+            .psql(database)
+            .arg("-f")
+            .arg(file.display().to_string())
+            .output(ctx);
+        fs::remove_file(&file)?;
+        result.map(drop)
+    }
+
+    fn describe(&self) -> String {
+        match (&self.host, self.port) {
+            (Some(host), Some(port)) => format!("{host}:{port}"),
+            (Some(host), None) => host.clone(),
+            (None, _) => "local".to_string(),
+        }
+    }
+}
+
+/// `name` as an SQL identifier.
+fn ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// `text` as an SQL string literal.
+fn literal(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+fn check_name(what: &str, name: &str) -> ShiftResult<()> {
+    if name.is_empty() || name.len() > 63 || name.contains('\0') {
+        return Err(ShiftError::Custom(format!(
+            "`{name}` is not a usable {what} name"
+        )));
+    }
+    Ok(())
+}
+
+/// A PostgreSQL role, by default one that can log in.
+///
+/// `superuser`, `createdb` and `createrole` grant those attributes. With
+/// `password_env`, a new role gets the password in that variable; an
+/// existing role's password is left alone, since it cannot be checked.
+/// Applied while the role exists with the given attributes. Revert drops
+/// a role this shift created, or else puts its attributes back.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PgRole {
+    name: String,
+    #[serde(default = "default_login")]
+    login: bool,
+    #[serde(default)]
+    superuser: bool,
+    #[serde(default)]
+    createdb: bool,
+    #[serde(default)]
+    createrole: bool,
+    #[serde(default)]
+    password_env: Option<String>,
+    #[serde(default)]
+    connection: PgConnection,
+}
+
+fn default_login() -> bool {
+    true
+}
+
+/// Role attributes, in `pg_roles` column order.
+const ROLE_ATTRIBUTES: [(&str, &str); 4] = [
+    ("rolcanlogin", "LOGIN"),
+    ("rolsuper", "SUPERUSER"),
+    ("rolcreatedb", "CREATEDB"),
+    ("rolcreaterole", "CREATEROLE"),
+];
+
+impl PgRole {
+    pub fn new(name: impl Into<String>) -> Self {
+        PgRole {
+            name: name.into(),
+            login: default_login(),
+            superuser: false,
+            createdb: false,
+            createrole: false,
+            password_env: None,
+            connection: PgConnection::default(),
+        }
+    }
+
+    pub fn login(mut self, login: bool) -> Self {
+        self.login = login;
+        self
+    }
+
+    pub fn superuser(mut self, superuser: bool) -> Self {
+        self.superuser = superuser;
+        self
+    }
+
+    pub fn createdb(mut self, createdb: bool) -> Self {
+        self.createdb = createdb;
+        self
+    }
+
+    pub fn createrole(mut self, createrole: bool) -> Self {
+        self.createrole = createrole;
+        self
+    }
+
+    /// Gives a new role the password in the environment variable `var`.
+    pub fn password_env(mut self, var: impl Into<String>) -> Self {
+        self.password_env = Some(var.into());
+        self
+    }
+
+    pub fn connection(mut self, connection: PgConnection) -> Self {
+        self.connection = connection;
+        self
+    }
+
+    fn wanted(&self) -> [bool; 4] {
+        [self.login, self.superuser, self.createdb, self.createrole]
+    }
+
+    /// The role's attributes, or `None` if it does not exist.
+    fn attributes(&self, ctx: &ExecutionContext) -> ShiftResult<Option<[bool; 4]>> {
+        let columns: Vec<&str> = ROLE_ATTRIBUTES.iter().map(|(column, _)| *column).collect();
+        let sql = format!(
+            "SELECT {} FROM pg_roles WHERE rolname = {}",
+            columns.join(", "),
+            literal(&self.name)
+        );
+        let out = self.connection.query(ctx, "postgres", &sql)?;
+        if out.is_empty() {
+            return Ok(None);
+        }
+        let mut attributes = [false; 4];
+        for (attribute, value) in attributes.iter_mut().zip(out.split('|')) {
+            *attribute = value == "t";
+        }
+        Ok(Some(attributes))
+    }
+
+    fn options(attributes: [bool; 4]) -> String {
+        ROLE_ATTRIBUTES
+            .iter()
+            .zip(attributes)
+            .map(|((_, option), on)| match on {
+                true => option.to_string(),
+                false => format!("NO{option}"),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+impl Shift for PgRole {
+    fn metadata(&self) -> ShiftMetadata {
+        let meta = ShiftMetadata::new(
+            "pg_role",
+            format!("create the PostgreSQL role {}", self.name),
+        )
+        .input("name", &self.name)
+        .input("attributes", Self::options(self.wanted()))
+        .input("server", self.connection.describe());
+        match &self.password_env {
+            Some(var) => meta.input("password_env", var),
+            None => meta,
+        }
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![Claim::exclusive(Resource::lock(format!(
+            "postgres role {}",
+            self.name
+        )))]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        check_name("role", &self.name)?;
+        if let Some(var) = &self.password_env {
+            ctx.require_env(var)?;
+        }
+        self.connection.validate(ctx)
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let wanted = self.wanted();
+        match self.attributes(ctx)? {
+            Some(current) if current == wanted => Ok(ShiftOutcome::Unchanged),
+            Some(current) => {
+                if ctx.get_state("previous").is_none() {
+                    ctx.set_state("previous", json!(current));
+                }
+                let sql = format!(
+                    "ALTER ROLE {} WITH {}",
+                    ident(&self.name),
+                    Self::options(wanted)
+                );
+                self.connection.query(ctx, "postgres", &sql)?;
+                Ok(ShiftOutcome::Changed)
+            }
+            None => {
+                let mut sql = format!(
+                    "CREATE ROLE {} WITH {}",
+                    ident(&self.name),
+                    Self::options(wanted)
+                );
+                let password = self
+                    .password_env
+                    .as_ref()
+                    .map(|var| {
+                        std::env::var(var).map_err(|_| {
+                            ShiftError::Custom(format!("environment variable `{var}` is not set"))
+                        })
+                    })
+                    .transpose()?;
+                match password {
+                    Some(password) => {
+                        sql.push_str(&format!(" PASSWORD {};\n", literal(&password)));
+                        self.connection.execute_secret(ctx, "postgres", &sql)?;
+                    }
+                    None => {
+                        self.connection.query(ctx, "postgres", &sql)?;
+                    }
+                }
+                ctx.set_state("created", json!(true));
+                Ok(ShiftOutcome::Changed)
+            }
+        }
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        if ctx.get_state("created") == Some(json!(true)) {
+            let sql = format!("DROP ROLE IF EXISTS {}", ident(&self.name));
+            self.connection
+                .query(ctx, "postgres", &sql)
+                .map_err(|err| {
+                    err.hint("drop or reassign what the role owns first, e.g. with a database shift's revert")
+                })?;
+        } else if let Some(previous) = ctx
+            .get_state("previous")
+            .and_then(|value| serde_json::from_value::<[bool; 4]>(value).ok())
+        {
+            if self.attributes(ctx)?.is_some() {
+                let sql = format!(
+                    "ALTER ROLE {} WITH {}",
+                    ident(&self.name),
+                    Self::options(previous)
+                );
+                self.connection.query(ctx, "postgres", &sql)?;
+            }
+        }
+        ctx.clear_state("created");
+        ctx.clear_state("previous");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        Ok(self.attributes(ctx)? == Some(self.wanted()))
+    }
+}
+
+/// A PostgreSQL database, optionally with an `owner`.
+///
+/// `encoding` and `template` only apply when the database is created.
+/// Applied while the database exists with the owner, if one is given.
+/// Revert drops a database this shift created, but refuses while it holds
+/// tables unless `--force` is passed, or else gives it back to its
+/// previous owner.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PgDatabase {
+    name: String,
+    #[serde(default)]
+    owner: Option<String>,
+    #[serde(default)]
+    encoding: Option<String>,
+    #[serde(default)]
+    template: Option<String>,
+    #[serde(default)]
+    connection: PgConnection,
+}
+
+impl PgDatabase {
+    pub fn new(name: impl Into<String>) -> Self {
+        PgDatabase {
+            name: name.into(),
+            owner: None,
+            encoding: None,
+            template: None,
+            connection: PgConnection::default(),
+        }
+    }
+
+    pub fn owner(mut self, owner: impl Into<String>) -> Self {
+        self.owner = Some(owner.into());
+        self
+    }
+
+    pub fn encoding(mut self, encoding: impl Into<String>) -> Self {
+        self.encoding = Some(encoding.into());
+        self
+    }
+
+    pub fn template(mut self, template: impl Into<String>) -> Self {
+        self.template = Some(template.into());
+        self
+    }
+
+    pub fn connection(mut self, connection: PgConnection) -> Self {
+        self.connection = connection;
+        self
+    }
+
+    /// The database's owner, or `None` if it does not exist.
+    fn current_owner(&self, ctx: &ExecutionContext) -> ShiftResult<Option<String>> {
+        let sql = format!(
+            "SELECT pg_get_userbyid(datdba) FROM pg_database WHERE datname = {}",
+            literal(&self.name)
+        );
+        let out = self.connection.query(ctx, "postgres", &sql)?;
+        Ok((!out.is_empty()).then_some(out))
+    }
+
+    fn set_owner(&self, ctx: &ExecutionContext, owner: &str) -> ShiftResult<()> {
+        let sql = format!(
+            "ALTER DATABASE {} OWNER TO {}",
+            ident(&self.name),
+            ident(owner)
+        );
+        self.connection.query(ctx, "postgres", &sql).map(drop)
+    }
+
+    /// How many user tables the database holds.
+    fn tables(&self, ctx: &ExecutionContext) -> ShiftResult<u64> {
+        let sql = "SELECT count(*) FROM information_schema.tables \
+                   WHERE table_schema NOT IN ('pg_catalog', 'information_schema')";
+        let out = self.connection.query(ctx, &self.name, sql)?;
+        Ok(out.parse().unwrap_or_default())
+    }
+}
+
+impl Shift for PgDatabase {
+    fn metadata(&self) -> ShiftMetadata {
+        let mut meta = ShiftMetadata::new(
+            "pg_database",
+            format!("create the PostgreSQL database {}", self.name),
+        )
+        .input("name", &self.name)
+        .input("server", self.connection.describe());
+        if let Some(owner) = &self.owner {
+            meta = meta.input("owner", owner);
+        }
+        meta
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![Claim::exclusive(Resource::lock(format!(
+            "postgres database {}",
+            self.name
+        )))]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        check_name("database", &self.name)?;
+        if let Some(owner) = &self.owner {
+            check_name("role", owner)?;
+        }
+        self.connection.validate(ctx)
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        match (self.current_owner(ctx)?, &self.owner) {
+            (Some(current), Some(owner)) if &current != owner => {
+                if ctx.get_state("previous_owner").is_none() {
+                    ctx.set_state("previous_owner", json!(current));
+                }
+                self.set_owner(ctx, owner)?;
+                Ok(ShiftOutcome::Changed)
+            }
+            (Some(_), _) => Ok(ShiftOutcome::Unchanged),
+            (None, _) => {
+                let mut sql = format!("CREATE DATABASE {}", ident(&self.name));
+                if let Some(owner) = &self.owner {
+                    sql.push_str(&format!(" OWNER {}", ident(owner)));
+                }
+                if let Some(encoding) = &self.encoding {
+                    sql.push_str(&format!(" ENCODING {}", literal(encoding)));
+                }
+                if let Some(template) = &self.template {
+                    sql.push_str(&format!(" TEMPLATE {}", ident(template)));
+                }
+                self.connection.query(ctx, "postgres", &sql)?;
+                ctx.set_state("created", json!(true));
+                Ok(ShiftOutcome::Changed)
+            }
+        }
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let exists = self.current_owner(ctx)?.is_some();
+        if ctx.get_state("created") == Some(json!(true)) && exists {
+            let tables = self.tables(ctx)?;
+            if tables > 0 && !ctx.is_forced() {
+                return Err(ShiftError::Conflict {
+                    resource: format!("database {}", self.name),
+                    reason: format!("still holds {tables} tables"),
+                }
+                .hint("back up or drop them first, or pass --force to drop the database anyway"));
+            }
+            let sql = format!("DROP DATABASE {}", ident(&self.name));
+            self.connection.query(ctx, "postgres", &sql)?;
+        } else if let (Some(Value::String(previous)), true) =
+            (ctx.get_state("previous_owner"), exists)
+        {
+            self.set_owner(ctx, &previous)?;
+        }
+        ctx.clear_state("created");
+        ctx.clear_state("previous_owner");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        Ok(match (self.current_owner(ctx)?, &self.owner) {
+            (Some(current), Some(owner)) => &current == owner,
+            (current, None) => current.is_some(),
+            (None, Some(_)) => false,
+        })
+    }
+}
+
+/// A PostgreSQL extension in `database`, e.g. `pgcrypto` or `postgis`.
+///
+/// With `version`, an older installed version is updated to it. Applied
+/// while the extension is installed (at `version`, if given). Revert drops
+/// an extension this shift created.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PgExtension {
+    name: String,
+    database: String,
+    #[serde(default)]
+    schema: Option<String>,
+    #[serde(default)]
+    version: Option<String>,
+    #[serde(default)]
+    connection: PgConnection,
+}
+
+impl PgExtension {
+    pub fn new(name: impl Into<String>, database: impl Into<String>) -> Self {
+        PgExtension {
+            name: name.into(),
+            database: database.into(),
+            schema: None,
+            version: None,
+            connection: PgConnection::default(),
+        }
+    }
+
+    pub fn schema(mut self, schema: impl Into<String>) -> Self {
+        self.schema = Some(schema.into());
+        self
+    }
+
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    pub fn connection(mut self, connection: PgConnection) -> Self {
+        self.connection = connection;
+        self
+    }
+
+    /// The installed version, if the extension is installed.
+    fn installed(&self, ctx: &ExecutionContext) -> ShiftResult<Option<String>> {
+        let sql = format!(
+            "SELECT extversion FROM pg_extension WHERE extname = {}",
+            literal(&self.name)
+        );
+        let out = self.connection.query(ctx, &self.database, &sql)?;
+        Ok((!out.is_empty()).then_some(out))
+    }
+}
+
+impl Shift for PgExtension {
+    fn metadata(&self) -> ShiftMetadata {
+        let mut meta = ShiftMetadata::new(
+            "pg_extension",
+            format!("install the {} extension in {}", self.name, self.database),
+        )
+        .input("name", &self.name)
+        .input("database", &self.database)
+        .input("server", self.connection.describe());
+        if let Some(version) = &self.version {
+            meta = meta.input("version", version);
+        }
+        meta
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![Claim::shared(Resource::lock(format!(
+            "postgres database {}",
+            self.database
+        )))]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        check_name("extension", &self.name)?;
+        check_name("database", &self.database)?;
+        self.connection.validate(ctx)
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        match (self.installed(ctx)?, &self.version) {
+            (Some(current), Some(version)) if &current != version => {
+                let sql = format!(
+                    "ALTER EXTENSION {} UPDATE TO {}",
+                    ident(&self.name),
+                    literal(version)
+                );
+                self.connection.query(ctx, &self.database, &sql)?;
+                Ok(ShiftOutcome::Changed)
+            }
+            (Some(_), _) => Ok(ShiftOutcome::Unchanged),
+            (None, _) => {
+                let mut sql = format!("CREATE EXTENSION IF NOT EXISTS {}", ident(&self.name));
+                if let Some(schema) = &self.schema {
+                    sql.push_str(&format!(" SCHEMA {}", ident(schema)));
+                }
+                if let Some(version) = &self.version {
+                    sql.push_str(&format!(" VERSION {}", literal(version)));
+                }
+                self.connection.query(ctx, &self.database, &sql)?;
+                ctx.set_state("created", json!(true));
+                Ok(ShiftOutcome::Changed)
+            }
+        }
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        if ctx.get_state("created") == Some(json!(true)) {
+            let sql = format!("DROP EXTENSION IF EXISTS {}", ident(&self.name));
+            self.connection.query(ctx, &self.database, &sql)?;
+        }
+        ctx.clear_state("created");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        Ok(match (self.installed(ctx)?, &self.version) {
+            (Some(current), Some(version)) => &current == version,
+            (installed, None) => installed.is_some(),
+            (None, Some(_)) => false,
+        })
+    }
+}
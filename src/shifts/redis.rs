@@ -0,0 +1,443 @@
+//! Redis configuration and readiness, through `redis-cli`.
+//!
+//! Both shifts take a `connection` table: `host` and `port`, or `socket`
+//! for a Unix socket, and `password_env` naming the variable that holds the
+//! password. Left empty, `redis-cli` connects to `127.0.0.1:6379`. The
+//! password is handed over in `REDISCLI_AUTH`, never on the command line.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::metadata::ShiftMetadata;
+use crate::plan_file::de;
+use crate::resource::{Claim, Resource};
+use crate::shift::{Shift, ShiftOutcome};
+use crate::shifts::Cmd;
+use crate::validate::ValidationContext;
+
+const DEFAULT_PORT: u16 = 6379;
+
+/// Where and how to connect.
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RedisConnection {
+    #[serde(default)]
+    host: Option<String>,
+    #[serde(default)]
+    port: Option<u16>,
+    #[serde(default)]
+    socket: Option<PathBuf>,
+    #[serde(default)]
+    password_env: Option<String>,
+}
+
+impl RedisConnection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn host(mut self, host: impl Into<String>) -> Self {
+        self.host = Some(host.into());
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    /// Connects through the Unix socket at `socket` instead of TCP.
+    pub fn socket(mut self, socket: impl Into<PathBuf>) -> Self {
+        self.socket = Some(socket.into());
+        self
+    }
+
+    /// Reads the password from the environment variable `var`.
+    pub fn password_env(mut self, var: impl Into<String>) -> Self {
+        self.password_env = Some(var.into());
+        self
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        ctx.require_program("redis-cli")?;
+        if self.socket.is_some() && (self.host.is_some() || self.port.is_some()) {
+            return Err(ShiftError::Custom(
+                "set `socket` or `host` and `port`, not both".into(),
+            ));
+        }
+        if let Some(var) = &self.password_env {
+            ctx.require_env(var)?;
+        }
+        Ok(())
+    }
+
+    /// Runs one Redis command and returns its reply. Error replies, which
+    /// `redis-cli` prints without failing, are turned into errors.
+    fn command(&self, ctx: &ExecutionContext, args: &[&str]) -> ShiftResult<String> {
+        let mut cmd = Cmd::new("redis-cli").arg("--raw");
+        match &self.socket {
+            Some(socket) => cmd = cmd.arg("-s").arg(socket.display().to_string()),
+            None => {
+                if let Some(host) = &self.host {
+                    cmd = cmd.args(["-h", host]);
+                }
+                if let Some(port) = self.port {
+                    cmd = cmd.arg("-p").arg(port.to_string());
+                }
+            }
+        }
+        if let Some(password) = self
+            .password_env
+            .as_ref()
+            .and_then(|var| std::env::var(var).ok())
+        {
+            cmd = cmd.env("REDISCLI_AUTH", password);
+        }
+        let out = cmd.args(args.iter().copied()).output(ctx)?;
+        let reply = out.trim_end().to_string();
+        let first = reply.split_whitespace().next().unwrap_or_default();
+        let is_error = matches!(
+            first,
+            "ERR" | "WRONGPASS" | "NOAUTH" | "NOPERM" | "LOADING" | "MASTERDOWN" | "BUSY"
+        ) || reply.starts_with("Could not connect");
+        if is_error {
+            return Err(ShiftError::Command {
+                command: format!("redis-cli {}", args.join(" ")),
+                code: None,
+                stderr: reply,
+            });
+        }
+        Ok(reply)
+    }
+
+    fn describe(&self) -> String {
+        match &self.socket {
+            Some(socket) => socket.display().to_string(),
+            None => format!(
+                "{}:{}",
+                self.host.as_deref().unwrap_or("127.0.0.1"),
+                self.port.unwrap_or(DEFAULT_PORT)
+            ),
+        }
+    }
+
+    fn resource(&self) -> Resource {
+        Resource::lock(format!("redis {}", self.describe()))
+    }
+}
+
+/// A config value as Redis reports it back: sizes written with a unit
+/// (`100mb`, `1g`) become bytes, and everything is lower-cased.
+fn normalize(value: &str) -> String {
+    let value = value.trim().to_lowercase();
+    let digits = value.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let factor: u64 = match &value[digits.len()..] {
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return value,
+    };
+    match digits.parse::<u64>() {
+        Ok(n) => (n * factor).to_string(),
+        Err(_) => value,
+    }
+}
+
+/// Sets Redis config parameters on a running server with `CONFIG SET`.
+///
+/// `settings` maps parameter names to values; numbers and booleans are
+/// accepted, the latter written as `yes` and `no`. With `persist`, the
+/// default, `CONFIG REWRITE` then saves them to the server's config file
+/// so they survive a restart. Applied while `CONFIG GET` reports every
+/// value. Revert sets the values back to what they were before the first
+/// apply.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RedisConfig {
+    #[schemars(with = "BTreeMap<String, serde_json::Value>")]
+    settings: BTreeMap<String, toml::Value>,
+    #[serde(default = "default_persist")]
+    persist: bool,
+    #[serde(default)]
+    connection: RedisConnection,
+}
+
+fn default_persist() -> bool {
+    true
+}
+
+impl RedisConfig {
+    pub fn new() -> Self {
+        RedisConfig {
+            settings: BTreeMap::new(),
+            persist: default_persist(),
+            connection: RedisConnection::default(),
+        }
+    }
+
+    pub fn set(mut self, name: impl Into<String>, value: impl Into<toml::Value>) -> Self {
+        self.settings.insert(name.into(), value.into());
+        self
+    }
+
+    pub fn persist(mut self, persist: bool) -> Self {
+        self.persist = persist;
+        self
+    }
+
+    pub fn connection(mut self, connection: RedisConnection) -> Self {
+        self.connection = connection;
+        self
+    }
+
+    fn wanted(&self) -> ShiftResult<BTreeMap<&str, String>> {
+        self.settings
+            .iter()
+            .map(|(name, value)| {
+                let value = match value {
+                    toml::Value::String(s) => s.clone(),
+                    toml::Value::Integer(n) => n.to_string(),
+                    toml::Value::Float(f) => f.to_string(),
+                    toml::Value::Boolean(true) => "yes".to_string(),
+                    toml::Value::Boolean(false) => "no".to_string(),
+                    _ => {
+                        return Err(ShiftError::Custom(format!(
+                            "redis setting `{name}` must be a string, number or boolean"
+                        )))
+                    }
+                };
+                Ok((name.as_str(), value))
+            })
+            .collect()
+    }
+
+    /// The current value of `name`, with `CONFIG GET`.
+    fn get(&self, ctx: &ExecutionContext, name: &str) -> ShiftResult<String> {
+        let reply = self.connection.command(ctx, &["CONFIG", "GET", name])?;
+        let mut lines = reply.lines();
+        match (lines.next(), lines.next()) {
+            (Some(_), value) => Ok(value.unwrap_or_default().to_string()),
+            (None, _) => Err(ShiftError::Custom(format!(
+                "redis has no config parameter `{name}`"
+            ))),
+        }
+    }
+
+    fn set_all<'a>(
+        &self,
+        ctx: &ExecutionContext,
+        values: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> ShiftResult<()> {
+        for (name, value) in values {
+            self.connection
+                .command(ctx, &["CONFIG", "SET", name, value])
+                .map_err(|err| err.context(format!("setting redis `{name}`")))?;
+        }
+        if self.persist {
+            self.connection
+                .command(ctx, &["CONFIG", "REWRITE"])
+                .map_err(|err| {
+                    err.hint("the server needs a config file to save to; set `persist = false` to skip saving")
+                })?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for RedisConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shift for RedisConfig {
+    fn metadata(&self) -> ShiftMetadata {
+        let names: Vec<&str> = self.settings.keys().map(String::as_str).collect();
+        let mut meta = ShiftMetadata::new(
+            "redis_config",
+            format!(
+                "set redis {} on {}",
+                names.join(", "),
+                self.connection.describe()
+            ),
+        )
+        .input("server", self.connection.describe())
+        .input("persist", self.persist);
+        for (name, value) in self.wanted().unwrap_or_default() {
+            meta = meta.input(format!("settings.{name}"), value);
+        }
+        meta
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![Claim::exclusive(self.connection.resource())]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        if self.settings.is_empty() {
+            return Err(ShiftError::Custom("no `settings` to set".into()));
+        }
+        self.wanted()?;
+        self.connection.validate(ctx)
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let mut previous = match ctx.get_state("previous") {
+            Some(Value::Object(previous)) => previous,
+            _ => serde_json::Map::new(),
+        };
+        let mut changes = Vec::new();
+        for (name, value) in self.wanted()? {
+            let current = self.get(ctx, name)?;
+            if normalize(&current) != normalize(&value) {
+                previous.entry(name).or_insert(json!(current));
+                changes.push((name, value));
+            }
+        }
+        if changes.is_empty() {
+            return Ok(ShiftOutcome::Unchanged);
+        }
+        ctx.set_state("previous", Value::Object(previous));
+        self.set_all(
+            ctx,
+            changes.iter().map(|(name, value)| (*name, value.as_str())),
+        )?;
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        if let Some(Value::Object(previous)) = ctx.get_state("previous") {
+            let values: Vec<(&str, &str)> = previous
+                .iter()
+                .filter_map(|(name, value)| Some((name.as_str(), value.as_str()?)))
+                .collect();
+            self.set_all(ctx, values)?;
+        }
+        ctx.clear_state("previous");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        for (name, value) in self.wanted()? {
+            if normalize(&self.get(ctx, name)?) != normalize(&value) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Waits until a Redis server answers `PING`, for plans that start one and
+/// then use it.
+///
+/// Polls every half second for up to `timeout` seconds, 30 by default; a
+/// server still loading its dataset counts as not ready. Like
+/// [`Assert`](crate::shifts::Assert), it changes nothing, never counts as
+/// applied, and has nothing to revert.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RedisReady {
+    #[serde(default, deserialize_with = "de::opt_secs")]
+    #[schemars(with = "Option<f64>")]
+    timeout: Option<Duration>,
+    #[serde(default)]
+    connection: RedisConnection,
+}
+
+const DEFAULT_READY_TIMEOUT: Duration = Duration::from_secs(30);
+
+impl RedisReady {
+    pub fn new() -> Self {
+        RedisReady {
+            timeout: None,
+            connection: RedisConnection::default(),
+        }
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn connection(mut self, connection: RedisConnection) -> Self {
+        self.connection = connection;
+        self
+    }
+}
+
+impl Default for RedisReady {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shift for RedisReady {
+    fn metadata(&self) -> ShiftMetadata {
+        let timeout = self.timeout.unwrap_or(DEFAULT_READY_TIMEOUT);
+        ShiftMetadata::new(
+            "redis_ready",
+            format!("wait for redis on {}", self.connection.describe()),
+        )
+        .input("server", self.connection.describe())
+        .input("timeout", timeout.as_secs_f64())
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![Claim::shared(self.connection.resource())]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        self.connection.validate(ctx)
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let timeout = self.timeout.unwrap_or(DEFAULT_READY_TIMEOUT);
+        let deadline = Instant::now().checked_add(timeout);
+        loop {
+            let why = match self.connection.command(ctx, &["PING"]) {
+                Ok(reply) if reply == "PONG" => return Ok(ShiftOutcome::Unchanged),
+                Ok(reply) => reply,
+                Err(ShiftError::Command { stderr, .. }) => stderr,
+                Err(err) => return Err(err),
+            };
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Err(ShiftError::TimedOut(format!(
+                    "redis on {} was not ready after {}s: {}",
+                    self.connection.describe(),
+                    timeout.as_secs_f32(),
+                    why.trim()
+                )));
+            }
+            ctx.check_cancelled()?;
+            thread::sleep(Duration::from_millis(500));
+        }
+    }
+
+    fn revert(&self, _ctx: &ExecutionContext) -> ShiftResult<()> {
+        Ok(())
+    }
+
+    fn is_applied(&self, _ctx: &ExecutionContext) -> ShiftResult<bool> {
+        Ok(false)
+    }
+}
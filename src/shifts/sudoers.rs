@@ -0,0 +1,308 @@
+use std::path::{Path, PathBuf};
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::fs::read_text;
+use crate::metadata::ShiftMetadata;
+use crate::permissions;
+use crate::resource::{Claim, Resource};
+use crate::shift::{Shift, ShiftOutcome};
+use crate::shifts::Cmd;
+use crate::validate::ValidationContext;
+
+/// Grants sudo rights through a drop-in, `/etc/sudoers.d/<name>`.
+///
+/// The rule lets `user`, or members of `group`, run `commands` (every
+/// command by default) as `runas`, root unless set; with `nopasswd` they
+/// need no password. `rules` instead gives the drop-in's lines as is.
+///
+/// A broken sudoers file can lock everyone out of sudo, so the drop-in is
+/// written next to its final path under a name sudo ignores, checked with
+/// `visudo -c`, and only then moved into place; if the full configuration
+/// then fails `visudo -c`, the previous drop-in is put back. Applied while
+/// the drop-in holds the rule with mode 0440. Revert restores the drop-in
+/// as it was before the first apply, or removes it.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SudoersRule {
+    name: String,
+    #[serde(default)]
+    user: Option<String>,
+    #[serde(default)]
+    group: Option<String>,
+    #[serde(default)]
+    commands: Vec<String>,
+    #[serde(default)]
+    runas: Option<String>,
+    #[serde(default)]
+    nopasswd: bool,
+    #[serde(default)]
+    rules: Vec<String>,
+    #[serde(default = "default_dir")]
+    dir: PathBuf,
+}
+
+fn default_dir() -> PathBuf {
+    PathBuf::from("/etc/sudoers.d")
+}
+
+const MODE: u32 = 0o440;
+
+impl SudoersRule {
+    fn new(name: impl Into<String>) -> Self {
+        SudoersRule {
+            name: name.into(),
+            user: None,
+            group: None,
+            commands: Vec::new(),
+            runas: None,
+            nopasswd: false,
+            rules: Vec::new(),
+            dir: default_dir(),
+        }
+    }
+
+    /// A rule for `user`, in the drop-in `name`.
+    pub fn user(name: impl Into<String>, user: impl Into<String>) -> Self {
+        SudoersRule {
+            user: Some(user.into()),
+            ..Self::new(name)
+        }
+    }
+
+    /// A rule for the members of `group`, in the drop-in `name`.
+    pub fn group(name: impl Into<String>, group: impl Into<String>) -> Self {
+        SudoersRule {
+            group: Some(group.into()),
+            ..Self::new(name)
+        }
+    }
+
+    /// A drop-in `name` holding `rules` verbatim.
+    pub fn rules<I, S>(name: impl Into<String>, rules: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        SudoersRule {
+            rules: rules.into_iter().map(Into::into).collect(),
+            ..Self::new(name)
+        }
+    }
+
+    /// Limits the rule to `command`, a full path with optional arguments.
+    pub fn command(mut self, command: impl Into<String>) -> Self {
+        self.commands.push(command.into());
+        self
+    }
+
+    pub fn runas(mut self, runas: impl Into<String>) -> Self {
+        self.runas = Some(runas.into());
+        self
+    }
+
+    pub fn nopasswd(mut self, nopasswd: bool) -> Self {
+        self.nopasswd = nopasswd;
+        self
+    }
+
+    pub fn dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = dir.into();
+        self
+    }
+
+    fn path(&self) -> PathBuf {
+        self.dir.join(&self.name)
+    }
+
+    fn who(&self) -> Option<String> {
+        match (&self.user, &self.group) {
+            (Some(user), None) => Some(user.clone()),
+            (None, Some(group)) => Some(format!("%{group}")),
+            _ => None,
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut text = String::from("# Managed by skies.\n");
+        if let Some(who) = self.who() {
+            let runas = self.runas.as_deref().unwrap_or("ALL");
+            let tag = if self.nopasswd { "NOPASSWD: " } else { "" };
+            let commands = match self.commands.is_empty() {
+                true => "ALL".to_string(),
+                false => self.commands.join(", "),
+            };
+            text.push_str(&format!("{who} ALL=({runas}) {tag}{commands}\n"));
+        }
+        for rule in &self.rules {
+            text.push_str(rule);
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Puts `contents` at `path` with mode 0440, via a checked staging
+    /// file. The staging name starts with `.`, which sudo skips.
+    fn install(ctx: &ExecutionContext, path: &Path, contents: &str) -> ShiftResult<()> {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let staging = path.with_file_name(format!(".skies-{name}"));
+        ctx.fs().write(&staging, contents.as_bytes(), Some(MODE))?;
+        let check = Cmd::new("visudo")
+            .args(["-c", "-q", "-f"])
+            .arg(staging.display().to_string())
+            .output(ctx);
+        if let Err(err) = check {
+            ctx.fs().remove_file(&staging)?;
+            return Err(err.context(format!("checking {} before installing it", path.display())));
+        }
+        ctx.fs().rename(&staging, path)
+    }
+
+    /// Checks the whole sudo configuration, now including the drop-in.
+    fn check_all(ctx: &ExecutionContext) -> ShiftResult<()> {
+        Cmd::new("visudo").args(["-c", "-q"]).output(ctx).map(drop)
+    }
+}
+
+impl Shift for SudoersRule {
+    fn metadata(&self) -> ShiftMetadata {
+        let summary = match self.who() {
+            Some(who) => format!("grant sudo to {who} in {}", self.path().display()),
+            None => format!("write sudo rules to {}", self.path().display()),
+        };
+        ShiftMetadata::new("sudoers", summary)
+            .target(self.path())
+            .input("rule", self.render())
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![Claim::exclusive(Resource::path(self.path()))]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        // sudo silently skips drop-ins whose names hold a `.` or end in `~`.
+        let valid = !self.name.is_empty()
+            && self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'));
+        if !valid {
+            return Err(ShiftError::Custom(format!(
+                "`{}` is not a usable drop-in name: sudo ignores names with `.` or `~`; \
+                 use letters, digits, `_` and `-`",
+                self.name
+            )));
+        }
+        match (&self.user, &self.group, self.rules.is_empty()) {
+            (Some(_), Some(_), _) => {
+                return Err(ShiftError::Custom("set `user` or `group`, not both".into()))
+            }
+            (None, None, true) => {
+                return Err(ShiftError::Custom(
+                    "nothing to grant: set `user`, `group` or `rules`".into(),
+                ))
+            }
+            _ => {}
+        }
+        if self.who().is_none() && (!self.commands.is_empty() || self.nopasswd) {
+            return Err(ShiftError::Custom(
+                "`commands` and `nopasswd` need a `user` or `group`".into(),
+            ));
+        }
+        if self.rules.iter().any(|rule| rule.contains('\n')) {
+            return Err(ShiftError::Custom(
+                "each of `rules` must be one line".into(),
+            ));
+        }
+        ctx.require_program("visudo")?;
+        ctx.exec().resolve(&self.path())?;
+        ctx.require_dir(&self.dir)
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Sudo]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        if self.is_applied(ctx)? {
+            return Ok(ShiftOutcome::Unchanged);
+        }
+        let path = ctx.resolve(&self.path())?;
+        let previous = read_text(ctx.fs(), &path)?;
+        Self::install(ctx, &path, &self.render())?;
+        if let Err(err) = Self::check_all(ctx) {
+            match &previous {
+                Some(previous) => Self::install(ctx, &path, previous)?,
+                None => ctx.fs().remove_file(&path)?,
+            }
+            return Err(err
+                .context(format!(
+                    "checking the sudo configuration with {}",
+                    path.display()
+                ))
+                .hint("the drop-in was put back as it was"));
+        }
+        if ctx.get_state("previous").is_none() {
+            ctx.set_state("previous", json!(previous));
+        }
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let path = ctx.resolve(&self.path())?;
+        match ctx.get_state("previous") {
+            Some(Value::String(previous)) => Self::install(ctx, &path, &previous)?,
+            Some(_) if ctx.fs().exists(&path) => ctx.fs().remove_file(&path)?,
+            _ => {}
+        }
+        ctx.clear_state("previous");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        let path = ctx.resolve(&self.path())?;
+        if read_text(ctx.fs(), &path)?.as_deref() != Some(self.render().as_str()) {
+            return Ok(false);
+        }
+        permissions::mode_matches(ctx.fs(), &path, Some(MODE))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use super::SudoersRule;
+    use crate::context::ExecutionContext;
+    use crate::exec::MockExec;
+    use crate::fs::{Fs, MemoryFs};
+    use crate::shift::{Shift, ShiftOutcome};
+
+    #[test]
+    fn drop_in_goes_through_the_context_fs() {
+        let fs = Arc::new(MemoryFs::new());
+        fs.create_dir_all(Path::new("/etc/sudoers.d")).unwrap();
+        let ctx = ExecutionContext::new()
+            .with_root("/")
+            .with_fs(fs.clone())
+            .with_executor(Arc::new(MockExec::new()))
+            .for_shift("sudo");
+        let rule = SudoersRule::user("deploy", "deploy").nopasswd(true);
+        let path = Path::new("/etc/sudoers.d/deploy");
+
+        assert_eq!(rule.apply(&ctx).unwrap(), ShiftOutcome::Changed);
+        assert!(rule.is_applied(&ctx).unwrap());
+        // The staging file was moved into place.
+        assert_eq!(fs.files().into_keys().collect::<Vec<_>>(), [path]);
+        assert_eq!(fs.mode(path).unwrap(), Some(0o440));
+
+        rule.revert(&ctx).unwrap();
+        assert!(fs.files().is_empty());
+    }
+}
@@ -0,0 +1,242 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::metadata::ShiftMetadata;
+use crate::permissions;
+use crate::resource::{Claim, Resource};
+use crate::shift::{Shift, ShiftOutcome};
+use crate::shifts::{fstab, Cmd};
+use crate::validate::ValidationContext;
+
+/// Where the kernel lists active swap areas.
+const PROC_SWAPS: &str = "/proc/swaps";
+
+/// How far the size in `/proc/swaps` may be below the file size: the
+/// kernel does not count the header page, and pages can be up to 64 KiB.
+const HEADER_SLACK_KIB: u64 = 64;
+
+/// Creates and enables a swap file of `size` (`512M`, `2G`, ...).
+///
+/// The file is written in full (swap files cannot be sparse) with mode
+/// `0o600`. With `persist`, the default, a marked fstab entry enables it
+/// at boot. Applied while the file is active in `/proc/swaps` at the
+/// right size and, when persisting, the entry is present; a size change
+/// turns the old file off and recreates it. Revert turns it off and
+/// removes the file and the entry.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SwapFile {
+    #[serde(default = "default_path")]
+    path: PathBuf,
+    size: String,
+    #[serde(default = "default_persist")]
+    persist: bool,
+    #[serde(default = "default_fstab")]
+    fstab: PathBuf,
+}
+
+fn default_path() -> PathBuf {
+    PathBuf::from("/swapfile")
+}
+
+fn default_persist() -> bool {
+    true
+}
+
+fn default_fstab() -> PathBuf {
+    PathBuf::from("/etc/fstab")
+}
+
+impl SwapFile {
+    pub fn new(size: impl Into<String>) -> Self {
+        SwapFile {
+            path: default_path(),
+            size: size.into(),
+            persist: default_persist(),
+            fstab: default_fstab(),
+        }
+    }
+
+    pub fn path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = path.into();
+        self
+    }
+
+    pub fn persist(mut self, persist: bool) -> Self {
+        self.persist = persist;
+        self
+    }
+
+    pub fn fstab(mut self, fstab: impl Into<PathBuf>) -> Self {
+        self.fstab = fstab.into();
+        self
+    }
+
+    /// `size` in bytes. Suffixes are binary: `K`, `M`, `G`, `T`.
+    fn bytes(&self) -> ShiftResult<u64> {
+        let invalid = || {
+            ShiftError::Custom(format!(
+                "invalid swap size `{}`: expected a number with an optional K, M, G or T suffix",
+                self.size
+            ))
+        };
+        let size = self.size.trim();
+        let (digits, shift) = match size.chars().last().map(|c| c.to_ascii_uppercase()) {
+            Some('K') => (&size[..size.len() - 1], 10),
+            Some('M') => (&size[..size.len() - 1], 20),
+            Some('G') => (&size[..size.len() - 1], 30),
+            Some('T') => (&size[..size.len() - 1], 40),
+            _ => (size, 0),
+        };
+        let number: u64 = digits.trim().parse().map_err(|_| invalid())?;
+        number.checked_mul(1 << shift).ok_or_else(invalid)
+    }
+
+    fn fstab_entry(path: &Path) -> String {
+        format!(
+            "{} none swap sw 0 0",
+            fstab::escape(&path.display().to_string())
+        )
+    }
+
+    /// Size in KiB of the active swap area at `path`, if it is active.
+    fn active_kib(path: &Path) -> ShiftResult<Option<u64>> {
+        let wanted = fstab::escape(&path.display().to_string());
+        let swaps = fs::read_to_string(PROC_SWAPS)?;
+        Ok(swaps.lines().skip(1).find_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (name, _, size) = (fields.next()?, fields.next()?, fields.next()?);
+            (name == wanted).then(|| size.parse().ok()).flatten()
+        }))
+    }
+
+    fn right_size(&self, kib: u64) -> ShiftResult<bool> {
+        let wanted = self.bytes()? / 1024;
+        Ok(kib <= wanted && wanted - kib <= HEADER_SLACK_KIB)
+    }
+
+    /// Writes `bytes` zeroes to a new file at `path`, readable only by root.
+    fn create(ctx: &ExecutionContext, path: &Path, bytes: u64) -> ShiftResult<()> {
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        permissions::write_file(path, &[], Some(0o600))?;
+        let mut file = OpenOptions::new().append(true).open(path)?;
+        let chunk = vec![0u8; 1 << 20];
+        let mut left = bytes;
+        while left > 0 {
+            ctx.check_cancelled()?;
+            let n = left.min(chunk.len() as u64);
+            file.write_all(&chunk[..n as usize])?;
+            left -= n;
+        }
+        file.sync_all()?;
+        Ok(())
+    }
+}
+
+impl Shift for SwapFile {
+    fn metadata(&self) -> ShiftMetadata {
+        ShiftMetadata::new(
+            "swap_file",
+            format!("enable {} of swap at {}", self.size, self.path.display()),
+        )
+        .target(&self.path)
+        .input("size", &self.size)
+        .input("persist", self.persist)
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        let mut claims = vec![Claim::exclusive(Resource::path(&self.path))];
+        if self.persist {
+            claims.push(Claim::shared(Resource::path(&self.fstab)));
+        }
+        claims
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        // mkswap refuses anything smaller than ten pages.
+        if self.bytes()? < 40 * 1024 {
+            return Err(ShiftError::Custom(format!(
+                "swap size `{}` is too small",
+                self.size
+            )));
+        }
+        for program in ["mkswap", "swapon", "swapoff"] {
+            ctx.require_program(program)?;
+        }
+        ctx.exec().resolve(&self.path)?;
+        if self.persist {
+            ctx.exec().resolve(&self.fstab)?;
+        }
+        ctx.require_parent(&self.path)
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Sudo]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let path = ctx.resolve(&self.path)?;
+        let display = path.display().to_string();
+        let mut changed = false;
+        match Self::active_kib(&path)? {
+            Some(kib) if self.right_size(kib)? => {}
+            active => {
+                changed = true;
+                if active.is_some() {
+                    ctx.info(&format!("resizing swap at {display} to {}", self.size));
+                    Cmd::new("swapoff").arg(&display).output(ctx)?;
+                }
+                Self::create(ctx, &path, self.bytes()?)?;
+                Cmd::new("mkswap").arg(&display).output(ctx)?;
+                Cmd::new("swapon").arg(&display).output(ctx)?;
+            }
+        }
+        if self.persist {
+            let fstab = ctx.resolve(&self.fstab)?;
+            let entry = Self::fstab_entry(&path);
+            if fstab::entry(&fstab, &display)?.as_ref() != Some(&entry) {
+                fstab::set_entry(&fstab, &display, &entry)?;
+                changed = true;
+            }
+        }
+        Ok(ShiftOutcome::changed_if(changed))
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let path = ctx.resolve(&self.path)?;
+        let display = path.display().to_string();
+        if Self::active_kib(&path)?.is_some() {
+            Cmd::new("swapoff").arg(&display).output(ctx)?;
+        }
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        if self.persist {
+            fstab::remove_entry(&ctx.resolve(&self.fstab)?, &display)?;
+        }
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        let path = ctx.resolve(&self.path)?;
+        let active = match Self::active_kib(&path)? {
+            Some(kib) => self.right_size(kib)?,
+            None => false,
+        };
+        if !active || !self.persist {
+            return Ok(active);
+        }
+        let key = path.display().to_string();
+        let entry = fstab::entry(&ctx.resolve(&self.fstab)?, &key)?;
+        Ok(entry == Some(Self::fstab_entry(&path)))
+    }
+}
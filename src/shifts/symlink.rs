@@ -0,0 +1,165 @@
+use std::path::{Path, PathBuf};
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::fs::Fs;
+use crate::metadata::ShiftMetadata;
+use crate::resource::{Claim, Resource};
+use crate::shift::{Shift, ShiftOutcome};
+use crate::validate::ValidationContext;
+
+/// Creates a symbolic link at `path` pointing to `target`.
+///
+/// Missing parent directories are created. An existing link is replaced,
+/// but any other file at `path` is left alone and fails preflight. Applied
+/// while the link points to `target`. Revert only removes the link if it
+/// still does.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Symlink {
+    path: PathBuf,
+    /// Stored in the link as written (after `~` expansion), so a relative
+    /// target is relative to the link's directory.
+    target: PathBuf,
+}
+
+impl Symlink {
+    pub fn new(path: impl Into<PathBuf>, target: impl Into<PathBuf>) -> Self {
+        Symlink {
+            path: path.into(),
+            target: target.into(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn target(&self) -> &Path {
+        &self.target
+    }
+
+    /// The link's location. Only its parent is checked against the root,
+    /// since the link itself points elsewhere by design.
+    fn link_path(&self, ctx: &ExecutionContext) -> ShiftResult<PathBuf> {
+        let joined = ctx.join_root(&self.path);
+        let (Some(parent), Some(name)) = (joined.parent(), joined.file_name()) else {
+            return Err(ShiftError::Custom(format!(
+                "{} cannot be a link",
+                self.path.display()
+            )));
+        };
+        Ok(ctx.resolve(parent)?.join(name))
+    }
+
+    /// What the link at `path` points to, if `path` is a link.
+    fn current(fs: &dyn Fs, path: &Path) -> ShiftResult<Option<PathBuf>> {
+        match fs.read_link(path)? {
+            Some(target) => Ok(Some(target)),
+            None if fs.exists(path) => Err(ShiftError::Conflict {
+                resource: path.display().to_string(),
+                reason: "already exists and is not a link; move it aside first".into(),
+            }),
+            None => Ok(None),
+        }
+    }
+}
+
+impl Shift for Symlink {
+    fn metadata(&self) -> ShiftMetadata {
+        ShiftMetadata::new(
+            "symlink",
+            format!("link {} -> {}", self.path.display(), self.target.display()),
+        )
+        .target(&self.path)
+        .input("target", self.target.display().to_string())
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![Claim::exclusive(Resource::path(&self.path))]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        let ctx = ctx.exec();
+        Self::current(ctx.fs(), &self.link_path(ctx)?).map(|_| ())
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let path = self.link_path(ctx)?;
+        let target = ctx.expand_home(&self.target);
+        let fs = ctx.fs();
+        match Self::current(fs, &path)? {
+            Some(current) if current == target => return Ok(ShiftOutcome::Unchanged),
+            Some(_) => fs.remove_file(&path)?,
+            None => {}
+        }
+        if let Some(parent) = path.parent() {
+            fs.create_dir_all(parent)?;
+        }
+        fs.symlink(&target, &path)?;
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let path = self.link_path(ctx)?;
+        if Self::current(ctx.fs(), &path)? == Some(ctx.expand_home(&self.target)) {
+            ctx.fs().remove_file(&path)?;
+        }
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        let path = self.link_path(ctx)?;
+        Ok(Self::current(ctx.fs(), &path)? == Some(ctx.expand_home(&self.target)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use super::Symlink;
+    use crate::context::ExecutionContext;
+    use crate::fs::{Fs, MemoryFs};
+    use crate::shift::{Shift, ShiftOutcome};
+
+    fn context(fs: &Arc<MemoryFs>) -> ExecutionContext {
+        fs.create_dir_all(Path::new("/home/dev")).unwrap();
+        ExecutionContext::new()
+            .with_root("/home/dev")
+            .with_fs(fs.clone())
+    }
+
+    #[test]
+    fn link_goes_through_the_context_fs() {
+        let fs = Arc::new(MemoryFs::new());
+        let ctx = context(&fs);
+        let link = Symlink::new(".config/nvim", "dotfiles/nvim");
+        let path = Path::new("/home/dev/.config/nvim");
+
+        assert_eq!(link.apply(&ctx).unwrap(), ShiftOutcome::Changed);
+        assert_eq!(fs.read_link(path).unwrap(), Some("dotfiles/nvim".into()));
+        assert!(link.is_applied(&ctx).unwrap());
+        assert_eq!(link.apply(&ctx).unwrap(), ShiftOutcome::Unchanged);
+
+        link.revert(&ctx).unwrap();
+        assert!(!fs.exists(path));
+    }
+
+    #[test]
+    fn file_in_the_way_is_a_conflict() {
+        let fs = Arc::new(MemoryFs::new());
+        let ctx = context(&fs);
+        fs.write(Path::new("/home/dev/.vimrc"), b"set nu\n", None)
+            .unwrap();
+
+        let err = Symlink::new(".vimrc", "dotfiles/vimrc")
+            .apply(&ctx)
+            .unwrap_err();
+        assert_eq!(err.kind(), "conflict");
+    }
+}
@@ -0,0 +1,212 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::metadata::ShiftMetadata;
+use crate::permissions;
+use crate::resource::{Claim, Resource};
+use crate::shift::{Shift, ShiftOutcome};
+use crate::shifts::Cmd;
+use crate::validate::ValidationContext;
+
+/// Sets a kernel parameter, like `vm.swappiness = 10`.
+///
+/// The live value is set with `sysctl -w`. With `persist`, the default,
+/// a `key = value` line is also kept in `file`, a drop-in owned by skies,
+/// so the setting survives a reboot. Applied while the live value matches
+/// (ignoring whitespace differences) and, when persisting, the line is
+/// present. The value before the first apply is kept in the shift's state;
+/// revert restores it and removes the line.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Sysctl {
+    key: String,
+    value: String,
+    #[serde(default = "default_persist")]
+    persist: bool,
+    #[serde(default = "default_file")]
+    file: PathBuf,
+}
+
+fn default_persist() -> bool {
+    true
+}
+
+fn default_file() -> PathBuf {
+    PathBuf::from("/etc/sysctl.d/99-skies.conf")
+}
+
+impl Sysctl {
+    pub fn new(key: impl Into<String>, value: impl Into<String>) -> Self {
+        Sysctl {
+            key: key.into(),
+            value: value.into(),
+            persist: default_persist(),
+            file: default_file(),
+        }
+    }
+
+    pub fn persist(mut self, persist: bool) -> Self {
+        self.persist = persist;
+        self
+    }
+
+    pub fn file(mut self, file: impl Into<PathBuf>) -> Self {
+        self.file = file.into();
+        self
+    }
+
+    fn line(&self) -> String {
+        format!("{} = {}", self.key, normalize(&self.value))
+    }
+
+    fn live(&self, ctx: &ExecutionContext) -> ShiftResult<String> {
+        let value = Cmd::new("sysctl").args(["-n", &self.key]).output(ctx)?;
+        Ok(normalize(&value))
+    }
+
+    fn set_live(&self, ctx: &ExecutionContext, value: &str) -> ShiftResult<()> {
+        Cmd::new("sysctl")
+            .args(["-w".to_string(), format!("{}={value}", self.key)])
+            .output(ctx)
+            .map(drop)
+    }
+
+    /// The drop-in's lines, minus any that set our key.
+    fn other_lines(&self, file: &Path) -> ShiftResult<Vec<String>> {
+        let text = match fs::read_to_string(file) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(text
+            .lines()
+            .filter(|line| {
+                line.split_once('=')
+                    .is_none_or(|(key, _)| key.trim() != self.key)
+            })
+            .map(String::from)
+            .collect())
+    }
+
+    fn persisted(&self, file: &Path) -> bool {
+        fs::read_to_string(file).is_ok_and(|text| text.lines().any(|line| line == self.line()))
+    }
+}
+
+/// Collapses runs of whitespace, since multi-value parameters are
+/// reported tab-separated.
+fn normalize(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+impl Shift for Sysctl {
+    fn metadata(&self) -> ShiftMetadata {
+        let meta = ShiftMetadata::new("sysctl", format!("set {}", self.line()))
+            .input("value", normalize(&self.value))
+            .input("persist", self.persist);
+        if self.persist {
+            meta.target(&self.file)
+        } else {
+            meta
+        }
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        let mut claims = vec![Claim::exclusive(Resource::lock(format!(
+            "sysctl {}",
+            self.key
+        )))];
+        if self.persist {
+            claims.push(Claim::shared(Resource::path(&self.file)));
+        }
+        claims
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        let valid = !self.key.is_empty()
+            && self
+                .key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | '/'));
+        if !valid {
+            return Err(ShiftError::Custom(format!(
+                "invalid sysctl key `{}`",
+                self.key
+            )));
+        }
+        if self.value.contains('\n') {
+            return Err(ShiftError::Custom("sysctl values cannot span lines".into()));
+        }
+        ctx.require_program("sysctl")?;
+        if self.persist {
+            ctx.exec().resolve(&self.file)?;
+            ctx.require_parent(&self.file)?;
+        }
+        Ok(())
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Sudo]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let live = self.live(ctx)?;
+        if ctx.get_state("previous").is_none() {
+            ctx.set_state("previous", json!(live));
+        }
+        let mut changed = false;
+        if live != normalize(&self.value) {
+            self.set_live(ctx, &self.value)?;
+            changed = true;
+        }
+        if self.persist {
+            let file = ctx.resolve(&self.file)?;
+            if !self.persisted(&file) {
+                let mut lines = self.other_lines(&file)?;
+                lines.push(self.line());
+                let mode = ctx.permissions().file_mode(Some(0o644));
+                permissions::write_file(&file, format!("{}\n", lines.join("\n")).as_bytes(), mode)?;
+                changed = true;
+            }
+        }
+        Ok(ShiftOutcome::changed_if(changed))
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        if let Some(previous) = ctx
+            .get_state("previous")
+            .and_then(|value| value.as_str().map(String::from))
+        {
+            self.set_live(ctx, &previous)?;
+        }
+        if self.persist {
+            let file = ctx.resolve(&self.file)?;
+            let lines = self.other_lines(&file)?;
+            if lines.iter().all(|line| line.trim().is_empty()) {
+                if file.exists() {
+                    fs::remove_file(&file)?;
+                }
+            } else {
+                let mode = permissions::mode_of(&file)?;
+                permissions::write_file(&file, format!("{}\n", lines.join("\n")).as_bytes(), mode)?;
+            }
+        }
+        ctx.clear_state("previous");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        if self.live(ctx)? != normalize(&self.value) {
+            return Ok(false);
+        }
+        Ok(!self.persist || self.persisted(&ctx.resolve(&self.file)?))
+    }
+}
@@ -0,0 +1,358 @@
+//! Scheduled jobs as systemd timers.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::metadata::ShiftMetadata;
+use crate::permissions;
+use crate::plan_file::de;
+use crate::resource::{Claim, Resource};
+use crate::service::{self, Service};
+use crate::shift::{Shift, ShiftOutcome};
+use crate::shifts::Cmd;
+use crate::validate::{find_program, ValidationContext};
+
+/// Runs a command on a schedule with a systemd timer.
+///
+/// Writes `<name>.service`, a oneshot service running `command`, and
+/// `<name>.timer`, which starts it on the `OnCalendar` expression
+/// `schedule` (`daily`, `Mon *-*-* 04:00`), to `dir`,
+/// `/etc/systemd/system` by default, then enables and starts the timer.
+/// `command` is argv, not a shell line; a bare program name is looked up
+/// by systemd in its usual directories. `environment_file` is loaded
+/// into the service's environment, for secrets the command needs.
+/// `persistent` (on by default) catches up on a run missed while the
+/// machine was off, and `randomized_delay` (seconds in plan files)
+/// spreads runs out.
+///
+/// Applied while both units are current and the timer is enabled and
+/// running. Revert puts back the units as they were, or removes them, and
+/// stops the timer if it was not enabled before.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SystemdTimer {
+    name: String,
+    command: Vec<String>,
+    schedule: String,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    user: Option<String>,
+    #[serde(default)]
+    environment_file: Option<PathBuf>,
+    #[serde(default = "default_persistent")]
+    persistent: bool,
+    /// Seconds in plan files.
+    #[serde(default, deserialize_with = "de::opt_secs")]
+    #[schemars(with = "Option<f64>")]
+    randomized_delay: Option<Duration>,
+    #[serde(default = "default_unit_dir")]
+    dir: PathBuf,
+}
+
+fn default_persistent() -> bool {
+    true
+}
+
+fn default_unit_dir() -> PathBuf {
+    PathBuf::from("/etc/systemd/system")
+}
+
+impl SystemdTimer {
+    pub fn new<I, S>(name: impl Into<String>, schedule: impl Into<String>, command: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        SystemdTimer {
+            name: name.into(),
+            command: command.into_iter().map(Into::into).collect(),
+            schedule: schedule.into(),
+            description: None,
+            user: None,
+            environment_file: None,
+            persistent: default_persistent(),
+            randomized_delay: None,
+            dir: default_unit_dir(),
+        }
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Runs the command as `user` instead of root.
+    pub fn user(mut self, user: impl Into<String>) -> Self {
+        self.user = Some(user.into());
+        self
+    }
+
+    pub fn environment_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.environment_file = Some(path.into());
+        self
+    }
+
+    pub fn persistent(mut self, on: bool) -> Self {
+        self.persistent = on;
+        self
+    }
+
+    pub fn randomized_delay(mut self, delay: Duration) -> Self {
+        self.randomized_delay = Some(delay);
+        self
+    }
+
+    pub fn dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = dir.into();
+        self
+    }
+
+    fn service_path(&self) -> PathBuf {
+        self.dir.join(format!("{}.service", self.name))
+    }
+
+    fn timer_path(&self) -> PathBuf {
+        self.dir.join(format!("{}.timer", self.name))
+    }
+
+    fn timer(&self) -> Service {
+        Service::new(format!("{}.timer", self.name))
+    }
+
+    fn description_text(&self) -> String {
+        self.description
+            .clone()
+            .unwrap_or_else(|| format!("{} (skies)", self.name))
+    }
+
+    /// `command` as an `ExecStart=` line, quoted the way systemd splits
+    /// it and with its specifiers and variables escaped.
+    fn exec_start(&self) -> String {
+        let words: Vec<String> = self
+            .command
+            .iter()
+            .map(|arg| {
+                let escaped = arg.replace('%', "%%").replace('$', "$$");
+                let plain = !escaped.is_empty()
+                    && !escaped.contains(|c: char| c.is_whitespace() || "\"'\\;".contains(c));
+                if plain {
+                    escaped
+                } else {
+                    format!("\"{}\"", escaped.replace('\\', "\\\\").replace('"', "\\\""))
+                }
+            })
+            .collect();
+        words.join(" ")
+    }
+
+    fn render_service(&self) -> String {
+        let mut text = format!(
+            "# Managed by skies.\n[Unit]\nDescription={}\n\n[Service]\nType=oneshot\nExecStart={}\n",
+            self.description_text(),
+            self.exec_start()
+        );
+        if let Some(user) = &self.user {
+            text.push_str(&format!("User={user}\n"));
+        }
+        if let Some(file) = &self.environment_file {
+            text.push_str(&format!("EnvironmentFile={}\n", file.display()));
+        }
+        text
+    }
+
+    fn render_timer(&self) -> String {
+        let mut text = format!(
+            "# Managed by skies.\n[Unit]\nDescription={}\n\n[Timer]\nOnCalendar={}\nPersistent={}\n",
+            self.description_text(),
+            self.schedule,
+            self.persistent
+        );
+        if let Some(delay) = self.randomized_delay {
+            text.push_str(&format!("RandomizedDelaySec={}\n", delay.as_secs()));
+        }
+        text.push_str("\n[Install]\nWantedBy=timers.target\n");
+        text
+    }
+
+    fn read(path: &Path) -> ShiftResult<Option<String>> {
+        match fs::read_to_string(path) {
+            Ok(text) => Ok(Some(text)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// The unit files with what they should contain.
+    fn units(&self, ctx: &ExecutionContext) -> ShiftResult<[(PathBuf, String); 2]> {
+        Ok([
+            (ctx.resolve(&self.service_path())?, self.render_service()),
+            (ctx.resolve(&self.timer_path())?, self.render_timer()),
+        ])
+    }
+
+    fn put_back(path: &Path, previous: &Value) -> ShiftResult<()> {
+        match previous {
+            Value::String(text) => permissions::write_file(path, text.as_bytes(), Some(0o644)),
+            _ if path.exists() => fs::remove_file(path).map_err(Into::into),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Shift for SystemdTimer {
+    fn metadata(&self) -> ShiftMetadata {
+        let mut meta = ShiftMetadata::new(
+            "systemd_timer",
+            format!("run {} on schedule {}", self.name, self.schedule),
+        )
+        .target(self.timer_path())
+        .input("command", &self.command)
+        .input("schedule", &self.schedule);
+        if let Some(user) = &self.user {
+            meta = meta.input("user", user);
+        }
+        meta
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![
+            Claim::exclusive(Resource::path(self.service_path())),
+            Claim::exclusive(Resource::path(self.timer_path())),
+        ]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        let valid_name = !self.name.is_empty()
+            && self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '@'))
+            && !self.name.ends_with(".service")
+            && !self.name.ends_with(".timer");
+        if !valid_name {
+            return Err(ShiftError::Custom(format!(
+                "`{}` is not a usable unit name; leave off `.service` or `.timer`",
+                self.name
+            )));
+        }
+        if self.command.is_empty() {
+            return Err(ShiftError::Custom("no `command` to run".into()));
+        }
+        let fields = self
+            .command
+            .iter()
+            .chain([&self.schedule])
+            .chain(&self.description)
+            .chain(&self.user);
+        if let Some(bad) = fields.into_iter().find(|value| value.contains('\n')) {
+            return Err(ShiftError::Custom(format!(
+                "`{bad}` spans several lines; unit settings must fit on one"
+            )));
+        }
+        if self.schedule.trim().is_empty() {
+            return Err(ShiftError::Custom("no `schedule` for the timer".into()));
+        }
+        if find_program("systemd-analyze").is_some() {
+            Cmd::new("systemd-analyze")
+                .args(["calendar", &self.schedule])
+                .output(ctx.exec())
+                .map_err(|err| {
+                    err.context(format!("`{}` is not a calendar event", self.schedule))
+                        .hint("see `man systemd.time` for the OnCalendar syntax")
+                })?;
+        }
+        ctx.require_program("systemctl")?;
+        ctx.exec().resolve(&self.service_path())?;
+        Ok(())
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Sudo, Capability::RunsArbitraryCode]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let units = self.units(ctx)?;
+        let mut current = Vec::new();
+        for (path, _) in &units {
+            current.push(Self::read(path)?);
+        }
+        let reconfigured = units
+            .iter()
+            .zip(&current)
+            .any(|((_, rendered), current)| current.as_deref() != Some(rendered.as_str()));
+        if reconfigured {
+            if ctx.get_state("previous").is_none() {
+                ctx.set_state(
+                    "previous",
+                    json!({ "service": current[0], "timer": current[1] }),
+                );
+            }
+            for (path, rendered) in &units {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                permissions::write_file(path, rendered.as_bytes(), Some(0o644))?;
+            }
+            service::daemon_reload(ctx)?;
+        }
+
+        let timer = self.timer();
+        let enabled = timer.is_enabled(ctx)?;
+        if !enabled || !timer.is_active(ctx)? {
+            if ctx.get_state("was_enabled").is_none() {
+                ctx.set_state("was_enabled", json!(enabled));
+            }
+            timer.enable_now(ctx)?;
+            return Ok(ShiftOutcome::Changed);
+        }
+        if reconfigured {
+            // A running timer keeps its old schedule until restarted.
+            timer.restart(ctx)?;
+        }
+        Ok(ShiftOutcome::changed_if(reconfigured))
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let timer = self.timer();
+        // Disabling needs the unit's [Install] section, so before the
+        // files go.
+        if ctx.get_state("was_enabled") == Some(json!(false)) {
+            timer.disable_now(ctx)?;
+        }
+        if let Some(previous) = ctx.get_state("previous") {
+            let units = self.units(ctx)?;
+            Self::put_back(&units[0].0, &previous["service"])?;
+            Self::put_back(&units[1].0, &previous["timer"])?;
+            service::daemon_reload(ctx)?;
+            if previous["timer"].is_string() {
+                timer.restart_if_active(ctx)?;
+            }
+        }
+        ctx.clear_state("previous");
+        ctx.clear_state("was_enabled");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        for (path, rendered) in self.units(ctx)? {
+            if Self::read(&path)?.as_deref() != Some(rendered.as_str()) {
+                return Ok(false);
+            }
+        }
+        if find_program("systemctl").is_none() {
+            return Ok(false);
+        }
+        let timer = self.timer();
+        Ok(timer.is_enabled(ctx)? && timer.is_active(ctx)?)
+    }
+}
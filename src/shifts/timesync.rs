@@ -0,0 +1,297 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::artifacts::Artifact;
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::metadata::ShiftMetadata;
+use crate::packages::PackageManager;
+use crate::permissions;
+use crate::resource::{Claim, Resource};
+use crate::service::Service;
+use crate::shift::{Shift, ShiftOutcome};
+use crate::validate::{find_program, ValidationContext};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeSyncDaemon {
+    Chrony,
+    /// systemd-timesyncd, an SNTP client that ships with systemd.
+    Timesyncd,
+}
+
+/// Keeps the clock in sync with the given NTP `servers`.
+///
+/// The daemon is chrony if it is installed, else systemd-timesyncd. With
+/// `install`, the default, a missing daemon is installed with the system
+/// package manager. chrony gets a whole config file rendered by skies
+/// (names starting with `pool.` or holding `.pool.` are used as pools);
+/// timesyncd gets a drop-in setting `NTP=`. `config` overrides where that
+/// file goes. Applied while the file matches and the service is enabled
+/// and running, so hand edits and a stopped service count as drift.
+/// Revert restores the file as it was, disables the service if it was
+/// disabled before, and removes the daemon if this shift installed it.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TimeSync {
+    servers: Vec<String>,
+    #[serde(default)]
+    daemon: Option<TimeSyncDaemon>,
+    #[serde(default = "default_install")]
+    install: bool,
+    #[serde(default)]
+    config: Option<PathBuf>,
+}
+
+fn default_install() -> bool {
+    true
+}
+
+/// Whether chrony uses Debian's file layout and unit name.
+fn debian_layout() -> bool {
+    PackageManager::detect() == Some(PackageManager::Apt) || Path::new("/etc/chrony").is_dir()
+}
+
+impl TimeSync {
+    pub fn new<I, S>(servers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        TimeSync {
+            servers: servers.into_iter().map(Into::into).collect(),
+            daemon: None,
+            install: default_install(),
+            config: None,
+        }
+    }
+
+    pub fn daemon(mut self, daemon: TimeSyncDaemon) -> Self {
+        self.daemon = Some(daemon);
+        self
+    }
+
+    pub fn install(mut self, install: bool) -> Self {
+        self.install = install;
+        self
+    }
+
+    pub fn config(mut self, config: impl Into<PathBuf>) -> Self {
+        self.config = Some(config.into());
+        self
+    }
+
+    fn daemon_or_detect(&self) -> TimeSyncDaemon {
+        self.daemon.unwrap_or_else(|| {
+            if find_program("chronyd").is_some() {
+                TimeSyncDaemon::Chrony
+            } else {
+                TimeSyncDaemon::Timesyncd
+            }
+        })
+    }
+
+    fn config_path(&self) -> PathBuf {
+        if let Some(config) = &self.config {
+            return config.clone();
+        }
+        match self.daemon_or_detect() {
+            TimeSyncDaemon::Chrony if debian_layout() => "/etc/chrony/chrony.conf".into(),
+            TimeSyncDaemon::Chrony => "/etc/chrony.conf".into(),
+            TimeSyncDaemon::Timesyncd => "/etc/systemd/timesyncd.conf.d/skies.conf".into(),
+        }
+    }
+
+    fn service(&self) -> Service {
+        Service::new(match self.daemon_or_detect() {
+            TimeSyncDaemon::Chrony if debian_layout() => "chrony",
+            TimeSyncDaemon::Chrony => "chronyd",
+            TimeSyncDaemon::Timesyncd => "systemd-timesyncd",
+        })
+    }
+
+    /// The package providing the daemon, where it is not part of systemd.
+    fn package(&self) -> Option<&'static str> {
+        match self.daemon_or_detect() {
+            TimeSyncDaemon::Chrony => Some("chrony"),
+            TimeSyncDaemon::Timesyncd if PackageManager::detect() == Some(PackageManager::Apt) => {
+                Some("systemd-timesyncd")
+            }
+            TimeSyncDaemon::Timesyncd => None,
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut text = String::from("# Managed by skies.\n");
+        match self.daemon_or_detect() {
+            TimeSyncDaemon::Chrony => {
+                for server in &self.servers {
+                    let kind = match server.starts_with("pool.") || server.contains(".pool.") {
+                        true => "pool",
+                        false => "server",
+                    };
+                    text.push_str(&format!("{kind} {server} iburst\n"));
+                }
+                let drift = match debian_layout() {
+                    true => "/var/lib/chrony/chrony.drift",
+                    false => "/var/lib/chrony/drift",
+                };
+                text.push_str(&format!("driftfile {drift}\nmakestep 1.0 3\nrtcsync\n"));
+            }
+            TimeSyncDaemon::Timesyncd => {
+                text.push_str(&format!("[Time]\nNTP={}\n", self.servers.join(" ")));
+            }
+        }
+        text
+    }
+
+    fn read_config(&self, ctx: &ExecutionContext) -> ShiftResult<Option<String>> {
+        match fs::read_to_string(ctx.resolve(&self.config_path())?) {
+            Ok(text) => Ok(Some(text)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+impl Shift for TimeSync {
+    fn metadata(&self) -> ShiftMetadata {
+        let daemon = match self.daemon_or_detect() {
+            TimeSyncDaemon::Chrony => "chrony",
+            TimeSyncDaemon::Timesyncd => "timesyncd",
+        };
+        ShiftMetadata::new(
+            "timesync",
+            format!("sync time from {} with {daemon}", self.servers.join(", ")),
+        )
+        .target(self.config_path())
+        .input("servers", &self.servers)
+        .input("daemon", daemon)
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![
+            Claim::exclusive(Resource::lock("timesync")),
+            Claim::exclusive(Resource::path(self.config_path())),
+        ]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        if self.servers.is_empty() {
+            return Err(ShiftError::Custom("no `servers` to sync from".into()));
+        }
+        if let Some(bad) = self
+            .servers
+            .iter()
+            .find(|server| server.is_empty() || server.contains(char::is_whitespace))
+        {
+            return Err(ShiftError::Custom(format!(
+                "`{bad}` is not an NTP server name"
+            )));
+        }
+        ctx.require_program("systemctl")?;
+        if self.install && self.package().is_some() {
+            PackageManager::require()?;
+        }
+        ctx.exec().resolve(&self.config_path())?;
+        Ok(())
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Sudo]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let mut changed = false;
+        if let (true, Some(package)) = (self.install, self.package()) {
+            let manager = PackageManager::require()?;
+            if !manager.is_installed(ctx, package)? {
+                manager.install(ctx, &[package])?;
+                ctx.set_state("installed", json!(package));
+                changed = true;
+            }
+        }
+        let rendered = self.render();
+        let current = self.read_config(ctx)?;
+        let reconfigured = current.as_deref() != Some(rendered.as_str());
+        if reconfigured {
+            if ctx.get_state("previous_config").is_none() {
+                ctx.set_state("previous_config", json!(current));
+            }
+            let path = ctx.resolve(&self.config_path())?;
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let mode = ctx.permissions().file_mode(Some(0o644));
+            permissions::write_file(&path, rendered.as_bytes(), mode)?;
+            changed = true;
+        }
+        let service = self.service();
+        let enabled = service.is_enabled(ctx)?;
+        if !enabled || !service.is_active(ctx)? {
+            if ctx.get_state("was_enabled").is_none() {
+                ctx.set_state("was_enabled", json!(enabled));
+            }
+            service.enable_now(ctx)?;
+            changed = true;
+        }
+        if reconfigured {
+            service.restart(ctx)?;
+        }
+        Ok(ShiftOutcome::changed_if(changed))
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let service = self.service();
+        match ctx.get_state("previous_config") {
+            Some(Value::String(previous)) => {
+                let path = ctx.resolve(&self.config_path())?;
+                let mode = permissions::mode_of(&path).ok().flatten();
+                permissions::write_file(&path, previous.as_bytes(), mode)?;
+                service.restart_if_active(ctx)?;
+            }
+            Some(_) => {
+                let path = ctx.resolve(&self.config_path())?;
+                if path.exists() {
+                    fs::remove_file(&path)?;
+                }
+                service.restart_if_active(ctx)?;
+            }
+            None => {}
+        }
+        if ctx.get_state("was_enabled") == Some(json!(false)) {
+            service.disable_now(ctx)?;
+        }
+        if let Some(Value::String(package)) = ctx.get_state("installed") {
+            PackageManager::require()?.remove(ctx, &[&package])?;
+        }
+        for key in ["previous_config", "was_enabled", "installed"] {
+            ctx.clear_state(key);
+        }
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        if find_program("systemctl").is_none() {
+            return Ok(false);
+        }
+        if self.read_config(ctx)?.as_deref() != Some(self.render().as_str()) {
+            return Ok(false);
+        }
+        let service = self.service();
+        Ok(service.is_enabled(ctx)? && service.is_active(ctx)?)
+    }
+
+    fn artifacts(&self, _ctx: &ExecutionContext) -> ShiftResult<Vec<Artifact>> {
+        Ok(match (self.install, self.package()) {
+            (true, Some(package)) => vec![Artifact::Packages(vec![package.to_string()])],
+            _ => Vec::new(),
+        })
+    }
+}
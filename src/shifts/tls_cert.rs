@@ -0,0 +1,280 @@
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::fs::RealFs;
+use crate::metadata::ShiftMetadata;
+use crate::permissions;
+use crate::resource::{Claim, Resource};
+use crate::shift::{Shift, ShiftOutcome};
+use crate::shifts::Cmd;
+use crate::validate::ValidationContext;
+
+/// How a [`TlsCert`] is issued.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CertProvider {
+    /// Self-signed with `openssl`.
+    #[default]
+    SelfSigned,
+    /// Signed by the local `mkcert` CA, so browsers on this machine trust it.
+    Mkcert,
+}
+
+/// Issues a TLS certificate and key for `domains`.
+///
+/// Applied while the certificate covers exactly `domains`, does not expire
+/// within `renew_days`, and the key has its mode; otherwise apply issues a
+/// new one, which is how renewal happens. The key is written `0o600`
+/// unless `key_mode` says otherwise. Inspection always uses `openssl`.
+/// Revert removes the certificate and key.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TlsCert {
+    cert: PathBuf,
+    key: PathBuf,
+    domains: Vec<String>,
+    #[serde(default)]
+    provider: CertProvider,
+    /// Validity of self-signed certificates.
+    #[serde(default = "default_days")]
+    days: u32,
+    #[serde(default = "default_renew_days")]
+    renew_days: u32,
+    #[serde(default)]
+    key_mode: Option<u32>,
+}
+
+fn default_days() -> u32 {
+    365
+}
+
+fn default_renew_days() -> u32 {
+    30
+}
+
+impl TlsCert {
+    pub fn new(
+        cert: impl Into<PathBuf>,
+        key: impl Into<PathBuf>,
+        domains: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        TlsCert {
+            cert: cert.into(),
+            key: key.into(),
+            domains: domains.into_iter().map(Into::into).collect(),
+            provider: CertProvider::default(),
+            days: default_days(),
+            renew_days: default_renew_days(),
+            key_mode: None,
+        }
+    }
+
+    pub fn provider(mut self, provider: CertProvider) -> Self {
+        self.provider = provider;
+        self
+    }
+
+    pub fn days(mut self, days: u32) -> Self {
+        self.days = days;
+        self
+    }
+
+    /// Reissue when the certificate expires within this many days.
+    pub fn renew_days(mut self, days: u32) -> Self {
+        self.renew_days = days;
+        self
+    }
+
+    pub fn key_mode(mut self, mode: u32) -> Self {
+        self.key_mode = Some(mode);
+        self
+    }
+
+    fn key_mode_or_default(&self) -> u32 {
+        self.key_mode.unwrap_or(0o600)
+    }
+
+    /// Issues a certificate into `dir`, returning the cert and key paths.
+    fn issue(&self, ctx: &ExecutionContext, dir: &Path) -> ShiftResult<(PathBuf, PathBuf)> {
+        let (cert, key) = (dir.join("cert.pem"), dir.join("key.pem"));
+        let (cert_arg, key_arg) = (cert.display().to_string(), key.display().to_string());
+        let cmd = match self.provider {
+            CertProvider::SelfSigned => Cmd::new("openssl").args([
+                "req".to_string(),
+                "-x509".into(),
+                "-newkey".into(),
+                "rsa:2048".into(),
+                "-nodes".into(),
+                "-keyout".into(),
+                key_arg,
+                "-out".into(),
+                cert_arg,
+                "-days".into(),
+                self.days.to_string(),
+                "-subj".into(),
+                format!("/CN={}", self.domains[0]),
+                "-addext".into(),
+                format!(
+                    "subjectAltName={}",
+                    subject_alt_names(&self.domains).join(",")
+                ),
+            ]),
+            CertProvider::Mkcert => Cmd::new("mkcert")
+                .args([
+                    "-cert-file".to_string(),
+                    cert_arg,
+                    "-key-file".into(),
+                    key_arg,
+                ])
+                .args(self.domains.iter().cloned()),
+        };
+        cmd.output(ctx)?;
+        Ok((cert, key))
+    }
+}
+
+/// `subjectAltName` entries for `names`.
+fn subject_alt_names(names: &[String]) -> Vec<String> {
+    names
+        .iter()
+        .map(|name| match name.parse::<IpAddr>() {
+            Ok(_) => format!("IP:{name}"),
+            Err(_) => format!("DNS:{name}"),
+        })
+        .collect()
+}
+
+/// Whether the certificate at `cert` names exactly `domains` and is valid
+/// for longer than `renew_days`.
+pub(super) fn is_current(
+    ctx: &ExecutionContext,
+    cert: &Path,
+    domains: &[String],
+    renew_days: u32,
+) -> ShiftResult<bool> {
+    let cert = cert.display().to_string();
+    let window = (u64::from(renew_days) * 86_400).to_string();
+    let fresh = Cmd::new("openssl")
+        .args(["x509", "-noout", "-in", &cert, "-checkend", &window])
+        .output(ctx)
+        .is_ok();
+    if !fresh {
+        return Ok(false);
+    }
+    let text = Cmd::new("openssl")
+        .args(["x509", "-noout", "-in", &cert, "-ext", "subjectAltName"])
+        .output(ctx)?;
+    let mut names: Vec<String> = text
+        .lines()
+        .skip(1)
+        .flat_map(|line| line.split(','))
+        .map(|entry| entry.trim().replacen("IP Address:", "IP:", 1))
+        .filter(|entry| !entry.is_empty())
+        .collect();
+    let mut wanted = subject_alt_names(domains);
+    names.sort();
+    wanted.sort();
+    Ok(names == wanted)
+}
+
+impl Shift for TlsCert {
+    fn metadata(&self) -> ShiftMetadata {
+        let provider = match self.provider {
+            CertProvider::SelfSigned => "self-signed",
+            CertProvider::Mkcert => "mkcert",
+        };
+        ShiftMetadata::new(
+            "tls_cert",
+            format!(
+                "issue a {provider} certificate for {} at {}",
+                self.domains.join(", "),
+                self.cert.display()
+            ),
+        )
+        .target(&self.cert)
+        .target(&self.key)
+        .input("domains", &self.domains)
+        .input("days", self.days)
+        .input("renew_days", self.renew_days)
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![
+            Claim::exclusive(Resource::path(&self.cert)),
+            Claim::exclusive(Resource::path(&self.key)),
+        ]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        if self.domains.is_empty() {
+            return Err(ShiftError::Custom("no domains given".into()));
+        }
+        if self.renew_days >= self.days && self.provider == CertProvider::SelfSigned {
+            return Err(ShiftError::Custom(format!(
+                "renew_days ({}) must be less than days ({}), or every run reissues",
+                self.renew_days, self.days
+            )));
+        }
+        ctx.require_program("openssl")?;
+        if self.provider == CertProvider::Mkcert {
+            ctx.require_program("mkcert")?;
+        }
+        ctx.exec().resolve(&self.cert)?;
+        ctx.exec().resolve(&self.key)?;
+        ctx.require_parent(&self.cert)?;
+        ctx.require_parent(&self.key)
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let cert_path = ctx.resolve(&self.cert)?;
+        let key_path = ctx.resolve(&self.key)?;
+        let (cert, key) = self.issue(ctx, &ctx.temp_dir()?)?;
+        // Replace rather than overwrite so the key gets its mode from the start.
+        for path in [&cert_path, &key_path] {
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+        permissions::write_file(
+            &key_path,
+            &fs::read(&key)?,
+            Some(self.key_mode_or_default()),
+        )?;
+        permissions::write_file(
+            &cert_path,
+            &fs::read(&cert)?,
+            ctx.permissions().file_mode(None),
+        )?;
+        fs::remove_file(key)?;
+        ctx.output("cert", cert_path);
+        ctx.output("key", key_path);
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        for path in [ctx.resolve(&self.cert)?, ctx.resolve(&self.key)?] {
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        let cert = ctx.resolve(&self.cert)?;
+        let key = ctx.resolve(&self.key)?;
+        if !cert.exists() || !key.exists() {
+            return Ok(false);
+        }
+        Ok(
+            permissions::mode_matches(&RealFs, &key, Some(self.key_mode_or_default()))?
+                && is_current(ctx, &cert, &self.domains, self.renew_days)?,
+        )
+    }
+}
@@ -0,0 +1,503 @@
+//! Language toolchains installed and pinned through their version managers.
+//!
+//! Each shift installs a version and makes it the manager's default. The
+//! previous default is kept in the shift's state so revert can switch back,
+//! and a version is only uninstalled on revert if this shift installed it.
+
+use std::path::{Path, PathBuf};
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::inputs::ExternalInput;
+use crate::metadata::ShiftMetadata;
+use crate::resource::{Claim, Resource};
+use crate::shift::{Shift, ShiftOutcome};
+use crate::shifts::Cmd;
+use crate::validate::ValidationContext;
+
+/// Operations a version manager provides.
+trait Manager {
+    fn program(&self) -> &str;
+    /// Whether the manager's name for a version, `actual`, is `wanted`.
+    fn is(&self, wanted: &str, actual: &str) -> bool {
+        version_matches(wanted, actual)
+    }
+    fn installed(&self, ctx: &ExecutionContext, version: &str) -> ShiftResult<bool>;
+    /// The default version, if one is set.
+    fn active(&self, ctx: &ExecutionContext) -> ShiftResult<Option<String>>;
+    fn install(&self, ctx: &ExecutionContext, version: &str) -> ShiftResult<()>;
+    fn activate(&self, ctx: &ExecutionContext, version: &str) -> ShiftResult<()>;
+    fn uninstall(&self, ctx: &ExecutionContext, version: &str) -> ShiftResult<()>;
+}
+
+fn run(ctx: &ExecutionContext, program: &str, args: &[&str]) -> ShiftResult<String> {
+    Cmd::new(program).args(args.iter().copied()).output(ctx)
+}
+
+/// Whether `actual` (e.g. `v20.11.1`) is the version `wanted` (e.g. `20`,
+/// `20.11` or `20.11.1`) asks for.
+fn version_matches(wanted: &str, actual: &str) -> bool {
+    let wanted = wanted.trim_start_matches('v');
+    let actual = actual.trim().trim_start_matches('v');
+    actual == wanted || actual.starts_with(&format!("{wanted}."))
+}
+
+fn apply_with(
+    manager: &dyn Manager,
+    ctx: &ExecutionContext,
+    version: &str,
+) -> ShiftResult<ShiftOutcome> {
+    let previous = manager.active(ctx)?;
+    let mut changed = !previous
+        .as_deref()
+        .is_some_and(|active| manager.is(version, active));
+    if !manager.installed(ctx, version)? {
+        manager.install(ctx, version)?;
+        ctx.set_state("installed", json!(true));
+        changed = true;
+    }
+    manager.activate(ctx, version)?;
+    if ctx.get_state("previous").is_none() {
+        ctx.set_state("previous", json!(previous));
+    }
+    Ok(ShiftOutcome::changed_if(changed))
+}
+
+fn revert_with(manager: &dyn Manager, ctx: &ExecutionContext, version: &str) -> ShiftResult<()> {
+    if let Some(previous) = ctx
+        .get_state("previous")
+        .and_then(|v| v.as_str().map(String::from))
+    {
+        if !manager.is(version, &previous) {
+            manager.activate(ctx, &previous)?;
+        }
+    }
+    if ctx.get_state("installed") == Some(json!(true)) {
+        manager.uninstall(ctx, version)?;
+    }
+    ctx.clear_state("previous");
+    ctx.clear_state("installed");
+    Ok(())
+}
+
+fn is_applied_with(
+    manager: &dyn Manager,
+    ctx: &ExecutionContext,
+    version: &str,
+) -> ShiftResult<bool> {
+    if crate::validate::find_program(manager.program()).is_none() {
+        return Ok(false);
+    }
+    Ok(manager
+        .active(ctx)?
+        .is_some_and(|active| manager.is(version, &active)))
+}
+
+/// Installs a Rust toolchain with `rustup` and makes it the default.
+///
+/// Applied when the default toolchain is `channel` and every component is
+/// installed for it. Revert makes the toolchain that was the default
+/// before the first apply the default again, and uninstalls `channel` if
+/// the shift installed it.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RustToolchain {
+    channel: String,
+    #[serde(default)]
+    components: Vec<String>,
+}
+
+impl RustToolchain {
+    pub fn new(channel: impl Into<String>) -> Self {
+        RustToolchain {
+            channel: channel.into(),
+            components: Vec::new(),
+        }
+    }
+
+    /// Adds a component such as `clippy` or `rust-src`.
+    pub fn component(mut self, component: impl Into<String>) -> Self {
+        self.components.push(component.into());
+        self
+    }
+}
+
+struct Rustup;
+
+impl Manager for Rustup {
+    fn program(&self) -> &str {
+        "rustup"
+    }
+
+    fn is(&self, wanted: &str, actual: &str) -> bool {
+        toolchain_matches(wanted, actual)
+    }
+
+    fn installed(&self, ctx: &ExecutionContext, channel: &str) -> ShiftResult<bool> {
+        let list = run(ctx, "rustup", &["toolchain", "list"])?;
+        Ok(list.lines().any(|line| toolchain_matches(channel, line)))
+    }
+
+    fn active(&self, ctx: &ExecutionContext) -> ShiftResult<Option<String>> {
+        let out = run(ctx, "rustup", &["default"]).unwrap_or_default();
+        Ok(out.split_whitespace().next().map(String::from))
+    }
+
+    fn install(&self, ctx: &ExecutionContext, channel: &str) -> ShiftResult<()> {
+        run(
+            ctx,
+            "rustup",
+            &["toolchain", "install", channel, "--no-self-update"],
+        )
+        .map(drop)
+    }
+
+    fn activate(&self, ctx: &ExecutionContext, channel: &str) -> ShiftResult<()> {
+        run(ctx, "rustup", &["default", channel]).map(drop)
+    }
+
+    fn uninstall(&self, ctx: &ExecutionContext, channel: &str) -> ShiftResult<()> {
+        run(ctx, "rustup", &["toolchain", "uninstall", channel]).map(drop)
+    }
+}
+
+/// Whether a `rustup` toolchain name such as `stable-x86_64-unknown-linux-gnu
+/// (default)` is `channel`.
+fn toolchain_matches(channel: &str, name: &str) -> bool {
+    let name = name.split_whitespace().next().unwrap_or_default();
+    name == channel || name.starts_with(&format!("{channel}-"))
+}
+
+impl RustToolchain {
+    fn missing_components(&self, ctx: &ExecutionContext) -> ShiftResult<Vec<&str>> {
+        if self.components.is_empty() {
+            return Ok(Vec::new());
+        }
+        let installed = run(
+            ctx,
+            "rustup",
+            &[
+                "component",
+                "list",
+                "--installed",
+                "--toolchain",
+                &self.channel,
+            ],
+        )?;
+        Ok(self
+            .components
+            .iter()
+            .map(String::as_str)
+            .filter(|component| {
+                !installed
+                    .lines()
+                    .any(|line| toolchain_matches(component, line))
+            })
+            .collect())
+    }
+}
+
+impl Shift for RustToolchain {
+    fn metadata(&self) -> ShiftMetadata {
+        ShiftMetadata::new("rust_toolchain", format!("use Rust {}", self.channel))
+            .input("channel", &self.channel)
+            .input("components", &self.components)
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![Claim::shared(Resource::lock("rustup"))]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        ctx.require_program("rustup")
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network]
+    }
+
+    fn inputs(&self, _ctx: &ExecutionContext) -> ShiftResult<Vec<ExternalInput>> {
+        Ok(vec![ExternalInput::toolchain("rust", &self.channel)])
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let outcome = apply_with(&Rustup, ctx, &self.channel)?;
+        let missing = self.missing_components(ctx)?;
+        if missing.is_empty() {
+            return Ok(outcome);
+        }
+        let mut args = vec!["component", "add", "--toolchain", &self.channel];
+        args.extend(missing);
+        run(ctx, "rustup", &args)?;
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        revert_with(&Rustup, ctx, &self.channel)
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        Ok(is_applied_with(&Rustup, ctx, &self.channel)?
+            && self.missing_components(ctx)?.is_empty())
+    }
+}
+
+/// Which Node.js version manager [`NodeVersion`] uses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeManager {
+    #[default]
+    Nvm,
+    Fnm,
+}
+
+/// Installs a Node.js version and makes it the default for new shells.
+///
+/// Applied while `version` is the manager's default.
+/// Revert makes the version that was the default before the first apply
+/// the default again, and uninstalls this one if the shift installed it.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct NodeVersion {
+    version: String,
+    #[serde(default)]
+    manager: NodeManager,
+}
+
+impl NodeVersion {
+    pub fn new(version: impl Into<String>) -> Self {
+        NodeVersion {
+            version: version.into(),
+            manager: NodeManager::default(),
+        }
+    }
+
+    pub fn manager(mut self, manager: NodeManager) -> Self {
+        self.manager = manager;
+        self
+    }
+}
+
+/// nvm is a shell function, so every call sources it first.
+const NVM: &str = r#". "${NVM_DIR:-$HOME/.nvm}/nvm.sh" && nvm "$@""#;
+
+fn nvm(ctx: &ExecutionContext, args: &[&str]) -> ShiftResult<String> {
+    let mut full = vec!["-c", NVM, "nvm"];
+    full.extend(args);
+    run(ctx, "bash", &full)
+}
+
+impl Manager for NodeManager {
+    fn program(&self) -> &str {
+        match self {
+            NodeManager::Nvm => "bash",
+            NodeManager::Fnm => "fnm",
+        }
+    }
+
+    fn installed(&self, ctx: &ExecutionContext, version: &str) -> ShiftResult<bool> {
+        match self {
+            NodeManager::Nvm => Ok(nvm(ctx, &["ls", version]).is_ok()),
+            NodeManager::Fnm => {
+                let list = run(ctx, "fnm", &["list"])?;
+                Ok(list.lines().any(|line| {
+                    line.split_whitespace()
+                        .any(|word| version_matches(version, word))
+                }))
+            }
+        }
+    }
+
+    fn active(&self, ctx: &ExecutionContext) -> ShiftResult<Option<String>> {
+        let out = match self {
+            NodeManager::Nvm => nvm(ctx, &["version", "default"]),
+            NodeManager::Fnm => run(
+                ctx,
+                "fnm",
+                &["exec", "--using", "default", "node", "--version"],
+            ),
+        };
+        Ok(out
+            .ok()
+            .map(|out| out.trim().to_string())
+            .filter(|v| v.starts_with('v')))
+    }
+
+    fn install(&self, ctx: &ExecutionContext, version: &str) -> ShiftResult<()> {
+        match self {
+            NodeManager::Nvm => nvm(ctx, &["install", version]).map(drop),
+            NodeManager::Fnm => run(ctx, "fnm", &["install", version]).map(drop),
+        }
+    }
+
+    fn activate(&self, ctx: &ExecutionContext, version: &str) -> ShiftResult<()> {
+        match self {
+            NodeManager::Nvm => nvm(ctx, &["alias", "default", version]).map(drop),
+            NodeManager::Fnm => run(ctx, "fnm", &["default", version]).map(drop),
+        }
+    }
+
+    fn uninstall(&self, ctx: &ExecutionContext, version: &str) -> ShiftResult<()> {
+        match self {
+            NodeManager::Nvm => nvm(ctx, &["uninstall", version]).map(drop),
+            NodeManager::Fnm => run(ctx, "fnm", &["uninstall", version]).map(drop),
+        }
+    }
+}
+
+impl Shift for NodeVersion {
+    fn metadata(&self) -> ShiftMetadata {
+        ShiftMetadata::new("node_version", format!("use Node.js {}", self.version))
+            .input("version", &self.version)
+            .input("manager", format!("{:?}", self.manager).to_lowercase())
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![Claim::shared(Resource::lock(self.manager.program()))]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        ctx.require_program(self.manager.program())?;
+        if self.manager == NodeManager::Nvm {
+            let dir = std::env::var_os("NVM_DIR")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| ctx.exec().expand_home(Path::new("~/.nvm")));
+            if !dir.join("nvm.sh").exists() {
+                return Err(ShiftError::Custom(format!(
+                    "nvm is not installed ({} not found)",
+                    dir.join("nvm.sh").display()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network]
+    }
+
+    fn inputs(&self, _ctx: &ExecutionContext) -> ShiftResult<Vec<ExternalInput>> {
+        Ok(vec![ExternalInput::toolchain("node", &self.version)])
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        apply_with(&self.manager, ctx, &self.version)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        revert_with(&self.manager, ctx, &self.version)
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        is_applied_with(&self.manager, ctx, &self.version)
+    }
+}
+
+/// Installs a Python version with `pyenv` and makes it the global default.
+///
+/// Applied while `version` is the global default.
+/// Revert makes the version that was the default before the first apply
+/// the default again, and uninstalls this one if the shift installed it.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PythonVersion {
+    version: String,
+}
+
+impl PythonVersion {
+    pub fn new(version: impl Into<String>) -> Self {
+        PythonVersion {
+            version: version.into(),
+        }
+    }
+}
+
+struct Pyenv;
+
+impl Manager for Pyenv {
+    fn program(&self) -> &str {
+        "pyenv"
+    }
+
+    fn installed(&self, ctx: &ExecutionContext, version: &str) -> ShiftResult<bool> {
+        let list = run(ctx, "pyenv", &["versions", "--bare"])?;
+        Ok(list.lines().any(|line| version_matches(version, line)))
+    }
+
+    fn active(&self, ctx: &ExecutionContext) -> ShiftResult<Option<String>> {
+        let out = run(ctx, "pyenv", &["global"])?;
+        Ok(out.lines().next().map(|v| v.trim().to_string()))
+    }
+
+    fn install(&self, ctx: &ExecutionContext, version: &str) -> ShiftResult<()> {
+        run(ctx, "pyenv", &["install", "--skip-existing", version]).map(drop)
+    }
+
+    fn activate(&self, ctx: &ExecutionContext, version: &str) -> ShiftResult<()> {
+        run(ctx, "pyenv", &["global", &Pyenv::exact(ctx, version)?]).map(drop)
+    }
+
+    fn uninstall(&self, ctx: &ExecutionContext, version: &str) -> ShiftResult<()> {
+        run(
+            ctx,
+            "pyenv",
+            &["uninstall", "--force", &Pyenv::exact(ctx, version)?],
+        )
+        .map(drop)
+    }
+}
+
+impl Pyenv {
+    /// The newest installed version matching `version`; `pyenv global` and
+    /// `pyenv uninstall` do not accept prefixes such as `3.12`.
+    fn exact(ctx: &ExecutionContext, version: &str) -> ShiftResult<String> {
+        let list = run(ctx, "pyenv", &["versions", "--bare"])?;
+        Ok(list
+            .lines()
+            .map(str::trim)
+            .rev()
+            .find(|line| version_matches(version, line))
+            .unwrap_or(version)
+            .to_string())
+    }
+}
+
+impl Shift for PythonVersion {
+    fn metadata(&self) -> ShiftMetadata {
+        ShiftMetadata::new("python_version", format!("use Python {}", self.version))
+            .input("version", &self.version)
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![Claim::shared(Resource::lock("pyenv"))]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        ctx.require_program("pyenv")
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network]
+    }
+
+    fn inputs(&self, _ctx: &ExecutionContext) -> ShiftResult<Vec<ExternalInput>> {
+        Ok(vec![ExternalInput::toolchain("python", &self.version)])
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        apply_with(&Pyenv, ctx, &self.version)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        revert_with(&Pyenv, ctx, &self.version)
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        is_applied_with(&Pyenv, ctx, &self.version)
+    }
+}
@@ -0,0 +1,498 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::metadata::ShiftMetadata;
+use crate::permissions;
+use crate::resource::{Claim, Resource};
+use crate::service::Service;
+use crate::shift::{Shift, ShiftOutcome};
+use crate::shifts::Cmd;
+use crate::validate::{find_program, ValidationContext};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WebServer {
+    Nginx,
+    Caddy,
+}
+
+impl WebServer {
+    fn name(self) -> &'static str {
+        match self {
+            WebServer::Nginx => "nginx",
+            WebServer::Caddy => "caddy",
+        }
+    }
+}
+
+/// A virtual host for `domain` (and `aliases`) on nginx or Caddy.
+///
+/// The host either proxies to `upstream` (`127.0.0.1:3000`, or a URL) or
+/// serves the files under `root`. With `tls_cert` and `tls_key` it serves
+/// HTTPS with that certificate, and nginx redirects plain HTTP to it;
+/// without them nginx serves plain HTTP and Caddy gets a certificate on
+/// its own.
+///
+/// The server is nginx if it is installed, else Caddy. The server block
+/// goes to `dir`, by default `/etc/nginx/conf.d` or `/etc/caddy/sites`;
+/// for Caddy, an `import` of that directory is added to `caddyfile` if it
+/// is missing. The whole config is checked with `nginx -t` or `caddy
+/// validate` before the server is reloaded, and a failing check puts the
+/// previous server block back. Applied while the server block is current.
+/// Revert restores the file as it was, or removes it, and reloads again.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct WebVhost {
+    domain: String,
+    #[serde(default)]
+    aliases: Vec<String>,
+    #[serde(default)]
+    upstream: Option<String>,
+    #[serde(default)]
+    root: Option<PathBuf>,
+    #[serde(default)]
+    tls_cert: Option<PathBuf>,
+    #[serde(default)]
+    tls_key: Option<PathBuf>,
+    #[serde(default)]
+    server: Option<WebServer>,
+    #[serde(default)]
+    dir: Option<PathBuf>,
+    #[serde(default = "default_caddyfile")]
+    caddyfile: PathBuf,
+}
+
+fn default_caddyfile() -> PathBuf {
+    PathBuf::from("/etc/caddy/Caddyfile")
+}
+
+impl WebVhost {
+    fn new(domain: impl Into<String>) -> Self {
+        WebVhost {
+            domain: domain.into(),
+            aliases: Vec::new(),
+            upstream: None,
+            root: None,
+            tls_cert: None,
+            tls_key: None,
+            server: None,
+            dir: None,
+            caddyfile: default_caddyfile(),
+        }
+    }
+
+    /// A host for `domain` that proxies to `upstream`.
+    pub fn proxy(domain: impl Into<String>, upstream: impl Into<String>) -> Self {
+        WebVhost {
+            upstream: Some(upstream.into()),
+            ..Self::new(domain)
+        }
+    }
+
+    /// A host for `domain` that serves the files under `root`.
+    pub fn files(domain: impl Into<String>, root: impl Into<PathBuf>) -> Self {
+        WebVhost {
+            root: Some(root.into()),
+            ..Self::new(domain)
+        }
+    }
+
+    pub fn alias(mut self, alias: impl Into<String>) -> Self {
+        self.aliases.push(alias.into());
+        self
+    }
+
+    /// Serves HTTPS with the certificate chain at `cert` and key at `key`.
+    pub fn tls(mut self, cert: impl Into<PathBuf>, key: impl Into<PathBuf>) -> Self {
+        self.tls_cert = Some(cert.into());
+        self.tls_key = Some(key.into());
+        self
+    }
+
+    pub fn server(mut self, server: WebServer) -> Self {
+        self.server = Some(server);
+        self
+    }
+
+    pub fn dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = Some(dir.into());
+        self
+    }
+
+    pub fn caddyfile(mut self, caddyfile: impl Into<PathBuf>) -> Self {
+        self.caddyfile = caddyfile.into();
+        self
+    }
+
+    fn server_or_detect(&self) -> WebServer {
+        self.server.unwrap_or_else(|| {
+            if find_program("nginx").is_none() && find_program("caddy").is_some() {
+                WebServer::Caddy
+            } else {
+                WebServer::Nginx
+            }
+        })
+    }
+
+    fn conf_dir(&self) -> PathBuf {
+        if let Some(dir) = &self.dir {
+            return dir.clone();
+        }
+        match self.server_or_detect() {
+            WebServer::Nginx => "/etc/nginx/conf.d".into(),
+            WebServer::Caddy => "/etc/caddy/sites".into(),
+        }
+    }
+
+    fn path(&self) -> PathBuf {
+        let name = self.domain.replace('*', "_");
+        match self.server_or_detect() {
+            WebServer::Nginx => self.conf_dir().join(format!("{name}.conf")),
+            WebServer::Caddy => self.conf_dir().join(format!("{name}.caddy")),
+        }
+    }
+
+    fn names(&self) -> Vec<&str> {
+        std::iter::once(self.domain.as_str())
+            .chain(self.aliases.iter().map(String::as_str))
+            .collect()
+    }
+
+    fn upstream_url(upstream: &str) -> String {
+        match upstream.contains("://") {
+            true => upstream.to_string(),
+            false => format!("http://{upstream}"),
+        }
+    }
+
+    fn render(&self) -> String {
+        match self.server_or_detect() {
+            WebServer::Nginx => self.render_nginx(),
+            WebServer::Caddy => self.render_caddy(),
+        }
+    }
+
+    fn render_nginx(&self) -> String {
+        let names = self.names().join(" ");
+        let mut location = String::from("    location / {\n");
+        if let Some(upstream) = &self.upstream {
+            location.push_str(&format!(
+                "        proxy_pass {};\n",
+                Self::upstream_url(upstream)
+            ));
+            for line in [
+                "proxy_http_version 1.1;",
+                "proxy_set_header Host $host;",
+                "proxy_set_header X-Real-IP $remote_addr;",
+                "proxy_set_header X-Forwarded-For $proxy_add_x_forwarded_for;",
+                "proxy_set_header X-Forwarded-Proto $scheme;",
+                "proxy_set_header Upgrade $http_upgrade;",
+                "proxy_set_header Connection $http_connection;",
+            ] {
+                location.push_str(&format!("        {line}\n"));
+            }
+        }
+        if let Some(root) = &self.root {
+            location.push_str(&format!("        root {};\n", root.display()));
+            location.push_str("        index index.html;\n");
+            location.push_str("        try_files $uri $uri/ =404;\n");
+        }
+        location.push_str("    }\n");
+
+        let mut text = String::from("# Managed by skies.\n");
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => {
+                text.push_str(&format!(
+                    "server {{\n    listen 80;\n    listen [::]:80;\n    server_name {names};\n    \
+                     return 301 https://$host$request_uri;\n}}\n\n"
+                ));
+                text.push_str(&format!(
+                    "server {{\n    listen 443 ssl;\n    listen [::]:443 ssl;\n    server_name {names};\n    \
+                     ssl_certificate {};\n    ssl_certificate_key {};\n\n{location}}}\n",
+                    cert.display(),
+                    key.display()
+                ));
+            }
+            _ => {
+                text.push_str(&format!(
+                    "server {{\n    listen 80;\n    listen [::]:80;\n    server_name {names};\n\n{location}}}\n"
+                ));
+            }
+        }
+        text
+    }
+
+    fn render_caddy(&self) -> String {
+        let mut text = format!("# Managed by skies.\n{} {{\n", self.names().join(", "));
+        if let (Some(cert), Some(key)) = (&self.tls_cert, &self.tls_key) {
+            text.push_str(&format!("\ttls {} {}\n", cert.display(), key.display()));
+        }
+        if let Some(upstream) = &self.upstream {
+            text.push_str(&format!("\treverse_proxy {upstream}\n"));
+        }
+        if let Some(root) = &self.root {
+            text.push_str(&format!("\troot * {}\n\tfile_server\n", root.display()));
+        }
+        text.push_str("}\n");
+        text
+    }
+
+    /// The Caddyfile line that pulls in the server blocks.
+    fn import_line(&self) -> String {
+        format!("import {}/*.caddy", self.conf_dir().display())
+    }
+
+    fn has_import(&self, caddyfile: &str) -> bool {
+        let import = self.import_line();
+        caddyfile.lines().any(|line| line.trim() == import)
+    }
+
+    fn read(path: &Path) -> ShiftResult<Option<String>> {
+        match fs::read_to_string(path) {
+            Ok(text) => Ok(Some(text)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Adds the `import` to the Caddyfile. Returns whether it was missing.
+    fn add_import(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        let path = ctx.resolve(&self.caddyfile)?;
+        let current = Self::read(&path)?.unwrap_or_default();
+        if self.has_import(&current) {
+            return Ok(false);
+        }
+        let mut text = current;
+        if !text.is_empty() && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        text.push_str(&self.import_line());
+        text.push('\n');
+        let mode = permissions::mode_of(&path).ok().flatten();
+        permissions::write_file(&path, text.as_bytes(), mode)?;
+        Ok(true)
+    }
+
+    fn remove_import(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let path = ctx.resolve(&self.caddyfile)?;
+        let Some(current) = Self::read(&path)? else {
+            return Ok(());
+        };
+        let import = self.import_line();
+        let text: String = current
+            .lines()
+            .filter(|line| line.trim() != import)
+            .map(|line| format!("{line}\n"))
+            .collect();
+        let mode = permissions::mode_of(&path).ok().flatten();
+        permissions::write_file(&path, text.as_bytes(), mode)
+    }
+
+    /// Checks the server's whole config, now including the server block.
+    fn check(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let cmd = match self.server_or_detect() {
+            WebServer::Nginx => Cmd::new("nginx").args(["-t", "-q"]),
+            WebServer::Caddy => Cmd::new("caddy")
+                .args(["validate", "--adapter", "caddyfile", "--config"])
+                .arg(ctx.resolve(&self.caddyfile)?.display().to_string()),
+        };
+        cmd.output(ctx).map(drop)
+    }
+
+    /// Puts `previous` back at `path`, or removes the file if there was none.
+    fn put_back(path: &Path, previous: Option<&str>) -> ShiftResult<()> {
+        match previous {
+            Some(previous) => {
+                let mode = permissions::mode_of(path).ok().flatten();
+                permissions::write_file(path, previous.as_bytes(), mode)
+            }
+            None if path.exists() => Ok(fs::remove_file(path)?),
+            None => Ok(()),
+        }
+        .map_err(|err| err.context(format!("putting back {}", path.display())))
+    }
+}
+
+impl Shift for WebVhost {
+    fn metadata(&self) -> ShiftMetadata {
+        let server = self.server_or_detect().name();
+        let what = match (&self.upstream, &self.root) {
+            (Some(upstream), _) => format!("proxy {} to {upstream}", self.domain),
+            (None, Some(root)) => format!("serve {} from {}", self.domain, root.display()),
+            (None, None) => format!("serve {}", self.domain),
+        };
+        let mut meta = ShiftMetadata::new("web_vhost", format!("{what} with {server}"))
+            .target(self.path())
+            .input("domain", &self.domain)
+            .input("server", server);
+        if !self.aliases.is_empty() {
+            meta = meta.input("aliases", &self.aliases);
+        }
+        if let Some(cert) = &self.tls_cert {
+            meta = meta.input("tls_cert", cert);
+        }
+        meta
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        let mut claims = vec![
+            Claim::exclusive(Resource::path(self.path())),
+            Claim::exclusive(Resource::lock(self.server_or_detect().name())),
+        ];
+        for path in [&self.tls_cert, &self.tls_key].into_iter().flatten() {
+            claims.push(Claim::shared(Resource::path(path)));
+        }
+        claims
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        for name in self.names() {
+            let body = name.strip_prefix("*.").unwrap_or(name);
+            let valid = !body.is_empty()
+                && body
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.'));
+            if !valid {
+                return Err(ShiftError::Custom(format!("`{name}` is not a domain name")));
+            }
+        }
+        match (&self.upstream, &self.root) {
+            (Some(_), Some(_)) => {
+                return Err(ShiftError::Custom(
+                    "set `upstream` or `root`, not both".into(),
+                ))
+            }
+            (None, None) => {
+                return Err(ShiftError::Custom(
+                    "nothing to serve: set `upstream` or `root`".into(),
+                ))
+            }
+            _ => {}
+        }
+        let unsafe_text = |text: &str| {
+            text.is_empty()
+                || text
+                    .chars()
+                    .any(|c| c.is_whitespace() || matches!(c, ';' | '{' | '}' | '"' | '\''))
+        };
+        if let Some(upstream) = self.upstream.as_deref().filter(|u| unsafe_text(u)) {
+            return Err(ShiftError::Custom(format!(
+                "`{upstream}` is not a usable upstream address"
+            )));
+        }
+        for path in [&self.root, &self.tls_cert, &self.tls_key]
+            .into_iter()
+            .flatten()
+        {
+            if unsafe_text(&path.to_string_lossy()) {
+                return Err(ShiftError::Custom(format!(
+                    "{} cannot be used in a server block: avoid spaces, quotes, `;` and braces",
+                    path.display()
+                )));
+            }
+        }
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(_), None) | (None, Some(_)) => {
+                return Err(ShiftError::Custom(
+                    "set both `tls_cert` and `tls_key`, or neither".into(),
+                ))
+            }
+            (Some(cert), Some(key)) => {
+                for path in [cert, key] {
+                    if !ctx.will_exist(path) {
+                        return Err(ShiftError::Custom(format!(
+                            "{} does not exist and no earlier shift creates it",
+                            path.display()
+                        )));
+                    }
+                }
+            }
+            (None, None) => {}
+        }
+        let server = self.server_or_detect();
+        ctx.require_program(server.name())?;
+        ctx.require_program("systemctl")?;
+        ctx.exec().resolve(&self.path())?;
+        if server == WebServer::Caddy {
+            ctx.exec().resolve(&self.caddyfile)?;
+        }
+        Ok(())
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Sudo]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        if self.is_applied(ctx)? {
+            return Ok(ShiftOutcome::Unchanged);
+        }
+        let server = self.server_or_detect();
+        let path = ctx.resolve(&self.path())?;
+        let previous = Self::read(&path)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mode = ctx.permissions().file_mode(Some(0o644));
+        permissions::write_file(&path, self.render().as_bytes(), mode)?;
+        let added_import = server == WebServer::Caddy && self.add_import(ctx)?;
+        if let Err(err) = self.check(ctx) {
+            Self::put_back(&path, previous.as_deref())?;
+            if added_import {
+                self.remove_import(ctx)?;
+            }
+            return Err(err
+                .context(format!("checking the {} config", server.name()))
+                .hint(format!("{} was put back as it was", path.display())));
+        }
+        if ctx.get_state("previous").is_none() {
+            ctx.set_state("previous", json!(previous));
+        }
+        if added_import {
+            ctx.set_state("added_import", json!(true));
+        }
+        Service::new(server.name()).reload_if_active(ctx)?;
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let server = self.server_or_detect();
+        let path = ctx.resolve(&self.path())?;
+        match ctx.get_state("previous") {
+            Some(Value::String(previous)) => Self::put_back(&path, Some(&previous))?,
+            Some(_) => Self::put_back(&path, None)?,
+            None => return Ok(()),
+        }
+        if ctx.get_state("added_import") == Some(json!(true)) {
+            self.remove_import(ctx)?;
+        }
+        self.check(ctx)
+            .map_err(|err| err.context(format!("checking the {} config", server.name())))?;
+        Service::new(server.name()).reload_if_active(ctx)?;
+        ctx.clear_state("previous");
+        ctx.clear_state("added_import");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        let path = ctx.resolve(&self.path())?;
+        if Self::read(&path)?.as_deref() != Some(self.render().as_str()) {
+            return Ok(false);
+        }
+        match self.server_or_detect() {
+            WebServer::Nginx => Ok(true),
+            WebServer::Caddy => {
+                let caddyfile = Self::read(&ctx.resolve(&self.caddyfile)?)?;
+                Ok(caddyfile.is_some_and(|text| self.has_import(&text)))
+            }
+        }
+    }
+}
@@ -0,0 +1,787 @@
+//! Joining private networks: WireGuard interfaces and Tailscale.
+//!
+//! Private keys and auth keys never appear on a command line, in plan
+//! metadata or in the journal; they are passed to the tools through the
+//! environment or files only root can read.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::fs::read_text;
+use crate::metadata::ShiftMetadata;
+use crate::permissions;
+use crate::resource::{Claim, Resource};
+use crate::service::Service;
+use crate::shift::{Shift, ShiftOutcome};
+use crate::shifts::Cmd;
+use crate::validate::{find_program, ValidationContext};
+
+/// A peer of a [`WireguardInterface`].
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct WireguardPeer {
+    public_key: String,
+    allowed_ips: Vec<String>,
+    #[serde(default)]
+    endpoint: Option<String>,
+    #[serde(default)]
+    persistent_keepalive: Option<u16>,
+}
+
+impl WireguardPeer {
+    pub fn new<I, S>(public_key: impl Into<String>, allowed_ips: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        WireguardPeer {
+            public_key: public_key.into(),
+            allowed_ips: allowed_ips.into_iter().map(Into::into).collect(),
+            endpoint: None,
+            persistent_keepalive: None,
+        }
+    }
+
+    /// Where to reach the peer, `host:port`.
+    pub fn endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = Some(endpoint.into());
+        self
+    }
+
+    /// Seconds between keepalive packets, for peers behind NAT.
+    pub fn persistent_keepalive(mut self, seconds: u16) -> Self {
+        self.persistent_keepalive = Some(seconds);
+        self
+    }
+}
+
+/// A WireGuard interface, `wg0` by default, brought up with `wg-quick`.
+///
+/// The config goes to `<dir>/<name>.conf`, `/etc/wireguard` by default,
+/// with mode 0600, and the `wg-quick@<name>` service is enabled and
+/// started. The private key comes from the environment variable
+/// `private_key_env`; without one, the key already in the config is kept,
+/// or a new one is generated with `wg genkey`. The interface's public key
+/// is recorded as the output `public_key`.
+///
+/// Peer changes are loaded into the running interface with `wg syncconf`,
+/// so existing tunnels stay up; changes to the interface itself restart
+/// it. Applied while the config is current, the service is running and
+/// `wg show` lists every peer. Revert puts back the config as it was (a
+/// copy is kept next to it, since it holds a private key) or removes it,
+/// and stops the service if it was not enabled before.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct WireguardInterface {
+    #[serde(default = "default_interface")]
+    name: String,
+    address: Vec<String>,
+    #[serde(default)]
+    listen_port: Option<u16>,
+    #[serde(default)]
+    dns: Vec<String>,
+    #[serde(default)]
+    private_key_env: Option<String>,
+    #[serde(default)]
+    peers: Vec<WireguardPeer>,
+    #[serde(default = "default_wireguard_dir")]
+    dir: PathBuf,
+}
+
+fn default_interface() -> String {
+    "wg0".to_string()
+}
+
+fn default_wireguard_dir() -> PathBuf {
+    PathBuf::from("/etc/wireguard")
+}
+
+impl WireguardInterface {
+    pub fn new<I, S>(address: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        WireguardInterface {
+            name: default_interface(),
+            address: address.into_iter().map(Into::into).collect(),
+            listen_port: None,
+            dns: Vec::new(),
+            private_key_env: None,
+            peers: Vec::new(),
+            dir: default_wireguard_dir(),
+        }
+    }
+
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    pub fn listen_port(mut self, port: u16) -> Self {
+        self.listen_port = Some(port);
+        self
+    }
+
+    pub fn dns(mut self, server: impl Into<String>) -> Self {
+        self.dns.push(server.into());
+        self
+    }
+
+    /// Reads the private key from the environment variable `var`.
+    pub fn private_key_env(mut self, var: impl Into<String>) -> Self {
+        self.private_key_env = Some(var.into());
+        self
+    }
+
+    pub fn peer(mut self, peer: WireguardPeer) -> Self {
+        self.peers.push(peer);
+        self
+    }
+
+    pub fn dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = dir.into();
+        self
+    }
+
+    fn path(&self) -> PathBuf {
+        self.dir.join(format!("{}.conf", self.name))
+    }
+
+    fn service(&self) -> Service {
+        Service::new(format!("wg-quick@{}", self.name))
+    }
+
+    /// The `[Interface]` section, which `wg syncconf` cannot change.
+    fn render_interface(&self, private_key: &str) -> String {
+        let mut text = format!(
+            "[Interface]\nPrivateKey = {private_key}\nAddress = {}\n",
+            self.address.join(", ")
+        );
+        if let Some(port) = self.listen_port {
+            text.push_str(&format!("ListenPort = {port}\n"));
+        }
+        if !self.dns.is_empty() {
+            text.push_str(&format!("DNS = {}\n", self.dns.join(", ")));
+        }
+        text
+    }
+
+    fn render(&self, private_key: &str) -> String {
+        let mut text = String::from("# Managed by skies.\n");
+        text.push_str(&self.render_interface(private_key));
+        for peer in &self.peers {
+            text.push_str(&format!(
+                "\n[Peer]\nPublicKey = {}\nAllowedIPs = {}\n",
+                peer.public_key,
+                peer.allowed_ips.join(", ")
+            ));
+            if let Some(endpoint) = &peer.endpoint {
+                text.push_str(&format!("Endpoint = {endpoint}\n"));
+            }
+            if let Some(seconds) = peer.persistent_keepalive {
+                text.push_str(&format!("PersistentKeepalive = {seconds}\n"));
+            }
+        }
+        text
+    }
+
+    /// The `[Interface]` section of an existing config, up to the first
+    /// peer.
+    fn interface_section(config: &str) -> &str {
+        let start = config.find("[Interface]").unwrap_or(0);
+        let end = config.find("[Peer]").unwrap_or(config.len());
+        config[start..end.max(start)].trim_end()
+    }
+
+    fn existing_key(config: &str) -> Option<String> {
+        config.lines().find_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            (key.trim() == "PrivateKey").then(|| value.trim().to_string())
+        })
+    }
+
+    /// The private key to use: from `private_key_env`, else the one in the
+    /// current config, if any.
+    fn private_key(&self, current: Option<&str>) -> ShiftResult<Option<String>> {
+        match &self.private_key_env {
+            Some(var) => std::env::var(var).map(Some).map_err(|_| {
+                ShiftError::Custom(format!("environment variable `{var}` is not set"))
+            }),
+            None => Ok(current.and_then(Self::existing_key)),
+        }
+    }
+
+    fn generate_key(ctx: &ExecutionContext) -> ShiftResult<String> {
+        let file = ctx.temp_dir()?.join("private.key");
+        // Through a file, so the key is never logged as command output;
+        // `wg` writes it, so it is on the real disk, not in `ctx.fs()`.
+        Cmd::new("sh")
+            .args(["-c", "umask 077 && wg genkey > \"$1\"", "sh"])
+            .arg(file.display().to_string())
+            .output(ctx)?;
+        let key = fs::read_to_string(&file)?.trim().to_string();
+        fs::remove_file(&file)?;
+        Ok(key)
+    }
+
+    fn public_key(ctx: &ExecutionContext, private_key: &str) -> ShiftResult<String> {
+        let out = Cmd::new("sh")
+            .args(["-c", "printf '%s\\n' \"$WG_PRIVATE_KEY\" | wg pubkey"])
+            .env("WG_PRIVATE_KEY", private_key)
+            .output(ctx)?;
+        Ok(out.trim().to_string())
+    }
+
+    /// Loads the peers in the config into the running interface.
+    fn sync(&self, ctx: &ExecutionContext, path: &Path) -> ShiftResult<()> {
+        let stripped = ctx.temp_dir()?.join(format!("{}.conf", self.name));
+        Cmd::new("sh")
+            .args([
+                "-c",
+                "umask 077 && wg-quick strip \"$2\" > \"$3\" && wg syncconf \"$1\" \"$3\"; \
+                 status=$?; rm -f \"$3\"; exit $status",
+                "sh",
+                &self.name,
+            ])
+            .arg(path.display().to_string())
+            .arg(stripped.display().to_string())
+            .output(ctx)
+            .map(drop)
+    }
+
+    fn backup_path(path: &Path) -> PathBuf {
+        path.with_extension("conf.skies-backup")
+    }
+
+    /// Public keys of the peers the running interface knows.
+    fn live_peers(&self, ctx: &ExecutionContext) -> ShiftResult<Option<BTreeSet<String>>> {
+        match Cmd::new("wg")
+            .args(["show", &self.name, "peers"])
+            .output(ctx)
+        {
+            Ok(out) => Ok(Some(out.lines().map(|l| l.trim().to_string()).collect())),
+            Err(ShiftError::Command { .. }) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl Shift for WireguardInterface {
+    fn metadata(&self) -> ShiftMetadata {
+        let peers: Vec<&str> = self.peers.iter().map(|p| p.public_key.as_str()).collect();
+        let mut meta = ShiftMetadata::new(
+            "wireguard",
+            format!(
+                "bring up WireGuard {} at {} with {} peers",
+                self.name,
+                self.address.join(", "),
+                self.peers.len()
+            ),
+        )
+        .target(self.path())
+        .input("name", &self.name)
+        .input("address", &self.address)
+        .input("peers", peers);
+        if let Some(port) = self.listen_port {
+            meta = meta.input("listen_port", port);
+        }
+        if let Some(var) = &self.private_key_env {
+            meta = meta.input("private_key_env", var);
+        }
+        meta
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        let mut claims = vec![
+            Claim::exclusive(Resource::path(self.path())),
+            Claim::exclusive(Resource::lock(format!("wireguard {}", self.name))),
+        ];
+        if let Some(port) = self.listen_port {
+            claims.push(Claim::exclusive(Resource::Port(port)));
+        }
+        claims
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        let valid_name = !self.name.is_empty()
+            && self.name.len() <= 15
+            && self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+        if !valid_name {
+            return Err(ShiftError::Custom(format!(
+                "`{}` is not a usable interface name",
+                self.name
+            )));
+        }
+        if self.address.is_empty() {
+            return Err(ShiftError::Custom("no `address` for the interface".into()));
+        }
+        let one_line = |text: &str| !text.is_empty() && !text.contains(['\n', '[', ']', ',']);
+        for peer in &self.peers {
+            if peer.public_key.len() != 44 || !peer.public_key.ends_with('=') {
+                return Err(ShiftError::Custom(format!(
+                    "`{}` is not a WireGuard public key",
+                    peer.public_key
+                )));
+            }
+            if peer.allowed_ips.is_empty() {
+                return Err(ShiftError::Custom(format!(
+                    "peer {} has no `allowed_ips`",
+                    peer.public_key
+                )));
+            }
+            if let Some(endpoint) = &peer.endpoint {
+                if !endpoint.contains(':') || endpoint.contains(char::is_whitespace) {
+                    return Err(ShiftError::Custom(format!(
+                        "`{endpoint}` is not a `host:port` endpoint"
+                    )));
+                }
+            }
+        }
+        let values = self
+            .address
+            .iter()
+            .chain(&self.dns)
+            .chain(self.peers.iter().flat_map(|p| &p.allowed_ips));
+        if let Some(bad) = values.into_iter().find(|value| !one_line(value)) {
+            return Err(ShiftError::Custom(format!("`{bad}` is not an address")));
+        }
+        if let Some(var) = &self.private_key_env {
+            ctx.require_env(var)?;
+        }
+        for program in ["wg", "wg-quick", "systemctl"] {
+            ctx.require_program(program)?;
+        }
+        ctx.exec().resolve(&self.path())?;
+        Ok(())
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Sudo]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let path = ctx.resolve(&self.path())?;
+        let current = read_text(ctx.fs(), &path)?;
+        let private_key = match self.private_key(current.as_deref())? {
+            Some(key) => key,
+            None => Self::generate_key(ctx)?,
+        };
+        let rendered = self.render(&private_key);
+        let service = self.service();
+        let mut changed = false;
+
+        let reconfigured = current.as_deref() != Some(rendered.as_str())
+            || !permissions::mode_matches(ctx.fs(), &path, Some(0o600))?;
+        if reconfigured {
+            if ctx.get_state("backup").is_none() {
+                let backup = match &current {
+                    Some(current) => {
+                        let backup = Self::backup_path(&path);
+                        ctx.fs().write(&backup, current.as_bytes(), Some(0o600))?;
+                        json!(backup)
+                    }
+                    None => Value::Null,
+                };
+                ctx.set_state("backup", backup);
+            }
+            if let Some(dir) = path.parent() {
+                ctx.fs().create_dir_all(dir)?;
+            }
+            ctx.fs().write(&path, rendered.as_bytes(), Some(0o600))?;
+            changed = true;
+        }
+
+        let enabled = service.is_enabled(ctx)?;
+        let active = service.is_active(ctx)?;
+        if !enabled || !active {
+            if ctx.get_state("was_enabled").is_none() {
+                ctx.set_state("was_enabled", json!(enabled));
+            }
+            service.enable_now(ctx)?;
+            changed = true;
+        } else if reconfigured {
+            let same_interface = current.as_deref().map(Self::interface_section)
+                == Some(Self::interface_section(&rendered));
+            if same_interface {
+                self.sync(ctx, &path)?;
+            } else {
+                service.restart(ctx)?;
+            }
+        }
+
+        ctx.output("public_key", Self::public_key(ctx, &private_key)?);
+        Ok(ShiftOutcome::changed_if(changed))
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let path = ctx.resolve(&self.path())?;
+        let service = self.service();
+        match ctx.get_state("backup") {
+            Some(Value::String(backup)) => {
+                ctx.fs().rename(Path::new(&backup), &path)?;
+                service.restart_if_active(ctx)?;
+            }
+            Some(_) if ctx.fs().exists(&path) => ctx.fs().remove_file(&path)?,
+            _ => {}
+        }
+        if ctx.get_state("was_enabled") == Some(json!(false)) {
+            service.disable_now(ctx)?;
+        }
+        ctx.clear_state("backup");
+        ctx.clear_state("was_enabled");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        let path = ctx.resolve(&self.path())?;
+        let Some(current) = read_text(ctx.fs(), &path)? else {
+            return Ok(false);
+        };
+        let Some(private_key) = self.private_key(Some(&current))? else {
+            return Ok(false);
+        };
+        if current != self.render(&private_key)
+            || !permissions::mode_matches(ctx.fs(), &path, Some(0o600))?
+        {
+            return Ok(false);
+        }
+        let service = self.service();
+        if !service.is_enabled(ctx)? || !service.is_active(ctx)? {
+            return Ok(false);
+        }
+        let Some(live) = self.live_peers(ctx)? else {
+            return Ok(false);
+        };
+        Ok(self
+            .peers
+            .iter()
+            .all(|peer| live.contains(&peer.public_key)))
+    }
+}
+
+/// Joins this machine to a Tailscale network (tailnet).
+///
+/// A machine not yet logged in authenticates with the auth key in the
+/// environment variable `auth_key_env`, handed to `tailscale up` through
+/// a file so it never shows in the process list or logs.
+/// `advertise_routes`, `advertise_exit_node`, `accept_routes`, `ssh`,
+/// `advertise_tags` and `login_server` (for Headscale or another control
+/// server) map to the `tailscale up` flags of the same names, and
+/// `machine_name` to `--hostname`. A machine already
+/// joined has changed settings applied with `tailscale set`, or with
+/// `tailscale up` when the tags or control server change.
+///
+/// Applied while the node is running with those settings. Revert logs the
+/// machine out if this shift joined it, and otherwise leaves it as is.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TailscaleJoin {
+    #[serde(default)]
+    auth_key_env: Option<String>,
+    #[serde(default)]
+    machine_name: Option<String>,
+    #[serde(default)]
+    advertise_routes: Vec<String>,
+    #[serde(default)]
+    advertise_exit_node: bool,
+    #[serde(default)]
+    accept_routes: bool,
+    #[serde(default)]
+    ssh: bool,
+    #[serde(default)]
+    advertise_tags: Vec<String>,
+    #[serde(default)]
+    login_server: Option<String>,
+}
+
+/// The routes `--advertise-exit-node` adds.
+const EXIT_NODE_ROUTES: [&str; 2] = ["0.0.0.0/0", "::/0"];
+
+/// `tailscale debug prefs`, as far as this shift cares.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+struct TailscalePrefs {
+    #[serde(rename = "ControlURL")]
+    control_url: String,
+    route_all: bool,
+    advertise_routes: Option<Vec<String>>,
+    advertise_tags: Option<Vec<String>>,
+    hostname: String,
+    #[serde(rename = "RunSSH")]
+    run_ssh: bool,
+}
+
+impl TailscaleJoin {
+    pub fn new() -> Self {
+        TailscaleJoin {
+            auth_key_env: None,
+            machine_name: None,
+            advertise_routes: Vec::new(),
+            advertise_exit_node: false,
+            accept_routes: false,
+            ssh: false,
+            advertise_tags: Vec::new(),
+            login_server: None,
+        }
+    }
+
+    /// Authenticates with the auth key in the environment variable `var`.
+    pub fn auth_key_env(mut self, var: impl Into<String>) -> Self {
+        self.auth_key_env = Some(var.into());
+        self
+    }
+
+    /// The machine's name in the tailnet, instead of the OS hostname.
+    pub fn machine_name(mut self, name: impl Into<String>) -> Self {
+        self.machine_name = Some(name.into());
+        self
+    }
+
+    pub fn advertise_route(mut self, route: impl Into<String>) -> Self {
+        self.advertise_routes.push(route.into());
+        self
+    }
+
+    pub fn advertise_exit_node(mut self, on: bool) -> Self {
+        self.advertise_exit_node = on;
+        self
+    }
+
+    pub fn accept_routes(mut self, on: bool) -> Self {
+        self.accept_routes = on;
+        self
+    }
+
+    pub fn ssh(mut self, on: bool) -> Self {
+        self.ssh = on;
+        self
+    }
+
+    pub fn advertise_tag(mut self, tag: impl Into<String>) -> Self {
+        self.advertise_tags.push(tag.into());
+        self
+    }
+
+    pub fn login_server(mut self, url: impl Into<String>) -> Self {
+        self.login_server = Some(url.into());
+        self
+    }
+
+    fn running(ctx: &ExecutionContext) -> ShiftResult<bool> {
+        let out = match Cmd::new("tailscale").args(["status", "--json"]).output(ctx) {
+            Ok(out) => out,
+            Err(ShiftError::Command { .. }) => return Ok(false),
+            Err(err) => return Err(err),
+        };
+        let status: Value = serde_json::from_str(&out).unwrap_or(Value::Null);
+        Ok(status["BackendState"] == "Running")
+    }
+
+    fn prefs(ctx: &ExecutionContext) -> ShiftResult<TailscalePrefs> {
+        let out = Cmd::new("tailscale").args(["debug", "prefs"]).output(ctx)?;
+        serde_json::from_str(&out).map_err(|err| {
+            ShiftError::Custom(format!(
+                "unexpected output from `tailscale debug prefs`: {err}"
+            ))
+        })
+    }
+
+    fn wanted_routes(&self) -> BTreeSet<String> {
+        let mut routes: BTreeSet<String> = self.advertise_routes.iter().cloned().collect();
+        if self.advertise_exit_node {
+            routes.extend(EXIT_NODE_ROUTES.iter().map(|r| r.to_string()));
+        }
+        routes
+    }
+
+    /// Whether the settings only `tailscale up` can change match.
+    fn up_settings_match(&self, prefs: &TailscalePrefs) -> bool {
+        let tags: BTreeSet<&String> = prefs.advertise_tags.iter().flatten().collect();
+        tags == self.advertise_tags.iter().collect()
+            && self.login_server.as_ref().is_none_or(|url| {
+                url.trim_end_matches('/') == prefs.control_url.trim_end_matches('/')
+            })
+    }
+
+    /// Whether the settings `tailscale set` can change match.
+    fn set_settings_match(&self, prefs: &TailscalePrefs) -> bool {
+        let routes: BTreeSet<String> = prefs.advertise_routes.iter().flatten().cloned().collect();
+        routes == self.wanted_routes()
+            && prefs.route_all == self.accept_routes
+            && prefs.run_ssh == self.ssh
+            && self
+                .machine_name
+                .as_ref()
+                .is_none_or(|hostname| *hostname == prefs.hostname)
+    }
+
+    /// Flags shared by `tailscale up` and `tailscale set`.
+    fn settings_flags(&self) -> Vec<String> {
+        let routes: Vec<&str> = self.advertise_routes.iter().map(String::as_str).collect();
+        let mut flags = vec![
+            format!("--advertise-routes={}", routes.join(",")),
+            format!("--advertise-exit-node={}", self.advertise_exit_node),
+            format!("--accept-routes={}", self.accept_routes),
+            format!("--ssh={}", self.ssh),
+        ];
+        if let Some(hostname) = &self.machine_name {
+            flags.push(format!("--hostname={hostname}"));
+        }
+        flags
+    }
+
+    fn up(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        let mut cmd = Cmd::new("tailscale")
+            .args(["up", "--reset", "--timeout=2m"])
+            .args(self.settings_flags());
+        if !self.advertise_tags.is_empty() {
+            cmd = cmd.arg(format!(
+                "--advertise-tags={}",
+                self.advertise_tags.join(",")
+            ));
+        }
+        if let Some(url) = &self.login_server {
+            cmd = cmd.arg(format!("--login-server={url}"));
+        }
+        let key_file = match &self.auth_key_env {
+            Some(var) => {
+                let key = std::env::var(var).map_err(|_| {
+                    ShiftError::Custom(format!("environment variable `{var}` is not set"))
+                })?;
+                // For `tailscale` to read, so on the real disk.
+                let file = ctx.temp_dir()?.join("authkey");
+                permissions::write_file(&file, key.trim().as_bytes(), Some(0o600))?;
+                cmd = cmd.arg(format!("--auth-key=file:{}", file.display()));
+                Some(file)
+            }
+            None => None,
+        };
+        let result = cmd.output(ctx);
+        if let Some(file) = key_file {
+            fs::remove_file(file)?;
+        }
+        result.map(drop).map_err(|err| {
+            let hint = match &self.auth_key_env {
+                Some(_) => "check the auth key is valid, unexpired and allows the requested tags",
+                None => "set `auth_key_env` so the machine can log in without a browser",
+            };
+            err.context("joining the tailnet").hint(hint)
+        })
+    }
+}
+
+impl Default for TailscaleJoin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shift for TailscaleJoin {
+    fn metadata(&self) -> ShiftMetadata {
+        let summary = match &self.machine_name {
+            Some(hostname) => format!("join the tailnet as {hostname}"),
+            None => "join the tailnet".to_string(),
+        };
+        let mut meta = ShiftMetadata::new("tailscale", summary)
+            .input("advertise_routes", self.wanted_routes())
+            .input("accept_routes", self.accept_routes)
+            .input("ssh", self.ssh);
+        if !self.advertise_tags.is_empty() {
+            meta = meta.input("advertise_tags", &self.advertise_tags);
+        }
+        if let Some(url) = &self.login_server {
+            meta = meta.input("login_server", url);
+        }
+        if let Some(var) = &self.auth_key_env {
+            meta = meta.input("auth_key_env", var);
+        }
+        meta
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![Claim::exclusive(Resource::lock("tailscale"))]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        if let Some(bad) = self
+            .advertise_tags
+            .iter()
+            .find(|tag| !tag.starts_with("tag:") || tag.contains([',', ' ']))
+        {
+            return Err(ShiftError::Custom(format!(
+                "`{bad}` is not a Tailscale tag; tags look like `tag:server`"
+            )));
+        }
+        if let Some(bad) = self
+            .advertise_routes
+            .iter()
+            .find(|route| !route.contains('/') || route.contains([',', ' ']))
+        {
+            return Err(ShiftError::Custom(format!(
+                "`{bad}` is not a route; give a CIDR such as `10.0.0.0/24`"
+            )));
+        }
+        if let Some(var) = &self.auth_key_env {
+            ctx.require_env(var)?;
+        }
+        ctx.require_program("tailscale")
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network, Capability::Sudo]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let daemon = Service::new("tailscaled");
+        if find_program("systemctl").is_some() && !daemon.is_active(ctx)? {
+            daemon.enable_now(ctx)?;
+        }
+        if !Self::running(ctx)? {
+            self.up(ctx)?;
+            if ctx.get_state("joined").is_none() {
+                ctx.set_state("joined", json!(true));
+            }
+            return Ok(ShiftOutcome::Changed);
+        }
+        let prefs = Self::prefs(ctx)?;
+        if !self.up_settings_match(&prefs) {
+            self.up(ctx)?;
+        } else if !self.set_settings_match(&prefs) {
+            Cmd::new("tailscale")
+                .arg("set")
+                .args(self.settings_flags())
+                .output(ctx)?;
+        } else {
+            return Ok(ShiftOutcome::Unchanged);
+        }
+        Ok(ShiftOutcome::Changed)
+    }
+
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        if ctx.get_state("joined") == Some(json!(true)) && Self::running(ctx)? {
+            Cmd::new("tailscale").arg("logout").output(ctx)?;
+        }
+        ctx.clear_state("joined");
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        if !Self::running(ctx)? {
+            return Ok(false);
+        }
+        let prefs = Self::prefs(ctx)?;
+        Ok(self.up_settings_match(&prefs) && self.set_settings_match(&prefs))
+    }
+}
@@ -0,0 +1,333 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::artifacts::Artifact;
+use crate::capability::Capability;
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::metadata::ShiftMetadata;
+use crate::resource::{Claim, Resource};
+use crate::shift::{Shift, ShiftOutcome};
+use crate::shifts::GitHubClone;
+use crate::validate::ValidationContext;
+
+/// Clones a set of repositories side by side into `dir`, a few at a time.
+///
+/// Repositories are listed inline in `repos`, in a `manifest` file of
+/// `[[repo]]` tables, or both. Each has a `url` (or GitHub `owner/name`),
+/// an optional `branch`, and an optional `path` within `dir` that defaults
+/// to the repository's name. Every repository is cloned as a
+/// [`GitHubClone`] would, so the shift is applied while every clone is,
+/// and reverting keeps clones with local work. A
+/// failed clone does not stop the others; the shift fails once they are
+/// all done, and reports how each went.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Workspace {
+    dir: PathBuf,
+    #[serde(default)]
+    repos: Vec<WorkspaceRepo>,
+    #[serde(default)]
+    manifest: Option<PathBuf>,
+    #[serde(default = "default_jobs")]
+    jobs: usize,
+    #[serde(default)]
+    token_env: Option<String>,
+}
+
+fn default_jobs() -> usize {
+    4
+}
+
+/// One repository of a [`Workspace`].
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct WorkspaceRepo {
+    url: String,
+    #[serde(default)]
+    branch: Option<String>,
+    #[serde(default)]
+    path: Option<PathBuf>,
+}
+
+impl WorkspaceRepo {
+    pub fn new(url: impl Into<String>) -> Self {
+        WorkspaceRepo {
+            url: url.into(),
+            branch: None,
+            path: None,
+        }
+    }
+
+    pub fn branch(mut self, branch: impl Into<String>) -> Self {
+        self.branch = Some(branch.into());
+        self
+    }
+
+    /// Where in the workspace to clone it.
+    pub fn path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// `path`, or the last segment of the URL without `.git`.
+    fn dir_name(&self) -> PathBuf {
+        if let Some(path) = &self.path {
+            return path.clone();
+        }
+        let name = self
+            .url
+            .trim_end_matches('/')
+            .rsplit(['/', ':'])
+            .next()
+            .unwrap_or(&self.url);
+        PathBuf::from(name.strip_suffix(".git").unwrap_or(name))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    #[serde(default)]
+    repo: Vec<WorkspaceRepo>,
+}
+
+impl Workspace {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Workspace {
+            dir: dir.into(),
+            repos: Vec::new(),
+            manifest: None,
+            jobs: default_jobs(),
+            token_env: None,
+        }
+    }
+
+    pub fn repo(mut self, repo: WorkspaceRepo) -> Self {
+        self.repos.push(repo);
+        self
+    }
+
+    /// Also clones the repositories listed in the TOML file at `path`.
+    pub fn manifest(mut self, path: impl Into<PathBuf>) -> Self {
+        self.manifest = Some(path.into());
+        self
+    }
+
+    /// How many repositories to clone at once.
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs;
+        self
+    }
+
+    /// Reads an access token for every clone from the environment
+    /// variable `var`.
+    pub fn token_env(mut self, var: impl Into<String>) -> Self {
+        self.token_env = Some(var.into());
+        self
+    }
+
+    /// Every repository, inline ones first, as clones into `dir`.
+    fn clones(&self, ctx: &ExecutionContext) -> ShiftResult<Vec<GitHubClone>> {
+        let mut repos = self.repos.clone();
+        if let Some(manifest) = &self.manifest {
+            let path = ctx.resolve(manifest)?;
+            let text = fs::read_to_string(&path)?;
+            let manifest: Manifest = toml::from_str(&text).map_err(|err| {
+                ShiftError::Custom(format!(
+                    "invalid manifest {}: {}",
+                    path.display(),
+                    err.message()
+                ))
+            })?;
+            repos.extend(manifest.repo);
+        }
+        Ok(repos.iter().map(|repo| self.clone_of(repo)).collect())
+    }
+
+    fn clone_of(&self, repo: &WorkspaceRepo) -> GitHubClone {
+        let mut clone = GitHubClone::new(&repo.url, self.dir.join(repo.dir_name()));
+        if let Some(branch) = &repo.branch {
+            clone = clone.branch(branch);
+        }
+        if let Some(var) = &self.token_env {
+            clone = clone.token_env(var);
+        }
+        clone
+    }
+}
+
+impl Shift for Workspace {
+    fn metadata(&self) -> ShiftMetadata {
+        let mut meta = ShiftMetadata::new(
+            "workspace",
+            format!("clone a workspace into {}", self.dir.display()),
+        )
+        .target(&self.dir)
+        .input(
+            "repos",
+            self.repos
+                .iter()
+                .map(|repo| repo.url.as_str())
+                .collect::<Vec<_>>(),
+        );
+        if let Some(manifest) = &self.manifest {
+            meta = meta.input("manifest", manifest);
+        }
+        if let Some(var) = &self.token_env {
+            meta = meta.input("token_env", var);
+        }
+        meta
+    }
+
+    fn resources(&self) -> Vec<Claim> {
+        vec![Claim::exclusive(Resource::path(&self.dir))]
+    }
+
+    fn validate(&self, ctx: &ValidationContext) -> ShiftResult<()> {
+        if self.jobs == 0 {
+            return Err(ShiftError::Custom("`jobs` must be at least 1".into()));
+        }
+        if self.repos.is_empty() && self.manifest.is_none() {
+            return Err(ShiftError::Custom(
+                "a workspace needs `repos`, a `manifest`, or both".into(),
+            ));
+        }
+        if let Some(manifest) = &self.manifest {
+            if !ctx.exec().resolve(manifest)?.exists() {
+                if ctx.will_exist(manifest) {
+                    // Written by an earlier shift; its repositories are
+                    // checked when they are cloned.
+                    return Ok(());
+                }
+                return Err(ShiftError::Custom(format!(
+                    "manifest {} does not exist and no earlier shift creates it",
+                    manifest.display()
+                )));
+            }
+        }
+        let clones = self.clones(ctx.exec())?;
+        let mut seen = BTreeMap::new();
+        for clone in &clones {
+            if let Some(url) = seen.insert(clone.target().to_path_buf(), clone.url()) {
+                return Err(ShiftError::Custom(format!(
+                    "{} and {} would both be cloned into {}",
+                    url,
+                    clone.url(),
+                    clone.target().display()
+                )));
+            }
+            clone.validate(ctx)?;
+        }
+        Ok(())
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network]
+    }
+
+    fn apply(&self, ctx: &ExecutionContext) -> ShiftResult<ShiftOutcome> {
+        let clones = self.clones(ctx)?;
+        let results: Vec<Mutex<Option<ShiftResult<ShiftOutcome>>>> =
+            clones.iter().map(|_| Mutex::new(None)).collect();
+        let next = AtomicUsize::new(0);
+        thread::scope(|scope| {
+            for _ in 0..self.jobs.min(clones.len()) {
+                scope.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(clone) = clones.get(i) else {
+                        break;
+                    };
+                    let result = ctx.check_cancelled().and_then(|()| {
+                        if clone.is_applied(ctx)? {
+                            Ok(ShiftOutcome::Unchanged)
+                        } else {
+                            clone.apply(ctx)
+                        }
+                    });
+                    *results[i].lock().unwrap_or_else(|p| p.into_inner()) = Some(result);
+                });
+            }
+        });
+
+        let mut status = BTreeMap::new();
+        let mut changed = false;
+        let mut failed = Vec::new();
+        for (clone, result) in clones.iter().zip(results) {
+            let path = clone.target().display().to_string();
+            let result = result.into_inner().unwrap_or_else(|p| p.into_inner());
+            let state = match result.expect("every repository is attempted") {
+                Ok(ShiftOutcome::Changed) => {
+                    changed = true;
+                    "cloned"
+                }
+                Ok(ShiftOutcome::Unchanged) => "up to date",
+                Err(err) => {
+                    ctx.warn(&format!("{path}: {err}"));
+                    failed.push(path.clone());
+                    "failed"
+                }
+            };
+            ctx.info(&format!("{state:>10}  {path}"));
+            status.insert(path, state);
+        }
+        ctx.output("repos", &status);
+        if !failed.is_empty() {
+            return Err(ShiftError::Custom(format!(
+                "{} of {} repositories failed to clone: {}",
+                failed.len(),
+                clones.len(),
+                failed.join(", ")
+            )));
+        }
+        Ok(ShiftOutcome::changed_if(changed))
+    }
+
+    /// Reverts every clone, then removes `dir` if that left it empty.
+    fn revert(&self, ctx: &ExecutionContext) -> ShiftResult<()> {
+        for clone in self.clones(ctx)?.iter().rev() {
+            clone.revert(ctx)?;
+        }
+        let dir = ctx.resolve(&self.dir)?;
+        if is_empty_dir(&dir) {
+            fs::remove_dir(&dir)?;
+        }
+        Ok(())
+    }
+
+    fn is_applied(&self, ctx: &ExecutionContext) -> ShiftResult<bool> {
+        for clone in self.clones(ctx)? {
+            if !clone.is_applied(ctx)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// The repositories' artifacts; those in a manifest an earlier shift
+    /// writes are not known yet.
+    fn artifacts(&self, ctx: &ExecutionContext) -> ShiftResult<Vec<Artifact>> {
+        let clones = match &self.manifest {
+            Some(manifest) if !ctx.resolve(manifest)?.exists() => {
+                self.repos.iter().map(|repo| self.clone_of(repo)).collect()
+            }
+            _ => self.clones(ctx)?,
+        };
+        let mut artifacts = Vec::new();
+        for clone in clones {
+            artifacts.extend(clone.artifacts(ctx)?);
+        }
+        Ok(artifacts)
+    }
+}
+
+fn is_empty_dir(path: &Path) -> bool {
+    fs::read_dir(path).is_ok_and(|mut entries| entries.next().is_none())
+}
@@ -0,0 +1,199 @@
+//! Starlark plan files, for those used to Bazel-style configuration.
+//!
+//! A plan file ending in `.star` is a [Starlark](https://github.com/bazelbuild/starlark)
+//! program. Running it builds the same document a TOML plan file holds,
+//! which is then read as one, so both formats have every feature and
+//! share the rest of skies:
+//!
+//! ```python
+//! plan(root = ".", vars = {"editor": "vim"})
+//!
+//! shift("create_dir", id = "src", path = "src")
+//!
+//! for repo in ["skies", "dotfiles"]:
+//!     shift(
+//!         "github_clone",
+//!         repo = "danbruder/" + repo,
+//!         target = "src/" + repo,
+//!         depends_on = ["src"],
+//!     )
+//!
+//! if facts["os"] == "linux":
+//!     shift("symlink", path = "~/.editor", target = "/usr/bin/${editor}")
+//!
+//! verify(command = ["git", "-C", "src/skies", "status"])
+//! ```
+//!
+//! - `shift(type, **fields)` adds a `[[shift]]` with the given fields and
+//!   returns its `id`, if it has one. Leave `type` out for an instance of
+//!   a role: `shift(role = "service", id = "api")`.
+//! - `verify(**fields)` adds a `[[verify]]` check.
+//! - `plan(**settings)` sets top-level tables and keys such as `root`,
+//!   `vars`, `params`, `roles` and `matrix`.
+//! - `facts` and `vars` are dicts of the machine's facts and the
+//!   variables set from outside, such as with `--var`.
+//!
+//! `print` logs at info level. Needs skies built with the `starlark`
+//! feature.
+
+use std::path::Path;
+
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+
+/// Whether the plan file at `path` is a Starlark one.
+pub fn is_starlark(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "star")
+}
+
+/// Runs the Starlark plan file at `path` and returns the plan document it
+/// built, in the shape of a TOML plan file.
+#[cfg(feature = "starlark")]
+pub fn evaluate(path: &Path, ctx: &ExecutionContext) -> ShiftResult<toml::Table> {
+    use std::cell::RefCell;
+
+    use serde_json::{Map, Value as Json};
+    use starlark::any::ProvidesStaticType;
+    use starlark::environment::{GlobalsBuilder, LibraryExtension, Module};
+    use starlark::eval::Evaluator;
+    use starlark::starlark_module;
+    use starlark::syntax::{AstModule, Dialect};
+    use starlark::values::dict::{AllocDict, DictRef};
+    use starlark::values::none::NoneType;
+    use starlark::values::Value;
+    use starlark::PrintHandler;
+
+    /// The plan document so far.
+    #[derive(Debug, Default, ProvidesStaticType)]
+    struct Document(RefCell<Map<String, Json>>);
+
+    impl Document {
+        fn of<'a>(eval: &Evaluator<'_, 'a, '_>) -> &'a Document {
+            let extra = eval.extra.expect("the document is set before evaluating");
+            extra.downcast_ref().expect("extra is the document")
+        }
+
+        fn push(&self, key: &str, table: Map<String, Json>) {
+            let mut document = self.0.borrow_mut();
+            let tables = document
+                .entry(key)
+                .or_insert_with(|| Json::Array(Vec::new()));
+            if let Json::Array(tables) = tables {
+                tables.push(Json::Object(table));
+            }
+        }
+    }
+
+    fn table(fields: DictRef<'_>) -> starlark::Result<Map<String, Json>> {
+        let mut table = Map::new();
+        for (key, value) in fields.iter() {
+            let key = key.unpack_str().unwrap_or_default().to_string();
+            table.insert(key, value.to_json_value()?);
+        }
+        Ok(table)
+    }
+
+    #[starlark_module]
+    fn plan_globals(builder: &mut GlobalsBuilder) {
+        fn shift<'v>(
+            #[starlark(require = pos)] r#type: Option<&str>,
+            #[starlark(kwargs)] fields: DictRef<'v>,
+            eval: &mut Evaluator<'v, '_, '_>,
+        ) -> starlark::Result<Value<'v>> {
+            let id = fields.get_str("id").unwrap_or_else(Value::new_none);
+            let mut entry = table(fields)?;
+            if let Some(kind) = r#type {
+                entry.insert("type".into(), Json::String(kind.into()));
+            }
+            Document::of(eval).push("shift", entry);
+            Ok(id)
+        }
+
+        fn verify<'v>(
+            #[starlark(kwargs)] fields: DictRef<'v>,
+            eval: &mut Evaluator<'v, '_, '_>,
+        ) -> starlark::Result<NoneType> {
+            Document::of(eval).push("verify", table(fields)?);
+            Ok(NoneType)
+        }
+
+        fn plan<'v>(
+            #[starlark(kwargs)] settings: DictRef<'v>,
+            eval: &mut Evaluator<'v, '_, '_>,
+        ) -> starlark::Result<NoneType> {
+            let settings = table(settings)?;
+            if let Some(key) = ["shift", "verify"]
+                .iter()
+                .find(|k| settings.contains_key(**k))
+            {
+                return Err(starlark::Error::new_other(ShiftError::Plan(format!(
+                    "add `{key}` entries with {key}() rather than plan()"
+                ))));
+            }
+            Document::of(eval).0.borrow_mut().extend(settings);
+            Ok(NoneType)
+        }
+    }
+
+    struct Log<'a>(&'a ExecutionContext);
+
+    impl PrintHandler for Log<'_> {
+        fn println(&self, text: &str) -> starlark::Result<()> {
+            self.0.info(text);
+            Ok(())
+        }
+    }
+
+    let located = |err: starlark::Error| {
+        let msg = err.without_diagnostic();
+        ShiftError::Plan(match err.span() {
+            Some(span) => format!("{span}: {msg}"),
+            None => format!("{}: {msg}", path.display()),
+        })
+    };
+    let source = std::fs::read_to_string(path)
+        .map_err(|err| ShiftError::Plan(format!("cannot read {}: {err}", path.display())))?;
+    let name = path.display().to_string();
+    let ast = AstModule::parse(&name, source, &Dialect::Extended).map_err(located)?;
+    let globals = GlobalsBuilder::extended_by(&[
+        LibraryExtension::StructType,
+        LibraryExtension::Map,
+        LibraryExtension::Filter,
+        LibraryExtension::Print,
+        LibraryExtension::Json,
+    ])
+    .with(plan_globals)
+    .build();
+    let module = Module::new();
+    let heap = module.heap();
+    module.set("facts", heap.alloc(AllocDict(ctx.facts().iter())));
+    let vars = ctx.vars().iter().map(|(k, v)| (k.as_str(), v.as_str()));
+    module.set("vars", heap.alloc(AllocDict(vars)));
+    let document = Document::default();
+    let log = Log(ctx);
+    {
+        let mut eval = Evaluator::new(&module);
+        eval.extra = Some(&document);
+        eval.set_print_handler(&log);
+        eval.eval_module(ast, &globals).map_err(located)?;
+    }
+    let document = Json::Object(document.0.into_inner());
+    toml::Value::try_from(document)
+        .ok()
+        .and_then(|value| value.as_table().cloned())
+        .ok_or_else(|| {
+            ShiftError::Plan(format!(
+                "{}: the plan holds a None, which plan files cannot",
+                path.display()
+            ))
+        })
+}
+
+/// Fails: Starlark plan files need the `starlark` feature.
+#[cfg(not(feature = "starlark"))]
+pub fn evaluate(path: &Path, _ctx: &ExecutionContext) -> ShiftResult<toml::Table> {
+    Err(ShiftError::Plan(format!(
+        "{}: Starlark plan files need skies built with the `starlark` feature",
+        path.display()
+    )))
+}
@@ -0,0 +1,83 @@
+//! Per-shift persistent state, e.g. values to restore on revert.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ShiftError, ShiftResult};
+
+/// Key/value state keyed by shift ID, optionally backed by a JSON file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct StateStore {
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    shifts: BTreeMap<String, BTreeMap<String, serde_json::Value>>,
+}
+
+impl StateStore {
+    /// A store that lives only in memory.
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Where the state for the plan at `plan_path` lives.
+    pub fn path_for(plan_path: &Path) -> PathBuf {
+        let dir = plan_path.parent().unwrap_or(Path::new("."));
+        dir.join(".skies").join("state.json")
+    }
+
+    /// Loads the store at `path`, treating a missing file as empty.
+    pub fn open(path: impl Into<PathBuf>) -> ShiftResult<Self> {
+        let path = path.into();
+        let mut store: StateStore = match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text).map_err(|err| {
+                ShiftError::Custom(format!("corrupt state file {}: {err}", path.display()))
+            })?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => StateStore::default(),
+            Err(err) => return Err(err.into()),
+        };
+        store.path = Some(path);
+        Ok(store)
+    }
+
+    /// Writes the store back to its file; a no-op for in-memory stores.
+    pub fn save(&self) -> ShiftResult<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text = serde_json::to_string_pretty(self)
+            .map_err(|err| ShiftError::Custom(format!("cannot encode state: {err}")))?;
+        fs::write(path, text)?;
+        Ok(())
+    }
+
+    pub fn get(&self, shift: &str, key: &str) -> Option<&serde_json::Value> {
+        self.shifts.get(shift)?.get(key)
+    }
+
+    pub fn set(&mut self, shift: &str, key: &str, value: serde_json::Value) {
+        self.shifts
+            .entry(shift.to_string())
+            .or_default()
+            .insert(key.to_string(), value);
+    }
+
+    pub fn remove(&mut self, shift: &str, key: &str) -> Option<serde_json::Value> {
+        let values = self.shifts.get_mut(shift)?;
+        let removed = values.remove(key);
+        if values.is_empty() {
+            self.shifts.remove(shift);
+        }
+        removed
+    }
+
+    /// All state recorded for one shift.
+    pub fn shift(&self, shift: &str) -> Option<&BTreeMap<String, serde_json::Value>> {
+        self.shifts.get(shift)
+    }
+}
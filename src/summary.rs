@@ -0,0 +1,214 @@
+//! A run's outcome as a short message for chat, such as a CI job posting
+//! to Slack or Discord when an apply ends.
+//!
+//! [`RunSummary::markdown`] suits Discord, GitHub comments and most other
+//! places that render Markdown; [`RunSummary::slack`] is a Slack message
+//! payload with Block Kit blocks, ready to send to an incoming webhook.
+
+use std::collections::BTreeMap;
+
+use serde_json::{json, Value};
+
+use crate::error::ShiftError;
+use crate::journal::{Operation, RunRecord, ShiftStatus};
+
+/// How many shift IDs a list names before it says how many more.
+const LISTED: usize = 10;
+/// Longest error message kept; chat messages have size limits.
+const ERROR_CHARS: usize = 300;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunSummary {
+    pub operation: Operation,
+    pub succeeded: bool,
+    /// The machine the run was on.
+    pub host: Option<String>,
+    /// Shifts that changed something, in the order they finished.
+    pub changed: Vec<String>,
+    pub unchanged: usize,
+    pub rolled_back: usize,
+    /// Shifts that failed, with their errors.
+    pub failed: Vec<(String, String)>,
+    /// Seconds the run took.
+    pub took: Option<u64>,
+    /// The run's error, if it failed other than in a shift (say, in
+    /// preflight) or was run elsewhere.
+    pub error: Option<String>,
+}
+
+impl RunSummary {
+    /// Sums up `run`, the journal record of a local run, if any, which
+    /// ended with `error`.
+    pub fn new(
+        operation: Operation,
+        run: Option<&RunRecord>,
+        error: Option<&ShiftError>,
+        host: Option<String>,
+    ) -> Self {
+        // A retried shift appears more than once; its last record counts.
+        let mut last = BTreeMap::new();
+        let mut order = Vec::new();
+        for shift in run.map_or(&[][..], |run| &run.shifts) {
+            if last.insert(shift.id.as_str(), shift).is_none() {
+                order.push(shift.id.as_str());
+            }
+        }
+        let mut summary = RunSummary {
+            operation,
+            succeeded: error.is_none(),
+            host,
+            changed: Vec::new(),
+            unchanged: 0,
+            rolled_back: 0,
+            failed: Vec::new(),
+            took: run.and_then(|run| Some(run.finished_at?.saturating_sub(run.started_at))),
+            error: None,
+        };
+        for id in order {
+            let shift = last[id];
+            match shift.status {
+                ShiftStatus::Applied | ShiftStatus::Reverted | ShiftStatus::Adopted => {
+                    summary.changed.push(id.to_string())
+                }
+                ShiftStatus::Skipped => summary.unchanged += 1,
+                ShiftStatus::RolledBack => summary.rolled_back += 1,
+                ShiftStatus::Failed | ShiftStatus::TimedOut => summary.failed.push((
+                    id.to_string(),
+                    shorten(shift.error.as_deref().unwrap_or("failed")),
+                )),
+            }
+        }
+        if summary.failed.is_empty() {
+            summary.error = error.map(|err| shorten(&err.to_string()));
+        }
+        summary
+    }
+
+    fn headline(&self) -> String {
+        let outcome = if self.succeeded {
+            "succeeded"
+        } else {
+            "failed"
+        };
+        match &self.host {
+            Some(host) => format!("skies {} {outcome} on {host}", self.operation),
+            None => format!("skies {} {outcome}", self.operation),
+        }
+    }
+
+    /// `3 changed · 5 unchanged · took 12s`, leaving out what is zero.
+    fn counts(&self) -> String {
+        let mut parts = vec![
+            format!("{} changed", self.changed.len()),
+            format!("{} unchanged", self.unchanged),
+        ];
+        if !self.failed.is_empty() {
+            parts.push(format!("{} failed", self.failed.len()));
+        }
+        if self.rolled_back > 0 {
+            parts.push(format!("{} rolled back", self.rolled_back));
+        }
+        if let Some(took) = self.took {
+            parts.push(format!("took {}", duration(took)));
+        }
+        parts.join(" · ")
+    }
+
+    /// The changed shifts as `` `a`, `b` and 3 more ``.
+    fn changed_list(&self) -> Option<String> {
+        if self.changed.is_empty() {
+            return None;
+        }
+        let mut list: Vec<String> = self
+            .changed
+            .iter()
+            .take(LISTED)
+            .map(|id| format!("`{id}`"))
+            .collect();
+        if self.changed.len() > LISTED {
+            list.push(format!("and {} more", self.changed.len() - LISTED));
+        }
+        Some(list.join(", "))
+    }
+
+    /// A few lines of Markdown.
+    pub fn markdown(&self) -> String {
+        let icon = if self.succeeded { "✅" } else { "❌" };
+        let mut out = format!("{icon} **{}**\n{}\n", self.headline(), self.counts());
+        for (id, err) in &self.failed {
+            out.push_str(&format!("- `{id}`: {}\n", one_line(err)));
+        }
+        if let Some(err) = &self.error {
+            out.push_str(&format!("- {}\n", one_line(err)));
+        }
+        if let Some(changed) = self.changed_list() {
+            out.push_str(&format!("Changed: {changed}\n"));
+        }
+        out
+    }
+
+    /// A Slack message: `text` for notifications, `blocks` for the message.
+    pub fn slack(&self) -> Value {
+        let icon = if self.succeeded {
+            ":white_check_mark:"
+        } else {
+            ":x:"
+        };
+        let mut blocks = vec![json!({
+            "type": "section",
+            "text": {
+                "type": "mrkdwn",
+                "text": format!("{icon} *{}*\n{}", slack_escape(&self.headline()), self.counts()),
+            },
+        })];
+        let mut failures: Vec<String> = self
+            .failed
+            .iter()
+            .map(|(id, err)| format!("• `{}`: {}", slack_escape(id), slack_escape(&one_line(err))))
+            .collect();
+        failures.extend(
+            self.error
+                .iter()
+                .map(|err| format!("• {}", slack_escape(&one_line(err)))),
+        );
+        if !failures.is_empty() {
+            blocks.push(json!({
+                "type": "section",
+                "text": { "type": "mrkdwn", "text": failures.join("\n") },
+            }));
+        }
+        if let Some(changed) = self.changed_list() {
+            blocks.push(json!({
+                "type": "context",
+                "elements": [{ "type": "mrkdwn", "text": format!("Changed: {}", slack_escape(&changed)) }],
+            }));
+        }
+        json!({ "text": self.headline(), "blocks": blocks })
+    }
+}
+
+fn shorten(text: &str) -> String {
+    match text.char_indices().nth(ERROR_CHARS) {
+        Some((idx, _)) => format!("{}…", &text[..idx]),
+        None => text.to_string(),
+    }
+}
+
+fn one_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Slack's mrkdwn reads `&`, `<` and `>` as markup.
+fn slack_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn duration(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{secs}s"),
+        60..=3599 => format!("{}m {}s", secs / 60, secs % 60),
+        _ => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+    }
+}
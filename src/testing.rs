@@ -0,0 +1,116 @@
+//! Helpers for testing shifts, behind the `test-utils` feature.
+//!
+//! [`check_idempotent`] walks a shift through a full lifecycle and says
+//! which step misbehaved:
+//!
+//! ```
+//! use skies::shifts::CreateFile;
+//! use skies::testing::assert_idempotent;
+//!
+//! assert_idempotent(&CreateFile::new("greeting.txt", "hello\n"));
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use crate::context::ExecutionContext;
+use crate::error::{ResultExt, ShiftError, ShiftResult};
+use crate::shift::{Shift, ShiftOutcome};
+use crate::validate::ValidationContext;
+use crate::workspace::TempWorkspace;
+
+/// ID the shift under test runs as, for its state.
+const SHIFT_ID: &str = "under-test";
+
+/// A throwaway plan root, removed when dropped.
+///
+/// Only relative paths land in the sandbox; a shift that works on
+/// absolute paths (`/etc/fstab`, a mount point) still touches the machine.
+pub struct Sandbox {
+    workspace: TempWorkspace,
+    root: PathBuf,
+}
+
+impl Sandbox {
+    pub fn new() -> ShiftResult<Self> {
+        let workspace = TempWorkspace::new();
+        let root = workspace.subdir("sandbox")?;
+        Ok(Sandbox { workspace, root })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// A non-interactive context rooted in the sandbox.
+    pub fn context(&self) -> ExecutionContext {
+        ExecutionContext::new()
+            .with_root(&self.root)
+            .with_interactive(false)
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        self.workspace.finish(true);
+    }
+}
+
+/// Runs `shift` through validate, is_applied, apply, is_applied, apply,
+/// revert and is_applied under `ctx`, and fails at the first step that
+/// errors or reports the wrong state:
+///
+/// - it must not be applied to begin with;
+/// - the first apply must report [`ShiftOutcome::Changed`];
+/// - it must be applied after each apply, including a second one;
+/// - the second apply must report [`ShiftOutcome::Unchanged`];
+/// - it must not be applied after the revert.
+pub fn check_idempotent(shift: &dyn Shift, ctx: &ExecutionContext) -> ShiftResult<()> {
+    let ctx = ctx.for_shift(SHIFT_ID);
+    shift
+        .validate(&ValidationContext::new(&ctx))
+        .context("validate")?;
+    expect_applied(shift, &ctx, false, "is_applied before apply")?;
+    match shift.apply(&ctx).context("first apply")? {
+        ShiftOutcome::Changed => {}
+        ShiftOutcome::Unchanged => {
+            return Err(failed("first apply", "reported no change"));
+        }
+    }
+    expect_applied(shift, &ctx, true, "is_applied after apply")?;
+    match shift.apply(&ctx).context("second apply")? {
+        ShiftOutcome::Unchanged => {}
+        ShiftOutcome::Changed => {
+            return Err(failed("second apply", "reported a change"));
+        }
+    }
+    expect_applied(shift, &ctx, true, "is_applied after second apply")?;
+    shift.revert(&ctx).context("revert")?;
+    expect_applied(shift, &ctx, false, "is_applied after revert")
+}
+
+/// [`check_idempotent`] in a fresh [`Sandbox`], panicking with the failed
+/// step.
+pub fn assert_idempotent(shift: &dyn Shift) {
+    let result = Sandbox::new().and_then(|sandbox| check_idempotent(shift, &sandbox.context()));
+    if let Err(err) = result {
+        panic!("{} is not idempotent: {err}", shift.metadata().summary);
+    }
+}
+
+fn expect_applied(
+    shift: &dyn Shift,
+    ctx: &ExecutionContext,
+    wanted: bool,
+    step: &str,
+) -> ShiftResult<()> {
+    let applied = shift.is_applied(ctx).context(step)?;
+    if applied == wanted {
+        Ok(())
+    } else {
+        Err(failed(step, &format!("expected {wanted}, got {applied}")))
+    }
+}
+
+fn failed(step: &str, what: &str) -> ShiftError {
+    ShiftError::Custom(what.to_string()).context(step)
+}
@@ -0,0 +1,149 @@
+//! Running a command as a transient systemd unit, for long commands that
+//! must outlive the skies process.
+//!
+//! [`run`] starts the command with `systemd-run`, polls the unit until it
+//! finishes and reads what it printed back from the journal. Because the
+//! unit belongs to systemd, not to skies, an SSH session dropping in the
+//! middle of a remote apply does not kill it, and the next apply waits
+//! for the unit still running instead of starting the command again.
+
+use std::collections::BTreeMap;
+use std::thread;
+use std::time::Duration;
+
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+use crate::exec::{CommandOutput, CommandSpec};
+use crate::hash::short_hash;
+use crate::shifts::Cmd;
+
+/// How often the unit is checked on.
+const POLL: Duration = Duration::from_secs(1);
+
+/// The unit name for `spec` run by the context's current shift. It stays
+/// the same across runs, so a later run finds the unit an earlier one
+/// left running.
+pub fn unit_name(ctx: &ExecutionContext, spec: &CommandSpec) -> String {
+    let key = format!(
+        "{}\0{}\0{}",
+        ctx.root().display(),
+        ctx.shift_id().unwrap_or_default(),
+        spec.command_line()
+    );
+    format!("skies-cmd-{}.service", short_hash(key, 16))
+}
+
+/// `systemctl show` properties of `unit`.
+fn show(ctx: &ExecutionContext, unit: &str) -> ShiftResult<BTreeMap<String, String>> {
+    let out = Cmd::new("systemctl")
+        .args(["show", unit, "--property"])
+        .arg("LoadState,ActiveState,Result,ExecMainCode,ExecMainStatus,InvocationID")
+        .output(ctx)?;
+    Ok(out
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect())
+}
+
+fn is_loaded(props: &BTreeMap<String, String>) -> bool {
+    props.get("LoadState").map(String::as_str) == Some("loaded")
+}
+
+fn is_running(props: &BTreeMap<String, String>) -> bool {
+    matches!(
+        props.get("ActiveState").map(String::as_str),
+        Some("activating" | "deactivating" | "reloading")
+    )
+}
+
+fn start(ctx: &ExecutionContext, unit: &str, spec: &CommandSpec) -> ShiftResult<()> {
+    let mut cmd = Cmd::new("systemd-run")
+        .arg(format!("--unit={unit}"))
+        .args(["--no-block", "--quiet"])
+        .args(["--service-type=oneshot", "--remain-after-exit"])
+        .arg(format!("--working-directory={}", spec.cwd.display()))
+        .arg(format!("--description=skies: {}", spec.command_line()));
+    for (key, value) in &spec.env {
+        cmd = cmd.arg(format!("--setenv={key}={value}"));
+    }
+    if let Some(timeout) = spec.timeout {
+        cmd = cmd.arg(format!(
+            "--property=RuntimeMaxSec={}",
+            timeout.as_secs().max(1)
+        ));
+    }
+    cmd.arg("--")
+        .arg(&spec.program)
+        .args(spec.args.iter().map(String::as_str))
+        .output(ctx)
+        .map(drop)
+}
+
+/// Stops `unit` if it still runs and unloads it. Failing to only leaves
+/// it for the next run to pick up.
+fn unload(ctx: &ExecutionContext, unit: &str) {
+    let _ = Cmd::new("systemctl").args(["stop", unit]).output(ctx);
+    let _ = Cmd::new("systemctl")
+        .args(["reset-failed", unit])
+        .output(ctx);
+}
+
+/// Runs `spec` as `unit` and returns how it exited, with what it printed
+/// as stdout. If an earlier run left `unit` behind, that one is waited for
+/// and its result used instead.
+pub fn run(ctx: &ExecutionContext, unit: &str, spec: &CommandSpec) -> ShiftResult<CommandOutput> {
+    // Units are unloaded once their result is read, so a loaded one was
+    // started by a run that lost track of it.
+    if is_loaded(&show(ctx, unit)?) {
+        ctx.info(&format!("picking up {unit}, started by an earlier run"));
+    } else {
+        ctx.debug(&format!("starting `{}` as {unit}", spec.command_line()));
+        start(ctx, unit, spec)?;
+    }
+    let props = loop {
+        if let Err(err) = ctx.check_cancelled() {
+            unload(ctx, unit);
+            return Err(err);
+        }
+        let props = show(ctx, unit)?;
+        if !is_loaded(&props) {
+            return Err(ShiftError::Custom(format!(
+                "{unit} went away before it finished"
+            )));
+        }
+        if !is_running(&props) {
+            break props;
+        }
+        thread::sleep(POLL);
+    };
+    let mut journal = Cmd::new("journalctl").args(["--no-pager", "--output=cat"]);
+    journal = match props.get("InvocationID").filter(|id| !id.is_empty()) {
+        Some(id) => journal.arg(format!("_SYSTEMD_INVOCATION_ID={id}")),
+        None => journal.args(["--unit", unit]),
+    };
+    let printed = journal.output(ctx).unwrap_or_default();
+    unload(ctx, unit);
+
+    let result = props.get("Result").map(String::as_str).unwrap_or_default();
+    if result == "timeout" {
+        return Err(ShiftError::TimedOut(format!(
+            "`{}` timed out after {}s",
+            spec.command_line(),
+            spec.timeout.unwrap_or_default().as_secs()
+        )));
+    }
+    let code = match props.get("ExecMainCode").map(String::as_str) {
+        // CLD_EXITED; anything else means a signal ended it.
+        Some("1") => props.get("ExecMainStatus").and_then(|s| s.parse().ok()),
+        _ => None,
+    };
+    if code == Some(0) && result == "success" {
+        return Ok(CommandOutput::success(printed));
+    }
+    Ok(CommandOutput {
+        code: code.filter(|code| *code != 0),
+        stdout: String::new(),
+        stderr: printed,
+    })
+}
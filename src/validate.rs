@@ -0,0 +1,175 @@
+//! Preflight checks run over the whole plan before anything is applied.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use crate::context::ExecutionContext;
+use crate::error::{ShiftError, ShiftResult};
+
+/// What earlier shifts in the plan will have produced by the time a shift
+/// runs, plus helpers for the common checks.
+///
+/// Paths handed to the helpers are resolved against the execution root.
+#[derive(Clone)]
+pub struct ValidationContext {
+    exec: ExecutionContext,
+    planned: Vec<PathBuf>,
+    programs: Vec<String>,
+}
+
+impl ValidationContext {
+    pub fn new(exec: &ExecutionContext) -> Self {
+        ValidationContext {
+            exec: exec.clone(),
+            planned: Vec::new(),
+            programs: Vec::new(),
+        }
+    }
+
+    /// The context the shift being validated will run with.
+    pub fn exec(&self) -> &ExecutionContext {
+        &self.exec
+    }
+
+    /// Switches to the context of the next shift to validate.
+    pub(crate) fn set_exec(&mut self, exec: ExecutionContext) {
+        self.exec = exec;
+    }
+
+    /// Records that `path` will exist once the shifts so far are applied.
+    pub fn plan(&mut self, path: impl AsRef<Path>) {
+        let resolved = self.exec.join_root(path.as_ref());
+        self.planned.push(resolved);
+    }
+
+    /// Records that the program `name` will be installed once the shifts so
+    /// far are applied.
+    pub fn plan_program(&mut self, name: impl Into<String>) {
+        self.programs.push(name.into());
+    }
+
+    /// Whether `path` exists now or will be created by an earlier shift
+    /// (either directly or as the parent of something created).
+    pub fn will_exist(&self, path: &Path) -> bool {
+        let path = self.exec.join_root(path);
+        self.exec.fs().exists(&path) || self.planned.iter().any(|p| p.starts_with(&path))
+    }
+
+    /// Fails unless the directory `path` will be placed in exists or is
+    /// planned.
+    pub fn require_parent(&self, path: &Path) -> ShiftResult<()> {
+        match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() && !self.will_exist(parent) => {
+                Err(invalid(format!(
+                    "parent directory {} does not exist and no earlier shift creates it",
+                    parent.display()
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn require_dir(&self, path: &Path) -> ShiftResult<()> {
+        let resolved = self.exec.resolve(path)?;
+        let fs = self.exec.fs();
+        if fs.exists(&resolved) && !fs.is_dir(&resolved) {
+            return Err(invalid(format!("{} is not a directory", path.display())));
+        }
+        if !self.will_exist(path) {
+            return Err(invalid(format!(
+                "directory {} does not exist and no earlier shift creates it",
+                path.display()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Fails unless `program` is on `PATH` or an earlier shift installs it
+    /// (or, for paths, will exist).
+    pub fn require_program(&self, program: &str) -> ShiftResult<()> {
+        if program.contains(std::path::MAIN_SEPARATOR) {
+            if self.will_exist(Path::new(program)) {
+                return Ok(());
+            }
+        } else if self.programs.iter().any(|name| name == program)
+            || find_program(program).is_some()
+        {
+            return Ok(());
+        }
+        Err(
+            invalid(format!("`{program}` not found on PATH")).hint(format!(
+                "install {program} or add a shift that installs it, such as a Package shift"
+            )),
+        )
+    }
+
+    /// Fails unless the environment variable `name` is set and non-empty.
+    pub fn require_env(&self, name: &str) -> ShiftResult<()> {
+        match env::var_os(name) {
+            Some(value) if !value.is_empty() => Ok(()),
+            _ => Err(invalid(format!("environment variable {name} is not set"))),
+        }
+    }
+}
+
+/// Locates an executable on `PATH`.
+pub fn find_program(name: &str) -> Option<PathBuf> {
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path)
+        .map(|dir| dir.join(name))
+        .find(|candidate| is_executable(candidate))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata()
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+fn invalid(msg: String) -> ShiftError {
+    ShiftError::Custom(msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::context::ExecutionContext;
+    use crate::error::ShiftError;
+    use crate::plan::{PlanEntry, ShiftPlan};
+    use crate::shifts::{Cmd, Package};
+
+    /// The problems preflight found with the shift `id`.
+    fn problems(plan: &ShiftPlan, id: &str) -> Vec<String> {
+        match plan.preflight(&ExecutionContext::new()) {
+            Ok(()) => Vec::new(),
+            Err(ShiftError::Preflight(problems)) => problems
+                .into_iter()
+                .filter(|(shift, _)| shift == id)
+                .map(|(_, err)| err.to_string())
+                .collect(),
+            Err(err) => panic!("{err}"),
+        }
+    }
+
+    #[test]
+    fn missing_program_is_reported() {
+        let mut plan = ShiftPlan::new();
+        plan.push(PlanEntry::new(Cmd::new("skies-test-missing-tool")).with_id("use"));
+        assert_eq!(problems(&plan, "use").len(), 1);
+    }
+
+    #[test]
+    fn program_installed_earlier_is_found() {
+        let mut plan = ShiftPlan::new();
+        plan.push(PlanEntry::new(Package::new(["skies-test-missing-tool"])).with_id("install"));
+        plan.push(PlanEntry::new(Cmd::new("skies-test-missing-tool")).with_id("use"));
+        assert_eq!(problems(&plan, "use"), Vec::<String>::new());
+    }
+}
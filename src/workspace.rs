@@ -0,0 +1,91 @@
+//! Scratch space shared by the shifts of a single run.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::ShiftResult;
+
+/// A per-run temporary directory for downloads, rendered templates,
+/// scripts and the like.
+///
+/// The directory is only created when first requested. Call
+/// [`TempWorkspace::finish`] at the end of the run: it is removed after a
+/// successful run and kept for debugging after a failed one.
+pub struct TempWorkspace {
+    base: PathBuf,
+    dir: Mutex<Option<PathBuf>>,
+    counter: AtomicU64,
+}
+
+impl TempWorkspace {
+    /// A workspace under the system temporary directory.
+    pub fn new() -> Self {
+        Self::under(std::env::temp_dir())
+    }
+
+    /// A workspace under `base`.
+    pub fn under(base: impl Into<PathBuf>) -> Self {
+        TempWorkspace {
+            base: base.into(),
+            dir: Mutex::new(None),
+            counter: AtomicU64::new(0),
+        }
+    }
+
+    /// The workspace directory, created on first use.
+    pub fn dir(&self) -> ShiftResult<PathBuf> {
+        let mut dir = self.dir.lock().unwrap_or_else(|p| p.into_inner());
+        if let Some(dir) = &*dir {
+            return Ok(dir.clone());
+        }
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+        let path = self.base.join(format!("skies-{stamp}-{}", process::id()));
+        fs::create_dir_all(&path)?;
+        *dir = Some(path.clone());
+        Ok(path)
+    }
+
+    /// A fresh, empty subdirectory whose name starts with `prefix`.
+    pub fn subdir(&self, prefix: &str) -> ShiftResult<PathBuf> {
+        let n = self.counter.fetch_add(1, Ordering::Relaxed);
+        let path = self.dir()?.join(format!("{prefix}-{n}"));
+        fs::create_dir_all(&path)?;
+        Ok(path)
+    }
+
+    /// The directory, if anything has requested it so far.
+    pub fn created(&self) -> Option<PathBuf> {
+        self.dir.lock().unwrap_or_else(|p| p.into_inner()).clone()
+    }
+
+    /// Cleans up after the run. Returns the path if it was kept.
+    pub fn finish(&self, succeeded: bool) -> Option<PathBuf> {
+        let dir = self.dir.lock().unwrap_or_else(|p| p.into_inner()).take()?;
+        if succeeded {
+            let _ = remove(&dir);
+            None
+        } else {
+            Some(dir)
+        }
+    }
+}
+
+impl Default for TempWorkspace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn remove(dir: &Path) -> std::io::Result<()> {
+    if dir.exists() {
+        fs::remove_dir_all(dir)?;
+    }
+    Ok(())
+}
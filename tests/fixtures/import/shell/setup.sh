@@ -0,0 +1,25 @@
+#!/usr/bin/env bash
+set -euo pipefail
+
+APP_DIR=/opt/app
+
+# Build tools
+sudo apt-get install -y git make
+
+mkdir -p "$APP_DIR"
+git clone https://github.com/example/app.git "$APP_DIR/src"
+
+# Config for the app
+cat > "$APP_DIR/app.conf" <<'CONF'
+port=8080
+CONF
+
+echo 'export PATH="$HOME/.local/bin:$PATH"' >> ~/.bashrc
+ln -sf "$APP_DIR/src/app.service" /etc/systemd/system/app.service
+chmod 600 "$APP_DIR/app.conf"
+
+make -C "$APP_DIR/src"
+
+for svc in app worker; do
+  systemctl enable "$svc"
+done